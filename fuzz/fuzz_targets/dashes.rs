@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use piet_fuzz::Dashes;
+
+fuzz_target!(|input: Dashes| {
+    piet_fuzz::check(&input);
+});
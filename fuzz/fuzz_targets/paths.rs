@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use piet_fuzz::Paths;
+
+fuzz_target!(|input: Paths| {
+    piet_fuzz::check(&input);
+});
@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use piet_fuzz::TextInput;
+
+fuzz_target!(|input: TextInput| {
+    piet_fuzz::check(&input);
+});
@@ -0,0 +1,274 @@
+//! Fuzz targets for piet backends.
+//!
+//! Each target decodes its input into calls to a render context, makes them
+//! with every backend that runs here, and checks two things:
+//!
+//! - No call panics, whatever the input: NaN and infinite coordinates,
+//!   degenerate shapes and transforms, dash patterns of zeros, any text.
+//! - A bad frame leaves nothing behind. A frame drawn after it, to the same
+//!   target, has to finish without an error and draw what it's asked to.
+//!   Cairo in particular puts its context into an error state it never
+//!   leaves on some bad input, after which it silently draws nothing.
+//!
+//! The raster backend is the one piet-common picks, cairo or Direct2D. The
+//! SVG backend is checked too, except for text, which it doesn't draw.
+//!
+//! Run a target with [cargo-fuzz](https://github.com/rust-fuzz/cargo-fuzz),
+//! from this directory, on a nightly compiler:
+//!
+//! ```text
+//! cargo fuzz run dashes
+//! ```
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use piet_common::kurbo::{Affine, BezPath, Circle, Point, Rect, RoundedRect};
+use piet_common::{
+    Capabilities, Color, Device, FontBuilder, ImageFormat, LineCap, LineJoin, RenderContext,
+    StrokeStyle, Text, TextLayout, TextLayoutBuilder,
+};
+
+/// The width and height of the targets drawn to, in pixels.
+const SIZE: usize = 32;
+
+/// The rectangle the frame after an input fills, to check it's drawn.
+const MARK: Rect = Rect::new(8.0, 8.0, 24.0, 24.0);
+
+/// A fuzz input, drawn by each backend.
+pub trait Input {
+    /// Make the input's calls to `rc`.
+    ///
+    /// Saves are balanced, so what the input does to the state of `rc` ends
+    /// with it; whether anything else carries over is what's checked.
+    fn draw(&self, rc: &mut impl RenderContext);
+
+    /// Whether a backend with `capabilities` can draw the input.
+    fn is_supported(&self, _capabilities: &Capabilities) -> bool {
+        true
+    }
+}
+
+/// Draw `input` with every backend, and check each can still draw after it.
+///
+/// Panics if a backend panics, or if the frame after the input fails.
+pub fn check(input: &impl Input) {
+    // unbalanced calls are an error, not a panic, whatever the build
+    piet_common::set_panic_free(true);
+    check_raster(input);
+    check_svg(input);
+}
+
+fn check_raster(input: &impl Input) {
+    let mut device = Device::new().unwrap();
+    let mut target = device.bitmap_target(SIZE, SIZE, 1.0).unwrap();
+    {
+        let mut rc = target.render_context();
+        if input.is_supported(&rc.capabilities()) {
+            input.draw(&mut rc);
+        }
+        // the input may fail its own frame
+        let _ = rc.finish();
+    }
+    {
+        let mut rc = target.render_context();
+        draw_mark(&mut rc);
+        if let Err(e) = rc.finish() {
+            panic!("the raster frame after the input failed: {}", e);
+        }
+    }
+    let pixels = target.into_raw_pixels(ImageFormat::RgbaPremul).unwrap();
+    let center = (SIZE / 2 * SIZE + SIZE / 2) * 4;
+    assert_eq!(
+        &pixels[center..center + 4],
+        &[0, 0, 0, 0xff],
+        "the raster frame after the input drew nothing"
+    );
+}
+
+fn check_svg(input: &impl Input) {
+    let mut rc = piet_svg::RenderContext::new();
+    if !input.is_supported(&rc.capabilities()) {
+        return;
+    }
+    input.draw(&mut rc);
+    let _ = rc.finish();
+    // an SVG context makes one document, so the next frame is drawn to it
+    draw_mark(&mut rc);
+    if let Err(e) = rc.finish() {
+        panic!("the SVG frame after the input failed: {}", e);
+    }
+    let mut doc = Vec::new();
+    rc.write(&mut doc).unwrap();
+}
+
+/// What the frame after an input draws: a black square on white.
+fn draw_mark(rc: &mut impl RenderContext) {
+    rc.clear(Color::WHITE);
+    rc.fill(MARK, &Color::BLACK);
+}
+
+/// An element of a path, with any coordinates.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub enum El {
+    MoveTo(f64, f64),
+    LineTo(f64, f64),
+    QuadTo(f64, f64, f64, f64),
+    CurveTo(f64, f64, f64, f64, f64, f64),
+    ClosePath,
+}
+
+/// The path of `els`, which may not start with a move.
+pub fn path(els: &[El]) -> BezPath {
+    let mut path = BezPath::new();
+    for &el in els {
+        match el {
+            El::MoveTo(x, y) => path.move_to((x, y)),
+            El::LineTo(x, y) => path.line_to((x, y)),
+            El::QuadTo(x1, y1, x, y) => path.quad_to((x1, y1), (x, y)),
+            El::CurveTo(x1, y1, x2, y2, x, y) => path.curve_to((x1, y1), (x2, y2), (x, y)),
+            El::ClosePath => path.close_path(),
+        }
+    }
+    path
+}
+
+/// A call to a render context, for the `paths` target.
+#[derive(Arbitrary, Debug)]
+pub enum Op {
+    Fill(Vec<El>),
+    FillEvenOdd(Vec<El>),
+    Stroke(Vec<El>, f64),
+    Clip(Vec<El>),
+    Rect(f64, f64, f64, f64),
+    RoundedRect(f64, f64, f64, f64, f64),
+    Circle(f64, f64, f64),
+    Transform([f64; 6]),
+    Save,
+    Restore,
+}
+
+/// Shapes, clips and transforms, for the `paths` target.
+#[derive(Arbitrary, Debug)]
+pub struct Paths(pub Vec<Op>);
+
+impl Input for Paths {
+    fn draw(&self, rc: &mut impl RenderContext) {
+        let brush = Color::rgba8(0x20, 0x40, 0x80, 0xc0);
+        let _ = rc.with_save(|rc| {
+            let mut saves = 0;
+            for op in &self.0 {
+                match op {
+                    Op::Fill(els) => rc.fill(path(els), &brush),
+                    Op::FillEvenOdd(els) => rc.fill_even_odd(path(els), &brush),
+                    Op::Stroke(els, width) => rc.stroke(path(els), &brush, *width),
+                    Op::Clip(els) => rc.clip(path(els)),
+                    &Op::Rect(x0, y0, x1, y1) => rc.fill(Rect::new(x0, y0, x1, y1), &brush),
+                    &Op::RoundedRect(x0, y0, x1, y1, radius) => {
+                        rc.fill(RoundedRect::new(x0, y0, x1, y1, radius), &brush)
+                    }
+                    &Op::Circle(x, y, radius) => {
+                        rc.stroke(Circle::new((x, y), radius), &brush, 1.0)
+                    }
+                    &Op::Transform(coeffs) => rc.transform(Affine::new(coeffs)),
+                    // an error may be one deferred from an earlier call, so
+                    // the save is counted whatever the result
+                    Op::Save => {
+                        let _ = rc.save();
+                        saves += 1;
+                    }
+                    Op::Restore => {
+                        if saves > 0 {
+                            let _ = rc.restore();
+                            saves -= 1;
+                        }
+                    }
+                }
+            }
+            for _ in 0..saves {
+                let _ = rc.restore();
+            }
+            Ok(())
+        });
+    }
+}
+
+/// A dashed stroke, for the `dashes` target.
+#[derive(Arbitrary, Debug)]
+pub struct Dashes {
+    pub path: Vec<El>,
+    pub width: f64,
+    pub dashes: Vec<f64>,
+    pub offset: f64,
+    pub cap: u8,
+    pub join: u8,
+    pub miter_limit: Option<f64>,
+    pub transform: Option<[f64; 6]>,
+}
+
+impl Input for Dashes {
+    fn draw(&self, rc: &mut impl RenderContext) {
+        let mut style = StrokeStyle::new();
+        style.set_dash(self.dashes.clone(), self.offset);
+        style.set_line_cap([LineCap::Butt, LineCap::Round, LineCap::Square][self.cap as usize % 3]);
+        style.set_line_join(
+            [LineJoin::Miter, LineJoin::Round, LineJoin::Bevel][self.join as usize % 3],
+        );
+        if let Some(miter_limit) = self.miter_limit {
+            style.set_miter_limit(miter_limit);
+        }
+        let _ = rc.with_save(|rc| {
+            if let Some(coeffs) = self.transform {
+                rc.transform(Affine::new(coeffs));
+            }
+            rc.stroke_styled(path(&self.path), &Color::BLACK, self.width, &style);
+            // the same dashes on a shape with a known good path
+            rc.stroke_styled(MARK, &Color::BLACK, self.width, &style);
+            Ok(())
+        });
+    }
+}
+
+/// Text laid out and drawn, for the `text` target.
+#[derive(Arbitrary, Debug)]
+pub struct TextInput {
+    pub text: String,
+    pub family: String,
+    pub size: f64,
+    pub max_width: Option<f64>,
+    pub x: f64,
+    pub y: f64,
+    /// Points to hit test, in the layout's space.
+    pub points: Vec<(f64, f64)>,
+    /// Positions in the text to hit test, which may not be on character
+    /// boundaries, or in the text at all.
+    pub positions: Vec<usize>,
+}
+
+impl Input for TextInput {
+    fn draw(&self, rc: &mut impl RenderContext) {
+        let mut text = rc.text();
+        let font = match text.new_font_by_name(&self.family, self.size).build() {
+            Ok(font) => font,
+            Err(_) => return,
+        };
+        let mut builder = text.new_text_layout(&font, &self.text);
+        if let Some(width) = self.max_width {
+            builder = builder.max_width(width);
+        }
+        let layout = match builder.build() {
+            Ok(layout) => layout,
+            Err(_) => return,
+        };
+        layout.width();
+        for &(x, y) in &self.points {
+            layout.hit_test_point(Point::new(x, y));
+        }
+        for &position in &self.positions {
+            layout.hit_test_text_position(position);
+        }
+        rc.draw_text(&layout, (self.x, self.y), &Color::BLACK);
+    }
+
+    fn is_supported(&self, capabilities: &Capabilities) -> bool {
+        capabilities.text
+    }
+}
@@ -0,0 +1,84 @@
+//! Time fills and strokes of simple rectangles, and drawing glyphs.
+//!
+//! Run with `cargo bench -p piet-cairo`. That they don't allocate is
+//! checked by `tests/allocations.rs`.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use cairo::{Context, Format, ImageSurface};
+
+use piet::kurbo::{Point, Rect};
+use piet::{
+    Color, DynShape, FixedLinearGradient, FontBuilder, GradientColorSpace, GradientStops,
+    RenderContext, StrokeStyle, Text,
+};
+use piet_cairo::CairoRenderContext;
+
+const ITERATIONS: u32 = 10_000;
+
+/// Run `f` once to warm up, then time it.
+fn bench(name: &str, mut f: impl FnMut(u32)) {
+    f(0);
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        f(i);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<24} {:>8.1} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / ITERATIONS as f64,
+    );
+}
+
+fn rect(i: u32) -> Rect {
+    let x = (i % 100) as f64;
+    Rect::new(x, 10.0, x + 20.0, 30.0)
+}
+
+fn main() {
+    let surface = ImageSurface::create(Format::ARgb32, 128, 64).unwrap();
+    let mut ctx = Context::new(&surface);
+    let mut rc = CairoRenderContext::new(&mut ctx);
+
+    let black = Color::BLACK;
+    let dashed = StrokeStyle::dashed(&[4.0, 2.0, 1.0], 0.0);
+    let gradient = rc
+        .gradient(FixedLinearGradient {
+            start: Point::new(0.0, 0.0),
+            end: Point::new(128.0, 0.0),
+            stops: (Color::rgb8(0xff, 0, 0), Color::rgb8(0, 0, 0xff)).to_vec(),
+            color_space: GradientColorSpace::Srgb,
+        })
+        .unwrap();
+    let font = rc
+        .text()
+        .new_font_by_name("monospace", 12.0)
+        .build()
+        .unwrap();
+    let glyphs: Vec<_> = (0..16)
+        .map(|i| (1, Point::new(i as f64 * 8.0, 40.0)))
+        .collect();
+
+    bench("fill rect", |i| rc.fill(black_box(rect(i)), &black));
+    bench("fill dyn rect", |i| {
+        let shape = rect(i);
+        let shape: &dyn DynShape = &shape;
+        rc.fill(shape, &black);
+    });
+    bench("fill rect gradient", |i| {
+        rc.fill(black_box(rect(i)), &gradient)
+    });
+    bench("stroke rect", |i| {
+        rc.stroke(black_box(rect(i)), &black, 2.0)
+    });
+    bench("stroke rect dashed", |i| {
+        rc.stroke_styled(black_box(rect(i)), &black, 2.0, &dashed)
+    });
+    bench("draw glyphs", |_| {
+        rc.draw_glyphs(&font, black_box(&glyphs), &black)
+    });
+
+    rc.finish().unwrap();
+}
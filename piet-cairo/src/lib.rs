@@ -4,28 +4,123 @@
 //! The Cairo backend for the Piet 2D graphics abstraction.
 
 mod text;
+#[cfg(feature = "parallel")]
+mod tiled;
 
 use std::borrow::Cow;
 use std::fmt;
+use std::rc::Rc;
 
-use cairo::{BorrowError, Context, Filter, Format, ImageSurface, Matrix, Status, SurfacePattern};
+use cairo::{
+    Antialias, BorrowError, Context, Filter, Format, Glyph, ImageSurface, Matrix, MeshCorner,
+    Operator, ScaledFont, Status, SurfacePattern,
+};
 
-use piet::kurbo::{Affine, PathEl, Point, QuadBez, Rect, Shape};
+use piet::kurbo::{Affine, Insets, PathEl, Point, QuadBez, Rect, Shape, Size};
 
 use piet::{
-    new_error, Color, Error, ErrorKind, FixedGradient, ImageFormat, InterpolationMode, IntoBrush,
-    LineCap, LineJoin, RenderContext, StrokeStyle,
+    align_stroke, grid_background_runs, grid_foreground_runs, new_error, shape_elements,
+    user_tolerance, Antialiasing, Capabilities, Color, DrawCall, DrawProfile, Error, ErrorKind,
+    FallbackPolicy, FixedGradient, FrameArena, GlyphPositioning, GradientStop, GridCell,
+    GridMetrics, Image, ImageFormat, ImagePositioning, InterpolationMode, IntoBrush, LayerPool,
+    LineCap, LineJoin, MemoryCharge, MemoryCounters, MemoryReport, MeshPatch, OwnedImage, Profiler,
+    RenderContext, StrokeStyle, DEFAULT_TOLERANCE,
 };
 
 pub use crate::text::{
     CairoFont, CairoFontBuilder, CairoText, CairoTextLayout, CairoTextLayoutBuilder,
 };
+#[cfg(feature = "parallel")]
+pub use crate::tiled::{render_tiled, TileOptions};
+
+thread_local! {
+    /// The memory held by the images, glyph caches and layers made on this
+    /// thread.
+    pub(crate) static MEMORY: MemoryCounters = MemoryCounters::new();
+}
+
+/// The approximate memory held by cairo resources made on this thread, which
+/// all its render contexts share.
+pub fn memory_report() -> MemoryReport {
+    MEMORY.with(|memory| memory.report())
+}
+
+/// Free the glyph caches of this thread.
+///
+/// The buffers and layers of each context are its own, and are freed with
+/// its `trim_caches`.
+pub fn trim_caches() {
+    text::clear_glyph_caches();
+}
+
+/// The buffers and surfaces a context reuses for the transient data of its
+/// draw calls: the glyphs of runs, gradient stops, and layers to blur.
+///
+/// Each context has its own, and resets it from `finish`, which frees the
+/// buffers far larger than the frame needed; layers are kept until
+/// `trim_layer_pool`. An app that makes a context for each frame can carry
+/// the arena from one to the next with [`into_arena`] and [`with_arena`],
+/// so that the frames after the first allocate nothing. Paths and dash
+/// patterns are streamed into cairo as they are, so need no buffers.
+///
+/// [`into_arena`]: struct.CairoRenderContext.html#method.into_arena
+/// [`with_arena`]: struct.CairoRenderContext.html#method.with_arena
+pub struct CairoArena {
+    glyphs: FrameArena<Glyph>,
+    stops: FrameArena<GradientStop>,
+    layers: LayerPool<ImageSurface>,
+}
+
+impl CairoArena {
+    /// An empty arena, whose layers are charged to this thread's memory
+    /// report.
+    pub fn new() -> CairoArena {
+        CairoArena {
+            glyphs: FrameArena::new(),
+            stops: FrameArena::new(),
+            layers: MEMORY.with(|memory| LayerPool::with_memory(&memory.layers, 4)),
+        }
+    }
+
+    /// End a frame.
+    fn reset(&mut self) {
+        self.glyphs.reset();
+        self.stops.reset();
+    }
+
+    fn trim(&mut self) {
+        self.glyphs.trim();
+        self.stops.trim();
+        self.layers.trim();
+    }
+}
+
+impl Default for CairoArena {
+    fn default() -> Self {
+        CairoArena::new()
+    }
+}
 
 pub struct CairoRenderContext<'a> {
     // Cairo has this as Clone and with &self methods, but we do this to avoid
     // concurrency problems.
     ctx: &'a mut Context,
     text: CairoText<'a>,
+    glyph_positioning: GlyphPositioning,
+    image_positioning: ImagePositioning,
+    antialiasing: Antialiasing,
+    /// The tolerance of converting shapes to paths, in device pixels.
+    tolerance: f64,
+    /// The calls made so far, for `draw_profile`.
+    profiler: Profiler,
+    /// The saves not yet restored, each true if `push_clip` made it. Cairo
+    /// treats a restore without a save as an error it never recovers from,
+    /// so we check first.
+    saves: Vec<bool>,
+    /// An error to report from `status`, for those cairo would never
+    /// recover from, which are caught before they reach it.
+    err: Result<(), Error>,
+    arena: CairoArena,
 }
 
 impl<'a> CairoRenderContext<'a> {
@@ -35,10 +130,129 @@ impl<'a> CairoRenderContext<'a> {
     /// we change to a more sophisticated text layout approach, we'll probably
     /// need a factory for that as an additional argument.
     pub fn new(ctx: &mut Context) -> CairoRenderContext {
+        CairoRenderContext::with_arena(ctx, CairoArena::new())
+    }
+
+    /// Create a back-end that reuses `arena`, kept from an earlier context
+    /// with [`into_arena`].
+    ///
+    /// [`into_arena`]: #method.into_arena
+    pub fn with_arena(ctx: &mut Context, arena: CairoArena) -> CairoRenderContext<'_> {
         CairoRenderContext {
             ctx,
             text: CairoText::new(),
+            glyph_positioning: GlyphPositioning::default(),
+            image_positioning: ImagePositioning::default(),
+            antialiasing: Antialiasing::default(),
+            tolerance: DEFAULT_TOLERANCE,
+            profiler: Profiler::new(),
+            saves: Vec::new(),
+            err: Ok(()),
+            arena,
+        }
+    }
+
+    /// The context's buffers and layers, for the next frame's context to
+    /// reuse.
+    pub fn into_arena(self) -> CairoArena {
+        self.arena
+    }
+}
+
+/// An image surface, with the format its pixels were given in.
+#[derive(Clone)]
+pub struct CairoImage {
+    surface: ImageSurface,
+    format: ImageFormat,
+    /// Shared by the clones, which share the surface.
+    _memory: Rc<MemoryCharge>,
+}
+
+/// A path for drawing with a cairo context, made with `make_path`.
+pub struct CairoPath {
+    path: cairo::Path,
+    bounding_box: Rect,
+}
+
+impl CairoPath {
+    pub fn path(&self) -> &cairo::Path {
+        &self.path
+    }
+}
+
+impl CairoImage {
+    fn new(surface: ImageSurface, format: ImageFormat) -> CairoImage {
+        let bytes = surface.get_stride() as usize * surface.get_height() as usize;
+        CairoImage {
+            surface,
+            format,
+            _memory: Rc::new(MEMORY.with(|memory| memory.images.charge(bytes))),
+        }
+    }
+
+    pub fn surface(&self) -> &ImageSurface {
+        &self.surface
+    }
+
+    pub fn into_surface(self) -> ImageSurface {
+        self.surface
+    }
+
+    /// Copy the pixels, for another thread to hold.
+    ///
+    /// The copy is in the form cairo keeps them: premultiplied RGBA for
+    /// images with alpha, RGB for those without, and alpha for masks.
+    pub fn to_owned_image(&self) -> Result<OwnedImage, Error> {
+        let cairo_fmt = self.surface.get_format();
+        let (width, height) = (self.surface.get_width(), self.surface.get_height());
+        // The surface may be shared with clones and patterns, so its data
+        // can't be borrowed; paint it into one that isn't.
+        let mut copy = ImageSurface::create(cairo_fmt, width, height).wrap()?;
+        {
+            let ctx = Context::new(&copy);
+            ctx.set_operator(Operator::Source);
+            ctx.set_source_surface(&self.surface, 0.0, 0.0);
+            ctx.paint();
+        }
+        copy.flush();
+        let (width, height) = (width as usize, height as usize);
+        let stride = copy.get_stride() as usize;
+        let data = copy.get_data().wrap()?;
+        let format = match cairo_fmt {
+            Format::ARgb32 => ImageFormat::RgbaPremul,
+            Format::Rgb24 => ImageFormat::Rgb,
+            Format::A8 => ImageFormat::Alpha,
+            _ => return Err(new_error(ErrorKind::NotSupported)),
+        };
+        let mut pixels = Vec::with_capacity(width * height * format.bytes_per_pixel());
+        for row in data.chunks(stride).take(height) {
+            if format == ImageFormat::Alpha {
+                pixels.extend_from_slice(&row[..width]);
+                continue;
+            }
+            for pixel in row[..width * 4].chunks_exact(4) {
+                // native-endian words, alpha in the high byte
+                let word = u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                let [a, r, g, b] = word.to_be_bytes();
+                if format == ImageFormat::Rgb {
+                    pixels.extend_from_slice(&[r, g, b]);
+                } else {
+                    pixels.extend_from_slice(&[r, g, b, a]);
+                }
+            }
         }
+        OwnedImage::new(width, height, pixels, format)
+    }
+}
+
+impl Image for CairoImage {
+    fn size(&self) -> Size {
+        let (width, height) = (self.surface.get_width(), self.surface.get_height());
+        Size::new(width as f64, height as f64)
+    }
+
+    fn format(&self) -> ImageFormat {
+        self.format
     }
 }
 
@@ -47,6 +261,7 @@ pub enum Brush {
     Solid(u32),
     Linear(cairo::LinearGradient),
     Radial(cairo::RadialGradient),
+    Mesh(cairo::Mesh),
 }
 
 #[derive(Debug)]
@@ -107,9 +322,12 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
     type Text = CairoText<'a>;
     type TextLayout = CairoTextLayout;
 
-    type Image = ImageSurface;
+    type Image = CairoImage;
+
+    type Path = CairoPath;
 
     fn status(&mut self) -> Result<(), Error> {
+        std::mem::replace(&mut self.err, Ok(()))?;
         let status = self.ctx.status();
         if status == Status::Success {
             Ok(())
@@ -120,13 +338,52 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
     }
 
     fn clear(&mut self, color: Color) {
-        let rgba = color.as_rgba_u32();
-        self.ctx.set_source_rgb(
-            byte_to_frac(rgba >> 24),
-            byte_to_frac(rgba >> 16),
-            byte_to_frac(rgba >> 8),
-        );
+        let _timer = self.profiler.time(DrawCall::Fill);
+        self.ctx.save();
+        self.ctx.set_operator(Operator::Source);
+        self.set_brush(&Brush::Solid(color.as_rgba_u32()));
         self.ctx.paint();
+        self.ctx.restore();
+    }
+
+    fn clear_rect(&mut self, rect: Rect, color: Color) {
+        let _timer = self.profiler.time(DrawCall::Fill);
+        self.ctx.save();
+        self.ctx.set_operator(Operator::Source);
+        self.set_brush(&Brush::Solid(color.as_rgba_u32()));
+        self.set_path(rect);
+        self.ctx.fill();
+        self.ctx.restore();
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            text: true,
+            images: true,
+            image_formats: &[
+                ImageFormat::Rgb,
+                ImageFormat::RgbaSeparate,
+                ImageFormat::RgbaPremul,
+                ImageFormat::BgraPremul,
+                ImageFormat::Grayscale,
+                ImageFormat::Alpha,
+            ],
+            nearest_neighbor_interpolation: true,
+            linear_gradients: true,
+            radial_gradients: true,
+            mesh_gradients: true,
+            dashes: true,
+            read_back: true,
+            render_targets: true,
+            antialiasing: true,
+            picking: false,
+            // the toy font API only finds fonts through fontconfig
+            font_loading: false,
+            vertical_text: false,
+            // font options take variations from cairo 1.16
+            variable_fonts: cfg!(feature = "variable-fonts"),
+            glyph_drawing: true,
+        }
     }
 
     fn solid_brush(&mut self, color: Color) -> Brush {
@@ -139,7 +396,12 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
                 let (x0, y0) = (linear.start.x, linear.start.y);
                 let (x1, y1) = (linear.end.x, linear.end.y);
                 let lg = cairo::LinearGradient::new(x0, y0, x1, y1);
-                set_gradient_stops!(&lg, &linear.stops);
+                let stops = self
+                    .arena
+                    .stops
+                    .collect(linear.color_space.resolved_stops(&linear.stops));
+                set_gradient_stops!(&lg, &stops);
+                self.arena.stops.give_back(stops);
                 Ok(Brush::Linear(lg))
             }
             FixedGradient::Radial(radial) => {
@@ -147,13 +409,26 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
                 let (xo, yo) = (radial.origin_offset.x, radial.origin_offset.y);
                 let r = radial.radius;
                 let rg = cairo::RadialGradient::new(xc + xo, yc + yo, 0.0, xc, yc, r);
-                set_gradient_stops!(&rg, &radial.stops);
+                let stops = self
+                    .arena
+                    .stops
+                    .collect(radial.color_space.resolved_stops(&radial.stops));
+                set_gradient_stops!(&rg, &stops);
+                self.arena.stops.give_back(stops);
                 Ok(Brush::Radial(rg))
             }
+            FixedGradient::Mesh(mesh) => {
+                let pattern = cairo::Mesh::new();
+                for patch in &mesh.patches {
+                    add_mesh_patch(&pattern, patch);
+                }
+                Ok(Brush::Mesh(pattern))
+            }
         }
     }
 
     fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
         self.set_brush(&*brush);
@@ -162,6 +437,7 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
     }
 
     fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
         self.set_brush(&*brush);
@@ -169,13 +445,150 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         self.ctx.fill();
     }
 
+    fn fill_rects(&mut self, rects: &[Rect], brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
+        if rects.is_empty() {
+            return;
+        }
+        let brush = brush.make_brush(self, || {
+            rects
+                .iter()
+                .fold(rects[0].abs(), |bbox, rect| bbox.union(rect.abs()))
+        });
+        self.ctx.new_path();
+        for rect in rects {
+            // the same way round, so overlaps don't cancel out
+            let rect = rect.abs();
+            self.ctx
+                .rectangle(rect.x0, rect.y0, rect.width(), rect.height());
+        }
+        self.set_brush(&*brush);
+        self.ctx.set_fill_rule(cairo::FillRule::Winding);
+        self.ctx.fill();
+    }
+
     fn clip(&mut self, shape: impl Shape) {
+        let _timer = self.profiler.time(DrawCall::State);
+        // cairo clips with the current antialias mode, like it fills
         self.set_path(shape);
         self.ctx.set_fill_rule(cairo::FillRule::Winding);
         self.ctx.clip();
     }
 
+    fn clip_text(&mut self, layout: &Self::TextLayout, pos: impl Into<Point>) {
+        let _timer = self.profiler.time(DrawCall::State);
+        let pos = self.text_origin(pos.into());
+        let fonts = layout.cluster_fonts();
+        self.ctx.new_path();
+        let mut glyphs = layout.glyphs.as_slice();
+        while let Some(&(cluster, _)) = glyphs.first() {
+            // one path per font, for the runs of a styled layout
+            let font = fonts.get(cluster).copied().unwrap_or(&layout.font);
+            let len = glyphs
+                .iter()
+                .take_while(|(c, _)| {
+                    std::ptr::eq(fonts.get(*c).copied().unwrap_or(&layout.font), font)
+                })
+                .count();
+            let (run, rest) = glyphs.split_at(len);
+            glyphs = rest;
+            let run = run.iter().map(|&(_, glyph)| Glyph {
+                x: glyph.x + pos.x,
+                y: glyph.y + pos.y,
+                ..glyph
+            });
+            self.set_scaled_font(font);
+            let run = self.arena.glyphs.collect(run);
+            self.ctx.glyph_path(&run);
+            self.arena.glyphs.give_back(run);
+        }
+        self.ctx.set_fill_rule(cairo::FillRule::Winding);
+        self.ctx.clip();
+    }
+
+    fn push_clip(&mut self, shape: impl Shape) {
+        let _timer = self.profiler.time(DrawCall::State);
+        self.ctx.save();
+        self.saves.push(true);
+        self.clip(shape);
+    }
+
+    fn pop_clip(&mut self) -> Result<(), Error> {
+        let _timer = self.profiler.time(DrawCall::State);
+        if self.saves.last() != Some(&true) {
+            return Err(new_error(ErrorKind::StackUnbalance));
+        }
+        let matrix = self.ctx.get_matrix();
+        self.restore_one();
+        self.ctx.set_matrix(matrix);
+        self.status()
+    }
+
+    fn reset_clip(&mut self) {
+        let _timer = self.profiler.time(DrawCall::State);
+        while self.pop_clip().is_ok() {}
+        if self.saves.is_empty() {
+            self.ctx.reset_clip();
+        } else {
+            // cairo's reset_clip would remove the clips from before the save
+            // too, so go back to the save and make it again
+            let matrix = self.ctx.get_matrix();
+            self.ctx.restore();
+            self.ctx.save();
+            self.ctx.set_matrix(matrix);
+            self.ctx
+                .set_antialias(antialias_from_mode(self.antialiasing));
+        }
+    }
+
+    fn make_path(&mut self, shape: impl Shape) -> Result<Self::Path, Error> {
+        let bounding_box = shape.bounding_box();
+        self.set_path(shape);
+        // copied in user space, so it follows the transform when drawn
+        let path = self.ctx.copy_path();
+        self.ctx.new_path();
+        self.status()?;
+        Ok(CairoPath { path, bounding_box })
+    }
+
+    fn fill_path(&mut self, path: &Self::Path, brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
+        let brush = brush.make_brush(self, || path.bounding_box);
+        self.ctx.new_path();
+        self.ctx.append_path(&path.path);
+        self.set_brush(&brush);
+        self.ctx.set_fill_rule(cairo::FillRule::Winding);
+        self.ctx.fill();
+    }
+
+    fn stroke_path(&mut self, path: &Self::Path, brush: &impl IntoBrush<Self>, width: f64) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
+        let brush = brush.make_brush(self, || path.bounding_box);
+        self.ctx.new_path();
+        self.ctx.append_path(&path.path);
+        self.set_stroke(width, None);
+        self.set_brush(&brush);
+        self.ctx.stroke();
+    }
+
+    fn stroke_path_styled(
+        &mut self,
+        path: &Self::Path,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
+        let brush = brush.make_brush(self, || path.bounding_box);
+        self.ctx.new_path();
+        self.ctx.append_path(&path.path);
+        self.set_stroke(width, Some(style));
+        self.set_brush(&brush);
+        self.ctx.stroke();
+    }
+
     fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
         self.set_stroke(width, None);
@@ -190,6 +603,10 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         width: f64,
         style: &StrokeStyle,
     ) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
+        if let Some((path, style)) = align_stroke(&shape, width, style) {
+            return self.stroke_styled(path, brush, width, &style);
+        }
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
         self.set_stroke(width, Some(style));
@@ -197,8 +614,8 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         self.ctx.stroke();
     }
 
-    fn text(&mut self) -> &mut Self::Text {
-        &mut self.text
+    fn text(&self) -> Self::Text {
+        self.text.clone()
     }
 
     fn draw_text(
@@ -207,31 +624,205 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         pos: impl Into<Point>,
         brush: &impl IntoBrush<Self>,
     ) {
+        let _timer = self.profiler.time(DrawCall::Text);
         // TODO: bounding box for text
         let brush = brush.make_brush(self, || Rect::ZERO);
-        self.ctx.set_scaled_font(&layout.font);
-        self.set_brush(&*brush);
-        let pos = pos.into();
-        self.ctx.move_to(pos.x, pos.y);
-        self.ctx.show_text(&layout.text);
+        let pos = self.prepare_text(layout, pos.into(), &brush);
+        match layout.split_runs() {
+            None => {
+                self.ctx.move_to(pos.x, pos.y);
+                self.ctx.show_text(&layout.text);
+            }
+            Some((bitmaps, runs)) => {
+                for (font, color, glyphs) in runs {
+                    self.set_scaled_font(font);
+                    match color {
+                        Some(color) => self.set_brush(&Brush::Solid(color.as_rgba_u32())),
+                        None => self.set_brush(&brush),
+                    }
+                    let glyphs = glyphs.into_iter().map(|glyph| Glyph {
+                        x: glyph.x + pos.x,
+                        y: glyph.y + pos.y,
+                        ..glyph
+                    });
+                    self.show_glyphs(glyphs);
+                }
+                for (rect, bitmap) in bitmaps {
+                    let rect = bitmap.fit(rect + pos.to_vec2());
+                    if let Ok(image) = self.make_image(
+                        bitmap.width(),
+                        bitmap.height(),
+                        bitmap.buf(),
+                        ImageFormat::RgbaSeparate,
+                    ) {
+                        self.draw_image(&image, rect, InterpolationMode::Bilinear);
+                    }
+                }
+            }
+        }
+        self.draw_decorations(layout, pos, &brush);
+    }
+
+    fn draw_glyph_run(
+        &mut self,
+        layout: &Self::TextLayout,
+        pos: impl Into<Point>,
+        brush: &impl IntoBrush<Self>,
+        transforms: &[Affine],
+    ) {
+        let _timer = self.profiler.time(DrawCall::Text);
+        let brush = brush.make_brush(self, || Rect::ZERO);
+        let pos = self.prepare_text(layout, pos.into(), &brush);
+        let colors = layout.model.cluster_colors();
+        let fonts = layout.cluster_fonts();
+        let mut glyphs = layout.glyphs.as_slice();
+        while let Some(&(cluster, first)) = glyphs.first() {
+            let len = glyphs.iter().take_while(|(c, _)| *c == cluster).count();
+            let (run, rest) = glyphs.split_at(len);
+            glyphs = rest;
+            // glyphs relative to the cluster's origin
+            let run = run.iter().map(|(_, glyph)| Glyph {
+                x: glyph.x - first.x,
+                y: glyph.y - first.y,
+                ..*glyph
+            });
+            let origin = Affine::translate((pos.x + first.x, pos.y + first.y));
+            let transform = transforms.get(cluster).copied().unwrap_or_default();
+            match colors.get(cluster).copied().flatten() {
+                Some(color) => self.set_brush(&Brush::Solid(color.as_rgba_u32())),
+                None => self.set_brush(&brush),
+            }
+            self.set_scaled_font(fonts.get(cluster).copied().unwrap_or(&layout.font));
+            self.ctx.save();
+            self.ctx.transform(affine_to_matrix(origin * transform));
+            self.show_glyphs(run);
+            self.ctx.restore();
+        }
+        self.draw_decorations(layout, pos, &brush);
+    }
+
+    fn draw_glyphs(
+        &mut self,
+        font: &CairoFont,
+        glyphs: &[(u16, Point)],
+        brush: &impl IntoBrush<Self>,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Text);
+        let brush = brush.make_brush(self, || Rect::ZERO);
+        self.set_scaled_font(font.scaled_font());
+        self.set_brush(&brush);
+        let glyphs = glyphs.iter().map(|&(index, pos)| Glyph {
+            index: index.into(),
+            x: pos.x,
+            y: pos.y,
+        });
+        self.show_glyphs(glyphs);
+    }
+
+    fn draw_grid_row(
+        &mut self,
+        font: &CairoFont,
+        metrics: &GridMetrics,
+        origin: impl Into<Point>,
+        cells: &[GridCell],
+    ) {
+        let _timer = self.profiler.time(DrawCall::Text);
+        let origin = match self.glyph_positioning {
+            GlyphPositioning::PixelSnapped => self.snap_to_device_pixel(origin.into()),
+            GlyphPositioning::Subpixel => origin.into(),
+        };
+        for (range, color) in grid_background_runs(cells) {
+            self.fill(metrics.cells_rect(origin, range), &color);
+        }
+        self.set_scaled_font(font.scaled_font());
+        let y = origin.y + metrics.baseline;
+        for (range, color) in grid_foreground_runs(cells) {
+            self.set_brush(&Brush::Solid(color.as_rgba_u32()));
+            let mut glyphs = Vec::with_capacity(range.len());
+            for (cell, i) in cells[range.clone()].iter().zip(range) {
+                let x = origin.x + i as f64 * metrics.cell_size.width;
+                match font.grid_glyph(cell.ch) {
+                    Some(glyph) => glyphs.push(Glyph { x, y, ..glyph }),
+                    // let the toy API find a fallback
+                    None => {
+                        self.ctx.move_to(x, y);
+                        self.ctx.show_text(cell.ch.encode_utf8(&mut [0; 4]));
+                    }
+                }
+            }
+            self.ctx.show_glyphs(&glyphs);
+        }
+    }
+
+    fn set_glyph_positioning(&mut self, positioning: GlyphPositioning) {
+        self.glyph_positioning = positioning;
+    }
+
+    fn set_image_positioning(&mut self, positioning: ImagePositioning) {
+        self.image_positioning = positioning;
+    }
+
+    fn set_tolerance(&mut self, tolerance: f64) {
+        if tolerance > 0.0 && tolerance.is_finite() {
+            self.tolerance = tolerance;
+        }
+    }
+
+    fn tolerance(&self) -> f64 {
+        self.tolerance
     }
 
+    fn set_antialiasing(&mut self, mode: Antialiasing) {
+        self.antialiasing = mode;
+        self.ctx.set_antialias(antialias_from_mode(mode));
+    }
+
+    fn set_pick_id(&mut self, _id: Option<u64>) {}
+
+    // cairo has every feature the policy covers
+    fn set_fallback_policy(&mut self, _policy: FallbackPolicy) {}
+
     fn save(&mut self) -> Result<(), Error> {
+        let _timer = self.profiler.time(DrawCall::State);
         self.ctx.save();
+        self.saves.push(false);
         self.status()
     }
 
     fn restore(&mut self) -> Result<(), Error> {
-        self.ctx.restore();
+        let _timer = self.profiler.time(DrawCall::State);
+        // clips pushed since the save go with it
+        let depth = self
+            .saves
+            .iter()
+            .rposition(|&pushed_clip| !pushed_clip)
+            .ok_or_else(|| new_error(ErrorKind::StackUnbalance))?;
+        while self.saves.len() > depth {
+            self.restore_one();
+        }
         self.status()
     }
 
     fn finish(&mut self) -> Result<(), Error> {
+        let balanced = piet::check_usage(
+            self.saves.is_empty(),
+            ErrorKind::StackUnbalance,
+            "finish called with saves not restored or clips not popped",
+        );
+        self.arena.reset();
+        balanced?;
         self.status()
     }
 
     fn transform(&mut self, transform: Affine) {
-        self.ctx.transform(affine_to_matrix(transform));
+        let _timer = self.profiler.time(DrawCall::State);
+        // a matrix cairo can't invert puts the context in an error state for
+        // good, so it's not passed on
+        if is_invertible(self.current_transform() * transform) {
+            self.ctx.transform(affine_to_matrix(transform));
+        } else {
+            self.err = Err(new_error(ErrorKind::InvalidInput));
+        }
     }
 
     fn current_transform(&self) -> Affine {
@@ -245,9 +836,14 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         buf: &[u8],
         format: ImageFormat,
     ) -> Result<Self::Image, Error> {
+        piet::check_image_buffer(width, height, buf, format)?;
         let cairo_fmt = match format {
-            ImageFormat::Rgb => Format::Rgb24,
-            ImageFormat::RgbaSeparate | ImageFormat::RgbaPremul => Format::ARgb32,
+            // cairo has no gray format
+            ImageFormat::Rgb | ImageFormat::Grayscale => Format::Rgb24,
+            ImageFormat::RgbaSeparate | ImageFormat::RgbaPremul | ImageFormat::BgraPremul => {
+                Format::ARgb32
+            }
+            ImageFormat::Alpha => Format::A8,
             _ => return Err(new_error(ErrorKind::NotSupported)),
         };
         let mut image = ImageSurface::create(cairo_fmt, width as i32, height as i32).wrap()?;
@@ -271,7 +867,6 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
                     ImageFormat::RgbaPremul => {
                         // It's annoying that Cairo exposes only ARGB. Ah well. Let's
                         // hope that LLVM generates pretty good code for this.
-                        // TODO: consider adding BgraPremul format.
                         for x in 0..width {
                             data[dst_off + x * 4 + 0] = buf[src_off + x * 4 + 2];
                             data[dst_off + x * 4 + 1] = buf[src_off + x * 4 + 1];
@@ -279,11 +874,20 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
                             data[dst_off + x * 4 + 3] = buf[src_off + x * 4 + 3];
                         }
                     }
-                    ImageFormat::RgbaSeparate => {
-                        fn premul(x: u8, a: u8) -> u8 {
-                            let y = (x as u16) * (a as u16);
-                            ((y + (y >> 8) + 0x80) >> 8) as u8
+                    ImageFormat::BgraPremul => {
+                        let src = &buf[src_off..src_off + bytes_per_row];
+                        let dst = &mut data[dst_off..dst_off + bytes_per_row];
+                        // ARGB32 pixels are native-endian words, so BGRA in
+                        // memory on little-endian machines.
+                        if cfg!(target_endian = "little") {
+                            dst.copy_from_slice(src);
+                        } else {
+                            for (dst, src) in dst.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+                                dst.copy_from_slice(&[src[3], src[2], src[1], src[0]]);
+                            }
                         }
+                    }
+                    ImageFormat::RgbaSeparate => {
                         for x in 0..width {
                             let a = buf[src_off + x * 4 + 3];
                             data[dst_off + x * 4 + 0] = premul(buf[src_off + x * 4 + 2], a);
@@ -292,11 +896,21 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
                             data[dst_off + x * 4 + 3] = a;
                         }
                     }
+                    ImageFormat::Grayscale => {
+                        for x in 0..width {
+                            let gray = buf[src_off + x];
+                            data[dst_off + x * 4..dst_off + x * 4 + 3].copy_from_slice(&[gray; 3]);
+                        }
+                    }
+                    ImageFormat::Alpha => {
+                        data[dst_off..dst_off + width]
+                            .copy_from_slice(&buf[src_off..src_off + width]);
+                    }
                     _ => return Err(new_error(ErrorKind::NotSupported)),
                 }
             }
         }
-        Ok(image)
+        Ok(CairoImage::new(image, format))
     }
 
     #[inline]
@@ -306,7 +920,13 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        draw_image(self, image, None, dst_rect.into(), interp);
+        let _timer = self.profiler.time(DrawCall::Image);
+        let dst_rect = piet::position_image(
+            dst_rect.into(),
+            self.image_positioning,
+            self.current_transform(),
+        );
+        draw_image(self, image, None, dst_rect, interp);
     }
 
     #[inline]
@@ -317,88 +937,455 @@ impl<'a> RenderContext for CairoRenderContext<'a> {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        draw_image(self, image, Some(src_rect.into()), dst_rect.into(), interp);
+        let _timer = self.profiler.time(DrawCall::Image);
+        let dst_rect = piet::position_image(
+            dst_rect.into(),
+            self.image_positioning,
+            self.current_transform(),
+        );
+        draw_image(self, image, Some(src_rect.into()), dst_rect, interp);
     }
-}
 
-fn draw_image<'a>(
-    ctx: &mut CairoRenderContext<'a>,
-    image: &<CairoRenderContext<'a> as RenderContext>::Image,
-    src_rect: Option<Rect>,
-    dst_rect: Rect,
-    interp: InterpolationMode,
-) {
-    let _ = ctx.with_save(|rc| {
-        let surface_pattern = SurfacePattern::create(image);
-        let filter = match interp {
-            InterpolationMode::NearestNeighbor => Filter::Nearest,
-            InterpolationMode::Bilinear => Filter::Bilinear,
-        };
-        surface_pattern.set_filter(filter);
-        let src_rect = match src_rect {
-            Some(src_rect) => src_rect,
-            None => Rect::new(
-                0.0,
-                0.0,
-                image.get_width() as f64,
-                image.get_height() as f64,
-            ),
-        };
-        let scale_x = dst_rect.width() / src_rect.width();
-        let scale_y = dst_rect.height() / src_rect.height();
-        rc.clip(dst_rect);
-        rc.ctx.translate(
-            dst_rect.x0 - scale_x * src_rect.x0,
-            dst_rect.y0 - scale_y * src_rect.y0,
-        );
-        rc.ctx.scale(scale_x, scale_y);
-        rc.ctx.set_source(&surface_pattern);
-        rc.ctx.paint();
-        Ok(())
-    });
-}
+    fn draw_image_transformed(
+        &mut self,
+        image: &Self::Image,
+        transform: Affine,
+        interp: InterpolationMode,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        if transform.determinant() == 0.0 {
+            return;
+        }
+        let pattern = SurfacePattern::create(&image.surface);
+        pattern.set_filter(convert_interp(interp));
+        pattern.set_matrix(affine_to_matrix(transform.inverse()));
+        // the image's outline, which may be any parallelogram
+        self.ctx.new_path();
+        let outline = image.size().to_rect();
+        self.append_path(shape_elements(&outline, 1e-3).map(|el| transform * el));
+        self.ctx.set_source(&pattern);
+        self.ctx.set_fill_rule(cairo::FillRule::Winding);
+        self.ctx.fill();
+    }
 
-impl<'a> IntoBrush<CairoRenderContext<'a>> for Brush {
-    fn make_brush<'b>(
-        &'b self,
-        _piet: &mut CairoRenderContext,
-        _bbox: impl FnOnce() -> Rect,
-    ) -> std::borrow::Cow<'b, Brush> {
-        Cow::Borrowed(self)
+    fn draw_tiles(
+        &mut self,
+        atlas: &Self::Image,
+        tiles: &[(Rect, Point)],
+        interp: InterpolationMode,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        let pattern = SurfacePattern::create(&atlas.surface);
+        pattern.set_filter(convert_interp(interp));
+        for &(src_rect, dst_pos) in tiles {
+            let dst_rect = piet::position_image(
+                Rect::from_origin_size(dst_pos, src_rect.size()),
+                self.image_positioning,
+                self.current_transform(),
+            );
+            if dst_rect.area() == 0.0 || src_rect.area() == 0.0 {
+                continue;
+            }
+            // one pattern for the whole atlas, moved under each tile
+            let to_user = image_to_user(src_rect, dst_rect);
+            if !is_invertible(to_user) {
+                continue;
+            }
+            pattern.set_matrix(affine_to_matrix(to_user.inverse()));
+            self.set_path(dst_rect);
+            self.ctx.set_source(&pattern);
+            self.ctx.fill();
+        }
     }
-}
 
-fn convert_line_cap(line_cap: LineCap) -> cairo::LineCap {
-    match line_cap {
-        LineCap::Butt => cairo::LineCap::Butt,
-        LineCap::Round => cairo::LineCap::Round,
-        LineCap::Square => cairo::LineCap::Square,
+    fn draw_images(&mut self, images: &[(&Self::Image, Rect, f64)], interp: InterpolationMode) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        for &(image, dst_rect, alpha) in images {
+            let dst_rect =
+                piet::position_image(dst_rect, self.image_positioning, self.current_transform());
+            if dst_rect.area() == 0.0 || alpha.is_nan() || alpha <= 0.0 {
+                continue;
+            }
+            let pattern = match image_pattern(image, image.size().to_rect(), dst_rect, interp) {
+                Some(pattern) => pattern,
+                None => continue,
+            };
+            self.set_path(dst_rect);
+            self.ctx.set_source(&pattern);
+            if alpha >= 1.0 {
+                self.ctx.fill();
+            } else {
+                // fills have no alpha, so paint through a clip
+                self.ctx.save();
+                self.ctx.clip();
+                self.ctx.paint_with_alpha(alpha);
+                self.ctx.restore();
+            }
+        }
     }
-}
 
-fn convert_line_join(line_join: LineJoin) -> cairo::LineJoin {
-    match line_join {
-        LineJoin::Miter => cairo::LineJoin::Miter,
-        LineJoin::Round => cairo::LineJoin::Round,
-        LineJoin::Bevel => cairo::LineJoin::Bevel,
+    fn make_render_target(&mut self, width: usize, height: usize) -> Result<Self::Image, Error> {
+        let image = ImageSurface::create(Format::ARgb32, width as i32, height as i32).wrap()?;
+        Ok(CairoImage::new(image, ImageFormat::RgbaPremul))
     }
-}
 
-impl<'a> CairoRenderContext<'a> {
-    /// Set the source pattern to the brush.
-    ///
-    /// Cairo is super stateful, and we're trying to have more retained stuff.
-    /// This is part of the impedance matching.
-    fn set_brush(&mut self, brush: &Brush) {
-        match *brush {
-            Brush::Solid(rgba) => self.ctx.set_source_rgba(
-                byte_to_frac(rgba >> 24),
-                byte_to_frac(rgba >> 16),
-                byte_to_frac(rgba >> 8),
-                byte_to_frac(rgba),
+    fn draw_to_target(
+        &mut self,
+        target: &mut Self::Image,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        // images without alpha or with only alpha can't keep what's drawn
+        if target.surface.get_format() != Format::ARgb32 {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        // Swapping the cairo context in place keeps the caller's state intact
+        // in the one we swapped out.
+        let mut ctx = Context::new(&target.surface);
+        ctx.set_antialias(antialias_from_mode(self.antialiasing));
+        std::mem::swap(self.ctx, &mut ctx);
+        // saves left in the swapped in context go with it
+        let saves = std::mem::take(&mut self.saves);
+        let result = f(self);
+        self.saves = saves;
+        std::mem::swap(self.ctx, &mut ctx);
+        drop(ctx);
+        target.surface.flush();
+        result
+    }
+
+    fn capture_image_area(&mut self, src_rect: impl Into<Rect>) -> Result<Self::Image, Error> {
+        let device_rect = self
+            .current_transform()
+            .transform_rect_bbox(src_rect.into())
+            .expand();
+        let (width, height) = (device_rect.width() as i32, device_rect.height() as i32);
+        if width <= 0 || height <= 0 {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        let image = ImageSurface::create(Format::ARgb32, width, height).wrap()?;
+        let ctx = Context::new(&image);
+        ctx.set_source_surface(&self.ctx.get_target(), -device_rect.x0, -device_rect.y0);
+        ctx.set_operator(Operator::Source);
+        ctx.paint();
+        drop(ctx);
+        image.flush();
+        Ok(CairoImage::new(image, ImageFormat::RgbaPremul))
+    }
+
+    fn with_blur(
+        &mut self,
+        radius: f64,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let transform = self.current_transform();
+        let std_dev = radius.abs() * transform.determinant().abs().sqrt();
+        // The layer covers the clip plus what can blur into it.
+        let spread = if std_dev.is_finite() {
+            (3.0 * std_dev).ceil()
+        } else {
+            0.0
+        };
+        let (x0, y0, x1, y1) = self.ctx.clip_extents();
+        let area = transform
+            .transform_rect_bbox(Rect::new(x0, y0, x1, y1))
+            .inflate(spread, spread)
+            .expand();
+        if area.width() <= 0.0 || area.height() <= 0.0 {
+            return Ok(());
+        }
+        let (width, height) = (area.width() as usize, area.height() as usize);
+        let mut pooled = self
+            .arena
+            .layers
+            .take(width, height, |w, h| {
+                ImageSurface::create(Format::ARgb32, w as i32, h as i32)
+            })
+            .wrap()?;
+        let result = self.draw_blurred(pooled.surface_mut(), area, std_dev, f);
+        self.arena.layers.give_back(pooled);
+        result
+    }
+
+    fn trim_layer_pool(&mut self) {
+        self.arena.layers.trim();
+    }
+
+    fn memory_report(&self) -> MemoryReport {
+        memory_report()
+    }
+
+    fn trim_caches(&mut self) {
+        self.arena.trim();
+        trim_caches();
+    }
+
+    fn draw_profile(&self) -> DrawProfile {
+        self.profiler.profile()
+    }
+
+    fn reset_draw_profile(&mut self) {
+        self.profiler.reset();
+    }
+
+    fn draw_image_nine(
+        &mut self,
+        image: &Self::Image,
+        insets: Insets,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        let dst_rect = piet::position_image(
+            dst_rect.into(),
+            self.image_positioning,
+            self.current_transform(),
+        );
+        for (src, dst) in piet::nine_slice(image.size(), insets, dst_rect) {
+            draw_image(self, image, Some(src), dst, interp);
+        }
+    }
+
+    fn draw_image_clipped(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        shape: impl Shape,
+        interp: InterpolationMode,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        let dst_rect = piet::position_image(
+            dst_rect.into(),
+            self.image_positioning,
+            self.current_transform(),
+        );
+        if dst_rect.area() == 0.0 {
+            return;
+        }
+        let pattern = match image_pattern(image, image.size().to_rect(), dst_rect, interp) {
+            Some(pattern) => pattern,
+            None => return,
+        };
+        // Filling with the image as the source antialiases the edges like
+        // any other fill; outside the image, the pattern is transparent.
+        self.set_path(shape);
+        self.ctx.set_source(&pattern);
+        self.ctx.fill();
+    }
+}
+
+fn draw_image<'a>(
+    ctx: &mut CairoRenderContext<'a>,
+    image: &<CairoRenderContext<'a> as RenderContext>::Image,
+    src_rect: Option<Rect>,
+    dst_rect: Rect,
+    interp: InterpolationMode,
+) {
+    let src_rect = match src_rect {
+        Some(src_rect) => src_rect,
+        None => image.size().to_rect(),
+    };
+    let pattern = match image_pattern(image, src_rect, dst_rect, interp) {
+        Some(pattern) => pattern,
+        None => return,
+    };
+    let _ = ctx.with_save(|rc| {
+        rc.clip(dst_rect);
+        rc.ctx.set_source(&pattern);
+        rc.ctx.paint();
+        Ok(())
+    });
+}
+
+/// A pattern drawing `src_rect` of the image in `dst_rect` of user space, or
+/// `None` if either is empty or not finite, so there's nothing to draw.
+fn image_pattern(
+    image: &CairoImage,
+    src_rect: Rect,
+    dst_rect: Rect,
+    interp: InterpolationMode,
+) -> Option<SurfacePattern> {
+    let to_user = image_to_user(src_rect, dst_rect);
+    if !is_invertible(to_user) {
+        return None;
+    }
+    let pattern = SurfacePattern::create(&image.surface);
+    pattern.set_filter(convert_interp(interp));
+    // pattern matrices map user space to pattern space
+    pattern.set_matrix(affine_to_matrix(to_user.inverse()));
+    Some(pattern)
+}
+
+/// Whether cairo can invert `affine`; it panics on matrices it can't.
+fn is_invertible(affine: Affine) -> bool {
+    let det = affine.determinant();
+    det != 0.0 && det.is_finite() && affine.as_coeffs().iter().all(|c| c.is_finite())
+}
+
+/// The transform from image pixels to user space that puts `src_rect` of an
+/// image in `dst_rect`.
+fn image_to_user(src_rect: Rect, dst_rect: Rect) -> Affine {
+    let scale_x = dst_rect.width() / src_rect.width();
+    let scale_y = dst_rect.height() / src_rect.height();
+    Affine::translate((
+        dst_rect.x0 - scale_x * src_rect.x0,
+        dst_rect.y0 - scale_y * src_rect.y0,
+    )) * Affine::scale_non_uniform(scale_x, scale_y)
+}
+
+fn convert_interp(interp: InterpolationMode) -> Filter {
+    match interp {
+        InterpolationMode::NearestNeighbor => Filter::Nearest,
+        InterpolationMode::Bilinear => Filter::Bilinear,
+    }
+}
+
+impl<'a> IntoBrush<CairoRenderContext<'a>> for Brush {
+    fn make_brush<'b>(
+        &'b self,
+        _piet: &mut CairoRenderContext,
+        _bbox: impl FnOnce() -> Rect,
+    ) -> std::borrow::Cow<'b, Brush> {
+        Cow::Borrowed(self)
+    }
+}
+
+fn convert_line_cap(line_cap: LineCap) -> cairo::LineCap {
+    match line_cap {
+        LineCap::Butt => cairo::LineCap::Butt,
+        LineCap::Round => cairo::LineCap::Round,
+        LineCap::Square => cairo::LineCap::Square,
+    }
+}
+
+fn convert_line_join(line_join: LineJoin) -> cairo::LineJoin {
+    match line_join {
+        LineJoin::Miter => cairo::LineJoin::Miter,
+        LineJoin::Round => cairo::LineJoin::Round,
+        LineJoin::Bevel => cairo::LineJoin::Bevel,
+    }
+}
+
+impl<'a> CairoRenderContext<'a> {
+    /// Get ready to draw `layout` at `pos`: paint its backgrounds, and set
+    /// the font and brush. Returns the origin to draw the glyphs at.
+    fn prepare_text(&mut self, layout: &CairoTextLayout, pos: Point, brush: &Brush) -> Point {
+        let pos = self.text_origin(pos);
+        for (rect, color) in layout.model.backgrounds() {
+            self.fill(rect + pos.to_vec2(), color);
+        }
+        self.set_scaled_font(&layout.font);
+        self.set_brush(brush);
+        pos
+    }
+
+    /// Paint the underlines and strikethroughs of `layout`, drawn at `pos`,
+    /// over its glyphs.
+    fn draw_decorations(&mut self, layout: &CairoTextLayout, pos: Point, brush: &Brush) {
+        for (rect, color) in layout.model.decorations() {
+            match color {
+                Some(color) => self.fill(rect + pos.to_vec2(), color),
+                None => self.fill(rect + pos.to_vec2(), brush),
+            }
+        }
+    }
+
+    /// Undo the last save, whether `save` or `push_clip` made it.
+    fn restore_one(&mut self) {
+        self.saves.pop();
+        self.ctx.restore();
+        // cairo saves the antialias mode, but piet doesn't
+        self.ctx
+            .set_antialias(antialias_from_mode(self.antialiasing));
+    }
+
+    /// Draw what `f` draws into the top left of `layer`, standing in for
+    /// the device `area` of the target, then blur it and paint it there.
+    fn draw_blurred(
+        &mut self,
+        layer: &mut ImageSurface,
+        area: Rect,
+        std_dev: f64,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        // With the offset, the caller's transform lands in the layer's pixels.
+        layer.set_device_offset(-area.x0, -area.y0);
+        let mut ctx = Context::new(layer);
+        // The layer may be larger than the area, and hold an earlier one.
+        ctx.rectangle(area.x0, area.y0, area.width(), area.height());
+        ctx.clip();
+        ctx.set_operator(Operator::Clear);
+        ctx.paint();
+        ctx.set_operator(Operator::Over);
+        ctx.set_matrix(affine_to_matrix(self.current_transform()));
+        ctx.set_antialias(antialias_from_mode(self.antialiasing));
+        std::mem::swap(self.ctx, &mut ctx);
+        // saves left in the swapped in context go with it
+        let saves = std::mem::take(&mut self.saves);
+        let result = f(self);
+        self.saves = saves;
+        std::mem::swap(self.ctx, &mut ctx);
+        drop(ctx);
+        layer.flush();
+        layer.set_device_offset(0.0, 0.0);
+        result?;
+        {
+            let (width, height) = (area.width() as usize, area.height() as usize);
+            let stride = layer.get_stride() as usize;
+            let mut data = layer.get_data().wrap()?;
+            piet::blur_premul(&mut data, width, height, stride, std_dev);
+        }
+        self.ctx.save();
+        self.ctx.identity_matrix();
+        self.ctx.set_source_surface(layer, area.x0, area.y0);
+        self.ctx
+            .rectangle(area.x0, area.y0, area.width(), area.height());
+        self.ctx.fill();
+        self.ctx.restore();
+        Ok(())
+    }
+
+    /// Where to put the origin of text drawn at `pos`, honoring the glyph
+    /// positioning.
+    fn text_origin(&self, pos: Point) -> Point {
+        match self.glyph_positioning {
+            GlyphPositioning::PixelSnapped => self.snap_to_device_pixel(pos),
+            GlyphPositioning::Subpixel => pos,
+        }
+    }
+
+    /// Set the font for drawing text, honoring the antialiasing mode.
+    fn set_scaled_font(&mut self, font: &ScaledFont) {
+        if self.antialiasing == Antialiasing::Default {
+            self.ctx.set_scaled_font(font);
+        } else {
+            // The context's antialias mode doesn't apply to text.
+            let mut options = font.get_font_options();
+            options.set_antialias(antialias_from_mode(self.antialiasing));
+            let font = ScaledFont::new(
+                &font.get_font_face(),
+                &font.get_font_matrix(),
+                &font.get_ctm(),
+                &options,
+            );
+            self.ctx.set_scaled_font(&font);
+        }
+    }
+
+    /// Set the source pattern to the brush.
+    ///
+    /// Cairo is super stateful, and we're trying to have more retained stuff.
+    /// This is part of the impedance matching.
+    fn set_brush(&mut self, brush: &Brush) {
+        match *brush {
+            Brush::Solid(rgba) => self.ctx.set_source_rgba(
+                byte_to_frac(rgba >> 24),
+                byte_to_frac(rgba >> 16),
+                byte_to_frac(rgba >> 8),
+                byte_to_frac(rgba),
             ),
             Brush::Linear(ref linear) => self.ctx.set_source(linear),
             Brush::Radial(ref radial) => self.ctx.set_source(radial),
+            Brush::Mesh(ref mesh) => self.ctx.set_source(mesh),
         }
     }
 
@@ -419,18 +1406,40 @@ impl<'a> CairoRenderContext<'a> {
         let miter_limit = style.and_then(|style| style.miter_limit).unwrap_or(10.0);
         self.ctx.set_miter_limit(miter_limit);
 
-        match style.and_then(|style| style.dash.as_ref()) {
+        // cairo errors on the patterns that piet draws solid, and repeats
+        // odd ones itself
+        match style.and_then(|style| style.dash_lengths()) {
             None => self.ctx.set_dash(&[], 0.0),
-            Some((dashes, offset)) => self.ctx.set_dash(dashes, *offset),
+            Some((dashes, offset)) => self.ctx.set_dash(dashes, offset),
         }
     }
 
+    /// Round a point in user space to the nearest whole device pixel.
+    fn snap_to_device_pixel(&self, point: Point) -> Point {
+        let (x, y) = self.ctx.user_to_device(point.x, point.y);
+        let (x, y) = self.ctx.device_to_user(x.round(), y.round());
+        Point::new(x, y)
+    }
+
+    /// Show `glyphs` in the current font, from a buffer of the arena.
+    fn show_glyphs(&mut self, glyphs: impl IntoIterator<Item = Glyph>) {
+        let glyphs = self.arena.glyphs.collect(glyphs);
+        self.ctx.show_glyphs(&glyphs);
+        self.arena.glyphs.give_back(glyphs);
+    }
+
     fn set_path(&mut self, shape: impl Shape) {
         // This shouldn't be necessary, we always leave the context in no-path
         // state. But just in case, and it should be harmless.
         self.ctx.new_path();
+        let tolerance = user_tolerance(self.tolerance, self.current_transform());
+        self.append_path(shape_elements(&shape, tolerance));
+    }
+
+    /// Add `path` to the current path, streamed in without copying it.
+    fn append_path(&mut self, path: impl IntoIterator<Item = PathEl>) {
         let mut last = Point::ZERO;
-        for el in shape.to_bez_path(1e-3) {
+        for el in path {
             match el {
                 PathEl::MoveTo(p) => {
                     self.ctx.move_to(p.x, p.y);
@@ -457,10 +1466,60 @@ impl<'a> CairoRenderContext<'a> {
     }
 }
 
+fn antialias_from_mode(mode: Antialiasing) -> Antialias {
+    match mode {
+        Antialiasing::Default => Antialias::Default,
+        Antialiasing::None => Antialias::None,
+        Antialiasing::Grayscale => Antialias::Gray,
+        Antialiasing::Subpixel => Antialias::Subpixel,
+    }
+}
+
 fn byte_to_frac(byte: u32) -> f64 {
     ((byte & 255) as f64) * (1.0 / 255.0)
 }
 
+/// `x` multiplied by the alpha `a`, both out of 255, rounded to nearest.
+fn premul(x: u8, a: u8) -> u8 {
+    let y = (x as u16) * (a as u16) + 0x80;
+    ((y + (y >> 8)) >> 8) as u8
+}
+
+fn add_mesh_patch(mesh: &cairo::Mesh, patch: &MeshPatch) {
+    let corners = [
+        MeshCorner::MeshCorner0,
+        MeshCorner::MeshCorner1,
+        MeshCorner::MeshCorner2,
+        MeshCorner::MeshCorner3,
+    ];
+    mesh.begin_patch();
+    mesh.move_to(patch.corners[0].x, patch.corners[0].y);
+    for i in 0..4 {
+        let end = patch.corners[(i + 1) % 4];
+        match patch.edges {
+            Some(edges) => {
+                let (c1, c2) = edges[i];
+                mesh.curve_to(c1.x, c1.y, c2.x, c2.y, end.x, end.y);
+            }
+            // cairo closes the patch itself; a line back to the start would
+            // be a fifth side
+            None if i == 3 => (),
+            None => mesh.line_to(end.x, end.y),
+        }
+    }
+    for (corner, color) in corners.iter().zip(&patch.colors) {
+        let rgba = color.as_rgba_u32();
+        mesh.set_corner_color_rgba(
+            *corner,
+            byte_to_frac(rgba >> 24),
+            byte_to_frac(rgba >> 16),
+            byte_to_frac(rgba >> 8),
+            byte_to_frac(rgba),
+        );
+    }
+    mesh.end_patch();
+}
+
 /// Can't implement RoundFrom here because both types belong to other crates.
 fn affine_to_matrix(affine: Affine) -> Matrix {
     let a = affine.as_coeffs();
@@ -479,3 +1538,1485 @@ fn matrix_to_affine(matrix: Matrix) -> Affine {
         matrix.xx, matrix.yx, matrix.xy, matrix.yy, matrix.x0, matrix.y0,
     ])
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use piet::kurbo::Vec2;
+    use piet::kurbo::{Circle, Line};
+    use piet::{
+        EmojiAtlas, EmojiBitmap, EmojiStrategy, FixedLinearGradient, FixedMeshGradient, Font,
+        FontBuilder, FontSubstitutions, GradientColorSpace, GradientStop, ImageAtlasBuilder,
+        SaveGuard, ShapeGroup, StrokeAlignment, Text, TextAttribute, TextLayout, TextLayoutBuilder,
+    };
+    use piet_test::properties::{self, EDGE_VALUES};
+    use proptest::prelude::*;
+    use std::convert::TryFrom;
+    use std::sync::Arc;
+
+    /// Draw with `f` on a clear surface `width` by `height` pixels, and
+    /// return the surface's premultiplied BGRA pixels.
+    fn render(width: i32, height: i32, f: impl FnOnce(&mut CairoRenderContext)) -> Vec<u8> {
+        let mut surface = ImageSurface::create(Format::ARgb32, width, height).unwrap();
+        {
+            let mut ctx = Context::new(&surface);
+            let mut piet = CairoRenderContext::new(&mut ctx);
+            f(&mut piet);
+            piet.finish().unwrap();
+        }
+        let data = surface.get_data().unwrap().to_vec();
+        data
+    }
+
+    /// The pixel at `x`, `y` of what `render` returned for a surface
+    /// `width` pixels wide.
+    fn pixel(data: &[u8], width: usize, x: usize, y: usize) -> &[u8] {
+        let i = (y * width + x) * 4;
+        &data[i..i + 4]
+    }
+
+    /// The alpha of the pixel at `x`, `y`.
+    fn alpha(data: &[u8], width: usize, x: usize, y: usize) -> u8 {
+        pixel(data, width, x, y)[3]
+    }
+
+    #[test]
+    fn capture_image_area() {
+        let data = render(20, 20, |piet| {
+            piet.fill(Rect::new(10.0, 0.0, 20.0, 20.0), &Color::rgb8(255, 0, 0));
+            piet.transform(Affine::scale(2.0));
+            let image = piet
+                .capture_image_area(Rect::new(2.5, 2.5, 7.5, 7.5))
+                .unwrap();
+            assert_eq!(image.size(), Size::new(10.0, 10.0));
+            assert!(piet.capture_image_area(Rect::ZERO).is_err());
+            // rotated, the image is the device box around the rect
+            piet.save().unwrap();
+            piet.transform(Affine::rotate(std::f64::consts::FRAC_PI_4));
+            let rotated = piet
+                .capture_image_area(Rect::new(0.0, 0.0, 2.0, 2.0))
+                .unwrap();
+            assert_eq!(rotated.size(), Size::new(6.0, 6.0));
+            piet.restore().unwrap();
+
+            // stash the capture in the surface's top-left corner to inspect it
+            piet.draw_image(
+                &image,
+                Rect::new(0.0, 0.0, 5.0, 5.0),
+                InterpolationMode::NearestNeighbor,
+            );
+        });
+        // left half of the capture is empty, right half is red (BGRA)
+        assert_eq!(pixel(&data, 20, 0, 0), &[0, 0, 0, 0]);
+        assert_eq!(pixel(&data, 20, 9, 1), &[0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn render_target() {
+        let mut ctx = Context::new(&ImageSurface::create(Format::ARgb32, 8, 8).unwrap());
+        let mut piet = CairoRenderContext::new(&mut ctx);
+        piet.transform(Affine::translate((3.0, 3.0)));
+        let mut image = piet.make_render_target(4, 2).unwrap();
+        piet.draw_to_target(&mut image, |rc| {
+            assert_eq!(
+                rc.current_transform().as_coeffs(),
+                [1.0, 0.0, 0.0, 1.0, 0.0, 0.0]
+            );
+            rc.fill(Rect::new(0.0, 0.0, 2.0, 2.0), &Color::rgb8(0, 0, 255));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(piet.current_transform().as_coeffs()[4..], [3.0, 3.0]);
+        // drawing again keeps what was drawn before
+        piet.draw_to_target(&mut image, |rc| {
+            rc.fill(Rect::new(3.0, 0.0, 4.0, 2.0), &Color::rgb8(255, 0, 0));
+            Ok(())
+        })
+        .unwrap();
+        piet.finish().unwrap();
+
+        let mut image = image.into_surface();
+        assert_eq!((image.get_width(), image.get_height()), (4, 2));
+        let data = image.get_data().unwrap();
+        assert_eq!(&data[0..4], &[255, 0, 0, 255]);
+        assert_eq!(&data[8..12], &[0, 0, 0, 0]);
+        assert_eq!(&data[12..16], &[0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn render_target_drawn_between_updates() {
+        let mut target = None;
+        let data = render(4, 1, |piet| {
+            let image = target.get_or_insert_with(|| piet.make_render_target(1, 1).unwrap());
+            for x in 0..2 {
+                piet.draw_to_target(image, |rc| {
+                    rc.fill(Rect::new(0.0, 0.0, 1.0, 1.0), &Color::rgba8(0, 0, 0, 0x80));
+                    Ok(())
+                })
+                .unwrap();
+                let dst = Rect::new(x as f64, 0.0, x as f64 + 1.0, 1.0);
+                piet.draw_image(image, dst, InterpolationMode::NearestNeighbor);
+            }
+        });
+        // the second draw sees both fills
+        assert!(alpha(&data, 4, 1, 0) > alpha(&data, 4, 0, 0));
+    }
+
+    #[test]
+    fn render_target_needs_alpha() {
+        render(8, 8, |piet| {
+            let mut image = piet.make_image(1, 1, &[0; 3], ImageFormat::Rgb).unwrap();
+            let err = piet.draw_to_target(&mut image, |_| Ok(())).unwrap_err();
+            assert_eq!(err.to_string(), "Invalid input");
+        });
+    }
+
+    #[test]
+    fn antialiasing_none() {
+        let data = render(8, 8, |piet| {
+            piet.set_antialiasing(Antialiasing::None);
+            // the mode outlives a save/restore pair
+            piet.save().unwrap();
+            piet.restore().unwrap();
+            piet.fill(Circle::new((4.0, 4.0), 2.7), &Color::BLACK);
+        });
+        assert!(data.chunks(4).all(|px| px[3] == 0 || px[3] == 255));
+        assert!(data.chunks(4).any(|px| px[3] == 255));
+    }
+
+    #[test]
+    fn stroke_hairline() {
+        let data = render(16, 16, |piet| {
+            piet.transform(Affine::scale(3.0));
+            piet.stroke_hairline(Line::new((1.1, 1.0), (1.1, 4.0)), &Color::BLACK);
+        });
+        let stride = 16 * 4;
+        // a single fully opaque column, at device x = 3
+        let row: Vec<u8> = (0..16).map(|x| data[6 * stride + x * 4 + 3]).collect();
+        let mut expected = vec![0; 16];
+        expected[3] = 255;
+        assert_eq!(row, expected);
+    }
+
+    #[test]
+    fn mesh_gradient() {
+        let data = render(12, 10, |piet| {
+            let red = Color::rgb8(255, 0, 0);
+            let blue = Color::rgb8(0, 0, 255);
+            let patch = MeshPatch::new(
+                [
+                    Point::new(0.0, 0.0),
+                    Point::new(10.0, 0.0),
+                    Point::new(10.0, 10.0),
+                    Point::new(0.0, 10.0),
+                ],
+                [red.clone(), blue.clone(), blue, red],
+            );
+            let brush = piet
+                .gradient(FixedMeshGradient {
+                    patches: vec![patch],
+                })
+                .unwrap();
+            piet.fill(Rect::new(0.0, 0.0, 12.0, 10.0), &brush);
+        });
+        let (left, right) = (pixel(&data, 12, 0, 5), pixel(&data, 12, 9, 5));
+        // BGRA: red on the left, blue on the right, nothing outside the patch
+        assert!(left[2] > 200 && left[0] < 50);
+        assert!(right[0] > 200 && right[2] < 50);
+        assert_eq!(pixel(&data, 12, 11, 5), &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn text_handles_share_settings() {
+        render(40, 30, |piet| {
+            let mut text = piet.text();
+            let mut subs = FontSubstitutions::new();
+            subs.insert("No Such Family Piet Test", "monospace");
+            text.set_font_substitutions(subs);
+            // set on one handle, seen by the next
+            let font = piet
+                .text()
+                .new_font_by_name("no such family piet test", 12.0)
+                .build()
+                .unwrap();
+            assert_eq!(font.warnings().len(), 1);
+
+            // the handle is held across drawing
+            let brush = piet.solid_brush(Color::BLACK);
+            for label in &["one", "two"] {
+                let layout = text.new_text_layout(&font, label).build().unwrap();
+                piet.draw_text(&layout, (0.0, 20.0), &brush);
+            }
+        });
+    }
+
+    #[test]
+    fn text_background() {
+        let mut bounds = Rect::ZERO;
+        let data = render(40, 30, |piet| {
+            let font = piet
+                .text()
+                .new_font_by_name("sans-serif", 12.0)
+                .build()
+                .unwrap();
+            // spaces, so the glyphs don't cover the background
+            let layout = piet
+                .text()
+                .new_text_layout(&font, "    ")
+                .range_attribute(1..3, TextAttribute::Background(Color::rgb8(0, 0, 255)))
+                .build()
+                .unwrap();
+            bounds = layout.range_bounds(1..3).unwrap() + Vec2::new(0.0, 20.0);
+            piet.draw_text(&layout, (0.0, 20.0), &Color::BLACK);
+        });
+        let at = |p: Point| pixel(&data, 40, p.x as usize, p.y as usize);
+        let center = bounds.center();
+        assert_eq!(at(center), &[255, 0, 0, 255]);
+        assert_eq!(at(Point::new(bounds.x0 - 1.0, center.y)), &[0, 0, 0, 0]);
+        assert_eq!(at(Point::new(center.x, bounds.y0 - 1.0)), &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn stroke_alignment() {
+        let data = render(40, 20, |piet| {
+            let inside = StrokeStyle::new().with_alignment(StrokeAlignment::Inside);
+            let outside = StrokeStyle::new().with_alignment(StrokeAlignment::Outside);
+            let rect = Rect::new(4.0, 4.0, 16.0, 16.0);
+            piet.stroke_styled(rect, &Color::WHITE, 2.0, &inside);
+            piet.stroke_styled(rect + Vec2::new(20.0, 0.0), &Color::WHITE, 2.0, &outside);
+        });
+        assert_eq!(
+            (
+                alpha(&data, 40, 3, 10),
+                alpha(&data, 40, 4, 10),
+                alpha(&data, 40, 5, 10),
+                alpha(&data, 40, 6, 10)
+            ),
+            (0, 255, 255, 0)
+        );
+        assert_eq!(
+            (
+                alpha(&data, 40, 21, 10),
+                alpha(&data, 40, 22, 10),
+                alpha(&data, 40, 23, 10),
+                alpha(&data, 40, 24, 10)
+            ),
+            (0, 255, 255, 0)
+        );
+    }
+
+    #[test]
+    fn fill_shape_group() {
+        let data = render(40, 40, |piet| {
+            // both circles are drawn the same way round
+            let ring = ShapeGroup::new()
+                .with_shape(Circle::new((20.0, 20.0), 16.0))
+                .with_hole(Circle::new((20.0, 20.0), 8.0));
+            piet.fill(&ring, &Color::WHITE);
+        });
+        assert_eq!(
+            (
+                alpha(&data, 40, 2, 20),
+                alpha(&data, 40, 8, 20),
+                alpha(&data, 40, 20, 20),
+                alpha(&data, 40, 32, 20)
+            ),
+            (0, 255, 0, 255)
+        );
+    }
+
+    #[test]
+    fn bulk_drawing() {
+        let data = render(40, 20, |piet| {
+            // the second is drawn backwards, and still adds to the first
+            let rects = [
+                Rect::new(0.0, 0.0, 10.0, 10.0),
+                Rect::new(15.0, 10.0, 5.0, 0.0),
+            ];
+            piet.fill_rects(&rects, &Color::WHITE);
+            let points = [Point::new(30.0, 5.0), Point::new(30.0, 15.0)];
+            piet.draw_markers(Circle::new(Point::ORIGIN, 3.0), &points, &Color::WHITE);
+        });
+        assert_eq!(
+            (
+                alpha(&data, 40, 2, 5),
+                alpha(&data, 40, 7, 5),
+                alpha(&data, 40, 12, 5)
+            ),
+            (255, 255, 255)
+        );
+        assert_eq!(
+            (
+                alpha(&data, 40, 17, 5),
+                alpha(&data, 40, 30, 5),
+                alpha(&data, 40, 30, 15)
+            ),
+            (0, 255, 255)
+        );
+        assert_eq!(alpha(&data, 40, 30, 10), 0);
+    }
+
+    #[test]
+    fn tolerance() {
+        let surface = ImageSurface::create(Format::ARgb32, 8, 8).unwrap();
+        let mut ctx = Context::new(&surface);
+        let mut piet = CairoRenderContext::new(&mut ctx);
+        assert_eq!(piet.tolerance(), piet::DEFAULT_TOLERANCE);
+        piet.set_tolerance(0.25);
+        piet.set_tolerance(0.0);
+        piet.set_tolerance(f64::NAN);
+        assert_eq!(piet.tolerance(), 0.25);
+        // kept across saves
+        piet.save().unwrap();
+        piet.set_tolerance(0.5);
+        piet.restore().unwrap();
+        assert_eq!(piet.tolerance(), 0.5);
+    }
+
+    #[test]
+    fn draw_grid_row() {
+        let mut metrics = None;
+        let data = render(80, 20, |piet| {
+            let font = piet
+                .text()
+                .new_font_by_name("monospace", 12.0)
+                .build()
+                .unwrap();
+            let measured = GridMetrics::measure(&mut piet.text(), &font).unwrap();
+            let white = Color::rgb8(255, 255, 255);
+            let cells = [
+                GridCell::new('#', white.clone()).with_bg(Color::rgb8(0, 0, 255)),
+                GridCell::new(' ', white.clone()).with_bg(Color::rgb8(0, 0, 255)),
+                // nothing to draw, which cairo-rs would panic on
+                GridCell::new('\0', white.clone()),
+                GridCell::new('\u{2588}', Color::rgb8(0, 255, 0)),
+            ];
+            piet.draw_grid_row(&font, &measured, (0.0, 0.0), &cells);
+            metrics = Some(measured);
+        });
+        let metrics = metrics.unwrap();
+        let cell = |cell: usize| {
+            let x = (cell as f64 + 0.5) * metrics.cell_size.width;
+            pixel(&data, 80, x as usize, (metrics.baseline - 2.0) as usize)
+        };
+        assert_eq!(cell(1), &[255, 0, 0, 255]);
+        assert_eq!(cell(2), &[0, 0, 0, 0]);
+        // the full block glyph lands on its own cell
+        assert_eq!(cell(3), &[0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn draw_glyphs() {
+        let data = render(60, 20, |piet| {
+            let font = piet
+                .text()
+                .new_font_by_name("monospace", 12.0)
+                .build()
+                .unwrap();
+            assert!(piet.capabilities().glyph_drawing);
+            let block = font.grid_glyph('\u{2588}').unwrap().index as u16;
+            // out of order, and far apart, as no layout would place them
+            let glyphs = [
+                (block, Point::new(40.0, 15.0)),
+                (block, Point::new(2.0, 15.0)),
+            ];
+            piet.draw_glyphs(&font, &glyphs, &Color::rgb8(0, 255, 0));
+        });
+        assert_eq!(pixel(&data, 60, 4, 10), &[0, 255, 0, 255]);
+        assert_eq!(pixel(&data, 60, 25, 10), &[0, 0, 0, 0]);
+        assert_eq!(pixel(&data, 60, 42, 10), &[0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn glyph_arena() {
+        let surface = ImageSurface::create(Format::ARgb32, 60, 20).unwrap();
+        let mut ctx = Context::new(&surface);
+        let mut piet = CairoRenderContext::new(&mut ctx);
+        let font = piet
+            .text()
+            .new_font_by_name("monospace", 12.0)
+            .build()
+            .unwrap();
+        let long: Vec<_> = (0..64).map(|i| (1, Point::new(i as f64, 15.0))).collect();
+        let short = [(1, Point::new(2.0, 15.0))];
+
+        piet.draw_glyphs(&font, &long, &Color::WHITE);
+        let grown = piet.arena.glyphs.allocations();
+        piet.draw_glyphs(&font, &short, &Color::WHITE);
+        piet.finish().unwrap();
+        // reused within the frame, and kept when it ends
+        assert_eq!(piet.arena.glyphs.allocations(), grown);
+        assert_eq!(piet.arena.glyphs.len(), 1);
+
+        // another context's frame leaves this one's buffers alone
+        {
+            let other_surface = ImageSurface::create(Format::ARgb32, 4, 4).unwrap();
+            let mut other_ctx = Context::new(&other_surface);
+            let mut other = CairoRenderContext::new(&mut other_ctx);
+            other.draw_glyphs(&font, &short, &Color::WHITE);
+            other.finish().unwrap();
+        }
+        assert_eq!(piet.arena.glyphs.len(), 1);
+
+        // a frame of short runs frees the long buffer
+        piet.draw_glyphs(&font, &short, &Color::WHITE);
+        piet.finish().unwrap();
+        assert!(piet.arena.glyphs.is_empty());
+    }
+
+    #[test]
+    fn arena_carries_to_next_context() {
+        let surface = ImageSurface::create(Format::ARgb32, 20, 20).unwrap();
+        let mut ctx = Context::new(&surface);
+        let mut piet = CairoRenderContext::new(&mut ctx);
+        piet.fill_blurred(Rect::new(0.0, 0.0, 20.0, 20.0), &Color::BLACK, 1.0);
+        piet.finish().unwrap();
+        let arena = piet.into_arena();
+        assert_eq!(arena.layers.len(), 1);
+
+        let mut piet = CairoRenderContext::with_arena(&mut ctx, arena);
+        piet.fill_blurred(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::BLACK, 1.0);
+        piet.finish().unwrap();
+        assert_eq!(piet.arena.layers.len(), 1);
+    }
+
+    #[test]
+    fn single_channel_images() {
+        let data = render(3, 1, |piet| {
+            let gray = piet
+                .make_image(1, 1, &[0x80], ImageFormat::Grayscale)
+                .unwrap();
+            let alpha = piet
+                .make_image(2, 1, &[0xff, 0], ImageFormat::Alpha)
+                .unwrap();
+            let nearest = InterpolationMode::NearestNeighbor;
+            piet.draw_image(&gray, Rect::new(0.0, 0.0, 1.0, 1.0), nearest);
+            piet.draw_image(&alpha, Rect::new(1.0, 0.0, 3.0, 1.0), nearest);
+        });
+        assert_eq!(&data[0..4], &[0x80, 0x80, 0x80, 0xff]);
+        assert_eq!(&data[4..8], &[0, 0, 0, 0xff]);
+        assert_eq!(&data[8..12], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn draw_glyph_run() {
+        let mut split = 0.0;
+        let data = render(60, 60, |piet| {
+            let font = piet
+                .text()
+                .new_font_by_name("monospace", 12.0)
+                .build()
+                .unwrap();
+            let layout = piet
+                .text()
+                .new_text_layout(&font, "\u{2588}\u{2588}")
+                .build()
+                .unwrap();
+            split = layout.hit_test_text_position(3).unwrap().point.x;
+            let transforms = [Affine::default(), Affine::translate((0.0, 20.0))];
+            piet.draw_glyph_run(&layout, (0.0, 20.0), &Color::WHITE, &transforms);
+        });
+        let top = |x: f64| (0..60).find(|&y| alpha(&data, 60, x as usize, y) != 0);
+        let first = top(split / 2.0).unwrap();
+        let second = top(split * 1.5).unwrap();
+        assert_eq!(second, first + 20);
+    }
+
+    #[test]
+    fn draw_text_on_path() {
+        let mut clusters = Vec::new();
+        let data = render(60, 60, |piet| {
+            let font = piet
+                .text()
+                .new_font_by_name("monospace", 12.0)
+                .build()
+                .unwrap();
+            let layout = piet
+                .text()
+                .new_text_layout(&font, "\u{2588}\u{2588}")
+                .build()
+                .unwrap();
+            clusters = layout.cluster_metrics();
+            // straight down, so the glyphs' tops face right
+            let path = Line::new((30.0, 0.0), (30.0, 60.0));
+            piet.draw_text_on_path(&layout, path, &Color::WHITE);
+        });
+        assert_eq!(clusters.len(), 2);
+        assert!((clusters[1].origin.x - clusters[0].advance).abs() < 1e-6);
+        for cluster in &clusters {
+            let center = (cluster.origin.x + cluster.advance / 2.0) as usize;
+            assert_eq!(alpha(&data, 60, 33, center), 255);
+            assert_eq!(alpha(&data, 60, 20, center), 0);
+        }
+    }
+
+    #[test]
+    fn emoji_bitmaps() {
+        let mut bounds = Rect::ZERO;
+        let data = render(60, 30, |piet| {
+            let red = [0xff, 0, 0, 0xff].repeat(16);
+            let red = EmojiBitmap::new(4, 4, red).unwrap();
+            let mut atlas = EmojiAtlas::new();
+            atlas.insert("\u{2764}", red);
+            piet.text()
+                .set_emoji_strategy(EmojiStrategy::Bitmap(Arc::new(atlas)));
+            let font = piet
+                .text()
+                .new_font_by_name("monospace", 12.0)
+                .build()
+                .unwrap();
+            let layout = piet
+                .text()
+                .new_text_layout(&font, "a\u{2764}\u{fe0f}b")
+                .build()
+                .unwrap();
+            bounds = layout.range_bounds(1..7).unwrap() + Vec2::new(0.0, 20.0);
+            piet.draw_text(&layout, (0.0, 20.0), &Color::WHITE);
+        });
+        let center = bounds.center();
+        let emoji = pixel(&data, 60, center.x as usize, center.y as usize);
+        assert_eq!(emoji, &[0, 0, 255, 255]);
+        // the rest of the text is still drawn
+        let ink = |x0: f64, x1: f64| {
+            (0..30).any(|y| (x0 as usize..x1 as usize).any(|x| alpha(&data, 60, x, y) != 0))
+        };
+        assert!(ink(0.0, bounds.x0));
+        assert!(ink(bounds.x1, 60.0));
+    }
+
+    #[test]
+    fn bgra_premul_image() {
+        let buf = [0xff, 0, 0, 0xff, 0, 0x40, 0, 0x80];
+        let data = render(2, 1, |piet| {
+            let image = piet
+                .make_image(2, 1, &buf, ImageFormat::BgraPremul)
+                .unwrap();
+            let rect = Rect::new(0.0, 0.0, 2.0, 1.0);
+            piet.draw_image(&image, rect, InterpolationMode::NearestNeighbor);
+        });
+        let word =
+            |x: usize| u32::from_ne_bytes(<[u8; 4]>::try_from(pixel(&data, 2, x, 0)).unwrap());
+        // opaque blue, then half transparent dark green
+        assert_eq!(word(0), 0xff00_00ff);
+        assert_eq!(word(1), 0x8000_4000);
+    }
+
+    #[test]
+    fn owned_image_between_threads() {
+        render(8, 8, |piet| {
+            let image = piet
+                .make_image(
+                    2,
+                    1,
+                    &[0xff, 0, 0, 0xff, 0, 0, 0xff, 0x80],
+                    ImageFormat::RgbaSeparate,
+                )
+                .unwrap();
+            let owned = image.to_owned_image().unwrap();
+            assert_eq!(owned.format(), ImageFormat::RgbaPremul);
+            assert_eq!(owned.pixels(), [0xff, 0, 0, 0xff, 0, 0, 0x80, 0x80]);
+
+            let owned = std::thread::spawn(move || owned).join().unwrap();
+            let image = owned.to_image(piet).unwrap();
+            assert_eq!(image.to_owned_image().unwrap().pixels(), owned.pixels());
+
+            let mask = piet
+                .make_image(2, 1, &[0xff, 0x40], ImageFormat::Alpha)
+                .unwrap();
+            assert_eq!(mask.to_owned_image().unwrap().pixels(), [0xff, 0x40]);
+        });
+    }
+
+    #[test]
+    fn owned_text_layout_between_threads() {
+        fn send_sync<T: Send + Sync>() {}
+        send_sync::<CairoText>();
+
+        let label = std::thread::spawn(|| {
+            piet::OwnedTextLayout::new("sans-serif", 12.0, "piet text")
+                .range_attribute(0..4, TextAttribute::Weight(piet::FontWeight::BOLD))
+        })
+        .join()
+        .unwrap();
+        render(8, 8, |piet| {
+            let layout = label.build(&mut piet.text()).unwrap();
+            assert_eq!(layout.text, "piet text");
+        });
+    }
+
+    #[test]
+    fn image_size_and_format() {
+        render(8, 8, |piet| {
+            let image = piet.make_image(3, 2, &[0; 18], ImageFormat::Rgb).unwrap();
+            assert_eq!(image.size(), Size::new(3.0, 2.0));
+            assert_eq!(image.format(), ImageFormat::Rgb);
+            let rendered = piet.make_render_target(4, 4).unwrap();
+            assert_eq!(rendered.format(), ImageFormat::RgbaPremul);
+        });
+    }
+
+    #[test]
+    fn layout_colors() {
+        let (mut split0, mut split1) = (0.0, 0.0);
+        let data = render(60, 30, |piet| {
+            let font = piet
+                .text()
+                .new_font_by_name("monospace", 12.0)
+                .build()
+                .unwrap();
+            let layout = piet
+                .text()
+                .new_text_layout(&font, "\u{2588}\u{2588}\u{2588}")
+                .default_color(Color::rgb8(0, 0xff, 0))
+                .range_attribute(3..6, TextAttribute::Foreground(Color::rgb8(0xff, 0, 0)))
+                .build()
+                .unwrap();
+            split0 = layout.hit_test_text_position(3).unwrap().point.x;
+            split1 = layout.hit_test_text_position(6).unwrap().point.x;
+            // the layout's colors win over the brush
+            piet.draw_text(&layout, (0.0, 20.0), &Color::WHITE);
+        });
+        let at = |x: f64| pixel(&data, 60, x as usize, 15);
+        assert_eq!(at(split0 / 2.0), &[0, 0xff, 0, 0xff]);
+        assert_eq!(at((split0 + split1) / 2.0), &[0, 0, 0xff, 0xff]);
+        assert_eq!(at(split1 + split0 / 2.0), &[0, 0xff, 0, 0xff]);
+    }
+
+    #[test]
+    fn styled_text() {
+        let (mut split0, mut split1, mut underline) = (0.0, 0.0, Rect::ZERO);
+        let data = render(60, 30, |piet| {
+            let font = piet
+                .text()
+                .new_font_by_name("monospace", 12.0)
+                .build()
+                .unwrap();
+            let layout = piet
+                .text()
+                .new_text_layout(&font, "\u{2588}\u{2588}  ")
+                .range_attribute(3..6, TextAttribute::Size(24.0))
+                .range_attribute(6..8, TextAttribute::Underline(true))
+                .build()
+                .unwrap();
+            split0 = layout.hit_test_text_position(3).unwrap().point.x;
+            split1 = layout.hit_test_text_position(6).unwrap().point.x;
+            underline = layout.model.decorations()[0].0;
+            piet.draw_text(&layout, (0.0, 24.0), &Color::WHITE);
+        });
+        let at = |x: f64, y: f64| alpha(&data, 60, x as usize, y as usize);
+        // the larger block reaches higher
+        assert_eq!(at(split0 / 2.0, 4.0), 0);
+        assert_eq!(at((split0 + split1) / 2.0, 4.0), 0xff);
+        assert!(split1 > split0 * 1.5);
+        // the spaces are underlined, below the baseline
+        let center = underline.center() + Vec2::new(0.0, 24.0);
+        assert!(underline.x0 >= split1 && underline.y0 > 0.0);
+        assert!(at(center.x, center.y) > 0);
+        assert_eq!(at(center.x, center.y - 3.0), 0);
+    }
+
+    #[test]
+    fn clear_rect() {
+        let data = render(4, 1, |piet| {
+            piet.clear(Color::rgba8(0xff, 0, 0, 0x80));
+            piet.fill(Rect::new(2.0, 0.0, 4.0, 1.0), &Color::BLACK);
+            piet.transform(Affine::translate((1.0, 0.0)));
+            piet.clear_rect(Rect::new(0.0, 0.0, 2.0, 1.0), Color::rgba8(0, 0, 0, 0));
+        });
+        // a translucent clear isn't blended with what was there
+        assert_eq!(&data[0..4], &[0, 0, 0x80, 0x80]);
+        assert_eq!(&data[4..12], &[0; 8]);
+        assert_eq!(&data[12..16], &[0, 0, 0, 0xff]);
+    }
+
+    #[test]
+    fn image_positioning() {
+        let data = render(4, 1, |piet| {
+            let white = piet
+                .make_image(2, 1, &[0xff; 8], ImageFormat::RgbaSeparate)
+                .unwrap();
+            piet.set_image_positioning(ImagePositioning::PixelSnapped);
+            let dst = Rect::new(0.6, 0.0, 2.6, 1.0);
+            piet.draw_image(&white, dst, InterpolationMode::Bilinear);
+        });
+        let alpha: Vec<u8> = data.chunks(4).map(|px| px[3]).collect();
+        assert_eq!(alpha, vec![0, 0xff, 0xff, 0]);
+    }
+
+    #[test]
+    fn image_positioning_mirrored() {
+        let data = render(4, 1, |piet| {
+            // red, then blue
+            let pixels = [0xff, 0, 0, 0xff, 0, 0, 0xff, 0xff];
+            let image = piet
+                .make_image(2, 1, &pixels, ImageFormat::RgbaSeparate)
+                .unwrap();
+            piet.set_image_positioning(ImagePositioning::PixelSnapped);
+            let dst = Rect::new(2.6, 0.0, 0.6, 1.0);
+            piet.draw_image(&image, dst, InterpolationMode::NearestNeighbor);
+        });
+        // still mirrored, blue on the left (BGRA)
+        assert_eq!(pixel(&data, 4, 0, 0), &[0, 0, 0, 0]);
+        assert_eq!(pixel(&data, 4, 1, 0), &[0xff, 0, 0, 0xff]);
+        assert_eq!(pixel(&data, 4, 2, 0), &[0, 0, 0xff, 0xff]);
+        assert_eq!(pixel(&data, 4, 3, 0), &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn draw_image_clipped() {
+        let data = render(8, 8, |piet| {
+            let white = piet
+                .make_image(4, 4, &[0xff; 64], ImageFormat::RgbaSeparate)
+                .unwrap();
+            let dst = Rect::new(0.0, 0.0, 8.0, 8.0);
+            let circle = Circle::new((4.0, 4.0), 4.0);
+            piet.draw_image_clipped(&white, dst, circle, InterpolationMode::NearestNeighbor);
+        });
+        assert_eq!(alpha(&data, 8, 0, 0), 0);
+        assert_eq!(alpha(&data, 8, 4, 4), 0xff);
+        // the edge is antialiased
+        assert!((0..8).any(|x| alpha(&data, 8, x, 1) > 0 && alpha(&data, 8, x, 1) < 0xff));
+    }
+
+    #[test]
+    fn clip_antialiased() {
+        let clipped_alpha = |mode: Antialiasing| {
+            let data = render(8, 8, |piet| {
+                piet.set_antialiasing(mode);
+                piet.clip(Circle::new((4.0, 4.0), 4.0));
+                piet.fill(Rect::new(0.0, 0.0, 8.0, 8.0), &Color::WHITE);
+            });
+            (0..8).map(|x| alpha(&data, 8, x, 1)).collect::<Vec<_>>()
+        };
+        let partial = |row: &[u8]| row.iter().any(|&a| a > 0 && a < 0xff);
+        assert!(partial(&clipped_alpha(Antialiasing::Default)));
+        assert!(!partial(&clipped_alpha(Antialiasing::None)));
+    }
+
+    #[test]
+    fn dashes() {
+        // a row of the alpha of a horizontal dashed line through y = 4
+        let dashed = |width: f64, dashes: &'static [f64], offset: f64| {
+            let data = render(24, 8, |piet| {
+                let style = StrokeStyle::dashed(dashes, offset);
+                let line = Line::new((0.0, 4.0), (24.0, 4.0));
+                piet.stroke_styled(line, &Color::WHITE, width, &style);
+            });
+            (0..24)
+                .map(|x| alpha(&data, 24, x, 4) == 0xff)
+                .collect::<Vec<_>>()
+        };
+        let on = |xs: &[usize]| (0..24).map(|x| xs.contains(&x)).collect::<Vec<_>>();
+
+        // lengths don't scale with the width
+        assert_eq!(dashed(2.0, &[2.0, 6.0], 0.0), on(&[0, 1, 8, 9, 16, 17]));
+        assert_eq!(dashed(6.0, &[2.0, 6.0], 0.0), on(&[0, 1, 8, 9, 16, 17]));
+        // offsets wrap around
+        assert_eq!(dashed(2.0, &[2.0, 6.0], -2.0), on(&[2, 3, 10, 11, 18, 19]));
+        assert_eq!(dashed(2.0, &[2.0, 6.0], 14.0), on(&[2, 3, 10, 11, 18, 19]));
+        // odd patterns are read twice
+        assert_eq!(
+            dashed(2.0, &[4.0, 2.0, 2.0], 0.0),
+            on(&[0, 1, 2, 3, 6, 7, 12, 13, 16, 17, 18, 19, 22, 23])
+        );
+        // invalid patterns are solid, rather than an error
+        assert_eq!(dashed(2.0, &[2.0, -2.0], 0.0), vec![true; 24]);
+        assert_eq!(dashed(2.0, &[0.0, 0.0], 0.0), vec![true; 24]);
+    }
+
+    #[test]
+    fn make_path() {
+        let data = render(16, 8, |piet| {
+            let square = piet.make_path(Rect::new(0.0, 0.0, 4.0, 4.0)).unwrap();
+            piet.fill_path(&square, &Color::WHITE);
+            // drawn with the transform in effect now
+            piet.transform(Affine::translate((8.0, 0.0)));
+            piet.stroke_path(&square, &Color::WHITE, 2.0);
+        });
+        assert_eq!(alpha(&data, 16, 2, 2), 0xff);
+        assert_eq!(alpha(&data, 16, 6, 2), 0);
+        // the stroke is hollow
+        assert_eq!(alpha(&data, 16, 8, 2), 0xff);
+        assert_eq!(alpha(&data, 16, 10, 2), 0);
+    }
+
+    #[test]
+    fn draw_images() {
+        let data = render(8, 4, |piet| {
+            let white = piet
+                .make_image(2, 2, &[0xff; 16], ImageFormat::RgbaSeparate)
+                .unwrap();
+            let images = [
+                (&white, Rect::new(0.0, 0.0, 4.0, 4.0), 1.0),
+                (&white, Rect::new(4.0, 0.0, 8.0, 4.0), 0.5),
+                (&white, Rect::new(0.0, 0.0, 8.0, 4.0), 0.0),
+            ];
+            piet.draw_images(&images, InterpolationMode::NearestNeighbor);
+        });
+        assert_eq!(alpha(&data, 8, 1, 1), 0xff);
+        assert!((0x7f..=0x80).contains(&alpha(&data, 8, 5, 1)));
+    }
+
+    #[test]
+    fn draw_image_transformed() {
+        let data = render(6, 6, |piet| {
+            // opaque on the left, transparent on the right
+            let pixels = [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0];
+            let image = piet
+                .make_image(2, 1, &pixels, ImageFormat::RgbaSeparate)
+                .unwrap();
+            let transform = Affine::translate((4.0, 0.0))
+                * Affine::rotate(std::f64::consts::FRAC_PI_2)
+                * Affine::scale(2.0);
+            piet.draw_image_transformed(&image, transform, InterpolationMode::NearestNeighbor);
+        });
+        // rotated to run down from the top, between x = 2 and x = 4
+        assert_eq!(alpha(&data, 6, 3, 1), 0xff);
+        assert_eq!(alpha(&data, 6, 3, 3), 0);
+        assert_eq!(alpha(&data, 6, 1, 1), 0);
+        assert_eq!(alpha(&data, 6, 5, 1), 0);
+    }
+
+    #[test]
+    fn draw_tiles() {
+        let data = render(6, 4, |piet| {
+            // an opaque cell on the left, a transparent one on the right
+            let mut pixels = [0u8; 32];
+            for y in 0..2 {
+                pixels[y * 16..y * 16 + 8].copy_from_slice(&[0xff; 8]);
+            }
+            let atlas = piet
+                .make_image(4, 2, &pixels, ImageFormat::RgbaSeparate)
+                .unwrap();
+            let opaque = Rect::new(0.0, 0.0, 2.0, 2.0);
+            let clear = Rect::new(2.0, 0.0, 4.0, 2.0);
+            let tiles = [
+                (clear, Point::new(0.0, 0.0)),
+                (opaque, Point::new(2.0, 0.0)),
+                (opaque, Point::new(4.0, 2.0)),
+            ];
+            piet.draw_tiles(&atlas, &tiles, InterpolationMode::NearestNeighbor);
+        });
+        assert_eq!(alpha(&data, 6, 1, 1), 0);
+        assert_eq!(alpha(&data, 6, 3, 1), 0xff);
+        assert_eq!(alpha(&data, 6, 5, 1), 0);
+        assert_eq!(alpha(&data, 6, 5, 3), 0xff);
+        assert_eq!(alpha(&data, 6, 3, 3), 0);
+    }
+
+    #[test]
+    fn image_atlas() {
+        let data = render(8, 8, |piet| {
+            let mut builder = ImageAtlasBuilder::new(ImageFormat::RgbaSeparate);
+            let red = builder.add(2, 2, &[0xff, 0, 0, 0xff].repeat(4)).unwrap();
+            let blue = builder.add(2, 2, &[0, 0, 0xff, 0xff].repeat(4)).unwrap();
+            let atlas = builder.build(piet).unwrap();
+            assert_eq!(atlas.size(blue), Size::new(2.0, 2.0));
+            // scaled up, the edges sample the gutter rather than the blue
+            // sprite or the transparent space around the sprites
+            atlas.draw(
+                piet,
+                red,
+                Rect::new(0.0, 0.0, 8.0, 8.0),
+                InterpolationMode::Bilinear,
+            );
+        });
+        for px in data.chunks(4) {
+            assert_eq!(px, &[0, 0, 0xff, 0xff]);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn render_tiled() {
+        fn draw(rc: &mut CairoRenderContext) -> Result<(), Error> {
+            rc.fill(Circle::new((20.0, 15.0), 12.5), &Color::rgb8(0, 0x80, 0xff));
+            rc.stroke(Rect::new(3.5, 4.5, 36.5, 25.5), &Color::BLACK, 3.0);
+            Ok(())
+        }
+
+        let direct_data = render(40, 30, |piet| draw(piet).unwrap());
+        // tiles that don't divide the surface evenly
+        let mut tiled = ImageSurface::create(Format::ARgb32, 40, 30).unwrap();
+        let options = crate::TileOptions::new().size(7);
+        crate::render_tiled(&mut tiled, &options, draw).unwrap();
+        // cairo rounds coverage a little differently once translated
+        let tiled_data = tiled.get_data().unwrap().to_vec();
+        assert!(tiled_data
+            .iter()
+            .zip(direct_data.iter())
+            .all(|(a, b)| a.abs_diff(*b) <= 1));
+
+        let failing = |_: &mut CairoRenderContext| Err(new_error(ErrorKind::InvalidInput));
+        assert!(crate::render_tiled(&mut tiled, &options, failing).is_err());
+        let mut a8 = ImageSurface::create(Format::A8, 4, 4).unwrap();
+        assert!(crate::render_tiled(&mut a8, &options, draw).is_err());
+    }
+
+    #[cfg(feature = "profiling")]
+    #[test]
+    fn draw_profile() {
+        let surface = ImageSurface::create(Format::ARgb32, 16, 16).unwrap();
+        let mut ctx = Context::new(&surface);
+        let mut piet = CairoRenderContext::new(&mut ctx);
+        piet.save().unwrap();
+        piet.fill(Rect::new(0.0, 0.0, 8.0, 8.0), &Color::BLACK);
+        // the inside stroke is drawn by stroking again, and counted once
+        let style = StrokeStyle::new().with_alignment(StrokeAlignment::Inside);
+        piet.stroke_styled(Rect::new(2.0, 2.0, 14.0, 14.0), &Color::WHITE, 2.0, &style);
+        piet.restore().unwrap();
+        piet.finish().unwrap();
+
+        let profile = piet.draw_profile();
+        assert_eq!(profile.get(DrawCall::Fill).count, 1);
+        assert_eq!(profile.get(DrawCall::Stroke).count, 1);
+        assert_eq!(profile.get(DrawCall::State).count, 2);
+        assert_eq!(profile.get(DrawCall::Text).count, 0);
+        assert_eq!(profile.total().count, 4);
+        piet.reset_draw_profile();
+        assert_eq!(piet.draw_profile().total().count, 0);
+    }
+
+    #[test]
+    fn with_blur() {
+        let data = render(20, 20, |piet| {
+            piet.transform(Affine::translate((5.0, 5.0)));
+            piet.fill_blurred(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::BLACK, 1.5);
+            assert_eq!(piet.current_transform().as_coeffs()[4..], [5.0, 5.0]);
+        });
+        assert_eq!(alpha(&data, 20, 10, 10), 255);
+        // the edges are soft on both sides, and fade out within a few pixels
+        assert!(alpha(&data, 20, 5, 10) > 0 && alpha(&data, 20, 5, 10) < 255);
+        assert!(alpha(&data, 20, 4, 10) > 0 && alpha(&data, 20, 4, 10) < alpha(&data, 20, 5, 10));
+        assert!(alpha(&data, 20, 15, 10) > 0 && alpha(&data, 20, 15, 10) < 255);
+        assert_eq!(alpha(&data, 20, 0, 0), 0);
+    }
+
+    #[test]
+    fn with_blur_reuses_layers() {
+        let data = render(20, 20, |piet| {
+            piet.fill_blurred(Rect::new(0.0, 0.0, 20.0, 20.0), &Color::BLACK, 1.0);
+            // a smaller layer in the same surface, which must start out clear
+            piet.clip(Rect::new(0.0, 0.0, 4.0, 4.0));
+            piet.with_blur(1.0, |_| Ok(())).unwrap();
+            piet.clear(Color::rgba8(0, 0, 0, 0));
+            piet.fill_blurred(Rect::new(0.0, 0.0, 1.0, 1.0), &Color::BLACK, 0.5);
+            assert_eq!(piet.arena.layers.len(), 1);
+            piet.trim_layer_pool();
+            assert!(piet.arena.layers.is_empty());
+        });
+        assert!(alpha(&data, 20, 0, 0) > 0);
+        assert_eq!(alpha(&data, 20, 3, 3), 0);
+    }
+
+    #[test]
+    fn clip_text() {
+        let text = |clip: bool| {
+            render(60, 30, |piet| {
+                let font = piet
+                    .text()
+                    .new_font_by_name("sans-serif", 16.0)
+                    .build()
+                    .unwrap();
+                let layout = piet.text().new_text_layout(&font, "Ag").build().unwrap();
+                let red = Color::rgb8(255, 0, 0);
+                if clip {
+                    piet.clip_text(&layout, (5.5, 20.0));
+                    piet.fill(Rect::new(0.0, 0.0, 60.0, 30.0), &red);
+                } else {
+                    piet.draw_text(&layout, (5.5, 20.0), &red);
+                }
+            })
+        };
+        let clipped = text(true);
+        let drawn = text(false);
+        // the fill shows through the glyphs, as if they were drawn with it
+        assert!(clipped.chunks(4).any(|px| px == [0, 0, 255, 255]));
+        assert!(clipped.chunks(4).any(|px| px[3] == 0));
+        let max_diff = clipped
+            .iter()
+            .zip(&drawn)
+            .map(|(&a, &b)| (a as i32 - b as i32).abs())
+            .max();
+        // glyphs and their outlines antialias a little differently
+        assert!(max_diff.unwrap() <= 32);
+    }
+
+    #[test]
+    fn save_guard() {
+        let mut surface = ImageSurface::create(Format::ARgb32, 10, 10).unwrap();
+        let mut ctx = Context::new(&surface);
+        let mut piet = CairoRenderContext::new(&mut ctx);
+        {
+            let mut rc = piet.save_guard();
+            rc.transform(Affine::translate((5.0, 5.0)));
+            rc.clip(Rect::new(0.0, 0.0, 1.0, 1.0));
+        }
+        assert_eq!(
+            piet.current_transform().as_coeffs(),
+            Affine::default().as_coeffs()
+        );
+        let rc = piet.save_guard();
+        SaveGuard::restore(rc).unwrap();
+        // an unbalanced restore leaves the context usable
+        assert!(piet.restore().is_err());
+        piet.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::WHITE);
+        piet.finish().unwrap();
+        drop(ctx);
+        assert_eq!(surface.get_data().unwrap()[0], 0xff);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "saves not restored")]
+    fn finish_unbalanced() {
+        let surface = ImageSurface::create(Format::ARgb32, 10, 10).unwrap();
+        let mut ctx = Context::new(&surface);
+        let mut piet = CairoRenderContext::new(&mut ctx);
+        piet.save().unwrap();
+        let _ = piet.finish();
+    }
+
+    #[test]
+    fn non_finite_transform() {
+        render(10, 10, |piet| {
+            piet.transform(Affine::translate((2.0, 3.0)));
+            piet.transform(Affine::scale(f64::NAN));
+            assert_eq!(piet.current_transform().as_coeffs()[4..], [2.0, 3.0]);
+            assert!(piet.status().is_err());
+            piet.transform(Affine::scale(0.0));
+            assert!(piet.status().is_err());
+            // the context is still usable
+            piet.transform(Affine::scale(2.0));
+            piet.fill(Rect::new(0.0, 0.0, 1.0, 1.0), &Color::BLACK);
+            assert_eq!(piet.current_transform().as_coeffs()[0], 2.0);
+        });
+    }
+
+    #[test]
+    fn push_clip() {
+        let data = render(20, 10, |piet| {
+            piet.push_clip(Rect::new(0.0, 0.0, 10.0, 10.0));
+            piet.push_clip(Rect::new(0.0, 0.0, 5.0, 10.0));
+            piet.transform(Affine::translate((1.0, 0.0)));
+            piet.pop_clip().unwrap();
+            // the transform outlives the inner clip, the outer one is kept
+            assert_eq!(piet.current_transform().as_coeffs()[4], 1.0);
+            piet.fill(Rect::new(-1.0, 0.0, 19.0, 10.0), &Color::WHITE);
+
+            piet.save().unwrap();
+            assert!(piet.pop_clip().is_err());
+            piet.push_clip(Rect::ZERO);
+            // restore pops the clips pushed since the save
+            piet.restore().unwrap();
+            piet.pop_clip().unwrap();
+            assert!(piet.pop_clip().is_err());
+        });
+        assert_eq!(alpha(&data, 20, 9, 0), 0xff);
+        assert_eq!(alpha(&data, 20, 10, 0), 0);
+    }
+
+    #[test]
+    fn reset_clip() {
+        let data = render(20, 10, |piet| {
+            piet.clip(Rect::new(0.0, 0.0, 15.0, 10.0));
+            piet.save().unwrap();
+            piet.clip(Rect::new(0.0, 0.0, 5.0, 10.0));
+            piet.push_clip(Rect::new(0.0, 0.0, 2.0, 10.0));
+            piet.transform(Affine::translate((1.0, 0.0)));
+            piet.reset_clip();
+            assert!(piet.pop_clip().is_err());
+            // only the clip from before the save is left
+            piet.fill(Rect::new(-1.0, 0.0, 19.0, 10.0), &Color::WHITE);
+            piet.restore().unwrap();
+        });
+        assert_eq!(alpha(&data, 20, 14, 0), 0xff);
+        assert_eq!(alpha(&data, 20, 15, 0), 0);
+    }
+
+    #[test]
+    fn text_layout_transform() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 11.0).build().unwrap();
+        let mut layout = |transform: Affine| {
+            text.new_text_layout(&font, "Wavy illumination")
+                .transform(transform)
+                .build()
+                .unwrap()
+        };
+        // advances are whole device pixels when scaled
+        let scaled = layout(Affine::scale(3.0));
+        assert!(scaled
+            .glyphs
+            .iter()
+            .all(|(_, glyph)| (glyph.x * 3.0).fract().abs() < 1e-9));
+        // and not rounded at all when rotated, so every angle agrees
+        let rotated = layout(Affine::rotate(0.3));
+        let steeper = layout(Affine::rotate(1.1) * Affine::scale(2.0));
+        assert!((rotated.width() - steeper.width()).abs() < 1e-9);
+        // finer pixels round less
+        assert!((rotated.width() - scaled.width()).abs() < 1.0);
+    }
+
+    #[test]
+    fn every_test_picture() {
+        for number in 0..piet_test::TEST_PICTURE_COUNT {
+            let surface = ImageSurface::create(Format::ARgb32, 200, 100).unwrap();
+            let mut ctx = Context::new(&surface);
+            let mut piet = CairoRenderContext::new(&mut ctx);
+            piet_test::draw_test_picture(&mut piet, number).unwrap();
+            if let Err(e) = piet.finish() {
+                panic!("picture {}: {}", number, e);
+            }
+        }
+    }
+
+    #[test]
+    fn strokes_in_user_space() {
+        let data = render(200, 100, |piet| {
+            piet_test::draw_test_picture(piet, 7).unwrap()
+        });
+        // black on white, so any channel gives the coverage
+        let coverage = |&(x, y): &(usize, usize)| 1.0 - pixel(&data, 200, x, y)[0] as f64 / 255.0;
+        for pixel in piet_test::PICTURE_7_INKED {
+            assert!(coverage(pixel) >= 0.75, "{:?} not inked", pixel);
+        }
+        for pixel in piet_test::PICTURE_7_BLANK {
+            assert!(coverage(pixel) <= 0.25, "{:?} not blank", pixel);
+        }
+    }
+
+    #[test]
+    fn serialized_test_pictures() {
+        use piet::{DisplayList, RecordingContext, SerializedDisplayList};
+
+        let draw = |list: &DisplayList<CairoTextLayout>| {
+            render(200, 100, |piet| list.replay(piet).unwrap())
+        };
+        for number in 0..piet_test::TEST_PICTURE_COUNT {
+            let mut rc = RecordingContext::new(CairoText::new());
+            piet_test::draw_test_picture(&mut rc, number).unwrap();
+            let list = rc.into_display_list();
+            let json = serde_json::to_string(&list).unwrap();
+            let read: SerializedDisplayList = serde_json::from_str(&json).unwrap();
+            let read = read.to_display_list(CairoText::new()).unwrap();
+            // text is laid out again, images made again, and paths parsed,
+            // all to the same pixels
+            assert!(draw(&list) == draw(&read), "picture {}", number);
+        }
+    }
+
+    #[test]
+    fn replay() {
+        use piet::{RecordingContext, ReplayOptions};
+
+        let mut rc = RecordingContext::new(CairoText::new());
+        rc.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::rgb8(255, 0, 0));
+        let image = rc
+            .make_image(1, 1, &[0, 0, 0, 255], ImageFormat::RgbaSeparate)
+            .unwrap();
+        rc.draw_image(
+            &image,
+            Rect::new(0.0, 10.0, 5.0, 15.0),
+            InterpolationMode::NearestNeighbor,
+        );
+        // left open, for the replay to close
+        rc.save().unwrap();
+        rc.transform(Affine::translate((10.0, 0.0)));
+        rc.push_clip(Rect::new(0.0, 0.0, 5.0, 20.0));
+        rc.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::rgb8(0, 0, 255));
+        let list = rc.into_display_list();
+
+        let replayed = |options: ReplayOptions| {
+            let data = render(20, 20, |piet| {
+                list.replay_with(piet, &options).unwrap();
+                // nothing of the list's transform and clip is left
+                assert_eq!(
+                    piet.current_transform().as_coeffs(),
+                    [1.0, 0.0, 0.0, 1.0, 0.0, 0.0]
+                );
+                piet.fill(Rect::new(15.0, 15.0, 20.0, 20.0), &Color::BLACK);
+            });
+            move |x: usize, y: usize| <[u8; 4]>::try_from(pixel(&data, 20, x, y)).unwrap()
+        };
+
+        let all = replayed(ReplayOptions::new());
+        assert_eq!(all(5, 5), [0, 0, 255, 255]);
+        assert_eq!(all(2, 12), [0, 0, 0, 255]);
+        assert_eq!(all(12, 5), [255, 0, 0, 255]);
+        assert_eq!(all(17, 5), [0, 0, 0, 0]);
+        assert_eq!(all(17, 17), [0, 0, 0, 255]);
+
+        let first = replayed(ReplayOptions::new().steps(1));
+        assert_eq!(first(5, 5), [0, 0, 255, 255]);
+        assert_eq!(first(2, 12), [0, 0, 0, 0]);
+        assert_eq!(first(12, 5), [0, 0, 0, 0]);
+
+        // the image's bounds, outlined past the edge of the red square
+        let outlined = replayed(
+            ReplayOptions::new()
+                .steps(2)
+                .highlight(Color::rgb8(0, 255, 0)),
+        );
+        assert_eq!(outlined(2, 12), [0, 0, 0, 255]);
+        assert_eq!(outlined(2, 15), [0, 255, 0, 255]);
+        assert_eq!(outlined(12, 5), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn memory_accounting() {
+        let surface = ImageSurface::create(Format::ARgb32, 20, 20).unwrap();
+        let mut ctx = Context::new(&surface);
+        let mut piet = CairoRenderContext::new(&mut ctx);
+        let before = piet.memory_report();
+
+        let image = piet
+            .make_image(4, 2, &[0; 32], ImageFormat::RgbaPremul)
+            .unwrap();
+        let copy = image.clone();
+        assert_eq!(piet.memory_report().images, before.images + 32);
+        // clones share the surface
+        drop(image);
+        assert_eq!(piet.memory_report().images, before.images + 32);
+        drop(copy);
+        assert_eq!(piet.memory_report().images, before.images);
+
+        let font = piet
+            .text()
+            .new_font_by_name("monospace", 12.0)
+            .build()
+            .unwrap();
+        assert!(font.grid_glyph('a').is_some());
+        assert!(piet.memory_report().glyphs > before.glyphs);
+        piet.fill_blurred(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::BLACK, 1.0);
+        assert!(piet.memory_report().layers > before.layers);
+
+        piet.trim_caches();
+        let trimmed = piet.memory_report();
+        assert_eq!(trimmed.glyphs, 0);
+        assert_eq!(trimmed.layers, 0);
+        assert_eq!(trimmed.total(), trimmed.images);
+        // the cache fills up again
+        assert!(font.grid_glyph('a').is_some());
+        assert!(piet.memory_report().glyphs > 0);
+        piet.finish().unwrap();
+    }
+
+    #[test]
+    fn malformed_input() {
+        render(16, 16, |piet| {
+            let short = piet.make_image(4, 4, &[0; 15], ImageFormat::Rgb);
+            assert!(short.is_err());
+            assert!(piet
+                .text()
+                .new_font_by_name("sans", f64::NAN)
+                .build()
+                .is_err());
+            assert!(piet.text().new_font_by_name("sans", 1e300).build().is_err());
+
+            let font = piet.text().new_font_by_name("sans", 12.0).build().unwrap();
+            let layout = piet
+                .text()
+                .new_text_layout(&font, "h\u{e9}llo")
+                .range_attribute(0..2, TextAttribute::Size(1e300))
+                .build()
+                .unwrap();
+            // the range splits the 'é', so it's widened to cover it
+            let (split, whole) = (layout.rects_for_range(2..3), layout.rects_for_range(1..3));
+            assert_eq!(split.len(), 1);
+            assert_eq!((split[0].x0, split[0].x1), (whole[0].x0, whole[0].x1));
+            let (start, end) = (3, 1);
+            assert!(layout.rects_for_range(start..end).is_empty());
+            let hit = layout.hit_test_point(Point::new(f64::NAN, f64::NAN));
+            assert_eq!(hit.metrics.text_position, 0);
+
+            let image = piet
+                .make_image(1, 1, &[0xff; 4], ImageFormat::RgbaPremul)
+                .unwrap();
+            let nan_rect = Rect::new(f64::NAN, 0.0, 4.0, 4.0);
+            piet.draw_image(&image, nan_rect, InterpolationMode::Bilinear);
+            piet.draw_image_area(&image, nan_rect, nan_rect, InterpolationMode::Bilinear);
+        });
+    }
+
+    #[test]
+    fn huge_shapes() {
+        let data = render(8, 8, |piet| {
+            // flattened to a few dozen arcs, not to more than fit in memory
+            piet.stroke(Circle::new((0.0, 0.0), 1e300), &Color::WHITE, 1.0);
+            piet.stroke(
+                Circle::new((f64::NAN, 0.0), f64::INFINITY),
+                &Color::WHITE,
+                1.0,
+            );
+            piet.fill(Circle::new((4.0, 4.0), 2.0), &Color::BLACK);
+        });
+        let center = (4 * 8 + 4) * 4;
+        assert_eq!(data[center + 3], 0xff);
+    }
+
+    #[test]
+    fn text_cairo_cant_take() {
+        render(16, 16, |piet| {
+            // cairo's C API ends text at a NUL and rejects noncharacters, which
+            // cairo-rs panics on
+            let font = piet
+                .text()
+                .new_font_by_name("sans\0serif\u{ffff}", 12.0)
+                .build()
+                .unwrap();
+            let text = "a\0b\u{ffff}c\u{1fffe}";
+            let layout = piet.text().new_text_layout(&font, text).build().unwrap();
+            // positions are in the text as it was given
+            let c = text.find('c').unwrap();
+            let before = layout.hit_test_text_position(c).unwrap();
+            let after = layout.hit_test_text_position(c + 1).unwrap();
+            assert!(after.point.x > before.point.x);
+            piet.draw_text(&layout, (0.0, 12.0), &Color::BLACK);
+            piet.text().prewarm(&font, text);
+            // too large for FreeType
+            assert!(piet.text().new_font_by_name("sans", 1e5).build().is_err());
+        });
+    }
+
+    /// Affine transforms with each edge value in each coefficient, in
+    /// assorted combinations.
+    fn edge_affines() -> impl Iterator<Item = Affine> {
+        let n = EDGE_VALUES.len();
+        (0..n * n).map(move |i| {
+            let (k, m) = (i / n, i % n);
+            let mut coeffs = [0.0; 6];
+            for (j, c) in coeffs.iter_mut().enumerate() {
+                *c = EDGE_VALUES[(k + m * j) % n];
+            }
+            Affine::new(coeffs)
+        })
+    }
+
+    fn bits(affine: Affine) -> [u64; 6] {
+        let mut bits = [0; 6];
+        for (b, c) in bits.iter_mut().zip(&affine.as_coeffs()) {
+            *b = c.to_bits();
+        }
+        bits
+    }
+
+    /// Check that `affine` survives the trip to a cairo matrix and back,
+    /// and that it's current as it was given if it's invertible, give or
+    /// take the sign of its zeros.
+    fn check_affine(ctx: &mut Context, affine: Affine) {
+        let back = matrix_to_affine(affine_to_matrix(affine));
+        assert_eq!(bits(back), bits(affine), "{:?}", affine);
+        if is_invertible(affine) {
+            let mut piet = CairoRenderContext::new(ctx);
+            piet.save().unwrap();
+            piet.transform(affine);
+            assert_eq!(piet.current_transform().as_coeffs(), affine.as_coeffs());
+            piet.restore().unwrap();
+            piet.finish().unwrap();
+        }
+    }
+
+    #[test]
+    fn affine_matrix_round_trip_edges() {
+        let surface = ImageSurface::create(Format::ARgb32, 1, 1).unwrap();
+        let mut ctx = Context::new(&surface);
+        for affine in edge_affines() {
+            check_affine(&mut ctx, affine);
+        }
+    }
+
+    #[test]
+    fn premul_rounds_to_nearest() {
+        properties::check_premul_rounds_to_nearest(premul);
+    }
+
+    /// Check that cairo is given `color` as it is.
+    fn check_solid(piet: &mut CairoRenderContext, color: Color) {
+        let brush = piet.solid_brush(color.clone());
+        piet.set_brush(&brush);
+        let source = cairo::SolidPattern::try_from(piet.ctx.get_source()).unwrap();
+        let (r, g, b, a) = source.get_rgba();
+        assert_eq!(Color::rgba(r, g, b, a).as_rgba_u32(), color.as_rgba_u32());
+    }
+
+    #[test]
+    fn byte_frac_round_trip() {
+        render(1, 1, |piet| {
+            for byte in 0..=255u32 {
+                let frac = byte_to_frac(byte);
+                assert!((0.0..=1.0).contains(&frac));
+                // each channel in turn, with the others at other levels
+                let rgba = byte << 24 | (255 - byte) << 16 | (byte * 7 % 256) << 8 | byte;
+                check_solid(piet, Color::from_rgba32_u32(rgba));
+            }
+        });
+    }
+
+    /// Check that cairo is given `stops` as they're resolved, in each color
+    /// space.
+    fn check_stops(piet: &mut CairoRenderContext, stops: &[GradientStop]) {
+        for &color_space in &[GradientColorSpace::Srgb, GradientColorSpace::LinearRgb] {
+            let gradient = FixedLinearGradient {
+                start: Point::ZERO,
+                end: Point::new(1.0, 0.0),
+                stops: stops.to_vec(),
+                color_space,
+            };
+            let resolved = color_space.resolve_stops(stops).into_owned();
+            let linear = match piet.gradient(gradient).unwrap() {
+                Brush::Linear(linear) => linear,
+                _ => panic!("a linear gradient made another brush"),
+            };
+            assert_eq!(linear.get_color_stop_count(), resolved.len() as isize);
+            let mut last = 0.0;
+            for (i, stop) in resolved.iter().enumerate() {
+                let (pos, r, g, b, a) = linear.get_color_stop_rgba(i as isize);
+                assert_eq!(pos, stop.pos as f64);
+                assert!(pos >= last);
+                last = pos;
+                let color = Color::rgba(r, g, b, a).as_rgba_u32();
+                assert_eq!(color, stop.color.as_rgba_u32(), "stop {}", i);
+            }
+            // interpolating in another space keeps the stops it was given
+            let (first, last) = (&resolved[0], &resolved[resolved.len() - 1]);
+            assert_eq!(first.color.as_rgba_u32(), stops[0].color.as_rgba_u32());
+            let given = &stops[stops.len() - 1];
+            assert_eq!(last.color.as_rgba_u32(), given.color.as_rgba_u32());
+        }
+    }
+
+    #[test]
+    fn gradient_stops_round_trip() {
+        render(1, 1, |piet| {
+            // every byte in every channel, at positions packed toward both ends
+            let stops: Vec<GradientStop> = (0..=255u32)
+                .map(|i| GradientStop {
+                    pos: (i as f32 / 255.0).powi(3),
+                    color: Color::from_rgba32_u32(
+                        i << 24 | (255 - i) << 16 | (i * 97 % 256) << 8 | (i * 31 % 256),
+                    ),
+                })
+                .collect();
+            check_stops(piet, &stops);
+        });
+    }
+
+    proptest! {
+        #[test]
+        fn affine_matrix_round_trip(affine in properties::affine()) {
+            let surface = ImageSurface::create(Format::ARgb32, 1, 1).unwrap();
+            check_affine(&mut Context::new(&surface), affine);
+        }
+
+        #[test]
+        fn solid_color_round_trip(color in properties::color()) {
+            render(1, 1, |piet| check_solid(piet, color));
+        }
+
+        #[test]
+        fn generated_gradient_stops_round_trip(stops in properties::stops(8)) {
+            render(1, 1, |piet| check_stops(piet, &stops));
+        }
+    }
+}
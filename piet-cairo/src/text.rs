@@ -1,53 +1,254 @@
 //! Text functionality for Piet cairo backend
 
-mod grapheme;
-
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ops::Range;
+use std::rc::{Rc, Weak};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use cairo::{
+    Context, FontFace, FontOptions, FontSlant, FontWeight, Format, Glyph, HintMetrics,
+    ImageSurface, Matrix, ScaledFont,
+};
 
-use cairo::{FontFace, FontOptions, FontSlant, FontWeight, Matrix, ScaledFont};
-
-use piet::kurbo::Point;
+use piet::kurbo::{Affine, Point, Rect};
 
 use piet::{
-    Error, Font, FontBuilder, HitTestMetrics, HitTestPoint, HitTestTextPosition, RoundInto, Text,
-    TextLayout, TextLayoutBuilder,
+    new_error, ClusterMetric, Color, DecorationMetrics, EmojiBitmap, EmojiStrategy, Error,
+    ErrorKind, Font, FontBuilder, FontFileInfo, FontStretch, FontStyle, FontSubstitutions,
+    FontVariation, FontWarning, GenericFamily, HitTestPoint, HitTestTextPosition, LayoutModel,
+    LayoutRun, LineMetric, MemoryCharge, RoundInto, Text, TextAlignment, TextAttribute, TextLayout,
+    TextLayoutBuilder, TextTruncation, TruncatedText, WritingMode,
 };
 
 use unicode_segmentation::UnicodeSegmentation;
 
-use self::grapheme::point_x_in_grapheme;
+use crate::MEMORY;
+
+/// Emoji bitmaps with their bounds relative to the origin.
+type EmojiBitmaps<'a> = Vec<(Rect, &'a EmojiBitmap)>;
 
-/// Right now, we don't need any state, as the "toy text API" treats the
-/// access to system font information as a global. This will change.
+/// Glyphs sharing a font and a color from the layout, or `None` for the
+/// brush.
+type GlyphRuns<'a> = Vec<(&'a ScaledFont, Option<&'a Color>, Vec<Glyph>)>;
+
+/// The "toy text API" treats access to system font information as a global,
+/// so the only state is the font substitution table and emoji strategy. This
+/// will change.
+///
+/// The toy API also resolves each font to a single face, with no fallback for
+/// characters it lacks, so layouts look for fallback faces themselves, one
+/// grapheme cluster at a time.
+///
+/// Clones are handles to the same state.
 // we use a phantom lifetime here to match the API of the d2d backend,
 // and the likely API of something with access to system font information.
-pub struct CairoText<'a>(PhantomData<&'a ()>);
+#[derive(Clone)]
+pub struct CairoText<'a> {
+    settings: Arc<Mutex<TextSettings>>,
+    phantom: PhantomData<&'a ()>,
+}
+
+/// The state shared by the handles to a text factory.
+struct TextSettings {
+    substitutions: FontSubstitutions,
+    emoji: EmojiStrategy,
+    system_fonts: bool,
+}
+
+pub struct CairoFont {
+    font: ScaledFont,
+    face: ToyFace,
+    warnings: Vec<FontWarning>,
+    grid_glyphs: Rc<RefCell<GlyphCache>>,
+}
+
+/// Glyphs looked up for grid drawing, charged to the thread's memory.
+struct GlyphCache {
+    glyphs: HashMap<char, Option<Glyph>>,
+    memory: MemoryCharge,
+}
+
+thread_local! {
+    /// The glyph caches of the fonts made on this thread, to clear when
+    /// trimming. Those of dropped fonts are removed as new ones are added.
+    static GLYPH_CACHES: RefCell<Vec<Weak<RefCell<GlyphCache>>>> = const { RefCell::new(Vec::new()) };
+}
 
-pub struct CairoFont(ScaledFont);
+/// The face the toy API was asked for, to make the font again in other
+/// styles. cairo-rs releases the face it gets from a scaled font without
+/// having taken a reference to it, so it's kept here instead.
+#[derive(Clone)]
+struct ToyFace {
+    family: String,
+    slant: FontSlant,
+    weight: FontWeight,
+    /// The families given to the font builder, to try before
+    /// `DEFAULT_FALLBACKS`.
+    fallbacks: Vec<String>,
+}
 
 pub struct CairoFontBuilder {
     family: String,
     weight: FontWeight,
     slant: FontSlant,
     size: f64,
+    substitute: Option<String>,
+    fallbacks: Vec<String>,
+    variations: Vec<FontVariation>,
+    system_fonts: bool,
 }
 
+/// A laid out text.
+///
+/// Right-to-left text is reordered with piet's partial bidirectional
+/// algorithm: only the implicit rules of [UAX #9] are applied, so explicit
+/// embeddings, overrides and isolates are ignored, and characters are
+/// classed by the blocks of their scripts rather than from the full Unicode
+/// data. Text mixing directions inside an embedding may come out in the
+/// wrong order.
+///
+/// [UAX #9]: https://www.unicode.org/reports/tr9/
 pub struct CairoTextLayout {
     // TODO should these fields be pub(crate)?
     pub font: ScaledFont,
+    face: ToyFace,
+    /// The displayed text.
     pub text: String,
+    /// The text's lines, its attributes, and where its clusters are.
+    pub(crate) model: LayoutModel,
+    /// The shaped glyphs, relative to the origin, each with the index of the
+    /// grapheme cluster it belongs to. Line breaks and the spaces hanging
+    /// at wraps have none.
+    pub(crate) glyphs: Vec<(usize, Glyph)>,
+    /// The displayed text split where its font or underline changes, in
+    /// order and covering all of it.
+    runs: Vec<StyleRun>,
+    emoji: EmojiStrategy,
 }
 
-pub struct CairoTextLayoutBuilder(CairoTextLayout);
+/// A range of the displayed text, starting and ending at grapheme
+/// boundaries, drawn in one style.
+struct StyleRun {
+    range: Range<usize>,
+    style: RunStyle,
+    font: ScaledFont,
+    /// The index of the family the font is of in the layout's fallbacks, if
+    /// the run is drawn with one.
+    fallback: Option<usize>,
+}
+
+/// The layout's font, changed by the attributes over a run.
+#[derive(Clone, Copy, PartialEq)]
+struct RunStyle {
+    size: f64,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strikethrough: bool,
+    letter_spacing: f64,
+    word_spacing: f64,
+    /// Whether digits are widened to the widest one, for the `tnum` feature.
+    tabular_numbers: bool,
+}
+
+impl RunStyle {
+    fn is_spaced(&self) -> bool {
+        self.letter_spacing != 0.0 || self.word_spacing != 0.0 || self.tabular_numbers
+    }
+
+    /// The space added after `grapheme`.
+    fn spacing(&self, grapheme: &str) -> f64 {
+        if piet::is_word_separator(grapheme) {
+            self.letter_spacing + self.word_spacing
+        } else {
+            self.letter_spacing
+        }
+    }
+
+    /// The space added before and after `grapheme` in `font`: the padding
+    /// that centers a digit in the advance `digits` of the widest one, when
+    /// numbers are tabular, and its spacing.
+    fn padding(&self, grapheme: &str, font: &ScaledFont, digits: f64) -> (f64, f64) {
+        let after = self.spacing(grapheme);
+        if !self.tabular_numbers || !is_digit(grapheme) {
+            return (0.0, after);
+        }
+        let pad = (digits - font.text_extents(grapheme).x_advance).max(0.0) / 2.0;
+        (pad, pad + after)
+    }
+
+    /// The advance of the widest digit in `font`, when numbers are tabular.
+    fn digit_width(&self, font: &ScaledFont) -> f64 {
+        if !self.tabular_numbers {
+            return 0.0;
+        }
+        DIGITS
+            .iter()
+            .map(|digit| font.text_extents(digit).x_advance)
+            .fold(0.0, f64::max)
+    }
+
+    /// The space added across `text` in `font`, which starts and ends at
+    /// grapheme boundaries.
+    fn text_spacing(&self, text: &str, font: &ScaledFont) -> f64 {
+        if !self.is_spaced() {
+            return 0.0;
+        }
+        let digits = self.digit_width(font);
+        text.graphemes(true)
+            .map(|g| {
+                let (before, after) = self.padding(g, font, digits);
+                before + after
+            })
+            .sum()
+    }
+}
+
+const DIGITS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
+fn is_digit(grapheme: &str) -> bool {
+    grapheme.len() == 1 && grapheme.as_bytes()[0].is_ascii_digit()
+}
+
+pub struct CairoTextLayoutBuilder {
+    font: ScaledFont,
+    face: ToyFace,
+    text: String,
+    /// Ranges are into the original text.
+    attributes: Vec<(Range<usize>, TextAttribute)>,
+    default_color: Option<Color>,
+    emoji: EmojiStrategy,
+    mask: Option<char>,
+    transform: Option<Affine>,
+    max_width: f64,
+    alignment: TextAlignment,
+    max_lines: Option<(usize, TextTruncation)>,
+}
 
 impl<'a> CairoText<'a> {
     /// Create a new factory that satisfies the piet `Text` trait.
     ///
-    /// No state is needed for now because the current implementation is just
-    /// toy text, but that will change when proper text is implemented.
+    /// Little state is needed for now because the current implementation is
+    /// just toy text, but that will change when proper text is implemented.
     #[allow(clippy::new_without_default)]
     pub fn new() -> CairoText<'a> {
-        CairoText(PhantomData)
+        let settings = TextSettings {
+            substitutions: FontSubstitutions::new(),
+            emoji: EmojiStrategy::Platform,
+            system_fonts: true,
+        };
+        CairoText {
+            settings: Arc::new(Mutex::new(settings)),
+            phantom: PhantomData,
+        }
+    }
+
+    fn settings(&self) -> MutexGuard<'_, TextSettings> {
+        // the settings are only assigned, so a panic can't leave them torn
+        self.settings.lock().unwrap_or_else(PoisonError::into_inner)
     }
 }
 
@@ -58,173 +259,927 @@ impl<'a> Text for CairoText<'a> {
     type TextLayoutBuilder = CairoTextLayoutBuilder;
 
     fn new_font_by_name(&mut self, name: &str, size: f64) -> Self::FontBuilder {
+        let settings = self.settings();
         CairoFontBuilder {
             family: name.to_owned(),
             size: size.round_into(),
             weight: FontWeight::Normal,
             slant: FontSlant::Normal,
+            substitute: settings.substitutions.get(name).map(str::to_owned),
+            fallbacks: Vec::new(),
+            variations: Vec::new(),
+            system_fonts: settings.system_fonts,
         }
     }
 
     fn new_text_layout(&mut self, font: &Self::Font, text: &str) -> Self::TextLayoutBuilder {
-        let text_layout = CairoTextLayout {
-            font: font.0.clone(),
-            text: text.to_owned(),
-        };
-        CairoTextLayoutBuilder(text_layout)
+        CairoTextLayoutBuilder {
+            font: font.font.clone(),
+            face: font.face.clone(),
+            text: cairo_text(text),
+            attributes: Vec::new(),
+            default_color: None,
+            emoji: self.settings().emoji.clone(),
+            mask: None,
+            transform: None,
+            max_width: f64::INFINITY,
+            alignment: TextAlignment::Start,
+            max_lines: None,
+        }
+    }
+
+    fn set_font_substitutions(&mut self, substitutions: FontSubstitutions) {
+        self.settings().substitutions = substitutions;
+    }
+
+    fn set_emoji_strategy(&mut self, strategy: EmojiStrategy) {
+        self.settings().emoji = strategy;
+    }
+
+    fn load_font(&mut self, data: &[u8]) -> Result<String, Error> {
+        FontFileInfo::read(data).ok_or_else(|| new_error(ErrorKind::InvalidInput))?;
+        // The toy API makes faces from fontconfig's families; loading a face
+        // from memory needs FreeType.
+        Err(new_error(ErrorKind::NotSupported))
+    }
+
+    fn set_system_fonts(&mut self, enabled: bool) {
+        self.settings().system_fonts = enabled;
+    }
+
+    fn prewarm(&mut self, font: &Self::Font, text: &str) {
+        // Drawing each glyph once fills the scaled font's glyph cache, which
+        // is shared by every context that draws with it.
+        let glyphs = prewarm_glyphs(font, text);
+        let extents = font.font.extents();
+        let width = extents.max_x_advance.ceil().max(1.0) as i32;
+        let height = (extents.ascent + extents.descent).ceil().max(1.0) as i32;
+        if let Ok(surface) = ImageSurface::create(Format::A8, width, height) {
+            let ctx = Context::new(&surface);
+            ctx.set_scaled_font(&font.font);
+            ctx.show_glyphs(&glyphs);
+        }
+    }
+}
+
+/// Each of the glyphs `font` shows `text` with, once, drawn over each other
+/// on the baseline.
+fn prewarm_glyphs(font: &CairoFont, text: &str) -> Vec<Glyph> {
+    let (mut glyphs, _) = font.font.text_to_glyphs(0.0, 0.0, &cairo_text(text));
+    glyphs.sort_by_key(|g| g.index);
+    glyphs.dedup_by_key(|g| g.index);
+    let ascent = font.font.extents().ascent;
+    for glyph in &mut glyphs {
+        glyph.x = 0.0;
+        glyph.y = ascent;
     }
+    glyphs
 }
 
 impl FontBuilder for CairoFontBuilder {
     type Out = CairoFont;
 
+    fn weight(mut self, weight: piet::FontWeight) -> Self {
+        self.weight = if weight >= piet::FontWeight::SEMI_BOLD {
+            FontWeight::Bold
+        } else {
+            FontWeight::Normal
+        };
+        self
+    }
+
+    fn style(mut self, style: FontStyle) -> Self {
+        self.slant = match style {
+            FontStyle::Normal => FontSlant::Normal,
+            FontStyle::Italic => FontSlant::Italic,
+            FontStyle::Oblique => FontSlant::Oblique,
+        };
+        self
+    }
+
+    fn stretch(self, _stretch: FontStretch) -> Self {
+        // fontconfig knows widths, but the toy API doesn't pass them on
+        self
+    }
+
+    fn fallback(mut self, family: &str) -> Self {
+        self.fallbacks.push(family.to_owned());
+        self
+    }
+
+    fn variation(mut self, variation: FontVariation) -> Self {
+        self.variations.retain(|v| v.tag() != variation.tag());
+        self.variations.push(variation);
+        self
+    }
+
     fn build(self) -> Result<Self::Out, Error> {
-        let font_face = FontFace::toy_create(&self.family, self.slant, self.weight);
-        let font_matrix = scale_matrix(self.size);
-        let ctm = scale_matrix(1.0);
-        let options = FontOptions::default();
-        let scaled_font = ScaledFont::new(&font_face, &font_matrix, &ctm, &options);
-        Ok(CairoFont(scaled_font))
+        if !valid_font_size(self.size) {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        if !self.system_fonts {
+            // no fonts can be loaded, so there are none left
+            return Err(new_error(ErrorKind::NotSupported));
+        }
+        // fontconfig knows the CSS generics as aliases; normalize shorthands
+        // like "Mono" so they resolve too.
+        let generic = GenericFamily::from_name(&self.family);
+        let family = generic.map(GenericFamily::name).unwrap_or(&self.family);
+        let mut scaled_font = toy_scaled_font(family, self.slant, self.weight, self.size);
+        let mut face = ToyFace {
+            family: family.to_owned(),
+            slant: self.slant,
+            weight: self.weight,
+            fallbacks: self.fallbacks.clone(),
+        };
+        let mut warnings = Vec::new();
+        if generic.is_none() && self.is_fallback(&scaled_font) {
+            let substitute = self
+                .substitute
+                .as_ref()
+                .map(|name| {
+                    let font = toy_scaled_font(name, self.slant, self.weight, self.size);
+                    (name.clone(), font)
+                })
+                .filter(|(_, font)| !self.is_fallback(font));
+            if let Some((substitute, font)) = substitute {
+                scaled_font = font;
+                face.family = substitute.clone();
+                warnings.push(FontWarning::Substituted {
+                    requested: self.family,
+                    substitute,
+                });
+            } else {
+                warnings.push(FontWarning::MissingFamily {
+                    requested: self.family,
+                    fallback: FALLBACK_FAMILY.to_owned(),
+                });
+            }
+        }
+        #[cfg(feature = "variable-fonts")]
+        let scaled_font = with_variations(&scaled_font, &self.variations);
+        Ok(CairoFont {
+            font: scaled_font,
+            face,
+            warnings,
+            grid_glyphs: GlyphCache::new(),
+        })
     }
 }
 
-impl Font for CairoFont {}
+impl CairoFontBuilder {
+    /// Whether `font` is fontconfig's substitute for an unknown family.
+    ///
+    /// The toy API never fails: fontconfig silently substitutes its default
+    /// for unknown families, and cairo doesn't tell us which face it picked.
+    /// We compare against a family that can't exist, which gets the same
+    /// substitute; a family that *is* the system default will be misreported
+    /// as missing.
+    fn is_fallback(&self, font: &ScaledFont) -> bool {
+        let probe = toy_scaled_font(MISSING_FAMILY_PROBE, self.slant, self.weight, self.size);
+        let requested = font.text_extents(PROBE_TEXT);
+        let fallback = probe.text_extents(PROBE_TEXT);
+        requested.x_advance == fallback.x_advance
+            && requested.width == fallback.width
+            && requested.height == fallback.height
+    }
+}
 
-impl TextLayoutBuilder for CairoTextLayoutBuilder {
-    type Out = CairoTextLayout;
+/// The family fontconfig resolves unknown names to.
+const FALLBACK_FAMILY: &str = "sans-serif";
+
+/// Families tried after a font's own fallbacks, with the scripts and symbols
+/// the usual default faces lack. Those that aren't installed resolve to the
+/// default face, which doesn't have the glyph either.
+const DEFAULT_FALLBACKS: &[&str] = &[
+    "Noto Sans",
+    "Noto Sans CJK SC",
+    "Noto Sans Symbols",
+    "Noto Sans Symbols2",
+    "Noto Sans Math",
+    "DejaVu Sans",
+    "Symbola",
+    "Unifont",
+];
+/// Color emoji families, tried first for emoji whatever the font, since
+/// text faces often have plain outlines for the same characters. Cairo draws
+/// their color glyphs as they are, ignoring the brush.
+const EMOJI_FAMILIES: &[&str] = &["Noto Color Emoji", "Apple Color Emoji", "Twemoji", "emoji"];
+const MISSING_FAMILY_PROBE: &str = "piet missing family probe";
+const PROBE_TEXT: &str = "AaBbGgIiMmQqWw0123456789";
+
+/// Whether two faces gave the same glyphs for some text, which for fonts
+/// from the toy API means they're the same face.
+fn same_glyphs(a: &[Glyph], b: &[Glyph]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(a, b)| a.index == b.index && a.x == b.x)
+}
 
-    fn build(self) -> Result<Self::Out, Error> {
-        Ok(self.0)
+/// Whether cairo can scale a font to `size`; it panics on sizes whose
+/// matrix can't be inverted, including NaN, zero and huge ones, and on sizes
+/// FreeType can't render, which are 2^16 pixels or more.
+fn valid_font_size(size: f64) -> bool {
+    let det = size * size;
+    det.is_finite() && det != 0.0 && size.abs() < 65536.0
+}
+
+/// `text`, with the characters cairo's C API can't take replaced by others
+/// of the same length, so positions in the text stay where they were: a
+/// NUL, which would end the text, and the noncharacters cairo rejects as
+/// invalid UTF-8, which cairo-rs panics on.
+fn cairo_text(text: &str) -> String {
+    text.chars().map(cairo_char).collect()
+}
+
+/// `c`, or the character of the same length `cairo_text` replaces it with.
+fn cairo_char(c: char) -> char {
+    match c {
+        '\0' => '\u{1}',
+        '\u{fdd0}'..='\u{fdef}' | '\u{fffe}' | '\u{ffff}' => '\u{fffd}',
+        // the last two code points of each plane after the first
+        c if c as u32 & 0xfffe == 0xfffe => '\u{10fffd}',
+        c => c,
     }
 }
 
-impl TextLayout for CairoTextLayout {
-    fn width(&self) -> f64 {
-        self.font.text_extents(&self.text).x_advance
+/// The toy API's face for `family`.
+///
+/// cairo-rs panics on a name cairo can't take, so the name ends at the
+/// first NUL, where cairo would end it, and its noncharacters are replaced.
+fn toy_face(family: &str, slant: FontSlant, weight: FontWeight) -> FontFace {
+    let family = family.split('\0').next().unwrap_or_default();
+    FontFace::toy_create(&cairo_text(family), slant, weight)
+}
+
+fn toy_scaled_font(family: &str, slant: FontSlant, weight: FontWeight, size: f64) -> ScaledFont {
+    let font_face = toy_face(family, slant, weight);
+    let font_matrix = scale_matrix(size);
+    let ctm = scale_matrix(1.0);
+    let options = FontOptions::default();
+    ScaledFont::new(&font_face, &font_matrix, &ctm, &options)
+}
+
+impl CairoFont {
+    pub(crate) fn scaled_font(&self) -> &ScaledFont {
+        &self.font
     }
 
-    // first assume one line.
-    // TODO do with lines
-    fn hit_test_point(&self, point: Point) -> HitTestPoint {
-        // internal logic is using grapheme clusters, but return the text position associated
-        // with the border of the grapheme cluster.
+    /// The glyph for `ch`, or `None` if it doesn't map to exactly one.
+    ///
+    /// Lookups are cached, as a terminal draws the same few characters over
+    /// and over.
+    pub(crate) fn grid_glyph(&self, ch: char) -> Option<Glyph> {
+        let mut cache = self.grid_glyphs.borrow_mut();
+        if let Some(glyph) = cache.glyphs.get(&ch) {
+            return *glyph;
+        }
+        let mut buf = [0; 4];
+        let (glyphs, _) = self.font.text_to_glyphs(0.0, 0.0, ch.encode_utf8(&mut buf));
+        let glyph = match glyphs.as_slice() {
+            [glyph] => Some(*glyph),
+            _ => None,
+        };
+        cache.glyphs.insert(ch, glyph);
+        let bytes = cache.glyphs.capacity() * size_of::<(char, Option<Glyph>)>();
+        cache.memory.set(bytes);
+        glyph
+    }
+}
+
+impl GlyphCache {
+    /// An empty cache, kept track of to be cleared by `clear_glyph_caches`.
+    fn new() -> Rc<RefCell<GlyphCache>> {
+        let cache = Rc::new(RefCell::new(GlyphCache {
+            glyphs: HashMap::new(),
+            memory: MEMORY.with(|memory| memory.glyphs.charge(0)),
+        }));
+        GLYPH_CACHES.with(|caches| {
+            let mut caches = caches.borrow_mut();
+            caches.retain(|cache| cache.strong_count() > 0);
+            caches.push(Rc::downgrade(&cache));
+        });
+        cache
+    }
+}
+
+/// Empty the glyph caches of the fonts alive on this thread.
+pub(crate) fn clear_glyph_caches() {
+    GLYPH_CACHES.with(|caches| {
+        for cache in caches.borrow().iter().filter_map(Weak::upgrade) {
+            let mut cache = cache.borrow_mut();
+            cache.glyphs = HashMap::new();
+            cache.memory.set(0);
+        }
+    });
+}
+
+impl Font for CairoFont {
+    fn warnings(&self) -> &[FontWarning] {
+        &self.warnings
+    }
+
+    fn decoration_metrics(&self) -> DecorationMetrics {
+        decoration_metrics(&self.font)
+    }
+}
+
+impl TextLayoutBuilder for CairoTextLayoutBuilder {
+    type Out = CairoTextLayout;
+
+    fn obscure(mut self, mask: char) -> Self {
+        self.mask = Some(mask);
+        self
+    }
 
-        // null case
-        if self.text.is_empty() {
-            return HitTestPoint::default();
+    fn range_attribute(mut self, range: Range<usize>, attribute: TextAttribute) -> Self {
+        let end = range.end.min(self.text.len());
+        if range.start < end {
+            self.attributes.push((range.start..end, attribute));
         }
+        self
+    }
+
+    fn default_color(mut self, color: Color) -> Self {
+        self.default_color = Some(color);
+        self
+    }
 
-        // get bounds
-        // TODO handle if string is not null yet count is 0?
-        let end = UnicodeSegmentation::graphemes(self.text.as_str(), true).count() - 1;
-        let end_bounds = match self.get_grapheme_boundaries(end) {
-            Some(bounds) => bounds,
-            None => return HitTestPoint::default(),
+    fn transform(mut self, transform: Affine) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    fn max_width(mut self, width: f64) -> Self {
+        self.max_width = width;
+        self
+    }
+
+    fn alignment(mut self, alignment: TextAlignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    fn max_lines(mut self, lines: usize, truncation: TextTruncation) -> Self {
+        self.max_lines = Some((lines, truncation));
+        self
+    }
+
+    fn writing_mode(self, _mode: WritingMode) -> Self {
+        // the toy text API has no vertical metrics, so text stays horizontal
+        self
+    }
+
+    fn build(self) -> Result<Self::Out, Error> {
+        let font = match self.transform {
+            Some(transform) => transformed_font(&self.font, transform),
+            None => self.font,
+        };
+        let mask = self.mask.map(cairo_char);
+        let mut layout = CairoTextLayout {
+            font,
+            face: self.face,
+            text: String::new(),
+            model: LayoutModel::new(self.text, mask, self.attributes, self.default_color),
+            glyphs: Vec::new(),
+            runs: Vec::new(),
+            emoji: self.emoji,
         };
+        layout.runs = layout.style_runs();
+        let runs = &layout.runs;
+        layout
+            .model
+            .wrap(self.max_width, |text, range| advance(runs, text, range));
+        if let Some((max_lines, truncation)) = self.max_lines {
+            let ellipsis_width = layout.font.text_extents(TruncatedText::ELLIPSIS).x_advance;
+            let runs = &layout.runs;
+            if layout.model.truncate(
+                max_lines,
+                truncation,
+                self.max_width,
+                ellipsis_width,
+                |text, range| advance(runs, text, range),
+            ) {
+                layout.runs = layout.style_runs();
+            }
+        }
+        let measured = layout
+            .runs
+            .iter()
+            .map(|run| {
+                let extents = run.font.extents();
+                LayoutRun {
+                    range: run.range.clone(),
+                    ascent: extents.ascent,
+                    descent: extents.descent,
+                    height: extents.height,
+                    decorations: decoration_metrics(&run.font),
+                    underline: run.style.underline,
+                    strikethrough: run.style.strikethrough,
+                }
+            })
+            .collect();
+        let runs = &layout.runs;
+        layout
+            .model
+            .place(self.alignment, self.max_width, measured, |text, range| {
+                advance(runs, text, range)
+            });
+        layout.text = layout.model.text().to_owned();
+        layout.glyphs = layout.place_lines(layout.shape_runs());
+        Ok(layout)
+    }
+}
+
+/// `font` with its variable font axes set, as `"wght=650,wdth=75"`.
+///
+/// The variations are kept in the font's options, so the fonts made from it
+/// for styled runs and fallbacks keep them too.
+#[cfg(feature = "variable-fonts")]
+fn with_variations(font: &ScaledFont, variations: &[FontVariation]) -> ScaledFont {
+    if variations.is_empty() {
+        return font.clone();
+    }
+    let settings: Vec<String> = variations
+        .iter()
+        .map(|v| format!("{}={}", String::from_utf8_lossy(&v.tag()), v.value()))
+        .collect();
+    let options = font.get_font_options();
+    options.set_variations(settings.join(",").as_str());
+    ScaledFont::new(
+        &font.get_font_face(),
+        &font.get_font_matrix(),
+        &font.get_ctm(),
+        &options,
+    )
+}
+
+/// `font` with its metrics hinted for drawing under `transform`.
+///
+/// Rounding advances to whole device pixels only helps when text runs along
+/// the pixel grid, so under rotation or skew it's turned off.
+fn transformed_font(font: &ScaledFont, transform: Affine) -> ScaledFont {
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    let det = transform.determinant();
+    if det == 0.0 || !det.is_finite() {
+        // no device pixels to hint for
+        return font.clone();
+    }
+    let device_size = font.get_font_matrix().yy * a.hypot(b).max(c.hypot(d));
+    if !valid_font_size(device_size) {
+        return font.clone();
+    }
+    let ctm = Matrix::new(a, b, c, d, 0.0, 0.0);
+    let mut options = font.get_font_options();
+    if b != 0.0 || c != 0.0 {
+        options.set_hint_metrics(HintMetrics::Off);
+    }
+    ScaledFont::new(
+        &font.get_font_face(),
+        &font.get_font_matrix(),
+        &ctm,
+        &options,
+    )
+}
 
-        let start = 0;
-        let start_bounds = match self.get_grapheme_boundaries(start) {
-            Some(bounds) => bounds,
-            None => return HitTestPoint::default(),
+/// Shape `text`, tagging each glyph with its grapheme cluster.
+fn shape(font: &ScaledFont, text: &str) -> Vec<(usize, Glyph)> {
+    let (glyphs, clusters) = font.text_to_glyphs(0.0, 0.0, text);
+    let starts: Vec<usize> = text.grapheme_indices(true).map(|(i, _)| i).collect();
+    let last = starts.len().saturating_sub(1);
+    if clusters.is_empty() {
+        // no cluster mapping; assume a glyph per grapheme
+        return glyphs
+            .into_iter()
+            .enumerate()
+            .map(|(i, glyph)| (i.min(last), glyph))
+            .collect();
+    }
+    let mut result = Vec::with_capacity(glyphs.len());
+    let (mut byte, mut glyph) = (0, 0);
+    for cluster in clusters {
+        let grapheme = starts.partition_point(|&start| start <= byte).max(1) - 1;
+        let end = (glyph + cluster.num_glyphs as usize).min(glyphs.len());
+        result.extend(glyphs[glyph..end].iter().map(|g| (grapheme, *g)));
+        byte += cluster.num_bytes as usize;
+        glyph = end;
+    }
+    result
+}
+
+impl CairoTextLayout {
+    /// Split the displayed text into runs of one style, at the ends of the
+    /// attributes that change the font or underline it, moved back to the
+    /// start of the grapheme cluster they're in.
+    fn style_runs(&self) -> Vec<StyleRun> {
+        let base = RunStyle {
+            size: self.font.get_font_matrix().yy,
+            bold: self.face.weight == FontWeight::Bold,
+            italic: self.face.slant != FontSlant::Normal,
+            underline: false,
+            strikethrough: false,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+            tabular_numbers: false,
         };
+        let mut runs: Vec<StyleRun> = Vec::new();
+        for range in self.model.style_ranges() {
+            let style = self.style_at(base, range.start);
+            match runs.last_mut() {
+                Some(last) if last.style == style => last.range.end = range.end,
+                _ => runs.push(StyleRun {
+                    range,
+                    style,
+                    font: self.run_font(base, style),
+                    fallback: None,
+                }),
+            }
+        }
+        if runs.is_empty() {
+            runs.push(StyleRun {
+                range: 0..0,
+                style: base,
+                font: self.font.clone(),
+                fallback: None,
+            });
+        }
+        self.fallback_runs(base, runs)
+    }
 
-        // first test beyond ends
-        if point.x > end_bounds.trailing {
-            let mut res = HitTestPoint::default();
-            res.metrics.text_position = self.text.len();
-            return res;
+    /// Split runs where their font has no glyph for a grapheme cluster, into
+    /// runs of the first fallback family that has all of its glyphs.
+    ///
+    /// Emoji are drawn with the first color emoji family installed, if any,
+    /// whether or not the run's font has them.
+    fn fallback_runs(&self, base: RunStyle, runs: Vec<StyleRun>) -> Vec<StyleRun> {
+        let families: Vec<&str> = self
+            .face
+            .fallbacks
+            .iter()
+            .map(String::as_str)
+            .chain(DEFAULT_FALLBACKS.iter().copied())
+            .chain(EMOJI_FAMILIES.iter().copied())
+            .collect();
+        let emoji_families = families.len() - EMOJI_FAMILIES.len()..families.len();
+        let mut split: Vec<StyleRun> = Vec::with_capacity(runs.len());
+        for run in runs {
+            let text = &self.model.text()[run.range.clone()];
+            let graphemes: Vec<(usize, &str)> = text.grapheme_indices(true).collect();
+            let mut missing = vec![false; graphemes.len()];
+            for (cluster, glyph) in shape(&run.font, text) {
+                // glyph 0 is the font's box for characters it doesn't have
+                missing[cluster] |= glyph.index == 0;
+            }
+            let emoji: Vec<bool> = graphemes
+                .iter()
+                .map(|&(_, grapheme)| piet::is_emoji_presentation(grapheme))
+                .collect();
+            if !missing.contains(&true) && !emoji.contains(&true) {
+                split.push(run);
+                continue;
+            }
+            // the fallbacks in the run's style, made when first needed
+            let mut fonts: Vec<Option<ScaledFont>> = vec![None; families.len()];
+            // what uninstalled families resolve to, to skip emoji families
+            // that aren't there
+            let default_font = self.family_font(MISSING_FAMILY_PROBE, base, run.style);
+            for (i, &(start, grapheme)) in graphemes.iter().enumerate() {
+                let end = start + grapheme.len();
+                let visible = grapheme
+                    .chars()
+                    .any(|c| !c.is_control() && !c.is_whitespace());
+                // the family's glyphs for the cluster, if it has all of them
+                let mut glyphs_of = |family: usize| {
+                    let font = fonts[family]
+                        .get_or_insert_with(|| self.family_font(families[family], base, run.style));
+                    let (glyphs, _) = font.text_to_glyphs(0.0, 0.0, grapheme);
+                    Some(glyphs)
+                        .filter(|glyphs| !glyphs.is_empty() && glyphs.iter().all(|g| g.index != 0))
+                };
+                let color_emoji = if emoji[i] {
+                    let (defaults, _) = default_font.text_to_glyphs(0.0, 0.0, grapheme);
+                    emoji_families.clone().find(|&family| {
+                        glyphs_of(family).is_some_and(|glyphs| !same_glyphs(&glyphs, &defaults))
+                    })
+                } else {
+                    None
+                };
+                let fallback = if color_emoji.is_some() {
+                    color_emoji
+                } else if missing[i] && visible {
+                    (0..families.len()).find(|&family| glyphs_of(family).is_some())
+                } else {
+                    None
+                };
+                let range = run.range.start + start..run.range.start + end;
+                match split.last_mut() {
+                    Some(last)
+                        if last.range.end == range.start
+                            && last.style == run.style
+                            && last.fallback == fallback =>
+                    {
+                        last.range.end = range.end
+                    }
+                    _ => split.push(StyleRun {
+                        range,
+                        style: run.style,
+                        font: match fallback {
+                            Some(family) => fonts[family].clone().unwrap(),
+                            None => run.font.clone(),
+                        },
+                        fallback,
+                    }),
+                }
+            }
         }
-        if point.x <= start_bounds.leading {
-            return HitTestPoint::default();
+        split
+    }
+
+    /// The style at `position` in the displayed text.
+    fn style_at(&self, base: RunStyle, position: usize) -> RunStyle {
+        let mut style = base;
+        for attribute in self.model.attributes_at(position) {
+            match *attribute {
+                TextAttribute::Size(size) if size > 0.0 && valid_font_size(size) => {
+                    style.size = size
+                }
+                TextAttribute::Weight(weight) => {
+                    style.bold = weight >= piet::FontWeight::SEMI_BOLD;
+                }
+                TextAttribute::Italic(italic) => style.italic = italic,
+                TextAttribute::Underline(underline) => style.underline = underline,
+                TextAttribute::Strikethrough(strikethrough) => style.strikethrough = strikethrough,
+                TextAttribute::LetterSpacing(spacing) if spacing.is_finite() => {
+                    style.letter_spacing = spacing
+                }
+                TextAttribute::WordSpacing(spacing) if spacing.is_finite() => {
+                    style.word_spacing = spacing
+                }
+                // proportional numbers are the toy API's own
+                TextAttribute::FontFeature(feature) => match &feature.tag() {
+                    b"tnum" => style.tabular_numbers = feature.value() != 0,
+                    b"pnum" if feature.value() != 0 => style.tabular_numbers = false,
+                    _ => (),
+                },
+                _ => (),
+            }
         }
+        style
+    }
 
-        // then test the beginning and end (common cases)
-        if let Some(hit) = point_x_in_grapheme(point.x, &start_bounds) {
-            return hit;
+    /// The layout's font changed to `style`, hinted the same way.
+    fn run_font(&self, base: RunStyle, style: RunStyle) -> ScaledFont {
+        if (style.size, style.bold, style.italic) == (base.size, base.bold, base.italic) {
+            return self.font.clone();
         }
-        if let Some(hit) = point_x_in_grapheme(point.x, &end_bounds) {
-            return hit;
+        self.family_font(&self.face.family, base, style)
+    }
+
+    /// A font of `family` in `style`, hinted like the layout's font.
+    fn family_font(&self, family: &str, base: RunStyle, style: RunStyle) -> ScaledFont {
+        // an oblique font stays oblique unless a range changes it
+        let slant = match (style.italic, style.italic == base.italic) {
+            (_, true) => self.face.slant,
+            (true, false) => FontSlant::Italic,
+            (false, false) => FontSlant::Normal,
+        };
+        let weight = if style.bold {
+            FontWeight::Bold
+        } else {
+            FontWeight::Normal
+        };
+        ScaledFont::new(
+            &toy_face(family, slant, weight),
+            &scale_matrix(style.size),
+            &self.font.get_ctm(),
+            &self.font.get_font_options(),
+        )
+    }
+
+    /// The run `position` in the displayed text is in.
+    fn run_of(&self, position: usize) -> &StyleRun {
+        let run = self
+            .runs
+            .partition_point(|run| run.range.start <= position)
+            .saturating_sub(1);
+        &self.runs[run]
+    }
+
+    /// Shape each run with its font, one after another on a single line,
+    /// tagging each glyph with its grapheme cluster in the whole text.
+    ///
+    /// Spacing moves each cluster along by the space added after those
+    /// before it.
+    fn shape_runs(&self) -> Vec<(usize, Glyph)> {
+        let mut glyphs = Vec::with_capacity(self.text.len());
+        let (mut x, mut clusters) = (0.0, 0);
+        for run in &self.runs {
+            let text = &self.text[run.range.clone()];
+            let mut spaced = 0.0;
+            let mut offsets = Vec::new();
+            if run.style.is_spaced() {
+                let digits = run.style.digit_width(&run.font);
+                for grapheme in text.graphemes(true) {
+                    let (before, after) = run.style.padding(grapheme, &run.font, digits);
+                    offsets.push(spaced + before);
+                    spaced += before + after;
+                }
+            }
+            glyphs.extend(shape(&run.font, text).into_iter().map(|(cluster, glyph)| {
+                let offset = offsets.get(cluster).copied().unwrap_or(0.0);
+                let glyph = Glyph {
+                    x: glyph.x + x + offset,
+                    ..glyph
+                };
+                (clusters + cluster, glyph)
+            }));
+            x += run.font.text_extents(text).x_advance + spaced;
+            clusters += text.graphemes(true).count();
         }
+        glyphs
+    }
 
-        // Now that we know it's not beginning or end, begin binary search.
-        // Iterative style
-        let mut left = start;
-        let mut right = end;
-        loop {
-            // pick halfway point
-            let middle = left + ((right - left) / 2);
-
-            let grapheme_bounds = match self.get_grapheme_boundaries(middle) {
-                Some(bounds) => bounds,
-                None => return HitTestPoint::default(),
-            };
+    /// Move the glyphs shaped from the whole text onto their lines, leaving
+    /// out those between lines.
+    fn place_lines(&self, glyphs: Vec<(usize, Glyph)>) -> Vec<(usize, Glyph)> {
+        let model = &self.model;
+        let lines = model.lines();
+        if lines.len() == 1 && !model.is_aligned() && !model.has_rtl() {
+            return glyphs;
+        }
+        let starts: Vec<usize> = self.text.grapheme_indices(true).map(|(i, _)| i).collect();
+        // the left edge of each cluster of the reordered lines, by its start
+        let reordered: Vec<Option<HashMap<usize, f64>>> = (0..lines.len())
+            .map(|line| {
+                let clusters = model.visual_clusters(line)?;
+                Some(
+                    clusters
+                        .into_iter()
+                        .map(|c| (c.range.start, c.x0))
+                        .collect(),
+                )
+            })
+            .collect();
+        let mut current = 0;
+        let mut line_x = 0.0;
+        // the cluster last seen, and where its first glyph was shaped
+        let mut cluster_x = (usize::MAX, 0.0);
+        glyphs
+            .into_iter()
+            .filter_map(|(cluster, glyph)| {
+                let start = starts.get(cluster).copied().unwrap_or(0);
+                let line = model.line_of(start);
+                if start >= lines[line].end {
+                    return None;
+                }
+                if line != current {
+                    current = line;
+                    line_x = glyph.x;
+                }
+                if cluster != cluster_x.0 {
+                    cluster_x = (cluster, glyph.x);
+                }
+                let x = match &reordered[line] {
+                    Some(lefts) => {
+                        lefts.get(&start).copied().unwrap_or(0.0) + glyph.x - cluster_x.1
+                    }
+                    None => glyph.x - line_x + model.line_offset(line, start),
+                };
+                Some((
+                    cluster,
+                    Glyph {
+                        x,
+                        y: glyph.y + model.baseline(line),
+                        ..glyph
+                    },
+                ))
+            })
+            .collect()
+    }
 
-            if let Some(hit) = point_x_in_grapheme(point.x, &grapheme_bounds) {
-                return hit;
+    /// Split the text into emoji drawn as bitmaps, with their bounds
+    /// relative to the origin, and runs of glyphs for the rest of it.
+    ///
+    /// Returns `None` when the text can be drawn in one go, on one line
+    /// with neither bitmaps nor colors from the layout.
+    pub(crate) fn split_runs(&self) -> Option<(EmojiBitmaps<'_>, GlyphRuns<'_>)> {
+        let model = &self.model;
+        let found = self.emoji.find(&self.text);
+        let one_font = self.runs.len() == 1
+            && self.runs[0].fallback.is_none()
+            && !self.runs[0].style.is_spaced();
+        let one_line = model.lines().len() == 1 && !model.is_aligned() && !model.has_rtl();
+        if found.is_empty() && !model.has_colors() && one_line && one_font {
+            return None;
+        }
+        let starts: Vec<usize> = self.text.grapheme_indices(true).map(|(i, _)| i).collect();
+        let colors = model.cluster_colors();
+        let fonts = self.cluster_fonts();
+        let mut runs: GlyphRuns = Vec::new();
+        for &(cluster, glyph) in &self.glyphs {
+            if let Some(start) = starts.get(cluster) {
+                if found.iter().any(|(range, _)| range.contains(start)) {
+                    continue;
+                }
+            }
+            let color = colors.get(cluster).copied().flatten();
+            let font = fonts.get(cluster).copied().unwrap_or(&self.font);
+            match runs.last_mut() {
+                Some((last_font, last, glyphs))
+                    if std::ptr::eq(*last_font, font) && same_color(*last, color) =>
+                {
+                    glyphs.push(glyph)
+                }
+                _ => runs.push((font, color, vec![glyph])),
             }
+        }
+        let bitmaps = found
+            .into_iter()
+            .filter_map(|(range, bitmap)| Some((model.display_range_bounds(range)?, bitmap)))
+            .collect();
+        Some((bitmaps, runs))
+    }
+
+    /// The font of each grapheme cluster of the displayed text.
+    pub(crate) fn cluster_fonts(&self) -> Vec<&ScaledFont> {
+        self.text
+            .grapheme_indices(true)
+            .map(|(i, _)| &self.run_of(i).font)
+            .collect()
+    }
+}
 
-            // since it's not a hit, check if closer to start or finish
-            // and move the appropriate search boundary
-            if point.x < grapheme_bounds.leading {
-                right = middle;
-            } else if point.x > grapheme_bounds.trailing {
-                left = middle + 1;
+/// The advance of `range` of `text`, measured run by run, with its spacing.
+fn advance(runs: &[StyleRun], text: &str, range: Range<usize>) -> f64 {
+    runs.iter()
+        .filter_map(|run| {
+            let start = range.start.max(run.range.start);
+            let end = range.end.min(run.range.end);
+            if start < end {
+                let text = &text[start..end];
+                Some(
+                    run.font.text_extents(text).x_advance + run.style.text_spacing(text, &run.font),
+                )
             } else {
-                unreachable!("hit_test_point conditional is exhaustive");
+                None
             }
-        }
+        })
+        .sum()
+}
+
+fn same_color(a: Option<&Color>, b: Option<&Color>) -> bool {
+    a.map(Color::as_rgba_u32) == b.map(Color::as_rgba_u32)
+}
+
+impl TextLayout for CairoTextLayout {
+    fn width(&self) -> f64 {
+        self.model.width()
+    }
+
+    fn hit_test_point(&self, point: Point) -> HitTestPoint {
+        self.model.hit_test_point(point)
     }
 
     fn hit_test_text_position(&self, text_position: usize) -> Option<HitTestTextPosition> {
-        // Using substrings, but now with unicode grapheme awareness
+        self.model.hit_test_text_position(text_position)
+    }
 
-        let text_len = self.text.len();
+    fn range_bounds(&self, range: Range<usize>) -> Option<Rect> {
+        self.model.range_bounds(range)
+    }
 
-        if text_position == 0 {
-            return Some(HitTestTextPosition::default());
-        }
+    fn rects_for_range(&self, range: Range<usize>) -> Vec<Rect> {
+        self.model.rects_for_range(range)
+    }
 
-        if text_position as usize >= text_len {
-            return Some(HitTestTextPosition {
-                point: Point {
-                    x: self.font.text_extents(&self.text).x_advance,
-                    y: 0.0,
-                },
-                metrics: HitTestMetrics {
-                    text_position: text_len,
-                },
-            });
-        }
+    fn caret_bounds(&self, text_position: usize) -> Option<Rect> {
+        self.model.caret_bounds(text_position)
+    }
+
+    fn truncated_at(&self) -> Option<usize> {
+        self.model.truncated_at()
+    }
 
-        // Already checked that text_position > 0 and text_position < count.
-        // If text position is not at a grapheme boundary, use the text position of current
-        // grapheme cluster. But return the original text position
-        // Use the indices (byte offset, which for our purposes = utf8 code units).
-        let grapheme_indices = UnicodeSegmentation::grapheme_indices(self.text.as_str(), true)
-            .take_while(|(byte_idx, _s)| text_position >= *byte_idx);
+    fn line_metrics(&self) -> Vec<LineMetric> {
+        self.model.line_metrics()
+    }
 
-        if let Some((byte_idx, _s)) = grapheme_indices.last() {
-            let point_x = self.font.text_extents(&self.text[0..byte_idx]).x_advance;
+    fn cluster_metrics(&self) -> Vec<ClusterMetric> {
+        self.model.cluster_metrics()
+    }
 
-            Some(HitTestTextPosition {
-                point: Point { x: point_x, y: 0.0 },
-                metrics: HitTestMetrics { text_position },
-            })
-        } else {
-            // iterated to end boundary
-            Some(HitTestTextPosition {
-                point: Point {
-                    x: self.font.text_extents(&self.text).x_advance,
-                    y: 0.0,
-                },
-                metrics: HitTestMetrics {
-                    text_position: text_len,
-                },
-            })
-        }
+    fn decoration_metrics(&self) -> DecorationMetrics {
+        decoration_metrics(&self.font)
     }
 }
 
+/// The toy text API doesn't expose the font's tables, so this is an estimate
+/// from the size.
+fn decoration_metrics(font: &ScaledFont) -> DecorationMetrics {
+    DecorationMetrics::estimate(font.get_font_matrix().yy)
+}
+
 fn scale_matrix(scale: f64) -> Matrix {
     Matrix {
         xx: scale,
@@ -239,7 +1194,7 @@ fn scale_matrix(scale: f64) -> Matrix {
 #[cfg(test)]
 mod test {
     use super::*;
-    use piet::TextLayout;
+    use piet::{FontFeature, TextLayout};
 
     // - x: calculated value
     // - target: f64
@@ -806,4 +1761,795 @@ mod test {
         let pt = layout.hit_test_point(Point::new(27.0, 0.0));
         assert_eq!(pt.metrics.text_position, 6);
     }
+
+    #[test]
+    fn test_hit_test_point_nearer_edge() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let layout = text.new_text_layout(&font, "piet").build().unwrap();
+        let leading = layout.hit_test_text_position(2).unwrap().point.x;
+        let trailing = layout.hit_test_text_position(3).unwrap().point.x;
+        let quarter = (trailing - leading) / 4.0;
+
+        let hit = layout.hit_test_point(Point::new(leading + quarter, 0.0));
+        assert_eq!(hit.metrics.text_position, 2);
+        assert!(hit.is_inside);
+        let hit = layout.hit_test_point(Point::new(trailing - quarter, 0.0));
+        assert_eq!(hit.metrics.text_position, 3);
+        assert!(hit.is_inside);
+    }
+
+    #[test]
+    fn test_range_bounds() {
+        let mut text_layout = CairoText::new();
+        let font = text_layout
+            .new_font_by_name("sans-serif", 12.0)
+            .build()
+            .unwrap();
+        let layout = text_layout
+            .new_text_layout(&font, "piet text!")
+            .build()
+            .unwrap();
+
+        assert!(layout.range_bounds(3..3).is_none());
+
+        let piet = layout.range_bounds(0..4).unwrap();
+        let text = layout.range_bounds(4..10).unwrap();
+        assert_close_to(piet.x0, 0.0, 1e-9);
+        assert_close_to(piet.x1, text.x0, 1e-9);
+        assert_close_to(text.x1, layout.width(), 1e-9);
+        assert!(piet.y0 < 0.0 && piet.y1 > 0.0);
+        assert_eq!(piet.y0, text.y0);
+
+        // ranges past the end are clamped
+        let clamped = layout.range_bounds(4..100).unwrap();
+        assert_eq!(clamped.x1, text.x1);
+    }
+
+    #[test]
+    fn test_missing_family_warning() {
+        let mut text = CairoText::new();
+        let generic = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        assert!(generic.warnings().is_empty());
+
+        let missing = text
+            .new_font_by_name("No Such Family Piet Test", 12.0)
+            .build()
+            .unwrap();
+        assert_eq!(
+            missing.warnings(),
+            &[FontWarning::MissingFamily {
+                requested: "No Such Family Piet Test".to_owned(),
+                fallback: "sans-serif".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_generic_monospace() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("Mono", 12.0).build().unwrap();
+        assert!(font.warnings().is_empty());
+        let narrow = text.new_text_layout(&font, "iii").build().unwrap();
+        let wide = text.new_text_layout(&font, "MMM").build().unwrap();
+        assert_close_to(narrow.width(), wide.width(), 1e-9);
+    }
+
+    #[test]
+    fn test_font_substitution() {
+        let mut text = CairoText::new();
+        let mut subs = FontSubstitutions::new();
+        subs.insert("No Such Family Piet Test", "monospace");
+        text.set_font_substitutions(subs);
+        let font = text
+            .new_font_by_name("no such family piet test", 12.0)
+            .build()
+            .unwrap();
+        assert_eq!(
+            font.warnings(),
+            &[FontWarning::Substituted {
+                requested: "no such family piet test".to_owned(),
+                substitute: "monospace".to_owned(),
+            }]
+        );
+        let narrow = text.new_text_layout(&font, "iii").build().unwrap();
+        let wide = text.new_text_layout(&font, "MMM").build().unwrap();
+        assert_close_to(narrow.width(), wide.width(), 1e-9);
+    }
+
+    #[test]
+    fn test_prewarm() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 48.0).build().unwrap();
+        let before = text.new_text_layout(&font, "piet").build().unwrap();
+
+        // each glyph is drawn once, however often the text repeats it
+        let glyphs = prewarm_glyphs(&font, "piet text! piet");
+        let (shown, _) = font.font.text_to_glyphs(0.0, 0.0, "piet text!");
+        let mut indices: Vec<_> = shown.iter().map(|g| g.index).collect();
+        indices.sort_unstable();
+        indices.dedup();
+        assert_eq!(glyphs.iter().map(|g| g.index).collect::<Vec<_>>(), indices);
+        assert_eq!(glyphs.len(), "piet x!".chars().count());
+        assert!(prewarm_glyphs(&font, "").is_empty());
+
+        text.prewarm(&font, "piet text! \u{4e2d}\u{6587}");
+        text.prewarm(&font, "");
+        // prewarming doesn't change layout
+        let after = text.new_text_layout(&font, "piet").build().unwrap();
+        assert!(after.width() > 0.0);
+        assert_eq!(after.width(), before.width());
+    }
+
+    #[test]
+    fn test_obscure() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let layout = text
+            .new_text_layout(&font, "pa\u{df}word")
+            .obscure('\u{2022}')
+            .build()
+            .unwrap();
+        let plain = text
+            .new_text_layout(&font, &"\u{2022}".repeat(7))
+            .build()
+            .unwrap();
+        assert_close_to(layout.width(), plain.width(), 1e-9);
+
+        // positions are in the original text, past the two-byte 'ß'
+        let hit = layout.hit_test_text_position(4).unwrap();
+        assert_eq!(hit.metrics.text_position, 4);
+        let plain_hit = plain.hit_test_text_position(9).unwrap();
+        assert_close_to(hit.point.x, plain_hit.point.x, 1e-9);
+
+        let point = layout.hit_test_point(Point::new(hit.point.x + 0.1, 0.0));
+        assert_eq!(point.metrics.text_position, 4);
+        let end = layout.hit_test_point(Point::new(1000.0, 0.0));
+        assert_eq!(end.metrics.text_position, 8);
+    }
+
+    #[test]
+    fn test_decoration_metrics() {
+        let mut text = CairoText::new();
+        let small = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let large = text.new_font_by_name("sans-serif", 24.0).build().unwrap();
+        let metrics = small.decoration_metrics();
+        assert!(metrics.underline_offset > 0.0);
+        assert!(metrics.strikethrough_offset < 0.0);
+        assert_eq!(
+            large.decoration_metrics().underline_thickness,
+            metrics.underline_thickness * 2.0
+        );
+
+        let layout = text.new_text_layout(&small, "text").build().unwrap();
+        assert_eq!(layout.decoration_metrics(), metrics);
+    }
+
+    #[test]
+    fn test_max_width() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let input = "wrap these words\nbreak";
+        let one_line = text.new_text_layout(&font, "wrap these").build().unwrap();
+        let layout = text
+            .new_text_layout(&font, input)
+            .max_width(one_line.width() + 1.0)
+            .build()
+            .unwrap();
+        assert_eq!(layout.model.lines(), vec![0..10, 11..16, 17..22]);
+        assert_close_to(layout.width(), one_line.width(), 1e-9);
+
+        // "words" starts the second line, one line height down
+        let words = layout.hit_test_text_position(11).unwrap();
+        assert_eq!(words.point.x, 0.0);
+        assert_close_to(words.point.y, layout.line_metrics()[0].height, 1e-9);
+        let hit = layout.hit_test_point(Point::new(0.1, layout.line_metrics()[0].height));
+        assert_eq!(hit.metrics.text_position, 11);
+        // past the end of a line, the position before its hanging space
+        let hit = layout.hit_test_point(Point::new(1000.0, 0.0));
+        assert_eq!(hit.metrics.text_position, 10);
+        let hit = layout.hit_test_point(Point::new(1000.0, 1000.0));
+        assert_eq!(hit.metrics.text_position, input.len());
+        assert!(!hit.is_inside);
+
+        // a range over the wrap covers both lines
+        let bounds = layout.range_bounds(5..16).unwrap();
+        assert_eq!(bounds.x0, 0.0);
+        assert!(bounds.height() > layout.line_metrics()[0].height);
+        // no glyphs for the space at the wrap, or the line break
+        assert_eq!(layout.glyphs.len(), input.len() - 2);
+
+        let no_wrap = text
+            .new_text_layout(&font, "wrap these words")
+            .build()
+            .unwrap();
+        assert_eq!(no_wrap.model.lines(), vec![0..16]);
+    }
+
+    #[test]
+    fn test_alignment() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let input = "wrap these words\nbreak";
+        let width = 200.0;
+        let build = |text: &mut CairoText, alignment| {
+            text.new_text_layout(&font, input)
+                .max_width(width)
+                .alignment(alignment)
+                .build()
+                .unwrap()
+        };
+        let start = build(&mut text, TextAlignment::Start);
+        // the end of each line, unaligned
+        let ends: Vec<f64> = start
+            .model
+            .lines()
+            .iter()
+            .map(|line| start.hit_test_text_position(line.end).unwrap().point.x)
+            .collect();
+
+        let end = build(&mut text, TextAlignment::End);
+        let center = build(&mut text, TextAlignment::Center);
+        for (line, range) in start.model.lines().iter().enumerate() {
+            let x = |layout: &CairoTextLayout, position| {
+                layout.hit_test_text_position(position).unwrap().point.x
+            };
+            assert_close_to(x(&end, range.end), width, 1e-9);
+            assert_close_to(x(&center, range.start), (width - ends[line]) / 2.0, 1e-9);
+        }
+        // the glyphs move with the positions
+        let (_, glyph) = center.glyphs[0];
+        assert_close_to(glyph.x, (width - ends[0]) / 2.0, 1e-9);
+        assert!(center.split_runs().is_some());
+        assert_close_to(end.width(), width, 1e-9);
+
+        // justified, the paragraph's first line widens to both edges and
+        // its last stays put
+        let narrow = ends[0] * 0.75;
+        let justified = text
+            .new_text_layout(&font, input)
+            .max_width(narrow)
+            .alignment(TextAlignment::Justified)
+            .build()
+            .unwrap();
+        assert_eq!(justified.model.lines(), vec![0..10, 11..16, 17..22]);
+        let x = |position| justified.hit_test_text_position(position).unwrap().point.x;
+        assert_eq!(x(0), 0.0);
+        assert_close_to(x(10), narrow, 1e-9);
+        assert!(x(16) < narrow);
+        // "these" moves along by the space added to the one before it
+        let (_, these) = justified
+            .glyphs
+            .iter()
+            .find(|(cluster, _)| *cluster == 5)
+            .unwrap();
+        assert_close_to(these.x, x(5), 1e-9);
+        assert!(x(5) > advance(&justified.runs, &justified.text, 0..5));
+    }
+
+    #[test]
+    fn test_bidi() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        // "abc", then alef and bet, which read right to left
+        let input = "abc \u{5d0}\u{5d1}";
+        let layout = text.new_text_layout(&font, input).build().unwrap();
+        let x = |position| layout.hit_test_text_position(position).unwrap().point.x;
+        let width = layout.width();
+        let (alef, bet) = (4, 6);
+        // alef is at the right edge, bet to its left, after the space
+        assert_close_to(x(alef), width, 1e-9);
+        assert!(x(bet) < x(alef));
+        assert!(x(3) < x(bet));
+        assert_close_to(x(input.len()), width, 1e-9);
+        let (_, alef_glyph) = layout.glyphs[4];
+        let (_, bet_glyph) = layout.glyphs[5];
+        assert!(bet_glyph.x < alef_glyph.x);
+        assert!(layout.split_runs().is_some());
+
+        // hit testing reads each half of a cluster by its direction
+        let hit = |x| {
+            layout
+                .hit_test_point(Point::new(x, 0.0))
+                .metrics
+                .text_position
+        };
+        assert_eq!(hit(x(alef) - 0.5), alef);
+        assert_eq!(hit(x(bet) + 0.5), alef + 2);
+        assert_eq!(hit(x(bet) - 0.5), bet);
+        assert_eq!(hit(width + 10.0), alef);
+
+        // "abc" and alef aren't next to each other on the line
+        assert_eq!(layout.rects_for_range(0..alef + 2).len(), 2);
+        assert_eq!(layout.rects_for_range(alef..input.len()).len(), 1);
+
+        // a paragraph starting with alef reads from the right
+        let rtl = text.new_text_layout(&font, "\u{5d0} abc").build().unwrap();
+        let x = |position| rtl.hit_test_text_position(position).unwrap().point.x;
+        assert_close_to(x(0), rtl.width(), 1e-9);
+        assert!(x(3) < x(2));
+    }
+
+    #[test]
+    fn test_bidi_visual_runs() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        // alef, bet and gimel between two latin words
+        let input = "abc \u{5d0}\u{5d1}\u{5d2} def";
+        let layout = text.new_text_layout(&font, input).build().unwrap();
+        assert_eq!(
+            layout.model.visual_runs(0),
+            [(0..4, false), (4..10, true), (10..14, false)]
+        );
+
+        // the glyphs are drawn in visual order: gimel, bet, alef, then "def"
+        let cluster_x = |cluster| {
+            let (_, glyph) = layout.glyphs.iter().find(|(c, _)| *c == cluster).unwrap();
+            glyph.x
+        };
+        let (alef, bet, gimel, d) = (4, 5, 6, 8);
+        assert!(cluster_x(3) < cluster_x(gimel));
+        assert!(cluster_x(gimel) < cluster_x(bet));
+        assert!(cluster_x(bet) < cluster_x(alef));
+        assert!(cluster_x(alef) < cluster_x(d));
+
+        // hit testing follows the runs: the left half of a right-to-left
+        // cluster is after it
+        let x = |position| layout.hit_test_text_position(position).unwrap().point.x;
+        let hit = |x| {
+            layout
+                .hit_test_point(Point::new(x, 0.0))
+                .metrics
+                .text_position
+        };
+        assert!(x(8) < x(6) && x(6) < x(4));
+        assert_eq!(hit(x(4) - 0.5), 4);
+        assert_eq!(hit(x(6) + 0.5), 6);
+        assert_eq!(hit(x(8) - 0.5), 8);
+        assert_close_to(x(10), x(4), 1e-9);
+        assert_eq!(hit(x(4) + 0.5), 10);
+        assert_eq!(hit(x(11) + 0.5), 11);
+        assert_eq!(hit(x(0) + 0.5), 0);
+    }
+
+    #[test]
+    fn test_truncation() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let input = "wrap these words\nbreak";
+        let width = text
+            .new_text_layout(&font, "wrap these")
+            .build()
+            .unwrap()
+            .width()
+            + 1.0;
+        let build = |text: &mut CairoText, lines, truncation| {
+            text.new_text_layout(&font, input)
+                .max_width(width)
+                .max_lines(lines, truncation)
+                .build()
+                .unwrap()
+        };
+
+        let clipped = build(&mut text, 2, TextTruncation::Clip);
+        assert_eq!(clipped.text, "wrap these words");
+        assert_eq!(clipped.model.lines(), vec![0..10, 11..16]);
+        assert_eq!(clipped.truncated_at(), Some(16));
+        let hit = clipped.hit_test_point(Point::new(1000.0, 1000.0));
+        assert_eq!(hit.metrics.text_position, 16);
+
+        // "wrap these" fills the line, leaving no room for the ellipsis
+        let words = build(&mut text, 1, TextTruncation::WordEllipsis);
+        assert_eq!(words.text, "wrap\u{2026}");
+        assert_eq!(words.model.lines(), vec![0..words.text.len()]);
+        assert_eq!(words.truncated_at(), Some(4));
+        let characters = build(&mut text, 1, TextTruncation::CharacterEllipsis);
+        let cut = characters.truncated_at().unwrap();
+        assert!(cut > 4 && cut < 10);
+        assert!(characters.text.ends_with(TruncatedText::ELLIPSIS));
+        assert!(characters.width() <= width);
+
+        // the ellipsis stands for the rest of the text
+        let end = words.hit_test_text_position(words.text.len()).unwrap();
+        let hit = words.hit_test_point(Point::new(end.point.x - 0.1, 0.0));
+        assert_eq!(hit.metrics.text_position, input.len());
+        let hidden = words.hit_test_text_position(12).unwrap();
+        assert_eq!(hidden.point.x, end.point.x);
+        assert_eq!(hidden.metrics.text_position, 12);
+        assert_eq!(words.rects_for_range(2..16)[0].x1, end.point.x);
+
+        assert!(!build(&mut text, 3, TextTruncation::WordEllipsis).is_truncated());
+        // one mask character to a line, cut in the original's positions
+        let obscured = text
+            .new_text_layout(&font, "\u{e9}\u{e9}\u{e9}")
+            .obscure('*')
+            .max_width(0.0)
+            .max_lines(2, TextTruncation::Clip)
+            .build()
+            .unwrap();
+        assert_eq!(obscured.text, "**");
+        assert_eq!(obscured.truncated_at(), Some(4));
+    }
+
+    #[test]
+    fn test_caret_bounds() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let layout = text.new_text_layout(&font, "ab\ncd").build().unwrap();
+
+        let caret = layout.caret_bounds(1).unwrap();
+        let hit = layout.hit_test_text_position(1).unwrap();
+        assert_eq!(caret.width(), 0.0);
+        assert_eq!(caret.x0, hit.point.x);
+        let bounds = layout.range_bounds(1..2).unwrap();
+        assert_eq!((caret.y0, caret.y1), (bounds.y0, bounds.y1));
+
+        // on the second line, and clamped past the end
+        let end = layout.caret_bounds(100).unwrap();
+        assert_close_to(end.y0, caret.y0 + layout.line_metrics()[0].height, 1e-9);
+        assert_eq!(end.x0, layout.hit_test_text_position(5).unwrap().point.x);
+        // inside a cluster, at its start
+        let layout = text.new_text_layout(&font, "e\u{301}").build().unwrap();
+        assert_eq!(layout.caret_bounds(1).unwrap().x0, 0.0);
+    }
+
+    #[test]
+    fn test_rects_for_range() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let layout = text.new_text_layout(&font, "ab\ncd").build().unwrap();
+
+        let rects = layout.rects_for_range(1..4);
+        assert_eq!(rects.len(), 2);
+        assert_eq!(
+            rects[0].x0,
+            layout.hit_test_text_position(1).unwrap().point.x
+        );
+        assert_eq!(rects[1].x0, 0.0);
+        assert_close_to(
+            rects[1].y0,
+            rects[0].y0 + layout.line_metrics()[0].height,
+            1e-9,
+        );
+        let bounds = layout.range_bounds(1..4).unwrap();
+        let union = rects[0].union(rects[1]);
+        assert_eq!(
+            (union.x0, union.y0, union.x1, union.y1),
+            (bounds.x0, bounds.y0, bounds.x1, bounds.y1)
+        );
+
+        // starting at the break only covers the line after it
+        assert_eq!(layout.rects_for_range(2..4).len(), 1);
+        assert!(layout.rects_for_range(2..2).is_empty());
+        assert_eq!(layout.rects_for_range(3..100).len(), 1);
+    }
+
+    #[test]
+    fn test_lasso_recorded_text() {
+        use piet::kurbo::Rect;
+        use piet::{RecordingContext, RenderContext};
+
+        let mut rc = RecordingContext::new(CairoText::new());
+        let font = rc
+            .text()
+            .new_font_by_name("sans-serif", 12.0)
+            .build()
+            .unwrap();
+        let layout = rc.text().new_text_layout(&font, "lasso").build().unwrap();
+        rc.draw_text(&layout, (10.0, 20.0), &Color::BLACK);
+        let list = rc.into_display_list();
+
+        // just above the baseline, in the middle of the text
+        let middle = 10.0 + layout.width() / 2.0;
+        let lasso = Rect::new(middle, 17.0, middle + 1.0, 18.0);
+        assert_eq!(list.hit_test_lasso(lasso), [0]);
+        let past_end = Rect::new(15.0 + layout.width(), 0.0, 100.0, 40.0);
+        assert!(list.hit_test_lasso(past_end).is_empty());
+    }
+
+    #[test]
+    fn test_line_metrics() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let layout = text.new_text_layout(&font, "one\ntwo").build().unwrap();
+        let lines = layout.line_metrics();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            (lines[0].range.clone(), lines[1].range.clone()),
+            (0..3, 4..7)
+        );
+        assert_eq!(lines[0].baseline, 0.0);
+        assert_close_to(lines[1].baseline, lines[0].height, 1e-9);
+        // the same extents as the bounds of the text
+        let bounds = layout.range_bounds(0..3).unwrap();
+        assert_close_to(lines[0].ascent, -bounds.y0, 1e-9);
+        assert_close_to(lines[0].descent, bounds.y1, 1e-9);
+
+        let empty = text.new_text_layout(&font, "").build().unwrap();
+        assert_eq!(empty.line_metrics().len(), 1);
+        let obscured = text
+            .new_text_layout(&font, "\u{e9}\u{e9}")
+            .obscure('*')
+            .build()
+            .unwrap();
+        assert_eq!(obscured.line_metrics()[0].range, 0..4);
+    }
+
+    #[test]
+    fn test_styled_runs() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let input = "small\nBIG small";
+        let plain = text.new_text_layout(&font, input).build().unwrap();
+        let styled = text
+            .new_text_layout(&font, input)
+            .range_attribute(6..9, TextAttribute::Size(24.0))
+            .range_attribute(6..9, TextAttribute::Weight(piet::FontWeight::BOLD))
+            .range_attribute(10..15, TextAttribute::Underline(true))
+            .build()
+            .unwrap();
+        assert_eq!(styled.runs.len(), 4);
+
+        // the first line keeps its metrics, the second grows to fit
+        let (plain_lines, lines) = (plain.line_metrics(), styled.line_metrics());
+        assert_close_to(lines[0].ascent, plain_lines[0].ascent, 1e-9);
+        assert!(lines[1].ascent > plain_lines[1].ascent);
+        assert!(lines[1].baseline > plain_lines[1].baseline);
+        assert_close_to(lines[1].baseline - lines[0].baseline, lines[0].height, 1e-9);
+        assert!(styled.width() > plain.width());
+
+        // measuring and hit testing follow the larger glyphs
+        let big = styled.range_bounds(6..9).unwrap();
+        let small = styled.range_bounds(10..15).unwrap();
+        assert!(big.width() > plain.range_bounds(6..9).unwrap().width());
+        assert_close_to(
+            big.x1,
+            styled.hit_test_text_position(9).unwrap().point.x,
+            1e-9,
+        );
+        let hit = styled.hit_test_point(Point::new(big.x1 - 1.0, lines[1].baseline));
+        assert_eq!(hit.metrics.text_position, 9);
+        assert!(hit.is_inside);
+        assert!(small.x0 > big.x1);
+
+        // each run's glyphs are drawn with its own font
+        let fonts = styled.cluster_fonts();
+        assert!(!std::ptr::eq(fonts[6], fonts[10]));
+
+        // an underline under the last word, just below the baseline
+        let underlines = styled.model.decorations();
+        assert_eq!(underlines.len(), 1);
+        let (rect, color) = underlines[0];
+        assert!(color.is_none());
+        assert_close_to(rect.x0, small.x0, 1e-9);
+        assert_close_to(rect.x1, small.x1, 1e-9);
+        assert!(rect.y0 > lines[1].baseline && rect.y1 < lines[1].baseline + lines[1].descent);
+        assert!(plain.model.decorations().is_empty());
+    }
+
+    #[test]
+    fn test_strikethrough() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let layout = text
+            .new_text_layout(&font, "was now")
+            .range_attribute(0..3, TextAttribute::Strikethrough(true))
+            .range_attribute(0..3, TextAttribute::Underline(true))
+            .range_attribute(4..7, TextAttribute::Strikethrough(true))
+            .range_attribute(4..7, TextAttribute::Foreground(Color::rgb8(0xff, 0, 0)))
+            .build()
+            .unwrap();
+        let line = &layout.line_metrics()[0];
+        let decorations = layout.model.decorations();
+        assert_eq!(decorations.len(), 3);
+
+        // the first word has both, the line through it above the baseline
+        let (underline, strikethrough) = (decorations[0].0, decorations[1].0);
+        assert!(underline.y0 > line.baseline);
+        assert!(strikethrough.y1 < line.baseline);
+        assert!(strikethrough.y0 > line.baseline - line.ascent);
+        assert_close_to(strikethrough.x1, underline.x1, 1e-9);
+
+        // the second is struck through in its own color
+        let (rect, color) = decorations[2];
+        assert_close_to(rect.y0, strikethrough.y0, 1e-9);
+        assert_eq!(color.map(Color::as_rgba_u32), Some(0xff0000ff));
+    }
+
+    #[test]
+    fn test_letter_and_word_spacing() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let input = "ab cd";
+        let plain = text.new_text_layout(&font, input).build().unwrap();
+        let spaced = text
+            .new_text_layout(&font, input)
+            .range_attribute(0..5, TextAttribute::LetterSpacing(2.0))
+            .range_attribute(0..5, TextAttribute::WordSpacing(3.0))
+            .range_attribute(3..5, TextAttribute::LetterSpacing(f64::NAN))
+            .build()
+            .unwrap();
+        // five clusters spaced by 2, and the space by 3 more
+        assert_close_to(spaced.width(), plain.width() + 5.0 * 2.0 + 3.0, 1e-9);
+        let x = |layout: &CairoTextLayout, i| layout.hit_test_text_position(i).unwrap().point.x;
+        assert_close_to(x(&spaced, 1), x(&plain, 1) + 2.0, 1e-9);
+        assert_close_to(x(&spaced, 3), x(&plain, 3) + 3.0 * 2.0 + 3.0, 1e-9);
+
+        // the glyphs move with their clusters
+        let glyph_x = |layout: &CairoTextLayout, cluster| {
+            let (_, glyph) = layout.glyphs.iter().find(|(c, _)| *c == cluster).unwrap();
+            glyph.x
+        };
+        assert_close_to(
+            glyph_x(&spaced, 4),
+            glyph_x(&plain, 4) + 4.0 * 2.0 + 3.0,
+            1e-9,
+        );
+        let hit = spaced.hit_test_point(Point::new(x(&spaced, 4) + 1.0, 0.0));
+        assert_eq!(hit.metrics.text_position, 4);
+    }
+
+    #[test]
+    fn test_tabular_numbers() {
+        let mut text = CairoText::new();
+        let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+        let plain = text.new_text_layout(&font, "ab").build().unwrap();
+        let mut tabular = |input: &str| {
+            text.new_text_layout(&font, input)
+                .range_attribute(
+                    0..input.len(),
+                    TextAttribute::FontFeature(FontFeature::TABULAR_NUMBERS),
+                )
+                .build()
+                .unwrap()
+        };
+        // every digit takes the same advance, so rows of numbers line up
+        let ones = tabular("1111");
+        let eights = tabular("8888");
+        assert_close_to(ones.width(), eights.width(), 1e-9);
+        let x = |layout: &CairoTextLayout, i| layout.hit_test_text_position(i).unwrap().point.x;
+        assert_close_to(x(&ones, 2), x(&eights, 2), 1e-9);
+
+        // other text is left alone
+        assert_close_to(tabular("ab").width(), plain.width(), 1e-9);
+    }
+
+    #[test]
+    fn test_export_recorded_text() {
+        use piet::kurbo::{Rect, Size};
+        use piet::{ImageFormat, InterpolationMode, RecordingContext, RenderContext};
+
+        let mut rc = RecordingContext::new(CairoText::new());
+        let font = rc
+            .text()
+            .new_font_by_name("sans-serif", 12.0)
+            .build()
+            .unwrap();
+        let layout = rc
+            .text()
+            .new_text_layout(&font, "a < b\nc")
+            .build()
+            .unwrap();
+        let secret = rc
+            .text()
+            .new_text_layout(&font, "pin")
+            .obscure('*')
+            .build()
+            .unwrap();
+        rc.draw_text(&layout, (10.0, 20.0), &Color::BLACK);
+        rc.with_blur(2.0, |rc| {
+            rc.clip_text(&secret, (0.0, 60.0));
+            let image = rc
+                .make_image(1, 1, &[0xff, 0, 0, 0xff], ImageFormat::RgbaSeparate)
+                .unwrap();
+            rc.draw_image(
+                &image,
+                Rect::new(0.0, 40.0, 50.0, 70.0),
+                InterpolationMode::Bilinear,
+            );
+            Ok(())
+        })
+        .unwrap();
+        let list = rc.into_display_list();
+
+        let svg = list.to_svg(Size::new(100.0, 100.0));
+        // a text element for each line, escaped
+        assert!(svg.contains(">a &lt; b</text>"));
+        assert!(svg.contains(">c</text>"));
+        assert!(svg.contains("font-family=\"sans-serif\" font-size=\"12\""));
+        // the clip shows the mask, not the text
+        assert!(svg.contains(">***</text>"));
+        assert!(!svg.contains("pin"));
+        assert!(svg.contains("<feGaussianBlur"));
+        // the PNG signature, in base64
+        assert!(svg.contains("data:image/png;base64,iVBORw0KGgo"));
+        assert_eq!(svg.matches("<g").count(), svg.matches("</g>").count());
+
+        let html = list.to_canvas_html(Size::new(100.0, 100.0));
+        assert!(html.contains("ctx.fillText(\"a \\u003c b\", 10, 20);"));
+        assert!(html.contains("ctx.font = \"normal 400 normal 12px \\\"sans-serif\\\"\";"));
+        assert!(html.contains("ctx.filter = \"blur(2px)\";"));
+        // the pixel, as straight RGBA
+        assert!(html.contains("image(1, 1, \"/wAA/w==\")"));
+    }
+
+    #[test]
+    fn test_font_builder_style() {
+        use piet::{FontStyle, FontWeight};
+
+        let mut text = CairoText::new();
+        let width = |text: &mut CairoText, weight, style| {
+            let font = text
+                .new_font_by_name("sans-serif", 12.0)
+                .weight(weight)
+                .style(style)
+                .build()
+                .unwrap();
+            let layout = text.new_text_layout(&font, "Weighty").build().unwrap();
+            layout.width()
+        };
+        let normal = width(&mut text, FontWeight::NORMAL, FontStyle::Normal);
+        let bold = width(&mut text, FontWeight::BOLD, FontStyle::Normal);
+        assert!(bold > normal);
+        // the toy API only has two weights
+        assert_eq!(
+            width(&mut text, FontWeight::SEMI_BOLD, FontStyle::Normal),
+            bold
+        );
+        assert_eq!(
+            width(&mut text, FontWeight::MEDIUM, FontStyle::Normal),
+            normal
+        );
+
+        let italic = text
+            .new_font_by_name("sans-serif", 12.0)
+            .style(FontStyle::Italic)
+            .build()
+            .unwrap();
+        let layout = text.new_text_layout(&italic, "Weighty").build().unwrap();
+        assert_eq!(layout.face.slant, FontSlant::Italic);
+    }
+
+    #[test]
+    fn test_font_fallback() {
+        // only the math face has the script capital A
+        let text_with = |fallback: Option<&str>| {
+            let mut text = CairoText::new();
+            let mut builder = text.new_font_by_name("DejaVu Sans", 12.0);
+            if let Some(family) = fallback {
+                builder = builder.fallback("Missing Family").fallback(family);
+            }
+            let font = builder.build().unwrap();
+            text.new_text_layout(&font, "A\u{1d49c}\nB")
+                .build()
+                .unwrap()
+        };
+        let tofu = text_with(None);
+        assert!(tofu.glyphs.iter().any(|(_, glyph)| glyph.index == 0));
+
+        let layout = text_with(Some("DejaVu Math TeX Gyre"));
+        assert!(layout.glyphs.iter().all(|(_, glyph)| glyph.index != 0));
+        let runs: Vec<_> = layout
+            .runs
+            .iter()
+            .map(|run| (run.range.clone(), run.fallback))
+            .collect();
+        // the unknown family resolves to the default face, which lacks it too
+        assert_eq!(runs, [(0..1, None), (1..5, Some(1)), (5..7, None)]);
+        // drawn run by run, with the fallback's font
+        let (_, glyph_runs) = layout.split_runs().unwrap();
+        assert_eq!(glyph_runs.len(), 3);
+    }
+
+    #[test]
+    fn test_load_font() {
+        let mut text = CairoText::new();
+        let err = text.load_font(b"not a font").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid input");
+
+        text.set_system_fonts(false);
+        assert!(text.new_font_by_name("sans-serif", 12.0).build().is_err());
+        text.set_system_fonts(true);
+        assert!(text.new_font_by_name("sans-serif", 12.0).build().is_ok());
+    }
 }
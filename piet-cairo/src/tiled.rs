@@ -0,0 +1,182 @@
+//! Drawing an image surface in tiles, in parallel.
+
+use cairo::{Context, Format, ImageSurface};
+use rayon::prelude::*;
+
+use piet::kurbo::Affine;
+use piet::{new_error, Error, ErrorKind, RenderContext};
+
+use crate::{CairoRenderContext, WrapError};
+
+/// How [`render_tiled`] splits a surface into tiles.
+///
+/// [`render_tiled`]: fn.render_tiled.html
+#[derive(Debug, Clone)]
+pub struct TileOptions {
+    size: usize,
+    margin: usize,
+}
+
+/// A rectangle of pixels.
+#[derive(Debug, Clone, Copy)]
+struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl TileOptions {
+    /// Tiles of 256 by 256 pixels, with no margin.
+    pub fn new() -> TileOptions {
+        TileOptions {
+            size: 256,
+            margin: 0,
+        }
+    }
+
+    /// Tiles of `size` by `size` pixels, or less at the right and bottom
+    /// edges of the surface.
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size.max(1);
+        self
+    }
+
+    /// Draw `margin` more pixels on each side of each tile, and drop them.
+    ///
+    /// A tile only has its own pixels to draw into, so effects that spread
+    /// what's drawn, such as blurs, leave out what's drawn past its edges.
+    /// A margin as wide as they spread brings that back, at the cost of
+    /// drawing those pixels for each tile they're near.
+    pub fn margin(mut self, margin: usize) -> Self {
+        self.margin = margin;
+        self
+    }
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        TileOptions::new()
+    }
+}
+
+/// Draw onto `surface` in tiles, calling `draw` for each one on as many
+/// threads as rayon has.
+///
+/// Each call gets a render context of its own, for an image surface the
+/// size of its tile, with a transform that has `draw` draw in the
+/// coordinates of the whole of `surface`; what falls outside the tile is
+/// cut off. The calls happen at once on several threads, so `draw` has to
+/// draw the same thing each time, and make the text layouts and images it
+/// draws with the context it's given.
+///
+/// The tiles are then copied into `surface`, replacing what it had. Each
+/// pixel comes out as drawing to `surface` directly would make it, give or
+/// take cairo's rounding of antialiased edges, except near tile edges for
+/// effects that spread; see [`TileOptions::margin`].
+///
+/// Returns an error if `surface` isn't `ARgb32` or `Rgb24`, or if `draw`
+/// returns one. Errors can't move between threads, so those from `draw`
+/// come back as backend errors with the same message.
+///
+/// [`TileOptions::margin`]: struct.TileOptions.html#method.margin
+pub fn render_tiled<F>(
+    surface: &mut ImageSurface,
+    options: &TileOptions,
+    draw: F,
+) -> Result<(), Error>
+where
+    F: Fn(&mut CairoRenderContext) -> Result<(), Error> + Sync,
+{
+    let format = surface.get_format();
+    if !matches!(format, Format::ARgb32 | Format::Rgb24) {
+        return Err(new_error(ErrorKind::InvalidInput));
+    }
+    let bounds = Tile {
+        x: 0,
+        y: 0,
+        width: surface.get_width() as usize,
+        height: surface.get_height() as usize,
+    };
+    let tiles = split(bounds, options.size);
+    let rendered = tiles
+        .par_iter()
+        .map(|&tile| {
+            let area = tile.inflate(options.margin, bounds);
+            render_tile(format, tile, area, &draw).map_err(|e| e.to_string())
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|message| {
+            let e: Box<dyn std::error::Error> = message.into();
+            Error::from(e)
+        })?;
+
+    let stride = surface.get_stride() as usize;
+    let mut data = surface.get_data().wrap()?;
+    for (tile, pixels) in tiles.iter().zip(&rendered) {
+        let row_len = tile.width * 4;
+        for (row, src) in pixels.chunks_exact(row_len).enumerate() {
+            let start = (tile.y + row) * stride + tile.x * 4;
+            data[start..start + row_len].copy_from_slice(src);
+        }
+    }
+    Ok(())
+}
+
+impl Tile {
+    /// The tile grown by `margin` on each side, within `bounds`.
+    fn inflate(self, margin: usize, bounds: Tile) -> Tile {
+        let x = self.x.saturating_sub(margin);
+        let y = self.y.saturating_sub(margin);
+        let x1 = (self.x + self.width + margin).min(bounds.width);
+        let y1 = (self.y + self.height + margin).min(bounds.height);
+        Tile {
+            x,
+            y,
+            width: x1 - x,
+            height: y1 - y,
+        }
+    }
+}
+
+/// `bounds` in tiles of `size` by `size` pixels, row by row.
+fn split(bounds: Tile, size: usize) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y in (0..bounds.height).step_by(size) {
+        for x in (0..bounds.width).step_by(size) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: size.min(bounds.width - x),
+                height: size.min(bounds.height - y),
+            });
+        }
+    }
+    tiles
+}
+
+/// Draw the `area` of the whole surface that `tile` is in, and return the
+/// pixels of `tile`, row by row with no padding.
+fn render_tile<F>(format: Format, tile: Tile, area: Tile, draw: &F) -> Result<Vec<u8>, Error>
+where
+    F: Fn(&mut CairoRenderContext) -> Result<(), Error>,
+{
+    let mut surface = ImageSurface::create(format, area.width as i32, area.height as i32).wrap()?;
+    {
+        let mut ctx = Context::new(&surface);
+        let mut rc = CairoRenderContext::new(&mut ctx);
+        rc.transform(Affine::translate((-(area.x as f64), -(area.y as f64))));
+        draw(&mut rc)?;
+        rc.finish()?;
+    }
+    let stride = surface.get_stride() as usize;
+    let data = surface.get_data().wrap()?;
+    let (dx, dy) = (tile.x - area.x, tile.y - area.y);
+    let row_len = tile.width * 4;
+    let mut pixels = Vec::with_capacity(row_len * tile.height);
+    for row in dy..dy + tile.height {
+        let start = row * stride + dx * 4;
+        pixels.extend_from_slice(&data[start..start + row_len]);
+    }
+    Ok(pixels)
+}
@@ -0,0 +1,139 @@
+//! Check that fills and strokes of simple rectangles, and drawing glyphs,
+//! don't allocate once the context's buffers have grown.
+//!
+//! The allocator counts for the whole process, so these checks are kept in
+//! a file of their own. Each test counts only its own thread's allocations,
+//! so they can run side by side. Only allocations made from Rust are
+//! counted, not cairo's own.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::hint::black_box;
+
+use cairo::{Context, Format, ImageSurface};
+
+use piet::kurbo::{Point, Rect};
+use piet::{
+    Color, DynShape, FixedLinearGradient, FontBuilder, GradientColorSpace, GradientStops,
+    RenderContext, StrokeStyle, Text,
+};
+use piet_cairo::CairoRenderContext;
+
+/// The system allocator, counting each allocation made on each thread.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count() {
+    // the count may already be gone while the thread exits
+    let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const ITERATIONS: u32 = 1000;
+
+/// Draw with `f` on a fresh context, once to warm up and then again, and
+/// return the number of allocations it made after warming up.
+fn allocations(mut f: impl FnMut(&mut CairoRenderContext, u32)) -> usize {
+    let surface = ImageSurface::create(Format::ARgb32, 128, 64).unwrap();
+    let mut ctx = Context::new(&surface);
+    let mut rc = CairoRenderContext::new(&mut ctx);
+    f(&mut rc, 0);
+    let before = ALLOCATIONS.with(Cell::get);
+    for i in 0..ITERATIONS {
+        f(&mut rc, i);
+    }
+    let n = ALLOCATIONS.with(Cell::get) - before;
+    rc.finish().unwrap();
+    n
+}
+
+fn rect(i: u32) -> Rect {
+    let x = (i % 100) as f64;
+    Rect::new(x, 10.0, x + 20.0, 30.0)
+}
+
+#[test]
+fn fill_rect() {
+    let n = allocations(|rc, i| rc.fill(black_box(rect(i)), &Color::BLACK));
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn fill_dyn_rect() {
+    let n = allocations(|rc, i| {
+        let shape = rect(i);
+        let shape: &dyn DynShape = &shape;
+        rc.fill(shape, &Color::BLACK);
+    });
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn fill_rect_gradient() {
+    let mut gradient = None;
+    let n = allocations(|rc, i| {
+        let gradient = gradient.get_or_insert_with(|| {
+            rc.gradient(FixedLinearGradient {
+                start: Point::new(0.0, 0.0),
+                end: Point::new(128.0, 0.0),
+                stops: (Color::rgb8(0xff, 0, 0), Color::rgb8(0, 0, 0xff)).to_vec(),
+                color_space: GradientColorSpace::Srgb,
+            })
+            .unwrap()
+        });
+        rc.fill(black_box(rect(i)), gradient);
+    });
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn stroke_rect() {
+    let n = allocations(|rc, i| rc.stroke(black_box(rect(i)), &Color::BLACK, 2.0));
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn stroke_rect_dashed() {
+    let dashed = StrokeStyle::dashed(&[4.0, 2.0, 1.0], 0.0);
+    let n = allocations(|rc, i| rc.stroke_styled(black_box(rect(i)), &Color::BLACK, 2.0, &dashed));
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn draw_glyphs() {
+    let glyphs: Vec<_> = (0..16)
+        .map(|i| (1, Point::new(i as f64 * 8.0, 40.0)))
+        .collect();
+    let mut font = None;
+    let n = allocations(|rc, _| {
+        let font = font.get_or_insert_with(|| {
+            rc.text()
+                .new_font_by_name("monospace", 12.0)
+                .build()
+                .unwrap()
+        });
+        rc.draw_glyphs(font, black_box(&glyphs), &Color::BLACK);
+    });
+    assert_eq!(n, 0);
+}
@@ -13,7 +13,7 @@ use std::io::BufWriter;
 use std::marker::PhantomData;
 use std::path::Path;
 
-use piet::{ErrorKind, ImageFormat};
+use piet::{ErrorKind, ImageFormat, MemoryReport};
 #[doc(hidden)]
 pub use piet_cairo::*;
 
@@ -53,7 +53,12 @@ pub type PietTextLayoutBuilder<'a> = CairoTextLayoutBuilder;
 /// The associated image type for this backend.
 ///
 /// This type matches `RenderContext::Image`
-pub type Image = ImageSurface;
+pub type PietImage = CairoImage;
+
+/// The associated path type for this backend.
+///
+/// This type matches `RenderContext::Path`
+pub type PietPath = CairoPath;
 
 /// A struct that can be used to create bitmap render contexts.
 ///
@@ -73,6 +78,18 @@ impl Device {
         Ok(Device)
     }
 
+    /// The approximate memory held by the images, glyph caches and layers
+    /// made on this thread, which cairo render contexts share whichever
+    /// device they came from.
+    pub fn memory_report(&self) -> MemoryReport {
+        piet_cairo::memory_report()
+    }
+
+    /// Free the layers and glyph caches kept for reuse on this thread.
+    pub fn trim(&mut self) {
+        piet_cairo::trim_caches();
+    }
+
     /// Create a new bitmap target.
     pub fn bitmap_target(
         &mut self,
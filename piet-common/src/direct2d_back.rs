@@ -10,8 +10,8 @@ use std::path::Path;
 #[cfg(feature = "png")]
 use png::{ColorType, Encoder};
 
-use piet::{ErrorKind, ImageFormat};
-use piet_direct2d::d2d::{Bitmap, Brush as D2DBrush};
+use piet::{ErrorKind, ImageFormat, MemoryReport};
+use piet_direct2d::d2d::Brush as D2DBrush;
 use piet_direct2d::d3d::{
     D3D11Device, D3D11DeviceContext, D3D11Texture2D, TextureMode, DXGI_MAP_READ,
 };
@@ -54,7 +54,12 @@ pub type PietTextLayoutBuilder<'a> = D2DTextLayoutBuilder<'a>;
 /// The associated image type for this backend.
 ///
 /// This type matches `RenderContext::Image`
-pub type Image = Bitmap;
+pub type PietImage = D2DImage;
+
+/// The associated path type for this backend.
+///
+/// This type matches `RenderContext::Path`
+pub type PietPath = D2DPath;
 
 /// A struct that can be used to create bitmap render contexts.
 pub struct Device {
@@ -105,6 +110,21 @@ impl Device {
         })
     }
 
+    /// The approximate memory held by the images and layers of this device's
+    /// bitmap targets.
+    pub fn memory_report(&self) -> MemoryReport {
+        self.device.memory_report()
+    }
+
+    /// Free the resources Direct2D keeps for this device, such as rasterized
+    /// glyphs. Layers belong to each target's render context, and are
+    /// trimmed with [`trim_caches`] on it.
+    ///
+    /// [`trim_caches`]: trait.RenderContext.html#tymethod.trim_caches
+    pub fn trim(&mut self) {
+        self.device.clear_resources();
+    }
+
     /// Create a new bitmap target.
     pub fn bitmap_target(
         &mut self,
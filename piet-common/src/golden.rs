@@ -0,0 +1,479 @@
+//! Comparing drawn scenes with reference images.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+
+use piet::{ErrorKind, ImageFormat, RenderContext};
+
+use crate::{Device, ImageDiff, Piet};
+
+/// The environment variable that makes scene tests write their reference
+/// images instead of checking against them, when set to anything but `0`.
+pub const BLESS_VAR: &str = "PIET_BLESS";
+
+/// Snapshot tests for drawing code: each scene is drawn into a bitmap and
+/// compared with a reference PNG in a directory.
+///
+/// Running the tests with [`PIET_BLESS`] set to `1` writes the references
+/// from what's drawn, for new scenes and after intended changes. Otherwise,
+/// a scene that doesn't match fails, and `<name>.actual.png` and
+/// `<name>.diff.png` are written next to its reference: what was drawn, and
+/// the pixels that differ in red over a faded copy of it. Those are worth
+/// adding to `.gitignore`; blessing or matching removes them.
+///
+/// Images are compared as straight RGBA, so a scene's reference is the
+/// same however the backend stores pixels. Fonts and antialiasing vary
+/// between platforms and library versions, so references are best made on
+/// the machine that checks them, or compared with some [`tolerance`].
+///
+/// Usually used through [`assert_scene_matches!`], which keeps references
+/// in the calling crate's `tests/golden` directory.
+///
+/// # Examples
+///
+/// ```no_run
+/// use piet_common::kurbo::Circle;
+/// use piet_common::{Color, RenderContext, SceneTest};
+///
+/// SceneTest::new("tests/golden")
+///     .size(64, 64)
+///     .tolerance(2)
+///     .assert_matches("red_dot", |rc| {
+///         rc.clear(Color::WHITE);
+///         rc.fill(Circle::new((32.0, 32.0), 16.0), &Color::rgb8(0xff, 0, 0));
+///     });
+/// ```
+///
+/// [`PIET_BLESS`]: constant.BLESS_VAR.html
+/// [`tolerance`]: #method.tolerance
+/// [`assert_scene_matches!`]: macro.assert_scene_matches.html
+#[derive(Clone, Debug)]
+pub struct SceneTest {
+    dir: PathBuf,
+    width: usize,
+    height: usize,
+    pix_scale: f64,
+    tolerance: u8,
+    max_differing: usize,
+    min_ssim: f64,
+    bless: bool,
+}
+
+/// Why a scene didn't match its reference image.
+#[derive(Debug)]
+pub enum SceneMismatch {
+    /// There is no reference image for the scene.
+    MissingReference { reference: PathBuf },
+    /// The reference image is a different size from the scene.
+    Size {
+        reference: PathBuf,
+        /// The size of the reference, in pixels.
+        expected: (usize, usize),
+        /// The size of the scene, in pixels.
+        found: (usize, usize),
+        actual: PathBuf,
+    },
+    /// Too many pixels differ by more than the tolerance, or the scene
+    /// doesn't look enough like the reference.
+    Pixels {
+        reference: PathBuf,
+        /// The number of pixels that differ.
+        count: usize,
+        /// How alike the scene and the reference look; see
+        /// [`ImageDiff::ssim`].
+        ///
+        /// [`ImageDiff::ssim`]: struct.ImageDiff.html#method.ssim
+        ssim: f64,
+        actual: PathBuf,
+        diff: PathBuf,
+    },
+    /// Drawing the scene, or reading or writing an image, failed.
+    Error(piet::Error),
+}
+
+impl SceneTest {
+    /// Scene tests with their references in `dir`, drawn 256 by 256
+    /// pixels at a scale of 1 and compared exactly.
+    ///
+    /// Whether to bless is read from [`PIET_BLESS`].
+    ///
+    /// [`PIET_BLESS`]: constant.BLESS_VAR.html
+    pub fn new(dir: impl Into<PathBuf>) -> SceneTest {
+        let bless = std::env::var_os(BLESS_VAR).is_some_and(|value| value != "0");
+        SceneTest {
+            dir: dir.into(),
+            width: 256,
+            height: 256,
+            pix_scale: 1.0,
+            tolerance: 0,
+            max_differing: 0,
+            min_ssim: 0.0,
+            bless,
+        }
+    }
+
+    /// The size of the bitmap scenes are drawn into, in pixels.
+    pub fn size(mut self, width: usize, height: usize) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// The number of pixels per unit of user space.
+    pub fn pix_scale(mut self, pix_scale: f64) -> Self {
+        self.pix_scale = pix_scale;
+        self
+    }
+
+    /// How far each channel of a pixel may be from the reference, out of
+    /// 255, before the pixel counts as different.
+    pub fn tolerance(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// How many pixels may differ by more than the [`tolerance`] before the
+    /// scene fails, for scenes with text or thin antialiased edges, which
+    /// move a little from one rasterizer to the next.
+    ///
+    /// [`tolerance`]: #method.tolerance
+    pub fn max_differing(mut self, count: usize) -> Self {
+        self.max_differing = count;
+        self
+    }
+
+    /// How alike the scene has to look to the reference, as the structural
+    /// similarity from [`ImageDiff::ssim`], on top of the pixels that may
+    /// differ.
+    ///
+    /// Along with a generous [`max_differing`], this passes scenes whose
+    /// edges and glyphs moved a little, and fails those where something
+    /// was drawn differently. The default of 0 checks only the pixels.
+    ///
+    /// [`ImageDiff::ssim`]: struct.ImageDiff.html#method.ssim
+    /// [`max_differing`]: #method.max_differing
+    pub fn min_ssim(mut self, min_ssim: f64) -> Self {
+        self.min_ssim = min_ssim;
+        self
+    }
+
+    /// Whether to write references instead of checking against them, in
+    /// place of what [`PIET_BLESS`] says.
+    ///
+    /// [`PIET_BLESS`]: constant.BLESS_VAR.html
+    pub fn bless(mut self, bless: bool) -> Self {
+        self.bless = bless;
+        self
+    }
+
+    /// The path of the reference image for the scene `name`.
+    pub fn reference_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.png", name))
+    }
+
+    /// Draw a scene with `draw`, and compare it with the reference image
+    /// `name`, or make it the reference when blessing.
+    ///
+    /// `draw` starts with a transparent bitmap, and shouldn't call
+    /// `finish`.
+    pub fn check(&self, name: &str, draw: impl FnOnce(&mut Piet)) -> Result<(), SceneMismatch> {
+        let pixels = self.render(draw).map_err(SceneMismatch::Error)?;
+        let reference = self.reference_path(name);
+        let actual = self.dir.join(format!("{}.actual.png", name));
+        let diff = self.dir.join(format!("{}.diff.png", name));
+        let stale = || {
+            let _ = fs::remove_file(&actual);
+            let _ = fs::remove_file(&diff);
+        };
+
+        if self.bless {
+            fs::create_dir_all(&self.dir).map_err(|e| SceneMismatch::Error(io_error(e)))?;
+            write_png(&reference, self.width, self.height, &pixels)
+                .map_err(SceneMismatch::Error)?;
+            stale();
+            return Ok(());
+        }
+        if !reference.exists() {
+            return Err(SceneMismatch::MissingReference { reference });
+        }
+        let (width, height, expected) = read_png(&reference).map_err(SceneMismatch::Error)?;
+        if (width, height) != (self.width, self.height) {
+            write_png(&actual, self.width, self.height, &pixels).map_err(SceneMismatch::Error)?;
+            return Err(SceneMismatch::Size {
+                reference,
+                expected: (width, height),
+                found: (self.width, self.height),
+                actual,
+            });
+        }
+
+        let image_diff =
+            ImageDiff::new(&pixels, &expected, width, height).map_err(SceneMismatch::Error)?;
+        let count = image_diff.count_differing(self.tolerance);
+        let ssim = image_diff.ssim();
+        if count <= self.max_differing && ssim >= self.min_ssim {
+            stale();
+            return Ok(());
+        }
+        let diff_pixels = image_diff.diff_image(self.tolerance);
+        write_png(&actual, self.width, self.height, &pixels).map_err(SceneMismatch::Error)?;
+        write_png(&diff, self.width, self.height, &diff_pixels).map_err(SceneMismatch::Error)?;
+        Err(SceneMismatch::Pixels {
+            reference,
+            count,
+            ssim,
+            actual,
+            diff,
+        })
+    }
+
+    /// Like [`check`], but panics if the scene doesn't match.
+    ///
+    /// [`check`]: #method.check
+    pub fn assert_matches(&self, name: &str, draw: impl FnOnce(&mut Piet)) {
+        if let Err(mismatch) = self.check(name, draw) {
+            panic!("scene `{}` doesn't match: {}", name, mismatch);
+        }
+    }
+
+    /// Draw a scene, and read it back as straight RGBA.
+    fn render(&self, draw: impl FnOnce(&mut Piet)) -> Result<Vec<u8>, piet::Error> {
+        let mut device = Device::new()?;
+        let mut target = device.bitmap_target(self.width, self.height, self.pix_scale)?;
+        {
+            let mut rc = target.render_context();
+            draw(&mut rc);
+            rc.finish()?;
+        }
+        let mut pixels = target.into_raw_pixels(ImageFormat::RgbaPremul)?;
+        for px in pixels.chunks_exact_mut(4) {
+            let a = px[3] as u32;
+            for c in &mut px[..3] {
+                *c = match a {
+                    0 => 0,
+                    _ => ((*c as u32 * 0xff + a / 2) / a).min(0xff) as u8,
+                };
+            }
+        }
+        Ok(pixels)
+    }
+}
+
+impl fmt::Display for SceneMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneMismatch::MissingReference { reference } => write!(
+                f,
+                "there is no reference image at {}; run with {}=1 to make it",
+                reference.display(),
+                BLESS_VAR
+            ),
+            SceneMismatch::Size {
+                reference,
+                expected,
+                found,
+                actual,
+            } => write!(
+                f,
+                "the reference image {} is {}x{}, but the scene is {}x{}; it was written to {}",
+                reference.display(),
+                expected.0,
+                expected.1,
+                found.0,
+                found.1,
+                actual.display()
+            ),
+            SceneMismatch::Pixels {
+                reference,
+                count,
+                ssim,
+                actual,
+                diff,
+            } => write!(
+                f,
+                "{} pixels differ from the reference image {}, with a similarity of {:.3}; \
+                 the scene was written to {} and the differences to {}",
+                count,
+                reference.display(),
+                ssim,
+                actual.display(),
+                diff.display()
+            ),
+            SceneMismatch::Error(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SceneMismatch {}
+
+/// Check a scene against its reference image in the calling crate's
+/// `tests/golden` directory, panicking if it doesn't match.
+///
+/// The scene is drawn 256 by 256 pixels by a closure taking the render
+/// context; see [`SceneTest`] for blessing references, what's written
+/// when a scene doesn't match, and other sizes.
+///
+/// # Examples
+///
+/// ```no_run
+/// use piet_common::kurbo::Rect;
+/// use piet_common::{assert_scene_matches, Color, RenderContext};
+///
+/// assert_scene_matches!("blue_square", |rc| {
+///     rc.clear(Color::WHITE);
+///     rc.fill(Rect::new(64.0, 64.0, 192.0, 192.0), &Color::rgb8(0, 0, 0xff));
+/// });
+/// ```
+///
+/// [`SceneTest`]: struct.SceneTest.html
+#[macro_export]
+macro_rules! assert_scene_matches {
+    ($name:expr, $draw:expr $(,)?) => {
+        $crate::SceneTest::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"))
+            .assert_matches($name, $draw)
+    };
+}
+
+fn io_error(e: std::io::Error) -> piet::Error {
+    Into::<Box<dyn std::error::Error>>::into(e).into()
+}
+
+fn write_png(path: &Path, width: usize, height: usize, rgba: &[u8]) -> Result<(), piet::Error> {
+    let file = BufWriter::new(File::create(path).map_err(io_error)?);
+    let mut encoder = Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(ColorType::RGBA);
+    encoder.set_depth(BitDepth::Eight);
+    encoder
+        .write_header()
+        .map_err(Into::<Box<dyn std::error::Error>>::into)?
+        .write_image_data(rgba)
+        .map_err(Into::<Box<dyn std::error::Error>>::into)?;
+    Ok(())
+}
+
+/// Read a PNG as straight 8-bit RGBA, with its size.
+fn read_png(path: &Path) -> Result<(usize, usize, Vec<u8>), piet::Error> {
+    let mut decoder = Decoder::new(File::open(path).map_err(io_error)?);
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    let (info, mut reader) = decoder
+        .read_info()
+        .map_err(Into::<Box<dyn std::error::Error>>::into)?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader
+        .next_frame(&mut buf)
+        .map_err(Into::<Box<dyn std::error::Error>>::into)?;
+    let rgba = match info.color_type {
+        ColorType::RGBA => buf,
+        ColorType::RGB => buf
+            .chunks_exact(3)
+            .flat_map(|px| [px[0], px[1], px[2], 0xff])
+            .collect(),
+        ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|px| [px[0], px[0], px[0], px[1]])
+            .collect(),
+        ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 0xff]).collect(),
+        ColorType::Indexed => return Err(piet::new_error(ErrorKind::NotSupported)),
+    };
+    Ok((info.width as usize, info.height as usize, rgba))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use piet::kurbo::Rect;
+    use piet::Color;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("piet-golden-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn square(rc: &mut Piet, color: Color) {
+        rc.clear(Color::WHITE);
+        rc.fill(Rect::new(4.0, 4.0, 12.0, 12.0), &color);
+    }
+
+    #[test]
+    fn bless_then_match() {
+        let dir = scratch_dir("match");
+        let test = SceneTest::new(&dir).size(16, 16);
+        let missing = test.clone().bless(false).check("square", |rc| {
+            square(rc, Color::BLACK);
+        });
+        assert!(matches!(
+            missing,
+            Err(SceneMismatch::MissingReference { .. })
+        ));
+
+        test.clone()
+            .bless(true)
+            .check("square", |rc| square(rc, Color::BLACK))
+            .unwrap();
+        assert!(test.reference_path("square").exists());
+        test.clone()
+            .bless(false)
+            .check("square", |rc| square(rc, Color::BLACK))
+            .unwrap();
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn mismatch_writes_diff() {
+        let dir = scratch_dir("diff");
+        let test = SceneTest::new(&dir).size(16, 16);
+        test.clone()
+            .bless(true)
+            .check("square", |rc| square(rc, Color::BLACK))
+            .unwrap();
+
+        let test = test.bless(false);
+        let result = test.check("square", |rc| square(rc, Color::rgb8(0x10, 0, 0)));
+        match result {
+            Err(SceneMismatch::Pixels {
+                count,
+                actual,
+                diff,
+                ..
+            }) => {
+                assert_eq!(count, 64);
+                let (width, height, diff) = read_png(&diff).unwrap();
+                assert_eq!((width, height), (16, 16));
+                // inside the square, and outside it
+                assert_eq!(&diff[(8 * 16 + 8) * 4..][..4], &[0xff, 0, 0, 0xff]);
+                assert_eq!(&diff[..4], &[0xff; 4]);
+                assert!(actual.exists());
+            }
+            _ => panic!("expected differing pixels"),
+        }
+        // close enough with a tolerance, which clears the failure images
+        test.clone()
+            .tolerance(0x10)
+            .check("square", |rc| square(rc, Color::rgb8(0x10, 0, 0)))
+            .unwrap();
+        assert!(!dir.join("square.diff.png").exists());
+        // or with some pixels allowed to differ, unless it has to look the
+        // same as well
+        test.clone()
+            .max_differing(64)
+            .check("square", |rc| square(rc, Color::rgb8(0x10, 0, 0)))
+            .unwrap();
+        let unlike = test
+            .clone()
+            .max_differing(64)
+            .min_ssim(1.0)
+            .check("square", |rc| square(rc, Color::rgb8(0x10, 0, 0)));
+        assert!(matches!(unlike, Err(SceneMismatch::Pixels { ssim, .. }) if ssim < 1.0));
+
+        let resized = test
+            .size(8, 8)
+            .check("square", |rc| square(rc, Color::BLACK));
+        assert!(matches!(resized, Err(SceneMismatch::Size { .. })));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
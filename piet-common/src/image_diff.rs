@@ -0,0 +1,259 @@
+//! Comparing images, pixel by pixel and by how alike they look.
+
+use piet::{new_error, Error, ErrorKind};
+
+/// The differences between two images of the same size, for snapshot tests.
+///
+/// Images are compared in two ways. Each pixel differs by the most any of
+/// its channels does, which [`count_differing`] checks against a tolerance;
+/// that catches a shape drawn in the wrong color or place. The [`ssim`],
+/// the structural similarity of the two, measures how alike they look
+/// instead, and changes little when antialiasing or glyph rasterization
+/// moves edges by a fraction of a pixel, which a per-pixel check can't
+/// tell from a real difference.
+///
+/// Both images are straight (not premultiplied) RGBA, as piet-common's
+/// `BitmapTarget::into_raw_pixels` gives them once unpremultiplied, and as
+/// PNGs hold them.
+///
+/// # Examples
+///
+/// ```
+/// use piet_common::ImageDiff;
+///
+/// let white = [0xff; 32 * 32 * 4];
+/// let mut speck = white;
+/// speck[..3].copy_from_slice(&[0, 0, 0]);
+///
+/// let diff = ImageDiff::new(&speck, &white, 32, 32).unwrap();
+/// assert_eq!(diff.count_differing(0), 1);
+/// // one speck hardly changes how it looks
+/// assert!(diff.ssim() > 0.95);
+/// // the speck in red, over a faded copy of `speck`
+/// let image = diff.diff_image(0);
+/// assert_eq!(&image[..4], &[0xff, 0, 0, 0xff]);
+/// ```
+///
+/// [`count_differing`]: #method.count_differing
+/// [`ssim`]: #method.ssim
+#[derive(Debug, Clone)]
+pub struct ImageDiff {
+    width: usize,
+    height: usize,
+    /// The largest difference of any channel, for each pixel.
+    deltas: Vec<u8>,
+    /// Each pixel of the actual image in gray, faded toward white, for the
+    /// diff image.
+    shades: Vec<u8>,
+    ssim: f64,
+}
+
+/// The side of the square windows SSIM is measured over, in pixels.
+const WINDOW: usize = 8;
+
+/// The distance between windows, which overlap by half.
+const STEP: usize = WINDOW / 2;
+
+impl ImageDiff {
+    /// Compare `actual` with `expected`, both `width` by `height` pixels of
+    /// straight RGBA.
+    ///
+    /// Returns an error if either buffer isn't the right length.
+    pub fn new(
+        actual: &[u8],
+        expected: &[u8],
+        width: usize,
+        height: usize,
+    ) -> Result<ImageDiff, Error> {
+        let len = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .ok_or_else(|| new_error(ErrorKind::InvalidInput))?;
+        if actual.len() != len || expected.len() != len {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        let deltas = actual
+            .chunks_exact(4)
+            .zip(expected.chunks_exact(4))
+            .map(|(a, b)| a.iter().zip(b).map(|(&a, &b)| a.abs_diff(b)).max().unwrap())
+            .collect();
+        let shades = actual
+            .chunks_exact(4)
+            .map(|px| {
+                // light enough for red to stand out, with the shapes still
+                // recognizable
+                let luma = (px[0] as u32 * 3 + px[1] as u32 * 6 + px[2] as u32) / 10;
+                (0xff - ((0xff - luma) * px[3] as u32 / 0xff) / 4) as u8
+            })
+            .collect();
+        let ssim = ssim(&luma(actual), &luma(expected), width, height);
+        Ok(ImageDiff {
+            width,
+            height,
+            deltas,
+            shades,
+            ssim,
+        })
+    }
+
+    /// The size of the images, in pixels.
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// The largest difference of any channel of any pixel, out of 255.
+    pub fn max_delta(&self) -> u8 {
+        self.deltas.iter().copied().max().unwrap_or(0)
+    }
+
+    /// The number of pixels with a channel that differs by more than
+    /// `tolerance`, out of 255.
+    pub fn count_differing(&self, tolerance: u8) -> usize {
+        self.deltas
+            .iter()
+            .filter(|&&delta| delta > tolerance)
+            .count()
+    }
+
+    /// The mean structural similarity of the images, from 1 for images that
+    /// look the same down to about 0 for unrelated ones.
+    ///
+    /// This is SSIM over windows of 8 by 8 pixels overlapping by half, of
+    /// each image's luma over middle gray, so a difference in alpha alone
+    /// counts too. Images smaller than a window are measured as one window.
+    pub fn ssim(&self) -> f64 {
+        self.ssim
+    }
+
+    /// An image of the pixels that differ by more than `tolerance`, in red,
+    /// over a faded gray copy of the actual image.
+    pub fn diff_image(&self, tolerance: u8) -> Vec<u8> {
+        let mut image = Vec::with_capacity(self.deltas.len() * 4);
+        for (&delta, &shade) in self.deltas.iter().zip(&self.shades) {
+            if delta > tolerance {
+                image.extend_from_slice(&[0xff, 0, 0, 0xff]);
+            } else {
+                image.extend_from_slice(&[shade, shade, shade, 0xff]);
+            }
+        }
+        image
+    }
+}
+
+/// The luma of each pixel of straight RGBA `pixels`, composited over
+/// middle gray.
+fn luma(pixels: &[u8]) -> Vec<f64> {
+    pixels
+        .chunks_exact(4)
+        .map(|px| {
+            let luma = 0.299 * px[0] as f64 + 0.587 * px[1] as f64 + 0.114 * px[2] as f64;
+            let alpha = px[3] as f64 / 255.0;
+            luma * alpha + 127.5 * (1.0 - alpha)
+        })
+        .collect()
+}
+
+/// The mean SSIM of two images of luma, over windows `STEP` apart.
+fn ssim(a: &[f64], b: &[f64], width: usize, height: usize) -> f64 {
+    // the usual constants, for a range of 255
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    if width == 0 || height == 0 {
+        return 1.0;
+    }
+    let (window_width, window_height) = (WINDOW.min(width), WINDOW.min(height));
+    let n = (window_width * window_height) as f64;
+    let mut total = 0.0;
+    let mut windows = 0;
+    for y0 in (0..=height - window_height).step_by(STEP) {
+        for x0 in (0..=width - window_width).step_by(STEP) {
+            let pixels = (y0..y0 + window_height)
+                .flat_map(|y| (x0..x0 + window_width).map(move |x| y * width + x));
+            let (mut sum_a, mut sum_b) = (0.0, 0.0);
+            let (mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0);
+            for i in pixels {
+                let (a, b) = (a[i], b[i]);
+                sum_a += a;
+                sum_b += b;
+                sum_aa += a * a;
+                sum_bb += b * b;
+                sum_ab += a * b;
+            }
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covar = sum_ab / n - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covar + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A `size` by `size` image of vertical black and white stripes, each
+    /// `width` pixels wide, starting `shift` pixels in.
+    fn stripes(size: usize, width: usize, shift: usize) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(size * size * 4);
+        for _ in 0..size {
+            for x in 0..size {
+                let v = [0, 0xff][(x + shift) / width % 2];
+                pixels.extend_from_slice(&[v, v, v, 0xff]);
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn identical() {
+        let image = stripes(16, 2, 0);
+        let diff = ImageDiff::new(&image, &image, 16, 16).unwrap();
+        assert_eq!(diff.max_delta(), 0);
+        assert_eq!(diff.count_differing(0), 0);
+        assert!((diff.ssim() - 1.0).abs() < 1e-9);
+        assert!(diff.diff_image(0).chunks_exact(4).all(|px| px[0] == px[1]));
+    }
+
+    #[test]
+    fn structure_counts_more_than_level() {
+        let image = stripes(32, 4, 0);
+        // every pixel a little brighter: many differ, but it looks the same
+        let brighter: Vec<u8> = image.iter().map(|&c| c.saturating_add(6)).collect();
+        let diff = ImageDiff::new(&brighter, &image, 32, 32).unwrap();
+        assert_eq!(diff.max_delta(), 6);
+        assert_eq!(diff.count_differing(4), 32 * 32 / 2);
+        assert!(diff.ssim() > 0.99);
+
+        // the stripes moved by half their width: few pixels, but it looks
+        // quite different
+        let shifted = stripes(32, 4, 2);
+        let diff = ImageDiff::new(&shifted, &image, 32, 32).unwrap();
+        assert!(diff.ssim() < 0.5);
+    }
+
+    #[test]
+    fn alpha_alone() {
+        let opaque = [0, 0, 0, 0xff].repeat(8 * 8);
+        let clear = [0, 0, 0, 0].repeat(8 * 8);
+        let diff = ImageDiff::new(&opaque, &clear, 8, 8).unwrap();
+        assert_eq!(diff.count_differing(0), 64);
+        assert!(diff.ssim() < 0.5);
+    }
+
+    #[test]
+    fn small_and_mismatched() {
+        let image = stripes(3, 1, 0);
+        let diff = ImageDiff::new(&image, &image, 3, 3).unwrap();
+        assert_eq!(diff.size(), (3, 3));
+        assert!((diff.ssim() - 1.0).abs() < 1e-9);
+        assert!(ImageDiff::new(&image, &image[4..], 3, 3).is_err());
+        assert!(ImageDiff::new(&image, &image, 4, 3).is_err());
+        let empty = ImageDiff::new(&[], &[], 0, 5).unwrap();
+        assert_eq!(empty.ssim(), 1.0);
+    }
+}
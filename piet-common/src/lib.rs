@@ -11,8 +11,9 @@
 //!
 //! The associated types for brushes, text, and images are exported as type
 //! definitions (resolving to concrete types within the backend), so they can
-//! be used directly. The text-related types are prefixed with "Piet" to avoid
-//! conflict with the text traits that would otherwise have the same name.
+//! be used directly. The text-related types and the image and path types are
+//! prefixed with "Piet" to avoid conflict with the traits and kurbo types that
+//! would otherwise have the same name.
 //!
 //! Also note that all public types for the specific backend are re-exported,
 //! but have their docs hidden here. These types can be useful for platform
@@ -47,6 +48,29 @@ cfg_if::cfg_if! {
 
 pub use backend::*;
 
+mod image_diff;
+pub use image_diff::*;
+
+// scenes are drawn with `Device`, which the web backend doesn't have
+#[cfg(all(
+    feature = "png",
+    any(
+        feature = "cairo",
+        target_os = "windows",
+        not(any(target_arch = "wasm32", feature = "web"))
+    )
+))]
+mod golden;
+#[cfg(all(
+    feature = "png",
+    any(
+        feature = "cairo",
+        target_os = "windows",
+        not(any(target_arch = "wasm32", feature = "web"))
+    )
+))]
+pub use golden::*;
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -61,6 +85,6 @@ mod test {
         piet_font_builder: PietFontBuilder<'a>,
         piet_text_layout: PietTextLayout,
         piet_text_layout_builder: PietTextLayoutBuilder<'a>,
-        image: Image,
+        image: PietImage,
     }
 }
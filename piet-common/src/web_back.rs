@@ -37,4 +37,9 @@ pub type PietTextLayoutBuilder<'a> = WebTextLayoutBuilder;
 /// The associated image type for this backend.
 ///
 /// This type matches `RenderContext::Image`
-pub type Image = WebImage;
+pub type PietImage = WebImage;
+
+/// The associated path type for this backend.
+///
+/// This type matches `RenderContext::Path`
+pub type PietPath = WebPath;
@@ -0,0 +1,56 @@
+//! Clips, nested and popped, and even-odd fills.
+
+use piet::kurbo::{Affine, BezPath, Circle, Rect, Shape};
+use piet::{Color, Error, RenderContext};
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+
+    // a rectangle, then a circle inside it, each cutting down the other
+    rc.with_save(|rc| {
+        rc.clip(Rect::new(8.0, 8.0, 56.0, 56.0));
+        stripes(rc, &Color::rgb8(0xc0, 0xc0, 0xc0));
+        rc.clip(Circle::new((56.0, 56.0), 28.0));
+        stripes(rc, &Color::rgb8(0xc0, 0, 0));
+        Ok(())
+    })?;
+
+    // a clip that ends with pop_clip, after which drawing isn't clipped
+    rc.push_clip(Circle::new((96.0, 32.0), 20.0));
+    rc.fill(Rect::new(64.0, 0.0, 128.0, 64.0), &Color::rgb8(0, 0x80, 0));
+    rc.pop_clip()?;
+    rc.fill(Rect::new(112.0, 8.0, 120.0, 16.0), &Color::BLACK);
+
+    // a rotated clip
+    rc.with_save(|rc| {
+        rc.transform(
+            Affine::translate((32.0, 96.0))
+                * Affine::rotate(0.5)
+                * Affine::translate((-32.0, -96.0)),
+        );
+        rc.clip(Rect::new(16.0, 80.0, 48.0, 112.0));
+        stripes(rc, &Color::rgb8(0, 0, 0xc0));
+        Ok(())
+    })?;
+
+    // a ring, by filling two circles even-odd
+    let outer = Circle::new((96.0, 96.0), 24.0).to_bez_path(0.1);
+    let inner = Circle::new((96.0, 96.0), 12.0).to_bez_path(0.1);
+    let ring = BezPath::from_vec(outer.chain(inner).collect());
+    rc.fill_even_odd(ring, &Color::rgb8(0x80, 0, 0x80));
+    Ok(())
+}
+
+/// Diagonal stripes over the whole scene, to show what a clip lets through.
+fn stripes(rc: &mut impl RenderContext, color: &Color) {
+    for i in 0..16 {
+        let x = i as f64 * 16.0 - 128.0;
+        let mut stripe = BezPath::new();
+        stripe.move_to((x, 128.0));
+        stripe.line_to((x + 128.0, 0.0));
+        stripe.line_to((x + 136.0, 0.0));
+        stripe.line_to((x + 8.0, 128.0));
+        stripe.close_path();
+        rc.fill(stripe, color);
+    }
+}
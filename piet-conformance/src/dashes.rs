@@ -0,0 +1,41 @@
+//! Dashed strokes, in a scaled space and with offsets and odd patterns.
+
+use piet::kurbo::{Affine, Circle, Line};
+use piet::{Color, Error, LineCap, RenderContext, StrokeStyle};
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+    let black = Color::BLACK;
+
+    // the same dashes in device space, then in a space scaled by 2, where
+    // they should come out twice as long
+    let style = StrokeStyle::dashed(&[8.0, 4.0], 0.0);
+    rc.stroke_styled(Line::new((8.0, 8.0), (120.0, 8.0)), &black, 2.0, &style);
+    rc.with_save(|rc| {
+        rc.transform(Affine::scale(2.0));
+        rc.stroke_styled(Line::new((4.0, 8.0), (60.0, 8.0)), &black, 1.0, &style);
+        Ok(())
+    })?;
+
+    // an offset, and an odd pattern, which repeats doubled
+    let offset = StrokeStyle::dashed(&[8.0, 4.0], 6.0);
+    rc.stroke_styled(Line::new((8.0, 28.0), (120.0, 28.0)), &black, 2.0, &offset);
+    let odd = StrokeStyle::dashed(&[6.0, 2.0, 2.0], 0.0);
+    rc.stroke_styled(Line::new((8.0, 36.0), (120.0, 36.0)), &black, 2.0, &odd);
+
+    // caps apply to each dash
+    let round = StrokeStyle::dashed(&[0.0, 8.0], 0.0).with_line_cap(LineCap::Round);
+    rc.stroke_styled(Line::new((8.0, 48.0), (120.0, 48.0)), &black, 4.0, &round);
+    let square = StrokeStyle::dashed(&[4.0, 8.0], 0.0).with_line_cap(LineCap::Square);
+    rc.stroke_styled(Line::new((8.0, 58.0), (120.0, 58.0)), &black, 4.0, &square);
+
+    // dashes follow curves, here stretched so only the length along the
+    // path in user space counts
+    let blue = Color::rgb8(0, 0x40, 0xc0);
+    rc.with_save(|rc| {
+        rc.transform(Affine::translate((64.0, 96.0)) * Affine::scale_non_uniform(2.0, 1.0));
+        let style = StrokeStyle::dashed(&[6.0, 3.0], 0.0);
+        rc.stroke_styled(Circle::new((0.0, 0.0), 22.0), &blue, 2.0, &style);
+        Ok(())
+    })
+}
@@ -0,0 +1,64 @@
+//! Linear and radial gradients, in both color spaces.
+
+use piet::kurbo::{Circle, Point, Rect, Vec2};
+use piet::{
+    Color, Error, FixedLinearGradient, FixedRadialGradient, GradientColorSpace, GradientStop,
+    GradientStops, RenderContext,
+};
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+
+    // red to blue, blended in sRGB and then in linear light, where the
+    // middle is lighter; backends that ignore the color space draw these
+    // alike
+    let red = Color::rgb8(0xff, 0, 0);
+    let blue = Color::rgb8(0, 0, 0xff);
+    for (i, &color_space) in [GradientColorSpace::Srgb, GradientColorSpace::LinearRgb]
+        .iter()
+        .enumerate()
+    {
+        let y = 8.0 + i as f64 * 24.0;
+        let brush = rc.gradient(FixedLinearGradient {
+            start: Point::new(8.0, 0.0),
+            end: Point::new(120.0, 0.0),
+            stops: (red.clone(), blue.clone()).to_vec(),
+            color_space,
+        })?;
+        rc.fill(Rect::new(8.0, y, 120.0, y + 20.0), &brush);
+    }
+
+    // uneven stops, with a transparent one, on a diagonal
+    let stops = vec![
+        GradientStop {
+            pos: 0.0,
+            color: Color::rgb8(0, 0x80, 0),
+        },
+        GradientStop {
+            pos: 0.2,
+            color: Color::rgba8(0xff, 0xc0, 0, 0x40),
+        },
+        GradientStop {
+            pos: 1.0,
+            color: Color::BLACK,
+        },
+    ];
+    let brush = rc.gradient(FixedLinearGradient {
+        start: Point::new(8.0, 56.0),
+        end: Point::new(56.0, 120.0),
+        stops,
+        color_space: GradientColorSpace::Srgb,
+    })?;
+    rc.fill(Rect::new(8.0, 56.0, 56.0, 120.0), &brush);
+
+    // off-center radial, past its radius to check the last stop extends
+    let brush = rc.gradient(FixedRadialGradient {
+        center: Point::new(92.0, 88.0),
+        origin_offset: Vec2::new(-8.0, -8.0),
+        radius: 24.0,
+        stops: (Color::WHITE, Color::rgb8(0x80, 0, 0x80)).to_vec(),
+        color_space: GradientColorSpace::Srgb,
+    })?;
+    rc.fill(Circle::new((92.0, 88.0), 30.0), &brush);
+    Ok(())
+}
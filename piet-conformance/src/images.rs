@@ -0,0 +1,66 @@
+//! Images of each format, scaled with each interpolation mode.
+
+use piet::kurbo::Rect;
+use piet::{Color, Error, ImageFormat, InterpolationMode, RenderContext};
+
+/// A 4 by 4 checkerboard of red and translucent blue, as straight RGBA.
+fn checkerboard() -> Vec<u8> {
+    let mut buf = Vec::with_capacity(4 * 4 * 4);
+    for y in 0..4 {
+        for x in 0..4 {
+            let px = if (x + y) % 2 == 0 {
+                [0xff, 0, 0, 0xff]
+            } else {
+                [0, 0, 0xff, 0x80]
+            };
+            buf.extend_from_slice(&px);
+        }
+    }
+    buf
+}
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+    // stripes behind the images, to show their alpha
+    rc.fill(Rect::new(0.0, 24.0, 128.0, 40.0), &Color::BLACK);
+    rc.fill(Rect::new(0.0, 88.0, 128.0, 104.0), &Color::BLACK);
+
+    let checkers = rc.make_image(4, 4, &checkerboard(), ImageFormat::RgbaSeparate)?;
+    rc.draw_image(
+        &checkers,
+        Rect::new(8.0, 8.0, 56.0, 56.0),
+        InterpolationMode::NearestNeighbor,
+    );
+    rc.draw_image(
+        &checkers,
+        Rect::new(72.0, 8.0, 120.0, 56.0),
+        InterpolationMode::Bilinear,
+    );
+
+    // the same pixels premultiplied, and part of the image
+    let premul: Vec<u8> = checkerboard()
+        .chunks_exact(4)
+        .flat_map(|px| {
+            let a = px[3] as u32;
+            let c = |c: u8| ((c as u32 * a + 127) / 255) as u8;
+            vec![c(px[0]), c(px[1]), c(px[2]), px[3]]
+        })
+        .collect();
+    let premul = rc.make_image(4, 4, &premul, ImageFormat::RgbaPremul)?;
+    rc.draw_image_area(
+        &premul,
+        Rect::new(1.0, 1.0, 3.0, 3.0),
+        Rect::new(8.0, 72.0, 56.0, 120.0),
+        InterpolationMode::NearestNeighbor,
+    );
+
+    // a gray ramp without alpha
+    let ramp: Vec<u8> = (0..8u8).flat_map(|i| vec![i * 0x24; 3]).collect();
+    let ramp = rc.make_image(8, 1, &ramp, ImageFormat::Rgb)?;
+    rc.draw_image(
+        &ramp,
+        Rect::new(72.0, 72.0, 120.0, 120.0),
+        InterpolationMode::NearestNeighbor,
+    );
+    Ok(())
+}
@@ -0,0 +1,166 @@
+//! Canonical scenes for checking that piet backends draw alike.
+//!
+//! Each [`Scene`] exercises one area where backends have drifted apart
+//! before: gradient interpolation, dash lengths under a transform, clips,
+//! text, image sampling and transforms. The tests of this crate draw every
+//! scene with each backend that builds on the platform, and compare what
+//! they draw with references kept in `tests/reference`:
+//!
+//! - The raster backend piet-common picks, cairo or Direct2D, is compared
+//!   with `tests/reference/<name>.png`, pixel by pixel within the scene's
+//!   tolerances, and by how alike the two look.
+//! - The SVG backend is compared with `tests/reference/svg/<name>.svg`,
+//!   exactly, for the scenes it supports.
+//!
+//! The web backend only runs in a browser, so it isn't checked here.
+//!
+//! Running the tests with `PIET_BLESS=1` writes the references from what's
+//! drawn, after an intended change to a scene or to how backends draw it.
+//! A scene that doesn't match has what was drawn written next to its
+//! reference, with a `.actual` extension, and for raster scenes a `.diff`
+//! image of the pixels that differ.
+//!
+//! [`Scene`]: struct.Scene.html
+
+use piet::{Capabilities, Error, RenderContext};
+
+mod clips;
+mod dashes;
+mod gradients;
+mod images;
+mod text;
+mod transforms;
+
+/// A scene drawn by every backend and compared with a reference.
+#[derive(Debug, Clone, Copy)]
+pub struct Scene {
+    /// The name of the scene, which its references are named after.
+    pub name: &'static str,
+    /// The width of the scene, in pixels at a scale of 1.
+    pub width: usize,
+    /// The height of the scene, in pixels at a scale of 1.
+    pub height: usize,
+    /// How far each channel of a pixel may be from the reference, out of
+    /// 255, before the pixel counts as different.
+    pub tolerance: u8,
+    /// The fraction of pixels that may differ, for antialiased edges and
+    /// glyphs that rasterizers place a little differently.
+    pub max_differing: f64,
+    /// How alike the scene has to look to its reference all the same, as
+    /// the structural similarity piet-common's `ImageDiff::ssim` measures.
+    pub min_ssim: f64,
+    /// The features the scene needs, which backends without them skip it
+    /// for.
+    needs: fn(&Capabilities) -> bool,
+    draw: Draw,
+}
+
+/// The function that draws a scene, one per module.
+#[derive(Debug, Clone, Copy)]
+enum Draw {
+    Gradients,
+    Dashes,
+    Clips,
+    Text,
+    Images,
+    Transforms,
+}
+
+/// Every scene, in the order they're checked.
+pub const SCENES: &[Scene] = &[
+    Scene {
+        name: "gradients",
+        width: 128,
+        height: 128,
+        tolerance: 2,
+        max_differing: 0.01,
+        min_ssim: 0.95,
+        needs: |caps| caps.linear_gradients && caps.radial_gradients,
+        draw: Draw::Gradients,
+    },
+    Scene {
+        name: "dashes",
+        width: 128,
+        height: 128,
+        tolerance: 8,
+        max_differing: 0.01,
+        min_ssim: 0.95,
+        needs: |caps| caps.dashes,
+        draw: Draw::Dashes,
+    },
+    Scene {
+        name: "clips",
+        width: 128,
+        height: 128,
+        tolerance: 8,
+        max_differing: 0.01,
+        min_ssim: 0.95,
+        needs: |_| true,
+        draw: Draw::Clips,
+    },
+    Scene {
+        name: "text",
+        width: 128,
+        height: 128,
+        tolerance: 16,
+        // glyphs come from whatever fonts are installed, so this mostly
+        // checks where text goes and what color it is
+        max_differing: 0.1,
+        min_ssim: 0.8,
+        needs: |caps| caps.text,
+        draw: Draw::Text,
+    },
+    Scene {
+        name: "images",
+        width: 128,
+        height: 128,
+        tolerance: 4,
+        max_differing: 0.01,
+        min_ssim: 0.95,
+        needs: |caps| caps.images && caps.nearest_neighbor_interpolation,
+        draw: Draw::Images,
+    },
+    Scene {
+        name: "transforms",
+        width: 128,
+        height: 128,
+        tolerance: 8,
+        max_differing: 0.01,
+        min_ssim: 0.95,
+        needs: |_| true,
+        draw: Draw::Transforms,
+    },
+];
+
+impl Scene {
+    /// The scene called `name`, if there is one.
+    pub fn by_name(name: &str) -> Option<&'static Scene> {
+        SCENES.iter().find(|scene| scene.name == name)
+    }
+
+    /// Draw the scene with `rc`, from a transparent target.
+    ///
+    /// Doesn't call `finish`.
+    pub fn draw(&self, rc: &mut impl RenderContext) -> Result<(), Error> {
+        match self.draw {
+            Draw::Gradients => gradients::draw(rc),
+            Draw::Dashes => dashes::draw(rc),
+            Draw::Clips => clips::draw(rc),
+            Draw::Text => text::draw(rc),
+            Draw::Images => images::draw(rc),
+            Draw::Transforms => transforms::draw(rc),
+        }
+    }
+
+    /// Whether a backend with `capabilities` can draw the scene.
+    pub fn is_supported(&self, capabilities: &Capabilities) -> bool {
+        (self.needs)(capabilities)
+    }
+
+    /// The number of pixels that may differ, from [`max_differing`].
+    ///
+    /// [`max_differing`]: #structfield.max_differing
+    pub fn max_differing_pixels(&self) -> usize {
+        (self.max_differing * (self.width * self.height) as f64) as usize
+    }
+}
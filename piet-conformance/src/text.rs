@@ -0,0 +1,37 @@
+//! Text in a few sizes and colors, and an underline from its metrics.
+
+use piet::kurbo::{Line, Rect};
+use piet::{Color, Error, FontBuilder, RenderContext, Text, TextLayout, TextLayoutBuilder};
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+
+    let mut y = 24.0;
+    for &(size, ref color) in &[
+        (10.0, Color::BLACK),
+        (16.0, Color::rgb8(0, 0x60, 0xc0)),
+        (24.0, Color::rgb8(0xc0, 0x20, 0)),
+    ] {
+        let font = rc.text().new_font_by_name("sans-serif", size).build()?;
+        let layout = rc.text().new_text_layout(&font, "Piet ok").build()?;
+        rc.draw_text(&layout, (8.0, y), color);
+        // the layout's width puts the underline where the text ends
+        let end = 8.0 + layout.width();
+        rc.stroke(Line::new((8.0, y + 3.0), (end, y + 3.0)), color, 1.0);
+        y += size * 1.5 + 8.0;
+    }
+
+    // translucent text over a block, to check it's blended
+    rc.fill(
+        Rect::new(8.0, 100.0, 120.0, 124.0),
+        &Color::rgb8(0x20, 0x20, 0x20),
+    );
+    let font = rc.text().new_font_by_name("sans-serif", 16.0).build()?;
+    let layout = rc.text().new_text_layout(&font, "alpha").build()?;
+    rc.draw_text(
+        &layout,
+        (16.0, 118.0),
+        &Color::rgba8(0xff, 0xff, 0xff, 0x80),
+    );
+    Ok(())
+}
@@ -0,0 +1,43 @@
+//! Rotations, skews and non-uniform scales, and strokes under them.
+
+use piet::kurbo::{Affine, Circle, Rect};
+use piet::{Color, Error, RenderContext};
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+    let square = Rect::new(-12.0, -12.0, 12.0, 12.0);
+
+    // rotations compose, and restore undoes them
+    for i in 0..4 {
+        rc.with_save(|rc| {
+            rc.transform(Affine::translate((24.0 + i as f64 * 28.0, 24.0)));
+            rc.transform(Affine::rotate(i as f64 * 0.3));
+            rc.fill(square, &Color::rgb8(0, 0x60, 0xc0));
+            Ok(())
+        })?;
+    }
+
+    // a skew
+    rc.with_save(|rc| {
+        rc.transform(Affine::new([1.0, 0.0, 0.5, 1.0, 32.0, 72.0]));
+        rc.fill(square, &Color::rgb8(0xc0, 0x60, 0));
+        Ok(())
+    })?;
+
+    // strokes scale with the space, so one scaled more across than down
+    // is thicker at the sides than at the top and bottom
+    rc.with_save(|rc| {
+        rc.transform(Affine::translate((92.0, 72.0)) * Affine::scale_non_uniform(3.0, 1.0));
+        rc.stroke(Circle::new((0.0, 0.0), 8.0), &Color::BLACK, 2.0);
+        Ok(())
+    })?;
+
+    // a mirrored space, where the nested transform is applied first
+    rc.with_save(|rc| {
+        rc.transform(Affine::new([-1.0, 0.0, 0.0, 1.0, 128.0, 0.0]));
+        rc.transform(Affine::translate((24.0, 108.0)));
+        rc.fill(Rect::new(0.0, -8.0, 48.0, 8.0), &Color::rgb8(0, 0x80, 0));
+        rc.fill(Rect::new(0.0, -8.0, 8.0, 8.0), &Color::BLACK);
+        Ok(())
+    })
+}
@@ -0,0 +1,50 @@
+//! Draw every scene with the platform's raster backend, and compare it
+//! with its PNG reference.
+
+use std::cell::RefCell;
+
+use piet_common::{Capabilities, Device, RenderContext, SceneTest};
+use piet_conformance::SCENES;
+
+/// What the backend piet-common picked can do.
+fn capabilities() -> Capabilities {
+    let mut device = Device::new().unwrap();
+    let mut target = device.bitmap_target(1, 1, 1.0).unwrap();
+    let mut rc = target.render_context();
+    let capabilities = rc.capabilities();
+    rc.finish().unwrap();
+    capabilities
+}
+
+#[test]
+fn scenes_match_references() {
+    let capabilities = capabilities();
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/reference");
+    // every scene is checked before failing, to see all that drifted at once
+    let mut failures = Vec::new();
+    for scene in SCENES {
+        if !scene.is_supported(&capabilities) {
+            eprintln!("skipping `{}`, which the backend can't draw", scene.name);
+            continue;
+        }
+        let error = RefCell::new(None);
+        let result = SceneTest::new(dir)
+            .size(scene.width, scene.height)
+            .tolerance(scene.tolerance)
+            .max_differing(scene.max_differing_pixels())
+            .min_ssim(scene.min_ssim)
+            .check(scene.name, |rc| {
+                if let Err(e) = scene.draw(rc) {
+                    *error.borrow_mut() = Some(e);
+                }
+            });
+        if let Some(e) = error.into_inner() {
+            failures.push(format!("`{}` failed to draw: {}", scene.name, e));
+        } else if let Err(mismatch) = result {
+            failures.push(format!("`{}` doesn't match: {}", scene.name, mismatch));
+        }
+    }
+    if !failures.is_empty() {
+        panic!("{}", failures.join("\n"));
+    }
+}
@@ -0,0 +1,70 @@
+//! Draw every scene the SVG backend supports, and compare the document with
+//! its reference.
+
+use std::fs;
+use std::path::Path;
+
+use piet::RenderContext;
+use piet_common::BLESS_VAR;
+use piet_conformance::SCENES;
+
+/// The scene's document, from drawing it with a fresh context.
+fn render(scene: &piet_conformance::Scene) -> Result<String, piet::Error> {
+    let mut rc = piet_svg::RenderContext::new();
+    scene.draw(&mut rc)?;
+    rc.finish()?;
+    let mut doc = Vec::new();
+    rc.write(&mut doc)
+        .map_err(Into::<Box<dyn std::error::Error>>::into)?;
+    Ok(String::from_utf8(doc).expect("SVG isn't UTF-8"))
+}
+
+#[test]
+fn scenes_match_references() {
+    let bless = std::env::var_os(BLESS_VAR).is_some_and(|value| value != "0");
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/reference/svg");
+    let capabilities = piet_svg::RenderContext::new().capabilities();
+    let mut failures = Vec::new();
+    for scene in SCENES {
+        if !scene.is_supported(&capabilities) {
+            continue;
+        }
+        let doc = match render(scene) {
+            Ok(doc) => doc,
+            Err(e) => {
+                failures.push(format!("`{}` failed to draw: {}", scene.name, e));
+                continue;
+            }
+        };
+        let reference = dir.join(format!("{}.svg", scene.name));
+        let actual = dir.join(format!("{}.actual.svg", scene.name));
+        if bless {
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(&reference, &doc).unwrap();
+            let _ = fs::remove_file(&actual);
+            continue;
+        }
+        match fs::read_to_string(&reference) {
+            Ok(expected) if expected == doc => {
+                let _ = fs::remove_file(&actual);
+            }
+            Ok(_) => {
+                fs::write(&actual, &doc).unwrap();
+                failures.push(format!(
+                    "`{}` doesn't match {}; it was written to {}",
+                    scene.name,
+                    reference.display(),
+                    actual.display()
+                ));
+            }
+            Err(_) => failures.push(format!(
+                "there is no reference at {}; run with {}=1 to make it",
+                reference.display(),
+                BLESS_VAR
+            )),
+        }
+    }
+    if !failures.is_empty() {
+        panic!("{}", failures.join("\n"));
+    }
+}
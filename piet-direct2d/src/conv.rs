@@ -115,6 +115,12 @@ pub(crate) fn gradient_stop_to_d2d(stop: &GradientStop) -> D2D1_GRADIENT_STOP {
     }
 }
 
+/// Premultiply a channel `x` by alpha `a`, rounding to nearest.
+pub(crate) fn premul(x: u8, a: u8) -> u8 {
+    let y = (x as u16) * (a as u16) + 0x80;
+    ((y + (y >> 8)) >> 8) as u8
+}
+
 fn convert_line_cap(line_cap: LineCap) -> D2D1_CAP_STYLE {
     match line_cap {
         LineCap::Butt => D2D1_CAP_STYLE_FLAT,
@@ -142,11 +148,12 @@ pub(crate) fn convert_stroke_style(
     stroke_style: &StrokeStyle,
     width: f32,
 ) -> Result<crate::d2d::StrokeStyle, Error> {
-    #[allow(unused)]
     let cap = convert_line_cap(stroke_style.line_cap.unwrap_or(LineCap::Butt));
     let join = convert_line_join(stroke_style.line_join.unwrap_or(LineJoin::Miter));
-    let (dashes, dash_style, dash_off) = match &stroke_style.dash {
+    let (dashes, dash_style, dash_off) = match stroke_style.dash_pattern() {
         Some((dashes, off)) => {
+            // Direct2D measures both the dashes and the offset in stroke
+            // widths, piet in user space units.
             let width_recip = if width == 0.0 { 1.0 } else { width.recip() };
             assert!(dashes.len() <= 0xffff_ffff);
             (
@@ -157,7 +164,7 @@ pub(crate) fn convert_stroke_style(
                         .collect::<Vec<f32>>(),
                 ),
                 D2D1_DASH_STYLE_CUSTOM,
-                *off as f32,
+                off as f32 * width_recip,
             )
         }
         None => (None, D2D1_DASH_STYLE_SOLID, 0.0),
@@ -165,7 +172,8 @@ pub(crate) fn convert_stroke_style(
     let props = D2D1_STROKE_STYLE_PROPERTIES {
         startCap: cap,
         endCap: cap,
-        dashCap: D2D1_CAP_STYLE_FLAT,
+        // each dash gets the line cap, like in cairo and canvas
+        dashCap: cap,
         lineJoin: join,
         miterLimit: stroke_style.miter_limit.unwrap_or(10.0) as f32,
         dashStyle: dash_style,
@@ -173,3 +181,84 @@ pub(crate) fn convert_stroke_style(
     };
     Ok(factory.create_stroke_style(&props, dashes.as_deref())?)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use piet_test::properties::{self, EDGE_VALUES};
+    use proptest::prelude::*;
+
+    fn color_bytes(color: D2D1_COLOR_F) -> u32 {
+        [color.r, color.g, color.b, color.a]
+            .iter()
+            .fold(0, |rgba, &c| rgba << 8 | (c * 255.0).round() as u32)
+    }
+
+    /// Check that each coefficient of `affine` is narrowed to the nearest
+    /// `f32`.
+    fn check_narrowed(affine: Affine) {
+        let matrix = affine_to_matrix3x2f(affine).matrix;
+        let narrowed = [
+            matrix[0][0],
+            matrix[0][1],
+            matrix[1][0],
+            matrix[1][1],
+            matrix[2][0],
+            matrix[2][1],
+        ];
+        for (&n, &c) in narrowed.iter().zip(&affine.as_coeffs()) {
+            assert_eq!(n.to_bits(), (c as f32).to_bits(), "{:?}", affine);
+            // values within f32's range stay finite
+            if c.abs() <= f32::MAX as f64 {
+                assert!(n.is_finite());
+            }
+        }
+    }
+
+    #[test]
+    fn affine_narrows_each_edge_coefficient() {
+        for c in EDGE_VALUES.chunks(6) {
+            let mut coeffs = [0.0; 6];
+            coeffs[..c.len()].copy_from_slice(c);
+            check_narrowed(Affine::new(coeffs));
+        }
+    }
+
+    #[test]
+    fn color_round_trip() {
+        for byte in 0..=255u32 {
+            let rgba = byte << 24 | (255 - byte) << 16 | (byte * 7 % 256) << 8 | byte;
+            let color = color_to_colorf(Color::from_rgba32_u32(rgba));
+            assert_eq!(color_bytes(color), rgba);
+        }
+    }
+
+    #[test]
+    fn premul_rounds_to_nearest() {
+        properties::check_premul_rounds_to_nearest(premul);
+    }
+
+    proptest! {
+        #[test]
+        fn affine_narrows_each_coefficient(affine in properties::affine()) {
+            check_narrowed(affine);
+        }
+
+        #[test]
+        fn generated_color_round_trip(color in properties::color()) {
+            prop_assert_eq!(
+                color_bytes(color_to_colorf(color.clone())),
+                color.as_rgba_u32()
+            );
+        }
+
+        #[test]
+        fn gradient_stops_keep_position_and_color(stops in properties::stops(8)) {
+            for stop in stops {
+                let d2d = gradient_stop_to_d2d(&stop);
+                prop_assert_eq!(d2d.position.to_bits(), stop.pos.to_bits());
+                prop_assert_eq!(color_bytes(d2d.color), stop.color.as_rgba_u32());
+            }
+        }
+    }
+}
@@ -15,31 +15,41 @@ use std::ptr::{null, null_mut};
 use wio::com::ComPtr;
 
 use winapi::shared::dxgi::{IDXGIDevice, IDXGISurface};
-use winapi::shared::dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM;
-use winapi::shared::winerror::{HRESULT, SUCCEEDED};
+use winapi::shared::dxgiformat::{DXGI_FORMAT, DXGI_FORMAT_A8_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM};
+use winapi::shared::minwindef::{BYTE, FALSE, TRUE};
+use winapi::shared::winerror::{E_FAIL, HRESULT, SUCCEEDED};
 use winapi::um::d2d1::{
     D2D1CreateFactory, ID2D1Bitmap, ID2D1Brush, ID2D1Geometry, ID2D1GeometrySink,
     ID2D1GradientStopCollection, ID2D1Image, ID2D1Layer, ID2D1PathGeometry, ID2D1SolidColorBrush,
-    ID2D1StrokeStyle, D2D1_ANTIALIAS_MODE_PER_PRIMITIVE, D2D1_BEZIER_SEGMENT,
-    D2D1_BITMAP_INTERPOLATION_MODE, D2D1_BRUSH_PROPERTIES, D2D1_COLOR_F, D2D1_DEBUG_LEVEL_WARNING,
-    D2D1_DRAW_TEXT_OPTIONS, D2D1_EXTEND_MODE_CLAMP, D2D1_FACTORY_OPTIONS,
-    D2D1_FACTORY_TYPE_MULTI_THREADED, D2D1_FIGURE_BEGIN_FILLED, D2D1_FIGURE_BEGIN_HOLLOW,
-    D2D1_FIGURE_END_CLOSED, D2D1_FIGURE_END_OPEN, D2D1_FILL_MODE_ALTERNATE, D2D1_FILL_MODE_WINDING,
-    D2D1_GAMMA_2_2, D2D1_GRADIENT_STOP, D2D1_LAYER_OPTIONS_NONE, D2D1_LAYER_PARAMETERS,
-    D2D1_LINEAR_GRADIENT_BRUSH_PROPERTIES, D2D1_MATRIX_3X2_F, D2D1_POINT_2F,
-    D2D1_QUADRATIC_BEZIER_SEGMENT, D2D1_RADIAL_GRADIENT_BRUSH_PROPERTIES, D2D1_RECT_F, D2D1_SIZE_F,
-    D2D1_SIZE_U, D2D1_STROKE_STYLE_PROPERTIES,
+    ID2D1StrokeStyle, D2D1_ANTIALIAS_MODE, D2D1_ANTIALIAS_MODE_PER_PRIMITIVE, D2D1_BEZIER_SEGMENT,
+    D2D1_BITMAP_BRUSH_PROPERTIES, D2D1_BITMAP_INTERPOLATION_MODE, D2D1_BRUSH_PROPERTIES,
+    D2D1_COLOR_F, D2D1_DEBUG_LEVEL_WARNING, D2D1_DRAW_TEXT_OPTIONS, D2D1_EXTEND_MODE_CLAMP,
+    D2D1_FACTORY_OPTIONS, D2D1_FACTORY_TYPE_MULTI_THREADED, D2D1_FIGURE_BEGIN_FILLED,
+    D2D1_FIGURE_BEGIN_HOLLOW, D2D1_FIGURE_END_CLOSED, D2D1_FIGURE_END_OPEN,
+    D2D1_FILL_MODE_ALTERNATE, D2D1_FILL_MODE_WINDING, D2D1_GAMMA_2_2, D2D1_GRADIENT_STOP,
+    D2D1_LAYER_OPTIONS_NONE, D2D1_LAYER_PARAMETERS, D2D1_LINEAR_GRADIENT_BRUSH_PROPERTIES,
+    D2D1_MATRIX_3X2_F, D2D1_POINT_2F, D2D1_POINT_2U, D2D1_QUADRATIC_BEZIER_SEGMENT,
+    D2D1_RADIAL_GRADIENT_BRUSH_PROPERTIES, D2D1_RECT_F, D2D1_RECT_U, D2D1_SIZE_F, D2D1_SIZE_U,
+    D2D1_STROKE_STYLE_PROPERTIES, D2D1_TEXT_ANTIALIAS_MODE,
 };
 use winapi::um::d2d1_1::{
-    ID2D1Bitmap1, ID2D1Device, ID2D1DeviceContext, ID2D1Factory1, D2D1_BITMAP_OPTIONS_NONE,
-    D2D1_BITMAP_OPTIONS_TARGET, D2D1_BITMAP_PROPERTIES1, D2D1_DEVICE_CONTEXT_OPTIONS_NONE,
+    ID2D1Bitmap1, ID2D1Device, ID2D1DeviceContext, ID2D1Effect, ID2D1Factory1,
+    D2D1_BITMAP_OPTIONS_NONE, D2D1_BITMAP_OPTIONS_TARGET, D2D1_BITMAP_PROPERTIES1,
+    D2D1_COMPOSITE_MODE_SOURCE_OVER, D2D1_DEVICE_CONTEXT_OPTIONS_NONE,
+    D2D1_INTERPOLATION_MODE_LINEAR, D2D1_PROPERTY_TYPE_FLOAT,
 };
-use winapi::um::dcommon::{D2D1_ALPHA_MODE, D2D1_ALPHA_MODE_PREMULTIPLIED, D2D1_PIXEL_FORMAT};
+use winapi::um::d2d1effects::{CLSID_D2D1GaussianBlur, D2D1_GAUSSIANBLUR_PROP_STANDARD_DEVIATION};
+use winapi::um::dcommon::{
+    D2D1_ALPHA_MODE, D2D1_ALPHA_MODE_PREMULTIPLIED, D2D1_PIXEL_FORMAT,
+    DWRITE_MEASURING_MODE_NATURAL,
+};
+use winapi::um::dwrite::{DWRITE_GLYPH_OFFSET, DWRITE_GLYPH_RUN};
+use winapi::um::unknwnbase::IUnknown;
 use winapi::Interface;
 
-use piet::{new_error, ErrorKind};
+use piet::{new_error, ErrorKind, LayerPool, MemoryCounters, MemoryReport, PooledSurface};
 
-use crate::dwrite::TextLayout;
+use crate::dwrite::{FontFace, TextLayout};
 
 pub enum FillRule {
     EvenOdd,
@@ -56,8 +66,8 @@ pub enum Error {
 /// that end-users directly use this struct.
 pub struct D2DFactory(ComPtr<ID2D1Factory1>);
 
-/// A Direct2D device.
-pub struct D2DDevice(ComPtr<ID2D1Device>);
+/// A Direct2D device, with the memory its contexts' resources hold.
+pub struct D2DDevice(ComPtr<ID2D1Device>, MemoryCounters);
 
 /// The main context that takes drawing operations.
 ///
@@ -66,7 +76,11 @@ pub struct D2DDevice(ComPtr<ID2D1Device>);
 ///
 /// This struct is public only to use for system integration in piet_common and druid-shell. It is not intended
 /// that end-users directly use this struct.
-pub struct DeviceContext(ComPtr<ID2D1DeviceContext>);
+pub struct DeviceContext(
+    ComPtr<ID2D1DeviceContext>,
+    LayerPool<Bitmap>,
+    MemoryCounters,
+);
 
 pub struct PathGeometry(ComPtr<ID2D1PathGeometry>);
 
@@ -93,6 +107,38 @@ pub struct Brush(ComPtr<ID2D1Brush>);
 
 pub struct Bitmap(ComPtr<ID2D1Bitmap1>);
 
+pub struct Effect(ComPtr<ID2D1Effect>);
+
+// Direct2D locks around every call into a multithreaded factory and the
+// resources made from it, so they can be used from any thread. A device
+// context draws for one thread at a time.
+unsafe impl Send for D2DFactory {}
+unsafe impl Sync for D2DFactory {}
+unsafe impl Send for D2DDevice {}
+unsafe impl Sync for D2DDevice {}
+unsafe impl Send for DeviceContext {}
+unsafe impl Send for PathGeometry {}
+unsafe impl Sync for PathGeometry {}
+unsafe impl Send for GradientStopCollection {}
+unsafe impl Sync for GradientStopCollection {}
+unsafe impl Send for StrokeStyle {}
+unsafe impl Sync for StrokeStyle {}
+unsafe impl Send for Brush {}
+unsafe impl Sync for Brush {}
+unsafe impl Send for Bitmap {}
+unsafe impl Sync for Bitmap {}
+
+impl Brush {
+    /// The brush as a DirectWrite drawing effect, which Direct2D paints
+    /// with in place of the brush passed to `draw_text_layout`.
+    pub(crate) fn as_drawing_effect(&self) -> *mut IUnknown {
+        self.0.as_raw() as *mut IUnknown
+    }
+}
+
+/// The image a device context draws into.
+pub(crate) struct Target(ComPtr<ID2D1Image>);
+
 impl From<HRESULT> for Error {
     fn from(hr: HRESULT) -> Error {
         Error::WinapiError(hr)
@@ -174,7 +220,7 @@ impl D2DFactory {
     pub unsafe fn create_device(&self, dxgi_device: *mut IDXGIDevice) -> Result<D2DDevice, Error> {
         let mut ptr = null_mut();
         let hr = self.0.CreateDevice(dxgi_device, &mut ptr);
-        wrap(hr, ptr, D2DDevice)
+        wrap(hr, ptr, |ptr| D2DDevice(ptr, MemoryCounters::new()))
     }
 
     /// Get the raw pointer
@@ -220,9 +266,25 @@ impl D2DDevice {
             let mut ptr = null_mut();
             let options = D2D1_DEVICE_CONTEXT_OPTIONS_NONE;
             let hr = self.0.CreateDeviceContext(options, &mut ptr);
-            wrap(hr, ptr, DeviceContext)
+            let memory = self.1.clone();
+            wrap(hr, ptr, |ptr| DeviceContext::with_memory(ptr, memory))
         }
     }
+
+    /// The approximate memory held by the images and layers of the device
+    /// contexts made from this device.
+    pub fn memory_report(&self) -> MemoryReport {
+        self.1.report()
+    }
+
+    /// Free the resources Direct2D keeps for the device, such as rasterized
+    /// glyphs, that haven't been used since the last frame.
+    ///
+    /// This is a wrapper for
+    /// [ID2D1Device::ClearResources](https://docs.microsoft.com/en-us/windows/win32/api/d2d1_1/nf-d2d1_1-id2d1device-clearresources).
+    pub fn clear_resources(&self) {
+        unsafe { self.0.ClearResources(0) }
+    }
 }
 
 const IDENTITY_MATRIX_3X2_F: D2D1_MATRIX_3X2_F = D2D1_MATRIX_3X2_F {
@@ -243,7 +305,29 @@ impl DeviceContext {
     /// # Safety
     /// TODO
     pub unsafe fn new(ptr: ComPtr<ID2D1DeviceContext>) -> DeviceContext {
-        DeviceContext(ptr)
+        DeviceContext::with_memory(ptr, MemoryCounters::new())
+    }
+
+    fn with_memory(ptr: ComPtr<ID2D1DeviceContext>, memory: MemoryCounters) -> DeviceContext {
+        let pool = LayerPool::with_memory(&memory.layers, 4);
+        DeviceContext(ptr, pool, memory)
+    }
+
+    /// The counters for the memory held by resources made with this context.
+    pub(crate) fn memory(&self) -> &MemoryCounters {
+        &self.2
+    }
+
+    /// Free the resources Direct2D keeps for the context's device.
+    pub(crate) fn clear_device_resources(&self) {
+        unsafe {
+            let mut ptr = null_mut();
+            self.0.GetDevice(&mut ptr);
+            if !ptr.is_null() {
+                let device: ComPtr<ID2D1Device> = ComPtr::from_raw(ptr);
+                device.ClearResources(0);
+            }
+        }
     }
 
     /// Get the raw pointer
@@ -298,6 +382,131 @@ impl DeviceContext {
         unsafe { self.0.SetTarget(target.0.as_raw() as *mut ID2D1Image) }
     }
 
+    /// The current target, to be put back with [`restore_target`] later.
+    ///
+    /// [`restore_target`]: #method.restore_target
+    pub(crate) fn get_target(&self) -> Option<Target> {
+        unsafe {
+            let mut ptr = null_mut();
+            self.0.GetTarget(&mut ptr);
+            if ptr.is_null() {
+                None
+            } else {
+                Some(Target(ComPtr::from_raw(ptr)))
+            }
+        }
+    }
+
+    pub(crate) fn restore_target(&mut self, target: Option<Target>) {
+        let ptr = target.as_ref().map(|t| t.0.as_raw()).unwrap_or(null_mut());
+        unsafe { self.0.SetTarget(ptr) }
+    }
+
+    /// Create a bitmap that can be used as the target of this context.
+    ///
+    /// The bitmap is 96 dpi, so that one device-independent pixel is one
+    /// pixel while drawing into it.
+    pub(crate) fn create_target_bitmap(
+        &mut self,
+        width: usize,
+        height: usize,
+    ) -> Result<Bitmap, Error> {
+        let size = D2D1_SIZE_U {
+            width: width as u32,
+            height: height as u32,
+        };
+        let props = D2D1_BITMAP_PROPERTIES1 {
+            pixelFormat: D2D1_PIXEL_FORMAT {
+                format: DXGI_FORMAT_R8G8B8A8_UNORM,
+                alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+            },
+            dpiX: 96.0,
+            dpiY: 96.0,
+            bitmapOptions: D2D1_BITMAP_OPTIONS_TARGET,
+            colorContext: null_mut(),
+        };
+        unsafe {
+            let mut ptr = null_mut();
+            let hr = self
+                .0
+                .deref()
+                .CreateBitmap(size, null(), 0, &props, &mut ptr);
+            wrap(hr, ptr, Bitmap)
+        }
+    }
+
+    /// Create a bitmap that can stand in for the current target, with the
+    /// same size in pixels and the same dpi.
+    pub(crate) fn create_layer_bitmap(&mut self) -> Result<Bitmap, Error> {
+        let size = unsafe { self.0.deref().deref().GetPixelSize() };
+        create_target_bitmap(&self.0, size, self.get_dpi())
+    }
+
+    /// Take a bitmap from the layer pool that can stand in for the current
+    /// target, at least as large and at the same dpi.
+    ///
+    /// The bitmap holds whatever was last drawn into it; give it back with
+    /// `give_back_layer_bitmap` when done.
+    pub(crate) fn take_layer_bitmap(&mut self) -> Result<PooledSurface<Bitmap>, Error> {
+        let size = unsafe { self.0.deref().deref().GetPixelSize() };
+        let dpi = self.get_dpi();
+        let (width, height) = (size.width as usize, size.height as usize);
+        let make = |ctx: &ComPtr<ID2D1DeviceContext>, w: usize, h: usize| {
+            let size = D2D1_SIZE_U {
+                width: w as u32,
+                height: h as u32,
+            };
+            create_target_bitmap(ctx, size, dpi)
+        };
+        let DeviceContext(ctx, pool, _) = self;
+        let pooled = pool.take(width, height, |w, h| make(ctx, w, h))?;
+        if pooled.surface().get_dpi() == dpi {
+            return Ok(pooled);
+        }
+        // pooled bitmaps were made for another dpi, so none will do
+        pool.trim();
+        pool.take(width, height, |w, h| make(ctx, w, h))
+    }
+
+    pub(crate) fn give_back_layer_bitmap(&mut self, bitmap: PooledSurface<Bitmap>) {
+        self.1.give_back(bitmap);
+    }
+
+    pub(crate) fn trim_layer_pool(&mut self) {
+        self.1.trim();
+    }
+
+    fn get_dpi(&self) -> (f32, f32) {
+        let (mut dpi_x, mut dpi_y) = (0.0, 0.0);
+        unsafe { self.0.GetDpi(&mut dpi_x, &mut dpi_y) };
+        (dpi_x, dpi_y)
+    }
+
+    /// Create a Gaussian blur of `input`, with the standard deviation in
+    /// device-independent pixels.
+    pub(crate) fn create_gaussian_blur(
+        &mut self,
+        input: &Bitmap,
+        std_dev: f32,
+    ) -> Result<Effect, Error> {
+        unsafe {
+            let mut ptr = null_mut();
+            let hr = self.0.CreateEffect(&CLSID_D2D1GaussianBlur, &mut ptr);
+            let effect = wrap(hr, ptr, Effect)?;
+            effect
+                .0
+                .SetInput(0, input.0.as_raw() as *const ID2D1Image, TRUE);
+            let hr = effect.0.SetValue(
+                D2D1_GAUSSIANBLUR_PROP_STANDARD_DEVIATION,
+                D2D1_PROPERTY_TYPE_FLOAT,
+                &std_dev as *const f32 as *const BYTE,
+                std::mem::size_of::<f32>() as u32,
+            );
+            wrap_unit(hr)?;
+            Ok(effect)
+        }
+    }
+
     /// Set the dpi scale.
     ///
     /// Mostly useful when rendering into bitmaps.
@@ -335,6 +544,34 @@ impl DeviceContext {
         }
     }
 
+    pub(crate) fn push_axis_aligned_clip(&mut self, rect: &D2D1_RECT_F, mode: D2D1_ANTIALIAS_MODE) {
+        unsafe {
+            self.0.PushAxisAlignedClip(rect, mode);
+        }
+    }
+
+    pub(crate) fn pop_axis_aligned_clip(&mut self) {
+        unsafe {
+            self.0.PopAxisAlignedClip();
+        }
+    }
+
+    pub(crate) fn set_antialias_mode(&mut self, mode: D2D1_ANTIALIAS_MODE) {
+        unsafe {
+            self.0.SetAntialiasMode(mode);
+        }
+    }
+
+    pub(crate) fn get_antialias_mode(&self) -> D2D1_ANTIALIAS_MODE {
+        unsafe { self.0.GetAntialiasMode() }
+    }
+
+    pub(crate) fn set_text_antialias_mode(&mut self, mode: D2D1_TEXT_ANTIALIAS_MODE) {
+        unsafe {
+            self.0.SetTextAntialiasMode(mode);
+        }
+    }
+
     pub(crate) fn set_transform(&mut self, transform: &D2D1_MATRIX_3X2_F) {
         unsafe {
             self.0.SetTransform(transform);
@@ -385,7 +622,12 @@ impl DeviceContext {
     }
 
     // Should be &mut layer?
-    pub(crate) fn push_layer_mask(&mut self, mask: &PathGeometry, layer: &Layer) {
+    pub(crate) fn push_layer_mask(
+        &mut self,
+        mask: &PathGeometry,
+        layer: &Layer,
+        antialias: D2D1_ANTIALIAS_MODE,
+    ) {
         unsafe {
             let params = D2D1_LAYER_PARAMETERS {
                 contentBounds: D2D1_RECT_F {
@@ -395,7 +637,7 @@ impl DeviceContext {
                     bottom: std::f32::INFINITY,
                 },
                 geometricMask: mask.0.as_raw() as *mut ID2D1Geometry,
-                maskAntialiasMode: D2D1_ANTIALIAS_MODE_PER_PRIMITIVE,
+                maskAntialiasMode: antialias,
                 maskTransform: IDENTITY_MATRIX_3X2_F,
                 opacity: 1.0,
                 opacityBrush: null_mut(),
@@ -405,6 +647,28 @@ impl DeviceContext {
         }
     }
 
+    /// Push a layer that lets through what's drawn in it with the opacity
+    /// of `brush`.
+    pub(crate) fn push_layer_opacity(&mut self, brush: &Brush, layer: &Layer) {
+        unsafe {
+            let params = D2D1_LAYER_PARAMETERS {
+                contentBounds: D2D1_RECT_F {
+                    left: std::f32::NEG_INFINITY,
+                    top: std::f32::NEG_INFINITY,
+                    right: std::f32::INFINITY,
+                    bottom: std::f32::INFINITY,
+                },
+                geometricMask: null_mut(),
+                maskAntialiasMode: D2D1_ANTIALIAS_MODE_PER_PRIMITIVE,
+                maskTransform: IDENTITY_MATRIX_3X2_F,
+                opacity: 1.0,
+                opacityBrush: brush.0.as_raw(),
+                layerOptions: D2D1_LAYER_OPTIONS_NONE,
+            };
+            self.0.deref().deref().PushLayer(&params, layer.0.as_raw());
+        }
+    }
+
     pub(crate) fn pop_layer(&mut self) {
         unsafe {
             self.0.PopLayer();
@@ -421,6 +685,35 @@ impl DeviceContext {
         }
     }
 
+    /// A brush painting `bitmap` with `transform` from its pixels to user
+    /// space, clamped at the edges.
+    pub(crate) fn create_bitmap_brush(
+        &mut self,
+        bitmap: &Bitmap,
+        interp: D2D1_BITMAP_INTERPOLATION_MODE,
+        transform: D2D1_MATRIX_3X2_F,
+    ) -> Result<Brush, Error> {
+        let bitmap_props = D2D1_BITMAP_BRUSH_PROPERTIES {
+            extendModeX: D2D1_EXTEND_MODE_CLAMP,
+            extendModeY: D2D1_EXTEND_MODE_CLAMP,
+            interpolationMode: interp,
+        };
+        let brush_props = D2D1_BRUSH_PROPERTIES {
+            opacity: 1.0,
+            transform,
+        };
+        unsafe {
+            let mut ptr = null_mut();
+            let hr = self.0.deref().deref().CreateBitmapBrush(
+                bitmap.0.as_raw() as *mut ID2D1Bitmap,
+                &bitmap_props,
+                &brush_props,
+                &mut ptr,
+            );
+            wrap(hr, ptr, |p| Brush(p.up()))
+        }
+    }
+
     pub(crate) fn create_gradient_stops(
         &mut self,
         stops: &[D2D1_GRADIENT_STOP],
@@ -483,6 +776,7 @@ impl DeviceContext {
         width: usize,
         height: usize,
         buf: &[u8],
+        format: DXGI_FORMAT,
         alpha_mode: D2D1_ALPHA_MODE,
     ) -> Result<Bitmap, Error> {
         // Maybe using TryInto would be more Rust-like.
@@ -493,8 +787,9 @@ impl DeviceContext {
             width: width as u32,
             height: height as u32,
         };
+        let bytes_per_pixel = if format == DXGI_FORMAT_A8_UNORM { 1 } else { 4 };
         let format = D2D1_PIXEL_FORMAT {
-            format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            format,
             alphaMode: alpha_mode,
         };
         let props = D2D1_BITMAP_PROPERTIES1 {
@@ -504,7 +799,7 @@ impl DeviceContext {
             bitmapOptions: D2D1_BITMAP_OPTIONS_NONE,
             colorContext: null_mut(),
         };
-        let pitch = (width * 4) as u32;
+        let pitch = (width * bytes_per_pixel) as u32;
         unsafe {
             let mut ptr = null_mut();
             let hr = self.0.deref().CreateBitmap(
@@ -518,6 +813,48 @@ impl DeviceContext {
         }
     }
 
+    /// Copy an area of the current target into a new bitmap.
+    ///
+    /// The area is in device-independent pixels, and is converted to target
+    /// pixels using the context's dpi. Pending drawing is flushed first.
+    pub(crate) fn copy_target_area(&mut self, area: D2D1_RECT_F) -> Result<Bitmap, Error> {
+        unsafe {
+            let mut target = null_mut();
+            self.0.GetTarget(&mut target);
+            if target.is_null() {
+                return Err(E_FAIL.into());
+            }
+            let target = ComPtr::from_raw(target).cast::<ID2D1Bitmap1>()?;
+            let (mut dpi_x, mut dpi_y) = (0.0, 0.0);
+            self.0.GetDpi(&mut dpi_x, &mut dpi_y);
+            let (scale_x, scale_y) = (dpi_x / 96.0, dpi_y / 96.0);
+            let src = D2D1_RECT_U {
+                left: (area.left * scale_x).floor().max(0.0) as u32,
+                top: (area.top * scale_y).floor().max(0.0) as u32,
+                right: (area.right * scale_x).ceil().max(0.0) as u32,
+                bottom: (area.bottom * scale_y).ceil().max(0.0) as u32,
+            };
+            let width = src.right.saturating_sub(src.left) as usize;
+            let height = src.bottom.saturating_sub(src.top) as usize;
+            let bitmap = self.create_bitmap(
+                width,
+                height,
+                &vec![0; width * height * 4],
+                DXGI_FORMAT_R8G8B8A8_UNORM,
+                D2D1_ALPHA_MODE_PREMULTIPLIED,
+            )?;
+            let hr = self.0.deref().deref().Flush(null_mut(), null_mut());
+            wrap_unit(hr)?;
+            let hr = bitmap.0.CopyFromBitmap(
+                &D2D1_POINT_2U { x: 0, y: 0 },
+                target.as_raw() as *mut ID2D1Bitmap,
+                &src,
+            );
+            wrap_unit(hr)?;
+            Ok(bitmap)
+        }
+    }
+
     pub(crate) fn draw_text_layout(
         &mut self,
         origin: D2D1_POINT_2F,
@@ -531,6 +868,53 @@ impl DeviceContext {
         }
     }
 
+    /// Draw glyphs of `face` at `size`, each placed by its offset from
+    /// `origin`, with no advances between them.
+    pub(crate) fn draw_glyph_run(
+        &mut self,
+        origin: D2D1_POINT_2F,
+        face: &FontFace,
+        size: f32,
+        indices: &[u16],
+        offsets: &[DWRITE_GLYPH_OFFSET],
+        brush: &Brush,
+    ) {
+        let advances = vec![0.0; indices.len()];
+        let run = DWRITE_GLYPH_RUN {
+            fontFace: face.get_raw(),
+            fontEmSize: size,
+            glyphCount: indices.len() as u32,
+            glyphIndices: indices.as_ptr(),
+            glyphAdvances: advances.as_ptr(),
+            glyphOffsets: offsets.as_ptr(),
+            isSideways: FALSE,
+            bidiLevel: 0,
+        };
+        unsafe {
+            self.0.DrawGlyphRun(
+                origin,
+                &run,
+                brush.0.as_raw(),
+                DWRITE_MEASURING_MODE_NATURAL,
+            );
+        }
+    }
+
+    pub(crate) fn draw_effect(&mut self, effect: &Effect) {
+        unsafe {
+            let mut output = null_mut();
+            effect.0.GetOutput(&mut output);
+            let output = ComPtr::from_raw(output);
+            self.0.DrawImage(
+                output.as_raw(),
+                null(),
+                null(),
+                D2D1_INTERPOLATION_MODE_LINEAR,
+                D2D1_COMPOSITE_MODE_SOURCE_OVER,
+            );
+        }
+    }
+
     pub(crate) fn draw_bitmap(
         &mut self,
         bitmap: &Bitmap,
@@ -641,6 +1025,47 @@ impl Bitmap {
     pub fn get_size(&self) -> D2D1_SIZE_F {
         unsafe { self.0.GetSize() }
     }
+
+    /// The approximate memory the bitmap's pixels hold.
+    pub(crate) fn bytes(&self) -> usize {
+        unsafe {
+            let size = self.0.GetPixelSize();
+            let bytes_per_pixel = match self.0.GetPixelFormat().format {
+                DXGI_FORMAT_A8_UNORM => 1,
+                _ => 4,
+            };
+            size.width as usize * size.height as usize * bytes_per_pixel
+        }
+    }
+
+    fn get_dpi(&self) -> (f32, f32) {
+        let (mut dpi_x, mut dpi_y) = (0.0, 0.0);
+        unsafe { self.0.GetDpi(&mut dpi_x, &mut dpi_y) };
+        (dpi_x, dpi_y)
+    }
+}
+
+/// Create a premultiplied bitmap that `ctx` can draw into.
+fn create_target_bitmap(
+    ctx: &ComPtr<ID2D1DeviceContext>,
+    size: D2D1_SIZE_U,
+    (dpi_x, dpi_y): (f32, f32),
+) -> Result<Bitmap, Error> {
+    let props = D2D1_BITMAP_PROPERTIES1 {
+        pixelFormat: D2D1_PIXEL_FORMAT {
+            format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+        },
+        dpiX: dpi_x,
+        dpiY: dpi_y,
+        bitmapOptions: D2D1_BITMAP_OPTIONS_TARGET,
+        colorContext: null_mut(),
+    };
+    unsafe {
+        let mut ptr = null_mut();
+        let hr = ctx.deref().CreateBitmap(size, null(), 0, &props, &mut ptr);
+        wrap(hr, ptr, Bitmap)
+    }
 }
 
 mod tests {
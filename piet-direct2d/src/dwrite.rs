@@ -7,18 +7,32 @@ use std::fmt::{Debug, Display, Formatter};
 use std::mem::MaybeUninit;
 use std::ptr::null_mut;
 
+use winapi::shared::minwindef::FALSE;
 use winapi::shared::winerror::{HRESULT, SUCCEEDED, S_OK};
 use winapi::um::dwrite::{
-    DWriteCreateFactory, IDWriteFactory, IDWriteTextFormat, IDWriteTextLayout,
-    DWRITE_FACTORY_TYPE_SHARED, DWRITE_FONT_STRETCH_NORMAL, DWRITE_FONT_STYLE_NORMAL,
-    DWRITE_FONT_WEIGHT_NORMAL, DWRITE_HIT_TEST_METRICS, DWRITE_LINE_METRICS, DWRITE_TEXT_METRICS,
+    DWriteCreateFactory, IDWriteFactory, IDWriteFont, IDWriteFontCollection, IDWriteFontFace,
+    IDWriteFontFamily, IDWriteTextFormat, IDWriteTextLayout, IDWriteTypography,
+    DWRITE_FACTORY_TYPE_SHARED, DWRITE_FLOW_DIRECTION_RIGHT_TO_LEFT, DWRITE_FONT_FEATURE,
+    DWRITE_FONT_METRICS, DWRITE_FONT_STRETCH, DWRITE_FONT_STRETCH_CONDENSED,
+    DWRITE_FONT_STRETCH_EXPANDED, DWRITE_FONT_STRETCH_EXTRA_CONDENSED,
+    DWRITE_FONT_STRETCH_EXTRA_EXPANDED, DWRITE_FONT_STRETCH_NORMAL,
+    DWRITE_FONT_STRETCH_SEMI_CONDENSED, DWRITE_FONT_STRETCH_SEMI_EXPANDED,
+    DWRITE_FONT_STRETCH_ULTRA_CONDENSED, DWRITE_FONT_STRETCH_ULTRA_EXPANDED, DWRITE_FONT_STYLE,
+    DWRITE_FONT_STYLE_ITALIC, DWRITE_FONT_STYLE_NORMAL, DWRITE_FONT_STYLE_OBLIQUE,
+    DWRITE_FONT_WEIGHT, DWRITE_FONT_WEIGHT_NORMAL, DWRITE_HIT_TEST_METRICS, DWRITE_LINE_METRICS,
+    DWRITE_READING_DIRECTION_RIGHT_TO_LEFT, DWRITE_READING_DIRECTION_TOP_TO_BOTTOM,
+    DWRITE_TEXT_ALIGNMENT_CENTER, DWRITE_TEXT_ALIGNMENT_JUSTIFIED, DWRITE_TEXT_ALIGNMENT_LEADING,
+    DWRITE_TEXT_ALIGNMENT_TRAILING, DWRITE_TEXT_METRICS, DWRITE_TEXT_RANGE,
+    DWRITE_WORD_WRAPPING_NO_WRAP, DWRITE_WORD_WRAPPING_WRAP,
 };
+use winapi::um::dwrite_1::IDWriteTextLayout1;
+use winapi::um::unknwnbase::IUnknown;
 use winapi::Interface;
 
 use wio::com::ComPtr;
 use wio::wide::ToWide;
 
-use piet::{new_error, ErrorKind};
+use piet::{new_error, ErrorKind, FontFeature, FontStretch, FontStyle, FontWeight, TextAlignment};
 
 // TODO: minimize cut'n'paste; probably the best way to do this is
 // unify with the crate error type
@@ -41,6 +55,9 @@ pub struct TextFormatBuilder<'a> {
     factory: &'a DwriteFactory,
     size: Option<f32>,
     family: Option<&'a str>,
+    weight: FontWeight,
+    style: FontStyle,
+    stretch: FontStretch,
 }
 
 pub struct TextLayoutBuilder<'a> {
@@ -53,6 +70,26 @@ pub struct TextLayoutBuilder<'a> {
 
 pub struct TextLayout(ComPtr<IDWriteTextLayout>);
 
+/// A face of an installed font family.
+#[derive(Clone)]
+pub struct Font(ComPtr<IDWriteFont>);
+
+/// The glyphs and metrics of a [`Font`], for drawing glyphs by id.
+pub struct FontFace(ComPtr<IDWriteFontFace>);
+
+// The shared factory and the formats and fonts from it are free-threaded,
+// and never changed once made. A layout is changed as it's built, so only
+// one thread can have it at a time.
+unsafe impl Send for DwriteFactory {}
+unsafe impl Sync for DwriteFactory {}
+unsafe impl Send for TextFormat {}
+unsafe impl Sync for TextFormat {}
+unsafe impl Send for Font {}
+unsafe impl Sync for Font {}
+unsafe impl Send for FontFace {}
+unsafe impl Sync for FontFace {}
+unsafe impl Send for TextLayout {}
+
 impl From<HRESULT> for Error {
     fn from(hr: HRESULT) -> Error {
         Error::WinapiError(hr)
@@ -116,6 +153,61 @@ impl DwriteFactory {
         self.0.as_raw()
     }
 
+    /// Whether a family with this name is installed on the system.
+    pub fn has_system_font_family(&self, family: &str) -> Result<bool, Error> {
+        let family = family.to_wide_null();
+        unsafe {
+            let mut ptr: *mut IDWriteFontCollection = null_mut();
+            let hr = self.0.GetSystemFontCollection(&mut ptr, FALSE);
+            let collection = wrap(hr, ptr, |c| c)?;
+            let mut index = 0;
+            let mut exists = FALSE;
+            let hr = collection.FindFamilyName(family.as_ptr(), &mut index, &mut exists);
+            if SUCCEEDED(hr) {
+                Ok(exists != FALSE)
+            } else {
+                Err(hr.into())
+            }
+        }
+    }
+
+    /// The installed face of `family` closest to a weight, style and
+    /// stretch, or `None` if the family isn't installed.
+    pub fn system_font(
+        &self,
+        family: &str,
+        weight: FontWeight,
+        style: FontStyle,
+        stretch: FontStretch,
+    ) -> Result<Option<Font>, Error> {
+        let family = family.to_wide_null();
+        unsafe {
+            let mut ptr: *mut IDWriteFontCollection = null_mut();
+            let hr = self.0.GetSystemFontCollection(&mut ptr, FALSE);
+            let collection = wrap(hr, ptr, |c| c)?;
+            let mut index = 0;
+            let mut exists = FALSE;
+            let hr = collection.FindFamilyName(family.as_ptr(), &mut index, &mut exists);
+            if !SUCCEEDED(hr) {
+                return Err(hr.into());
+            }
+            if exists == FALSE {
+                return Ok(None);
+            }
+            let mut ptr: *mut IDWriteFontFamily = null_mut();
+            let hr = collection.GetFontFamily(index, &mut ptr);
+            let font_family = wrap(hr, ptr, |f| f)?;
+            let mut ptr: *mut IDWriteFont = null_mut();
+            let hr = font_family.GetFirstMatchingFont(
+                font_weight(weight),
+                font_stretch(stretch),
+                font_style(style),
+                &mut ptr,
+            );
+            wrap(hr, ptr, Font).map(Some)
+        }
+    }
+
     /// Create from raw pointer
     ///
     /// # Safety
@@ -131,6 +223,9 @@ impl<'a> TextFormatBuilder<'a> {
             factory,
             size: None,
             family: None,
+            weight: FontWeight::NORMAL,
+            style: FontStyle::Normal,
+            stretch: FontStretch::Normal,
         }
     }
 
@@ -144,6 +239,21 @@ impl<'a> TextFormatBuilder<'a> {
         self
     }
 
+    pub fn weight(mut self, weight: FontWeight) -> TextFormatBuilder<'a> {
+        self.weight = weight;
+        self
+    }
+
+    pub fn style(mut self, style: FontStyle) -> TextFormatBuilder<'a> {
+        self.style = style;
+        self
+    }
+
+    pub fn stretch(mut self, stretch: FontStretch) -> TextFormatBuilder<'a> {
+        self.stretch = stretch;
+        self
+    }
+
     pub fn build(self) -> Result<TextFormat, Error> {
         let family = self
             .family
@@ -156,9 +266,9 @@ impl<'a> TextFormatBuilder<'a> {
             let hr = self.factory.0.CreateTextFormat(
                 family.as_ptr(),
                 null_mut(), // collection
-                DWRITE_FONT_WEIGHT_NORMAL,
-                DWRITE_FONT_STYLE_NORMAL,
-                DWRITE_FONT_STRETCH_NORMAL,
+                font_weight(self.weight),
+                font_style(self.style),
+                font_stretch(self.stretch),
                 size,
                 locale.as_ptr(),
                 &mut ptr,
@@ -227,6 +337,47 @@ impl<'a> TextLayoutBuilder<'a> {
 #[allow(clippy::unreadable_literal)]
 const E_NOT_SUFFICIENT_BUFFER: HRESULT = 0x8007007A;
 
+impl Font {
+    pub fn metrics(&self) -> DWRITE_FONT_METRICS {
+        unsafe {
+            let mut metrics = MaybeUninit::uninit();
+            self.0.GetMetrics(metrics.as_mut_ptr());
+            metrics.assume_init()
+        }
+    }
+
+    /// Whether the face has a glyph for `c`.
+    pub fn has_character(&self, c: char) -> Result<bool, Error> {
+        let mut exists = FALSE;
+        let hr = unsafe { self.0.HasCharacter(c as u32, &mut exists) };
+        if SUCCEEDED(hr) {
+            Ok(exists != FALSE)
+        } else {
+            Err(hr.into())
+        }
+    }
+
+    pub fn create_face(&self) -> Result<FontFace, Error> {
+        unsafe {
+            let mut ptr = null_mut();
+            let hr = self.0.CreateFontFace(&mut ptr);
+            wrap(hr, ptr, FontFace)
+        }
+    }
+}
+
+impl FontFace {
+    pub fn get_raw(&self) -> *mut IDWriteFontFace {
+        self.0.as_raw()
+    }
+}
+
+impl TextFormat {
+    pub fn get_font_size(&self) -> f32 {
+        unsafe { self.0.GetFontSize() }
+    }
+}
+
 impl TextLayout {
     /// Get line metrics, storing them in the provided buffer.
     ///
@@ -257,6 +408,192 @@ impl TextLayout {
         self.0.as_raw()
     }
 
+    /// Set the drawing effect for a range of utf-16 positions; a null
+    /// `effect` clears it.
+    ///
+    /// The layout holds a reference to the effect until it's replaced.
+    pub fn set_drawing_effect(&self, effect: *mut IUnknown, position: u32, length: u32) {
+        unsafe {
+            self.0
+                .SetDrawingEffect(effect, text_range(position, length));
+        }
+    }
+
+    /// Set how the lines are placed across the layout's width.
+    ///
+    /// DirectWrite leaves the last line of each paragraph at the start when
+    /// justifying, as `TextAlignment::Justified` asks. Its alignments follow
+    /// the reading direction, so `rtl` says whether the layout reads right to
+    /// left, for the start to stay on the left.
+    pub fn set_text_alignment(&self, alignment: TextAlignment, rtl: bool) {
+        let alignment = match alignment {
+            TextAlignment::Start if rtl => DWRITE_TEXT_ALIGNMENT_TRAILING,
+            TextAlignment::Start => DWRITE_TEXT_ALIGNMENT_LEADING,
+            TextAlignment::End if rtl => DWRITE_TEXT_ALIGNMENT_LEADING,
+            TextAlignment::End => DWRITE_TEXT_ALIGNMENT_TRAILING,
+            TextAlignment::Center => DWRITE_TEXT_ALIGNMENT_CENTER,
+            TextAlignment::Justified => DWRITE_TEXT_ALIGNMENT_JUSTIFIED,
+        };
+        unsafe {
+            self.0.SetTextAlignment(alignment);
+        }
+    }
+
+    /// Read the text right to left, which orders runs of mixed directions
+    /// as a right-to-left paragraph does.
+    pub fn set_right_to_left(&self) {
+        unsafe {
+            self.0
+                .SetReadingDirection(DWRITE_READING_DIRECTION_RIGHT_TO_LEFT);
+        }
+    }
+
+    /// Set the text in columns read top to bottom, flowing right to left,
+    /// which wrap at `column_length`.
+    ///
+    /// DirectWrite turns horizontal scripts a quarter turn and keeps East
+    /// Asian ones upright by default. Vertical reading directions need
+    /// Windows 8.1, and are refused before it.
+    pub fn set_vertical(&self, column_length: f32) -> Result<(), Error> {
+        unsafe {
+            let hr = self
+                .0
+                .SetReadingDirection(DWRITE_READING_DIRECTION_TOP_TO_BOTTOM);
+            if !SUCCEEDED(hr) {
+                return Err(hr.into());
+            }
+            let hr = self.0.SetFlowDirection(DWRITE_FLOW_DIRECTION_RIGHT_TO_LEFT);
+            if !SUCCEEDED(hr) {
+                return Err(hr.into());
+            }
+            self.0.SetWordWrapping(DWRITE_WORD_WRAPPING_WRAP);
+            self.0.SetMaxWidth(1e6);
+            self.0.SetMaxHeight(column_length);
+        }
+        Ok(())
+    }
+
+    /// Whether the text is read top to bottom.
+    pub fn is_vertical(&self) -> bool {
+        unsafe { self.0.GetReadingDirection() == DWRITE_READING_DIRECTION_TOP_TO_BOTTOM }
+    }
+
+    /// Set the size of the box the text is laid out in.
+    pub fn set_size(&self, width: f32, height: f32) {
+        unsafe {
+            self.0.SetMaxWidth(width);
+            self.0.SetMaxHeight(height);
+        }
+    }
+
+    /// Set the width lines are placed across, without wrapping them to it.
+    pub fn set_unwrapped_width(&self, width: f32) {
+        unsafe {
+            self.0.SetWordWrapping(DWRITE_WORD_WRAPPING_NO_WRAP);
+            self.0.SetMaxWidth(width);
+        }
+    }
+
+    /// Set the font size for a range of utf-16 positions.
+    pub fn set_font_size(&self, size: f32, position: u32, length: u32) {
+        unsafe {
+            self.0.SetFontSize(size, text_range(position, length));
+        }
+    }
+
+    /// Set the font family for a range of utf-16 positions.
+    pub fn set_font_family(&self, family: &str, position: u32, length: u32) {
+        let family = family.to_wide_null();
+        unsafe {
+            self.0
+                .SetFontFamilyName(family.as_ptr(), text_range(position, length));
+        }
+    }
+
+    /// Set the font weight for a range of utf-16 positions.
+    pub fn set_font_weight(&self, weight: FontWeight, position: u32, length: u32) {
+        unsafe {
+            self.0
+                .SetFontWeight(font_weight(weight), text_range(position, length));
+        }
+    }
+
+    /// Set whether a range of utf-16 positions is italic.
+    pub fn set_italic(&self, italic: bool, position: u32, length: u32) {
+        let style = if italic {
+            DWRITE_FONT_STYLE_ITALIC
+        } else {
+            DWRITE_FONT_STYLE_NORMAL
+        };
+        unsafe {
+            self.0.SetFontStyle(style, text_range(position, length));
+        }
+    }
+
+    /// Set whether a range of utf-16 positions is underlined.
+    pub fn set_underline(&self, underline: bool, position: u32, length: u32) {
+        unsafe {
+            self.0
+                .SetUnderline(underline.into(), text_range(position, length));
+        }
+    }
+
+    /// Set whether a range of utf-16 positions is struck through.
+    pub fn set_strikethrough(&self, strikethrough: bool, position: u32, length: u32) {
+        unsafe {
+            self.0
+                .SetStrikethrough(strikethrough.into(), text_range(position, length));
+        }
+    }
+
+    /// Set the space added after each cluster in a range of utf-16
+    /// positions.
+    ///
+    /// This needs DirectWrite 1.1, from Windows 8; before it, the text isn't
+    /// spaced.
+    pub fn set_character_spacing(&self, trailing: f32, position: u32, length: u32) {
+        if let Ok(layout) = self.0.cast::<IDWriteTextLayout1>() {
+            unsafe {
+                layout.SetCharacterSpacing(0.0, trailing, 0.0, text_range(position, length));
+            }
+        }
+    }
+
+    /// Set the OpenType features of a range of the text, in utf-16, in place
+    /// of any set on it before.
+    pub fn set_font_features(
+        &self,
+        factory: &DwriteFactory,
+        features: &[FontFeature],
+        position: u32,
+        length: u32,
+    ) -> Result<(), Error> {
+        unsafe {
+            let mut ptr: *mut IDWriteTypography = null_mut();
+            let hr = factory.0.CreateTypography(&mut ptr);
+            if !SUCCEEDED(hr) {
+                return Err(hr.into());
+            }
+            let typography = ComPtr::from_raw(ptr);
+            for feature in features {
+                let hr = typography.AddFontFeature(DWRITE_FONT_FEATURE {
+                    nameTag: u32::from_le_bytes(feature.tag()),
+                    parameter: feature.value(),
+                });
+                if !SUCCEEDED(hr) {
+                    return Err(hr.into());
+                }
+            }
+            let hr = self
+                .0
+                .SetTypography(typography.as_raw(), text_range(position, length));
+            if !SUCCEEDED(hr) {
+                return Err(hr.into());
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_metrics(&self) -> DWRITE_TEXT_METRICS {
         unsafe {
             let mut result = std::mem::zeroed();
@@ -286,6 +623,41 @@ impl TextLayout {
         }
     }
 
+    /// Get the regions covered by a range of text.
+    ///
+    /// There can be more than one region when the range spans several lines
+    /// or bidi runs.
+    pub fn hit_test_text_range(&self, position: u32, length: u32) -> Vec<HitTestMetrics> {
+        unsafe {
+            let mut actual_count = 0;
+            let mut hr = self.0.HitTestTextRange(
+                position,
+                length,
+                0.0,
+                0.0,
+                null_mut(),
+                0,
+                &mut actual_count,
+            );
+            let mut buf = Vec::with_capacity(actual_count as usize);
+            if hr == E_NOT_SUFFICIENT_BUFFER {
+                hr = self.0.HitTestTextRange(
+                    position,
+                    length,
+                    0.0,
+                    0.0,
+                    buf.as_mut_ptr(),
+                    actual_count,
+                    &mut actual_count,
+                );
+            }
+            if SUCCEEDED(hr) {
+                buf.set_len(actual_count as usize);
+            }
+            buf.into_iter().map(HitTestMetrics::from).collect()
+        }
+    }
+
     pub fn hit_test_text_position(
         &self,
         position: u32,
@@ -378,3 +750,37 @@ impl From<DWRITE_HIT_TEST_METRICS> for HitTestMetrics {
         }
     }
 }
+
+fn text_range(position: u32, length: u32) -> DWRITE_TEXT_RANGE {
+    DWRITE_TEXT_RANGE {
+        startPosition: position,
+        length,
+    }
+}
+
+/// DirectWrite's weights stop at 999.
+fn font_weight(weight: FontWeight) -> DWRITE_FONT_WEIGHT {
+    weight.to_raw().min(999).into()
+}
+
+fn font_style(style: FontStyle) -> DWRITE_FONT_STYLE {
+    match style {
+        FontStyle::Normal => DWRITE_FONT_STYLE_NORMAL,
+        FontStyle::Italic => DWRITE_FONT_STYLE_ITALIC,
+        FontStyle::Oblique => DWRITE_FONT_STYLE_OBLIQUE,
+    }
+}
+
+fn font_stretch(stretch: FontStretch) -> DWRITE_FONT_STRETCH {
+    match stretch {
+        FontStretch::UltraCondensed => DWRITE_FONT_STRETCH_ULTRA_CONDENSED,
+        FontStretch::ExtraCondensed => DWRITE_FONT_STRETCH_EXTRA_CONDENSED,
+        FontStretch::Condensed => DWRITE_FONT_STRETCH_CONDENSED,
+        FontStretch::SemiCondensed => DWRITE_FONT_STRETCH_SEMI_CONDENSED,
+        FontStretch::Normal => DWRITE_FONT_STRETCH_NORMAL,
+        FontStretch::SemiExpanded => DWRITE_FONT_STRETCH_SEMI_EXPANDED,
+        FontStretch::Expanded => DWRITE_FONT_STRETCH_EXPANDED,
+        FontStretch::ExtraExpanded => DWRITE_FONT_STRETCH_EXTRA_EXPANDED,
+        FontStretch::UltraExpanded => DWRITE_FONT_STRETCH_ULTRA_EXPANDED,
+    }
+}
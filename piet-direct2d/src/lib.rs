@@ -11,19 +11,32 @@ pub mod dwrite;
 mod text;
 
 use std::borrow::Cow;
+use std::ops::Range;
+use std::ptr::null_mut;
 
+use winapi::shared::dxgiformat::{
+    DXGI_FORMAT_A8_UNORM, DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM,
+};
 use winapi::um::d2d1::{
+    D2D1_ANTIALIAS_MODE_ALIASED, D2D1_ANTIALIAS_MODE_PER_PRIMITIVE, D2D1_BITMAP_INTERPOLATION_MODE,
     D2D1_BITMAP_INTERPOLATION_MODE_LINEAR, D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
-    D2D1_DRAW_TEXT_OPTIONS_NONE, D2D1_LINEAR_GRADIENT_BRUSH_PROPERTIES,
-    D2D1_RADIAL_GRADIENT_BRUSH_PROPERTIES,
+    D2D1_DRAW_TEXT_OPTIONS, D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+    D2D1_DRAW_TEXT_OPTIONS_NO_SNAP, D2D1_LINEAR_GRADIENT_BRUSH_PROPERTIES,
+    D2D1_RADIAL_GRADIENT_BRUSH_PROPERTIES, D2D1_TEXT_ANTIALIAS_MODE_ALIASED,
+    D2D1_TEXT_ANTIALIAS_MODE_CLEARTYPE, D2D1_TEXT_ANTIALIAS_MODE_DEFAULT,
+    D2D1_TEXT_ANTIALIAS_MODE_GRAYSCALE,
 };
 use winapi::um::dcommon::{D2D1_ALPHA_MODE_IGNORE, D2D1_ALPHA_MODE_PREMULTIPLIED};
+use winapi::um::dwrite::DWRITE_GLYPH_OFFSET;
 
-use piet::kurbo::{Affine, PathEl, Point, Rect, Shape};
+use piet::kurbo::{Affine, Insets, PathEl, Point, Rect, Shape, Size};
 
 use piet::{
-    new_error, Color, Error, ErrorKind, FixedGradient, ImageFormat, InterpolationMode, IntoBrush,
-    RenderContext, StrokeStyle,
+    align_stroke, flattening_tolerance, new_error, user_tolerance, Antialiasing, Capabilities,
+    Color, DrawCall, DrawProfile, Error, ErrorKind, FallbackPolicy, FixedGradient,
+    GlyphPositioning, Image, ImageFormat, ImagePositioning, InterpolationMode, IntoBrush,
+    MemoryCharge, MemoryCounters, MemoryReport, Profiler, RenderContext, StrokeStyle,
+    DEFAULT_TOLERANCE,
 };
 
 pub use crate::d2d::{D2DDevice, D2DFactory, DeviceContext as D2DDeviceContext};
@@ -31,11 +44,59 @@ pub use crate::dwrite::DwriteFactory;
 pub use crate::text::{D2DFont, D2DFontBuilder, D2DText, D2DTextLayout, D2DTextLayoutBuilder};
 
 use crate::conv::{
-    affine_to_matrix3x2f, color_to_colorf, convert_stroke_style, gradient_stop_to_d2d,
+    affine_to_matrix3x2f, color_to_colorf, convert_stroke_style, gradient_stop_to_d2d, premul,
     rect_to_rectf, to_point2f,
 };
 use crate::d2d::{Bitmap, Brush, DeviceContext, FillRule, PathGeometry};
 
+/// A bitmap, with the format its pixels were given in.
+pub struct D2DImage {
+    bitmap: Bitmap,
+    format: ImageFormat,
+    /// Whether the bitmap can be drawn into, from `make_render_target`.
+    target: bool,
+    _memory: MemoryCharge,
+}
+
+/// A path geometry, made with `make_path`.
+pub struct D2DPath {
+    geometry: PathGeometry,
+    bounding_box: Rect,
+}
+
+impl D2DPath {
+    pub fn geometry(&self) -> &PathGeometry {
+        &self.geometry
+    }
+}
+
+impl D2DImage {
+    fn new(bitmap: Bitmap, format: ImageFormat, memory: &MemoryCounters) -> D2DImage {
+        let bytes = bitmap.bytes();
+        D2DImage {
+            bitmap,
+            format,
+            target: false,
+            _memory: memory.images.charge(bytes),
+        }
+    }
+
+    pub fn bitmap(&self) -> &Bitmap {
+        &self.bitmap
+    }
+}
+
+impl Image for D2DImage {
+    fn size(&self) -> Size {
+        let size = self.bitmap.get_size();
+        Size::new(size.width as f64, size.height as f64)
+    }
+
+    fn format(&self) -> ImageFormat {
+        self.format
+    }
+}
+
 pub struct D2DRenderContext<'a> {
     factory: &'a D2DFactory,
     inner_text: D2DText<'a>,
@@ -44,6 +105,14 @@ pub struct D2DRenderContext<'a> {
     /// The context state stack. There is always at least one, until finishing.
     ctx_stack: Vec<CtxState>,
 
+    glyph_positioning: GlyphPositioning,
+    image_positioning: ImagePositioning,
+    /// The tolerance of converting shapes to paths, in device pixels.
+    tolerance: f64,
+    /// The calls made so far, for `draw_profile`.
+    profiler: Profiler,
+    fallback_policy: FallbackPolicy,
+
     err: Result<(), Error>,
 }
 
@@ -54,6 +123,9 @@ struct CtxState {
     // Note: when we start pushing both layers and axis aligned clips, this will
     // need to keep track of which is which. But for now, keep it simple.
     n_layers_pop: usize,
+
+    /// Whether `push_clip` made this state, rather than `save`.
+    pushed_clip: bool,
 }
 
 impl<'b, 'a: 'b> D2DRenderContext<'a> {
@@ -71,10 +143,21 @@ impl<'b, 'a: 'b> D2DRenderContext<'a> {
             inner_text,
             rt,
             ctx_stack: vec![CtxState::default()],
+            glyph_positioning: GlyphPositioning::default(),
+            image_positioning: ImagePositioning::default(),
+            tolerance: DEFAULT_TOLERANCE,
+            profiler: Profiler::new(),
+            fallback_policy: FallbackPolicy::default(),
             err: Ok(()),
         }
     }
 
+    /// The tolerance for converting shapes to paths in the current user
+    /// space.
+    fn shape_tolerance(&self) -> f64 {
+        user_tolerance(self.tolerance, self.current_transform())
+    }
+
     fn pop_state(&mut self) {
         // This is an unwrap because we protect the invariant.
         let old_state = self.ctx_stack.pop().unwrap();
@@ -82,25 +165,158 @@ impl<'b, 'a: 'b> D2DRenderContext<'a> {
             self.rt.pop_layer();
         }
     }
+
+    /// Get ready to draw `layout` at `pos`, painting its backgrounds. Returns
+    /// the origin to draw the glyphs at and the options to draw them with.
+    fn prepare_text(
+        &mut self,
+        layout: &D2DTextLayout,
+        pos: Point,
+    ) -> (Point, D2D1_DRAW_TEXT_OPTIONS) {
+        let (pos, text_options) = self.text_origin(pos);
+        for (rect, color) in layout.backgrounds() {
+            self.fill(rect + pos.to_vec2(), color);
+        }
+        (pos, text_options)
+    }
+
+    /// Where to put the origin of text drawn at `pos`, and the options to
+    /// draw it with, honoring the glyph positioning.
+    ///
+    /// Color fonts are always enabled, so emoji keep their own colors rather
+    /// than taking the brush's. Device contexts before Windows 8.1 ignore
+    /// the option and draw them as outlines.
+    fn text_origin(&self, pos: Point) -> (Point, D2D1_DRAW_TEXT_OPTIONS) {
+        // Direct2D snaps glyphs vertically by default, we also snap the origin
+        // horizontally so that all backends agree.
+        match self.glyph_positioning {
+            GlyphPositioning::PixelSnapped => {
+                let transform = self.current_transform();
+                let device = transform * pos;
+                let pos = transform.inverse() * Point::new(device.x.round(), device.y.round());
+                (pos, D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT)
+            }
+            GlyphPositioning::Subpixel => (
+                pos,
+                D2D1_DRAW_TEXT_OPTIONS_NO_SNAP | D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+            ),
+        }
+    }
+
+    /// Clip to the glyphs of `layout`, through a layer whose opacity comes
+    /// from the text drawn into a bitmap the size of the target.
+    ///
+    /// DirectWrite only gives glyph outlines to a custom text renderer, so
+    /// this masks with the rendered text instead.
+    fn push_text_clip(&mut self, layout: &D2DTextLayout, pos: Point) -> Result<(), Error> {
+        let (pos, text_options) = self.text_origin(pos);
+        let mask = self.rt.create_layer_bitmap()?;
+        let opaque = self.rt.create_solid_color(color_to_colorf(Color::BLACK))?;
+        let old_target = self.rt.get_target();
+        self.rt.set_target(&mask);
+        self.rt.clear(color_to_colorf(Color::rgba8(0, 0, 0, 0)));
+        if let Some(baseline) = first_baseline(&layout.layout) {
+            let mut origin = to_point2f(pos);
+            origin.y -= baseline;
+            self.rt
+                .draw_text_layout(origin, &layout.layout, &opaque, text_options);
+        }
+        self.rt.restore_target(old_target);
+
+        // The mask is in device space, so undo the transform to lay it
+        // over the target pixel for pixel.
+        let transform = affine_to_matrix3x2f(self.current_transform().inverse());
+        let interp = convert_interp(InterpolationMode::NearestNeighbor);
+        let brush = self.rt.create_bitmap_brush(&mask, interp, transform)?;
+        let layer = self.rt.create_layer(None)?;
+        self.rt.push_layer_opacity(&brush, &layer);
+        self.ctx_stack.last_mut().unwrap().n_layers_pop += 1;
+        Ok(())
+    }
+
+    /// Set the layout's colors as drawing effects, replacing those of any
+    /// previous draw, which may have been on another render target.
+    fn apply_colors(&mut self, layout: &D2DTextLayout) {
+        for (start_16, len_16, color) in layout.color_ranges_16() {
+            match color {
+                Some(color) => {
+                    let brush = self.solid_brush(color.clone());
+                    layout
+                        .layout
+                        .set_drawing_effect(brush.as_drawing_effect(), start_16, len_16);
+                }
+                None => layout
+                    .layout
+                    .set_drawing_effect(null_mut(), start_16, len_16),
+            }
+        }
+    }
+
+    /// Draw the grapheme clusters of `layout` one by one, each with its
+    /// transform from `transforms`, skipping those starting in `hidden`.
+    fn draw_clusters(
+        &mut self,
+        layout: &D2DTextLayout,
+        pos: Point,
+        brush: &Brush,
+        text_options: D2D1_DRAW_TEXT_OPTIONS,
+        transforms: &[Affine],
+        hidden: &[Range<usize>],
+    ) {
+        let current = self.current_transform();
+        let clusters = layout.clusters(self.inner_text.dwrite());
+        for (i, (start, offset, cluster)) in clusters.iter().enumerate() {
+            if hidden.iter().any(|range| range.contains(start)) {
+                continue;
+            }
+            let color_brush = layout
+                .color_at(*start)
+                .map(|color| self.solid_brush(color.clone()));
+            let baseline = match first_baseline(cluster) {
+                Some(baseline) => baseline,
+                None => continue,
+            };
+            let transform = transforms.get(i).copied().unwrap_or_default();
+            let origin = Affine::translate(pos.to_vec2() + offset.to_vec2());
+            self.rt
+                .set_transform(&affine_to_matrix3x2f(current * origin * transform));
+            let top_left = to_point2f((0.0, -baseline as f64));
+            let brush = color_brush.as_ref().unwrap_or(brush);
+            self.rt
+                .draw_text_layout(top_left, cluster, brush, text_options);
+        }
+        self.rt.set_transform(&affine_to_matrix3x2f(current));
+    }
 }
 
-// The setting of 1e-3 is extremely conservative (absolutely no
-// differences should be visible) but setting a looser tolerance is
-// likely a tiny performance improvement. We could fine-tune based on
-// empirical study of both quality and performance.
-const BEZ_TOLERANCE: f64 = 1e-3;
+/// The baseline of the first line of a layout, or `None` if it is empty.
+///
+/// Vertical layouts are positioned by their top-left corner, so their
+/// baseline is taken to be at the top.
+fn first_baseline(layout: &dwrite::TextLayout) -> Option<f32> {
+    let mut line_metrics = Vec::with_capacity(1);
+    layout.get_line_metrics(&mut line_metrics);
+    let vertical = layout.is_vertical();
+    line_metrics
+        .first()
+        .map(|metrics| if vertical { 0.0 } else { metrics.baseline })
+}
 
+/// `shape` as a path geometry, with curves within `tolerance` in its own
+/// coordinates.
 fn path_from_shape(
     d2d: &D2DFactory,
     is_filled: bool,
     shape: impl Shape,
     fill_rule: FillRule,
+    tolerance: f64,
 ) -> Result<PathGeometry, Error> {
     let mut path = d2d.create_path_geometry()?;
     let mut sink = path.open()?;
     sink.set_fill_mode(fill_rule);
     let mut need_close = false;
-    for el in shape.to_bez_path(BEZ_TOLERANCE) {
+    let tolerance = flattening_tolerance(tolerance, &shape);
+    for el in shape.to_bez_path(tolerance) {
         match el {
             PathEl::MoveTo(p) => {
                 if need_close {
@@ -138,16 +354,56 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
 
     type TextLayout = D2DTextLayout;
 
-    type Image = Bitmap;
+    type Image = D2DImage;
+
+    type Path = D2DPath;
 
     fn status(&mut self) -> Result<(), Error> {
         std::mem::replace(&mut self.err, Ok(()))
     }
 
     fn clear(&mut self, color: Color) {
+        let _timer = self.profiler.time(DrawCall::Fill);
         self.rt.clear(color_to_colorf(color));
     }
 
+    fn clear_rect(&mut self, rect: Rect, color: Color) {
+        let _timer = self.profiler.time(DrawCall::Fill);
+        // Direct2D clears the bounding box of a transformed clip rectangle.
+        self.rt
+            .push_axis_aligned_clip(&rect_to_rectf(rect), D2D1_ANTIALIAS_MODE_ALIASED);
+        self.rt.clear(color_to_colorf(color));
+        self.rt.pop_axis_aligned_clip();
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            text: true,
+            images: true,
+            image_formats: &[
+                ImageFormat::Rgb,
+                ImageFormat::RgbaSeparate,
+                ImageFormat::RgbaPremul,
+                ImageFormat::BgraPremul,
+                ImageFormat::Grayscale,
+                ImageFormat::Alpha,
+            ],
+            nearest_neighbor_interpolation: true,
+            linear_gradients: true,
+            radial_gradients: true,
+            mesh_gradients: false,
+            dashes: true,
+            read_back: true,
+            render_targets: true,
+            antialiasing: true,
+            picking: false,
+            font_loading: false,
+            vertical_text: true,
+            variable_fonts: false,
+            glyph_drawing: true,
+        }
+    }
+
     fn solid_brush(&mut self, color: Color) -> Brush {
         self.rt
             .create_solid_color(color_to_colorf(color))
@@ -161,7 +417,12 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
                     startPoint: to_point2f(linear.start),
                     endPoint: to_point2f(linear.end),
                 };
-                let stops: Vec<_> = linear.stops.iter().map(gradient_stop_to_d2d).collect();
+                let stops: Vec<_> = linear
+                    .color_space
+                    .resolve_stops(&linear.stops)
+                    .iter()
+                    .map(gradient_stop_to_d2d)
+                    .collect();
                 let stops = self.rt.create_gradient_stops(&stops)?;
                 let result = self.rt.create_linear_gradient(&props, &stops)?;
                 Ok(result)
@@ -173,36 +434,113 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
                     radiusX: radial.radius as f32,
                     radiusY: radial.radius as f32,
                 };
-                let stops: Vec<_> = radial.stops.iter().map(gradient_stop_to_d2d).collect();
+                let stops: Vec<_> = radial
+                    .color_space
+                    .resolve_stops(&radial.stops)
+                    .iter()
+                    .map(gradient_stop_to_d2d)
+                    .collect();
                 let stops = self.rt.create_gradient_stops(&stops)?;
                 let result = self.rt.create_radial_gradient(&props, &stops)?;
                 Ok(result)
             }
+            // gradient meshes need ID2D1DeviceContext2, which winapi doesn't
+            // expose yet
+            FixedGradient::Mesh(mesh) => {
+                let color = self.fallback_policy.degrade(|| mesh.fallback_color())?;
+                Ok(self.solid_brush(color))
+            }
         }
     }
 
     fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
         // TODO: various special-case shapes, for efficiency
         let brush = brush.make_brush(self, || shape.bounding_box());
-        match path_from_shape(self.factory, true, shape, FillRule::NonZero) {
+        match path_from_shape(
+            self.factory,
+            true,
+            shape,
+            FillRule::NonZero,
+            self.shape_tolerance(),
+        ) {
             Ok(path) => self.rt.fill_geometry(&path, &brush, None),
             Err(e) => self.err = Err(e),
         }
     }
 
     fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
         // TODO: various special-case shapes, for efficiency
         let brush = brush.make_brush(self, || shape.bounding_box());
-        match path_from_shape(self.factory, true, shape, FillRule::EvenOdd) {
+        match path_from_shape(
+            self.factory,
+            true,
+            shape,
+            FillRule::EvenOdd,
+            self.shape_tolerance(),
+        ) {
             Ok(path) => self.rt.fill_geometry(&path, &brush, None),
             Err(e) => self.err = Err(e),
         }
     }
 
+    fn make_path(&mut self, shape: impl Shape) -> Result<Self::Path, Error> {
+        let bounding_box = shape.bounding_box();
+        // Filled figures can be stroked too, so one geometry does for both.
+        let geometry = path_from_shape(
+            self.factory,
+            true,
+            shape,
+            FillRule::NonZero,
+            self.shape_tolerance(),
+        )?;
+        Ok(D2DPath {
+            geometry,
+            bounding_box,
+        })
+    }
+
+    fn fill_path(&mut self, path: &Self::Path, brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
+        let brush = brush.make_brush(self, || path.bounding_box);
+        self.rt.fill_geometry(&path.geometry, &brush, None);
+    }
+
+    fn stroke_path(&mut self, path: &Self::Path, brush: &impl IntoBrush<Self>, width: f64) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
+        let brush = brush.make_brush(self, || path.bounding_box);
+        self.rt
+            .draw_geometry(&path.geometry, &*brush, width as f32, None);
+    }
+
+    fn stroke_path_styled(
+        &mut self,
+        path: &Self::Path,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
+        let brush = brush.make_brush(self, || path.bounding_box);
+        let width = width as f32;
+        let style = convert_stroke_style(self.factory, style, width)
+            .expect("stroke style conversion failed");
+        self.rt
+            .draw_geometry(&path.geometry, &*brush, width, Some(&style));
+    }
+
     fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
         let brush = brush.make_brush(self, || shape.bounding_box());
         // TODO: various special-case shapes, for efficiency
-        let path = match path_from_shape(self.factory, false, shape, FillRule::EvenOdd) {
+        let path = match path_from_shape(
+            self.factory,
+            false,
+            shape,
+            FillRule::EvenOdd,
+            self.shape_tolerance(),
+        ) {
             Ok(path) => path,
             Err(e) => {
                 self.err = Err(e);
@@ -220,9 +558,19 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
         width: f64,
         style: &StrokeStyle,
     ) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
+        if let Some((path, style)) = align_stroke(&shape, width, style) {
+            return self.stroke_styled(path, brush, width, &style);
+        }
         let brush = brush.make_brush(self, || shape.bounding_box());
         // TODO: various special-case shapes, for efficiency
-        let path = match path_from_shape(self.factory, false, shape, FillRule::EvenOdd) {
+        let path = match path_from_shape(
+            self.factory,
+            false,
+            shape,
+            FillRule::EvenOdd,
+            self.shape_tolerance(),
+        ) {
             Ok(path) => path,
             Err(e) => {
                 self.err = Err(e);
@@ -236,6 +584,7 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
     }
 
     fn clip(&mut self, shape: impl Shape) {
+        let _timer = self.profiler.time(DrawCall::State);
         // TODO: set size based on bbox of shape.
         let layer = match self.rt.create_layer(None) {
             Ok(layer) => layer,
@@ -244,19 +593,61 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
                 return;
             }
         };
-        let path = match path_from_shape(self.factory, true, shape, FillRule::NonZero) {
+        let path = match path_from_shape(
+            self.factory,
+            true,
+            shape,
+            FillRule::NonZero,
+            self.shape_tolerance(),
+        ) {
             Ok(path) => path,
             Err(e) => {
                 self.err = Err(e);
                 return;
             }
         };
-        self.rt.push_layer_mask(&path, &layer);
+        // the mask follows the antialiasing mode, like fills
+        let antialias = self.rt.get_antialias_mode();
+        self.rt.push_layer_mask(&path, &layer, antialias);
         self.ctx_stack.last_mut().unwrap().n_layers_pop += 1;
     }
 
-    fn text(&mut self) -> &mut Self::Text {
-        &mut self.inner_text
+    fn clip_text(&mut self, layout: &Self::TextLayout, pos: impl Into<Point>) {
+        let _timer = self.profiler.time(DrawCall::State);
+        if let Err(e) = self.push_text_clip(layout, pos.into()) {
+            self.err = Err(e);
+        }
+    }
+
+    fn push_clip(&mut self, shape: impl Shape) {
+        let _timer = self.profiler.time(DrawCall::State);
+        let _ = self.save();
+        self.ctx_stack.last_mut().unwrap().pushed_clip = true;
+        self.clip(shape);
+    }
+
+    fn pop_clip(&mut self) -> Result<(), Error> {
+        let _timer = self.profiler.time(DrawCall::State);
+        if !self.ctx_stack.last().unwrap().pushed_clip {
+            return Err(new_error(ErrorKind::StackUnbalance));
+        }
+        let transform = self.current_transform();
+        self.pop_state();
+        self.ctx_stack.last_mut().unwrap().transform = transform;
+        Ok(())
+    }
+
+    fn reset_clip(&mut self) {
+        let _timer = self.profiler.time(DrawCall::State);
+        while self.pop_clip().is_ok() {}
+        let state = self.ctx_stack.last_mut().unwrap();
+        for _ in 0..std::mem::take(&mut state.n_layers_pop) {
+            self.rt.pop_layer();
+        }
+    }
+
+    fn text(&self) -> Self::Text {
+        self.inner_text.clone()
     }
 
     fn draw_text(
@@ -265,37 +656,163 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
         pos: impl Into<Point>,
         brush: &impl IntoBrush<Self>,
     ) {
+        let _timer = self.profiler.time(DrawCall::Text);
         // TODO: bounding box for text
         let brush = brush.make_brush(self, || Rect::ZERO);
-        let mut line_metrics = Vec::with_capacity(1);
-        layout.layout.get_line_metrics(&mut line_metrics);
-        if line_metrics.is_empty() {
+        let baseline = match first_baseline(&layout.layout) {
+            Some(baseline) => baseline,
             // Layout is empty, don't bother drawing.
+            None => return,
+        };
+        let (pos, text_options) = self.prepare_text(layout, pos.into());
+        if let Some((bitmaps, hidden)) = layout.split_emoji() {
+            // The bitmaps replace whole clusters, so draw the rest one
+            // cluster at a time.
+            self.draw_clusters(layout, pos, &brush, text_options, &[], &hidden);
+            for (rect, bitmap) in bitmaps {
+                let rect = bitmap.fit(rect + pos.to_vec2());
+                match self.make_image(
+                    bitmap.width(),
+                    bitmap.height(),
+                    bitmap.buf(),
+                    ImageFormat::RgbaSeparate,
+                ) {
+                    Ok(image) => self.draw_image(&image, rect, InterpolationMode::Bilinear),
+                    Err(e) => self.err = Err(e),
+                }
+            }
             return;
         }
+        if layout.has_colors() {
+            self.apply_colors(layout);
+        }
         // Direct2D takes upper-left, so adjust for baseline.
-        let mut pos = to_point2f(pos.into());
-        pos.y -= line_metrics[0].baseline;
-        let text_options = D2D1_DRAW_TEXT_OPTIONS_NONE;
+        let mut pos = to_point2f(pos);
+        pos.y -= baseline;
 
         self.rt
             .draw_text_layout(pos, &layout.layout, &*brush, text_options);
     }
 
+    fn draw_glyph_run(
+        &mut self,
+        layout: &Self::TextLayout,
+        pos: impl Into<Point>,
+        brush: &impl IntoBrush<Self>,
+        transforms: &[Affine],
+    ) {
+        let _timer = self.profiler.time(DrawCall::Text);
+        let brush = brush.make_brush(self, || Rect::ZERO);
+        let (pos, text_options) = self.prepare_text(layout, pos.into());
+        self.draw_clusters(layout, pos, &brush, text_options, transforms, &[]);
+    }
+
+    fn draw_glyphs(
+        &mut self,
+        font: &D2DFont,
+        glyphs: &[(u16, Point)],
+        brush: &impl IntoBrush<Self>,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Text);
+        let origin = match glyphs.first() {
+            Some(&(_, origin)) => origin,
+            None => return,
+        };
+        // a font that fell back to DirectWrite's own matching has no face
+        let (face, size) = match font.glyph_face() {
+            Some(face) => face,
+            None => return,
+        };
+        let face = match face.create_face() {
+            Ok(face) => face,
+            Err(e) => {
+                self.err = Err(e.into());
+                return;
+            }
+        };
+        let brush = brush.make_brush(self, || Rect::ZERO);
+        let indices: Vec<u16> = glyphs.iter().map(|&(id, _)| id).collect();
+        // DirectWrite measures offsets upward from the baseline
+        let offsets: Vec<DWRITE_GLYPH_OFFSET> = glyphs
+            .iter()
+            .map(|&(_, pos)| DWRITE_GLYPH_OFFSET {
+                advanceOffset: (pos.x - origin.x) as f32,
+                ascenderOffset: (origin.y - pos.y) as f32,
+            })
+            .collect();
+        self.rt
+            .draw_glyph_run(to_point2f(origin), &face, size, &indices, &offsets, &*brush);
+    }
+
+    fn set_glyph_positioning(&mut self, positioning: GlyphPositioning) {
+        self.glyph_positioning = positioning;
+    }
+
+    fn set_image_positioning(&mut self, positioning: ImagePositioning) {
+        self.image_positioning = positioning;
+    }
+
+    fn set_tolerance(&mut self, tolerance: f64) {
+        if tolerance > 0.0 && tolerance.is_finite() {
+            self.tolerance = tolerance;
+        }
+    }
+
+    fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    fn set_antialiasing(&mut self, mode: Antialiasing) {
+        let (geometry, text) = match mode {
+            Antialiasing::Default => (
+                D2D1_ANTIALIAS_MODE_PER_PRIMITIVE,
+                D2D1_TEXT_ANTIALIAS_MODE_DEFAULT,
+            ),
+            Antialiasing::None => (
+                D2D1_ANTIALIAS_MODE_ALIASED,
+                D2D1_TEXT_ANTIALIAS_MODE_ALIASED,
+            ),
+            Antialiasing::Grayscale => (
+                D2D1_ANTIALIAS_MODE_PER_PRIMITIVE,
+                D2D1_TEXT_ANTIALIAS_MODE_GRAYSCALE,
+            ),
+            Antialiasing::Subpixel => (
+                D2D1_ANTIALIAS_MODE_PER_PRIMITIVE,
+                D2D1_TEXT_ANTIALIAS_MODE_CLEARTYPE,
+            ),
+        };
+        self.rt.set_antialias_mode(geometry);
+        self.rt.set_text_antialias_mode(text);
+    }
+
+    fn set_pick_id(&mut self, _id: Option<u64>) {}
+
+    fn set_fallback_policy(&mut self, policy: FallbackPolicy) {
+        self.fallback_policy = policy;
+    }
+
     fn save(&mut self) -> Result<(), Error> {
+        let _timer = self.profiler.time(DrawCall::State);
         let new_state = CtxState {
             transform: self.current_transform(),
             n_layers_pop: 0,
+            pushed_clip: false,
         };
         self.ctx_stack.push(new_state);
         Ok(())
     }
 
     fn restore(&mut self) -> Result<(), Error> {
-        if self.ctx_stack.len() <= 1 {
-            return Err(new_error(ErrorKind::StackUnbalance));
+        let _timer = self.profiler.time(DrawCall::State);
+        // Clips pushed since the save go with it. The first state is the
+        // one before any save.
+        let depth = match self.ctx_stack.iter().rposition(|state| !state.pushed_clip) {
+            Some(depth) if depth > 0 => depth,
+            _ => return Err(new_error(ErrorKind::StackUnbalance)),
+        };
+        while self.ctx_stack.len() > depth {
+            self.pop_state();
         }
-        self.pop_state();
         // Move this code into impl to avoid duplication with transform?
         self.rt
             .set_transform(&affine_to_matrix3x2f(self.current_transform()));
@@ -306,17 +823,24 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
     // D2DRenderContext creation? I'm thinking not, as the shell might want
     // to do other stuff, possibly related to incremental paint.
     fn finish(&mut self) -> Result<(), Error> {
-        if self.ctx_stack.len() != 1 {
-            return Err(new_error(ErrorKind::StackUnbalance));
-        }
+        piet::check_usage(
+            self.ctx_stack.len() == 1,
+            ErrorKind::StackUnbalance,
+            "finish called with saves not restored",
+        )?;
         self.pop_state();
         std::mem::replace(&mut self.err, Ok(()))
     }
 
     fn transform(&mut self, transform: Affine) {
-        self.ctx_stack.last_mut().unwrap().transform *= transform;
-        self.rt
-            .set_transform(&affine_to_matrix3x2f(self.current_transform()));
+        let _timer = self.profiler.time(DrawCall::State);
+        let combined = self.current_transform() * transform;
+        if let Err(e) = piet::check_transform(combined) {
+            self.err = Err(e);
+            return;
+        }
+        self.ctx_stack.last_mut().unwrap().transform = combined;
+        self.rt.set_transform(&affine_to_matrix3x2f(combined));
     }
 
     fn current_transform(&self) -> Affine {
@@ -331,12 +855,21 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
         buf: &[u8],
         format: ImageFormat,
     ) -> Result<Self::Image, Error> {
-        // TODO: this method _really_ needs error checking, so much can go wrong...
+        piet::check_image_buffer(width, height, buf, format)?;
         let alpha_mode = match format {
-            ImageFormat::Rgb => D2D1_ALPHA_MODE_IGNORE,
-            ImageFormat::RgbaPremul | ImageFormat::RgbaSeparate => D2D1_ALPHA_MODE_PREMULTIPLIED,
+            ImageFormat::Rgb | ImageFormat::Grayscale => D2D1_ALPHA_MODE_IGNORE,
+            ImageFormat::RgbaPremul
+            | ImageFormat::RgbaSeparate
+            | ImageFormat::BgraPremul
+            | ImageFormat::Alpha => D2D1_ALPHA_MODE_PREMULTIPLIED,
             _ => return Err(new_error(ErrorKind::NotSupported)),
         };
+        // Direct2D has no gray bitmaps, but keeps alpha-only ones as A8.
+        let dxgi_format = match format {
+            ImageFormat::Alpha => DXGI_FORMAT_A8_UNORM,
+            ImageFormat::BgraPremul => DXGI_FORMAT_B8G8R8A8_UNORM,
+            _ => DXGI_FORMAT_R8G8B8A8_UNORM,
+        };
         let buf = match format {
             ImageFormat::Rgb => {
                 let mut new_buf = vec![255; width * height * 4];
@@ -350,10 +883,6 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
             ImageFormat::RgbaSeparate => {
                 let mut new_buf = vec![255; width * height * 4];
                 // TODO (performance): this would be soooo much faster with SIMD
-                fn premul(x: u8, a: u8) -> u8 {
-                    let y = (x as u16) * (a as u16);
-                    ((y + (y >> 8) + 0x80) >> 8) as u8
-                }
                 for i in 0..width * height {
                     let a = buf[i * 4 + 3];
                     new_buf[i * 4 + 0] = premul(buf[i * 4 + 0], a);
@@ -363,12 +892,23 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
                 }
                 Cow::from(new_buf)
             }
-            ImageFormat::RgbaPremul => Cow::from(buf),
+            ImageFormat::Grayscale => {
+                let mut new_buf = vec![255; width * height * 4];
+                for i in 0..width * height {
+                    new_buf[i * 4..i * 4 + 3].copy_from_slice(&[buf[i]; 3]);
+                }
+                Cow::from(new_buf)
+            }
+            ImageFormat::RgbaPremul | ImageFormat::BgraPremul | ImageFormat::Alpha => {
+                Cow::from(buf)
+            }
             // This should be unreachable, we caught it above.
             _ => return Err(new_error(ErrorKind::NotSupported)),
         };
-        let bitmap = self.rt.create_bitmap(width, height, &buf, alpha_mode)?;
-        Ok(bitmap)
+        let bitmap = self
+            .rt
+            .create_bitmap(width, height, &buf, dxgi_format, alpha_mode)?;
+        Ok(D2DImage::new(bitmap, format, self.rt.memory()))
     }
 
     #[inline]
@@ -378,7 +918,13 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        draw_image(self.rt, image, None, dst_rect.into(), interp);
+        let _timer = self.profiler.time(DrawCall::Image);
+        let dst_rect = piet::position_image(
+            dst_rect.into(),
+            self.image_positioning,
+            self.current_transform(),
+        );
+        draw_image(self.rt, image, None, dst_rect, interp);
     }
 
     #[inline]
@@ -389,13 +935,266 @@ impl<'a> RenderContext for D2DRenderContext<'a> {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        draw_image(
-            self.rt,
-            image,
-            Some(src_rect.into()),
+        let _timer = self.profiler.time(DrawCall::Image);
+        let dst_rect = piet::position_image(
             dst_rect.into(),
-            interp,
+            self.image_positioning,
+            self.current_transform(),
         );
+        draw_image(self.rt, image, Some(src_rect.into()), dst_rect, interp);
+    }
+
+    fn draw_image_transformed(
+        &mut self,
+        image: &Self::Image,
+        transform: Affine,
+        interp: InterpolationMode,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        let size = image.size();
+        self.rt
+            .set_transform(&affine_to_matrix3x2f(self.current_transform() * transform));
+        draw_image(self.rt, image, None, size.to_rect(), interp);
+        self.rt
+            .set_transform(&affine_to_matrix3x2f(self.current_transform()));
+    }
+
+    fn draw_tiles(
+        &mut self,
+        atlas: &Self::Image,
+        tiles: &[(Rect, Point)],
+        interp: InterpolationMode,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        let interp = convert_interp(interp);
+        for &(src_rect, dst_pos) in tiles {
+            let dst_rect = piet::position_image(
+                Rect::from_origin_size(dst_pos, src_rect.size()),
+                self.image_positioning,
+                self.current_transform(),
+            );
+            self.rt.draw_bitmap(
+                &atlas.bitmap,
+                &rect_to_rectf(dst_rect),
+                1.0,
+                interp,
+                Some(&rect_to_rectf(src_rect)),
+            );
+        }
+    }
+
+    fn draw_images(&mut self, images: &[(&Self::Image, Rect, f64)], interp: InterpolationMode) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        let interp = convert_interp(interp);
+        for &(image, dst_rect, alpha) in images {
+            let dst_rect =
+                piet::position_image(dst_rect, self.image_positioning, self.current_transform());
+            if alpha.is_nan() || alpha <= 0.0 {
+                continue;
+            }
+            self.rt.draw_bitmap(
+                &image.bitmap,
+                &rect_to_rectf(dst_rect),
+                alpha.min(1.0) as f32,
+                interp,
+                None,
+            );
+        }
+    }
+
+    fn make_render_target(&mut self, width: usize, height: usize) -> Result<Self::Image, Error> {
+        let bitmap = self.rt.create_target_bitmap(width, height)?;
+        // a new bitmap's pixels are undefined until cleared
+        let old_target = self.rt.get_target();
+        self.rt.set_target(&bitmap);
+        self.rt.clear(color_to_colorf(Color::rgba8(0, 0, 0, 0)));
+        self.rt.restore_target(old_target);
+        let mut image = D2DImage::new(bitmap, ImageFormat::RgbaPremul, self.rt.memory());
+        image.target = true;
+        Ok(image)
+    }
+
+    fn draw_to_target(
+        &mut self,
+        target: &mut Self::Image,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        // only bitmaps made with the target option can be drawn into
+        if !target.target {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        let old_target = self.rt.get_target();
+        let old_stack = std::mem::replace(&mut self.ctx_stack, vec![CtxState::default()]);
+        self.rt.set_target(&target.bitmap);
+        self.rt
+            .set_transform(&affine_to_matrix3x2f(Affine::default()));
+
+        let result = f(self);
+        // Layers belong to the offscreen target, so pop any left unbalanced.
+        while !self.ctx_stack.is_empty() {
+            self.pop_state();
+        }
+
+        self.ctx_stack = old_stack;
+        self.rt.restore_target(old_target);
+        self.rt
+            .set_transform(&affine_to_matrix3x2f(self.current_transform()));
+        result
+    }
+
+    fn capture_image_area(&mut self, src_rect: impl Into<Rect>) -> Result<Self::Image, Error> {
+        let area = self
+            .current_transform()
+            .transform_rect_bbox(src_rect.into())
+            .expand();
+        if area.width() <= 0.0 || area.height() <= 0.0 {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        let bitmap = self.rt.copy_target_area(rect_to_rectf(area))?;
+        Ok(D2DImage::new(
+            bitmap,
+            ImageFormat::RgbaPremul,
+            self.rt.memory(),
+        ))
+    }
+
+    fn with_blur(
+        &mut self,
+        radius: f64,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        // The transform is in device-independent pixels, which is what the
+        // blur effect measures in too.
+        let transform = self.current_transform();
+        let std_dev = radius.abs() * transform.determinant().abs().sqrt();
+        let pooled = self.rt.take_layer_bitmap()?;
+        let old_target = self.rt.get_target();
+        let old_stack = std::mem::replace(
+            &mut self.ctx_stack,
+            vec![CtxState {
+                transform,
+                ..CtxState::default()
+            }],
+        );
+        self.rt.set_target(pooled.surface());
+        self.rt.clear(color_to_colorf(Color::rgba8(0, 0, 0, 0)));
+
+        let result = f(self);
+        while !self.ctx_stack.is_empty() {
+            self.pop_state();
+        }
+
+        self.ctx_stack = old_stack;
+        self.rt.restore_target(old_target);
+        let result = result.and_then(|()| {
+            let effect = self
+                .rt
+                .create_gaussian_blur(pooled.surface(), std_dev as f32)?;
+            self.rt
+                .set_transform(&affine_to_matrix3x2f(Affine::default()));
+            self.rt.draw_effect(&effect);
+            self.rt.set_transform(&affine_to_matrix3x2f(transform));
+            Ok(())
+        });
+        self.rt.give_back_layer_bitmap(pooled);
+        result
+    }
+
+    fn trim_layer_pool(&mut self) {
+        self.rt.trim_layer_pool();
+    }
+
+    /// Covers the images and layers of every context made from the same
+    /// device. Direct2D keeps its glyph caches out of sight, so they aren't
+    /// counted, though `trim_caches` frees them.
+    fn memory_report(&self) -> MemoryReport {
+        self.rt.memory().report()
+    }
+
+    fn trim_caches(&mut self) {
+        self.rt.trim_layer_pool();
+        self.rt.clear_device_resources();
+    }
+
+    fn draw_profile(&self) -> DrawProfile {
+        self.profiler.profile()
+    }
+
+    fn reset_draw_profile(&mut self) {
+        self.profiler.reset();
+    }
+
+    fn draw_image_nine(
+        &mut self,
+        image: &Self::Image,
+        insets: Insets,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        let dst_rect = piet::position_image(
+            dst_rect.into(),
+            self.image_positioning,
+            self.current_transform(),
+        );
+        for (src, dst) in piet::nine_slice(image.size(), insets, dst_rect) {
+            draw_image(self.rt, image, Some(src), dst, interp);
+        }
+    }
+
+    fn draw_image_clipped(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        shape: impl Shape,
+        interp: InterpolationMode,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        let dst_rect = piet::position_image(
+            dst_rect.into(),
+            self.image_positioning,
+            self.current_transform(),
+        );
+        let size = image.size();
+        if dst_rect.area() == 0.0 || size.width == 0.0 || size.height == 0.0 {
+            return;
+        }
+        let interp = convert_interp(interp);
+        let to_user = Affine::translate(dst_rect.origin().to_vec2())
+            * Affine::scale_non_uniform(
+                dst_rect.width() / size.width,
+                dst_rect.height() / size.height,
+            );
+        let brush =
+            match self
+                .rt
+                .create_bitmap_brush(&image.bitmap, interp, affine_to_matrix3x2f(to_user))
+            {
+                Ok(brush) => brush,
+                Err(e) => {
+                    self.err = Err(e.into());
+                    return;
+                }
+            };
+        let path = match path_from_shape(
+            self.factory,
+            true,
+            shape,
+            FillRule::NonZero,
+            self.shape_tolerance(),
+        ) {
+            Ok(path) => path,
+            Err(e) => {
+                self.err = Err(e);
+                return;
+            }
+        };
+        // The brush clamps rather than leaving the outside of the image
+        // empty, so keep the fill within it.
+        self.rt
+            .push_axis_aligned_clip(&rect_to_rectf(dst_rect), D2D1_ANTIALIAS_MODE_PER_PRIMITIVE);
+        self.rt.fill_geometry(&path, &brush, None);
+        self.rt.pop_axis_aligned_clip();
     }
 }
 
@@ -406,16 +1205,13 @@ fn draw_image<'a>(
     dst_rect: Rect,
     interp: InterpolationMode,
 ) {
-    let interp = match interp {
-        InterpolationMode::NearestNeighbor => D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
-        InterpolationMode::Bilinear => D2D1_BITMAP_INTERPOLATION_MODE_LINEAR,
-    };
+    let interp = convert_interp(interp);
     let src_rect = match src_rect {
         Some(src_rect) => Some(rect_to_rectf(src_rect)),
         None => None,
     };
     rt.draw_bitmap(
-        &image,
+        &image.bitmap,
         &rect_to_rectf(dst_rect),
         1.0,
         interp,
@@ -423,6 +1219,13 @@ fn draw_image<'a>(
     );
 }
 
+fn convert_interp(interp: InterpolationMode) -> D2D1_BITMAP_INTERPOLATION_MODE {
+    match interp {
+        InterpolationMode::NearestNeighbor => D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
+        InterpolationMode::Bilinear => D2D1_BITMAP_INTERPOLATION_MODE_LINEAR,
+    }
+}
+
 impl<'a> IntoBrush<D2DRenderContext<'a>> for Brush {
     fn make_brush<'b>(
         &'b self,
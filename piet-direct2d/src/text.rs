@@ -3,44 +3,142 @@
 pub use d2d::{D2DDevice, D2DFactory, DeviceContext as D2DDeviceContext};
 pub use dwrite::DwriteFactory;
 
+use std::cell::OnceCell;
 use std::convert::TryInto;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
-use piet::kurbo::Point;
+use piet::kurbo::{Affine, Point, Rect};
+use unicode_segmentation::UnicodeSegmentation;
+use winapi::um::dwrite::{DWRITE_FONT_METRICS, DWRITE_LINE_METRICS};
 
 use piet::{
-    Error, Font, FontBuilder, HitTestMetrics, HitTestPoint, HitTestTextPosition, Text, TextLayout,
-    TextLayoutBuilder,
+    new_error, paragraph_is_rtl, ClusterMetric, Color, DecorationMetrics, EmojiBitmap,
+    EmojiStrategy, Error, ErrorKind, Font, FontBuilder, FontFeature, FontFileInfo, FontStretch,
+    FontStyle, FontSubstitutions, FontVariation, FontWarning, FontWeight, GenericFamily,
+    HitTestMetrics, HitTestPoint, HitTestTextPosition, LineMetric, ObscuredText, Text,
+    TextAlignment, TextAttribute, TextLayout, TextLayoutBuilder, TextTruncation, TruncatedText,
+    WritingMode,
 };
 
 use crate::d2d;
 use crate::dwrite::{self, TextFormat, TextFormatBuilder};
 
+/// Emoji bitmaps with their bounds relative to the origin.
+type EmojiBitmaps<'a> = Vec<(Rect, &'a EmojiBitmap)>;
+
+/// The text factory of a [`D2DRenderContext`]. Clones are handles to the
+/// same state.
+///
+/// [`D2DRenderContext`]: struct.D2DRenderContext.html
+#[derive(Clone)]
 pub struct D2DText<'a> {
     dwrite: &'a DwriteFactory,
+    settings: Arc<Mutex<TextSettings>>,
+}
+
+/// The state shared by the handles to a text factory.
+struct TextSettings {
+    substitutions: FontSubstitutions,
+    emoji: EmojiStrategy,
+    system_fonts: bool,
 }
 
-pub struct D2DFont(TextFormat);
+pub struct D2DFont {
+    format: TextFormat,
+    warnings: Vec<FontWarning>,
+    decorations: DecorationMetrics,
+    fallbacks: Fallbacks,
+}
+
+/// A font's fallback families that are installed, in order, with its own
+/// face to check for glyphs first.
+#[derive(Clone, Default)]
+struct Fallbacks {
+    face: Option<dwrite::Font>,
+    families: Vec<(String, dwrite::Font)>,
+}
 
 pub struct D2DFontBuilder<'a> {
+    dwrite: &'a DwriteFactory,
     builder: TextFormatBuilder<'a>,
     name: String,
+    substitute: Option<String>,
+    weight: FontWeight,
+    style: FontStyle,
+    stretch: FontStretch,
+    fallbacks: Vec<String>,
+    system_fonts: bool,
 }
 
+/// A laid out text.
+///
+/// DirectWrite reorders right-to-left text with the full bidirectional
+/// algorithm, but the whole layout reads in the direction of its first
+/// paragraph, found by [`paragraph_is_rtl`] from its first strong
+/// character.
+///
+/// [`paragraph_is_rtl`]: ../piet/fn.paragraph_is_rtl.html
 pub struct D2DTextLayout {
+    /// The displayed text.
     pub text: String,
     pub layout: dwrite::TextLayout,
+    obscured: Option<ObscuredText>,
+    /// Set when the text is cut short at the builder's line limit; the cut is
+    /// in the obscured text, when it's obscured.
+    truncated: Option<TruncatedText>,
+    /// Ranges are into the original text.
+    attributes: Vec<(Range<usize>, TextAttribute)>,
+    format: TextFormat,
+    /// A layout for each grapheme cluster with its start in the text and
+    /// offset from the origin, built when first needed.
+    clusters: OnceCell<Vec<(usize, Point, dwrite::TextLayout)>>,
+    default_color: Option<Color>,
+    emoji: EmojiStrategy,
+    decorations: DecorationMetrics,
+    /// The ranges of the displayed text drawn with a fallback family.
+    fallback_ranges: Vec<(Range<usize>, String)>,
 }
 
 pub struct D2DTextLayoutBuilder<'a> {
+    dwrite: &'a DwriteFactory,
     text: String,
+    format: TextFormat,
     builder: dwrite::TextLayoutBuilder<'a>,
+    mask: Option<char>,
+    attributes: Vec<(Range<usize>, TextAttribute)>,
+    default_color: Option<Color>,
+    emoji: EmojiStrategy,
+    decorations: DecorationMetrics,
+    fallbacks: Fallbacks,
+    max_width: f64,
+    alignment: TextAlignment,
+    max_lines: Option<(usize, TextTruncation)>,
+    writing_mode: WritingMode,
 }
 
 impl<'a> D2DText<'a> {
+    pub(crate) fn dwrite(&self) -> &'a DwriteFactory {
+        self.dwrite
+    }
+
     /// Create a new factory that satisfies the piet `Text` trait given
     /// the (platform-specific) dwrite factory.
     pub fn new(dwrite: &'a DwriteFactory) -> D2DText<'a> {
-        D2DText { dwrite }
+        let settings = TextSettings {
+            substitutions: FontSubstitutions::new(),
+            emoji: EmojiStrategy::Platform,
+            system_fonts: true,
+        };
+        D2DText {
+            dwrite,
+            settings: Arc::new(Mutex::new(settings)),
+        }
+    }
+
+    fn settings(&self) -> MutexGuard<'_, TextSettings> {
+        // the settings are only assigned, so a panic can't leave them torn
+        self.settings.lock().unwrap_or_else(PoisonError::into_inner)
     }
 }
 
@@ -54,18 +152,70 @@ impl<'a> Text for D2DText<'a> {
         // Note: the name is cloned here, rather than applied using `with_family` for
         // lifetime reasons. Maybe there's a better approach.
         let builder = TextFormatBuilder::new(self.dwrite).size(size as f32);
+        let settings = self.settings();
         D2DFontBuilder {
+            dwrite: self.dwrite,
             builder,
             name: name.to_owned(),
+            substitute: settings.substitutions.get(name).map(str::to_owned),
+            weight: FontWeight::NORMAL,
+            style: FontStyle::Normal,
+            stretch: FontStretch::Normal,
+            fallbacks: Vec::new(),
+            system_fonts: settings.system_fonts,
         }
     }
 
     fn new_text_layout(&mut self, font: &Self::Font, text: &str) -> Self::TextLayoutBuilder {
         D2DTextLayoutBuilder {
+            dwrite: self.dwrite,
             text: text.to_owned(),
-            builder: dwrite::TextLayoutBuilder::new(self.dwrite)
-                .format(&font.0)
-                .text(text),
+            format: font.format.clone(),
+            builder: dwrite::TextLayoutBuilder::new(self.dwrite).format(&font.format),
+            mask: None,
+            attributes: Vec::new(),
+            emoji: self.settings().emoji.clone(),
+            default_color: None,
+            decorations: font.decorations,
+            fallbacks: font.fallbacks.clone(),
+            max_width: f64::INFINITY,
+            alignment: TextAlignment::Start,
+            max_lines: None,
+            writing_mode: WritingMode::Horizontal,
+        }
+    }
+
+    fn set_font_substitutions(&mut self, substitutions: FontSubstitutions) {
+        self.settings().substitutions = substitutions;
+    }
+
+    fn set_emoji_strategy(&mut self, strategy: EmojiStrategy) {
+        self.settings().emoji = strategy;
+    }
+
+    fn load_font(&mut self, data: &[u8]) -> Result<String, Error> {
+        FontFileInfo::read(data).ok_or_else(|| new_error(ErrorKind::InvalidInput))?;
+        // DirectWrite reads fonts from memory through a custom collection
+        // loader, which isn't wired up yet.
+        Err(new_error(ErrorKind::NotSupported))
+    }
+
+    fn set_system_fonts(&mut self, enabled: bool) {
+        self.settings().system_fonts = enabled;
+    }
+
+    fn prewarm(&mut self, font: &Self::Font, text: &str) {
+        // Building a layout loads the font and shapes the text; glyph
+        // rasterization needs a render target, so that still happens on
+        // first draw.
+        let layout = dwrite::TextLayoutBuilder::new(self.dwrite)
+            .format(&font.format)
+            .text(text)
+            .width(1e6)
+            .height(1e6)
+            .build();
+        if let Ok(layout) = layout {
+            let _ = layout.get_metrics();
         }
     }
 }
@@ -73,36 +223,818 @@ impl<'a> Text for D2DText<'a> {
 impl<'a> FontBuilder for D2DFontBuilder<'a> {
     type Out = D2DFont;
 
+    fn weight(mut self, weight: FontWeight) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    fn style(mut self, style: FontStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    fn stretch(mut self, stretch: FontStretch) -> Self {
+        self.stretch = stretch;
+        self
+    }
+
+    fn fallback(mut self, family: &str) -> Self {
+        self.fallbacks.push(family.to_owned());
+        self
+    }
+
+    fn variation(self, _variation: FontVariation) -> Self {
+        // font axes are set on the text format with IDWriteTextFormat3,
+        // which winapi doesn't expose yet
+        self
+    }
+
     fn build(self) -> Result<Self::Out, Error> {
-        Ok(D2DFont(self.builder.family(&self.name).build()?))
+        if !self.system_fonts {
+            // no fonts can be loaded, so there are none left
+            return Err(new_error(ErrorKind::NotSupported));
+        }
+        // DirectWrite would silently substitute its own fallback at draw time;
+        // check up front so we can report it.
+        let mut warnings = Vec::new();
+        let family = if let Some(generic) = GenericFamily::from_name(&self.name) {
+            generic_family_name(generic)
+        } else if self.dwrite.has_system_font_family(&self.name)? {
+            self.name.as_str()
+        } else if let Some(substitute) = installed_substitute(self.dwrite, &self.substitute)? {
+            warnings.push(FontWarning::Substituted {
+                requested: self.name.clone(),
+                substitute: substitute.to_owned(),
+            });
+            substitute
+        } else {
+            warnings.push(FontWarning::MissingFamily {
+                requested: self.name.clone(),
+                fallback: FALLBACK_FAMILY.to_owned(),
+            });
+            FALLBACK_FAMILY
+        };
+        let format = self
+            .builder
+            .family(family)
+            .weight(self.weight)
+            .style(self.style)
+            .stretch(self.stretch)
+            .build()?;
+        let size = format.get_font_size() as f64;
+        let face = self
+            .dwrite
+            .system_font(family, self.weight, self.style, self.stretch)?;
+        let decorations = match &face {
+            Some(face) => decoration_metrics(&face.metrics(), size),
+            None => DecorationMetrics::estimate(size),
+        };
+        let mut families = Vec::new();
+        for name in &self.fallbacks {
+            let name = GenericFamily::from_name(name)
+                .map(generic_family_name)
+                .unwrap_or(name.as_str());
+            // missing fallbacks are skipped without a warning
+            let font = self
+                .dwrite
+                .system_font(name, self.weight, self.style, self.stretch)?;
+            if let Some(font) = font {
+                families.push((name.to_owned(), font));
+            }
+        }
+        let fallbacks = Fallbacks { face, families };
+        Ok(D2DFont {
+            format,
+            warnings,
+            decorations,
+            fallbacks,
+        })
     }
 }
 
-impl Font for D2DFont {}
+impl D2DFont {
+    /// The installed face of the font, and its size, for drawing glyphs by
+    /// id; `None` if no installed face matched.
+    pub(crate) fn glyph_face(&self) -> Option<(&dwrite::Font, f32)> {
+        let face = self.fallbacks.face.as_ref()?;
+        Some((face, self.format.get_font_size()))
+    }
+}
+
+impl Fallbacks {
+    /// The ranges of `text` the font has no glyphs for, each with the first
+    /// fallback family that has all of them.
+    ///
+    /// DirectWrite falls back to system fonts by itself, so this only
+    /// decides which family is tried first.
+    fn ranges(&self, text: &str) -> Result<Vec<(Range<usize>, String)>, Error> {
+        let mut ranges: Vec<(Range<usize>, String)> = Vec::new();
+        if self.families.is_empty() {
+            return Ok(ranges);
+        }
+        let has_all = |font: &dwrite::Font, grapheme: &str| -> Result<bool, Error> {
+            for c in grapheme.chars() {
+                if !font.has_character(c)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        };
+        for (i, grapheme) in text.grapheme_indices(true) {
+            if grapheme
+                .chars()
+                .all(|c| c.is_control() || c.is_whitespace())
+            {
+                continue;
+            }
+            if let Some(face) = &self.face {
+                if has_all(face, grapheme)? {
+                    continue;
+                }
+            }
+            let mut fallback = None;
+            for (family, font) in &self.families {
+                if has_all(font, grapheme)? {
+                    fallback = Some(family);
+                    break;
+                }
+            }
+            let range = i..i + grapheme.len();
+            match (ranges.last_mut(), fallback) {
+                (Some((last, last_family)), Some(family))
+                    if last.end == range.start && last_family == family =>
+                {
+                    last.end = range.end
+                }
+                (_, Some(family)) => ranges.push((range, family.clone())),
+                (_, None) => (),
+            }
+        }
+        Ok(ranges)
+    }
+}
+
+fn installed_substitute<'s>(
+    dwrite: &DwriteFactory,
+    substitute: &'s Option<String>,
+) -> Result<Option<&'s str>, Error> {
+    match substitute {
+        Some(substitute) if dwrite.has_system_font_family(substitute)? => Ok(Some(substitute)),
+        _ => Ok(None),
+    }
+}
+
+const FALLBACK_FAMILY: &str = "Segoe UI";
+
+/// Scale a font's decoration metrics from design units to `size`.
+fn decoration_metrics(metrics: &DWRITE_FONT_METRICS, size: f64) -> DecorationMetrics {
+    let scale = size / metrics.designUnitsPerEm as f64;
+    // DirectWrite measures positions upward from the baseline.
+    DecorationMetrics {
+        underline_offset: -(metrics.underlinePosition as f64) * scale,
+        underline_thickness: metrics.underlineThickness as f64 * scale,
+        strikethrough_offset: -(metrics.strikethroughPosition as f64) * scale,
+        strikethrough_thickness: metrics.strikethroughThickness as f64 * scale,
+    }
+}
+
+/// The families GDI and browsers on Windows use for the generics; these
+/// ship with every Windows install.
+fn generic_family_name(generic: GenericFamily) -> &'static str {
+    match generic {
+        GenericFamily::Serif => "Times New Roman",
+        GenericFamily::SansSerif => "Arial",
+        GenericFamily::Monospace => "Consolas",
+        GenericFamily::Cursive => "Comic Sans MS",
+        GenericFamily::Fantasy => "Impact",
+        GenericFamily::SystemUi => "Segoe UI",
+    }
+}
+
+impl Font for D2DFont {
+    fn warnings(&self) -> &[FontWarning] {
+        &self.warnings
+    }
+
+    fn decoration_metrics(&self) -> DecorationMetrics {
+        self.decorations
+    }
+}
 
 impl<'a> TextLayoutBuilder for D2DTextLayoutBuilder<'a> {
     type Out = D2DTextLayout;
 
+    fn obscure(mut self, mask: char) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    fn range_attribute(mut self, range: Range<usize>, attribute: TextAttribute) -> Self {
+        let end = range.end.min(self.text.len());
+        if range.start < end {
+            self.attributes.push((range.start..end, attribute));
+        }
+        self
+    }
+
+    fn default_color(mut self, color: Color) -> Self {
+        self.default_color = Some(color);
+        self
+    }
+
+    fn transform(self, _transform: Affine) -> Self {
+        // layouts use DirectWrite's ideal metrics, which scale exactly
+        self
+    }
+
+    fn max_width(mut self, width: f64) -> Self {
+        self.max_width = width;
+        self
+    }
+
+    fn alignment(mut self, alignment: TextAlignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    fn max_lines(mut self, lines: usize, truncation: TextTruncation) -> Self {
+        self.max_lines = Some((lines, truncation));
+        self
+    }
+
+    fn writing_mode(mut self, mode: WritingMode) -> Self {
+        self.writing_mode = mode;
+        self
+    }
+
     fn build(self) -> Result<Self::Out, Error> {
-        Ok(D2DTextLayout {
-            text: self.text,
-            layout: self
-                .builder
-                .width(1e6) // TODO: probably want to support wrapping
+        let obscured = self.mask.map(|mask| ObscuredText::new(&self.text, mask));
+        let text = match &obscured {
+            Some(obscured) => obscured.display().to_owned(),
+            None => self.text,
+        };
+        // DirectWrite wraps at the layout's width by default, breaking and
+        // hanging spaces the same way `wrap_lines` does.
+        let width = if self.max_width.is_finite() {
+            self.max_width.max(0.0) as f32
+        } else {
+            1e6
+        };
+        let fallback_ranges = self.fallbacks.ranges(&text)?;
+        let mut layout = D2DTextLayout {
+            layout: self.builder.text(&text).width(width).height(1e6).build()?,
+            text,
+            obscured,
+            truncated: None,
+            attributes: self.attributes,
+            format: self.format,
+            clusters: OnceCell::new(),
+            default_color: self.default_color,
+            emoji: self.emoji,
+            decorations: self.decorations,
+            fallback_ranges,
+        };
+        layout.apply_styles(self.dwrite, &layout.layout, 0..layout.text.len());
+        if let Some((max_lines, truncation)) = self.max_lines {
+            let factory = self.dwrite;
+            let lines: Vec<Range<usize>> = layout
+                .display_lines()
+                .into_iter()
+                .map(|(range, _)| range)
+                .collect();
+            let ellipsis_width = dwrite::TextLayoutBuilder::new(factory)
+                .format(&layout.format)
+                .text(TruncatedText::ELLIPSIS)
+                .width(1e6)
                 .height(1e6)
-                .build()?,
+                .build()?
+                .get_metrics()
+                .width as f64;
+            let truncated = TruncatedText::new(
+                &layout.text,
+                &lines,
+                max_lines,
+                truncation,
+                self.max_width,
+                ellipsis_width,
+                |range| layout.measure(factory, range),
+            );
+            if let Some(truncated) = truncated {
+                // the display text wraps the same way, up to the cut, and the
+                // ellipsis fits on the last line
+                layout.text = truncated.display().to_owned();
+                layout.fallback_ranges = self.fallbacks.ranges(&layout.text)?;
+                layout.layout = dwrite::TextLayoutBuilder::new(factory)
+                    .format(&layout.format)
+                    .text(&layout.text)
+                    .width(width)
+                    .height(1e6)
+                    .build()?;
+                layout.truncated = Some(truncated);
+                layout.apply_styles(factory, &layout.layout, 0..layout.text.len());
+            }
+        }
+        // DirectWrite runs the bidi algorithm itself, but takes the reading
+        // direction from the layout rather than each paragraph; the first
+        // one's is used
+        let rtl = paragraph_is_rtl(&layout.text);
+        if rtl {
+            layout.layout.set_right_to_left();
+        }
+        if self.alignment != TextAlignment::Start || rtl {
+            if !self.max_width.is_finite() {
+                // align across the widest line, rather than the placeholder
+                // width
+                let widest = layout.layout.get_metrics().width;
+                layout.layout.set_unwrapped_width(widest);
+            }
+            layout.layout.set_text_alignment(self.alignment, rtl);
+        }
+        // before Windows 8.1 the text stays horizontal
+        if self.writing_mode == WritingMode::VerticalRightToLeft
+            && layout.layout.set_vertical(width).is_ok()
+        {
+            // columns flow in from the right edge, so the layout is made as
+            // narrow as they are once they're laid out
+            let columns = layout.layout.get_metrics();
+            let length = if self.max_width.is_finite() {
+                width
+            } else {
+                columns.height
+            };
+            layout.layout.set_size(columns.width, length);
+        }
+        Ok(layout)
+    }
+}
+
+impl D2DTextLayout {
+    /// A layout for each grapheme cluster of the displayed text, with its
+    /// start and offset from the origin.
+    ///
+    /// DirectWrite only hands out glyph runs to a custom text renderer, so
+    /// clusters are laid out on their own instead.
+    pub(crate) fn clusters(&self, dwrite: &DwriteFactory) -> &[(usize, Point, dwrite::TextLayout)] {
+        self.clusters.get_or_init(|| {
+            self.text
+                .grapheme_indices(true)
+                .filter_map(|(i, grapheme)| {
+                    let offset = self.hit_test_text_position_16(count_utf16(&self.text[..i]))?;
+                    let layout = dwrite::TextLayoutBuilder::new(dwrite)
+                        .format(&self.format)
+                        .text(grapheme)
+                        .width(1e6)
+                        .height(1e6)
+                        .build()
+                        .ok()?;
+                    self.apply_styles(dwrite, &layout, i..i + grapheme.len());
+                    Some((i, offset, layout))
+                })
+                .collect()
         })
     }
+
+    /// The width of `range` of the displayed text, laid out on one line.
+    fn measure(&self, dwrite: &DwriteFactory, range: Range<usize>) -> f64 {
+        let layout = dwrite::TextLayoutBuilder::new(dwrite)
+            .format(&self.format)
+            .text(&self.text[range.clone()])
+            .width(1e6)
+            .height(1e6)
+            .build();
+        match layout {
+            Ok(layout) => {
+                self.apply_styles(dwrite, &layout, range);
+                layout.get_metrics().width as f64
+            }
+            // too wide to show
+            Err(_) => f64::INFINITY,
+        }
+    }
+
+    /// Set the attributes that change the font, its spacing or its
+    /// decorations, and the fallback families, on `layout`, which shows `range` of the displayed
+    /// text.
+    ///
+    /// DirectWrite applies them to runs of the text, so a line is as tall as
+    /// the largest font on it.
+    fn apply_styles(
+        &self,
+        dwrite: &DwriteFactory,
+        layout: &dwrite::TextLayout,
+        range: Range<usize>,
+    ) {
+        for (display, family) in &self.fallback_ranges {
+            let start = display.start.max(range.start);
+            let end = display.end.min(range.end);
+            if start < end {
+                let start_16 = count_utf16(&self.text[range.start..start]) as u32;
+                let len_16 = count_utf16(&self.text[start..end]) as u32;
+                layout.set_font_family(family, start_16, len_16);
+            }
+        }
+        for (original, attribute) in &self.attributes {
+            let display = self.to_display_range(original.clone());
+            let start = display.start.max(range.start);
+            let end = display.end.min(range.end);
+            if start >= end {
+                continue;
+            }
+            let start_16 = count_utf16(&self.text[range.start..start]) as u32;
+            let len_16 = count_utf16(&self.text[start..end]) as u32;
+            match *attribute {
+                TextAttribute::Size(size) if size > 0.0 && size.is_finite() => {
+                    layout.set_font_size(size as f32, start_16, len_16)
+                }
+                TextAttribute::Weight(weight) => layout.set_font_weight(weight, start_16, len_16),
+                TextAttribute::Italic(italic) => layout.set_italic(italic, start_16, len_16),
+                TextAttribute::Underline(underline) => {
+                    layout.set_underline(underline, start_16, len_16)
+                }
+                TextAttribute::Strikethrough(strikethrough) => {
+                    layout.set_strikethrough(strikethrough, start_16, len_16)
+                }
+                _ => (),
+            }
+        }
+        self.apply_spacing(layout, range.clone());
+        self.apply_features(dwrite, layout, range);
+    }
+
+    /// Set the OpenType features on `layout`, which shows `range` of the
+    /// displayed text.
+    ///
+    /// DirectWrite takes one set of features for each range, so they're
+    /// worked out cluster by cluster, and set on runs of clusters with the
+    /// same ones.
+    fn apply_features(
+        &self,
+        dwrite: &DwriteFactory,
+        layout: &dwrite::TextLayout,
+        range: Range<usize>,
+    ) {
+        let featured = self
+            .attributes
+            .iter()
+            .any(|(_, attribute)| matches!(attribute, TextAttribute::FontFeature(_)));
+        if !featured {
+            return;
+        }
+        // (start, length, features) in utf-16
+        let mut runs: Vec<(u32, u32, Vec<FontFeature>)> = Vec::new();
+        let mut position_16 = 0;
+        for (i, grapheme) in self.text[range.clone()].grapheme_indices(true) {
+            let len_16 = count_utf16(grapheme) as u32;
+            let features = self.features_at(range.start + i);
+            match runs.last_mut() {
+                Some((_, len, last)) if *last == features => *len += len_16,
+                _ => runs.push((position_16, len_16, features)),
+            }
+            position_16 += len_16;
+        }
+        for (start_16, len_16, features) in runs {
+            if !features.is_empty() {
+                // the font's own features are kept if this fails
+                let _ = layout.set_font_features(dwrite, &features, start_16, len_16);
+            }
+        }
+    }
+
+    /// The features at `position` of the displayed text, one for each tag,
+    /// with later ranges taking precedence.
+    fn features_at(&self, position: usize) -> Vec<FontFeature> {
+        let original = self.to_original(self.styled_position(position));
+        let mut features: Vec<FontFeature> = Vec::new();
+        for (range, attribute) in &self.attributes {
+            if let TextAttribute::FontFeature(feature) = attribute {
+                if range.contains(&original) {
+                    features.retain(|f| f.tag() != feature.tag());
+                    features.push(*feature);
+                }
+            }
+        }
+        features
+    }
+
+    /// Set the letter and word spacing on `layout`, which shows `range` of
+    /// the displayed text.
+    ///
+    /// Word spacing only widens some clusters, so the spacing is worked out
+    /// cluster by cluster, and set on runs of clusters spaced alike.
+    fn apply_spacing(&self, layout: &dwrite::TextLayout, range: Range<usize>) {
+        let spaced = self.attributes.iter().any(|(_, attribute)| {
+            matches!(
+                attribute,
+                TextAttribute::LetterSpacing(_) | TextAttribute::WordSpacing(_)
+            )
+        });
+        if !spaced {
+            return;
+        }
+        // (start, length, spacing) in utf-16
+        let mut runs: Vec<(u32, u32, f64)> = Vec::new();
+        let mut position_16 = 0;
+        for (i, grapheme) in self.text[range.clone()].grapheme_indices(true) {
+            let len_16 = count_utf16(grapheme) as u32;
+            let spacing = self.spacing_after(range.start + i, grapheme);
+            match runs.last_mut() {
+                Some((_, len, last)) if *last == spacing => *len += len_16,
+                _ => runs.push((position_16, len_16, spacing)),
+            }
+            position_16 += len_16;
+        }
+        for (start_16, len_16, spacing) in runs {
+            if spacing != 0.0 {
+                layout.set_character_spacing(spacing as f32, start_16, len_16);
+            }
+        }
+    }
+
+    /// The space added after `grapheme`, at `position` of the displayed text.
+    fn spacing_after(&self, position: usize, grapheme: &str) -> f64 {
+        let original = self.to_original(self.styled_position(position));
+        let (mut letter, mut word) = (0.0, 0.0);
+        for (range, attribute) in &self.attributes {
+            if !range.contains(&original) {
+                continue;
+            }
+            match *attribute {
+                TextAttribute::LetterSpacing(spacing) if spacing.is_finite() => letter = spacing,
+                TextAttribute::WordSpacing(spacing) if spacing.is_finite() => word = spacing,
+                _ => (),
+            }
+        }
+        if piet::is_word_separator(grapheme) {
+            letter + word
+        } else {
+            letter
+        }
+    }
+
+    /// The offset of the left edge of the cluster at a utf-16 position in
+    /// the displayed text, which is its trailing edge when it's right to
+    /// left.
+    fn hit_test_text_position_16(&self, idx_16: usize) -> Option<Point> {
+        let idx_16 = idx_16.try_into().ok()?;
+        // the leading edge, see the directwrite bool bug in hit_test_text_position
+        self.layout
+            .hit_test_text_position(idx_16, true)
+            .map(|http| Point::new(http.metrics.left as f64, http.point_y as f64))
+    }
+
+    /// DirectWrite's hit test for a position in the displayed text.
+    ///
+    /// Positions past the end are clamped, and positions inside a grapheme
+    /// cluster round down to its start, rather than panicking.
+    fn hit_test_display_position(&self, position: usize) -> Option<dwrite::HitTestTextPosition> {
+        let position = if position >= self.text.len() {
+            self.text.len()
+        } else {
+            self.text
+                .grapheme_indices(true)
+                .map(|(i, _)| i)
+                .take_while(|&i| i <= position)
+                .last()
+                .unwrap_or(0)
+        };
+        // Note: Directwrite will just return the line width if text position is
+        // out of bounds. This is what want for piet; return line width for the last text position
+        // (equal to line.len()). This is basically returning line width for the last cursor
+        // position.
+        let idx_16 = count_utf16(&self.text[..position]).try_into().ok()?;
+
+        // TODO quick fix until directwrite fixes bool bug
+        let trailing = true;
+        self.layout.hit_test_text_position(idx_16, trailing)
+    }
+
+    /// The baseline of the first line, from the top of the layout.
+    ///
+    /// DirectWrite positions are relative to the top of the layout, but
+    /// piet positions text by its first baseline. Lines share a height, so
+    /// the top of a line is its baseline's offset from the first.
+    fn first_baseline(&self) -> f64 {
+        if self.layout.is_vertical() {
+            // vertical layouts are positioned by their top-left corner
+            return 0.0;
+        }
+        let mut line_metrics = Vec::with_capacity(1);
+        self.layout.get_line_metrics(&mut line_metrics);
+        line_metrics.first().map(|l| l.baseline).unwrap_or(0.0) as f64
+    }
+
+    /// Split the text into emoji drawn as bitmaps, with their bounds
+    /// relative to the origin, and the ranges of the displayed text they
+    /// cover, which must not be drawn by the font.
+    ///
+    /// Returns `None` when there are no bitmaps to draw.
+    pub(crate) fn split_emoji(&self) -> Option<(EmojiBitmaps<'_>, Vec<Range<usize>>)> {
+        let found = self.emoji.find(&self.text);
+        if found.is_empty() {
+            return None;
+        }
+        let hidden = found.iter().map(|(range, _)| range.clone()).collect();
+        let bitmaps = found
+            .into_iter()
+            .filter_map(|(range, bitmap)| {
+                let rect = self
+                    .display_range_rects(range)?
+                    .into_iter()
+                    .fold(None, |acc: Option<Rect>, rect| {
+                        Some(acc.map(|acc| acc.union(rect)).unwrap_or(rect))
+                    })?;
+                Some((rect, bitmap))
+            })
+            .collect();
+        Some((bitmaps, hidden))
+    }
+
+    /// The layout's colors as utf-16 ranges of the displayed text, in the
+    /// order to apply them, or `None` where text is drawn with the brush.
+    ///
+    /// The first range covers the whole text, so applying them all replaces
+    /// whatever colors a previous draw left on the layout.
+    pub(crate) fn color_ranges_16(&self) -> Vec<(u32, u32, Option<&Color>)> {
+        let mut ranges = vec![(
+            0,
+            count_utf16(&self.text) as u32,
+            self.default_color.as_ref(),
+        )];
+        for (range, attribute) in &self.attributes {
+            if let TextAttribute::Foreground(color) = attribute {
+                let range = self.to_display_range(range.clone());
+                let start_16 = count_utf16(&self.text[..range.start]) as u32;
+                let len_16 = count_utf16(&self.text[range]) as u32;
+                ranges.push((start_16, len_16, Some(color)));
+            }
+        }
+        ranges
+    }
+
+    /// The color of the text at `index` in the displayed text, or `None`
+    /// where it's drawn with the brush.
+    pub(crate) fn color_at(&self, index: usize) -> Option<&Color> {
+        let original = self.to_original(self.styled_position(index));
+        self.attributes
+            .iter()
+            .rev()
+            .find_map(|(range, attribute)| match attribute {
+                TextAttribute::Foreground(color) if range.contains(&original) => Some(color),
+                _ => None,
+            })
+            .or(self.default_color.as_ref())
+    }
+
+    pub(crate) fn has_colors(&self) -> bool {
+        self.default_color.is_some()
+            || self
+                .attributes
+                .iter()
+                .any(|(_, attribute)| matches!(attribute, TextAttribute::Foreground(_)))
+    }
+
+    /// The background rectangles to paint before the glyphs, relative to the
+    /// baseline origin, in painting order.
+    ///
+    /// A range split by bidi reordering gets one rectangle per run.
+    pub(crate) fn backgrounds(&self) -> Vec<(Rect, &Color)> {
+        let mut backgrounds = Vec::new();
+        for (range, attribute) in &self.attributes {
+            if let TextAttribute::Background(color) = attribute {
+                for rect in self.range_rects(range.clone()).unwrap_or_default() {
+                    backgrounds.push((rect, color));
+                }
+            }
+        }
+        backgrounds
+    }
+
+    /// The range of each line of the displayed text, without the line break
+    /// or the spaces it wrapped at, with DirectWrite's metrics for it.
+    fn display_lines(&self) -> Vec<(Range<usize>, DWRITE_LINE_METRICS)> {
+        let mut metrics = Vec::new();
+        self.layout.get_line_metrics(&mut metrics);
+        let last = metrics.len().saturating_sub(1);
+        let to_utf8 = |position_16| {
+            count_until_utf16(&self.text, position_16).unwrap_or_else(|| self.text.len())
+        };
+        let mut start_16 = 0;
+        metrics
+            .into_iter()
+            .enumerate()
+            .map(|(i, m)| {
+                // the line break, or the spaces the line wrapped at
+                let hidden = if m.newlineLength > 0 {
+                    m.newlineLength
+                } else if i < last {
+                    m.trailingWhitespaceLength
+                } else {
+                    0
+                };
+                let end_16 = start_16 + (m.length - hidden) as usize;
+                let range = to_utf8(start_16)..to_utf8(end_16);
+                start_16 += m.length as usize;
+                (range, m)
+            })
+            .collect()
+    }
+
+    /// A position in the original text as a position in the displayed text,
+    /// which differs when it's obscured or truncated.
+    fn to_display(&self, position: usize) -> usize {
+        let position = match &self.obscured {
+            Some(obscured) => obscured.to_display(position),
+            None => position,
+        };
+        match &self.truncated {
+            Some(truncated) => truncated.to_display(position),
+            None => position,
+        }
+    }
+
+    /// The inverse of `to_display`.
+    fn to_original(&self, position: usize) -> usize {
+        let position = match &self.truncated {
+            Some(truncated) => truncated.to_original(position),
+            None => position,
+        };
+        match &self.obscured {
+            Some(obscured) => obscured.to_original(position),
+            None => position,
+        }
+    }
+
+    /// The position in the displayed text whose style the text at
+    /// `position` is drawn in; the ellipsis takes that of the text before it.
+    fn styled_position(&self, position: usize) -> usize {
+        match &self.truncated {
+            Some(truncated) if position >= truncated.cut() => truncated.cut().saturating_sub(1),
+            _ => position,
+        }
+    }
+
+    fn original_len(&self) -> usize {
+        match (&self.obscured, &self.truncated) {
+            (Some(obscured), _) => obscured.original_len(),
+            (None, Some(truncated)) => truncated.original_len(),
+            (None, None) => self.text.len(),
+        }
+    }
+
+    fn to_display_range(&self, range: Range<usize>) -> Range<usize> {
+        self.to_display(range.start)..self.to_display(range.end)
+    }
+
+    fn to_original_range(&self, range: Range<usize>) -> Range<usize> {
+        self.to_original(range.start)..self.to_original(range.end)
+    }
+
+    /// The rectangles covering `range`, one per run, relative to the baseline.
+    fn range_rects(&self, range: Range<usize>) -> Option<Vec<Rect>> {
+        self.display_range_rects(self.to_display_range(range))
+    }
+
+    /// Like `range_rects`, for a range of the displayed text.
+    fn display_range_rects(&self, range: Range<usize>) -> Option<Vec<Rect>> {
+        // ranges from callers may split a character
+        let range = piet::grapheme_range(&self.text, range);
+        let end = range.end;
+        if range.start >= end {
+            return None;
+        }
+        let start_16 = count_utf16(&self.text[..range.start]).try_into().ok()?;
+        let len_16 = count_utf16(&self.text[range.start..end]).try_into().ok()?;
+
+        let baseline = self.first_baseline();
+
+        let rects = self
+            .layout
+            .hit_test_text_range(start_16, len_16)
+            .iter()
+            .map(|m| {
+                Rect::new(
+                    m.left as f64,
+                    m.top as f64 - baseline,
+                    (m.left + m.width) as f64,
+                    (m.top + m.height) as f64 - baseline,
+                )
+            })
+            .collect();
+        Some(rects)
+    }
 }
 
 impl TextLayout for D2DTextLayout {
     fn width(&self) -> f64 {
-        self.layout.get_metrics().width as f64
+        // aligned lines start past the left edge
+        let metrics = self.layout.get_metrics();
+        (metrics.left + metrics.width) as f64
     }
 
     fn hit_test_point(&self, point: Point) -> HitTestPoint {
         // lossy from f64 to f32, but shouldn't have too much impact
-        let htp = self.layout.hit_test_point(point.x as f32, point.y as f32);
+        let y = point.y + self.first_baseline();
+        let htp = self.layout.hit_test_point(point.x as f32, y as f32);
 
         // Round up to next grapheme cluster boundary if directwrite
         // reports a trailing hit.
@@ -122,44 +1054,103 @@ impl TextLayout for D2DTextLayout {
         let text_position =
             count_until_utf16(&self.text, text_position_16).unwrap_or_else(|| self.text.len());
 
+        let text_position = self.to_original(text_position);
+
         HitTestPoint {
             metrics: HitTestMetrics { text_position },
             is_inside: htp.is_inside,
         }
     }
 
-    // Can panic if text position is not at a code point boundary, or if it's out of bounds.
     fn hit_test_text_position(&self, text_position: usize) -> Option<HitTestTextPosition> {
-        // Note: Directwrite will just return the line width if text position is
-        // out of bounds. This is what want for piet; return line width for the last text position
-        // (equal to line.len()). This is basically returning line width for the last cursor
-        // position.
+        // Positions in obscured or truncated text are into the original; the
+        // layout only knows the mask characters and the ellipsis.
+        let display_position = self.to_display(text_position);
+        let text_position = text_position.min(self.original_len());
+        self.hit_test_display_position(display_position)
+            .map(|http| HitTestTextPosition {
+                point: Point {
+                    x: http.point_x as f64,
+                    y: http.point_y as f64,
+                },
+                metrics: HitTestMetrics {
+                    text_position, // no need to use directwrite return value
+                },
+            })
+    }
 
-        // Now convert the utf8 index to utf16.
-        // This can panic;
-        let idx_16 = count_utf16(&self.text[0..text_position]);
+    fn range_bounds(&self, range: Range<usize>) -> Option<Rect> {
+        self.range_rects(range)?
+            .into_iter()
+            .fold(None, |acc: Option<Rect>, rect| {
+                Some(acc.map(|acc| acc.union(rect)).unwrap_or(rect))
+            })
+    }
 
-        // panic or Result are also fine options for dealing with overflow. Using Option here
-        // because it's already present and convenient.
-        // TODO this should probably go before convertin to utf16, since that's relatively slow
-        let idx_16 = idx_16.try_into().ok()?;
+    fn rects_for_range(&self, range: Range<usize>) -> Vec<Rect> {
+        self.range_rects(range).unwrap_or_default()
+    }
 
-        // TODO quick fix until directwrite fixes bool bug
-        let trailing = true;
+    fn caret_bounds(&self, text_position: usize) -> Option<Rect> {
+        let http = self.hit_test_display_position(self.to_display(text_position))?;
+        let x = http.point_x as f64;
+        let top = http.metrics.top as f64 - self.first_baseline();
+        Some(Rect::new(x, top, x, top + http.metrics.height as f64))
+    }
 
-        self.layout
-            .hit_test_text_position(idx_16, trailing)
-            .map(|http| {
-                HitTestTextPosition {
-                    point: Point {
-                        x: http.point_x as f64,
-                        y: http.point_y as f64,
-                    },
-                    metrics: HitTestMetrics {
-                        text_position, // no need to use directwrite return value
-                    },
+    fn truncated_at(&self) -> Option<usize> {
+        let cut = self.truncated.as_ref()?.cut();
+        Some(match &self.obscured {
+            Some(obscured) => obscured.to_original(cut),
+            None => cut,
+        })
+    }
+
+    fn line_metrics(&self) -> Vec<LineMetric> {
+        let lines = self.display_lines();
+        let first_baseline = lines.first().map(|(_, m)| m.baseline).unwrap_or(0.0) as f64;
+        let mut top = 0.0;
+        lines
+            .into_iter()
+            .map(|(range, m)| {
+                let metric = LineMetric {
+                    range: self.to_original_range(range),
+                    baseline: top + m.baseline as f64 - first_baseline,
+                    ascent: m.baseline as f64,
+                    descent: (m.height - m.baseline) as f64,
+                    height: m.height as f64,
+                };
+                top += m.height as f64;
+                metric
+            })
+            .collect()
+    }
+
+    fn cluster_metrics(&self) -> Vec<ClusterMetric> {
+        let lines = self.line_metrics();
+        self.text
+            .grapheme_indices(true)
+            .map(|(i, _)| {
+                let original = self.to_original(i);
+                let baseline = lines
+                    .iter()
+                    .rev()
+                    .find(|line| line.range.start <= original)
+                    .map_or(0.0, |line| line.baseline);
+                let (x, advance) = match self.hit_test_display_position(i) {
+                    Some(http) => (http.metrics.left as f64, http.metrics.width as f64),
+                    None => (0.0, 0.0),
+                };
+                ClusterMetric {
+                    origin: Point::new(x, baseline),
+                    advance,
                 }
             })
+            .collect()
+    }
+
+    fn decoration_metrics(&self) -> DecorationMetrics {
+        self.decorations
     }
 }
 
@@ -215,6 +1206,21 @@ mod test {
         assert!(x <= max && x >= min);
     }
 
+    #[test]
+    fn test_thread_safety() {
+        fn send<T: Send>() {}
+        fn send_sync<T: Send + Sync>() {}
+        send_sync::<DwriteFactory>();
+        send_sync::<D2DText>();
+        send_sync::<D2DFont>();
+        send::<D2DTextLayout>();
+        send_sync::<crate::D2DImage>();
+        send_sync::<crate::D2DPath>();
+        send_sync::<crate::d2d::Brush>();
+        send_sync::<D2DFactory>();
+        send_sync::<D2DDevice>();
+    }
+
     #[test]
     fn test_hit_test_text_position_basic() {
         let dwrite = DwriteFactory::new().unwrap();
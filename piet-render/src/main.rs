@@ -0,0 +1,379 @@
+//! Draw a test picture, a conformance scene or a saved display list with a
+//! chosen backend, and write it to a file.
+//!
+//! ```text
+//! piet-render [--backend <name>] [--size <width>x<height>] [--scale <scale>] <input> <output>
+//! ```
+//!
+//! The input is the number of one of piet-test's pictures, the name of one
+//! of piet-conformance's scenes, or a `.json` file holding a
+//! `SerializedDisplayList`. The output's extension picks what's written:
+//!
+//! - `.png`, drawn by the platform's raster backend, cairo or Direct2D.
+//! - `.pdf`, drawn by cairo.
+//! - `.svg`, drawn by piet-svg, or by cairo when asked for.
+//! - `.json`, the input recorded as a display list, with its text laid
+//!   out by the backend. This is the file to attach to a bug report, as
+//!   it draws the same with any backend that has the same fonts.
+//!
+//! With `--capabilities`, it prints what the backend supports instead, to
+//! go with a bug report.
+//!
+//! To see where cairo and piet-svg differ on test picture 9, say:
+//!
+//! ```text
+//! piet-render 9 picture-9.svg
+//! piet-render --backend cairo 9 picture-9-cairo.svg
+//! ```
+
+use std::env;
+use std::error::Error as StdError;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use piet::kurbo::{Affine, Size};
+use piet::{Error, RecordingContext, RenderContext, SerializedDisplayList, Text};
+use piet_common::Device;
+use piet_conformance::{Scene, SCENES};
+use piet_test::{draw_test_picture, TEST_PICTURE_COUNT};
+
+type Result<T> = std::result::Result<T, Box<dyn StdError>>;
+
+/// The name of the backend piet-common draws bitmaps with.
+#[cfg(not(target_os = "windows"))]
+const RASTER: &str = "cairo";
+#[cfg(target_os = "windows")]
+const RASTER: &str = "d2d";
+
+/// The size test pictures are drawn within.
+const PICTURE_SIZE: Size = Size::new(200.0, 100.0);
+
+fn usage() -> String {
+    format!(
+        "\
+usage: piet-render [options] <input> <output>
+       piet-render [--backend <name>] --capabilities
+
+Draws <input>, a test picture from 0 to {last}, a conformance scene or a
+display list saved as .json, and writes it to <output>, a .png, .pdf,
+.svg or .json file. With --capabilities, lists what the backend
+supports instead.
+
+options:
+    --backend <name>     {raster} or svg; by default, the one that writes
+                         the output's format
+    --size <w>x<h>       the size to draw within, before scaling; by
+                         default 200x100, or the scene's size
+    --scale <scale>      how much to scale the drawing up by; 1 if not
+                         given
+
+scenes: {scenes}",
+        last = TEST_PICTURE_COUNT - 1,
+        raster = RASTER,
+        scenes = SCENES
+            .iter()
+            .map(|scene| scene.name)
+            .collect::<Vec<_>>()
+            .join(", "),
+    )
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", usage());
+        return;
+    }
+    if let Err(e) = parse(&args).and_then(|command| command.run()) {
+        eprintln!("piet-render: {}", e);
+        process::exit(1);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Backend {
+    /// The backend piet-common picks for the platform.
+    Raster,
+    Svg,
+}
+
+impl Backend {
+    fn name(self) -> &'static str {
+        match self {
+            Backend::Raster => RASTER,
+            Backend::Svg => "svg",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Png,
+    Pdf,
+    Svg,
+    Json,
+}
+
+enum Command {
+    Render(Options),
+    /// Print the capabilities of a backend.
+    Report(Backend),
+}
+
+impl Command {
+    fn run(&self) -> Result<()> {
+        match self {
+            Command::Render(options) => options.run(),
+            Command::Report(backend) => {
+                let capabilities = match backend {
+                    Backend::Raster => {
+                        let mut device = Device::new()?;
+                        let mut target = device.bitmap_target(1, 1, 1.0)?;
+                        let capabilities = target.render_context().capabilities();
+                        capabilities
+                    }
+                    Backend::Svg => piet_svg::RenderContext::new().capabilities(),
+                };
+                print!("{}\n\n{}", backend.name(), capabilities);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// What to draw.
+enum Input {
+    Picture(usize),
+    Scene(&'static Scene),
+    List(SerializedDisplayList),
+}
+
+struct Options {
+    input: Input,
+    output: PathBuf,
+    format: Format,
+    backend: Backend,
+    size: Size,
+    scale: f64,
+}
+
+fn parse(args: &[String]) -> Result<Command> {
+    let mut backend = None;
+    let mut capabilities = false;
+    let mut size = None;
+    let mut scale = 1.0;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--backend" => {
+                backend = Some(match value()?.as_str() {
+                    name if name == RASTER => Backend::Raster,
+                    "svg" => Backend::Svg,
+                    name => return Err(format!("there's no backend called {}", name).into()),
+                })
+            }
+            "--size" => {
+                let value = value()?;
+                size =
+                    Some(parse_size(value).ok_or_else(|| {
+                        format!("the size should be <width>x<height>, not {}", value)
+                    })?)
+            }
+            "--capabilities" => capabilities = true,
+            "--scale" => {
+                let value = value()?;
+                scale = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|&scale| scale > 0.0 && scale.is_finite())
+                    .ok_or_else(|| format!("the scale should be above 0, not {}", value))?
+            }
+            arg if arg.starts_with("--") => {
+                return Err(format!("there's no option {}\n\n{}", arg, usage()).into())
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if capabilities {
+        if !paths.is_empty() {
+            return Err(usage().into());
+        }
+        return Ok(Command::Report(backend.unwrap_or(Backend::Raster)));
+    }
+    let (input, output) = match paths.as_slice() {
+        [input, output] => (read_input(input)?, PathBuf::from(output)),
+        _ => return Err(usage().into()),
+    };
+
+    let format = match output.extension().and_then(|ext| ext.to_str()) {
+        Some("png") => Format::Png,
+        Some("pdf") => Format::Pdf,
+        Some("svg") => Format::Svg,
+        Some("json") => Format::Json,
+        _ => {
+            return Err(format!(
+                "can't tell what to write to {}, which should end in .png, .pdf, .svg or .json",
+                output.display()
+            )
+            .into())
+        }
+    };
+    let backend = backend.unwrap_or(match format {
+        Format::Svg => Backend::Svg,
+        _ => Backend::Raster,
+    });
+    let size = size.unwrap_or(match input {
+        Input::Scene(scene) => Size::new(scene.width as f64, scene.height as f64),
+        _ => PICTURE_SIZE,
+    });
+    Ok(Command::Render(Options {
+        input,
+        output,
+        format,
+        backend,
+        size,
+        scale,
+    }))
+}
+
+impl Options {
+    fn run(&self) -> Result<()> {
+        match (self.backend, self.format) {
+            (Backend::Raster, Format::Png) => self.write_png(),
+            #[cfg(not(any(target_arch = "wasm32", target_os = "windows")))]
+            (Backend::Raster, Format::Pdf) => {
+                let size = self.size * self.scale;
+                let surface = cairo::PdfSurface::new(size.width, size.height, &self.output)
+                    .map_err(|e| cairo_error(&self.output, e))?;
+                self.draw_cairo(&surface)
+            }
+            #[cfg(not(any(target_arch = "wasm32", target_os = "windows")))]
+            (Backend::Raster, Format::Svg) => {
+                let size = self.size * self.scale;
+                let surface = cairo::SvgSurface::new(size.width, size.height, Some(&self.output))
+                    .map_err(|e| cairo_error(&self.output, e))?;
+                self.draw_cairo(&surface)
+            }
+            (Backend::Svg, Format::Svg) => self.write_svg(),
+            (Backend::Raster, Format::Json) => {
+                let mut device = Device::new()?;
+                let mut target = device.bitmap_target(1, 1, 1.0)?;
+                let text = target.render_context().text();
+                self.write_json(text)
+            }
+            (Backend::Svg, Format::Json) => self.write_json(piet_svg::Text::new()),
+            (backend, format) => Err(format!("{} can't write {:?}", backend.name(), format).into()),
+        }
+    }
+
+    /// Draw the input with `rc`, and finish.
+    fn draw(&self, rc: &mut impl RenderContext) -> Result<()> {
+        let drawn: std::result::Result<(), Error> = match &self.input {
+            Input::Picture(number) => draw_test_picture(rc, *number),
+            Input::Scene(scene) => scene.draw(rc),
+            Input::List(list) => list
+                .to_display_list(rc.text())
+                .and_then(|list| list.replay(rc)),
+        };
+        drawn.and_then(|()| rc.finish()).map_err(|e| {
+            let input = match &self.input {
+                Input::Picture(number) => format!("test picture {}", number),
+                Input::Scene(scene) => format!("the {} scene", scene.name),
+                Input::List(_) => "the display list".to_string(),
+            };
+            format!("{} couldn't draw {}: {}", self.backend.name(), input, e).into()
+        })
+    }
+
+    fn write_png(&self) -> Result<()> {
+        let size = (self.size * self.scale).ceil();
+        let mut device = Device::new()?;
+        let mut target =
+            device.bitmap_target(size.width as usize, size.height as usize, self.scale)?;
+        self.draw(&mut target.render_context())?;
+        target.save_to_file(&self.output)?;
+        Ok(())
+    }
+
+    #[cfg(not(any(target_arch = "wasm32", target_os = "windows")))]
+    fn draw_cairo(&self, surface: &cairo::Surface) -> Result<()> {
+        {
+            let mut ctx = cairo::Context::new(surface);
+            ctx.scale(self.scale, self.scale);
+            self.draw(&mut piet_cairo::CairoRenderContext::new(&mut ctx))?;
+        }
+        surface.finish();
+        match surface.status() {
+            cairo::Status::Success => Ok(()),
+            status => Err(cairo_error(&self.output, status).into()),
+        }
+    }
+
+    fn write_svg(&self) -> Result<()> {
+        let mut rc = piet_svg::RenderContext::new();
+        rc.set_size(self.size * self.scale);
+        rc.transform(Affine::scale(self.scale));
+        self.draw(&mut rc)?;
+        rc.write(BufWriter::new(create(&self.output)?))?;
+        Ok(())
+    }
+
+    /// Record the input with the text of a backend, and write it out.
+    fn write_json<T: Text + Clone>(&self, text: T) -> Result<()> {
+        let mut rc = RecordingContext::new(text);
+        self.draw(&mut rc)?;
+        serde_json::to_writer(BufWriter::new(create(&self.output)?), rc.display_list())?;
+        Ok(())
+    }
+}
+
+fn read_input(input: &str) -> Result<Input> {
+    if let Ok(number) = input.parse::<usize>() {
+        if number >= TEST_PICTURE_COUNT {
+            return Err(format!(
+                "there's no test picture {}, they go from 0 to {}",
+                number,
+                TEST_PICTURE_COUNT - 1
+            )
+            .into());
+        }
+        return Ok(Input::Picture(number));
+    }
+    if let Some(scene) = Scene::by_name(input) {
+        return Ok(Input::Scene(scene));
+    }
+    let file = File::open(input).map_err(|e| {
+        format!(
+            "{} isn't a test picture, a scene, or a display list that can be read: {}",
+            input, e
+        )
+    })?;
+    let list = serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("{} isn't a display list: {}", input, e))?;
+    Ok(Input::List(list))
+}
+
+/// The width and height in `<width>x<height>`.
+fn parse_size(size: &str) -> Option<Size> {
+    let mut parts = size.splitn(2, 'x').map(|part| part.parse::<f64>().ok());
+    let width = parts.next()??;
+    let height = parts.next()??;
+    if width > 0.0 && height > 0.0 && width.is_finite() && height.is_finite() {
+        Some(Size::new(width, height))
+    } else {
+        None
+    }
+}
+
+fn create(path: &Path) -> Result<File> {
+    File::create(path).map_err(|e| format!("couldn't create {}: {}", path.display(), e).into())
+}
+
+#[cfg(not(any(target_arch = "wasm32", target_os = "windows")))]
+fn cairo_error(path: &Path, status: cairo::Status) -> String {
+    format!("cairo couldn't write {}: {}", path.display(), status)
+}
@@ -0,0 +1,587 @@
+//! A retained scene graph for piet.
+//!
+//! A [`Scene`] keeps a tree of nodes, each with what it draws recorded in a
+//! display list, and remembers the parts of the scene that changed since it
+//! was last rendered. Rendering redraws only those parts, replaying only the
+//! nodes that reach into them, so a frame where one widget changed doesn't
+//! draw the whole window again.
+//!
+//! [`Scene`]: struct.Scene.html
+
+use piet::kurbo::{Affine, Rect, Size};
+use piet::{
+    new_error, Color, DisplayList, Error, ErrorKind, RecordingContext, RenderContext, Text,
+};
+
+/// How many separate damaged areas a scene keeps before merging the
+/// closest of them.
+const MAX_DAMAGE_RECTS: usize = 8;
+
+/// A tree of drawing nodes, redrawn where it changes.
+///
+/// Each node draws its recorded content, then its children in the order
+/// they were added, under its transform. Changing a node's content or
+/// transform, or removing it, marks the area it covered and covers as
+/// damaged; [`render`] then clears those areas to the background and draws
+/// the nodes that reach into them again, clipped to them. The rest of the
+/// target is left as the last render drew it, so the target has to keep its
+/// pixels from frame to frame.
+///
+/// Damage is kept as a few separate rectangles, so that changes at opposite
+/// corners don't redraw everything between them. Past a handful, the
+/// rectangles closest to each other are merged.
+///
+/// The scene is in the user space of the context it's rendered with, and
+/// damage is rounded out to whole units of it, so renders line up with the
+/// pixels when that space is the target's pixels. Content is recorded with
+/// the scene's [`Text`], so its text is laid out by the backend it's
+/// rendered with.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Rect, Size};
+/// use piet::{Color, NullRenderContext, RenderContext};
+/// use piet_scene::Scene;
+///
+/// let mut rc = NullRenderContext::new();
+/// let mut scene = Scene::new(rc.text(), Size::new(200.0, 100.0));
+/// let button = scene.add_child(scene.root()).unwrap();
+/// scene
+///     .record(button, |rc| {
+///         rc.fill(Rect::new(10.0, 10.0, 50.0, 30.0), &Color::WHITE);
+///         Ok(())
+///     })
+///     .unwrap();
+/// // the first render draws everything
+/// scene.render(&mut rc).unwrap();
+///
+/// // pressing the button changes only its color, and only its area is redrawn
+/// scene
+///     .record(button, |rc| {
+///         rc.fill(Rect::new(10.0, 10.0, 50.0, 30.0), &Color::BLACK);
+///         Ok(())
+///     })
+///     .unwrap();
+/// let redrawn = scene.render(&mut rc).unwrap();
+/// assert_eq!(redrawn.len(), 1);
+/// assert!(redrawn[0].width() < 50.0 && redrawn[0].height() < 30.0);
+/// ```
+///
+/// [`render`]: #method.render
+/// [`Text`]: ../piet/trait.Text.html
+pub struct Scene<T: Text> {
+    text: T,
+    size: Size,
+    background: Color,
+    /// The nodes by index, with `None` in the slots of removed ones.
+    nodes: Vec<Option<Node<T::TextLayout>>>,
+    /// The generation of each slot, bumped when its node is removed.
+    generations: Vec<u32>,
+    /// The slots free for new nodes.
+    free: Vec<usize>,
+    /// The areas that have changed since the last render.
+    damage: Vec<Rect>,
+}
+
+/// A node of a [`Scene`].
+///
+/// The id of a removed node is never valid again: the scene's queries
+/// return `None` for it, and its changes ignore it.
+///
+/// [`Scene`]: struct.Scene.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    generation: u32,
+}
+
+struct Node<L> {
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    transform: Affine,
+    content: DisplayList<L>,
+    /// The box around what the content paints, in the node's coordinates.
+    bounds: Option<Rect>,
+}
+
+impl<T: Text + Clone> Scene<T> {
+    /// An empty scene of `size`, cleared to transparent, which lays out text
+    /// with `text`. The whole scene is damaged, so the first render draws
+    /// all of it.
+    pub fn new(text: T, size: Size) -> Scene<T> {
+        let mut scene = Scene {
+            text,
+            size,
+            background: Color::rgba8(0, 0, 0, 0),
+            nodes: Vec::new(),
+            generations: Vec::new(),
+            free: Vec::new(),
+            damage: Vec::new(),
+        };
+        scene.insert(None);
+        scene.invalidate_all();
+        scene
+    }
+
+    /// The node at the root of the tree, which is never removed.
+    pub fn root(&self) -> NodeId {
+        NodeId {
+            index: 0,
+            generation: 0,
+        }
+    }
+
+    /// The size of the scene, from the origin.
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Resize the scene, damaging all of it.
+    pub fn set_size(&mut self, size: Size) {
+        self.size = size;
+        self.invalidate_all();
+    }
+
+    /// Set the color damaged areas are cleared to before they're drawn,
+    /// damaging the whole scene.
+    pub fn set_background(&mut self, color: Color) {
+        self.background = color;
+        self.invalidate_all();
+    }
+
+    /// Whether `id` is a node of the scene, and not one since removed.
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Add an empty node to the children of `parent`, drawn over those
+    /// before it, or `None` if `parent` was removed.
+    pub fn add_child(&mut self, parent: NodeId) -> Option<NodeId> {
+        self.get(parent)?;
+        let id = self.insert(Some(parent));
+        self.node_mut(parent).children.push(id);
+        Some(id)
+    }
+
+    /// The children of `id`, from the bottom up, or `None` if `id` was
+    /// removed.
+    pub fn children(&self, id: NodeId) -> Option<&[NodeId]> {
+        Some(&self.get(id)?.children)
+    }
+
+    /// The parent of `id`, or `None` for the root and for removed nodes.
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.get(id)?.parent
+    }
+
+    /// Remove `id` and the nodes under it, damaging what they drew.
+    ///
+    /// Removing the root instead removes its children and clears its
+    /// content. Removing a node that was already removed does nothing.
+    pub fn remove(&mut self, id: NodeId) {
+        if !self.contains(id) {
+            return;
+        }
+        if id == self.root() {
+            for child in self.node(id).children.clone() {
+                self.remove(child);
+            }
+            let empty = RecordingContext::new(self.text.clone()).into_display_list();
+            return self.set_content(id, empty);
+        }
+        self.invalidate_subtree(id);
+        if let Some(parent) = self.node(id).parent {
+            self.node_mut(parent).children.retain(|&child| child != id);
+        }
+        let mut removed = vec![id];
+        while let Some(id) = removed.pop() {
+            let node = self.nodes[id.index].take().unwrap();
+            removed.extend(node.children);
+            self.generations[id.index] += 1;
+            self.free.push(id.index);
+        }
+    }
+
+    /// The transform of `id`, from its coordinates to its parent's, or
+    /// `None` if `id` was removed.
+    pub fn transform(&self, id: NodeId) -> Option<Affine> {
+        Some(self.get(id)?.transform)
+    }
+
+    /// Set the transform of `id`, from its coordinates to its parent's,
+    /// damaging what it and the nodes under it draw before and after.
+    ///
+    /// Does nothing if `id` was removed.
+    pub fn set_transform(&mut self, id: NodeId, transform: Affine) {
+        if !self.contains(id) {
+            return;
+        }
+        self.invalidate_subtree(id);
+        self.node_mut(id).transform = transform;
+        self.invalidate_subtree(id);
+    }
+
+    /// Replace what `id` draws with what `f` draws, in the node's
+    /// coordinates, damaging what it drew before and draws now.
+    ///
+    /// If `f` returns an error, the node keeps what it drew before, and the
+    /// error is returned. If `id` was removed, `f` isn't called, and an
+    /// error is returned.
+    pub fn record(
+        &mut self,
+        id: NodeId,
+        f: impl FnOnce(&mut RecordingContext<T>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if !self.contains(id) {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        let mut rc = RecordingContext::new(self.text.clone());
+        f(&mut rc)?;
+        self.set_content(id, rc.into_display_list());
+        Ok(())
+    }
+
+    /// Damage `rect`, in the scene's coordinates, so the next render draws
+    /// it again.
+    pub fn invalidate(&mut self, rect: Rect) {
+        // antialiasing reaches a little past the edges of shapes
+        let rect = rect.abs().inflate(1.0, 1.0).expand();
+        if self.damage.iter().any(|&damage| covers(damage, rect)) {
+            return;
+        }
+        self.damage.retain(|&damage| !covers(rect, damage));
+        self.damage.push(rect);
+        if self.damage.len() > MAX_DAMAGE_RECTS {
+            self.merge_closest_damage();
+        }
+    }
+
+    /// Damage the whole scene.
+    pub fn invalidate_all(&mut self) {
+        self.invalidate(self.size.to_rect());
+    }
+
+    /// The areas the next render will draw, none if nothing has changed
+    /// since the last one.
+    pub fn damage(&self) -> Vec<Rect> {
+        let scene = self.size.to_rect();
+        self.damage
+            .iter()
+            .map(|damage| damage.intersect(scene))
+            .filter(|damage| damage.width() > 0.0 && damage.height() > 0.0)
+            .collect()
+    }
+
+    /// Draw the damaged areas of the scene with `rc`, and return them, none
+    /// if nothing has changed since the last render.
+    ///
+    /// Each area is cleared to the background, then drawn by the nodes that
+    /// reach into it, clipped to it; what's outside them is left alone. `rc`
+    /// has to be of the backend the scene's text is, since the recorded text
+    /// layouts are drawn as they are. If drawing fails, the damage is kept
+    /// for the next render.
+    pub fn render<R>(&mut self, rc: &mut R) -> Result<Vec<Rect>, Error>
+    where
+        R: RenderContext<TextLayout = T::TextLayout>,
+    {
+        let damage = self.damage();
+        for &area in &damage {
+            rc.with_save(|rc| {
+                rc.clip(area);
+                rc.clear_rect(area, self.background.clone());
+                self.draw(rc, self.root(), Affine::default(), area)
+            })?;
+        }
+        self.damage.clear();
+        Ok(damage)
+    }
+
+    /// Draw `id` and the nodes under it that reach into `damage`, where
+    /// `parent` is the transform from its parent's coordinates to the
+    /// scene's.
+    fn draw<R>(&self, rc: &mut R, id: NodeId, parent: Affine, damage: Rect) -> Result<(), Error>
+    where
+        R: RenderContext<TextLayout = T::TextLayout>,
+    {
+        let node = self.node(id);
+        let transform = parent * node.transform;
+        rc.with_save(|rc| {
+            rc.transform(node.transform);
+            if let Some(bounds) = self.scene_bounds(transform, node.bounds) {
+                if overlaps(bounds, damage) {
+                    node.content.replay(rc)?;
+                }
+            }
+            for &child in &node.children {
+                self.draw(rc, child, transform, damage)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn insert(&mut self, parent: Option<NodeId>) -> NodeId {
+        let node = Node {
+            parent,
+            children: Vec::new(),
+            transform: Affine::default(),
+            content: RecordingContext::new(self.text.clone()).into_display_list(),
+            bounds: None,
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = Some(node);
+                index
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.generations.push(0);
+                self.nodes.len() - 1
+            }
+        };
+        NodeId {
+            index,
+            generation: self.generations[index],
+        }
+    }
+
+    fn get(&self, id: NodeId) -> Option<&Node<T::TextLayout>> {
+        match self.nodes.get(id.index) {
+            Some(Some(node)) if self.generations[id.index] == id.generation => Some(node),
+            _ => None,
+        }
+    }
+
+    /// The node of `id`, which the caller knows is in the scene.
+    fn node(&self, id: NodeId) -> &Node<T::TextLayout> {
+        match self.get(id) {
+            Some(node) => node,
+            None => panic!("node {:?} was removed from the scene", id),
+        }
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut Node<T::TextLayout> {
+        self.node(id);
+        self.nodes[id.index].as_mut().unwrap()
+    }
+
+    fn set_content(&mut self, id: NodeId, content: DisplayList<T::TextLayout>) {
+        let transform = self.scene_transform(id);
+        let old = self.scene_bounds(transform, self.node(id).bounds);
+        let bounds = content.paint_bounds();
+        let node = self.node_mut(id);
+        node.content = content;
+        node.bounds = bounds;
+        let new = self.scene_bounds(transform, bounds);
+        for rect in old.into_iter().chain(new) {
+            self.invalidate(rect);
+        }
+    }
+
+    /// The transform from the coordinates of `id` to the scene's.
+    fn scene_transform(&self, id: NodeId) -> Affine {
+        let node = self.node(id);
+        match node.parent {
+            Some(parent) => self.scene_transform(parent) * node.transform,
+            None => node.transform,
+        }
+    }
+
+    /// `bounds` in the scene's coordinates. Bounds that aren't finite, like
+    /// those of a clear, cover the whole scene.
+    fn scene_bounds(&self, transform: Affine, bounds: Option<Rect>) -> Option<Rect> {
+        let bounds = bounds?;
+        let finite = [bounds.x0, bounds.y0, bounds.x1, bounds.y1]
+            .iter()
+            .all(|v| v.is_finite());
+        if finite {
+            Some(transform.transform_rect_bbox(bounds))
+        } else {
+            Some(self.size.to_rect())
+        }
+    }
+
+    /// Damage what `id` and the nodes under it draw.
+    fn invalidate_subtree(&mut self, id: NodeId) {
+        let mut damaged = Vec::new();
+        let mut pending = vec![(id, self.scene_transform(id))];
+        while let Some((id, transform)) = pending.pop() {
+            let node = self.node(id);
+            damaged.extend(self.scene_bounds(transform, node.bounds));
+            for &child in &node.children {
+                pending.push((child, transform * self.node(child).transform));
+            }
+        }
+        for rect in damaged {
+            self.invalidate(rect);
+        }
+    }
+
+    /// Merge the two damaged areas whose union covers the least area that
+    /// neither of them did.
+    fn merge_closest_damage(&mut self) {
+        let area = |rect: Rect| rect.width() * rect.height();
+        let mut closest = (0, 1, f64::INFINITY);
+        for i in 0..self.damage.len() {
+            for j in i + 1..self.damage.len() {
+                let (a, b) = (self.damage[i], self.damage[j]);
+                let waste = area(a.union(b)) - area(a) - area(b);
+                if waste < closest.2 {
+                    closest = (i, j, waste);
+                }
+            }
+        }
+        let (i, j, _) = closest;
+        let merged = self.damage.swap_remove(j);
+        self.damage[i] = self.damage[i].union(merged);
+    }
+}
+
+/// Whether `outer` covers all of `inner`.
+fn covers(outer: Rect, inner: Rect) -> bool {
+    outer.x0 <= inner.x0 && outer.y0 <= inner.y0 && outer.x1 >= inner.x1 && outer.y1 >= inner.y1
+}
+
+/// Whether two rectangles share some area.
+fn overlaps(a: Rect, b: Rect) -> bool {
+    let common = a.intersect(b);
+    common.width() > 0.0 && common.height() > 0.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use piet::kurbo::Vec2;
+    use piet::{DrawCommand, NullText, RecordingText};
+
+    type TestScene = Scene<RecordingText<NullText>>;
+
+    fn fill(scene: &mut TestScene, id: NodeId, rect: Rect, color: Color) {
+        scene
+            .record(id, |rc| {
+                rc.fill(rect, &color);
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    fn fills<L>(list: &DisplayList<L>) -> usize {
+        list.commands()
+            .iter()
+            .filter(|command| matches!(command, DrawCommand::Fill { .. }))
+            .count()
+    }
+
+    #[test]
+    fn redraw_changed_node() {
+        let mut target = RecordingContext::new(NullText);
+        let mut scene = Scene::new(target.text(), Size::new(100.0, 100.0));
+        let left = scene.add_child(scene.root()).unwrap();
+        let right = scene.add_child(scene.root()).unwrap();
+        let square = Rect::new(0.0, 0.0, 20.0, 20.0);
+        fill(&mut scene, left, square, Color::WHITE);
+        fill(
+            &mut scene,
+            right,
+            square + Vec2::new(60.0, 0.0),
+            Color::WHITE,
+        );
+        scene.render(&mut target).unwrap();
+        assert_eq!(fills(target.display_list()), 2);
+        assert!(scene.damage().is_empty());
+
+        let mut target = RecordingContext::new(NullText);
+        fill(
+            &mut scene,
+            right,
+            square + Vec2::new(60.0, 0.0),
+            Color::BLACK,
+        );
+        let damage = scene.render(&mut target).unwrap();
+        assert_eq!(damage.len(), 1);
+        assert_eq!((damage[0].x0, damage[0].x1), (59.0, 81.0));
+        assert_eq!(fills(target.display_list()), 1);
+    }
+
+    #[test]
+    fn move_and_remove() {
+        let mut target = RecordingContext::new(NullText);
+        let mut scene = Scene::new(target.text(), Size::new(100.0, 100.0));
+        let group = scene.add_child(scene.root()).unwrap();
+        let dot = scene.add_child(group).unwrap();
+        fill(
+            &mut scene,
+            dot,
+            Rect::new(0.0, 0.0, 10.0, 10.0),
+            Color::WHITE,
+        );
+        scene.render(&mut target).unwrap();
+
+        // moving the group damages where the dot was and where it is, and
+        // not the space between
+        scene.set_transform(group, Affine::translate((50.0, 0.0)));
+        let damage = scene.damage();
+        let spans: Vec<_> = damage.iter().map(|rect| (rect.x0, rect.x1)).collect();
+        assert_eq!(spans, [(0.0, 11.0), (49.0, 61.0)]);
+        scene.render(&mut target).unwrap();
+
+        scene.remove(group);
+        let damage = scene.damage();
+        assert_eq!((damage[0].x0, damage[0].x1), (49.0, 61.0));
+        assert_eq!(scene.children(scene.root()), Some(&[][..]));
+        let id = scene.add_child(scene.root()).unwrap();
+        assert_ne!(id, dot);
+    }
+
+    #[test]
+    fn removed_ids() {
+        let mut target = RecordingContext::new(NullText);
+        let mut scene = Scene::new(target.text(), Size::new(100.0, 100.0));
+        let group = scene.add_child(scene.root()).unwrap();
+        let dot = scene.add_child(group).unwrap();
+        scene.remove(group);
+        assert!(!scene.contains(dot));
+        assert_eq!(scene.children(dot), None);
+        assert_eq!(scene.parent(dot), None);
+        assert!(scene.transform(dot).is_none());
+        assert_eq!(scene.add_child(dot), None);
+        assert!(scene.record(dot, |_| Ok(())).is_err());
+
+        // changes to them are ignored, even once the slot is reused
+        let reused = scene.add_child(scene.root()).unwrap();
+        scene.render(&mut target).unwrap();
+        scene.set_transform(dot, Affine::translate((10.0, 0.0)));
+        scene.remove(dot);
+        assert!(scene.contains(reused));
+        assert!(scene.damage().is_empty());
+    }
+
+    #[test]
+    fn damage_merges_past_a_few_rects() {
+        let mut target = RecordingContext::new(NullText);
+        let mut scene = Scene::new(target.text(), Size::new(1000.0, 100.0));
+        scene.render(&mut target).unwrap();
+        for i in 0..MAX_DAMAGE_RECTS {
+            let x = i as f64 * 100.0;
+            scene.invalidate(Rect::new(x + 10.0, 10.0, x + 20.0, 20.0));
+        }
+        assert_eq!(scene.damage().len(), MAX_DAMAGE_RECTS);
+        // inside one already damaged, so nothing new
+        scene.invalidate(Rect::new(12.0, 12.0, 18.0, 18.0));
+        assert_eq!(scene.damage().len(), MAX_DAMAGE_RECTS);
+
+        // one more, next to the first, is merged with it
+        scene.invalidate(Rect::new(30.0, 10.0, 40.0, 20.0));
+        let corners = |rects: &[Rect]| {
+            rects
+                .iter()
+                .map(|rect| (rect.x0, rect.y0, rect.x1, rect.y1))
+                .collect::<Vec<_>>()
+        };
+        let damage = corners(&scene.damage());
+        assert_eq!(damage.len(), MAX_DAMAGE_RECTS);
+        assert!(damage.contains(&(9.0, 9.0, 41.0, 21.0)));
+        assert_eq!(corners(&scene.render(&mut target).unwrap()), damage);
+        assert!(scene.damage().is_empty());
+    }
+}
@@ -7,10 +7,12 @@ mod text;
 use std::borrow::Cow;
 use std::{io, mem};
 
-use piet::kurbo::{Affine, Point, Rect, Shape};
+use piet::kurbo::{Affine, BezPath, Insets, Point, Rect, Shape, Size};
 use piet::{
-    new_error, Color, Error, ErrorKind, FixedGradient, ImageFormat, InterpolationMode, IntoBrush,
-    LineCap, LineJoin, StrokeStyle,
+    align_stroke, flattening_tolerance, new_error, user_tolerance, Antialiasing, Capabilities,
+    Color, DrawCall, DrawProfile, Error, ErrorKind, FallbackPolicy, FixedGradient,
+    GlyphPositioning, ImageFormat, ImagePositioning, InterpolationMode, IntoBrush, LineCap,
+    LineJoin, MemoryReport, Profiler, StrokeStyle, DEFAULT_TOLERANCE,
 };
 use svg::node::Node;
 
@@ -25,6 +27,12 @@ pub struct RenderContext {
     doc: svg::Document,
     next_id: u64,
     text: Text,
+    /// The tolerance of converting shapes to paths, in device units.
+    tolerance: f64,
+    /// The calls made so far, for `draw_profile`.
+    profiler: Profiler,
+    fallback_policy: FallbackPolicy,
+    err: Result<()>,
 }
 
 impl RenderContext {
@@ -37,9 +45,21 @@ impl RenderContext {
             doc: svg::Document::new(),
             next_id: 0,
             text: Text::new(),
+            tolerance: DEFAULT_TOLERANCE,
+            profiler: Profiler::new(),
+            fallback_policy: FallbackPolicy::default(),
+            err: Ok(()),
         }
     }
 
+    /// Give the document a width and height, in pixels.
+    ///
+    /// Without one, the document fills whatever shows it.
+    pub fn set_size(&mut self, size: Size) {
+        self.doc.assign("width", size.width);
+        self.doc.assign("height", size.height);
+    }
+
     /// Write graphics rendered so far to an `std::io::Write` impl, such as `std::fs::File`
     ///
     /// Additional rendering can be done afterwards.
@@ -62,11 +82,14 @@ impl piet::RenderContext for RenderContext {
 
     type Image = Image;
 
+    type Path = Path;
+
     fn status(&mut self) -> Result<()> {
-        Ok(())
+        std::mem::replace(&mut self.err, Ok(()))
     }
 
     fn clear(&mut self, color: Color) {
+        let _timer = self.profiler.time(DrawCall::Fill);
         let brush = color.make_brush(self, || Rect::ZERO);
         let mut rect = svg::node::element::Rectangle::new()
             .set("width", "100%")
@@ -78,6 +101,21 @@ impl piet::RenderContext for RenderContext {
         self.doc.append(rect);
     }
 
+    fn clear_rect(&mut self, rect: Rect, color: Color) {
+        let _timer = self.profiler.time(DrawCall::Fill);
+        // an SVG document has no pixels to replace, only shapes to stack
+        self.fill(rect, &color);
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            linear_gradients: true,
+            radial_gradients: true,
+            dashes: true,
+            ..Capabilities::default()
+        }
+    }
+
     fn solid_brush(&mut self, color: Color) -> Brush {
         Brush {
             kind: BrushKind::Solid(color),
@@ -95,7 +133,7 @@ impl piet::RenderContext for RenderContext {
                     .set("y1", x.start.y)
                     .set("x2", x.end.x)
                     .set("y2", x.end.y);
-                for stop in x.stops {
+                for stop in x.color_space.resolve_stops(&x.stops).iter() {
                     gradient.append(
                         svg::node::element::Stop::new()
                             .set("offset", stop.pos)
@@ -113,7 +151,7 @@ impl piet::RenderContext for RenderContext {
                     .set("fx", x.center.x + x.origin_offset.x)
                     .set("fy", x.center.y + x.origin_offset.y)
                     .set("r", x.radius);
-                for stop in x.stops {
+                for stop in x.color_space.resolve_stops(&x.stops).iter() {
                     gradient.append(
                         svg::node::element::Stop::new()
                             .set("offset", stop.pos)
@@ -122,6 +160,11 @@ impl piet::RenderContext for RenderContext {
                 }
                 self.doc.append(gradient);
             }
+            // SVG2 meshgradient isn't implemented by browsers
+            FixedGradient::Mesh(mesh) => {
+                let color = self.fallback_policy.degrade(|| mesh.fallback_color())?;
+                return Ok(self.solid_brush(color));
+            }
         }
         Ok(Brush {
             kind: BrushKind::Ref(id),
@@ -129,10 +172,12 @@ impl piet::RenderContext for RenderContext {
     }
 
     fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
         let brush = brush.make_brush(self, || shape.bounding_box());
         add_shape(
             &mut self.doc,
             shape,
+            self.tolerance,
             &Attrs {
                 xf: self.state.xf,
                 clip: self.state.clip,
@@ -143,10 +188,12 @@ impl piet::RenderContext for RenderContext {
     }
 
     fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
         let brush = brush.make_brush(self, || shape.bounding_box());
         add_shape(
             &mut self.doc,
             shape,
+            self.tolerance,
             &Attrs {
                 xf: self.state.xf,
                 clip: self.state.clip,
@@ -157,11 +204,13 @@ impl piet::RenderContext for RenderContext {
     }
 
     fn clip(&mut self, shape: impl Shape) {
+        let _timer = self.profiler.time(DrawCall::State);
         let id = self.new_id();
         let mut clip = svg::node::element::ClipPath::new().set("id", id);
         add_shape(
             &mut clip,
             shape,
+            self.tolerance,
             &Attrs {
                 xf: self.state.xf,
                 clip: self.state.clip,
@@ -172,11 +221,69 @@ impl piet::RenderContext for RenderContext {
         self.state.clip = Some(id);
     }
 
+    fn clip_text(&mut self, _layout: &Self::TextLayout, _pos: impl Into<Point>) {
+        unimplemented!()
+    }
+
+    fn push_clip(&mut self, shape: impl Shape) {
+        let _timer = self.profiler.time(DrawCall::State);
+        let _ = self.save();
+        self.state.pushed_clip = true;
+        self.clip(shape);
+    }
+
+    fn pop_clip(&mut self) -> Result<()> {
+        let _timer = self.profiler.time(DrawCall::State);
+        if !self.state.pushed_clip {
+            return Err(new_error(ErrorKind::StackUnbalance));
+        }
+        let xf = self.state.xf;
+        self.state = self.stack.pop().unwrap();
+        self.state.xf = xf;
+        Ok(())
+    }
+
+    fn reset_clip(&mut self) {
+        let _timer = self.profiler.time(DrawCall::State);
+        while self.pop_clip().is_ok() {}
+        // back to the clip the save was made with
+        self.state.clip = self.stack.last().and_then(|state| state.clip);
+    }
+
+    fn make_path(&mut self, shape: impl Shape) -> Result<Path> {
+        let tolerance = user_tolerance(self.tolerance, self.state.xf);
+        let tolerance = flattening_tolerance(tolerance, &shape);
+        Ok(Path(shape.into_bez_path(tolerance)))
+    }
+
+    fn fill_path(&mut self, path: &Path, brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
+        self.fill(path.0.clone(), brush);
+    }
+
+    fn stroke_path(&mut self, path: &Path, brush: &impl IntoBrush<Self>, width: f64) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
+        self.stroke(path.0.clone(), brush, width);
+    }
+
+    fn stroke_path_styled(
+        &mut self,
+        path: &Path,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
+        self.stroke_styled(path.0.clone(), brush, width, style);
+    }
+
     fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
         let brush = brush.make_brush(self, || shape.bounding_box());
         add_shape(
             &mut self.doc,
             shape,
+            self.tolerance,
             &Attrs {
                 xf: self.state.xf,
                 clip: self.state.clip,
@@ -193,10 +300,15 @@ impl piet::RenderContext for RenderContext {
         width: f64,
         style: &StrokeStyle,
     ) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
+        if let Some((path, style)) = align_stroke(&shape, width, style) {
+            return self.stroke_styled(path, brush, width, &style);
+        }
         let brush = brush.make_brush(self, || shape.bounding_box());
         add_shape(
             &mut self.doc,
             shape,
+            self.tolerance,
             &Attrs {
                 xf: self.state.xf,
                 clip: self.state.clip,
@@ -206,8 +318,8 @@ impl piet::RenderContext for RenderContext {
         );
     }
 
-    fn text(&mut self) -> &mut Self::Text {
-        &mut self.text
+    fn text(&self) -> Self::Text {
+        self.text.clone()
     }
 
     fn draw_text(
@@ -219,26 +331,99 @@ impl piet::RenderContext for RenderContext {
         unimplemented!()
     }
 
+    fn draw_glyph_run(
+        &mut self,
+        _layout: &Self::TextLayout,
+        _pos: impl Into<Point>,
+        _brush: &impl IntoBrush<Self>,
+        _transforms: &[Affine],
+    ) {
+        unimplemented!()
+    }
+
+    fn draw_glyphs(
+        &mut self,
+        _font: &text::Font,
+        _glyphs: &[(u16, Point)],
+        _brush: &impl IntoBrush<Self>,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Text);
+        // SVG has no way to refer to a glyph by id
+    }
+
+    fn set_glyph_positioning(&mut self, _positioning: GlyphPositioning) {
+        // Text is not yet implemented for SVG; when it is, this maps to the
+        // `text-rendering` attribute.
+    }
+
+    fn set_image_positioning(&mut self, _positioning: ImagePositioning) {
+        // The device pixel grid is up to whatever renders the document.
+    }
+
+    fn set_tolerance(&mut self, tolerance: f64) {
+        if tolerance > 0.0 && tolerance.is_finite() {
+            self.tolerance = tolerance;
+        }
+    }
+
+    fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    fn set_antialiasing(&mut self, _mode: Antialiasing) {
+        // TODO: map to the `shape-rendering` and `text-rendering` attributes
+    }
+
+    fn set_pick_id(&mut self, _id: Option<u64>) {}
+
+    fn set_fallback_policy(&mut self, policy: FallbackPolicy) {
+        self.fallback_policy = policy;
+    }
+
     fn save(&mut self) -> Result<()> {
-        let new = self.state.clone();
+        let _timer = self.profiler.time(DrawCall::State);
+        let new = State {
+            pushed_clip: false,
+            ..self.state.clone()
+        };
         self.stack.push(mem::replace(&mut self.state, new));
         Ok(())
     }
 
     fn restore(&mut self) -> Result<()> {
-        self.state = self
-            .stack
-            .pop()
-            .ok_or_else(|| new_error(ErrorKind::StackUnbalance))?;
+        let _timer = self.profiler.time(DrawCall::State);
+        // Clips pushed since the save are popped along with it.
+        let mut depth = self.stack.len();
+        let mut state = &self.state;
+        while state.pushed_clip {
+            depth -= 1;
+            state = &self.stack[depth];
+        }
+        if depth == 0 {
+            return Err(new_error(ErrorKind::StackUnbalance));
+        }
+        while self.stack.len() >= depth {
+            self.state = self.stack.pop().unwrap();
+        }
         Ok(())
     }
 
     fn finish(&mut self) -> Result<()> {
-        Ok(())
+        piet::check_usage(
+            self.stack.is_empty(),
+            ErrorKind::StackUnbalance,
+            "finish called with saves not restored",
+        )?;
+        self.status()
     }
 
     fn transform(&mut self, transform: Affine) {
-        self.state.xf *= transform;
+        let _timer = self.profiler.time(DrawCall::State);
+        let combined = self.state.xf * transform;
+        match piet::check_transform(combined) {
+            Ok(()) => self.state.xf = combined,
+            Err(e) => self.err = Err(e),
+        }
     }
 
     fn current_transform(&self) -> Affine {
@@ -262,6 +447,7 @@ impl piet::RenderContext for RenderContext {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
+        let _timer = self.profiler.time(DrawCall::Image);
         draw_image(self, image, None, dst_rect.into(), interp);
     }
 
@@ -273,8 +459,124 @@ impl piet::RenderContext for RenderContext {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
+        let _timer = self.profiler.time(DrawCall::Image);
         draw_image(self, image, Some(src_rect.into()), dst_rect.into(), interp);
     }
+
+    fn draw_image_transformed(
+        &mut self,
+        _image: &Self::Image,
+        _transform: Affine,
+        _interp: InterpolationMode,
+    ) {
+        unimplemented!()
+    }
+
+    fn draw_tiles(
+        &mut self,
+        _atlas: &Self::Image,
+        _tiles: &[(Rect, Point)],
+        _interp: InterpolationMode,
+    ) {
+        unimplemented!()
+    }
+
+    fn draw_images(&mut self, images: &[(&Self::Image, Rect, f64)], interp: InterpolationMode) {
+        // images can't be made yet, so this only ever sees an empty slice
+        for &(image, dst_rect, _alpha) in images {
+            self.draw_image(image, dst_rect, interp);
+        }
+    }
+
+    fn make_render_target(&mut self, _width: usize, _height: usize) -> Result<Self::Image> {
+        Err(new_error(ErrorKind::NotSupported))
+    }
+
+    fn draw_to_target(
+        &mut self,
+        _target: &mut Self::Image,
+        _f: impl FnOnce(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        Err(new_error(ErrorKind::NotSupported))
+    }
+
+    fn capture_image_area(&mut self, _src_rect: impl Into<Rect>) -> Result<Self::Image> {
+        Err(new_error(ErrorKind::NotSupported))
+    }
+
+    fn with_blur(&mut self, radius: f64, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        let std_dev = radius.abs() * self.state.xf.determinant().abs().sqrt();
+        let id = self.new_id();
+        // Percentages of the viewport: the layer covers the whole image,
+        // with room to blur in from outside it.
+        let mut filter = svg::node::element::Filter::new()
+            .set("id", id)
+            .set("filterUnits", "userSpaceOnUse")
+            .set("x", "-50%")
+            .set("y", "-50%")
+            .set("width", "200%")
+            .set("height", "200%");
+        let mut blur = svg::node::element::Element::new("feGaussianBlur");
+        blur.assign("stdDeviation", std_dev);
+        filter.append(blur);
+        self.doc.append(filter);
+
+        // The layer is a nested document, drawn in the same coordinates.
+        let layer = svg::Document::new().set("overflow", "visible");
+        let outer_doc = mem::replace(&mut self.doc, layer);
+        let outer_stack = mem::take(&mut self.stack);
+        let outer_state = self.state.clone();
+        self.state.clip = None;
+        self.state.pushed_clip = false;
+        let result = f(self);
+        let mut layer = mem::replace(&mut self.doc, outer_doc);
+        self.stack = outer_stack;
+        self.state = outer_state;
+        result?;
+
+        layer.assign("filter", format!("url(#{})", id.to_string()));
+        if let Some(clip) = self.state.clip {
+            layer.assign("clip-path", format!("url(#{})", clip.to_string()));
+        }
+        self.doc.append(layer);
+        Ok(())
+    }
+
+    fn trim_layer_pool(&mut self) {}
+
+    fn memory_report(&self) -> MemoryReport {
+        MemoryReport::default()
+    }
+
+    fn trim_caches(&mut self) {}
+
+    fn draw_profile(&self) -> DrawProfile {
+        self.profiler.profile()
+    }
+
+    fn reset_draw_profile(&mut self) {
+        self.profiler.reset();
+    }
+
+    fn draw_image_nine(
+        &mut self,
+        _image: &Self::Image,
+        _insets: Insets,
+        _dst_rect: impl Into<Rect>,
+        _interp: InterpolationMode,
+    ) {
+        unimplemented!()
+    }
+
+    fn draw_image_clipped(
+        &mut self,
+        _image: &Self::Image,
+        _dst_rect: impl Into<Rect>,
+        _shape: impl Shape,
+        _interp: InterpolationMode,
+    ) {
+        unimplemented!()
+    }
 }
 
 fn draw_image(
@@ -334,8 +636,8 @@ impl Attrs<'_> {
                     node.assign("stroke-linecap", "square");
                 }
             }
-            if let Some((ref array, offset)) = style.dash {
-                node.assign("stroke-dasharray", array.clone());
+            if let Some((array, offset)) = style.dash_pattern() {
+                node.assign("stroke-dasharray", array.to_vec());
                 if offset != 0.0 {
                     node.assign("stroke-dashoffset", offset);
                 }
@@ -356,7 +658,9 @@ fn xf_val(xf: &Affine) -> svg::node::Value {
     .into()
 }
 
-fn add_shape(node: &mut impl Node, shape: impl Shape, attrs: &Attrs) {
+/// Add `shape` to `node`, with curves that aren't circles or rounded
+/// rectangles within `tolerance` of it in device units.
+fn add_shape(node: &mut impl Node, shape: impl Shape, tolerance: f64, attrs: &Attrs) {
     if let Some(circle) = shape.as_circle() {
         let mut x = svg::node::element::Circle::new()
             .set("cx", circle.center.x)
@@ -383,7 +687,10 @@ fn add_shape(node: &mut impl Node, shape: impl Shape, attrs: &Attrs) {
         attrs.apply_to(&mut x);
         node.append(x);
     } else {
-        let mut path = svg::node::element::Path::new().set("d", shape.into_bez_path(1e-3).to_svg());
+        let tolerance = user_tolerance(tolerance, attrs.xf);
+        let tolerance = flattening_tolerance(tolerance, &shape);
+        let mut path =
+            svg::node::element::Path::new().set("d", shape.into_bez_path(tolerance).to_svg());
         attrs.apply_to(&mut path);
         node.append(path)
     }
@@ -393,6 +700,8 @@ fn add_shape(node: &mut impl Node, shape: impl Shape, attrs: &Attrs) {
 struct State {
     xf: Affine,
     clip: Option<Id>,
+    /// Whether this state was entered by `push_clip` rather than `save`.
+    pushed_clip: bool,
 }
 
 /// An SVG brush
@@ -432,9 +741,22 @@ fn fmt_color(color: &Color) -> String {
     }
 }
 
+/// SVG path, written out each time it's drawn
+pub struct Path(BezPath);
+
 /// SVG image (unimplemented)
 pub struct Image(());
 
+impl piet::Image for Image {
+    fn size(&self) -> Size {
+        unimplemented!()
+    }
+
+    fn format(&self) -> ImageFormat {
+        unimplemented!()
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 struct Id(u64);
 
@@ -1,11 +1,18 @@
 //! Text functionality for Piet svg backend
 
-use piet::kurbo::Point;
-use piet::{new_error, Error, ErrorKind, HitTestPoint, HitTestTextPosition};
+use std::ops::Range;
+
+use piet::kurbo::{Affine, Point, Rect};
+use piet::{
+    new_error, ClusterMetric, Color, DecorationMetrics, EmojiStrategy, Error, ErrorKind,
+    FontStretch, FontStyle, FontSubstitutions, FontVariation, FontWeight, HitTestPoint,
+    HitTestTextPosition, LineMetric, TextAlignment, TextAttribute, TextTruncation, WritingMode,
+};
 
 type Result<T> = std::result::Result<T, Error>;
 
 /// SVG text (unimplemented)
+#[derive(Clone)]
 pub struct Text(());
 
 impl Text {
@@ -28,6 +35,16 @@ impl piet::Text for Text {
     fn new_text_layout(&mut self, _font: &Self::Font, _text: &str) -> TextLayoutBuilder {
         TextLayoutBuilder(())
     }
+
+    fn set_font_substitutions(&mut self, _substitutions: FontSubstitutions) {}
+
+    fn set_emoji_strategy(&mut self, _strategy: EmojiStrategy) {}
+
+    fn load_font(&mut self, _data: &[u8]) -> Result<String> {
+        Err(new_error(ErrorKind::NotSupported))
+    }
+
+    fn set_system_fonts(&mut self, _enabled: bool) {}
 }
 
 /// SVG font builder (unimplemented)
@@ -36,6 +53,26 @@ pub struct FontBuilder(());
 impl piet::FontBuilder for FontBuilder {
     type Out = Font;
 
+    fn weight(self, _weight: FontWeight) -> Self {
+        self
+    }
+
+    fn style(self, _style: FontStyle) -> Self {
+        self
+    }
+
+    fn stretch(self, _stretch: FontStretch) -> Self {
+        self
+    }
+
+    fn fallback(self, _family: &str) -> Self {
+        self
+    }
+
+    fn variation(self, _variation: FontVariation) -> Self {
+        self
+    }
+
     fn build(self) -> Result<Font> {
         Err(new_error(ErrorKind::NotSupported))
     }
@@ -44,13 +81,49 @@ impl piet::FontBuilder for FontBuilder {
 /// SVG font (unimplemented)
 pub struct Font(());
 
-impl piet::Font for Font {}
+impl piet::Font for Font {
+    fn decoration_metrics(&self) -> DecorationMetrics {
+        unimplemented!()
+    }
+}
 
 pub struct TextLayoutBuilder(());
 
 impl piet::TextLayoutBuilder for TextLayoutBuilder {
     type Out = TextLayout;
 
+    fn obscure(self, _mask: char) -> Self {
+        self
+    }
+
+    fn range_attribute(self, _range: Range<usize>, _attribute: TextAttribute) -> Self {
+        self
+    }
+
+    fn default_color(self, _color: Color) -> Self {
+        self
+    }
+
+    fn transform(self, _transform: Affine) -> Self {
+        self
+    }
+
+    fn max_width(self, _width: f64) -> Self {
+        self
+    }
+
+    fn alignment(self, _alignment: TextAlignment) -> Self {
+        self
+    }
+
+    fn max_lines(self, _lines: usize, _truncation: TextTruncation) -> Self {
+        self
+    }
+
+    fn writing_mode(self, _mode: WritingMode) -> Self {
+        self
+    }
+
     fn build(self) -> Result<TextLayout> {
         Err(new_error(ErrorKind::NotSupported))
     }
@@ -71,4 +144,32 @@ impl piet::TextLayout for TextLayout {
     fn hit_test_text_position(&self, _text_position: usize) -> Option<HitTestTextPosition> {
         unimplemented!()
     }
+
+    fn range_bounds(&self, _range: Range<usize>) -> Option<Rect> {
+        unimplemented!()
+    }
+
+    fn rects_for_range(&self, _range: Range<usize>) -> Vec<Rect> {
+        unimplemented!()
+    }
+
+    fn caret_bounds(&self, _text_position: usize) -> Option<Rect> {
+        unimplemented!()
+    }
+
+    fn line_metrics(&self) -> Vec<LineMetric> {
+        unimplemented!()
+    }
+
+    fn cluster_metrics(&self) -> Vec<ClusterMetric> {
+        unimplemented!()
+    }
+
+    fn decoration_metrics(&self) -> DecorationMetrics {
+        unimplemented!()
+    }
+
+    fn truncated_at(&self) -> Option<usize> {
+        unimplemented!()
+    }
 }
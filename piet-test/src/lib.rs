@@ -1,26 +1,59 @@
 //! Test code for piet.
 
-// Right now, this is just code to generate sample images.
+// Right now, this is mostly code to generate sample images.
 
 use piet::{Error, RenderContext};
+pub mod properties;
+
 mod picture_0;
 mod picture_1;
+mod picture_10;
+mod picture_11;
+mod picture_12;
 mod picture_2;
 mod picture_3;
 mod picture_4;
 mod picture_5;
+mod picture_6;
+mod picture_7;
+mod picture_8;
+mod picture_9;
 
 use crate::picture_0::draw as draw_picture_0;
 use crate::picture_1::draw as draw_picture_1;
+use crate::picture_10::draw as draw_picture_10;
+use crate::picture_11::draw as draw_picture_11;
+use crate::picture_12::draw as draw_picture_12;
 use crate::picture_2::draw as draw_picture_2;
 use crate::picture_3::draw as draw_picture_3;
 use crate::picture_4::draw as draw_picture_4;
 use crate::picture_5::draw as draw_picture_5;
+use crate::picture_6::draw as draw_picture_6;
+use crate::picture_7::draw as draw_picture_7;
+use crate::picture_8::draw as draw_picture_8;
+use crate::picture_9::draw as draw_picture_9;
+
+pub use crate::picture_7::{BLANK as PICTURE_7_BLANK, INKED as PICTURE_7_INKED};
+
+/// The number of test pictures, which are numbered from 0.
+pub const TEST_PICTURE_COUNT: usize = 13;
 
 /// Draw a test picture, by number.
 ///
 /// There are a few test pictures here now, and hopefully it will grow into
 /// a full suite, suitable for both benchmarking and correctness testing.
+///
+/// Pictures from 8 on each cover one feature, in every combination of its
+/// options that piet has:
+///
+/// - 8: line caps, line joins and dashes.
+/// - 9: gradients of each kind, and how they extend past their ends.
+/// - 10: clips, and how they end.
+/// - 11: images in each format, with each interpolation mode.
+/// - 12: text weights, slants, stretches, attributes and alignments.
+///
+/// These draw on every backend, within 200 by 100, leaving out the parts
+/// that need a feature the backend's `Capabilities` lack.
 pub fn draw_test_picture(rc: &mut impl RenderContext, number: usize) -> Result<(), Error> {
     match number {
         0 => draw_picture_0(rc),
@@ -29,6 +62,13 @@ pub fn draw_test_picture(rc: &mut impl RenderContext, number: usize) -> Result<(
         3 => draw_picture_3(rc),
         4 => draw_picture_4(rc),
         5 => draw_picture_5(rc),
+        6 => draw_picture_6(rc),
+        7 => draw_picture_7(rc),
+        8 => draw_picture_8(rc),
+        9 => draw_picture_9(rc),
+        10 => draw_picture_10(rc),
+        11 => draw_picture_11(rc),
+        12 => draw_picture_12(rc),
         _ => {
             eprintln!(
                 "Don't have test picture {} yet. Why don't you make it?",
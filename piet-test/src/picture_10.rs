@@ -0,0 +1,156 @@
+//! Clips.
+//!
+//! The top row clips to a rectangle, a star, a rectangle and then a circle,
+//! which intersect, a rotated rounded rectangle, and a circle pushed and
+//! popped. The bottom row checks that clips end, with the save they were
+//! made in, with `pop_clip` inside a save, and in turn when nested, and
+//! then clips to nothing at all and to a path of two pieces. The blue
+//! squares drawn after a clip ends should be whole.
+
+use piet::kurbo::{Affine, BezPath, Circle, Point, Rect, RoundedRect, Vec2};
+use piet::{Color, Error, RenderContext};
+
+/// The `i`th of five cells in a row starting at `y`.
+fn cell(i: usize, y: f64) -> Rect {
+    let x = 5.0 + 39.0 * i as f64;
+    Rect::new(x, y, x + 34.0, y + 40.0)
+}
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+    let shade = Color::rgb8(0xE0, 0xE0, 0xE0);
+    let red = Color::rgb8(0xC0, 0x00, 0x00);
+    let green = Color::rgb8(0x00, 0x80, 0x00);
+    let blue = Color::rgb8(0x00, 0x00, 0xC0);
+    for i in 0..5 {
+        rc.fill(cell(i, 5.0), &shade);
+        rc.fill(cell(i, 55.0), &shade);
+    }
+
+    let rect = cell(0, 5.0);
+    rc.with_save(|rc| {
+        rc.clip(rect.inset(-8.0));
+        stripes(rc, rect, &red);
+        Ok(())
+    })?;
+
+    let rect = cell(1, 5.0);
+    rc.with_save(|rc| {
+        rc.clip(star(rect.center(), 6.0, 17.0, 7));
+        stripes(rc, rect, &red);
+        Ok(())
+    })?;
+
+    // a second clip cuts down the first
+    let rect = cell(2, 5.0);
+    rc.with_save(|rc| {
+        rc.clip(Rect::new(rect.x0, rect.y0, rect.x1 - 10.0, rect.y1));
+        rc.clip(Circle::new((rect.x1 - 10.0, rect.center().y), 15.0));
+        stripes(rc, rect, &red);
+        Ok(())
+    })?;
+
+    // the clip is transformed with the rest of the drawing
+    let rect = cell(3, 5.0);
+    rc.with_save(|rc| {
+        rc.transform(Affine::translate(rect.center() - Point::ORIGIN) * Affine::rotate(0.5));
+        rc.clip(RoundedRect::new(-10.0, -10.0, 10.0, 10.0, 3.0));
+        rc.transform(Affine::rotate(-0.5) * Affine::translate(Point::ORIGIN - rect.center()));
+        stripes(rc, rect, &red);
+        Ok(())
+    })?;
+
+    let rect = cell(4, 5.0);
+    rc.push_clip(Circle::new(rect.center(), 14.0));
+    stripes(rc, rect, &red);
+    rc.pop_clip()?;
+
+    let corner = |rect: Rect| Rect::new(rect.x0, rect.y0, rect.x0 + 12.0, rect.y0 + 12.0);
+
+    let rect = cell(0, 55.0);
+    rc.with_save(|rc| {
+        rc.clip(rect.inset(-6.0));
+        stripes(rc, rect, &green);
+        Ok(())
+    })?;
+    rc.fill(corner(rect), &blue);
+
+    let rect = cell(1, 55.0);
+    rc.with_save(|rc| {
+        rc.push_clip(rect.inset(-6.0));
+        stripes(rc, rect, &green);
+        rc.pop_clip()?;
+        rc.fill(corner(rect), &blue);
+        Ok(())
+    })?;
+
+    // the inner of two pushed clips ends first
+    let rect = cell(2, 55.0);
+    rc.push_clip(Rect::new(rect.x0, rect.y0 + 14.0, rect.x1, rect.y1));
+    rc.push_clip(Circle::new(rect.center(), 10.0));
+    stripes(rc, rect, &green);
+    rc.pop_clip()?;
+    rc.fill(Rect::new(rect.x0, rect.y1 - 8.0, rect.x1, rect.y1), &blue);
+    rc.pop_clip()?;
+    rc.fill(corner(rect), &blue);
+
+    // a clip that covers nothing hides everything
+    let rect = cell(3, 55.0);
+    rc.with_save(|rc| {
+        rc.clip(Rect::new(rect.x0, rect.y0, rect.x0, rect.y1));
+        stripes(rc, rect, &green);
+        Ok(())
+    })?;
+    rc.fill(corner(rect), &blue);
+
+    // a clip made of two pieces
+    let rect = cell(4, 55.0);
+    rc.with_save(|rc| {
+        let mut halves = BezPath::new();
+        for &(x0, x1) in &[(rect.x0, rect.x0 + 12.0), (rect.x1 - 12.0, rect.x1)] {
+            halves.move_to((x0, rect.y0 + 14.0));
+            halves.line_to((x1, rect.y0 + 14.0));
+            halves.line_to((x1, rect.y1));
+            halves.line_to((x0, rect.y1));
+            halves.close_path();
+        }
+        rc.clip(halves);
+        stripes(rc, rect, &green);
+        Ok(())
+    })?;
+    rc.fill(corner(rect), &blue);
+    Ok(())
+}
+
+/// Diagonal stripes over `rect` and past it, to show what a clip lets
+/// through.
+fn stripes(rc: &mut impl RenderContext, rect: Rect, color: &Color) {
+    let height = rect.height();
+    let mut x = rect.x0 - height - 8.0;
+    while x < rect.x1 + 8.0 {
+        let mut stripe = BezPath::new();
+        stripe.move_to((x, rect.y1 + 4.0));
+        stripe.line_to((x + height + 8.0, rect.y0 - 4.0));
+        stripe.line_to((x + height + 12.0, rect.y0 - 4.0));
+        stripe.line_to((x + 4.0, rect.y1 + 4.0));
+        stripe.close_path();
+        rc.fill(stripe, color);
+        x += 7.0;
+    }
+}
+
+fn star(center: Point, inner: f64, outer: f64, n: usize) -> BezPath {
+    let mut result = BezPath::new();
+    let d_th = std::f64::consts::PI / (n as f64);
+    for i in 0..n {
+        let outer_pt = center + outer * Vec2::from_angle(d_th * ((i * 2) as f64));
+        if i == 0 {
+            result.move_to(outer_pt);
+        } else {
+            result.line_to(outer_pt);
+        }
+        result.line_to(center + inner * Vec2::from_angle(d_th * ((i * 2 + 1) as f64)));
+    }
+    result.close_path();
+    result
+}
@@ -0,0 +1,94 @@
+//! The same image in each image format, with each interpolation mode.
+//!
+//! Columns are the formats, in the order of `ImageFormat`, and each has a
+//! blank space where the backend doesn't accept the format. The rows are
+//! the whole image scaled up with nearest neighbor and bilinear
+//! interpolation, and its middle four pixels, with nearest neighbor. A
+//! black bar behind each row shows the image's alpha.
+
+use piet::kurbo::Rect;
+use piet::{Color, Error, ImageFormat, InterpolationMode, RenderContext};
+
+const FORMATS: &[ImageFormat] = &[
+    ImageFormat::Rgb,
+    ImageFormat::RgbaSeparate,
+    ImageFormat::RgbaPremul,
+    ImageFormat::BgraPremul,
+    ImageFormat::Grayscale,
+    ImageFormat::Alpha,
+];
+
+/// The width and height of the image, in pixels.
+const SIZE: usize = 4;
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+    for &y in &[12.0, 45.0, 78.0] {
+        rc.fill(Rect::new(0.0, y, 200.0, y + 6.0), &Color::BLACK);
+    }
+    let caps = rc.capabilities();
+    if !caps.images {
+        return Ok(());
+    }
+
+    for (i, &format) in FORMATS.iter().enumerate() {
+        if !caps.image_formats.contains(&format) {
+            continue;
+        }
+        let image = rc.make_image(SIZE, SIZE, &image_data(format), format)?;
+        let x = 5.0 + 33.0 * i as f64;
+        let dst = |y: f64| Rect::new(x, y, x + 28.0, y + 28.0);
+        rc.draw_image(&image, dst(1.0), InterpolationMode::NearestNeighbor);
+        rc.draw_image(&image, dst(34.0), InterpolationMode::Bilinear);
+        rc.draw_image_area(
+            &image,
+            Rect::new(1.0, 1.0, 3.0, 3.0),
+            dst(67.0),
+            InterpolationMode::NearestNeighbor,
+        );
+    }
+    Ok(())
+}
+
+/// The color of each pixel, as straight RGBA: a checkerboard of opaque
+/// red and translucent blue, with an opaque green pixel and a transparent
+/// one.
+fn pixel(x: usize, y: usize) -> [u8; 4] {
+    match (x, y) {
+        (1, 1) => [0x00, 0xC0, 0x00, 0xFF],
+        (2, 2) => [0x00, 0x00, 0x00, 0x00],
+        _ if (x + y) & 1 == 0 => [0xFF, 0x00, 0x00, 0xFF],
+        _ => [0x00, 0x00, 0xFF, 0x80],
+    }
+}
+
+/// The pixels of the image, in `format`.
+fn image_data(format: ImageFormat) -> Vec<u8> {
+    fn premul(x: u8, a: u8) -> u8 {
+        ((x as u32 * a as u32 + 127) / 255) as u8
+    }
+    let mut result = Vec::with_capacity(SIZE * SIZE * format.bytes_per_pixel());
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let [r, g, b, a] = pixel(x, y);
+            match format {
+                ImageFormat::Rgb => result.extend_from_slice(&[r, g, b]),
+                ImageFormat::RgbaSeparate => result.extend_from_slice(&[r, g, b, a]),
+                ImageFormat::RgbaPremul => {
+                    result.extend_from_slice(&[premul(r, a), premul(g, a), premul(b, a), a])
+                }
+                ImageFormat::BgraPremul => {
+                    result.extend_from_slice(&[premul(b, a), premul(g, a), premul(r, a), a])
+                }
+                // the luma of the color, as if over black
+                ImageFormat::Grayscale => {
+                    let luma = 0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64;
+                    result.push(premul(luma.round() as u8, a))
+                }
+                ImageFormat::Alpha => result.push(a),
+                _ => (),
+            }
+        }
+    }
+    result
+}
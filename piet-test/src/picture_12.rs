@@ -0,0 +1,130 @@
+//! Text styles.
+//!
+//! The first three lines set whole layouts in different weights, slants and
+//! stretches. The fourth sets runs of one layout with range attributes, and
+//! the boxes at the bottom wrap the same text with each alignment.
+
+use std::ops::Range;
+
+use piet::kurbo::{Point, Rect};
+use piet::{
+    Color, Error, FontBuilder, FontStretch, FontStyle, FontWeight, RenderContext, Text,
+    TextAlignment, TextAttribute, TextLayout, TextLayoutBuilder,
+};
+
+const FAMILY: &str = "sans-serif";
+
+const SIZE: f64 = 10.0;
+
+const ATTRIBUTED: &str = "under strike red mark wide big";
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+    if !rc.capabilities().text {
+        return Ok(());
+    }
+    let black = Color::BLACK;
+
+    let mut x = 5.0;
+    for &(word, weight) in &[
+        ("Thin", FontWeight::THIN),
+        ("Light", FontWeight::LIGHT),
+        ("Regular", FontWeight::NORMAL),
+        ("Bold", FontWeight::BOLD),
+        ("Heavy", FontWeight::BLACK),
+    ] {
+        let font = rc
+            .text()
+            .new_font_by_name(FAMILY, SIZE)
+            .weight(weight)
+            .build()?;
+        x += draw_word(rc, &font, word, (x, 14.0), &black)?;
+    }
+
+    let mut x = 5.0;
+    for &(word, style) in &[
+        ("Normal", FontStyle::Normal),
+        ("Italic", FontStyle::Italic),
+        ("Oblique", FontStyle::Oblique),
+    ] {
+        let font = rc
+            .text()
+            .new_font_by_name(FAMILY, SIZE)
+            .style(style)
+            .build()?;
+        x += draw_word(rc, &font, word, (x, 28.0), &black)?;
+    }
+
+    let mut x = 5.0;
+    for &(word, stretch) in &[
+        ("Condensed", FontStretch::Condensed),
+        ("Normal", FontStretch::Normal),
+        ("Expanded", FontStretch::Expanded),
+    ] {
+        let font = rc
+            .text()
+            .new_font_by_name(FAMILY, SIZE)
+            .stretch(stretch)
+            .build()?;
+        x += draw_word(rc, &font, word, (x, 42.0), &black)?;
+    }
+
+    let font = rc.text().new_font_by_name(FAMILY, SIZE).build()?;
+    let word = |word: &str| -> Range<usize> {
+        let start = ATTRIBUTED.find(word).unwrap();
+        start..start + word.len()
+    };
+    let layout = rc
+        .text()
+        .new_text_layout(&font, ATTRIBUTED)
+        .range_attribute(word("under"), TextAttribute::Underline(true))
+        .range_attribute(word("strike"), TextAttribute::Strikethrough(true))
+        .range_attribute(
+            word("red"),
+            TextAttribute::Foreground(Color::rgb8(0xC0, 0x00, 0x00)),
+        )
+        .range_attribute(
+            word("mark"),
+            TextAttribute::Background(Color::rgb8(0xFF, 0xE0, 0x40)),
+        )
+        .range_attribute(word("wide"), TextAttribute::LetterSpacing(2.0))
+        .range_attribute(word("big"), TextAttribute::Size(16.0))
+        .build()?;
+    rc.draw_text(&layout, (5.0, 60.0), &black);
+
+    let outline = Color::rgb8(0xA0, 0xA0, 0xA0);
+    for (i, &alignment) in [
+        TextAlignment::Start,
+        TextAlignment::Center,
+        TextAlignment::End,
+        TextAlignment::Justified,
+    ]
+    .iter()
+    .enumerate()
+    {
+        let x = 5.0 + 49.0 * i as f64;
+        rc.stroke(Rect::new(x, 66.0, x + 45.0, 96.0), &outline, 0.5);
+        let layout = rc
+            .text()
+            .new_text_layout(&font, "wrap it up")
+            .max_width(41.0)
+            .alignment(alignment)
+            .build()?;
+        rc.draw_text(&layout, (x + 2.0, 77.0), &black);
+    }
+    Ok(())
+}
+
+/// Draw `word` in `font` with its baseline at `origin`, and return how far
+/// to move along for the next word.
+fn draw_word<R: RenderContext>(
+    rc: &mut R,
+    font: &<R::Text as Text>::Font,
+    word: &str,
+    origin: impl Into<Point>,
+    color: &Color,
+) -> Result<f64, Error> {
+    let layout = rc.text().new_text_layout(font, word).build()?;
+    rc.draw_text(&layout, origin, color);
+    Ok(layout.width() + 6.0)
+}
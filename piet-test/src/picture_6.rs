@@ -0,0 +1,56 @@
+//! Dashed rulers, which should line up the same on every backend.
+
+use piet::kurbo::Line;
+use piet::{Color, Error, LineCap, RenderContext, StrokeStyle};
+
+static TICKS: &[f64] = &[2.0, 8.0];
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+    let brush = rc.solid_brush(Color::BLACK);
+
+    // The same pattern at different widths: the dashes stay 2 long and 10
+    // apart, in line with the guide at the top.
+    rc.stroke(Line::new((10.0, 5.0), (210.0, 5.0)), &brush, 0.5);
+    for (i, width) in [1.0, 2.0, 4.0, 8.0].iter().enumerate() {
+        let y = 20.0 + 15.0 * i as f64;
+        let style = StrokeStyle::dashed(TICKS, 0.0);
+        rc.stroke_styled(Line::new((10.0, y), (210.0, y)), &brush, *width, &style);
+    }
+
+    // Offsets shift the pattern back along the line, wrapping around.
+    for (i, offset) in [1.0, -1.0, 21.0].iter().enumerate() {
+        let y = 90.0 + 15.0 * i as f64;
+        let style = StrokeStyle::dashed(TICKS, *offset);
+        rc.stroke_styled(Line::new((10.0, y), (210.0, y)), &brush, 4.0, &style);
+    }
+
+    // An odd pattern is read twice, swapping dashes and gaps.
+    let style = StrokeStyle::dashed(&[10.0, 5.0, 5.0], 0.0);
+    rc.stroke_styled(
+        Line::new((10.0, 140.0), (210.0, 140.0)),
+        &brush,
+        4.0,
+        &style,
+    );
+
+    // Zero length dashes are dots with round caps.
+    let style = StrokeStyle::dashed(&[0.0, 10.0], 0.0).with_line_cap(LineCap::Round);
+    rc.stroke_styled(
+        Line::new((10.0, 155.0), (210.0, 155.0)),
+        &brush,
+        4.0,
+        &style,
+    );
+
+    // Patterns that can't be drawn give a solid line.
+    let style = StrokeStyle::dashed(&[4.0, -4.0], 0.0);
+    rc.stroke_styled(
+        Line::new((10.0, 170.0), (210.0, 170.0)),
+        &brush,
+        4.0,
+        &style,
+    );
+
+    Ok(())
+}
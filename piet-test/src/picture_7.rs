@@ -0,0 +1,87 @@
+//! Strokes under extreme scales and shears.
+//!
+//! Stroke widths are in user space: the transform applies to the stroke
+//! as a whole, so a hairline under a huge scale is thick, a stroke under a
+//! non-uniform scale is thicker along one axis than the other, and a
+//! sheared stroke is sheared with its line.
+
+use piet::kurbo::{Affine, Line, Rect};
+use piet::{Color, Error, RenderContext};
+
+/// Pixels of the picture, drawn at its own size of 200 by 100, that are at
+/// least three quarters covered by the strokes.
+pub static INKED: &[(usize, usize)] = &[
+    // the hairline under a huge scale, 4 thick
+    (100, 8),
+    (100, 10),
+    (100, 11),
+    // the wide stroke under a tiny scale, 4 thick
+    (100, 23),
+    (100, 26),
+    // the wide sides of the rectangle scaled 10 across and 1/4 down
+    (7, 50),
+    (13, 50),
+    (170, 50),
+    // the sheared lines, 8 wide across
+    (30, 80),
+    (110, 80),
+];
+
+/// Pixels of the picture, drawn at its own size, that are at most a
+/// quarter covered. A backend stroking in device space, or losing the
+/// width to rounding, covers some of these or misses some of `INKED`.
+pub static BLANK: &[(usize, usize)] = &[
+    (100, 6),
+    (100, 13),
+    (8, 10),
+    (191, 10),
+    (100, 21),
+    (100, 28),
+    // outside and inside the wide sides of the rectangle, and inside its
+    // thin top edge
+    (4, 50),
+    (15, 50),
+    (90, 50),
+    (90, 42),
+    // just beside the sheared lines
+    (25, 80),
+    (35, 80),
+    (105, 80),
+];
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+    let brush = rc.solid_brush(Color::BLACK);
+
+    rc.with_transform(Affine::translate((10.0, 10.0)) * Affine::scale(1e4), |rc| {
+        rc.stroke(Line::new((0.0, 0.0), (0.018, 0.0)), &brush, 4e-4);
+        Ok(())
+    })?;
+
+    rc.with_transform(
+        Affine::translate((10.0, 25.0)) * Affine::scale(1e-4),
+        |rc| {
+            rc.stroke(Line::new((0.0, 0.0), (1.8e6, 0.0)), &brush, 4e4);
+            Ok(())
+        },
+    )?;
+
+    // 0.8 wide: 8 on the sides, 0.2 on the top and bottom
+    let squash = Affine::new([10.0, 0.0, 0.0, 0.25, 0.0, 0.0]);
+    rc.with_transform(Affine::translate((10.0, 40.0)) * squash, |rc| {
+        rc.stroke(Rect::new(0.0, 0.0, 16.0, 80.0), &brush, 0.8);
+        Ok(())
+    })?;
+
+    // Vertical lines sheared to 45 degrees keep their width across, rather
+    // than across the sheared line.
+    let shear = Affine::new([1.0, 0.0, 1.0, 1.0, 0.0, 0.0]);
+    rc.with_transform(Affine::translate((20.0, 70.0)) * shear, |rc| {
+        for x in &[0.0, 40.0, 80.0] {
+            rc.stroke(Line::new((*x, 0.0), (*x, 20.0)), &brush, 8.0);
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
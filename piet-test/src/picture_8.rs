@@ -0,0 +1,55 @@
+//! Every combination of line cap, line join and dashing.
+//!
+//! Rows are caps (butt, round, square), and columns are joins (bevel,
+//! miter, round), solid and then dashed. A hairline down the middle of each
+//! stroke shows where its path ends, so the caps can be told apart.
+
+use piet::kurbo::{Affine, BezPath};
+use piet::{Color, Error, LineCap, LineJoin, RenderContext, StrokeStyle};
+
+static DASHES: &[f64] = &[5.0, 3.0];
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+    let dashes = rc.capabilities().dashes;
+    let brush = rc.solid_brush(Color::rgb8(0x00, 0x00, 0xC0));
+    let guide = rc.solid_brush(Color::rgb8(0xFF, 0x40, 0x40));
+
+    // sharp enough to show the joins, and open to show the caps
+    let mut path = BezPath::new();
+    path.move_to((0.0, 20.0));
+    path.line_to((6.0, 2.0));
+    path.line_to((13.0, 20.0));
+    path.line_to((22.0, 6.0));
+
+    for (row, &line_cap) in [LineCap::Butt, LineCap::Round, LineCap::Square]
+        .iter()
+        .enumerate()
+    {
+        let y = 6.0 + 32.0 * row as f64;
+        for (col, &dashed) in [false, true].iter().enumerate() {
+            if dashed && !dashes {
+                continue;
+            }
+            for (i, &line_join) in [LineJoin::Bevel, LineJoin::Miter, LineJoin::Round]
+                .iter()
+                .enumerate()
+            {
+                let x = 8.0 + 32.0 * (col * 3 + i) as f64;
+                let mut style = StrokeStyle::new()
+                    .with_line_cap(line_cap)
+                    .with_line_join(line_join);
+                if dashed {
+                    style.set_dash(DASHES, 0.0);
+                }
+                rc.with_save(|rc| {
+                    rc.transform(Affine::translate((x, y)));
+                    rc.stroke_styled(&path, &brush, 5.0, &style);
+                    rc.stroke(&path, &guide, 0.5);
+                    Ok(())
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,132 @@
+//! Each kind of gradient, and how it extends past its ends.
+//!
+//! The top row has the fixed gradients: linear in sRGB and in linear light,
+//! radial with its origin at its center and off it, and a mesh. Each fills
+//! more than the gradient spans, and piet's gradients pad, extending the
+//! colors at their ends. The bottom row has the gradients resolved against
+//! the shape they fill, translucent stops, and a gradient under a rotation.
+
+use piet::kurbo::{Affine, Circle, Point, Rect, Vec2};
+use piet::{
+    Color, Error, FixedLinearGradient, FixedMeshGradient, FixedRadialGradient, GradientColorSpace,
+    GradientStop, GradientStops, LinearGradient, MeshPatch, RadialGradient, RenderContext,
+    ScaleMode, UnitPoint,
+};
+
+/// The `i`th of five cells in a row starting at `y`.
+fn cell(i: usize, y: f64) -> Rect {
+    let x = 5.0 + 39.0 * i as f64;
+    Rect::new(x, y, x + 34.0, y + 40.0)
+}
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+    let caps = rc.capabilities();
+    let red = Color::rgb8(0xFF, 0x00, 0x00);
+    let blue = Color::rgb8(0x00, 0x00, 0xFF);
+
+    if caps.linear_gradients {
+        // the gradient runs across the middle of its cell, and pads out
+        for (i, &color_space) in [GradientColorSpace::Srgb, GradientColorSpace::LinearRgb]
+            .iter()
+            .enumerate()
+        {
+            let rect = cell(i, 5.0);
+            let brush = rc.gradient(FixedLinearGradient {
+                start: Point::new(rect.x0 + 8.0, 0.0),
+                end: Point::new(rect.x1 - 8.0, 0.0),
+                stops: (red.clone(), blue.clone()).to_vec(),
+                color_space,
+            })?;
+            rc.fill(rect, &brush);
+        }
+    }
+
+    if caps.radial_gradients {
+        for (i, &origin_offset) in [Vec2::ZERO, Vec2::new(-6.0, -8.0)].iter().enumerate() {
+            let rect = cell(i + 2, 5.0);
+            let brush = rc.gradient(FixedRadialGradient {
+                center: rect.center(),
+                origin_offset,
+                radius: 13.0,
+                stops: (Color::WHITE, Color::rgb8(0x80, 0x00, 0x80)).to_vec(),
+                color_space: GradientColorSpace::Srgb,
+            })?;
+            rc.fill(rect, &brush);
+        }
+    }
+
+    if caps.mesh_gradients {
+        let rect = cell(4, 5.0);
+        let patch = MeshPatch::new(
+            [
+                Point::new(rect.x0, rect.y0),
+                Point::new(rect.x1, rect.y0),
+                Point::new(rect.x1, rect.y1),
+                Point::new(rect.x0, rect.y1),
+            ],
+            [
+                red.clone(),
+                Color::rgb8(0xFF, 0xFF, 0x00),
+                blue.clone(),
+                Color::BLACK,
+            ],
+        );
+        let brush = rc.gradient(FixedMeshGradient {
+            patches: vec![patch],
+        })?;
+        rc.fill(rect, &brush);
+    }
+
+    if caps.linear_gradients {
+        // uneven stops, resolved against the bounds of the circle
+        let stops = [
+            GradientStop::new(0.0, Color::rgb8(0x00, 0x80, 0x00)),
+            GradientStop::new(0.3, Color::rgb8(0xFF, 0xC0, 0x00)),
+            GradientStop::new(1.0, Color::BLACK),
+        ];
+        let gradient = LinearGradient::new(UnitPoint::TOP_LEFT, UnitPoint::BOTTOM_RIGHT, stops);
+        rc.fill(Circle::new(cell(0, 55.0).center(), 17.0), &gradient);
+
+        // fading out over a black bar, which shouldn't darken on the way
+        let rect = cell(1, 55.0);
+        rc.fill(
+            Rect::new(rect.x0, rect.y0 + 15.0, rect.x1, rect.y0 + 25.0),
+            &Color::BLACK,
+        );
+        let gradient = LinearGradient::new(
+            UnitPoint::TOP,
+            UnitPoint::BOTTOM,
+            (
+                Color::rgb8(0xFF, 0x80, 0x00),
+                Color::rgba8(0x00, 0x00, 0xFF, 0x00),
+            ),
+        );
+        rc.fill(rect, &gradient);
+
+        // rotated with the shape it fills
+        let rect = cell(4, 55.0);
+        rc.with_save(|rc| {
+            rc.transform(Affine::translate(rect.center() - Point::ORIGIN) * Affine::rotate(0.6));
+            let rect = Rect::new(-12.0, -12.0, 12.0, 12.0);
+            let gradient = LinearGradient::new(UnitPoint::LEFT, UnitPoint::RIGHT, (red, blue));
+            rc.fill(rect, &gradient);
+            Ok(())
+        })?;
+    }
+
+    if caps.radial_gradients {
+        // in a wide rectangle, a radius of half scales to fit its height,
+        // or to fill its width
+        let wide = cell(2, 55.0).union(cell(3, 55.0));
+        let stops = (Color::WHITE, Color::rgb8(0x00, 0x60, 0x60));
+        for (i, scale_mode) in [ScaleMode::Fit, ScaleMode::Fill].iter().enumerate() {
+            let y = wide.y0 + 21.0 * i as f64;
+            let rect = Rect::new(wide.x0, y, wide.x1, y + 19.0);
+            let gradient =
+                RadialGradient::new(0.5, stops.clone()).with_scale_mode(scale_mode.clone());
+            rc.fill(rect, &gradient);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! Properties of the backends' conversions, and inputs for checking them.
+//!
+//! The properties every backend shares are checked by the functions here,
+//! which each backend calls with its own conversion. With the `proptest`
+//! feature, this also has [proptest] strategies for the inputs of the
+//! properties checked per backend. Their numbers lean on [`EDGE_VALUES`],
+//! which is where conversions tend to go wrong.
+//!
+//! [proptest]: https://docs.rs/proptest
+//! [`EDGE_VALUES`]: constant.EDGE_VALUES.html
+
+#[cfg(feature = "proptest")]
+use piet::kurbo::Affine;
+#[cfg(feature = "proptest")]
+use piet::{Color, GradientStop};
+#[cfg(feature = "proptest")]
+use proptest::prelude::*;
+
+/// Values that conversions tend to get wrong: signed zeros, subnormals, the
+/// extremes, and the values that aren't finite.
+pub const EDGE_VALUES: &[f64] = &[
+    0.0,
+    -0.0,
+    1.0,
+    -1.0,
+    0.5,
+    std::f64::consts::PI,
+    5e-324,
+    f64::MIN_POSITIVE,
+    1e-300,
+    1e300,
+    f64::MAX,
+    f64::MIN,
+    f64::INFINITY,
+    f64::NEG_INFINITY,
+    f64::NAN,
+];
+
+/// Check that `premul` multiplies a channel by an alpha, both as bytes,
+/// rounding to the nearest byte, for every channel and alpha.
+///
+/// # Examples
+///
+/// ```
+/// fn premul(x: u8, a: u8) -> u8 {
+///     ((x as u32 * a as u32 + 127) / 255) as u8
+/// }
+///
+/// piet_test::properties::check_premul_rounds_to_nearest(premul);
+/// ```
+pub fn check_premul_rounds_to_nearest(premul: impl Fn(u8, u8) -> u8) {
+    for a in 0..=255u8 {
+        for x in 0..=255u8 {
+            let exact = (2 * x as u32 * a as u32 + 255) / 510;
+            assert_eq!(premul(x, a) as u32, exact, "{} * {}", x, a);
+        }
+    }
+}
+
+/// Any `f64`: an edge value a quarter of the time, any bit pattern a
+/// quarter of the time, and otherwise one from -1000 to 1000.
+#[cfg(feature = "proptest")]
+pub fn any_f64() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        1 => prop::sample::select(EDGE_VALUES),
+        1 => any::<u64>().prop_map(f64::from_bits),
+        2 => -1000.0..1000.0,
+    ]
+}
+
+/// An affine transform with any coefficients.
+#[cfg(feature = "proptest")]
+pub fn affine() -> impl Strategy<Value = Affine> {
+    prop::array::uniform6(any_f64()).prop_map(Affine::new)
+}
+
+/// Any color, of any opacity.
+#[cfg(feature = "proptest")]
+pub fn color() -> impl Strategy<Value = Color> {
+    any::<u32>().prop_map(Color::from_rgba32_u32)
+}
+
+/// From one to `max` gradient stops, in order of their positions, which
+/// may repeat.
+#[cfg(feature = "proptest")]
+pub fn stops(max: usize) -> impl Strategy<Value = Vec<GradientStop>> {
+    let pos = prop_oneof![Just(0.0), Just(1.0), 0.0..1.0f32];
+    prop::collection::vec((pos, color()), 1..=max).prop_map(|mut stops| {
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        stops
+            .into_iter()
+            .map(|(pos, color)| GradientStop::new(pos, color))
+            .collect()
+    })
+}
@@ -84,7 +84,7 @@ mod test {
     }
 
     pub fn test_hit_test_text_position_basic(ctx: &mut WebRenderContext) {
-        let text_layout = ctx;
+        let mut text_layout = ctx.text();
 
         let input = "piet text!";
         let font = text_layout
@@ -171,7 +171,7 @@ mod test {
         let input = "é";
         assert_eq!(input.len(), 2);
 
-        let text_layout = ctx;
+        let mut text_layout = ctx.text();
         let font = text_layout
             .new_font_by_name("sans-serif", 12.0)
             .build()
@@ -248,7 +248,7 @@ mod test {
         let input = "é\u{0023}\u{FE0F}\u{20E3}1\u{1D407}"; // #️⃣,, 𝐇
         assert_eq!(input.len(), 14);
 
-        let text_layout = ctx;
+        let mut text_layout = ctx.text();
         let font = text_layout
             .new_font_by_name("sans-serif", 12.0)
             .build()
@@ -332,7 +332,7 @@ mod test {
 
     // NOTE brittle test
     pub fn test_hit_test_point_basic_0(ctx: &mut WebRenderContext) {
-        let text_layout = ctx;
+        let mut text_layout = ctx.text();
 
         let font = text_layout
             .new_font_by_name("sans-serif", 12.0)
@@ -377,7 +377,7 @@ mod test {
     }
 
     pub fn test_hit_test_point_basic_1(ctx: &mut WebRenderContext) {
-        let text_layout = ctx;
+        let mut text_layout = ctx.text();
 
         // base condition, one grapheme
         let font = text_layout
@@ -414,7 +414,7 @@ mod test {
         // 4 graphemes
         let input = "é\u{0023}\u{FE0F}\u{20E3}1\u{1D407}"; // #️⃣,, 𝐇
 
-        let text_layout = ctx;
+        let mut text_layout = ctx.text();
         let font = text_layout
             .new_font_by_name("sans-serif", 12.0)
             .build()
@@ -460,7 +460,7 @@ mod test {
         // This corresponds to the char 'y' in the input.
         let input = "tßßypi";
 
-        let text_layout = ctx;
+        let mut text_layout = ctx.text();
         let font = text_layout
             .new_font_by_name("sans-serif", 12.0)
             .build()
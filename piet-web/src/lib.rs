@@ -9,39 +9,202 @@ use std::borrow::Cow;
 use std::fmt;
 use std::ops::Deref;
 
-use js_sys::{Float64Array, Reflect};
+use js_sys::{Float64Array, Function, Reflect};
 use wasm_bindgen::{Clamped, JsCast, JsValue};
 use web_sys::{
     CanvasGradient, CanvasRenderingContext2d, CanvasWindingRule, HtmlCanvasElement, ImageData,
-    Window,
+    Path2d, Window,
 };
 
-use piet::kurbo::{Affine, PathEl, Point, Rect, Shape};
+use piet::kurbo::{Affine, Insets, PathEl, Point, Rect, Shape, Size};
 
 use piet::{
-    Color, Error, FixedGradient, GradientStop, ImageFormat, InterpolationMode, IntoBrush, LineCap,
-    LineJoin, RenderContext, StrokeStyle,
+    align_stroke, new_error, shape_elements, user_tolerance, Antialiasing, Capabilities, Color,
+    DrawCall, DrawProfile, Error, ErrorKind, FallbackPolicy, FixedGradient, FrameArena,
+    GlyphPositioning, GradientStop, Image, ImageFormat, ImagePositioning, InterpolationMode,
+    IntoBrush, LayerPool, LineCap, LineJoin, MemoryCharge, MemoryCounters, MemoryReport,
+    OwnedImage, PooledSurface, Profiler, RenderContext, StrokeStyle, DEFAULT_TOLERANCE,
 };
 
-pub use text::{WebFont, WebFontBuilder, WebTextLayout, WebTextLayoutBuilder};
+pub use text::{WebFont, WebFontBuilder, WebText, WebTextLayout, WebTextLayoutBuilder};
 
 pub struct WebRenderContext<'a> {
     ctx: CanvasRenderingContext2d,
     /// Used for creating image bitmaps and possibly other resources.
     window: Window,
     err: Result<(), Error>,
+    /// The canvas API doesn't let us read back the transform, so we track it.
+    transform: Affine,
+    saves: Vec<Save>,
+    glyph_positioning: GlyphPositioning,
+    image_positioning: ImagePositioning,
+    /// The tolerance of converting shapes to paths, in device pixels.
+    tolerance: f64,
+    /// The calls made so far, for `draw_profile`.
+    profiler: Profiler,
+    fallback_policy: FallbackPolicy,
+    text: WebText,
+    clip_layers: Vec<ClipLayer>,
+    arena: WebArena,
     phantom: std::marker::PhantomData<&'a ()>,
 }
 
+/// Drawing inside a clip, done on a copy of the canvas below and blended
+/// back when the clip ends, so the clip's edges can be antialiased.
+/// What a clip layer lets through.
+enum ClipMask {
+    Shape(Vec<PathEl>),
+    /// Runs of text, each filled in its font from the left of its
+    /// baseline, and whether it's right-to-left.
+    Text(Vec<(String, String, Point, bool)>),
+}
+
+impl ClipMask {
+    /// Fill the mask on `ctx`, with its current fill style.
+    fn fill(&self, ctx: &CanvasRenderingContext2d) {
+        match self {
+            ClipMask::Shape(shape) => {
+                trace_path(ctx, shape.iter().copied());
+                ctx.fill_with_canvas_winding_rule(CanvasWindingRule::Nonzero);
+            }
+            ClipMask::Text(runs) => {
+                for (text, font, pos, rtl) in runs {
+                    ctx.set_font(font);
+                    let _ = fill_text_run(ctx, text, *pos, *rtl);
+                }
+            }
+        }
+    }
+
+    /// Done with the mask, so return a shape's buffer to `paths`.
+    fn give_back(self, paths: &mut FrameArena<PathEl>) {
+        if let ClipMask::Shape(shape) = self {
+            paths.give_back(shape);
+        }
+    }
+}
+
+thread_local! {
+    /// The memory held by the images and layer canvases made on this thread.
+    static MEMORY: MemoryCounters = MemoryCounters::new();
+}
+
+/// The buffers and canvases a context reuses for the transient data of its
+/// draw calls: the paths of clips, gradient stops, dash patterns, and
+/// layers for clips and blurs.
+///
+/// Each context has its own, and resets it from `finish`, which frees the
+/// buffers far larger than the frame needed; layers are kept until
+/// `trim_layer_pool`. An app that makes a context for each frame can carry
+/// the arena from one to the next with [`into_arena`] and [`with_arena`].
+///
+/// [`into_arena`]: struct.WebRenderContext.html#method.into_arena
+/// [`with_arena`]: struct.WebRenderContext.html#method.with_arena
+pub struct WebArena {
+    paths: FrameArena<PathEl>,
+    stops: FrameArena<GradientStop>,
+    /// The array dash patterns are passed in, made again only for a
+    /// pattern of another length.
+    dashes: Float64Array,
+    layers: LayerPool<HtmlCanvasElement>,
+}
+
+impl WebArena {
+    /// An empty arena, whose layers are charged to this thread's memory
+    /// report.
+    pub fn new() -> WebArena {
+        WebArena {
+            paths: FrameArena::new(),
+            stops: FrameArena::new(),
+            dashes: Float64Array::new_with_length(0),
+            layers: MEMORY.with(|memory| LayerPool::with_memory(&memory.layers, 4)),
+        }
+    }
+
+    /// End a frame.
+    fn reset(&mut self) {
+        self.paths.reset();
+        self.stops.reset();
+    }
+
+    fn trim(&mut self) {
+        self.paths.trim();
+        self.stops.trim();
+        self.layers.trim();
+    }
+
+    /// `dashes` in the dash array.
+    fn dash_array(&mut self, dashes: &[f64]) -> &Float64Array {
+        if self.dashes.length() as usize != dashes.len() {
+            self.dashes = Float64Array::new_with_length(dashes.len() as u32);
+        }
+        for (i, &dash) in dashes.iter().enumerate() {
+            self.dashes.set_index(i as u32, dash);
+        }
+        &self.dashes
+    }
+}
+
+impl Default for WebArena {
+    fn default() -> Self {
+        WebArena::new()
+    }
+}
+
+/// A save not yet restored.
+struct Save {
+    /// The transform to go back to.
+    transform: Affine,
+    /// Whether `push_clip` made the save.
+    pushed_clip: bool,
+}
+
+struct ClipLayer {
+    /// The context to draw on again once the clip ends.
+    parent: CanvasRenderingContext2d,
+    canvas: PooledSurface<HtmlCanvasElement>,
+    mask: ClipMask,
+    /// The number of saves in effect when the clip was set.
+    depth: usize,
+}
+
 impl<'a> WebRenderContext<'a> {
     pub fn new(ctx: CanvasRenderingContext2d, window: Window) -> WebRenderContext<'a> {
+        WebRenderContext::with_arena(ctx, window, WebArena::new())
+    }
+
+    /// Create a context that reuses `arena`, kept from an earlier context
+    /// with [`into_arena`].
+    ///
+    /// [`into_arena`]: #method.into_arena
+    pub fn with_arena(
+        ctx: CanvasRenderingContext2d,
+        window: Window,
+        arena: WebArena,
+    ) -> WebRenderContext<'a> {
         WebRenderContext {
+            text: WebText::new(ctx.clone(), window.clone()),
             ctx,
             window,
             err: Ok(()),
+            transform: Affine::default(),
+            saves: Vec::new(),
+            glyph_positioning: GlyphPositioning::default(),
+            image_positioning: ImagePositioning::default(),
+            tolerance: DEFAULT_TOLERANCE,
+            profiler: Profiler::new(),
+            fallback_policy: FallbackPolicy::default(),
+            clip_layers: Vec::new(),
+            arena,
             phantom: std::marker::PhantomData,
         }
     }
+
+    /// The context's buffers and layers, for the next frame's context to
+    /// reuse.
+    pub fn into_arena(self) -> WebArena {
+        self.arena
+    }
 }
 
 #[derive(Clone)]
@@ -56,6 +219,63 @@ pub struct WebImage {
     inner: HtmlCanvasElement,
     width: u32,
     height: u32,
+    format: ImageFormat,
+    _memory: MemoryCharge,
+}
+
+/// A `Path2D`, made with `make_path`.
+pub struct WebPath {
+    inner: Path2d,
+    bounding_box: Rect,
+}
+
+impl WebPath {
+    pub fn path(&self) -> &Path2d {
+        &self.inner
+    }
+}
+
+impl WebImage {
+    fn new(inner: HtmlCanvasElement, width: usize, height: usize, format: ImageFormat) -> Self {
+        WebImage {
+            inner,
+            width: width as u32,
+            height: height as u32,
+            format,
+            _memory: MEMORY.with(|memory| memory.images.charge(width * height * 4)),
+        }
+    }
+
+    /// Copy the pixels, unpremultiplied RGBA as the canvas gives them, for
+    /// a worker to hold.
+    pub fn to_owned_image(&self) -> Result<OwnedImage, Error> {
+        let context = self
+            .inner
+            .get_context("2d")
+            .wrap()?
+            .ok_or_else(|| new_error(ErrorKind::NotSupported))?
+            .dyn_into::<CanvasRenderingContext2d>()
+            .map_err(|_| new_error(ErrorKind::NotSupported))?;
+        let data = context
+            .get_image_data(0.0, 0.0, self.width as f64, self.height as f64)
+            .wrap()?;
+        OwnedImage::new(
+            self.width as usize,
+            self.height as usize,
+            data.data().0,
+            ImageFormat::RgbaSeparate,
+        )
+    }
+}
+
+impl Image for WebImage {
+    fn size(&self) -> Size {
+        Size::new(self.width as f64, self.height as f64)
+    }
+
+    fn format(&self) -> ImageFormat {
+        self.format
+    }
 }
 
 #[derive(Debug)]
@@ -104,25 +324,71 @@ impl<'a> RenderContext for WebRenderContext<'a> {
     /// wasm-bindgen doesn't have a native Point type, so use kurbo's.
     type Brush = Brush;
 
-    type Text = Self;
+    type Text = WebText;
     type TextLayout = WebTextLayout;
 
     type Image = WebImage;
 
+    type Path = WebPath;
+
     fn status(&mut self) -> Result<(), Error> {
         std::mem::replace(&mut self.err, Ok(()))
     }
 
     fn clear(&mut self, color: Color) {
+        let _timer = self.profiler.time(DrawCall::Fill);
         let (width, height) = match self.ctx.canvas() {
-            Some(canvas) => (canvas.offset_width(), canvas.offset_height()),
+            Some(canvas) => (canvas.width(), canvas.height()),
             None => return,
             /* Canvas might be null if the dom node is not in
              * the document; do nothing. */
         };
-        let shape = Rect::new(0.0, 0.0, width as f64, height as f64);
+        self.ctx.save();
+        // the canvas size is in device pixels
+        let _ = self.ctx.set_transform(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        self.clear_rect(Rect::new(0.0, 0.0, width as f64, height as f64), color);
+        self.ctx.restore();
+    }
+
+    fn clear_rect(&mut self, rect: Rect, color: Color) {
+        let _timer = self.profiler.time(DrawCall::Fill);
+        let rect = rect.abs();
+        // clearing leaves transparent black, so drawing over it is exact
+        self.ctx
+            .clear_rect(rect.x0, rect.y0, rect.width(), rect.height());
         let brush = self.solid_brush(color);
-        self.fill(shape, &brush);
+        self.set_brush(&brush, true);
+        self.ctx
+            .fill_rect(rect.x0, rect.y0, rect.width(), rect.height());
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            text: true,
+            images: true,
+            image_formats: &[
+                ImageFormat::Rgb,
+                ImageFormat::RgbaSeparate,
+                ImageFormat::RgbaPremul,
+                ImageFormat::BgraPremul,
+                ImageFormat::Grayscale,
+                ImageFormat::Alpha,
+            ],
+            // TODO: nearest neighbor; see draw_image
+            nearest_neighbor_interpolation: false,
+            linear_gradients: true,
+            radial_gradients: true,
+            mesh_gradients: false,
+            dashes: true,
+            read_back: true,
+            render_targets: true,
+            antialiasing: false,
+            picking: false,
+            font_loading: true,
+            vertical_text: false,
+            variable_fonts: false,
+            glyph_drawing: false,
+        }
     }
 
     fn solid_brush(&mut self, color: Color) -> Brush {
@@ -135,7 +401,10 @@ impl<'a> RenderContext for WebRenderContext<'a> {
                 let (x0, y0) = (linear.start.x, linear.start.y);
                 let (x1, y1) = (linear.end.x, linear.end.y);
                 let mut lg = self.ctx.create_linear_gradient(x0, y0, x1, y1);
-                set_gradient_stops(&mut lg, &linear.stops);
+                let stops =
+                    (self.arena.stops).collect(linear.color_space.resolved_stops(&linear.stops));
+                set_gradient_stops(&mut lg, &stops);
+                self.arena.stops.give_back(stops);
                 Ok(Brush::Gradient(lg))
             }
             FixedGradient::Radial(radial) => {
@@ -146,13 +415,22 @@ impl<'a> RenderContext for WebRenderContext<'a> {
                     .ctx
                     .create_radial_gradient(xc + xo, yc + yo, 0.0, xc, yc, r)
                     .wrap()?;
-                set_gradient_stops(&mut rg, &radial.stops);
+                let stops =
+                    (self.arena.stops).collect(radial.color_space.resolved_stops(&radial.stops));
+                set_gradient_stops(&mut rg, &stops);
+                self.arena.stops.give_back(stops);
                 Ok(Brush::Gradient(rg))
             }
+            // canvas has no mesh gradients
+            FixedGradient::Mesh(mesh) => {
+                let color = self.fallback_policy.degrade(|| mesh.fallback_color())?;
+                Ok(self.solid_brush(color))
+            }
         }
     }
 
     fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
         self.set_brush(&*brush, true);
@@ -161,6 +439,7 @@ impl<'a> RenderContext for WebRenderContext<'a> {
     }
 
     fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
         self.set_brush(&*brush, true);
@@ -168,13 +447,147 @@ impl<'a> RenderContext for WebRenderContext<'a> {
             .fill_with_canvas_winding_rule(CanvasWindingRule::Evenodd);
     }
 
+    fn fill_rects(&mut self, rects: &[Rect], brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
+        if rects.is_empty() {
+            return;
+        }
+        let brush = brush.make_brush(self, || {
+            rects
+                .iter()
+                .fold(rects[0].abs(), |bbox, rect| bbox.union(rect.abs()))
+        });
+        // one call per rectangle, rather than one per corner
+        self.ctx.begin_path();
+        for rect in rects {
+            let rect = rect.abs();
+            self.ctx.rect(rect.x0, rect.y0, rect.width(), rect.height());
+        }
+        self.set_brush(&*brush, true);
+        self.ctx
+            .fill_with_canvas_winding_rule(CanvasWindingRule::Nonzero);
+    }
+
     fn clip(&mut self, shape: impl Shape) {
-        self.set_path(shape);
+        let _timer = self.profiler.time(DrawCall::State);
+        // Browsers don't all antialias clips, so the clip is applied with a
+        // fill when it ends instead.
+        let tolerance = user_tolerance(self.tolerance, self.transform);
+        let shape = self.arena.paths.collect(shape_elements(&shape, tolerance));
+        if let Err(e) = self.push_clip_layer(ClipMask::Shape(shape)) {
+            self.err = Err(e);
+        }
+    }
+
+    fn clip_text(&mut self, layout: &Self::TextLayout, pos: impl Into<Point>) {
+        let _timer = self.profiler.time(DrawCall::State);
+        // canvas has no text paths, so text can only clip through a layer
+        let pos = self.text_origin(pos.into());
+        let mask = ClipMask::Text(
+            layout
+                .lines()
+                .map(|(text, origin, font, rtl)| {
+                    (
+                        text.to_owned(),
+                        font.get_font_string(),
+                        pos + origin.to_vec2(),
+                        rtl,
+                    )
+                })
+                .collect(),
+        );
+        if let Err(e) = self.push_clip_layer(mask) {
+            self.err = Err(e);
+        }
+    }
+
+    fn push_clip(&mut self, shape: impl Shape) {
+        let _timer = self.profiler.time(DrawCall::State);
+        let _ = self.save();
+        self.saves.last_mut().unwrap().pushed_clip = true;
+        self.clip(shape);
+    }
+
+    fn pop_clip(&mut self) -> Result<(), Error> {
+        let _timer = self.profiler.time(DrawCall::State);
+        if !self.saves.last().is_some_and(|save| save.pushed_clip) {
+            return Err(new_error(ErrorKind::StackUnbalance));
+        }
+        let transform = self.transform;
+        self.restore_one();
+        self.transform = transform;
+        let a = transform.as_coeffs();
         self.ctx
-            .clip_with_canvas_winding_rule(CanvasWindingRule::Nonzero);
+            .set_transform(a[0], a[1], a[2], a[3], a[4], a[5])
+            .wrap()
+    }
+
+    fn reset_clip(&mut self) {
+        let _timer = self.profiler.time(DrawCall::State);
+        while self.pop_clip().is_ok() {}
+        self.pop_clip_layers(self.saves.len());
+        if self.ctx.canvas().is_none() && !self.saves.is_empty() {
+            // Without a canvas, clips are the context's own, and going back
+            // to the save is the only way to remove them.
+            self.ctx.restore();
+            self.ctx.save();
+            let a = self.transform.as_coeffs();
+            let _ = self.ctx.set_transform(a[0], a[1], a[2], a[3], a[4], a[5]);
+        }
+    }
+
+    fn make_path(&mut self, shape: impl Shape) -> Result<Self::Path, Error> {
+        let inner = Path2d::new().wrap()?;
+        let tolerance = user_tolerance(self.tolerance, self.transform);
+        for el in shape_elements(&shape, tolerance) {
+            match el {
+                PathEl::MoveTo(p) => inner.move_to(p.x, p.y),
+                PathEl::LineTo(p) => inner.line_to(p.x, p.y),
+                PathEl::QuadTo(p1, p2) => inner.quadratic_curve_to(p1.x, p1.y, p2.x, p2.y),
+                PathEl::CurveTo(p1, p2, p3) => {
+                    inner.bezier_curve_to(p1.x, p1.y, p2.x, p2.y, p3.x, p3.y)
+                }
+                PathEl::ClosePath => inner.close_path(),
+            }
+        }
+        Ok(WebPath {
+            inner,
+            bounding_box: shape.bounding_box(),
+        })
+    }
+
+    fn fill_path(&mut self, path: &Self::Path, brush: &impl IntoBrush<Self>) {
+        let _timer = self.profiler.time(DrawCall::Fill);
+        let brush = brush.make_brush(self, || path.bounding_box);
+        self.set_brush(&brush, true);
+        self.ctx
+            .fill_with_path_2d_and_winding(&path.inner, CanvasWindingRule::Nonzero);
+    }
+
+    fn stroke_path(&mut self, path: &Self::Path, brush: &impl IntoBrush<Self>, width: f64) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
+        let brush = brush.make_brush(self, || path.bounding_box);
+        self.set_stroke(width, None);
+        self.set_brush(&brush, false);
+        self.ctx.stroke_with_path(&path.inner);
+    }
+
+    fn stroke_path_styled(
+        &mut self,
+        path: &Self::Path,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
+        let brush = brush.make_brush(self, || path.bounding_box);
+        self.set_stroke(width, Some(style));
+        self.set_brush(&brush, false);
+        self.ctx.stroke_with_path(&path.inner);
     }
 
     fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
         self.set_stroke(width, None);
@@ -189,6 +602,10 @@ impl<'a> RenderContext for WebRenderContext<'a> {
         width: f64,
         style: &StrokeStyle,
     ) {
+        let _timer = self.profiler.time(DrawCall::Stroke);
+        if let Some((path, style)) = align_stroke(&shape, width, style) {
+            return self.stroke_styled(path, brush, width, &style);
+        }
         let brush = brush.make_brush(self, || shape.bounding_box());
         self.set_path(shape);
         self.set_stroke(width, Some(style));
@@ -196,8 +613,8 @@ impl<'a> RenderContext for WebRenderContext<'a> {
         self.ctx.stroke();
     }
 
-    fn text(&mut self) -> &mut Self::Text {
-        self
+    fn text(&self) -> Self::Text {
+        self.text.clone()
     }
 
     fn draw_text(
@@ -206,40 +623,179 @@ impl<'a> RenderContext for WebRenderContext<'a> {
         pos: impl Into<Point>,
         brush: &impl IntoBrush<Self>,
     ) {
+        let _timer = self.profiler.time(DrawCall::Text);
         // TODO: bounding box for text
         let brush = brush.make_brush(self, || Rect::ZERO);
-        self.ctx.set_font(&layout.font.get_font_string());
-        self.set_brush(&*brush, true);
-        let pos = pos.into();
-        if let Err(e) = self.ctx.fill_text(&layout.text, pos.x, pos.y).wrap() {
-            self.err = Err(e);
+        let pos = self.prepare_text(layout, pos.into(), &brush);
+        let (bitmaps, runs) = match layout.split_runs() {
+            Some(split) => split,
+            None => (
+                Vec::new(),
+                layout
+                    .lines()
+                    .map(|(text, origin, font, rtl)| (text, origin, None, font, rtl))
+                    .collect(),
+            ),
+        };
+        for (run, offset, color, font, rtl) in runs {
+            self.ctx.set_font(&font.get_font_string());
+            match color {
+                Some(color) => self.set_brush(&Brush::Solid(color.as_rgba_u32()), true),
+                None => self.set_brush(&brush, true),
+            }
+            let origin = pos + offset.to_vec2();
+            if let Err(e) = fill_text_run(&self.ctx, run, origin, rtl).wrap() {
+                self.err = Err(e);
+            }
+        }
+        for (rect, bitmap) in bitmaps {
+            let rect = bitmap.fit(rect + pos.to_vec2());
+            match self.make_image(
+                bitmap.width(),
+                bitmap.height(),
+                bitmap.buf(),
+                ImageFormat::RgbaSeparate,
+            ) {
+                Ok(image) => self.draw_image(&image, rect, InterpolationMode::Bilinear),
+                Err(e) => self.err = Err(e),
+            }
+        }
+        self.draw_decorations(layout, pos, &brush);
+    }
+
+    fn draw_glyph_run(
+        &mut self,
+        layout: &Self::TextLayout,
+        pos: impl Into<Point>,
+        brush: &impl IntoBrush<Self>,
+        transforms: &[Affine],
+    ) {
+        let _timer = self.profiler.time(DrawCall::Text);
+        let brush = brush.make_brush(self, || Rect::ZERO);
+        let pos = self.prepare_text(layout, pos.into(), &brush);
+        let colors = layout.model.cluster_colors();
+        let fonts = layout.cluster_fonts();
+        // canvas doesn't expose glyphs, so draw each cluster on its own
+        for (i, (range, offset)) in layout.clusters().iter().enumerate() {
+            let offset = match offset {
+                Some(offset) => *offset,
+                None => continue,
+            };
+            match colors.get(i).copied().flatten() {
+                Some(color) => self.set_brush(&Brush::Solid(color.as_rgba_u32()), true),
+                None => self.set_brush(&brush, true),
+            }
+            if let Some(font) = fonts.get(i) {
+                self.ctx.set_font(&font.get_font_string());
+            }
+            let transform = transforms.get(i).copied().unwrap_or_default();
+            let a = (Affine::translate(pos.to_vec2() + offset.to_vec2()) * transform).as_coeffs();
+            self.ctx.save();
+            let _ = self.ctx.transform(a[0], a[1], a[2], a[3], a[4], a[5]);
+            if let Err(e) = self
+                .ctx
+                .fill_text(&layout.text[range.clone()], 0.0, 0.0)
+                .wrap()
+            {
+                self.err = Err(e);
+            }
+            self.ctx.restore();
         }
+        self.draw_decorations(layout, pos, &brush);
+    }
+
+    fn draw_glyphs(
+        &mut self,
+        _font: &WebFont,
+        _glyphs: &[(u16, Point)],
+        _brush: &impl IntoBrush<Self>,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Text);
+        // canvas only draws strings, so there's no way to name a glyph
+    }
+
+    fn set_glyph_positioning(&mut self, positioning: GlyphPositioning) {
+        self.glyph_positioning = positioning;
+    }
+
+    fn set_image_positioning(&mut self, positioning: ImagePositioning) {
+        self.image_positioning = positioning;
+    }
+
+    fn set_tolerance(&mut self, tolerance: f64) {
+        if tolerance > 0.0 && tolerance.is_finite() {
+            self.tolerance = tolerance;
+        }
+    }
+
+    fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    fn set_antialiasing(&mut self, _mode: Antialiasing) {}
+
+    fn set_pick_id(&mut self, _id: Option<u64>) {}
+
+    fn set_fallback_policy(&mut self, policy: FallbackPolicy) {
+        self.fallback_policy = policy;
     }
 
     fn save(&mut self) -> Result<(), Error> {
+        let _timer = self.profiler.time(DrawCall::State);
         self.ctx.save();
+        self.saves.push(Save {
+            transform: self.transform,
+            pushed_clip: false,
+        });
         Ok(())
     }
 
     fn restore(&mut self) -> Result<(), Error> {
-        self.ctx.restore();
+        let _timer = self.profiler.time(DrawCall::State);
+        // clips pushed since the save go with it
+        let depth = self
+            .saves
+            .iter()
+            .rposition(|save| !save.pushed_clip)
+            .ok_or_else(|| new_error(ErrorKind::StackUnbalance))?;
+        while self.saves.len() > depth {
+            self.restore_one();
+        }
         Ok(())
     }
 
     fn finish(&mut self) -> Result<(), Error> {
+        let balanced = piet::check_usage(
+            self.saves.is_empty(),
+            ErrorKind::StackUnbalance,
+            "finish called with saves not restored or clips not popped",
+        );
+        self.pop_clip_layers(0);
+        self.arena.reset();
+        balanced?;
         self.status()
     }
 
     fn transform(&mut self, transform: Affine) {
+        let _timer = self.profiler.time(DrawCall::State);
+        // canvas ignores non-finite transforms, but the tracked one would
+        // keep them
+        let combined = self.transform * transform;
+        if let Err(e) = piet::check_transform(combined) {
+            self.err = Err(e);
+            return;
+        }
+        self.transform = combined;
         let a = transform.as_coeffs();
         let _ = self.ctx.transform(a[0], a[1], a[2], a[3], a[4], a[5]);
     }
 
     fn current_transform(&self) -> Affine {
-        // todo
-        // current_transform() and get_transform() currently not implemented:
+        // getTransform() isn't exposed by web-sys, see
         // https://github.com/rustwasm/wasm-bindgen/blob/f8354b3a88de013845a304ea77d8b9b9286a0d7b/crates/web-sys/webidls/enabled/CanvasRenderingContext2D.webidl#L136
-        Affine::default()
+        // so this is the transform applied through piet, which doesn't
+        // include anything set on the context before it was wrapped.
+        self.transform
     }
 
     fn make_image(
@@ -249,6 +805,7 @@ impl<'a> RenderContext for WebRenderContext<'a> {
         buf: &[u8],
         format: ImageFormat,
     ) -> Result<Self::Image, Error> {
+        piet::check_image_buffer(width, height, buf, format)?;
         let document = self.window.document().unwrap();
         let element = document.create_element("canvas").unwrap();
         let canvas = element.dyn_into::<HtmlCanvasElement>().unwrap();
@@ -259,21 +816,18 @@ impl<'a> RenderContext for WebRenderContext<'a> {
             // See https://github.com/rustwasm/wasm-bindgen/issues/1005 for an issue that might
             // also resolve the need to clone.
             ImageFormat::RgbaSeparate => buf.to_vec(),
-            ImageFormat::RgbaPremul => {
-                fn unpremul(x: u8, a: u8) -> u8 {
-                    if a == 0 {
-                        0
-                    } else {
-                        let y = (x as u32 * 255 + (a as u32 / 2)) / (a as u32);
-                        y.min(255) as u8
-                    }
-                }
+            ImageFormat::RgbaPremul | ImageFormat::BgraPremul => {
+                let (r, b) = if format == ImageFormat::BgraPremul {
+                    (2, 0)
+                } else {
+                    (0, 2)
+                };
                 let mut new_buf = vec![0; width * height * 4];
                 for i in 0..width * height {
                     let a = buf[i * 4 + 3];
-                    new_buf[i * 4 + 0] = unpremul(buf[i * 4 + 0], a);
+                    new_buf[i * 4 + 0] = unpremul(buf[i * 4 + r], a);
                     new_buf[i * 4 + 1] = unpremul(buf[i * 4 + 1], a);
-                    new_buf[i * 4 + 2] = unpremul(buf[i * 4 + 2], a);
+                    new_buf[i * 4 + 2] = unpremul(buf[i * 4 + b], a);
                     new_buf[i * 4 + 3] = a;
                 }
                 new_buf
@@ -288,6 +842,20 @@ impl<'a> RenderContext for WebRenderContext<'a> {
                 }
                 new_buf
             }
+            ImageFormat::Grayscale => {
+                let mut new_buf = vec![255; width * height * 4];
+                for i in 0..width * height {
+                    new_buf[i * 4..i * 4 + 3].copy_from_slice(&[buf[i]; 3]);
+                }
+                new_buf
+            }
+            ImageFormat::Alpha => {
+                let mut new_buf = vec![0; width * height * 4];
+                for i in 0..width * height {
+                    new_buf[i * 4 + 3] = buf[i];
+                }
+                new_buf
+            }
             _ => Vec::new(),
         };
         let image_data =
@@ -299,11 +867,7 @@ impl<'a> RenderContext for WebRenderContext<'a> {
             .dyn_into::<web_sys::CanvasRenderingContext2d>()
             .unwrap();
         context.put_image_data(&image_data, 0.0, 0.0).wrap()?;
-        Ok(WebImage {
-            inner: canvas,
-            width: width as u32,
-            height: height as u32,
-        })
+        Ok(WebImage::new(canvas, width, height, format))
     }
 
     #[inline]
@@ -313,7 +877,13 @@ impl<'a> RenderContext for WebRenderContext<'a> {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        draw_image(self, image, None, dst_rect.into(), interp);
+        let _timer = self.profiler.time(DrawCall::Image);
+        let dst_rect = piet::position_image(
+            dst_rect.into(),
+            self.image_positioning,
+            self.current_transform(),
+        );
+        draw_image(self, image, None, dst_rect, interp);
     }
 
     #[inline]
@@ -324,7 +894,318 @@ impl<'a> RenderContext for WebRenderContext<'a> {
         dst_rect: impl Into<Rect>,
         interp: InterpolationMode,
     ) {
-        draw_image(self, image, Some(src_rect.into()), dst_rect.into(), interp);
+        let _timer = self.profiler.time(DrawCall::Image);
+        let dst_rect = piet::position_image(
+            dst_rect.into(),
+            self.image_positioning,
+            self.current_transform(),
+        );
+        draw_image(self, image, Some(src_rect.into()), dst_rect, interp);
+    }
+
+    fn draw_image_transformed(
+        &mut self,
+        image: &Self::Image,
+        transform: Affine,
+        _interp: InterpolationMode,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        self.ctx.save();
+        // not through self.transform, so the tracked transform is unchanged
+        let a = transform.as_coeffs();
+        let _ = self.ctx.transform(a[0], a[1], a[2], a[3], a[4], a[5]);
+        let result = self
+            .ctx
+            .draw_image_with_html_canvas_element(&image.inner, 0.0, 0.0)
+            .wrap();
+        self.ctx.restore();
+        if let Err(e) = result {
+            self.err = Err(e);
+        }
+    }
+
+    fn draw_tiles(
+        &mut self,
+        atlas: &Self::Image,
+        tiles: &[(Rect, Point)],
+        _interp: InterpolationMode,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        for &(src_rect, dst_pos) in tiles {
+            let dst_rect = piet::position_image(
+                Rect::from_origin_size(dst_pos, src_rect.size()),
+                self.image_positioning,
+                self.current_transform(),
+            );
+            // canvas doesn't sample outside the source rectangle
+            let result = self
+                .ctx
+                .draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                    &atlas.inner,
+                    src_rect.x0,
+                    src_rect.y0,
+                    src_rect.width(),
+                    src_rect.height(),
+                    dst_rect.x0,
+                    dst_rect.y0,
+                    dst_rect.width(),
+                    dst_rect.height(),
+                )
+                .wrap();
+            if let Err(e) = result {
+                self.err = Err(e);
+                return;
+            }
+        }
+    }
+
+    fn draw_images(&mut self, images: &[(&Self::Image, Rect, f64)], _interp: InterpolationMode) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        self.ctx.save();
+        for &(image, dst_rect, alpha) in images {
+            let dst_rect =
+                piet::position_image(dst_rect, self.image_positioning, self.current_transform());
+            if alpha.is_nan() || alpha <= 0.0 {
+                continue;
+            }
+            self.ctx.set_global_alpha(alpha.min(1.0));
+            let result = self
+                .ctx
+                .draw_image_with_html_canvas_element_and_dw_and_dh(
+                    &image.inner,
+                    dst_rect.x0,
+                    dst_rect.y0,
+                    dst_rect.width(),
+                    dst_rect.height(),
+                )
+                .wrap();
+            if let Err(e) = result {
+                self.err = Err(e);
+                break;
+            }
+        }
+        self.ctx.restore();
+    }
+
+    fn make_render_target(&mut self, width: usize, height: usize) -> Result<Self::Image, Error> {
+        let document = self.window.document().unwrap();
+        let element = document.create_element("canvas").unwrap();
+        let canvas = element.dyn_into::<HtmlCanvasElement>().unwrap();
+        canvas.set_width(width as u32);
+        canvas.set_height(height as u32);
+        Ok(WebImage::new(
+            canvas,
+            width,
+            height,
+            ImageFormat::RgbaPremul,
+        ))
+    }
+
+    fn draw_to_target(
+        &mut self,
+        target: &mut Self::Image,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let context = target
+            .inner
+            .get_context("2d")
+            .wrap()?
+            .ok_or_else(|| new_error(ErrorKind::InvalidInput))?
+            .dyn_into::<web_sys::CanvasRenderingContext2d>()
+            .map_err(|_| new_error(ErrorKind::InvalidInput))?;
+
+        let old_ctx = std::mem::replace(&mut self.ctx, context);
+        let old_transform = std::mem::take(&mut self.transform);
+        let old_saves = std::mem::take(&mut self.saves);
+        let old_layers = std::mem::take(&mut self.clip_layers);
+        // The target is drawn into again later, and its context keeps its
+        // state, so leave the clip and transform as they were.
+        self.ctx.save();
+        let _ = self.ctx.set_transform(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        let result = f(self);
+        self.pop_clip_layers(0);
+        for _ in self.saves.drain(..) {
+            self.ctx.restore();
+        }
+        self.ctx.restore();
+        self.ctx = old_ctx;
+        self.transform = old_transform;
+        self.saves = old_saves;
+        self.clip_layers = old_layers;
+        result
+    }
+
+    fn capture_image_area(&mut self, src_rect: impl Into<Rect>) -> Result<Self::Image, Error> {
+        // getImageData works in canvas pixels, ignoring the transform.
+        let device_rect = self.transform.transform_rect_bbox(src_rect.into()).expand();
+        let (width, height) = (device_rect.width(), device_rect.height());
+        if width <= 0.0 || height <= 0.0 {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        let image_data = self
+            .ctx
+            .get_image_data(device_rect.x0, device_rect.y0, width, height)
+            .wrap()?;
+        let document = self.window.document().unwrap();
+        let element = document.create_element("canvas").unwrap();
+        let canvas = element.dyn_into::<HtmlCanvasElement>().unwrap();
+        canvas.set_width(width as u32);
+        canvas.set_height(height as u32);
+        let context = canvas
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<web_sys::CanvasRenderingContext2d>()
+            .unwrap();
+        context.put_image_data(&image_data, 0.0, 0.0).wrap()?;
+        let (width, height) = (width as usize, height as usize);
+        Ok(WebImage::new(
+            canvas,
+            width,
+            height,
+            ImageFormat::RgbaPremul,
+        ))
+    }
+
+    fn with_blur(
+        &mut self,
+        radius: f64,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let target = match self.ctx.canvas() {
+            Some(canvas) => canvas,
+            // no canvas to size a layer by, so draw unblurred
+            None => {
+                self.fallback_policy.degrade(|| ())?;
+                return f(self);
+            }
+        };
+        let (width, height) = (target.width(), target.height());
+        let (canvas, context) = self.take_layer(width, height)?;
+        let matrix = match copy_transform(&self.ctx, &context) {
+            Ok(matrix) => matrix,
+            Err(e) => {
+                self.arena.layers.give_back(canvas);
+                return Err(e);
+            }
+        };
+        let coeff = |name| {
+            Reflect::get(&matrix, &JsValue::from_str(name))
+                .ok()
+                .and_then(|value| value.as_f64())
+                .unwrap_or(0.0)
+        };
+        let det = coeff("a") * coeff("d") - coeff("b") * coeff("c");
+        let std_dev = radius.abs() * det.abs().sqrt();
+
+        let old_ctx = std::mem::replace(&mut self.ctx, context);
+        let transform = self.transform;
+        let old_saves = std::mem::take(&mut self.saves);
+        let old_layers = std::mem::take(&mut self.clip_layers);
+        let result = f(self);
+        self.pop_clip_layers(0);
+        // the canvas goes back in the pool, so leave no saves on it
+        for _ in self.saves.drain(..) {
+            self.ctx.restore();
+        }
+        let context = std::mem::replace(&mut self.ctx, old_ctx);
+        self.transform = transform;
+        self.saves = old_saves;
+        self.clip_layers = old_layers;
+        let result =
+            result.and_then(|()| self.draw_blurred(&canvas, &context, (width, height), std_dev));
+        self.arena.layers.give_back(canvas);
+        result
+    }
+
+    fn trim_layer_pool(&mut self) {
+        self.arena.layers.trim();
+    }
+
+    /// Covers the images and layer canvases made on this thread. Text is
+    /// laid out and drawn by the browser, which keeps its own caches.
+    fn memory_report(&self) -> MemoryReport {
+        MEMORY.with(|memory| memory.report())
+    }
+
+    fn trim_caches(&mut self) {
+        self.arena.trim();
+    }
+
+    fn draw_profile(&self) -> DrawProfile {
+        self.profiler.profile()
+    }
+
+    fn reset_draw_profile(&mut self) {
+        self.profiler.reset();
+    }
+
+    fn path_allocations(&self) -> usize {
+        self.arena.paths.allocations()
+    }
+
+    fn draw_image_nine(
+        &mut self,
+        image: &Self::Image,
+        insets: Insets,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        let size = Size::new(image.width as f64, image.height as f64);
+        let dst_rect = piet::position_image(
+            dst_rect.into(),
+            self.image_positioning,
+            self.current_transform(),
+        );
+        for (src, dst) in piet::nine_slice(size, insets, dst_rect) {
+            draw_image(self, image, Some(src), dst, interp);
+        }
+    }
+
+    fn draw_image_clipped(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        shape: impl Shape,
+        _interp: InterpolationMode,
+    ) {
+        let _timer = self.profiler.time(DrawCall::Image);
+        let dst_rect = piet::position_image(
+            dst_rect.into(),
+            self.image_positioning,
+            self.current_transform(),
+        );
+        if dst_rect.area() == 0.0 || image.width == 0 || image.height == 0 {
+            return;
+        }
+        // Filling the shape with the image as a pattern is antialiased too,
+        // without the copy of the canvas a clip takes.
+        let pattern = match self
+            .ctx
+            .create_pattern_with_html_canvas_element(&image.inner, "no-repeat")
+            .wrap()
+        {
+            Ok(Some(pattern)) => pattern,
+            Ok(None) => return,
+            Err(e) => {
+                self.err = Err(e);
+                return;
+            }
+        };
+        self.ctx.save();
+        // The path keeps the transform it was built with, while the pattern
+        // follows the one in effect when filling.
+        self.set_path(shape);
+        let _ = self.ctx.translate(dst_rect.x0, dst_rect.y0);
+        let _ = self.ctx.scale(
+            dst_rect.width() / image.width as f64,
+            dst_rect.height() / image.height as f64,
+        );
+        self.ctx.set_fill_style_canvas_pattern(&pattern);
+        self.ctx
+            .fill_with_canvas_winding_rule(CanvasWindingRule::Nonzero);
+        self.ctx.restore();
     }
 }
 
@@ -396,6 +1277,38 @@ fn set_gradient_stops(dst: &mut CanvasGradient, src: &[GradientStop]) {
 }
 
 impl WebRenderContext<'_> {
+    /// Get ready to draw `layout` at `pos`: paint its backgrounds, and set
+    /// the font and brush. Returns the origin to draw the glyphs at.
+    fn prepare_text(&mut self, layout: &WebTextLayout, pos: Point, brush: &Brush) -> Point {
+        let pos = self.text_origin(pos);
+        for (rect, color) in layout.model.backgrounds() {
+            self.fill(rect + pos.to_vec2(), color);
+        }
+        self.ctx.set_font(&layout.font.get_font_string());
+        self.set_brush(brush, true);
+        pos
+    }
+
+    /// Paint the underlines and strikethroughs of `layout`, drawn at `pos`,
+    /// over its glyphs.
+    fn draw_decorations(&mut self, layout: &WebTextLayout, pos: Point, brush: &Brush) {
+        for (rect, color) in layout.model.decorations() {
+            match color {
+                Some(color) => self.fill(rect + pos.to_vec2(), color),
+                None => self.fill(rect + pos.to_vec2(), brush),
+            }
+        }
+    }
+
+    /// Where to put the origin of text drawn at `pos`, honoring the glyph
+    /// positioning.
+    fn text_origin(&self, pos: Point) -> Point {
+        match self.glyph_positioning {
+            GlyphPositioning::PixelSnapped => self.snap_to_device_pixel(pos),
+            GlyphPositioning::Subpixel => pos,
+        }
+    }
+
     /// Set the source pattern to the brush.
     ///
     /// Web canvas is super stateful, and we're trying to have more retained stuff.
@@ -440,45 +1353,308 @@ impl WebRenderContext<'_> {
         let miter_limit = style.and_then(|style| style.miter_limit).unwrap_or(10.0);
         self.ctx.set_miter_limit(miter_limit);
 
-        let (dash_segs, dash_offset) = style
-            .and_then(|style| style.dash.as_ref())
-            .map(|dash| {
-                let len = dash.0.len() as u32;
-                let array = Float64Array::new_with_length(len);
-                for (i, elem) in dash.0.iter().enumerate() {
-                    Reflect::set(
-                        array.as_ref(),
-                        &JsValue::from(i as u32),
-                        &JsValue::from(*elem),
-                    )
-                    .unwrap();
-                }
-                (array, dash.1)
-            })
-            .unwrap_or((Float64Array::new_with_length(0), 0.0));
+        // canvas repeats odd patterns itself
+        let (dashes, offset) = style
+            .and_then(|style| style.dash_lengths())
+            .unwrap_or((&[], 0.0));
+        let dashes = self.arena.dash_array(dashes);
+        self.ctx.set_line_dash(dashes.as_ref()).unwrap();
+        self.ctx.set_line_dash_offset(offset);
+    }
 
-        self.ctx.set_line_dash(dash_segs.as_ref()).unwrap();
-        self.ctx.set_line_dash_offset(dash_offset);
+    /// Round a point in user space to the nearest whole device pixel.
+    fn snap_to_device_pixel(&self, point: Point) -> Point {
+        let device = self.transform * point;
+        self.transform.inverse() * Point::new(device.x.round(), device.y.round())
     }
 
     fn set_path(&mut self, shape: impl Shape) {
-        // This shouldn't be necessary, we always leave the context in no-path
-        // state. But just in case, and it should be harmless.
-        self.ctx.begin_path();
-        for el in shape.to_bez_path(1e-3) {
-            match el {
-                PathEl::MoveTo(p) => self.ctx.move_to(p.x, p.y),
-                PathEl::LineTo(p) => self.ctx.line_to(p.x, p.y),
-                PathEl::QuadTo(p1, p2) => self.ctx.quadratic_curve_to(p1.x, p1.y, p2.x, p2.y),
-                PathEl::CurveTo(p1, p2, p3) => {
-                    self.ctx.bezier_curve_to(p1.x, p1.y, p2.x, p2.y, p3.x, p3.y)
-                }
-                PathEl::ClosePath => self.ctx.close_path(),
+        let tolerance = user_tolerance(self.tolerance, self.transform);
+        trace_path(&self.ctx, shape_elements(&shape, tolerance));
+    }
+
+    /// Continue drawing on a copy of the canvas, until the clip ends.
+    fn push_clip_layer(&mut self, mask: ClipMask) -> Result<(), Error> {
+        let target = match (self.ctx.canvas(), &mask) {
+            (Some(canvas), _) => canvas,
+            (None, ClipMask::Shape(shape)) => {
+                // nothing to copy, so fall back to the canvas's own clip
+                trace_path(&self.ctx, shape.iter().copied());
+                self.ctx
+                    .clip_with_canvas_winding_rule(CanvasWindingRule::Nonzero);
+                mask.give_back(&mut self.arena.paths);
+                return Ok(());
+            }
+            (None, ClipMask::Text(_)) => return Err(new_error(ErrorKind::NotSupported)),
+        };
+        let (canvas, context) = self.take_layer(target.width(), target.height())?;
+        let copied = context
+            .draw_image_with_html_canvas_element(&target, 0.0, 0.0)
+            .wrap()
+            .and_then(|()| copy_transform(&self.ctx, &context));
+        if let Err(e) = copied {
+            self.arena.layers.give_back(canvas);
+            return Err(e);
+        }
+        // what pop_clip_layer restores to, to apply the clip
+        context.save();
+
+        let parent = std::mem::replace(&mut self.ctx, context);
+        self.clip_layers.push(ClipLayer {
+            parent,
+            canvas,
+            mask,
+            depth: self.saves.len(),
+        });
+        Ok(())
+    }
+
+    /// End the clips set with at least `depth` saves in effect.
+    fn pop_clip_layers(&mut self, depth: usize) {
+        while self
+            .clip_layers
+            .last()
+            .is_some_and(|layer| layer.depth >= depth)
+        {
+            let layer = self.clip_layers.pop().unwrap();
+            let context = std::mem::replace(&mut self.ctx, layer.parent);
+            // back to the transform the clip was set with, which the parent
+            // still has
+            context.restore();
+            let _ = context.set_global_composite_operation("destination-in");
+            layer.mask.fill(&context);
+
+            // Erase the parent where the layer shows, and add the two, so
+            // partly covered pixels blend by coverage.
+            self.ctx.save();
+            self.set_brush(&Brush::Solid(0xff), true);
+            let _ = self.ctx.set_global_composite_operation("destination-out");
+            layer.mask.fill(&self.ctx);
+            let _ = self.ctx.set_global_composite_operation("lighter");
+            let _ = self.ctx.set_transform(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+            let _ = self
+                .ctx
+                .draw_image_with_html_canvas_element(layer.canvas.surface(), 0.0, 0.0);
+            self.ctx.restore();
+            self.arena.layers.give_back(layer.canvas);
+            layer.mask.give_back(&mut self.arena.paths);
+        }
+    }
+
+    /// Undo the last save, whether `save` or `push_clip` made it.
+    fn restore_one(&mut self) {
+        self.pop_clip_layers(self.saves.len());
+        self.ctx.restore();
+        self.transform = self.saves.pop().unwrap().transform;
+    }
+
+    /// Take a canvas from the layer pool with at least `width` by `height`
+    /// pixels, cleared and with its context in its default state.
+    fn take_layer(
+        &mut self,
+        width: u32,
+        height: u32,
+    ) -> Result<(PooledSurface<HtmlCanvasElement>, CanvasRenderingContext2d), Error> {
+        let window = &self.window;
+        let canvas = self
+            .arena
+            .layers
+            .take(width as usize, height as usize, |w, h| {
+                let document = window.document().unwrap();
+                let element = document.create_element("canvas").unwrap();
+                let canvas = element.dyn_into::<HtmlCanvasElement>().unwrap();
+                canvas.set_width(w as u32);
+                canvas.set_height(h as u32);
+                Ok::<_, Error>(canvas)
+            })?;
+        let context = canvas
+            .surface()
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<web_sys::CanvasRenderingContext2d>()
+            .unwrap();
+        // undo whatever the last layer left set
+        let _ = context.set_transform(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        context.set_global_alpha(1.0);
+        let _ = context.set_global_composite_operation("source-over");
+        context.clear_rect(0.0, 0.0, canvas.width() as f64, canvas.height() as f64);
+        Ok((canvas, context))
+    }
+
+    /// Blur the top left `width` by `height` pixels of a layer, and draw
+    /// them onto the canvas with no transform.
+    fn draw_blurred(
+        &mut self,
+        canvas: &PooledSurface<HtmlCanvasElement>,
+        context: &CanvasRenderingContext2d,
+        (width, height): (u32, u32),
+        std_dev: f64,
+    ) -> Result<(), Error> {
+        // Canvas pixels have separate alpha, and blurring needs it
+        // premultiplied.
+        let (w, h) = (width as usize, height as usize);
+        let mut buf = context
+            .get_image_data(0.0, 0.0, width as f64, height as f64)
+            .wrap()?
+            .data()
+            .0;
+        for px in buf.chunks_exact_mut(4) {
+            let a = px[3];
+            for c in &mut px[..3] {
+                *c = premul(*c, a);
+            }
+        }
+        piet::blur_premul(&mut buf, w, h, w * 4, std_dev);
+        for px in buf.chunks_exact_mut(4) {
+            let a = px[3];
+            for c in &mut px[..3] {
+                *c = unpremul(*c, a);
             }
         }
+        let image_data =
+            ImageData::new_with_u8_clamped_array(Clamped(buf.as_slice()), width).wrap()?;
+        context.put_image_data(&image_data, 0.0, 0.0).wrap()?;
+
+        self.ctx.save();
+        let _ = self.ctx.set_transform(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        let result = self
+            .ctx
+            .draw_image_with_html_canvas_element(canvas.surface(), 0.0, 0.0)
+            .wrap();
+        self.ctx.restore();
+        result
+    }
+}
+
+/// Give `to` the transform of `from`, returning it as a `DOMMatrix`.
+fn copy_transform(
+    from: &CanvasRenderingContext2d,
+    to: &CanvasRenderingContext2d,
+) -> Result<JsValue, Error> {
+    // The transform can include one set before the context was wrapped,
+    // and getTransform() isn't exposed by web-sys.
+    let get_transform = Reflect::get(from, &JsValue::from_str("getTransform")).wrap()?;
+    let matrix = get_transform
+        .dyn_into::<Function>()
+        .wrap()?
+        .call0(from)
+        .wrap()?;
+    let set_transform = Reflect::get(to, &JsValue::from_str("setTransform")).wrap()?;
+    set_transform
+        .dyn_into::<Function>()
+        .wrap()?
+        .call1(to, &matrix)
+        .wrap()?;
+    Ok(matrix)
+}
+
+fn premul(x: u8, a: u8) -> u8 {
+    ((x as u32 * a as u32 + 127) / 255) as u8
+}
+
+fn unpremul(x: u8, a: u8) -> u8 {
+    if a == 0 {
+        0
+    } else {
+        let y = (x as u32 * 255 + (a as u32 / 2)) / (a as u32);
+        y.min(255) as u8
+    }
+}
+
+/// Fill `text` from the left of its baseline at `pos`.
+///
+/// The canvas orders the text itself, so right-to-left runs are filled with
+/// its direction set to match, for the neutrals at their ends to go where
+/// the layout put them. web-sys doesn't expose the direction.
+fn fill_text_run(
+    ctx: &CanvasRenderingContext2d,
+    text: &str,
+    pos: Point,
+    rtl: bool,
+) -> Result<(), JsValue> {
+    if !rtl {
+        return ctx.fill_text(text, pos.x, pos.y);
+    }
+    let direction = JsValue::from_str("direction");
+    Reflect::set(ctx, &direction, &JsValue::from_str("rtl"))?;
+    ctx.set_text_align("left");
+    let result = ctx.fill_text(text, pos.x, pos.y);
+    Reflect::set(ctx, &direction, &JsValue::from_str("inherit"))?;
+    ctx.set_text_align("start");
+    result
+}
+
+fn trace_path(ctx: &CanvasRenderingContext2d, path: impl IntoIterator<Item = PathEl>) {
+    // This shouldn't be necessary, we always leave the context in no-path
+    // state. But just in case, and it should be harmless.
+    ctx.begin_path();
+    for el in path {
+        match el {
+            PathEl::MoveTo(p) => ctx.move_to(p.x, p.y),
+            PathEl::LineTo(p) => ctx.line_to(p.x, p.y),
+            PathEl::QuadTo(p1, p2) => ctx.quadratic_curve_to(p1.x, p1.y, p2.x, p2.y),
+            PathEl::CurveTo(p1, p2, p3) => ctx.bezier_curve_to(p1.x, p1.y, p2.x, p2.y, p3.x, p3.y),
+            PathEl::ClosePath => ctx.close_path(),
+        }
     }
 }
 
 fn byte_to_frac(byte: u32) -> f64 {
     ((byte & 255) as f64) * (1.0 / 255.0)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use piet_test::properties;
+    use proptest::prelude::*;
+
+    /// The color a canvas reads from `format_color`'s string.
+    fn parse_color(color: &str) -> u32 {
+        if let Some(hex) = color.strip_prefix('#') {
+            return u32::from_str_radix(hex, 16).unwrap() << 8 | 0xff;
+        }
+        let channels: Vec<&str> = color["rgba(".len()..color.len() - 1].split(',').collect();
+        let rgb = channels[..3]
+            .iter()
+            .fold(0, |rgb, c| rgb << 8 | c.parse::<u32>().unwrap());
+        let a = channels[3].parse::<f64>().unwrap();
+        rgb << 8 | (a * 255.0).round() as u32
+    }
+
+    #[test]
+    fn format_color_round_trip() {
+        for a in 0..=255u32 {
+            let rgba = (a * 97 % 256) << 24 | (255 - a) << 16 | a << 8 | a;
+            assert_eq!(parse_color(&format_color(rgba)), rgba, "{:08x}", rgba);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn generated_color_round_trip(color in properties::color()) {
+            let rgba = color.as_rgba_u32();
+            prop_assert_eq!(parse_color(&format_color(rgba)), rgba, "{:08x}", rgba);
+        }
+    }
+
+    #[test]
+    fn premul_rounds_to_nearest() {
+        properties::check_premul_rounds_to_nearest(premul);
+    }
+
+    #[test]
+    fn unpremul_inverts_premul() {
+        for a in 1..=255u8 {
+            // every premultiplied value, which is at most the alpha
+            for x in 0..=a {
+                assert_eq!(premul(unpremul(x, a), a), x, "{} / {}", x, a);
+            }
+        }
+        for x in 0..=255u8 {
+            assert_eq!(unpremul(premul(x, 255), 255), x);
+            assert_eq!(unpremul(x, 0), 0);
+        }
+    }
+}
@@ -1,68 +1,204 @@
 //! Text functionality for Piet web backend
 
-mod grapheme;
+use std::cell::{OnceCell, RefCell};
+use std::ops::Range;
+use std::rc::Rc;
 
-use std::borrow::Cow;
+use web_sys::{CanvasRenderingContext2d, FontFace, FontFaceDescriptors, Window};
 
-use web_sys::CanvasRenderingContext2d;
-
-use piet::kurbo::Point;
+use piet::kurbo::{Affine, Point, Rect};
 
 use piet::{
-    Error, Font, FontBuilder, HitTestMetrics, HitTestPoint, HitTestTextPosition, Text, TextLayout,
-    TextLayoutBuilder,
+    new_error, ClusterMetric, Color, DecorationMetrics, EmojiBitmap, EmojiStrategy, Error,
+    ErrorKind, Font, FontBuilder, FontFileInfo, FontStretch, FontStyle, FontSubstitutions,
+    FontVariation, FontWarning, FontWeight, GenericFamily, HitTestPoint, HitTestTextPosition,
+    LayoutModel, LayoutRun, LineMetric, Text, TextAlignment, TextAttribute, TextLayout,
+    TextLayoutBuilder, TextTruncation, TruncatedText, VisualCluster, WritingMode,
 };
 use unicode_segmentation::UnicodeSegmentation;
 
-use self::grapheme::point_x_in_grapheme;
-use crate::WebRenderContext;
+use crate::WrapError;
+
+/// Emoji bitmaps with their bounds relative to the origin.
+type EmojiBitmaps<'a> = Vec<(Rect, &'a EmojiBitmap)>;
+
+/// Runs of text with their offsets from the origin, their color from the
+/// layout, or `None` for the brush, their font, and whether they're
+/// right-to-left.
+type TextRuns<'a> = Vec<(&'a str, Point, Option<&'a Color>, &'a WebFont, bool)>;
+
+/// The text factory of a [`WebRenderContext`]. Clones are handles to the
+/// same state.
+///
+/// [`WebRenderContext`]: struct.WebRenderContext.html
+#[derive(Clone)]
+pub struct WebText {
+    /// Where text is measured, for layout.
+    ctx: CanvasRenderingContext2d,
+    window: Window,
+    settings: Rc<RefCell<TextSettings>>,
+}
+
+/// The state shared by the handles to a text factory.
+struct TextSettings {
+    substitutions: FontSubstitutions,
+    emoji: EmojiStrategy,
+    /// The families added to the document with `load_font`.
+    loaded_families: Vec<String>,
+    system_fonts: bool,
+}
 
 #[derive(Clone)]
 pub struct WebFont {
     family: String,
     weight: u32,
     style: FontStyle,
+    stretch: FontStretch,
     size: f64,
+    /// Listed after `family` in the font string, so the browser uses it if
+    /// `family` is missing.
+    substitute: Option<String>,
+    /// Listed after that, for the browser to draw what the families before
+    /// have no glyphs for.
+    fallbacks: Vec<String>,
+    /// Whether a generic family is listed last, for the browser to draw the
+    /// rest from the system's fonts.
+    system_fonts: bool,
+    warnings: Vec<FontWarning>,
 }
 
-pub struct WebFontBuilder(WebFont);
+pub struct WebFontBuilder {
+    ctx: CanvasRenderingContext2d,
+    font: WebFont,
+    /// The families loaded from memory, which are available once the
+    /// browser has parsed them.
+    loaded: Vec<String>,
+}
 
+/// A laid out text.
+///
+/// Right-to-left text is reordered with piet's partial bidirectional
+/// algorithm: only the implicit rules of [UAX #9] are applied, so explicit
+/// embeddings, overrides and isolates are ignored, and characters are
+/// classed by the blocks of their scripts rather than from the full Unicode
+/// data. Text mixing directions inside an embedding may come out in the
+/// wrong order.
+///
+/// [UAX #9]: https://www.unicode.org/reports/tr9/
 pub struct WebTextLayout {
     ctx: CanvasRenderingContext2d,
     // TODO like cairo, should this be pub(crate)?
     pub font: WebFont,
+    /// The displayed text.
     pub text: String,
+    /// The text's lines, its attributes, and where its clusters are.
+    pub(crate) model: LayoutModel,
+    /// Each grapheme cluster with its offset from the origin, found when
+    /// first needed.
+    clusters: OnceCell<Vec<(Range<usize>, Option<Point>)>>,
+    /// The displayed text split where its font or underline changes, in
+    /// order and covering all of it.
+    runs: Vec<StyleRun>,
+    emoji: EmojiStrategy,
+}
+
+/// A range of the displayed text, starting and ending at grapheme
+/// boundaries, drawn in one style.
+struct StyleRun {
+    range: Range<usize>,
+    style: RunStyle,
+    font: WebFont,
+}
+
+/// The layout's font, changed by the attributes over a run.
+#[derive(Clone, Copy, PartialEq)]
+struct RunStyle {
+    size: f64,
+    weight: u32,
+    italic: bool,
+    underline: bool,
+    strikethrough: bool,
+    letter_spacing: f64,
+    word_spacing: f64,
+}
+
+impl RunStyle {
+    fn is_spaced(&self) -> bool {
+        self.letter_spacing != 0.0 || self.word_spacing != 0.0
+    }
+
+    /// The space added after `grapheme`.
+    fn spacing(&self, grapheme: &str) -> f64 {
+        if piet::is_word_separator(grapheme) {
+            self.letter_spacing + self.word_spacing
+        } else {
+            self.letter_spacing
+        }
+    }
+
+    /// The space added across `text`, which starts and ends at grapheme
+    /// boundaries.
+    fn text_spacing(&self, text: &str) -> f64 {
+        if !self.is_spaced() {
+            return 0.0;
+        }
+        text.graphemes(true).map(|g| self.spacing(g)).sum()
+    }
 }
 
 pub struct WebTextLayoutBuilder {
     ctx: CanvasRenderingContext2d,
     font: WebFont,
     text: String,
+    mask: Option<char>,
+    attributes: Vec<(Range<usize>, TextAttribute)>,
+    default_color: Option<Color>,
+    emoji: EmojiStrategy,
+    max_width: f64,
+    alignment: TextAlignment,
+    max_lines: Option<(usize, TextTruncation)>,
 }
 
-/// https://developer.mozilla.org/en-US/docs/Web/CSS/font-style
-#[allow(dead_code)] // TODO: Remove
-#[derive(Clone)]
-enum FontStyle {
-    Normal,
-    Italic,
-    Oblique(Option<f64>),
+impl WebText {
+    pub(crate) fn new(ctx: CanvasRenderingContext2d, window: Window) -> WebText {
+        let settings = TextSettings {
+            substitutions: FontSubstitutions::new(),
+            emoji: EmojiStrategy::Platform,
+            loaded_families: Vec::new(),
+            system_fonts: true,
+        };
+        WebText {
+            ctx,
+            window,
+            settings: Rc::new(RefCell::new(settings)),
+        }
+    }
 }
 
-impl<'a> Text for WebRenderContext<'a> {
+impl Text for WebText {
     type Font = WebFont;
     type FontBuilder = WebFontBuilder;
     type TextLayout = WebTextLayout;
     type TextLayoutBuilder = WebTextLayoutBuilder;
 
     fn new_font_by_name(&mut self, name: &str, size: f64) -> Self::FontBuilder {
+        let settings = self.settings.borrow();
         let font = WebFont {
             family: name.to_owned(),
             size,
             weight: 400,
             style: FontStyle::Normal,
+            stretch: FontStretch::Normal,
+            substitute: settings.substitutions.get(name).map(str::to_owned),
+            fallbacks: Vec::new(),
+            system_fonts: settings.system_fonts,
+            warnings: Vec::new(),
         };
-        WebFontBuilder(font)
+        WebFontBuilder {
+            ctx: self.ctx.clone(),
+            font,
+            loaded: settings.loaded_families.clone(),
+        }
     }
 
     fn new_text_layout(&mut self, font: &Self::Font, text: &str) -> Self::TextLayoutBuilder {
@@ -72,32 +208,191 @@ impl<'a> Text for WebRenderContext<'a> {
             ctx: self.ctx.clone(),
             font: font.clone(),
             text: text.to_owned(),
+            mask: None,
+            attributes: Vec::new(),
+            default_color: None,
+            emoji: self.settings.borrow().emoji.clone(),
+            max_width: f64::INFINITY,
+            alignment: TextAlignment::Start,
+            max_lines: None,
         }
     }
+
+    fn set_font_substitutions(&mut self, substitutions: FontSubstitutions) {
+        self.settings.borrow_mut().substitutions = substitutions;
+    }
+
+    fn set_emoji_strategy(&mut self, strategy: EmojiStrategy) {
+        self.settings.borrow_mut().emoji = strategy;
+    }
+
+    fn load_font(&mut self, data: &[u8]) -> Result<String, Error> {
+        let info = FontFileInfo::read(data).ok_or_else(|| new_error(ErrorKind::InvalidInput))?;
+        let descriptors = FontFaceDescriptors::new();
+        descriptors.set_weight(&info.weight.to_raw().to_string());
+        descriptors.set_style(info.style.name());
+        // The browser parses the data in the background; until it's done,
+        // text in the family is drawn with the fallbacks.
+        let face =
+            FontFace::new_with_u8_array_and_descriptors(&info.family, data, &descriptors).wrap()?;
+        let document = self.window.document().unwrap();
+        document.fonts().add(&face).wrap()?;
+        let loaded = &mut self.settings.borrow_mut().loaded_families;
+        if !loaded.contains(&info.family) {
+            loaded.push(info.family.clone());
+        }
+        Ok(info.family)
+    }
+
+    fn set_system_fonts(&mut self, enabled: bool) {
+        self.settings.borrow_mut().system_fonts = enabled;
+    }
+
+    fn prewarm(&mut self, font: &Self::Font, text: &str) {
+        // Measuring makes the browser load the face and shape the text; it
+        // rasterizes on its own schedule.
+        let saved = self.ctx.font();
+        self.ctx.set_font(&font.get_font_string());
+        let _ = self.ctx.measure_text(text);
+        self.ctx.set_font(&saved);
+    }
 }
 
 impl FontBuilder for WebFontBuilder {
     type Out = WebFont;
 
+    fn weight(mut self, weight: FontWeight) -> Self {
+        self.font.weight = weight.to_raw().into();
+        self
+    }
+
+    fn style(mut self, style: FontStyle) -> Self {
+        self.font.style = style;
+        self
+    }
+
+    fn stretch(mut self, stretch: FontStretch) -> Self {
+        self.font.stretch = stretch;
+        self
+    }
+
+    fn fallback(mut self, family: &str) -> Self {
+        self.font.fallbacks.push(family.to_owned());
+        self
+    }
+
+    fn variation(self, _variation: FontVariation) -> Self {
+        // canvas has no font-variation-settings
+        self
+    }
+
     fn build(self) -> Result<Self::Out, Error> {
-        Ok(self.0)
+        let mut font = self.font;
+        let loaded = self.loaded;
+        if !font.system_fonts {
+            let first = loaded
+                .first()
+                .ok_or_else(|| new_error(ErrorKind::NotSupported))?;
+            if !loaded.contains(&font.family) {
+                font.warnings.push(FontWarning::MissingFamily {
+                    requested: std::mem::replace(&mut font.family, first.clone()),
+                    fallback: first.clone(),
+                });
+            }
+            font.substitute = font.substitute.filter(|family| loaded.contains(family));
+            font.fallbacks.retain(|family| loaded.contains(family));
+            return Ok(font);
+        }
+        if GenericFamily::from_name(&font.family).is_none()
+            && !loaded.contains(&font.family)
+            && !family_available(&self.ctx, &font, &font.family)
+        {
+            let warning = match font.substitute {
+                Some(ref substitute) if family_available(&self.ctx, &font, substitute) => {
+                    FontWarning::Substituted {
+                        requested: font.family.clone(),
+                        substitute: substitute.clone(),
+                    }
+                }
+                _ => FontWarning::MissingFamily {
+                    requested: font.family.clone(),
+                    fallback: FALLBACK_FAMILY.to_owned(),
+                },
+            };
+            font.warnings.push(warning);
+        }
+        Ok(font)
+    }
+}
+
+/// A family name as it's written in a font string: generics must be unquoted
+/// to be recognized as such, and other names are quoted.
+fn css_family(family: &str) -> String {
+    match GenericFamily::from_name(family) {
+        Some(generic) => generic.name().to_owned(),
+        None => format!("\"{}\"", family),
     }
 }
 
-impl Font for WebFont {}
+/// The generic family listed last in the font string.
+const FALLBACK_FAMILY: &str = "sans-serif";
+const PROBE_TEXT: &str = "AaBbGgIiMmQqWw0123456789";
+
+/// Canvas has no API for asking whether a family exists, so we measure
+/// against two generic families with different metrics: if the text width
+/// matches both generics when they are listed as fallbacks, the browser must
+/// have used them. Web fonts that haven't finished loading count as missing.
+fn family_available(ctx: &CanvasRenderingContext2d, font: &WebFont, family: &str) -> bool {
+    let saved = ctx.font();
+    let measure = |family: &str| {
+        ctx.set_font(&font.font_string_with_families(family));
+        ctx.measure_text(PROBE_TEXT).map(|m| m.width()).ok()
+    };
+    let available = ["monospace", "serif"].iter().any(|generic| {
+        let requested = format!("\"{}\", {}", family, generic);
+        measure(&requested) != measure(generic)
+    });
+    ctx.set_font(&saved);
+    available
+}
+
+impl Font for WebFont {
+    fn warnings(&self) -> &[FontWarning] {
+        &self.warnings
+    }
+
+    fn decoration_metrics(&self) -> DecorationMetrics {
+        // Canvas doesn't expose the font's tables.
+        DecorationMetrics::estimate(self.size)
+    }
+}
 
 impl WebFont {
     // TODO should this be pub(crate)?
     pub fn get_font_string(&self) -> String {
-        let style_str = match self.style {
-            FontStyle::Normal => Cow::from("normal"),
-            FontStyle::Italic => Cow::from("italic"),
-            FontStyle::Oblique(None) => Cow::from("italic"),
-            FontStyle::Oblique(Some(angle)) => Cow::from(format!("oblique {}deg", angle)),
-        };
+        // browsers fall back family by family for each character, so the
+        // fallbacks go after the requested family and before the default
+        let mut families = vec![css_family(&self.family)];
+        let generic = GenericFamily::from_name(&self.family).is_some();
+        if let (false, Some(substitute)) = (generic, &self.substitute) {
+            families.push(css_family(substitute));
+        }
+        families.extend(self.fallbacks.iter().map(|family| css_family(family)));
+        if !generic && self.system_fonts {
+            families.push(FALLBACK_FAMILY.to_owned());
+        }
+        self.font_string_with_families(&families.join(", "))
+    }
+
+    fn font_string_with_families(&self, families: &str) -> String {
+        // the shorthand only takes the keywords for widths
         format!(
-            "{} {} {}px \"{}\"",
-            style_str, self.weight, self.size, self.family
+            "{} {} {} {}px {}",
+            self.style.name(),
+            self.weight,
+            self.stretch.name(),
+            self.size,
+            families
         )
     }
 }
@@ -105,147 +400,514 @@ impl WebFont {
 impl TextLayoutBuilder for WebTextLayoutBuilder {
     type Out = WebTextLayout;
 
+    fn obscure(mut self, mask: char) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    fn range_attribute(mut self, range: Range<usize>, attribute: TextAttribute) -> Self {
+        let end = range.end.min(self.text.len());
+        if range.start < end {
+            self.attributes.push((range.start..end, attribute));
+        }
+        self
+    }
+
+    fn default_color(mut self, color: Color) -> Self {
+        self.default_color = Some(color);
+        self
+    }
+
+    fn transform(self, _transform: Affine) -> Self {
+        // canvas measures text unhinted, so it scales exactly
+        self
+    }
+
+    fn max_width(mut self, width: f64) -> Self {
+        self.max_width = width;
+        self
+    }
+
+    fn alignment(mut self, alignment: TextAlignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    fn max_lines(mut self, lines: usize, truncation: TextTruncation) -> Self {
+        self.max_lines = Some((lines, truncation));
+        self
+    }
+
+    fn writing_mode(self, _mode: WritingMode) -> Self {
+        // canvas can only draw text horizontally
+        self
+    }
+
     fn build(self) -> Result<Self::Out, Error> {
-        self.ctx.set_font(&self.font.get_font_string());
-        Ok(WebTextLayout {
+        let mut layout = WebTextLayout {
             ctx: self.ctx,
             font: self.font,
-            text: self.text,
-        })
+            text: String::new(),
+            model: LayoutModel::new(self.text, self.mask, self.attributes, self.default_color),
+            clusters: OnceCell::new(),
+            runs: Vec::new(),
+            emoji: self.emoji,
+        };
+        layout.runs = layout.style_runs();
+        let (ctx, runs) = (&layout.ctx, &layout.runs);
+        layout.model.wrap(self.max_width, |text, range| {
+            advance(ctx, runs, text, range)
+        });
+        if let Some((max_lines, truncation)) = self.max_lines {
+            layout.ctx.set_font(&layout.font.get_font_string());
+            let ellipsis_width = layout
+                .ctx
+                .measure_text(TruncatedText::ELLIPSIS)
+                .map(|m| m.width())
+                .expect("Text measurement failed");
+            let (ctx, runs) = (&layout.ctx, &layout.runs);
+            if layout.model.truncate(
+                max_lines,
+                truncation,
+                self.max_width,
+                ellipsis_width,
+                |text, range| advance(ctx, runs, text, range),
+            ) {
+                layout.runs = layout.style_runs();
+            }
+        }
+        let measured = layout
+            .runs
+            .iter()
+            .map(|run| {
+                layout.ctx.set_font(&run.font.get_font_string());
+                let (ascent, descent) = font_extents(&layout.ctx).unwrap_or((run.font.size, 0.0));
+                LayoutRun {
+                    range: run.range.clone(),
+                    ascent,
+                    descent,
+                    height: ascent + descent,
+                    decorations: run.font.decoration_metrics(),
+                    underline: run.style.underline,
+                    strikethrough: run.style.strikethrough,
+                }
+            })
+            .collect();
+        let (ctx, runs) = (&layout.ctx, &layout.runs);
+        layout
+            .model
+            .place(self.alignment, self.max_width, measured, |text, range| {
+                advance(ctx, runs, text, range)
+            });
+        layout.text = layout.model.text().to_owned();
+        Ok(layout)
     }
 }
 
-impl TextLayout for WebTextLayout {
-    fn width(&self) -> f64 {
-        //cairo:
-        //self.font.text_extents(&self.text).x_advance
-        self.ctx
-            .measure_text(&self.text)
-            .map(|m| m.width())
-            .expect("Text measurement failed")
+impl WebTextLayout {
+    /// The grapheme clusters of the displayed text, each with its offset
+    /// from the origin, or `None` for line breaks and the spaces hanging at
+    /// wraps, which aren't drawn.
+    pub(crate) fn clusters(&self) -> &[(Range<usize>, Option<Point>)] {
+        self.clusters.get_or_init(|| {
+            let model = &self.model;
+            // the left edge of each cluster of the reordered lines
+            let reordered: Vec<Option<Vec<VisualCluster>>> = (0..model.lines().len())
+                .map(|line| model.visual_clusters(line))
+                .collect();
+            self.text
+                .grapheme_indices(true)
+                .map(|(i, grapheme)| {
+                    let line = model.line_of(i);
+                    let baseline = model.baseline(line);
+                    let offset = if i >= model.lines()[line].end {
+                        None
+                    } else if let Some(clusters) = &reordered[line] {
+                        let cluster = clusters.iter().find(|c| c.range.start == i);
+                        cluster.map(|c| Point::new(c.x0, baseline))
+                    } else {
+                        Some(Point::new(model.line_x(line, i), baseline))
+                    };
+                    (i..i + grapheme.len(), offset)
+                })
+                .collect()
+        })
     }
 
-    // first assume one line.
-    // TODO do with lines
-    fn hit_test_point(&self, point: Point) -> HitTestPoint {
-        // internal logic is using grapheme clusters, but return the text position associated
-        // with the border of the grapheme cluster.
-
-        // null case
-        if self.text.is_empty() {
-            return HitTestPoint::default();
-        }
-
-        // get bounds
-        // TODO handle if string is not null yet count is 0?
-        let end = UnicodeSegmentation::graphemes(self.text.as_str(), true).count() - 1;
-        let end_bounds = match self.get_grapheme_boundaries(end) {
-            Some(bounds) => bounds,
-            None => return HitTestPoint::default(),
-        };
+    /// The text of each line, split where the font or the direction
+    /// changes, with the origin of each piece's baseline at its left, its
+    /// font, and whether it's right-to-left.
+    pub(crate) fn lines(&self) -> impl Iterator<Item = (&str, Point, &WebFont, bool)> + '_ {
+        (0..self.model.lines().len())
+            .flat_map(move |line| line_pieces(&self.model, &self.runs, line))
+    }
 
-        let start = 0;
-        let start_bounds = match self.get_grapheme_boundaries(start) {
-            Some(bounds) => bounds,
-            None => return HitTestPoint::default(),
+    /// Split the displayed text into runs of one style, at the ends of the
+    /// attributes that change the font or underline it, moved back to the
+    /// start of the grapheme cluster they're in.
+    fn style_runs(&self) -> Vec<StyleRun> {
+        let base = RunStyle {
+            size: self.font.size,
+            weight: self.font.weight,
+            italic: !matches!(self.font.style, FontStyle::Normal),
+            underline: false,
+            strikethrough: false,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
         };
-
-        // first test beyond ends
-        if point.x > end_bounds.trailing {
-            let mut res = HitTestPoint::default();
-            res.metrics.text_position = self.text.len();
-            return res;
+        let mut runs: Vec<StyleRun> = Vec::new();
+        for range in self.model.style_ranges() {
+            let style = self.style_at(base, range.start);
+            match runs.last_mut() {
+                Some(last) if last.style == style => last.range.end = range.end,
+                _ => runs.push(StyleRun {
+                    range,
+                    style,
+                    font: self.run_font(base, style),
+                }),
+            }
         }
-        if point.x <= start_bounds.leading {
-            return HitTestPoint::default();
+        if runs.is_empty() {
+            runs.push(StyleRun {
+                range: 0..0,
+                style: base,
+                font: self.font.clone(),
+            });
         }
+        runs
+    }
 
-        // then test the beginning and end (common cases)
-        if let Some(hit) = point_x_in_grapheme(point.x, &start_bounds) {
-            return hit;
+    /// The style at `position` in the displayed text.
+    fn style_at(&self, base: RunStyle, position: usize) -> RunStyle {
+        let mut style = base;
+        for attribute in self.model.attributes_at(position) {
+            match *attribute {
+                TextAttribute::Size(size) if size > 0.0 && size.is_finite() => style.size = size,
+                TextAttribute::Weight(weight) => style.weight = weight.to_raw().into(),
+                TextAttribute::Italic(italic) => style.italic = italic,
+                TextAttribute::Underline(underline) => style.underline = underline,
+                TextAttribute::Strikethrough(strikethrough) => style.strikethrough = strikethrough,
+                TextAttribute::LetterSpacing(spacing) if spacing.is_finite() => {
+                    style.letter_spacing = spacing
+                }
+                TextAttribute::WordSpacing(spacing) if spacing.is_finite() => {
+                    style.word_spacing = spacing
+                }
+                _ => (),
+            }
         }
-        if let Some(hit) = point_x_in_grapheme(point.x, &end_bounds) {
-            return hit;
+        style
+    }
+
+    /// The layout's font changed to `style`.
+    fn run_font(&self, base: RunStyle, style: RunStyle) -> WebFont {
+        let mut font = self.font.clone();
+        font.size = style.size;
+        font.weight = style.weight;
+        if style.italic != base.italic {
+            font.style = if style.italic {
+                FontStyle::Italic
+            } else {
+                FontStyle::Normal
+            };
         }
+        font
+    }
 
-        // Now that we know it's not beginning or end, begin binary search.
-        // Iterative style
-        let mut left = start;
-        let mut right = end;
-        loop {
-            // pick halfway point
-            let middle = left + ((right - left) / 2);
+    /// The run `position` in the displayed text is in.
+    fn run_of(&self, position: usize) -> &StyleRun {
+        let run = self
+            .runs
+            .partition_point(|run| run.range.start <= position)
+            .saturating_sub(1);
+        &self.runs[run]
+    }
 
-            let grapheme_bounds = match self.get_grapheme_boundaries(middle) {
-                Some(bounds) => bounds,
-                None => return HitTestPoint::default(),
+    /// Split the text into emoji drawn as bitmaps, with their bounds
+    /// relative to the origin, and runs of the rest of it.
+    ///
+    /// Returns `None` when the text can be drawn a line at a time, with
+    /// neither bitmaps, colors from the layout, nor spacing. Spaced and
+    /// justified text is drawn a cluster at a time, as canvas can't space it.
+    pub(crate) fn split_runs(&self) -> Option<(EmojiBitmaps<'_>, TextRuns<'_>)> {
+        let model = &self.model;
+        let found = self.emoji.find(&self.text);
+        let justified = model.is_justified();
+        let spaced = justified || self.runs.iter().any(|run| run.style.is_spaced());
+        if found.is_empty() && !model.has_colors() && !spaced {
+            return None;
+        }
+        let colors = model.cluster_colors();
+        let mut runs: Vec<(Range<usize>, Point, Option<&Color>, &WebFont)> = Vec::new();
+        for ((range, offset), &color) in self.clusters().iter().zip(&colors) {
+            let offset = match offset {
+                Some(offset) => *offset,
+                None => continue,
             };
-
-            if let Some(hit) = point_x_in_grapheme(point.x, &grapheme_bounds) {
-                return hit;
+            if found.iter().any(|(emoji, _)| emoji.contains(&range.start)) {
+                continue;
             }
+            let style_run = self.run_of(range.start);
+            let font = &style_run.font;
+            let rtl = model.is_rtl_at(range.start);
+            match runs.last_mut() {
+                Some((run, start, last, last_font))
+                    if !justified
+                        && !style_run.style.is_spaced()
+                        && !self.run_of(run.start).style.is_spaced()
+                        && run.end == range.start
+                        && start.y == offset.y
+                        && same_color(*last, color)
+                        && std::ptr::eq(*last_font, font)
+                        && model.is_rtl_at(run.start) == rtl =>
+                {
+                    run.end = range.end;
+                    // a right-to-left run grows leftwards
+                    start.x = start.x.min(offset.x);
+                }
+                _ => runs.push((range.clone(), offset, color, font)),
+            }
+        }
+        let runs = runs
+            .into_iter()
+            .map(|(range, offset, color, font)| {
+                let rtl = model.is_rtl_at(range.start);
+                (&self.text[range], offset, color, font, rtl)
+            })
+            .collect();
+        let bitmaps = found
+            .into_iter()
+            .filter_map(|(range, bitmap)| Some((model.display_range_bounds(range)?, bitmap)))
+            .collect();
+        Some((bitmaps, runs))
+    }
 
-            // since it's not a hit, check if closer to start or finish
-            // and move the appropriate search boundary
-            if point.x < grapheme_bounds.leading {
-                right = middle;
-            } else if point.x > grapheme_bounds.trailing {
-                left = middle + 1;
+    /// The font of each grapheme cluster of the displayed text.
+    pub(crate) fn cluster_fonts(&self) -> Vec<&WebFont> {
+        self.text
+            .grapheme_indices(true)
+            .map(|(i, _)| &self.run_of(i).font)
+            .collect()
+    }
+}
+
+/// The text of `line` split where the font of `runs` or the direction
+/// changes, in visual order, with the origin of each piece's baseline at its
+/// left, its font, and whether it's right-to-left.
+fn line_pieces<'a>(
+    model: &'a LayoutModel,
+    runs: &'a [StyleRun],
+    line: usize,
+) -> Vec<(&'a str, Point, &'a WebFont, bool)> {
+    let text = model.text();
+    let pieces = |range: Range<usize>| {
+        runs.iter().filter_map(move |run| {
+            let start = range.start.max(run.range.start);
+            let end = range.end.min(run.range.end);
+            if start < end {
+                Some((start..end, &run.font))
             } else {
-                unreachable!("hit_test_point conditional is exhaustive");
+                None
+            }
+        })
+    };
+    let baseline = model.baseline(line);
+    match model.visual_clusters(line) {
+        Some(clusters) => {
+            let mut line_pieces = Vec::new();
+            for (run, rtl) in model.visual_runs(line) {
+                let mut run_pieces: Vec<_> = pieces(run).collect();
+                if rtl {
+                    run_pieces.reverse();
+                }
+                for (piece, font) in run_pieces {
+                    let left = clusters
+                        .iter()
+                        .filter(|c| piece.contains(&c.range.start))
+                        .map(|c| c.x0)
+                        .fold(f64::INFINITY, f64::min);
+                    let origin = Point::new(left, baseline);
+                    line_pieces.push((&text[piece], origin, font, rtl));
+                }
             }
+            line_pieces
         }
+        None => pieces(model.lines()[line].clone())
+            .map(|(piece, font)| {
+                let origin = Point::new(model.line_x(line, piece.start), baseline);
+                (&text[piece], origin, font, false)
+            })
+            .collect(),
+    }
+}
+
+/// The advance of `range` of `text`, measured run by run with `ctx`, with
+/// its spacing.
+fn advance(
+    ctx: &CanvasRenderingContext2d,
+    runs: &[StyleRun],
+    text: &str,
+    range: Range<usize>,
+) -> f64 {
+    runs.iter()
+        .filter_map(|run| {
+            let start = range.start.max(run.range.start);
+            let end = range.end.min(run.range.end);
+            if start < end {
+                let text = &text[start..end];
+                ctx.set_font(&run.font.get_font_string());
+                let measured = ctx.measure_text(text);
+                Some(
+                    measured
+                        .map(|m| m.width())
+                        .expect("Text measurement failed")
+                        + run.style.text_spacing(text),
+                )
+            } else {
+                None
+            }
+        })
+        .sum()
+}
+
+fn same_color(a: Option<&Color>, b: Option<&Color>) -> bool {
+    a.map(Color::as_rgba_u32) == b.map(Color::as_rgba_u32)
+}
+
+/// The ascent and descent of the bounding box of the font set on `ctx`.
+fn font_extents(ctx: &CanvasRenderingContext2d) -> Option<(f64, f64)> {
+    let metrics = ctx.measure_text("").ok()?;
+    Some((
+        metrics.font_bounding_box_ascent(),
+        metrics.font_bounding_box_descent(),
+    ))
+}
+
+impl TextLayout for WebTextLayout {
+    fn width(&self) -> f64 {
+        self.model.width()
+    }
+
+    fn hit_test_point(&self, point: Point) -> HitTestPoint {
+        self.model.hit_test_point(point)
     }
 
     fn hit_test_text_position(&self, text_position: usize) -> Option<HitTestTextPosition> {
-        // Using substrings, but now with unicode grapheme awareness
+        self.model.hit_test_text_position(text_position)
+    }
 
-        let text_len = self.text.len();
+    fn range_bounds(&self, range: Range<usize>) -> Option<Rect> {
+        self.model.range_bounds(range)
+    }
 
-        if text_position == 0 {
-            return Some(HitTestTextPosition::default());
-        }
+    fn rects_for_range(&self, range: Range<usize>) -> Vec<Rect> {
+        self.model.rects_for_range(range)
+    }
 
-        if text_position as usize >= text_len {
-            let x = self.width();
+    fn caret_bounds(&self, text_position: usize) -> Option<Rect> {
+        self.model.caret_bounds(text_position)
+    }
 
-            return Some(HitTestTextPosition {
-                point: Point { x, y: 0.0 },
-                metrics: HitTestMetrics {
-                    text_position: text_len,
-                },
-            });
-        }
+    fn truncated_at(&self) -> Option<usize> {
+        self.model.truncated_at()
+    }
 
-        // Already checked that text_position > 0 and text_position < count.
-        // If text position is not at a grapheme boundary, use the text position of current
-        // grapheme cluster. But return the original text position
-        // Use the indices (byte offset, which for our purposes = utf8 code units).
-        let grapheme_indices = UnicodeSegmentation::grapheme_indices(self.text.as_str(), true)
-            .take_while(|(byte_idx, _s)| text_position >= *byte_idx);
+    fn line_metrics(&self) -> Vec<LineMetric> {
+        self.model.line_metrics()
+    }
 
-        if let Some((byte_idx, _s)) = grapheme_indices.last() {
-            let x = self
-                .ctx
-                .measure_text(&self.text[0..byte_idx])
-                .map(|m| m.width())
-                .expect("Text measurement failed");
+    fn cluster_metrics(&self) -> Vec<ClusterMetric> {
+        self.model.cluster_metrics()
+    }
 
-            Some(HitTestTextPosition {
-                point: Point { x, y: 0.0 },
-                metrics: HitTestMetrics { text_position },
-            })
-        } else {
-            // iterated to end boundary
-            Some(HitTestTextPosition {
-                point: Point {
-                    x: self.width(),
-                    y: 0.0,
-                },
-                metrics: HitTestMetrics {
-                    text_position: text_len,
-                },
-            })
+    fn decoration_metrics(&self) -> DecorationMetrics {
+        self.font.decoration_metrics()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use piet::{FontStretch, LayoutRun};
+
+    fn font(size: f64) -> WebFont {
+        WebFont {
+            family: "sans-serif".to_owned(),
+            weight: 400,
+            style: FontStyle::Normal,
+            stretch: FontStretch::Normal,
+            size,
+            substitute: None,
+            fallbacks: Vec::new(),
+            system_fonts: true,
+            warnings: Vec::new(),
         }
     }
+
+    fn style_run(range: Range<usize>, size: f64) -> StyleRun {
+        let style = RunStyle {
+            size,
+            weight: 400,
+            italic: false,
+            underline: false,
+            strikethrough: false,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+        };
+        StyleRun {
+            range,
+            style,
+            font: font(size),
+        }
+    }
+
+    #[test]
+    fn bidi_pieces_in_visual_order() {
+        // every character is 1 wide
+        let measure = |text: &str, range: Range<usize>| text[range].chars().count() as f64;
+        // alef, bet and gimel between two latin words, with a larger font
+        // from bet on
+        let input = "abc \u{5d0}\u{5d1}\u{5d2} def";
+        let mut model = LayoutModel::new(input.to_owned(), None, Vec::new(), None);
+        let runs = [style_run(0..6, 10.0), style_run(6..14, 12.0)];
+        let layout_runs = runs
+            .iter()
+            .map(|run| LayoutRun {
+                range: run.range.clone(),
+                ascent: run.style.size,
+                height: run.style.size,
+                ..Default::default()
+            })
+            .collect();
+        model.place(TextAlignment::Start, f64::INFINITY, layout_runs, measure);
+
+        let pieces: Vec<_> = line_pieces(&model, &runs, 0)
+            .into_iter()
+            .map(|(text, origin, font, rtl)| (text, origin.x, font.size, rtl))
+            .collect();
+        assert_eq!(
+            pieces,
+            [
+                ("abc ", 0.0, 10.0, false),
+                // the right-to-left run's pieces are drawn from its end
+                ("\u{5d1}\u{5d2}", 4.0, 12.0, true),
+                ("\u{5d0}", 6.0, 10.0, true),
+                (" def", 7.0, 12.0, false),
+            ]
+        );
+
+        // hit testing follows the same order
+        let hit = |x| {
+            model
+                .hit_test_point(Point::new(x, 0.0))
+                .metrics
+                .text_position
+        };
+        assert_eq!(hit(4.2), 10);
+        assert_eq!(hit(4.8), 8);
+        assert_eq!(hit(6.2), 6);
+        assert_eq!(hit(6.8), 4);
+        assert_eq!(hit(7.2), 10);
+        assert_eq!(hit(8.2), 11);
+    }
 }
@@ -0,0 +1,81 @@
+//! Time the steps from a shape to backend commands that every backend
+//! shares.
+//!
+//! Run with `cargo bench -p piet`. That the allocation-free steps stay that
+//! way is checked by `tests/allocations.rs`.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use piet::kurbo::{Affine, Rect};
+use piet::{
+    align_stroke, shape_elements, user_tolerance, Color, DynShape, FrameArena, GradientColorSpace,
+    GradientStops, StrokeStyle,
+};
+
+const ITERATIONS: u32 = 100_000;
+
+/// Run `f` once to warm up, then time it.
+fn bench(name: &str, mut f: impl FnMut(u32)) {
+    f(0);
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        f(i);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<24} {:>8.1} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / ITERATIONS as f64,
+    );
+}
+
+fn rect(i: u32) -> Rect {
+    let x = (i % 100) as f64;
+    Rect::new(x, 0.0, x + 10.0, 10.0)
+}
+
+fn main() {
+    let mut paths = FrameArena::new();
+    let mut stop_arena = FrameArena::new();
+    let transform = Affine::scale(2.0);
+    let solid = StrokeStyle::new();
+    let dashed = StrokeStyle::dashed(&[4.0, 2.0, 1.0], 0.0);
+    let stops = (Color::rgb8(0xff, 0, 0), Color::rgb8(0, 0, 0xff)).to_vec();
+
+    bench("collect rect", |i| {
+        let tolerance = user_tolerance(1e-3, transform);
+        let path = paths.collect(shape_elements(&rect(i), tolerance));
+        paths.give_back(black_box(path));
+    });
+    bench("collect dyn rect", |i| {
+        let shape = rect(i);
+        let shape: &dyn DynShape = &shape;
+        let path = paths.collect(shape_elements(&shape, 1e-3));
+        paths.give_back(black_box(path));
+    });
+    bench("stream dyn rect", |i| {
+        let shape = rect(i);
+        let shape: &dyn DynShape = &shape;
+        black_box(shape_elements(&shape, 1e-3).count());
+    });
+    bench("centered stroke", |i| {
+        black_box(align_stroke(rect(i), 1.0, &solid));
+    });
+    bench("odd dash lengths", |_| {
+        black_box(dashed.dash_lengths());
+    });
+    bench("odd dash pattern", |_| {
+        black_box(dashed.dash_pattern());
+    });
+    bench("srgb gradient stops", |_| {
+        black_box(GradientColorSpace::Srgb.resolve_stops(&stops));
+    });
+    bench("linear gradient stops", |_| {
+        black_box(GradientColorSpace::LinearRgb.resolve_stops(&stops));
+    });
+    bench("collect linear stops", |_| {
+        let resolved = stop_arena.collect(GradientColorSpace::LinearRgb.resolved_stops(&stops));
+        stop_arena.give_back(black_box(resolved));
+    });
+}
@@ -0,0 +1,535 @@
+//! A partial Unicode bidirectional algorithm, for laying out right-to-left
+//! text.
+
+use std::ops::Range;
+
+/// The bidi embedding levels of a text, and the visual order of its lines.
+///
+/// Levels come from the implicit rules of the Unicode bidirectional
+/// algorithm ([UAX #9]): even levels are left-to-right and odd ones
+/// right-to-left. Each paragraph takes its direction from its first strong
+/// character, and is left-to-right if it has none. Explicit embeddings,
+/// overrides and isolates aren't supported; their control characters are
+/// ignored, like the other formatting characters.
+///
+/// Characters are classed by the blocks of their scripts rather than from
+/// the full Unicode data, so a character outside the right-to-left scripts'
+/// blocks, the digits and the common punctuation is taken to be
+/// left-to-right.
+///
+/// [UAX #9]: https://www.unicode.org/reports/tr9/
+#[derive(Debug, Clone)]
+pub(crate) struct BidiLevels {
+    /// The level of each byte of the text.
+    levels: Vec<u8>,
+    /// The class of each byte's character, for the rules applied per line.
+    classes: Vec<BidiClass>,
+    /// The level of each paragraph, with the byte range it covers.
+    paragraphs: Vec<(Range<usize>, u8)>,
+}
+
+/// A run of a line at one bidi level, from [`BidiLevels::visual_runs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BidiRun {
+    /// The run's range of the text.
+    pub range: Range<usize>,
+    pub level: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BidiClass {
+    L,
+    R,
+    AL,
+    EN,
+    ES,
+    ET,
+    AN,
+    CS,
+    Nsm,
+    BN,
+    B,
+    S,
+    WS,
+    ON,
+}
+
+impl BidiLevels {
+    /// Run the algorithm over `text`.
+    pub fn new(text: &str) -> BidiLevels {
+        let mut levels = vec![0; text.len()];
+        let mut classes = vec![BidiClass::L; text.len()];
+        let mut paragraphs = Vec::new();
+        let mut start = 0;
+        for paragraph in text.split_inclusive(is_paragraph_separator) {
+            let chars: Vec<(usize, BidiClass)> = paragraph
+                .char_indices()
+                .map(|(i, c)| (start + i, bidi_class(c)))
+                .collect();
+            let level = paragraph_level(&chars);
+            resolve_paragraph(&chars, level, &mut levels);
+            for (n, &(i, class)) in chars.iter().enumerate() {
+                let end = chars.get(n + 1).map_or(start + paragraph.len(), |c| c.0);
+                for byte in i..end {
+                    levels[byte] = levels[i];
+                    classes[byte] = class;
+                }
+            }
+            paragraphs.push((start..start + paragraph.len(), level));
+            start += paragraph.len();
+        }
+        BidiLevels {
+            levels,
+            classes,
+            paragraphs,
+        }
+    }
+
+    /// Whether any of the text is right-to-left, so that lines need
+    /// reordering.
+    pub fn has_rtl(&self) -> bool {
+        self.levels.iter().any(|level| level % 2 == 1)
+    }
+
+    /// The level of the character at `position`, a byte offset.
+    ///
+    /// Returns the level of the last paragraph at the end of the text.
+    pub fn level_at(&self, position: usize) -> u8 {
+        match self.levels.get(position) {
+            Some(&level) => level,
+            None => self.paragraph_level(position),
+        }
+    }
+
+    /// Whether the character at `position` is right-to-left.
+    pub fn is_rtl_at(&self, position: usize) -> bool {
+        self.level_at(position) % 2 == 1
+    }
+
+    /// The level of the paragraph that `position` is in.
+    pub fn paragraph_level(&self, position: usize) -> u8 {
+        self.paragraphs
+            .iter()
+            .find(|(range, _)| position < range.end)
+            .or_else(|| self.paragraphs.last())
+            .map_or(0, |(_, level)| *level)
+    }
+
+    /// The runs of the line covering `line` of the text, from left to right.
+    ///
+    /// The whitespace at the end of the line and before tabs takes the
+    /// paragraph's level, so it stays at the end the paragraph reads
+    /// towards.
+    pub fn visual_runs(&self, line: Range<usize>) -> Vec<BidiRun> {
+        let line = line.start.min(self.levels.len())..line.end.min(self.levels.len());
+        let mut levels = self.levels[line.clone()].to_vec();
+        let paragraph = self.paragraph_level(line.start);
+        let mut trailing = true;
+        for i in (0..levels.len()).rev() {
+            match self.classes[line.start + i] {
+                BidiClass::S | BidiClass::B => {
+                    levels[i] = paragraph;
+                    trailing = true;
+                }
+                BidiClass::WS | BidiClass::BN if trailing => levels[i] = paragraph,
+                _ => trailing = false,
+            }
+        }
+
+        let mut runs: Vec<BidiRun> = Vec::new();
+        for (i, &level) in levels.iter().enumerate() {
+            match runs.last_mut() {
+                Some(run) if run.level == level => run.range.end = line.start + i + 1,
+                _ => runs.push(BidiRun {
+                    range: line.start + i..line.start + i + 1,
+                    level,
+                }),
+            }
+        }
+
+        let highest = runs.iter().map(|run| run.level).max().unwrap_or(0);
+        let lowest_odd = runs.iter().map(|run| run.level | 1).min().unwrap_or(1);
+        for level in (lowest_odd..=highest).rev() {
+            let mut i = 0;
+            while i < runs.len() {
+                let end = i + runs[i..]
+                    .iter()
+                    .take_while(|run| run.level >= level)
+                    .count();
+                runs[i..end].reverse();
+                i = end + 1;
+            }
+        }
+        runs
+    }
+}
+
+impl BidiRun {
+    /// Whether the run reads right-to-left.
+    pub fn is_rtl(&self) -> bool {
+        self.level % 2 == 1
+    }
+}
+
+/// Whether the first paragraph of `text` reads right to left, for backends
+/// that reorder text themselves but need to be told its direction.
+///
+/// The direction comes from the paragraph's first strong character, as in
+/// [UAX #9], but characters are classed by the blocks of their scripts
+/// rather than from the full Unicode data. A paragraph with no strong
+/// characters reads left to right.
+///
+/// # Examples
+///
+/// ```
+/// use piet::paragraph_is_rtl;
+///
+/// // alef and bet, then "abc"
+/// assert!(paragraph_is_rtl("\u{5d0}\u{5d1} abc"));
+/// assert!(!paragraph_is_rtl("abc \u{5d0}\u{5d1}"));
+/// // only the first paragraph counts
+/// assert!(!paragraph_is_rtl("123\nabc\n\u{5d0}"));
+/// ```
+///
+/// [UAX #9]: https://www.unicode.org/reports/tr9/
+pub fn paragraph_is_rtl(text: &str) -> bool {
+    BidiLevels::new(text).paragraph_level(0) % 2 == 1
+}
+
+fn is_paragraph_separator(c: char) -> bool {
+    bidi_class(c) == BidiClass::B
+}
+
+/// The level of a paragraph: 1 if its first strong character is
+/// right-to-left, or else 0.
+fn paragraph_level(chars: &[(usize, BidiClass)]) -> u8 {
+    chars
+        .iter()
+        .find_map(|(_, class)| match class {
+            BidiClass::L => Some(0),
+            BidiClass::R | BidiClass::AL => Some(1),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+/// Resolve the levels of a paragraph's characters, writing each to the
+/// byte it starts at.
+fn resolve_paragraph(chars: &[(usize, BidiClass)], level: u8, levels: &mut [u8]) {
+    use BidiClass::*;
+
+    let embedding = if level % 2 == 1 { R } else { L };
+    // formatting characters are removed, and take the level before them
+    let kept: Vec<usize> = (0..chars.len()).filter(|&n| chars[n].1 != BN).collect();
+    let mut types: Vec<BidiClass> = kept.iter().map(|&n| chars[n].1).collect();
+
+    // W1: marks take the type before them
+    for i in 0..types.len() {
+        if types[i] == Nsm {
+            types[i] = if i == 0 { embedding } else { types[i - 1] };
+        }
+    }
+    // W2: numbers after Arabic letters are Arabic numbers
+    let mut strong = embedding;
+    for t in types.iter_mut() {
+        match *t {
+            L | R | AL => strong = *t,
+            EN if strong == AL => *t = AN,
+            _ => (),
+        }
+    }
+    // W3
+    for t in types.iter_mut() {
+        if *t == AL {
+            *t = R;
+        }
+    }
+    // W4: single separators between numbers of a type join them
+    for i in 1..types.len().saturating_sub(1) {
+        let (before, after) = (types[i - 1], types[i + 1]);
+        if before != after {
+            continue;
+        }
+        match types[i] {
+            ES if before == EN => types[i] = EN,
+            CS if before == EN || before == AN => types[i] = before,
+            _ => (),
+        }
+    }
+    // W5: terminators next to European numbers join them
+    let mut i = 0;
+    while i < types.len() {
+        if types[i] != ET {
+            i += 1;
+            continue;
+        }
+        let end = i + types[i..].iter().take_while(|&&t| t == ET).count();
+        let touches = (i > 0 && types[i - 1] == EN) || types.get(end) == Some(&EN);
+        if touches {
+            types[i..end].iter_mut().for_each(|t| *t = EN);
+        }
+        i = end;
+    }
+    // W6
+    for t in types.iter_mut() {
+        if matches!(*t, ES | ET | CS) {
+            *t = ON;
+        }
+    }
+    // W7: European numbers after left-to-right text are left-to-right
+    let mut strong = embedding;
+    for t in types.iter_mut() {
+        match *t {
+            L | R => strong = *t,
+            EN if strong == L => *t = L,
+            _ => (),
+        }
+    }
+    // N1, N2: neutrals between text of one direction take it, and others
+    // take the paragraph's
+    let direction = |t: BidiClass| match t {
+        L => Some(L),
+        R | EN | AN => Some(R),
+        _ => None,
+    };
+    let mut i = 0;
+    while i < types.len() {
+        if direction(types[i]).is_some() {
+            i += 1;
+            continue;
+        }
+        let end = i + types[i..]
+            .iter()
+            .take_while(|&&t| direction(t).is_none())
+            .count();
+        let before = if i == 0 {
+            embedding
+        } else {
+            direction(types[i - 1]).unwrap_or(embedding)
+        };
+        let after = types
+            .get(end)
+            .and_then(|&t| direction(t))
+            .unwrap_or(embedding);
+        let resolved = if before == after { before } else { embedding };
+        types[i..end].iter_mut().for_each(|t| *t = resolved);
+        i = end;
+    }
+
+    // I1, I2
+    let mut previous = level;
+    let mut k = 0;
+    for (n, &(byte, _)) in chars.iter().enumerate() {
+        if kept.get(k) != Some(&n) {
+            levels[byte] = previous;
+            continue;
+        }
+        let resolved = match (level % 2 == 1, types[k]) {
+            (false, R) => level + 1,
+            (false, AN) | (false, EN) => level + 2,
+            (true, L) | (true, EN) | (true, AN) => level + 1,
+            _ => level,
+        };
+        levels[byte] = resolved;
+        previous = resolved;
+        k += 1;
+    }
+}
+
+/// The bidi class of `c`, from the blocks of the scripts that use it.
+fn bidi_class(c: char) -> BidiClass {
+    use BidiClass::*;
+
+    match c {
+        '\n' | '\r' | '\u{1c}'..='\u{1e}' | '\u{85}' | '\u{2029}' => B,
+        '\t' | '\u{b}' | '\u{1f}' => S,
+        ' '
+        | '\u{c}'
+        | '\u{1680}'
+        | '\u{2000}'..='\u{200a}'
+        | '\u{2028}'
+        | '\u{205f}'
+        | '\u{3000}' => WS,
+        '0'..='9'
+        | '\u{b2}'
+        | '\u{b3}'
+        | '\u{b9}'
+        | '\u{6f0}'..='\u{6f9}'
+        | '\u{2070}'
+        | '\u{2074}'..='\u{2079}'
+        | '\u{2080}'..='\u{2089}'
+        | '\u{ff10}'..='\u{ff19}' => EN,
+        '+' | '-' | '\u{207a}' | '\u{207b}' | '\u{208a}' | '\u{208b}' | '\u{2212}' | '\u{fb29}' => {
+            ES
+        }
+        '#'
+        | '$'
+        | '%'
+        | '\u{a2}'..='\u{a5}'
+        | '\u{b0}'
+        | '\u{b1}'
+        | '\u{609}'
+        | '\u{60a}'
+        | '\u{66a}'
+        | '\u{2030}'..='\u{2034}'
+        | '\u{20a0}'..='\u{20cf}' => ET,
+        ',' | '.' | '/' | ':' | '\u{a0}' | '\u{60c}' | '\u{202f}' | '\u{2044}' => CS,
+        '\u{600}'..='\u{605}' | '\u{660}'..='\u{669}' | '\u{66b}' | '\u{66c}' | '\u{6dd}' => AN,
+        '\u{300}'..='\u{36f}'
+        | '\u{483}'..='\u{489}'
+        | '\u{591}'..='\u{5bd}'
+        | '\u{5bf}'
+        | '\u{5c1}'
+        | '\u{5c2}'
+        | '\u{5c4}'
+        | '\u{5c5}'
+        | '\u{5c7}'
+        | '\u{610}'..='\u{61a}'
+        | '\u{64b}'..='\u{65f}'
+        | '\u{670}'
+        | '\u{6d6}'..='\u{6dc}'
+        | '\u{6df}'..='\u{6e4}'
+        | '\u{6e7}'
+        | '\u{6e8}'
+        | '\u{6ea}'..='\u{6ed}'
+        | '\u{1ab0}'..='\u{1aff}'
+        | '\u{1dc0}'..='\u{1dff}'
+        | '\u{20d0}'..='\u{20ff}'
+        | '\u{fe00}'..='\u{fe0f}'
+        | '\u{fe20}'..='\u{fe2f}'
+        | '\u{e0100}'..='\u{e01ef}' => Nsm,
+        '\u{0}'..='\u{8}'
+        | '\u{e}'..='\u{1b}'
+        | '\u{7f}'..='\u{84}'
+        | '\u{86}'..='\u{9f}'
+        | '\u{ad}'
+        | '\u{180e}'
+        | '\u{200b}'..='\u{200d}'
+        | '\u{202a}'..='\u{202e}'
+        | '\u{2060}'..='\u{206f}'
+        | '\u{feff}' => BN,
+        '\u{200e}' => L,
+        '\u{200f}' => R,
+        '\u{61c}' => AL,
+        '!' | '"' | '&' | '\'' | '(' | ')' | '*' | ';' | '<' | '=' | '>' | '?' | '@' | '['
+        | '\\' | ']' | '^' | '_' | '`' | '{' | '|' | '}' | '~' => ON,
+        '\u{a1}'
+        | '\u{a6}'..='\u{a9}'
+        | '\u{ab}'
+        | '\u{ac}'
+        | '\u{ae}'
+        | '\u{af}'
+        | '\u{b4}'
+        | '\u{b6}'..='\u{b8}'
+        | '\u{bb}'..='\u{bf}'
+        | '\u{d7}'
+        | '\u{f7}'
+        | '\u{2010}'..='\u{2027}'
+        | '\u{2035}'..='\u{2043}'
+        | '\u{2045}'..='\u{205e}'
+        | '\u{2190}'..='\u{2bff}'
+        | '\u{3001}'..='\u{3003}'
+        | '\u{1f000}'..='\u{1faff}' => ON,
+        '\u{590}'..='\u{5ff}'
+        | '\u{7c0}'..='\u{85f}'
+        | '\u{fb1d}'..='\u{fb4f}'
+        | '\u{10800}'..='\u{10cff}'
+        | '\u{1e800}'..='\u{1edff}' => R,
+        '\u{600}'..='\u{7bf}'
+        | '\u{860}'..='\u{8ff}'
+        | '\u{fb50}'..='\u{fdff}'
+        | '\u{fe70}'..='\u{fefe}'
+        | '\u{1ee00}'..='\u{1eeff}' => AL,
+        _ => L,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kurbo::Point;
+    use crate::{LayoutModel, LayoutRun, TextAlignment};
+
+    #[test]
+    fn levels() {
+        // "abc" and a space, then alef, bet, gimel
+        let bidi = BidiLevels::new("abc \u{5d0}\u{5d1}\u{5d2}");
+        assert!(bidi.has_rtl());
+        assert!(!bidi.is_rtl_at(3));
+        assert!(bidi.is_rtl_at(4));
+        assert!(bidi.is_rtl_at(9));
+        assert!(!BidiLevels::new("abc 123").has_rtl());
+    }
+
+    #[test]
+    fn visual_runs() {
+        // a right-to-left paragraph puts the left-to-right run on its left
+        let text = "\u{5d0}\u{5d1} abc";
+        let runs = BidiLevels::new(text).visual_runs(0..text.len());
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].range, 5..8);
+        assert!(!runs[0].is_rtl());
+        assert_eq!(runs[1].range, 0..5);
+        assert!(runs[1].is_rtl());
+
+        // a left-to-right paragraph keeps its runs in order
+        let text = "abc \u{5d0}\u{5d1}\u{5d2} def";
+        let runs = BidiLevels::new(text).visual_runs(0..text.len());
+        let ranges: Vec<_> = runs.iter().map(|run| run.range.clone()).collect();
+        assert_eq!(ranges, [0..4, 4..10, 10..14]);
+        assert_eq!(runs.iter().filter(|run| run.is_rtl()).count(), 1);
+        assert!(runs[1].is_rtl());
+    }
+
+    #[test]
+    fn trailing_whitespace_takes_the_paragraph_level() {
+        let text = "\u{5d0}\u{5d1} abc  ";
+        let runs = BidiLevels::new(text).visual_runs(0..text.len());
+        // the spaces at the end stay at the paragraph's end, on the left
+        assert_eq!(runs[0].range, 8..10);
+        assert!(runs[0].is_rtl());
+        assert_eq!(runs[1].range, 5..8);
+    }
+
+    #[test]
+    fn layout_follows_visual_runs() {
+        // every character is 1 wide
+        let measure = |text: &str, range: Range<usize>| text[range].chars().count() as f64;
+        let text = "abc \u{5d0}\u{5d1}\u{5d2} def";
+        let mut model = LayoutModel::new(text.to_string(), None, Vec::new(), None);
+        let run = LayoutRun {
+            range: 0..text.len(),
+            ascent: 8.0,
+            descent: 2.0,
+            height: 12.0,
+            ..Default::default()
+        };
+        model.place(TextAlignment::Start, f64::INFINITY, vec![run], measure);
+        assert_eq!(
+            model.visual_runs(0),
+            [(0..4, false), (4..10, true), (10..14, false)]
+        );
+
+        // gimel is drawn first of the three, and alef last
+        let clusters = model.visual_clusters(0).unwrap();
+        let starts: Vec<_> = clusters.iter().map(|c| c.range.start).collect();
+        assert_eq!(starts, [0, 1, 2, 3, 8, 6, 4, 10, 11, 12, 13]);
+        let x = |position| model.hit_test_text_position(position).unwrap().point.x;
+        assert_eq!(x(4), 7.0);
+        assert_eq!(x(8), 5.0);
+        assert_eq!(x(11), 8.0);
+
+        let hit = |x| {
+            model
+                .hit_test_point(Point::new(x, 0.0))
+                .metrics
+                .text_position
+        };
+        // the left half of a right-to-left cluster is after it
+        assert_eq!(hit(4.2), 10);
+        assert_eq!(hit(4.8), 8);
+        assert_eq!(hit(6.2), 6);
+        assert_eq!(hit(6.8), 4);
+        assert_eq!(hit(8.2), 11);
+    }
+}
@@ -0,0 +1,205 @@
+//! Runtime queries for what a backend supports.
+
+use std::fmt;
+
+use crate::{new_error, Error, ErrorKind, ImageFormat};
+
+/// Every image format, in the order the report lists them.
+const IMAGE_FORMATS: &[ImageFormat] = &[
+    ImageFormat::Rgb,
+    ImageFormat::RgbaSeparate,
+    ImageFormat::RgbaPremul,
+    ImageFormat::BgraPremul,
+    ImageFormat::Grayscale,
+    ImageFormat::Alpha,
+];
+
+/// The optional features a [`RenderContext`] supports.
+///
+/// Backends differ in what they can do; code that runs on several of them can
+/// check this at runtime to pick a fallback, rather than relying on `cfg`.
+/// Calls to unsupported features either do nothing, draw an approximation
+/// or return [`ErrorKind::NotSupported`], as documented on each method and
+/// chosen for some by the context's [`FallbackPolicy`].
+///
+/// New fields will be added as piet grows; backends should construct this
+/// with `..Capabilities::default()`, which reports nothing as supported.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{NullRenderContext, RenderContext};
+///
+/// let rc = NullRenderContext::new();
+/// if !rc.capabilities().radial_gradients {
+///     // fall back to a solid color
+/// }
+/// ```
+///
+/// [`RenderContext`]: trait.RenderContext.html
+/// [`ErrorKind::NotSupported`]: enum.ErrorKind.html#variant.NotSupported
+/// [`FallbackPolicy`]: enum.FallbackPolicy.html
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+    /// Text can be laid out and drawn.
+    pub text: bool,
+    /// Images can be made and drawn.
+    pub images: bool,
+    /// The formats accepted by [`make_image`].
+    ///
+    /// [`make_image`]: trait.RenderContext.html#tymethod.make_image
+    pub image_formats: &'static [ImageFormat],
+    /// [`InterpolationMode::NearestNeighbor`] is honored, rather than
+    /// treated as bilinear.
+    ///
+    /// [`InterpolationMode::NearestNeighbor`]: enum.InterpolationMode.html#variant.NearestNeighbor
+    pub nearest_neighbor_interpolation: bool,
+    /// Linear gradient brushes.
+    pub linear_gradients: bool,
+    /// Radial gradient brushes.
+    pub radial_gradients: bool,
+    /// Mesh gradient brushes, from a [`FixedMeshGradient`].
+    ///
+    /// [`FixedMeshGradient`]: struct.FixedMeshGradient.html
+    pub mesh_gradients: bool,
+    /// Dashed strokes.
+    pub dashes: bool,
+    /// Rendered pixels can be read back with [`capture_image_area`].
+    ///
+    /// [`capture_image_area`]: trait.RenderContext.html#tymethod.capture_image_area
+    pub read_back: bool,
+    /// Offscreen drawing with [`make_render_target`].
+    ///
+    /// [`make_render_target`]: trait.RenderContext.html#tymethod.make_render_target
+    pub render_targets: bool,
+    /// [`set_antialiasing`] has an effect.
+    ///
+    /// [`set_antialiasing`]: trait.RenderContext.html#tymethod.set_antialiasing
+    pub antialiasing: bool,
+    /// Draw calls tagged with [`set_pick_id`] can be found again by the ids
+    /// under a point.
+    ///
+    /// [`set_pick_id`]: trait.RenderContext.html#tymethod.set_pick_id
+    pub picking: bool,
+    /// Fonts can be loaded from memory with [`Text::load_font`], and text
+    /// drawn without the system's fonts.
+    ///
+    /// [`Text::load_font`]: trait.Text.html#tymethod.load_font
+    pub font_loading: bool,
+    /// Text can be set in columns with [`WritingMode::VerticalRightToLeft`].
+    /// Without this, vertical layouts are laid out horizontally.
+    ///
+    /// [`WritingMode::VerticalRightToLeft`]: enum.WritingMode.html#variant.VerticalRightToLeft
+    pub vertical_text: bool,
+    /// The axes of variable fonts can be set with [`FontBuilder::variation`].
+    /// Without this, fonts use the face nearest their weight and stretch.
+    ///
+    /// [`FontBuilder::variation`]: trait.FontBuilder.html#tymethod.variation
+    pub variable_fonts: bool,
+    /// Glyphs shaped by the caller can be drawn by id with [`draw_glyphs`].
+    ///
+    /// [`draw_glyphs`]: trait.RenderContext.html#tymethod.draw_glyphs
+    pub glyph_drawing: bool,
+}
+
+/// The full feature matrix, a line for each feature and image format with
+/// whether it's supported, for diagnostics and bug reports.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{NullRenderContext, RenderContext};
+///
+/// let rc = NullRenderContext::new();
+/// let report = rc.capabilities().to_string();
+/// assert!(report.contains("radial gradients"));
+/// ```
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut row = |depth: usize, name: &str, supported: bool| {
+            writeln!(
+                f,
+                "{:indent$}{:width$}{}",
+                "",
+                name,
+                if supported { "yes" } else { "no" },
+                indent = 2 * depth,
+                width = 32 - 2 * depth,
+            )
+        };
+        row(0, "linear gradients", self.linear_gradients)?;
+        row(0, "radial gradients", self.radial_gradients)?;
+        row(0, "mesh gradients", self.mesh_gradients)?;
+        row(0, "dashes", self.dashes)?;
+        row(0, "antialiasing", self.antialiasing)?;
+        row(0, "render targets", self.render_targets)?;
+        row(0, "read back", self.read_back)?;
+        row(0, "picking", self.picking)?;
+        row(0, "images", self.images)?;
+        row(1, "nearest neighbor", self.nearest_neighbor_interpolation)?;
+        for format in IMAGE_FORMATS {
+            let name = format!("format {:?}", format);
+            row(1, &name, self.image_formats.contains(format))?;
+        }
+        row(0, "text", self.text)?;
+        row(1, "font loading", self.font_loading)?;
+        row(1, "vertical text", self.vertical_text)?;
+        row(1, "variable fonts", self.variable_fonts)?;
+        row(1, "glyph drawing", self.glyph_drawing)
+    }
+}
+
+/// What a context does when asked for a feature its backend doesn't have,
+/// set with [`set_fallback_policy`].
+///
+/// Each feature that can be missing documents how it degrades, so that
+/// every backend draws the same approximation:
+///
+/// - a mesh gradient from [`gradient`] is a solid brush of the mesh's
+///   [`fallback_color`];
+/// - [`with_blur`] draws its contents unblurred.
+///
+/// Under [`Error`], these return [`ErrorKind::NotSupported`] instead, and
+/// draw nothing. Check [`Capabilities`] first to avoid either.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{FallbackPolicy, FixedMeshGradient, NullRenderContext, RenderContext};
+///
+/// let mut rc = NullRenderContext::new();
+/// rc.set_fallback_policy(FallbackPolicy::Error);
+/// let mesh = FixedMeshGradient { patches: Vec::new() };
+/// if rc.capabilities().mesh_gradients {
+///     rc.gradient(mesh).unwrap();
+/// }
+/// ```
+///
+/// [`set_fallback_policy`]: trait.RenderContext.html#tymethod.set_fallback_policy
+/// [`gradient`]: trait.RenderContext.html#tymethod.gradient
+/// [`fallback_color`]: struct.FixedMeshGradient.html#method.fallback_color
+/// [`with_blur`]: trait.RenderContext.html#tymethod.with_blur
+/// [`Error`]: #variant.Error
+/// [`ErrorKind::NotSupported`]: enum.ErrorKind.html#variant.NotSupported
+/// [`Capabilities`]: struct.Capabilities.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FallbackPolicy {
+    /// Draw the documented approximation.
+    #[default]
+    Degrade,
+    /// Return [`ErrorKind::NotSupported`].
+    ///
+    /// [`ErrorKind::NotSupported`]: enum.ErrorKind.html#variant.NotSupported
+    Error,
+}
+
+impl FallbackPolicy {
+    /// For backends: the approximation made by `fallback`, or an error,
+    /// depending on the policy.
+    pub fn degrade<T>(self, fallback: impl FnOnce() -> T) -> Result<T, Error> {
+        match self {
+            FallbackPolicy::Degrade => Ok(fallback()),
+            FallbackPolicy::Error => Err(new_error(ErrorKind::NotSupported)),
+        }
+    }
+}
@@ -54,6 +54,8 @@ impl Color {
     }
 
     /// Create a color with a grey value in the range 0.0..=1.0.
+    ///
+    /// See [`grey8`](#method.grey8) for a `const` equivalent.
     pub fn grey(grey: impl Into<f64>) -> Color {
         let grey = grey.into();
         Color::rgb(grey, grey, grey)
@@ -164,8 +166,24 @@ impl Color {
         Color::from_rgba32_u32((self.as_rgba_u32() & !0xff) | a)
     }
 
+    /// Change just the alpha value of a color, to an 8 bit value.
+    ///
+    /// Unlike [`with_alpha`](#method.with_alpha), this can be used in
+    /// `const` contexts:
+    ///
+    /// ```
+    /// use piet::Color;
+    ///
+    /// const SHADOW: Color = Color::BLACK.with_alpha8(0x40);
+    ///
+    /// assert_eq!(SHADOW.as_rgba_u32(), 0x0000_0040);
+    /// ```
+    pub const fn with_alpha8(self, a: u8) -> Color {
+        Color::from_rgba32_u32((self.as_rgba_u32() & !0xff) | a as u32)
+    }
+
     /// Convert a color value to a 32-bit rgba value.
-    pub fn as_rgba_u32(&self) -> u32 {
+    pub const fn as_rgba_u32(&self) -> u32 {
         match *self {
             Color::Rgba32(rgba) => rgba,
         }
@@ -177,3 +195,33 @@ impl Color {
     /// Opaque black.
     pub const BLACK: Color = Color::rgb8(0, 0, 0);
 }
+
+/// Colors are written as `"#rrggbbaa"` strings, and read from those or from
+/// `"#rrggbb"` ones, which are opaque.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Color {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", self))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        use serde::de::Error;
+
+        let hex = String::deserialize(deserializer)?;
+        let digits = hex.strip_prefix('#').unwrap_or(&hex);
+        let rgba = u32::from_str_radix(digits, 16)
+            .ok()
+            .filter(|_| digits.bytes().all(|b| b.is_ascii_hexdigit()));
+        match (rgba, digits.len()) {
+            (Some(rgb), 6) => Ok(Color::from_rgba32_u32(rgb << 8 | 0xff)),
+            (Some(rgba), 8) => Ok(Color::from_rgba32_u32(rgba)),
+            _ => Err(D::Error::custom(format!(
+                "expected a \"#rrggbb\" or \"#rrggbbaa\" color, found {:?}",
+                hex
+            ))),
+        }
+    }
+}
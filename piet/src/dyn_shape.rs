@@ -0,0 +1,153 @@
+//! Shapes as trait objects.
+
+use kurbo::{Circle, Line, PathEl, Point, Rect, RoundedRect, Shape};
+
+/// A [`Shape`] that can be a trait object, for drawing collections of
+/// shapes of different types.
+///
+/// `Shape` has an associated iterator type, so `&dyn Shape` isn't possible.
+/// Every shape is a `DynShape`, and `&dyn DynShape` is a `Shape`, so it can
+/// be handed to [`fill`], [`stroke`], [`clip`] and everything else that
+/// takes one. Backends still see rectangles, circles and path slices for
+/// what they are, through the `as_` methods.
+///
+/// The methods mirror `Shape`'s, with a `dyn_` prefix so that calls to
+/// `Shape`'s aren't ambiguous when both traits are in scope; there's no
+/// need to call them directly.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Circle, Line, Rect, Shape};
+/// use piet::{Color, DynShape, NullRenderContext, RenderContext};
+///
+/// let shapes: Vec<Box<dyn DynShape>> = vec![
+///     Box::new(Rect::new(0.0, 0.0, 10.0, 10.0)),
+///     Box::new(Circle::new((20.0, 5.0), 5.0)),
+///     Box::new(Line::new((0.0, 20.0), (30.0, 20.0))),
+/// ];
+///
+/// let mut rc = NullRenderContext::new();
+/// let brush = rc.solid_brush(Color::BLACK);
+/// for shape in &shapes {
+///     rc.stroke(shape.as_ref(), &brush, 1.0);
+/// }
+/// // the rectangle is still drawn as one
+/// assert!(shapes[0].as_ref().as_rect().is_some());
+/// assert!(shapes[1].as_ref().as_rect().is_none());
+/// ```
+///
+/// [`Shape`]: https://docs.rs/kurbo/0.5/kurbo/trait.Shape.html
+/// [`fill`]: trait.RenderContext.html#tymethod.fill
+/// [`stroke`]: trait.RenderContext.html#tymethod.stroke
+/// [`clip`]: trait.RenderContext.html#tymethod.clip
+pub trait DynShape {
+    /// See `Shape::to_bez_path`.
+    fn dyn_to_bez_path(&self, tolerance: f64) -> Box<dyn Iterator<Item = PathEl> + '_>;
+    /// See `Shape::area`.
+    fn dyn_area(&self) -> f64;
+    /// See `Shape::perimeter`.
+    fn dyn_perimeter(&self, accuracy: f64) -> f64;
+    /// See `Shape::winding`.
+    fn dyn_winding(&self, pt: Point) -> i32;
+    /// See `Shape::bounding_box`.
+    fn dyn_bounding_box(&self) -> Rect;
+    /// See `Shape::as_line`.
+    fn dyn_as_line(&self) -> Option<Line>;
+    /// See `Shape::as_rect`.
+    fn dyn_as_rect(&self) -> Option<Rect>;
+    /// See `Shape::as_rounded_rect`.
+    fn dyn_as_rounded_rect(&self) -> Option<RoundedRect>;
+    /// See `Shape::as_circle`.
+    fn dyn_as_circle(&self) -> Option<Circle>;
+    /// See `Shape::as_path_slice`.
+    fn dyn_as_path_slice(&self) -> Option<&[PathEl]>;
+}
+
+impl<T: Shape> DynShape for T {
+    fn dyn_to_bez_path(&self, tolerance: f64) -> Box<dyn Iterator<Item = PathEl> + '_> {
+        Box::new(self.to_bez_path(tolerance))
+    }
+
+    fn dyn_area(&self) -> f64 {
+        self.area()
+    }
+
+    fn dyn_perimeter(&self, accuracy: f64) -> f64 {
+        self.perimeter(accuracy)
+    }
+
+    fn dyn_winding(&self, pt: Point) -> i32 {
+        self.winding(pt)
+    }
+
+    fn dyn_bounding_box(&self) -> Rect {
+        self.bounding_box()
+    }
+
+    fn dyn_as_line(&self) -> Option<Line> {
+        self.as_line()
+    }
+
+    fn dyn_as_rect(&self) -> Option<Rect> {
+        self.as_rect()
+    }
+
+    fn dyn_as_rounded_rect(&self) -> Option<RoundedRect> {
+        self.as_rounded_rect()
+    }
+
+    fn dyn_as_circle(&self) -> Option<Circle> {
+        self.as_circle()
+    }
+
+    fn dyn_as_path_slice(&self) -> Option<&[PathEl]> {
+        self.as_path_slice()
+    }
+}
+
+impl<'a> Shape for &'a dyn DynShape {
+    type BezPathIter = Box<dyn Iterator<Item = PathEl> + 'a>;
+
+    fn to_bez_path(&self, tolerance: f64) -> Self::BezPathIter {
+        // through the object, as `self` is a shape too
+        let shape: &'a dyn DynShape = *self;
+        shape.dyn_to_bez_path(tolerance)
+    }
+
+    fn area(&self) -> f64 {
+        (**self).dyn_area()
+    }
+
+    fn perimeter(&self, accuracy: f64) -> f64 {
+        (**self).dyn_perimeter(accuracy)
+    }
+
+    fn winding(&self, pt: Point) -> i32 {
+        (**self).dyn_winding(pt)
+    }
+
+    fn bounding_box(&self) -> Rect {
+        (**self).dyn_bounding_box()
+    }
+
+    fn as_line(&self) -> Option<Line> {
+        (**self).dyn_as_line()
+    }
+
+    fn as_rect(&self) -> Option<Rect> {
+        (**self).dyn_as_rect()
+    }
+
+    fn as_rounded_rect(&self) -> Option<RoundedRect> {
+        (**self).dyn_as_rounded_rect()
+    }
+
+    fn as_circle(&self) -> Option<Circle> {
+        (**self).dyn_as_circle()
+    }
+
+    fn as_path_slice(&self) -> Option<&[PathEl]> {
+        (**self).dyn_as_path_slice()
+    }
+}
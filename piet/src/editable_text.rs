@@ -0,0 +1,177 @@
+//! Text layout for editing, updated incrementally.
+
+use std::ops::Range;
+
+use crate::{new_error, Error, ErrorKind, Text, TextLayout, TextLayoutBuilder};
+
+/// A multi-line text layout that can be edited without laying out all of
+/// the text again.
+///
+/// The text is split into lines at `'\n'`, each with its own layout. An edit
+/// only rebuilds the layouts of the lines it touches, so the cost of a
+/// keystroke depends on the length of the line being edited rather than the
+/// size of the document.
+///
+/// Offsets are in UTF-8 code units, like the rest of the text API.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{EditableTextLayout, FontBuilder, NullRenderContext, RenderContext, Text};
+///
+/// let mut rc = NullRenderContext::new();
+/// let mut text = rc.text();
+/// let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+/// let mut layout = EditableTextLayout::new(&mut text, font, "one\ntwo\nthree").unwrap();
+///
+/// let rebuilt = layout.splice(&mut text, 4..7, "2\n2.5").unwrap();
+/// assert_eq!(layout.text(), "one\n2\n2.5\nthree");
+/// assert_eq!(rebuilt, 1..3);
+/// assert_eq!(layout.line_count(), 4);
+/// assert_eq!(layout.line_range(3), 10..15);
+/// ```
+pub struct EditableTextLayout<T: Text> {
+    font: T::Font,
+    text: String,
+    lines: Vec<Line<T::TextLayout>>,
+}
+
+struct Line<L> {
+    /// Offset of the start of the line in the full text.
+    start: usize,
+    /// Length in bytes, excluding the newline.
+    len: usize,
+    layout: L,
+}
+
+impl<T: Text> EditableTextLayout<T> {
+    /// Lay out `s` line by line in `font`.
+    pub fn new(text: &mut T, font: T::Font, s: &str) -> Result<Self, Error> {
+        let mut layout = EditableTextLayout {
+            font,
+            text: String::new(),
+            lines: Vec::new(),
+        };
+        layout.lines = layout.build_lines(text, s, 0)?;
+        layout.text = s.to_owned();
+        Ok(layout)
+    }
+
+    /// The full text.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The font the text is laid out in.
+    pub fn font(&self) -> &T::Font {
+        &self.font
+    }
+
+    /// The number of lines; a trailing newline starts an empty last line.
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// The layout of line `line`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `line` is out of bounds.
+    pub fn line_layout(&self, line: usize) -> &T::TextLayout {
+        &self.lines[line].layout
+    }
+
+    /// The range of the full text covered by line `line`, excluding the
+    /// newline.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `line` is out of bounds.
+    pub fn line_range(&self, line: usize) -> Range<usize> {
+        let line = &self.lines[line];
+        line.start..line.start + line.len
+    }
+
+    /// The line containing the text position `offset`.
+    ///
+    /// A position just after a line's last character, before the newline,
+    /// belongs to that line. Positions past the end belong to the last line.
+    pub fn line_for_offset(&self, offset: usize) -> usize {
+        // the first line starts at 0, so this is never 0
+        let after = self.lines.partition_point(|line| line.start <= offset);
+        after - 1
+    }
+
+    /// The width of the widest line.
+    pub fn width(&self) -> f64 {
+        self.lines
+            .iter()
+            .map(|line| line.layout.width())
+            .fold(0.0, f64::max)
+    }
+
+    /// Replace `range` of the text with `replacement`, and update the layout.
+    ///
+    /// Only lines overlapping `range` are laid out again; the lines after
+    /// them are just renumbered. Returns the range of line indices whose
+    /// layouts were rebuilt, which is what needs repainting besides any lines
+    /// that moved.
+    ///
+    /// Returns an error if `range` is out of bounds or doesn't fall on
+    /// character boundaries, leaving the layout unchanged.
+    pub fn splice(
+        &mut self,
+        text: &mut T,
+        range: Range<usize>,
+        replacement: &str,
+    ) -> Result<Range<usize>, Error> {
+        if range.start > range.end
+            || range.end > self.text.len()
+            || !self.text.is_char_boundary(range.start)
+            || !self.text.is_char_boundary(range.end)
+        {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+
+        let first = self.line_for_offset(range.start);
+        let last = self.line_for_offset(range.end);
+        let old_start = self.lines[first].start;
+        let old_end = self.lines[last].start + self.lines[last].len;
+
+        let mut new_text = String::with_capacity(old_end - old_start + replacement.len());
+        new_text.push_str(&self.text[old_start..range.start]);
+        new_text.push_str(replacement);
+        new_text.push_str(&self.text[range.end..old_end]);
+        let new_lines = self.build_lines(text, &new_text, old_start)?;
+
+        let delta = replacement.len() as isize - (range.end - range.start) as isize;
+        for line in &mut self.lines[last + 1..] {
+            line.start = (line.start as isize + delta) as usize;
+        }
+        let rebuilt = first..first + new_lines.len();
+        self.lines.splice(first..=last, new_lines);
+        self.text.replace_range(range, replacement);
+        Ok(rebuilt)
+    }
+
+    fn build_lines(
+        &self,
+        text: &mut T,
+        s: &str,
+        start: usize,
+    ) -> Result<Vec<Line<T::TextLayout>>, Error> {
+        let mut offset = start;
+        s.split('\n')
+            .map(|line| {
+                let layout = text.new_text_layout(&self.font, line).build()?;
+                let result = Line {
+                    start: offset,
+                    len: line.len(),
+                    layout,
+                };
+                offset += line.len() + 1;
+                Ok(result)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,341 @@
+//! Bitmap fallback for color emoji.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::kurbo::{Rect, Vec2};
+use crate::{new_error, Error, ErrorKind};
+
+/// Requests the emoji presentation of the character before it; ignored when
+/// looking sequences up.
+const VARIATION_SELECTOR_16: char = '\u{fe0f}';
+
+/// Requests the text presentation of the character before it.
+const VARIATION_SELECTOR_15: char = '\u{fe0e}';
+
+/// The characters shown as pictures unless a selector asks for text, from
+/// the `Emoji_Presentation` property. Ranges in the supplementary planes are
+/// taken whole where most of their assigned characters have it.
+const EMOJI_PRESENTATION: &[(u32, u32)] = &[
+    (0x231a, 0x231b),
+    (0x23e9, 0x23ec),
+    (0x23f0, 0x23f0),
+    (0x23f3, 0x23f3),
+    (0x25fd, 0x25fe),
+    (0x2614, 0x2615),
+    (0x2648, 0x2653),
+    (0x267f, 0x267f),
+    (0x2693, 0x2693),
+    (0x26a1, 0x26a1),
+    (0x26aa, 0x26ab),
+    (0x26bd, 0x26be),
+    (0x26c4, 0x26c5),
+    (0x26ce, 0x26ce),
+    (0x26d4, 0x26d4),
+    (0x26ea, 0x26ea),
+    (0x26f2, 0x26f3),
+    (0x26f5, 0x26f5),
+    (0x26fa, 0x26fa),
+    (0x26fd, 0x26fd),
+    (0x2705, 0x2705),
+    (0x270a, 0x270b),
+    (0x2728, 0x2728),
+    (0x274c, 0x274c),
+    (0x274e, 0x274e),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2795, 0x2797),
+    (0x27b0, 0x27b0),
+    (0x27bf, 0x27bf),
+    (0x2b1b, 0x2b1c),
+    (0x2b50, 0x2b50),
+    (0x2b55, 0x2b55),
+    (0x1f004, 0x1f004),
+    (0x1f0cf, 0x1f0cf),
+    (0x1f18e, 0x1f18e),
+    (0x1f191, 0x1f19a),
+    (0x1f1e6, 0x1f1ff),
+    (0x1f201, 0x1f201),
+    (0x1f21a, 0x1f21a),
+    (0x1f22f, 0x1f22f),
+    (0x1f232, 0x1f236),
+    (0x1f238, 0x1f23a),
+    (0x1f250, 0x1f251),
+    (0x1f300, 0x1f64f),
+    (0x1f680, 0x1f6ff),
+    (0x1f7e0, 0x1f7f0),
+    (0x1f90c, 0x1f9ff),
+    (0x1fa70, 0x1faff),
+];
+
+/// How text layouts draw emoji, set with [`Text::set_emoji_strategy`].
+///
+/// Color emoji fonts are often missing on minimal Linux images, so the same
+/// text renders as color glyphs on one machine and as outlines or boxes on
+/// another. Drawing emoji from bitmaps shipped with the application keeps
+/// them identical everywhere, which is what snapshot tests need.
+///
+/// [`Text::set_emoji_strategy`]: trait.Text.html#tymethod.set_emoji_strategy
+#[derive(Debug, Clone, Default)]
+pub enum EmojiStrategy {
+    /// Draw emoji with whatever the platform's fonts provide. Backends
+    /// prefer a color emoji font for the clusters that
+    /// [`is_emoji_presentation`] picks out, and draw its glyphs in their own
+    /// colors rather than the brush's.
+    ///
+    /// [`is_emoji_presentation`]: fn.is_emoji_presentation.html
+    #[default]
+    Platform,
+    /// Draw the sequences found in the atlas as bitmaps, in place of the
+    /// fonts' glyphs. Other text, including emoji missing from the atlas,
+    /// is drawn as usual.
+    Bitmap(Arc<EmojiAtlas>),
+}
+
+impl EmojiStrategy {
+    /// The ranges of `text` to draw as bitmaps, in order.
+    pub fn find<'a>(&'a self, text: &str) -> Vec<(Range<usize>, &'a EmojiBitmap)> {
+        match self {
+            EmojiStrategy::Platform => Vec::new(),
+            EmojiStrategy::Bitmap(atlas) => atlas.find(text),
+        }
+    }
+}
+
+/// An image for one emoji, 4 bytes per pixel in RGBA order with separate
+/// alpha.
+#[derive(Clone)]
+pub struct EmojiBitmap {
+    width: usize,
+    height: usize,
+    buf: Vec<u8>,
+}
+
+impl EmojiBitmap {
+    /// Wrap the pixels of an image.
+    ///
+    /// Returns an error if the image is empty or `buf` has the wrong length.
+    pub fn new(width: usize, height: usize, buf: Vec<u8>) -> Result<EmojiBitmap, Error> {
+        if width == 0 || height == 0 || buf.len() != width * height * 4 {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        Ok(EmojiBitmap { width, height, buf })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The pixels, suitable for [`make_image`] with
+    /// `ImageFormat::RgbaSeparate`.
+    ///
+    /// [`make_image`]: trait.RenderContext.html#tymethod.make_image
+    pub fn buf(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// The largest rectangle with this bitmap's aspect ratio that fits in
+    /// `rect`, centered in it.
+    ///
+    /// Backends draw each emoji into the box its glyph would have occupied.
+    pub fn fit(&self, rect: Rect) -> Rect {
+        let rect = rect.abs();
+        let scale = (rect.width() / self.width as f64).min(rect.height() / self.height as f64);
+        let half = Vec2::new(self.width as f64, self.height as f64) * (scale / 2.0);
+        let center = rect.center();
+        Rect::from_points(center - half, center + half)
+    }
+}
+
+impl fmt::Debug for EmojiBitmap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EmojiBitmap")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+/// A set of emoji bitmaps, keyed by the character sequence they stand for.
+///
+/// Sequences can be single characters or longer ones such as flags and ZWJ
+/// sequences; when several match, the longest wins. The emoji presentation
+/// selector U+FE0F is ignored in both the keys and the text.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{EmojiAtlas, EmojiBitmap};
+///
+/// let red = EmojiBitmap::new(1, 1, vec![0xff, 0, 0, 0xff]).unwrap();
+/// let mut atlas = EmojiAtlas::new();
+/// atlas.insert("\u{2764}", red.clone());
+/// atlas.insert("\u{1f468}\u{200d}\u{1f4bb}", red);
+///
+/// let text = "I \u{2764}\u{fe0f} \u{1f468}\u{200d}\u{1f4bb}s";
+/// let ranges: Vec<_> = atlas.find(text).into_iter().map(|(r, _)| r).collect();
+/// assert_eq!(ranges, vec![2..8, 9..20]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EmojiAtlas {
+    map: HashMap<String, EmojiBitmap>,
+    /// The length of the longest key, in chars.
+    max_len: usize,
+}
+
+impl EmojiAtlas {
+    /// An empty atlas.
+    pub fn new() -> EmojiAtlas {
+        EmojiAtlas::default()
+    }
+
+    /// Slice an atlas out of a sheet of square cells.
+    ///
+    /// The sheet is `width` by `height` pixels, RGBA with separate alpha, and
+    /// holds one emoji per `cell` by `cell` square, left to right and then
+    /// top to bottom, in the order of `sequences`. Empty strings skip a cell.
+    ///
+    /// Returns an error if `buf` has the wrong length or the sheet has fewer
+    /// cells than `sequences`.
+    pub fn from_grid(
+        width: usize,
+        height: usize,
+        buf: &[u8],
+        cell: usize,
+        sequences: &[&str],
+    ) -> Result<EmojiAtlas, Error> {
+        if cell == 0 || buf.len() != width * height * 4 {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        let columns = width / cell;
+        if sequences.len() > columns * (height / cell) {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        let mut atlas = EmojiAtlas::new();
+        for (i, sequence) in sequences.iter().enumerate() {
+            if sequence.is_empty() {
+                continue;
+            }
+            let (x0, y0) = ((i % columns) * cell, (i / columns) * cell);
+            let mut pixels = Vec::with_capacity(cell * cell * 4);
+            for y in y0..y0 + cell {
+                let row = (y * width + x0) * 4;
+                pixels.extend_from_slice(&buf[row..row + cell * 4]);
+            }
+            atlas.insert(sequence, EmojiBitmap::new(cell, cell, pixels)?);
+        }
+        Ok(atlas)
+    }
+
+    /// Draw `sequence` as `bitmap`, replacing any previous entry.
+    pub fn insert(&mut self, sequence: &str, bitmap: EmojiBitmap) {
+        let key: String = sequence
+            .chars()
+            .filter(|&c| c != VARIATION_SELECTOR_16)
+            .collect();
+        if key.is_empty() {
+            return;
+        }
+        self.max_len = self.max_len.max(key.chars().count());
+        self.map.insert(key, bitmap);
+    }
+
+    /// The bitmap for `sequence`, if there is one.
+    pub fn get(&self, sequence: &str) -> Option<&EmojiBitmap> {
+        let key: String = sequence
+            .chars()
+            .filter(|&c| c != VARIATION_SELECTOR_16)
+            .collect();
+        self.map.get(&key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// The ranges of `text` that have a bitmap, in order.
+    ///
+    /// Matches are taken greedily from the start, longest first. A range
+    /// includes any presentation selectors following its sequence.
+    pub fn find(&self, text: &str) -> Vec<(Range<usize>, &EmojiBitmap)> {
+        let mut found = Vec::new();
+        let mut start = 0;
+        while let Some(first) = text[start..].chars().next() {
+            let mut key = String::new();
+            let mut best = None;
+            for (i, c) in text[start..].char_indices() {
+                let end = start + i + c.len_utf8();
+                if c == VARIATION_SELECTOR_16 {
+                    if let Some((best_end, _)) = &mut best {
+                        if *best_end == end - c.len_utf8() {
+                            *best_end = end;
+                        }
+                    }
+                    continue;
+                }
+                if key.chars().count() == self.max_len {
+                    break;
+                }
+                key.push(c);
+                if let Some(bitmap) = self.map.get(&key) {
+                    best = Some((end, bitmap));
+                }
+            }
+            match best {
+                Some((end, bitmap)) => {
+                    found.push((start..end, bitmap));
+                    start = end;
+                }
+                None => start += first.len_utf8(),
+            }
+        }
+        found
+    }
+}
+
+/// Whether a grapheme cluster is shown as a color emoji, rather than as a
+/// glyph drawn in the text's color.
+///
+/// Emoji presentation comes from the cluster's first character, unless a
+/// variation selector asks otherwise: U+FE0F for emoji, as in keycaps and
+/// "❤️", and U+FE0E for text. Backends use this to look for color emoji
+/// fonts before the text's own font, which may have a plain outline for the
+/// same character.
+///
+/// # Examples
+///
+/// ```
+/// use piet::is_emoji_presentation;
+///
+/// assert!(is_emoji_presentation("\u{1f600}"));
+/// assert!(is_emoji_presentation("\u{2764}\u{fe0f}"));
+/// assert!(is_emoji_presentation("1\u{fe0f}\u{20e3}"));
+/// assert!(!is_emoji_presentation("\u{2764}"));
+/// assert!(!is_emoji_presentation("\u{231a}\u{fe0e}"));
+/// assert!(!is_emoji_presentation("a"));
+/// ```
+pub fn is_emoji_presentation(grapheme: &str) -> bool {
+    if grapheme.contains(VARIATION_SELECTOR_16) {
+        return true;
+    }
+    if grapheme.contains(VARIATION_SELECTOR_15) {
+        return false;
+    }
+    let first = match grapheme.chars().next() {
+        Some(c) => c as u32,
+        None => return false,
+    };
+    EMOJI_PRESENTATION
+        .iter()
+        .any(|&(start, end)| (start..=end).contains(&first))
+}
@@ -3,6 +3,9 @@
 use std::fmt;
 
 /// An error that can occur while rendering 2D graphics.
+///
+/// Backend errors keep the platform's error, which may not be `Send`, so
+/// errors stay on the thread they happened on.
 #[derive(Debug)]
 pub struct Error(Box<ErrorKind>);
 
@@ -0,0 +1,1135 @@
+//! Exporting display lists as standalone SVG and HTML files.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use kurbo::{Affine, BezPath, PathEl, Point, Rect, Shape, Size};
+
+use crate::recording::{text_bounds, GraphicsState};
+use crate::{
+    Antialiasing, Color, DisplayList, DrawCommand, FixedGradient, FixedLinearGradient,
+    FixedRadialGradient, GradientStop, Image, ImageFormat, InterpolationMode, LineCap, LineJoin,
+    PaintBrush, RecordedImage, RecordedTextLayout, StrokeStyle, TextLayout, TruncatedText,
+};
+
+impl<L: TextLayout> DisplayList<L> {
+    /// The list as a standalone SVG document, `size` units wide and high,
+    /// for attaching a reproduction to a bug report.
+    ///
+    /// Each command becomes an element with the transform it was drawn
+    /// under; clips and blurs become groups around the elements they
+    /// apply to, and images are embedded as PNG data. Pick ids are kept as
+    /// `data-pick-id` attributes.
+    ///
+    /// SVG can't express everything a display list can, so some commands
+    /// are approximated: text is drawn a line at a time with the font it
+    /// was laid out with, without its range attributes
+    /// or glyph transforms; mesh gradients are drawn as the first color of
+    /// their first patch; clears are painted over what's beneath them
+    /// rather than replacing it; and glyph and image positioning is left to
+    /// the viewer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::{Circle, Size};
+    /// use piet::{Color, RecordingContext, RenderContext};
+    /// # use piet::NullText;
+    ///
+    /// let mut rc = RecordingContext::new(NullText);
+    /// rc.with_save(|rc| {
+    ///     rc.clip(Circle::new((50.0, 50.0), 40.0));
+    ///     rc.fill(Circle::new((50.0, 50.0), 50.0), &Color::rgb8(0xff, 0, 0));
+    ///     Ok(())
+    /// })
+    /// .unwrap();
+    ///
+    /// let svg = rc.into_display_list().to_svg(Size::new(100.0, 100.0));
+    /// assert!(svg.starts_with("<svg"));
+    /// assert!(svg.contains("<clipPath"));
+    /// assert!(svg.contains(r##"fill="#ff0000""##));
+    /// ```
+    pub fn to_svg(&self, size: Size) -> String {
+        let mut svg = Svg {
+            out: String::new(),
+            size,
+            ids: 0,
+            images: HashMap::new(),
+            pick_id: None,
+            crisp: false,
+        };
+        let _ = writeln!(
+            svg.out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" \
+             xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+             width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+            w = size.width,
+            h = size.height,
+        );
+        export(self, &mut svg);
+        svg.out.push_str("</svg>\n");
+        svg.out
+    }
+
+    /// The list as a standalone HTML page, which draws it on a canvas
+    /// `size` pixels wide and high with the equivalent canvas calls.
+    ///
+    /// This is for comparing what a browser's canvas draws with what a
+    /// backend does, and for stepping through a scene in a browser's
+    /// debugger: each command becomes a few lines of script, under a
+    /// comment naming it. Images are embedded as base64 pixels, and blurs
+    /// and clips to text are drawn through offscreen canvases.
+    ///
+    /// Text is approximated as for [`to_svg`], and so are mesh gradients.
+    /// Antialiasing and glyph and image positioning are left to the browser.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::{Rect, Size};
+    /// use piet::{Color, RecordingContext, RenderContext};
+    /// # use piet::NullText;
+    ///
+    /// let mut rc = RecordingContext::new(NullText);
+    /// rc.fill(Rect::new(10.0, 10.0, 90.0, 90.0), &Color::rgba8(0, 0, 0xff, 0x80));
+    ///
+    /// let html = rc.into_display_list().to_canvas_html(Size::new(100.0, 100.0));
+    /// assert!(html.contains("<canvas"));
+    /// assert!(html.contains("ctx.fill(\"nonzero\");"));
+    /// ```
+    ///
+    /// [`to_svg`]: #method.to_svg
+    pub fn to_canvas_html(&self, size: Size) -> String {
+        let mut canvas = Canvas {
+            out: String::new(),
+            images: HashMap::new(),
+        };
+        let _ = write!(
+            canvas.out,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>piet display list</title>\n</head>\n<body>\n\
+             <canvas id=\"piet\" width=\"{}\" height=\"{}\"></canvas>\n<script>\n{}",
+            size.width.ceil().max(0.0),
+            size.height.ceil().max(0.0),
+            CANVAS_PRELUDE,
+        );
+        export(self, &mut canvas);
+        canvas.out.push_str("</script>\n</body>\n</html>\n");
+        canvas.out
+    }
+}
+
+/// The helpers the commands of a canvas export call.
+const CANVAS_PRELUDE: &str = "\
+const canvas = document.getElementById(\"piet\");
+let ctx = canvas.getContext(\"2d\");
+// the contexts under the offscreen ones being drawn into
+const layers = [];
+function layer() {
+  const c = document.createElement(\"canvas\");
+  c.width = canvas.width;
+  c.height = canvas.height;
+  return c.getContext(\"2d\");
+}
+function image(width, height, rgba) {
+  const c = document.createElement(\"canvas\");
+  c.width = width;
+  c.height = height;
+  const pixels = Uint8ClampedArray.from(atob(rgba), (ch) => ch.charCodeAt(0));
+  c.getContext(\"2d\").putImageData(new ImageData(pixels, width, height), 0, 0);
+  return c;
+}
+";
+
+/// A group that applies to the commands drawn inside it.
+enum Group<'a, L> {
+    /// A clip to a shape, under a transform.
+    Clip(Affine, BezPath),
+    /// A clip to text, under a transform.
+    TextClip(Affine, &'a RecordedTextLayout<L>, Point),
+    /// A blur, with a standard deviation in device pixels.
+    Blur(f64),
+}
+
+/// How an export writes out what the commands draw.
+trait Exporter<L> {
+    /// Start on a command, before any groups it opens or closes.
+    fn begin(&mut self, _command: &DrawCommand<L>) {}
+
+    fn open(&mut self, group: &Group<L>);
+
+    fn close(&mut self, group: &Group<L>);
+
+    /// Write a command under `transform`, ignoring those that don't draw.
+    fn draw(&mut self, transform: Affine, command: &DrawCommand<L>);
+}
+
+/// Walk `list`, opening and closing groups as clips and blurs come and go.
+fn export<L: TextLayout>(list: &DisplayList<L>, exporter: &mut impl Exporter<L>) {
+    let mut state = GraphicsState::default();
+    let mut groups: Vec<Group<L>> = Vec::new();
+    let mut clips = 0;
+    for command in list.commands() {
+        // a clip to text lasts as long as a clip to a shape would
+        let tracked = match command {
+            DrawCommand::ClipText { .. } => state.update(&DrawCommand::<L>::Clip(BezPath::new())),
+            _ => state.update(command),
+        };
+        if tracked.is_err() {
+            continue;
+        }
+        exporter.begin(command);
+        if let DrawCommand::EndBlur = command {
+            while let Some(group) = groups.pop() {
+                exporter.close(&group);
+                match group {
+                    Group::Blur(_) => break,
+                    _ => clips -= 1,
+                }
+            }
+        }
+        // clips only ever go from the top, above the last blur
+        while clips > state.clips.len() {
+            if let Some(group) = groups.pop() {
+                exporter.close(&group);
+            }
+            clips -= 1;
+        }
+        if state.clips.len() > clips {
+            let group = match command {
+                DrawCommand::ClipText { layout, pos } => {
+                    Group::TextClip(state.transform, layout, *pos)
+                }
+                _ => {
+                    let (transform, shape) = &state.clips[clips];
+                    Group::Clip(*transform, shape.clone())
+                }
+            };
+            exporter.open(&group);
+            groups.push(group);
+            clips += 1;
+        }
+        if let DrawCommand::BeginBlur(radius) = command {
+            let group = Group::Blur(radius * state.transform.determinant().abs().sqrt());
+            exporter.open(&group);
+            groups.push(group);
+        }
+        exporter.draw(state.transform, command);
+    }
+    while let Some(group) = groups.pop() {
+        exporter.close(&group);
+    }
+}
+
+/// A brush resolved for the shape it paints.
+enum Paint {
+    Color(Color),
+    Linear(FixedLinearGradient),
+    Radial(FixedRadialGradient),
+}
+
+fn paint(brush: &PaintBrush, bbox: impl FnOnce() -> Rect) -> Paint {
+    match brush {
+        PaintBrush::Color(color) => Paint::Color(color.clone()),
+        PaintBrush::Linear(linear) => Paint::Linear(linear.resolve(bbox())),
+        PaintBrush::Radial(radial) => Paint::Radial(radial.resolve(bbox())),
+        PaintBrush::Fixed(FixedGradient::Linear(linear)) => Paint::Linear(linear.clone()),
+        PaintBrush::Fixed(FixedGradient::Radial(radial)) => Paint::Radial(radial.clone()),
+        PaintBrush::Fixed(FixedGradient::Mesh(mesh)) => Paint::Color(mesh.fallback_color()),
+    }
+}
+
+/// The bounding box a brush is resolved against when it paints `command`.
+fn brush_bounds<L: TextLayout>(command: &DrawCommand<L>) -> Rect {
+    match command {
+        DrawCommand::Fill { shape, .. } | DrawCommand::Stroke { shape, .. } => shape.bounding_box(),
+        DrawCommand::Text { layout, pos, .. } => {
+            text_bounds(layout.layout(), *pos).unwrap_or(Rect::ZERO)
+        }
+        _ => Rect::ZERO,
+    }
+}
+
+/// The family of a layout's font, then its fallbacks.
+fn font_families<L>(layout: &RecordedTextLayout<L>) -> Vec<&str> {
+    let fallbacks = layout.font_fallbacks().iter().map(String::as_str);
+    std::iter::once(layout.font_family())
+        .chain(fallbacks)
+        .collect()
+}
+
+/// A `style` attribute setting the variable font axes of a layout's font,
+/// or nothing if it has none.
+fn font_variation_style<L>(layout: &RecordedTextLayout<L>) -> String {
+    let variations = layout.font_variations();
+    if variations.is_empty() {
+        return String::new();
+    }
+    let settings: Vec<String> = variations.iter().map(ToString::to_string).collect();
+    format!(
+        " style=\"font-variation-settings: {}\"",
+        xml_escape(&settings.join(", "))
+    )
+}
+
+/// Each line of `layout` drawn at `pos`, with the point its baseline
+/// starts at, which alignment may move along from `pos`.
+fn text_lines<L: TextLayout>(layout: &RecordedTextLayout<L>, pos: Point) -> Vec<(String, Point)> {
+    layout
+        .line_metrics()
+        .into_iter()
+        .filter_map(|line| {
+            let x = layout
+                .hit_test_text_position(line.range.start)
+                .map_or(0.0, |hit| hit.point.x);
+            // a line past the cut ends in an ellipsis standing for the rest
+            let mut range = line.range;
+            let cut = layout.truncated_at().filter(|&cut| range.end > cut);
+            if let Some(cut) = cut {
+                range.end = cut.max(range.start);
+            }
+            let text = layout.text().get(range)?;
+            let mut text = match layout.mask() {
+                Some(mask) => text.chars().map(|_| mask).collect(),
+                None => text.to_owned(),
+            };
+            if cut.is_some() {
+                text.push_str(TruncatedText::ELLIPSIS);
+            }
+            Some((text, Point::new(pos.x + x, pos.y + line.baseline)))
+        })
+        .collect()
+}
+
+/// The bytes of an image as straight RGBA.
+fn straight_rgba(image: &RecordedImage) -> Vec<u8> {
+    let pixels = image.pixels();
+    let unpremultiply = |r: u8, g: u8, b: u8, a: u8| {
+        if a == 0 {
+            return [0; 4];
+        }
+        let un = |c: u8| ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8;
+        [un(r), un(g), un(b), a]
+    };
+    let mut rgba = Vec::with_capacity(image.width() * image.height() * 4);
+    match image.format() {
+        ImageFormat::Rgb => {
+            for px in pixels.chunks_exact(3) {
+                rgba.extend_from_slice(&[px[0], px[1], px[2], 0xff]);
+            }
+        }
+        ImageFormat::RgbaSeparate => rgba.extend_from_slice(pixels),
+        ImageFormat::RgbaPremul => {
+            for px in pixels.chunks_exact(4) {
+                rgba.extend_from_slice(&unpremultiply(px[0], px[1], px[2], px[3]));
+            }
+        }
+        ImageFormat::BgraPremul => {
+            for px in pixels.chunks_exact(4) {
+                rgba.extend_from_slice(&unpremultiply(px[2], px[1], px[0], px[3]));
+            }
+        }
+        ImageFormat::Grayscale => {
+            for &gray in pixels {
+                rgba.extend_from_slice(&[gray, gray, gray, 0xff]);
+            }
+        }
+        ImageFormat::Alpha => {
+            for &alpha in pixels {
+                rgba.extend_from_slice(&[0, 0, 0, alpha]);
+            }
+        }
+        ImageFormat::_NonExhaustive => (),
+    }
+    rgba.resize(image.width() * image.height() * 4, 0);
+    rgba
+}
+
+/// Writes SVG elements.
+struct Svg {
+    out: String,
+    size: Size,
+    /// The number of ids given out, for naming the next one.
+    ids: usize,
+    /// The id of the definition of each image written, by its pixels.
+    images: HashMap<*const u8, String>,
+    pick_id: Option<u64>,
+    crisp: bool,
+}
+
+impl Svg {
+    fn new_id(&mut self, prefix: &str) -> String {
+        self.ids += 1;
+        format!("{}{}", prefix, self.ids)
+    }
+
+    /// The attributes every drawn element gets.
+    fn common(&self, transform: Affine) -> String {
+        let mut attrs = format!(" transform=\"{}\"", svg_matrix(transform));
+        if self.crisp {
+            attrs.push_str(" shape-rendering=\"crispEdges\"");
+        }
+        if let Some(id) = self.pick_id {
+            let _ = write!(attrs, " data-pick-id=\"{}\"", id);
+        }
+        attrs
+    }
+
+    /// Write any definition `paint` needs, and return its value and
+    /// opacity for a `fill` or `stroke` attribute.
+    fn paint(&mut self, paint: &Paint) -> (String, f64) {
+        match paint {
+            Paint::Color(color) => svg_color(color),
+            Paint::Linear(linear) => {
+                let id = self.new_id("gradient");
+                let _ = writeln!(
+                    self.out,
+                    "<linearGradient id=\"{}\" gradientUnits=\"userSpaceOnUse\" \
+                     x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\">",
+                    id, linear.start.x, linear.start.y, linear.end.x, linear.end.y,
+                );
+                self.stops(&linear.color_space.resolve_stops(&linear.stops));
+                self.out.push_str("</linearGradient>\n");
+                (format!("url(#{})", id), 1.0)
+            }
+            Paint::Radial(radial) => {
+                let id = self.new_id("gradient");
+                let origin = radial.center + radial.origin_offset;
+                let _ = writeln!(
+                    self.out,
+                    "<radialGradient id=\"{}\" gradientUnits=\"userSpaceOnUse\" \
+                     cx=\"{}\" cy=\"{}\" r=\"{}\" fx=\"{}\" fy=\"{}\">",
+                    id, radial.center.x, radial.center.y, radial.radius, origin.x, origin.y,
+                );
+                self.stops(&radial.color_space.resolve_stops(&radial.stops));
+                self.out.push_str("</radialGradient>\n");
+                (format!("url(#{})", id), 1.0)
+            }
+        }
+    }
+
+    fn stops(&mut self, stops: &[GradientStop]) {
+        for stop in stops {
+            let (color, opacity) = svg_color(&stop.color);
+            let _ = writeln!(
+                self.out,
+                "<stop offset=\"{}\" stop-color=\"{}\" stop-opacity=\"{}\"/>",
+                stop.pos, color, opacity,
+            );
+        }
+    }
+
+    /// Write `text` as a `<text>` element for each line.
+    fn text<L: TextLayout>(
+        &mut self,
+        transform: Affine,
+        layout: &RecordedTextLayout<L>,
+        pos: Point,
+        fill: &str,
+    ) {
+        let common = self.common(transform);
+        for (line, origin) in text_lines(layout, pos) {
+            let _ = writeln!(
+                self.out,
+                "<text x=\"{}\" y=\"{}\" font-family=\"{}\" font-size=\"{}\" \
+                 font-weight=\"{}\" font-style=\"{}\" font-stretch=\"{}\" \
+                 xml:space=\"preserve\"{}{}{}>{}</text>",
+                origin.x,
+                origin.y,
+                xml_escape(&font_families(layout).join(", ")),
+                layout.font_size(),
+                layout.font_weight().to_raw(),
+                layout.font_style().name(),
+                layout.font_stretch().name(),
+                font_variation_style(layout),
+                fill,
+                common,
+                xml_escape(&line),
+            );
+        }
+    }
+
+    /// The id of the definition of `image`, writing it if it's new.
+    fn image(&mut self, image: &RecordedImage) -> String {
+        let key = image.pixels().as_ptr();
+        if let Some(id) = self.images.get(&key) {
+            return id.clone();
+        }
+        let id = self.new_id("image");
+        let png = png(image.width(), image.height(), &straight_rgba(image));
+        let _ = writeln!(
+            self.out,
+            "<defs><image id=\"{}\" width=\"{}\" height=\"{}\" \
+             xlink:href=\"data:image/png;base64,{}\"/></defs>",
+            id,
+            image.width(),
+            image.height(),
+            base64(&png),
+        );
+        self.images.insert(key, id.clone());
+        id
+    }
+}
+
+impl<L: TextLayout> Exporter<L> for Svg {
+    fn open(&mut self, group: &Group<L>) {
+        match group {
+            Group::Clip(transform, shape) => {
+                let id = self.new_id("clip");
+                let _ = writeln!(
+                    self.out,
+                    "<clipPath id=\"{}\" clipPathUnits=\"userSpaceOnUse\">\
+                     <path d=\"{}\" transform=\"{}\"/></clipPath>",
+                    id,
+                    shape.to_svg(),
+                    svg_matrix(*transform),
+                );
+                let _ = writeln!(self.out, "<g clip-path=\"url(#{})\">", id);
+            }
+            Group::TextClip(transform, layout, pos) => {
+                let id = self.new_id("clip");
+                let _ = writeln!(
+                    self.out,
+                    "<clipPath id=\"{}\" clipPathUnits=\"userSpaceOnUse\">",
+                    id
+                );
+                // the clip path's text is only used for its outlines
+                let pick_id = self.pick_id.take();
+                self.text(*transform, layout, *pos, "");
+                self.pick_id = pick_id;
+                self.out.push_str("</clipPath>\n");
+                let _ = writeln!(self.out, "<g clip-path=\"url(#{})\">", id);
+            }
+            Group::Blur(std_dev) => {
+                let id = self.new_id("blur");
+                let (w, h) = (self.size.width, self.size.height);
+                // wide enough to blur in what's drawn just off the edges
+                let _ = writeln!(
+                    self.out,
+                    "<filter id=\"{}\" filterUnits=\"userSpaceOnUse\" \
+                     x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\">\
+                     <feGaussianBlur stdDeviation=\"{}\"/></filter>",
+                    id,
+                    -w,
+                    -h,
+                    3.0 * w,
+                    3.0 * h,
+                    std_dev,
+                );
+                let _ = writeln!(self.out, "<g filter=\"url(#{})\">", id);
+            }
+        }
+    }
+
+    fn close(&mut self, _group: &Group<L>) {
+        self.out.push_str("</g>\n");
+    }
+
+    fn draw(&mut self, transform: Affine, command: &DrawCommand<L>) {
+        match command {
+            DrawCommand::Clear(color) => {
+                let (fill, opacity) = svg_color(color);
+                let _ = writeln!(
+                    self.out,
+                    "<rect width=\"{}\" height=\"{}\" fill=\"{}\" fill-opacity=\"{}\"/>",
+                    self.size.width, self.size.height, fill, opacity,
+                );
+            }
+            DrawCommand::ClearRect(rect, color) => {
+                let (fill, opacity) = svg_color(color);
+                let common = self.common(transform);
+                let rect = rect.abs();
+                let _ = writeln!(
+                    self.out,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" \
+                     fill-opacity=\"{}\"{}/>",
+                    rect.x0,
+                    rect.y0,
+                    rect.width(),
+                    rect.height(),
+                    fill,
+                    opacity,
+                    common,
+                );
+            }
+            DrawCommand::Fill {
+                shape,
+                brush,
+                even_odd,
+            } => {
+                let (fill, opacity) = self.paint(&paint(brush, || brush_bounds(command)));
+                let common = self.common(transform);
+                let rule = if *even_odd { "evenodd" } else { "nonzero" };
+                let _ = writeln!(
+                    self.out,
+                    "<path d=\"{}\" fill=\"{}\" fill-opacity=\"{}\" fill-rule=\"{}\"{}/>",
+                    shape.to_svg(),
+                    fill,
+                    opacity,
+                    rule,
+                    common,
+                );
+            }
+            DrawCommand::Stroke {
+                shape,
+                brush,
+                width,
+                style,
+            } => {
+                let (stroke, opacity) = self.paint(&paint(brush, || brush_bounds(command)));
+                let common = self.common(transform);
+                let style = style.clone().unwrap_or_else(StrokeStyle::new);
+                let mut dashes = String::new();
+                if let Some((pattern, offset)) = style.dash_pattern() {
+                    let pattern: Vec<String> = pattern.iter().map(f64::to_string).collect();
+                    let _ = write!(
+                        dashes,
+                        " stroke-dasharray=\"{}\" stroke-dashoffset=\"{}\"",
+                        pattern.join(" "),
+                        offset,
+                    );
+                }
+                let _ = writeln!(
+                    self.out,
+                    "<path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-opacity=\"{}\" \
+                     stroke-width=\"{}\" stroke-linejoin=\"{}\" stroke-linecap=\"{}\" \
+                     stroke-miterlimit=\"{}\"{}{}/>",
+                    shape.to_svg(),
+                    stroke,
+                    opacity,
+                    width,
+                    line_join(&style),
+                    line_cap(&style),
+                    style.miter_limit.unwrap_or(10.0),
+                    dashes,
+                    common,
+                );
+            }
+            DrawCommand::Text {
+                layout, pos, brush, ..
+            } => {
+                let (fill, opacity) = self.paint(&paint(brush, || brush_bounds(command)));
+                let fill = format!(" fill=\"{}\" fill-opacity=\"{}\"", fill, opacity);
+                self.text(transform, layout, *pos, &fill);
+            }
+            DrawCommand::Image {
+                image,
+                src_rect,
+                dst_rect,
+                interp,
+                alpha,
+            } => {
+                if image.width() == 0 || image.height() == 0 {
+                    return;
+                }
+                let id = self.image(image);
+                let common = self.common(transform);
+                let rendering = match interp {
+                    InterpolationMode::NearestNeighbor => " image-rendering=\"pixelated\"",
+                    InterpolationMode::Bilinear => "",
+                };
+                let (src, dst) = (src_rect.abs(), dst_rect.abs());
+                let _ = writeln!(
+                    self.out,
+                    "<g opacity=\"{}\"{}><svg x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" \
+                     viewBox=\"{} {} {} {}\" preserveAspectRatio=\"none\">\
+                     <use xlink:href=\"#{}\"{}/></svg></g>",
+                    alpha,
+                    common,
+                    dst.x0,
+                    dst.y0,
+                    dst.width(),
+                    dst.height(),
+                    src.x0,
+                    src.y0,
+                    src.width(),
+                    src.height(),
+                    id,
+                    rendering,
+                );
+            }
+            DrawCommand::Antialiasing(antialiasing) => {
+                self.crisp = *antialiasing == Antialiasing::None;
+            }
+            DrawCommand::PickId(id) => self.pick_id = *id,
+            _ => (),
+        }
+    }
+}
+
+/// Writes the script of a canvas export.
+struct Canvas {
+    out: String,
+    /// The name of the variable holding each image written, by its pixels.
+    images: HashMap<*const u8, String>,
+}
+
+impl Canvas {
+    fn set_transform(&mut self, transform: Affine) {
+        let [a, b, c, d, e, f] = transform.as_coeffs();
+        let _ = writeln!(
+            self.out,
+            "ctx.setTransform({}, {}, {}, {}, {}, {});",
+            a, b, c, d, e, f
+        );
+    }
+
+    fn path(&mut self, shape: &BezPath) {
+        self.out.push_str("ctx.beginPath();\n");
+        for el in shape.elements() {
+            let _ = match el {
+                PathEl::MoveTo(p) => writeln!(self.out, "ctx.moveTo({}, {});", p.x, p.y),
+                PathEl::LineTo(p) => writeln!(self.out, "ctx.lineTo({}, {});", p.x, p.y),
+                PathEl::QuadTo(p1, p2) => writeln!(
+                    self.out,
+                    "ctx.quadraticCurveTo({}, {}, {}, {});",
+                    p1.x, p1.y, p2.x, p2.y
+                ),
+                PathEl::CurveTo(p1, p2, p3) => writeln!(
+                    self.out,
+                    "ctx.bezierCurveTo({}, {}, {}, {}, {}, {});",
+                    p1.x, p1.y, p2.x, p2.y, p3.x, p3.y
+                ),
+                PathEl::ClosePath => writeln!(self.out, "ctx.closePath();"),
+            };
+        }
+    }
+
+    /// Set `property` of the context to `paint`.
+    fn paint(&mut self, property: &str, paint: &Paint) {
+        match paint {
+            Paint::Color(color) => {
+                let _ = writeln!(self.out, "ctx.{} = \"{}\";", property, css_color(color));
+            }
+            Paint::Linear(linear) => {
+                let _ = writeln!(self.out, "ctx.{} = ((g) => {{", property);
+                self.stops(&linear.color_space.resolve_stops(&linear.stops));
+                let _ = writeln!(
+                    self.out,
+                    "  return g;\n}})(ctx.createLinearGradient({}, {}, {}, {}));",
+                    linear.start.x, linear.start.y, linear.end.x, linear.end.y,
+                );
+            }
+            Paint::Radial(radial) => {
+                let origin = radial.center + radial.origin_offset;
+                let _ = writeln!(self.out, "ctx.{} = ((g) => {{", property);
+                self.stops(&radial.color_space.resolve_stops(&radial.stops));
+                let _ = writeln!(
+                    self.out,
+                    "  return g;\n}})(ctx.createRadialGradient({}, {}, 0, {}, {}, {}));",
+                    origin.x, origin.y, radial.center.x, radial.center.y, radial.radius,
+                );
+            }
+        }
+    }
+
+    fn stops(&mut self, stops: &[GradientStop]) {
+        for stop in stops {
+            let _ = writeln!(
+                self.out,
+                "  g.addColorStop({}, \"{}\");",
+                stop.pos.clamp(0.0, 1.0),
+                css_color(&stop.color),
+            );
+        }
+    }
+
+    fn text<L: TextLayout>(&mut self, layout: &RecordedTextLayout<L>, pos: Point) {
+        let _ = writeln!(
+            self.out,
+            "ctx.font = {};",
+            js_string(&format!(
+                "{} {} {} {}px {}",
+                layout.font_style().name(),
+                layout.font_weight().to_raw(),
+                layout.font_stretch().name(),
+                layout.font_size(),
+                font_families(layout)
+                    .iter()
+                    .map(|family| format!("\"{}\"", family))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        );
+        for (line, origin) in text_lines(layout, pos) {
+            let _ = writeln!(
+                self.out,
+                "ctx.fillText({}, {}, {});",
+                js_string(&line),
+                origin.x,
+                origin.y
+            );
+        }
+    }
+
+    /// The name of the variable holding `image`, writing it if it's new.
+    fn image(&mut self, image: &RecordedImage) -> String {
+        let key = image.pixels().as_ptr();
+        if let Some(name) = self.images.get(&key) {
+            return name.clone();
+        }
+        let name = format!("image{}", self.images.len() + 1);
+        let _ = writeln!(
+            self.out,
+            "const {} = image({}, {}, \"{}\");",
+            name,
+            image.width(),
+            image.height(),
+            base64(&straight_rgba(image)),
+        );
+        self.images.insert(key, name.clone());
+        name
+    }
+
+    /// Draw the offscreen context being drawn into onto the one under it,
+    /// with `filter`.
+    fn composite(&mut self, filter: &str) {
+        let _ = writeln!(
+            self.out,
+            "{{\n  const drawn = ctx;\n  ctx = layers.pop();\n  ctx.save();\n  \
+             ctx.setTransform(1, 0, 0, 1, 0, 0);\n  ctx.filter = \"{}\";\n  \
+             ctx.drawImage(drawn.canvas, 0, 0);\n  ctx.restore();\n}}",
+            filter
+        );
+    }
+}
+
+impl<L: TextLayout> Exporter<L> for Canvas {
+    fn open(&mut self, group: &Group<L>) {
+        match group {
+            Group::Clip(transform, shape) => {
+                self.out.push_str("ctx.save();\n");
+                self.set_transform(*transform);
+                self.path(shape);
+                self.out.push_str("ctx.clip(\"nonzero\");\n");
+            }
+            Group::TextClip(..) | Group::Blur(_) => {
+                self.out.push_str("layers.push(ctx);\nctx = layer();\n");
+            }
+        }
+    }
+
+    fn close(&mut self, group: &Group<L>) {
+        match group {
+            Group::Clip(..) => self.out.push_str("ctx.restore();\n"),
+            Group::TextClip(transform, layout, pos) => {
+                // keep only what's under the text
+                self.set_transform(*transform);
+                self.out.push_str(
+                    "ctx.globalCompositeOperation = \"destination-in\";\nctx.fillStyle = \"#000\";\n",
+                );
+                self.text(layout, *pos);
+                self.composite("none");
+            }
+            Group::Blur(std_dev) => self.composite(&format!("blur({}px)", std_dev)),
+        }
+    }
+
+    fn begin(&mut self, command: &DrawCommand<L>) {
+        let _ = writeln!(self.out, "// {}", command_name(command));
+    }
+
+    fn draw(&mut self, transform: Affine, command: &DrawCommand<L>) {
+        match command {
+            DrawCommand::Clear(color) => {
+                let _ = writeln!(
+                    self.out,
+                    "ctx.setTransform(1, 0, 0, 1, 0, 0);\n\
+                     ctx.clearRect(0, 0, canvas.width, canvas.height);\n\
+                     ctx.fillStyle = \"{}\";\nctx.fillRect(0, 0, canvas.width, canvas.height);",
+                    css_color(color),
+                );
+            }
+            DrawCommand::ClearRect(rect, color) => {
+                self.set_transform(transform);
+                let rect = rect.abs();
+                let (x, y, w, h) = (rect.x0, rect.y0, rect.width(), rect.height());
+                let _ = writeln!(
+                    self.out,
+                    "ctx.clearRect({x}, {y}, {w}, {h});\nctx.fillStyle = \"{}\";\n\
+                     ctx.fillRect({x}, {y}, {w}, {h});",
+                    css_color(color),
+                    x = x,
+                    y = y,
+                    w = w,
+                    h = h,
+                );
+            }
+            DrawCommand::Fill {
+                shape,
+                brush,
+                even_odd,
+            } => {
+                self.set_transform(transform);
+                self.paint("fillStyle", &paint(brush, || brush_bounds(command)));
+                self.path(shape);
+                let rule = if *even_odd { "evenodd" } else { "nonzero" };
+                let _ = writeln!(self.out, "ctx.fill(\"{}\");", rule);
+            }
+            DrawCommand::Stroke {
+                shape,
+                brush,
+                width,
+                style,
+            } => {
+                self.set_transform(transform);
+                self.paint("strokeStyle", &paint(brush, || brush_bounds(command)));
+                let style = style.clone().unwrap_or_else(StrokeStyle::new);
+                let (pattern, offset) = style.dash_pattern().unwrap_or_default();
+                let pattern: Vec<String> = pattern.iter().map(f64::to_string).collect();
+                let _ = writeln!(
+                    self.out,
+                    "ctx.lineWidth = {};\nctx.lineJoin = \"{}\";\nctx.lineCap = \"{}\";\n\
+                     ctx.miterLimit = {};\nctx.setLineDash([{}]);\nctx.lineDashOffset = {};",
+                    width,
+                    line_join(&style),
+                    line_cap(&style),
+                    style.miter_limit.unwrap_or(10.0),
+                    pattern.join(", "),
+                    offset,
+                );
+                self.path(shape);
+                self.out.push_str("ctx.stroke();\n");
+            }
+            DrawCommand::Text {
+                layout, pos, brush, ..
+            } => {
+                self.set_transform(transform);
+                self.paint("fillStyle", &paint(brush, || brush_bounds(command)));
+                self.text(layout, *pos);
+            }
+            DrawCommand::Image {
+                image,
+                src_rect,
+                dst_rect,
+                interp,
+                alpha,
+            } => {
+                if image.width() == 0 || image.height() == 0 {
+                    return;
+                }
+                let name = self.image(image);
+                self.set_transform(transform);
+                let (src, dst) = (src_rect.abs(), dst_rect.abs());
+                let _ = writeln!(
+                    self.out,
+                    "ctx.imageSmoothingEnabled = {};\nctx.globalAlpha = {};\n\
+                     ctx.drawImage({}, {}, {}, {}, {}, {}, {}, {}, {});\nctx.globalAlpha = 1;",
+                    *interp == InterpolationMode::Bilinear,
+                    alpha,
+                    name,
+                    src.x0,
+                    src.y0,
+                    src.width(),
+                    src.height(),
+                    dst.x0,
+                    dst.y0,
+                    dst.width(),
+                    dst.height(),
+                );
+            }
+            _ => (),
+        }
+    }
+}
+
+/// A comment naming a command in a canvas export.
+fn command_name<L>(command: &DrawCommand<L>) -> String {
+    match command {
+        DrawCommand::Clear(_) => "clear".into(),
+        DrawCommand::ClearRect(..) => "clear_rect".into(),
+        DrawCommand::Fill { even_odd, .. } if *even_odd => "fill_even_odd".into(),
+        DrawCommand::Fill { .. } => "fill".into(),
+        DrawCommand::Stroke { .. } => "stroke".into(),
+        DrawCommand::Clip(_) => "clip".into(),
+        DrawCommand::ClipText { .. } => "clip_text".into(),
+        DrawCommand::PushClip(_) => "push_clip".into(),
+        DrawCommand::PopClip => "pop_clip".into(),
+        DrawCommand::ResetClip => "reset_clip".into(),
+        DrawCommand::Text { .. } => "draw_text".into(),
+        DrawCommand::GlyphPositioning(positioning) => {
+            format!("set_glyph_positioning({:?})", positioning)
+        }
+        DrawCommand::ImagePositioning(positioning) => {
+            format!("set_image_positioning({:?})", positioning)
+        }
+        DrawCommand::Antialiasing(antialiasing) => {
+            format!("set_antialiasing({:?})", antialiasing)
+        }
+        DrawCommand::PickId(id) => format!("set_pick_id({:?})", id),
+        DrawCommand::Save => "save".into(),
+        DrawCommand::Restore => "restore".into(),
+        DrawCommand::Transform(transform) => format!("transform({:?})", transform.as_coeffs()),
+        DrawCommand::Image { .. } => "draw_image".into(),
+        DrawCommand::BeginBlur(radius) => format!("with_blur({})", radius),
+        DrawCommand::EndBlur => "end of with_blur".into(),
+    }
+}
+
+fn line_join(style: &StrokeStyle) -> &'static str {
+    match style.line_join.unwrap_or(LineJoin::Miter) {
+        LineJoin::Miter => "miter",
+        LineJoin::Round => "round",
+        LineJoin::Bevel => "bevel",
+    }
+}
+
+fn line_cap(style: &StrokeStyle) -> &'static str {
+    match style.line_cap.unwrap_or(LineCap::Butt) {
+        LineCap::Butt => "butt",
+        LineCap::Round => "round",
+        LineCap::Square => "square",
+    }
+}
+
+fn svg_matrix(transform: Affine) -> String {
+    let [a, b, c, d, e, f] = transform.as_coeffs();
+    format!("matrix({} {} {} {} {} {})", a, b, c, d, e, f)
+}
+
+/// A color as an SVG color and opacity.
+fn svg_color(color: &Color) -> (String, f64) {
+    let rgba = color.as_rgba_u32();
+    (format!("#{:06x}", rgba >> 8), (rgba & 0xff) as f64 / 255.0)
+}
+
+fn css_color(color: &Color) -> String {
+    let rgba = color.as_rgba_u32();
+    format!(
+        "rgba({}, {}, {}, {})",
+        rgba >> 24,
+        (rgba >> 16) & 0xff,
+        (rgba >> 8) & 0xff,
+        (rgba & 0xff) as f64 / 255.0,
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `text` as a quoted JavaScript string, safe to put inside a `<script>`.
+fn js_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            // keeps `</script>` from ending the script
+            '<' => quoted.push_str("\\u003c"),
+            '\u{2028}' | '\u{2029}' => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+pub(crate) fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (chunk.get(1).copied().unwrap_or(0) as u32) << 8
+            | chunk.get(2).copied().unwrap_or(0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Encode straight RGBA pixels as a PNG, uncompressed.
+fn png(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGBA, no interlacing
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    png_chunk(&mut png, b"IHDR", &header);
+
+    // each row starts with the filter it uses, which is none
+    let mut rows = Vec::with_capacity(height * (width * 4 + 1));
+    for row in rgba.chunks_exact(width * 4) {
+        rows.push(0);
+        rows.extend_from_slice(row);
+    }
+    // a zlib stream of stored deflate blocks
+    let mut data = vec![0x78, 0x01];
+    let mut blocks = rows.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        data.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        data.push(last as u8);
+        data.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        data.extend_from_slice(block);
+    }
+    data.extend_from_slice(&adler32(&rows).to_be_bytes());
+    png_chunk(&mut png, b"IDAT", &data);
+
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
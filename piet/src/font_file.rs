@@ -0,0 +1,159 @@
+//! Reading font files, for loading fonts from memory.
+
+use crate::{FontStyle, FontWeight};
+
+/// The family and face of the font in a TrueType or OpenType file, as
+/// [`Text::load_font`] registers it.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{FontFileInfo, FontStyle, FontWeight};
+///
+/// # fn font_file(family: &str) -> Vec<u8> {
+/// #     let name: Vec<u8> = family.encode_utf16().flat_map(u16::to_be_bytes).collect();
+/// #     let mut table = vec![0, 0, 0, 1, 0, 18];
+/// #     // Windows, Unicode BMP, US English, family name
+/// #     for field in [3, 1, 0x409, 1, name.len() as u16, 0] {
+/// #         table.extend(field.to_be_bytes());
+/// #     }
+/// #     table.extend(name);
+/// #     let mut file = vec![0, 1, 0, 0, 0, 1, 0, 16, 0, 0, 0, 0];
+/// #     file.extend(b"name");
+/// #     file.extend([0; 4]);
+/// #     file.extend(28u32.to_be_bytes());
+/// #     file.extend((table.len() as u32).to_be_bytes());
+/// #     file.extend(table);
+/// #     file
+/// # }
+/// let data = font_file("Inter");
+/// let info = FontFileInfo::read(&data).unwrap();
+/// assert_eq!(info.family, "Inter");
+/// // without an OS/2 table, the face is taken to be the regular one
+/// assert_eq!(info.weight, FontWeight::NORMAL);
+/// assert_eq!(info.style, FontStyle::Normal);
+///
+/// assert!(FontFileInfo::read(b"GIF89a").is_none());
+/// ```
+///
+/// [`Text::load_font`]: trait.Text.html#tymethod.load_font
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFileInfo {
+    /// The family the face belongs to, which can have faces of other weights
+    /// and styles.
+    pub family: String,
+    pub weight: FontWeight,
+    pub style: FontStyle,
+}
+
+impl FontFileInfo {
+    /// Read the font in `data`, or the first one if it's a collection.
+    ///
+    /// Returns `None` if `data` isn't a font, or has no family name. The
+    /// glyphs aren't checked; backends find out whether they're usable when
+    /// they load them.
+    pub fn read(data: &[u8]) -> Option<FontFileInfo> {
+        let font = match data.get(0..4)? {
+            b"ttcf" => read_u32(data, 12)? as usize,
+            [0, 1, 0, 0] | b"OTTO" | b"true" => 0,
+            _ => return None,
+        };
+        let family = family_name(table(data, font, b"name")?)?;
+        let (weight, style) = match table(data, font, b"OS/2") {
+            Some(os2) => {
+                let weight = FontWeight::new(read_u16(os2, 4)?);
+                let selection = read_u16(os2, 62)?;
+                let style = if selection & 1 != 0 {
+                    FontStyle::Italic
+                } else if selection & 1 << 9 != 0 {
+                    FontStyle::Oblique
+                } else {
+                    FontStyle::Normal
+                };
+                (weight, style)
+            }
+            None => (FontWeight::NORMAL, FontStyle::Normal),
+        };
+        Some(FontFileInfo {
+            family,
+            weight,
+            style,
+        })
+    }
+}
+
+/// The table with `tag` of the font starting at `font` in `data`.
+fn table<'a>(data: &'a [u8], font: usize, tag: &[u8]) -> Option<&'a [u8]> {
+    let count = read_u16(data, font + 4)? as usize;
+    (0..count).find_map(|i| {
+        let record = font + 12 + i * 16;
+        if data.get(record..record + 4)? != tag {
+            return None;
+        }
+        let offset = read_u32(data, record + 8)? as usize;
+        let length = read_u32(data, record + 12)? as usize;
+        data.get(offset..offset.checked_add(length)?)
+    })
+}
+
+/// The family name in a naming table: the typographic family, which groups
+/// more than four faces, or else the family.
+///
+/// Names for Windows, in US English first, are preferred to those for
+/// other platforms, as they're the ones every font has.
+fn family_name(name: &[u8]) -> Option<String> {
+    let count = read_u16(name, 2)? as usize;
+    let strings = read_u16(name, 4)? as usize;
+    let mut best: Option<(u16, String)> = None;
+    for i in 0..count {
+        let record = 6 + i * 12;
+        let field = |n: usize| read_u16(name, record + n * 2);
+        let (platform, encoding, language, id) = (field(0)?, field(1)?, field(2)?, field(3)?);
+        let id_rank = match id {
+            16 => 0,
+            1 => 4,
+            _ => continue,
+        };
+        let platform_rank = match (platform, encoding) {
+            (3, 0) | (3, 1) | (3, 10) if language == 0x409 => 0,
+            (3, 0) | (3, 1) | (3, 10) => 1,
+            (0, _) => 2,
+            (1, 0) => 3,
+            _ => continue,
+        };
+        let rank = id_rank + platform_rank;
+        if best.as_ref().is_some_and(|(best, _)| *best <= rank) {
+            continue;
+        }
+        let start = strings + field(5)? as usize;
+        let bytes = match name.get(start..start + field(4)? as usize) {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+        let string: String = if platform == 1 {
+            // Mac Roman; the family names of fonts in use are ASCII
+            bytes.iter().map(|&b| char::from(b)).collect()
+        } else {
+            let units = bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]));
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        };
+        if !string.is_empty() {
+            best = Some((rank, string));
+        }
+    }
+    best.map(|(_, family)| family)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
@@ -0,0 +1,133 @@
+//! Buffers for the transient data of a frame, kept for the next one.
+
+/// A pool of buffers, for backends to collect the transient data of a draw
+/// call into, such as glyph positions or a path to clip with, without
+/// allocating each time.
+///
+/// A backend [`collect`]s into a buffer from the arena and [`give_back`]s
+/// it when the call is done with it, or when a clip that holds it ends.
+/// Buffers keep their capacity, so once a frame's longest run of glyphs has
+/// been seen, the frames after it allocate nothing; each time a buffer has
+/// to grow is counted in [`allocations`].
+///
+/// Backends [`reset`] the arena from `finish`, at the end of each frame,
+/// which frees buffers far larger than anything the frame needed. A frame
+/// with one unusually long run of text holds on to its buffer only until
+/// the next frame that collects anything ends.
+///
+/// # Examples
+///
+/// ```
+/// use piet::FrameArena;
+///
+/// let mut arena = FrameArena::new();
+/// let doubled = arena.collect((0..100).map(|i| i * 2));
+/// assert_eq!(doubled[50], 100);
+/// arena.give_back(doubled);
+/// let grown = arena.allocations();
+/// // shorter runs reuse the buffer
+/// let squares = arena.collect((0..10).map(|i| i * i));
+/// assert_eq!(squares.len(), 10);
+/// arena.give_back(squares);
+/// assert_eq!(arena.allocations(), grown);
+///
+/// // the next frame only needs short runs, so the long buffer is freed
+/// arena.reset();
+/// let short = arena.collect(0..10);
+/// arena.give_back(short);
+/// arena.reset();
+/// assert!(arena.is_empty());
+/// ```
+///
+/// [`collect`]: #method.collect
+/// [`give_back`]: #method.give_back
+/// [`allocations`]: #method.allocations
+/// [`reset`]: #method.reset
+#[derive(Debug, Clone)]
+pub struct FrameArena<T> {
+    free: Vec<Vec<T>>,
+    /// The longest buffer collected since the last reset.
+    longest: usize,
+    allocations: usize,
+}
+
+/// Buffers no more than this many times longer than a frame needed are
+/// kept when the frame ends.
+const SLACK: usize = 4;
+
+impl<T> FrameArena<T> {
+    /// An empty arena, which allocates when the first buffer is collected.
+    pub fn new() -> FrameArena<T> {
+        FrameArena {
+            free: Vec::new(),
+            longest: 0,
+            allocations: 0,
+        }
+    }
+
+    /// Take a buffer from the arena and fill it with `items`.
+    ///
+    /// The buffer is returned by value, so it can be kept for as long as
+    /// it's needed; give it back with [`give_back`] for the next call to
+    /// use. A buffer that isn't given back is freed when it's dropped.
+    ///
+    /// [`give_back`]: #method.give_back
+    pub fn collect(&mut self, items: impl IntoIterator<Item = T>) -> Vec<T> {
+        let mut buf = self.free.pop().unwrap_or_default();
+        let capacity = buf.capacity();
+        buf.extend(items);
+        if buf.capacity() != capacity {
+            self.allocations += 1;
+        }
+        self.longest = self.longest.max(buf.len());
+        buf
+    }
+
+    /// Return a buffer to the arena, for the next call to reuse.
+    pub fn give_back(&mut self, mut buf: Vec<T>) {
+        buf.clear();
+        self.free.push(buf);
+    }
+
+    /// End the frame, freeing the buffers more than a few times longer
+    /// than the longest one it collected. A frame that collected nothing
+    /// frees nothing.
+    ///
+    /// Buffers taken and not yet given back are kept when they are.
+    pub fn reset(&mut self) {
+        if self.longest == 0 {
+            return;
+        }
+        let limit = self.longest.saturating_mul(SLACK);
+        self.free.retain(|buf| buf.capacity() <= limit);
+        self.longest = 0;
+    }
+
+    /// Free all the buffers, for the next calls to allocate again.
+    ///
+    /// The count of allocations is kept.
+    pub fn trim(&mut self) {
+        self.free.clear();
+        self.longest = 0;
+    }
+
+    /// The number of times a buffer has had to grow, or been made.
+    pub fn allocations(&self) -> usize {
+        self.allocations
+    }
+
+    /// The number of free buffers.
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.free.is_empty()
+    }
+}
+
+impl<T> Default for FrameArena<T> {
+    fn default() -> Self {
+        FrameArena::new()
+    }
+}
@@ -0,0 +1,684 @@
+//! Geometry helpers built on kurbo.
+
+use std::cmp::Ordering;
+use std::ops::Range;
+use std::slice;
+
+use kurbo::{
+    Affine, BezPath, Line, ParamCurve, ParamCurveArclen, ParamCurveDeriv, ParamCurveNearest,
+    PathEl, PathSeg, Point, Rect, Shape, Vec2,
+};
+
+use crate::{new_error, ClusterMetric, Error, ErrorKind, ImagePositioning};
+
+/// Snap a shape's points to the device pixel grid, so that a stroke
+/// `device_stroke_width` device pixels wide covers whole pixels.
+///
+/// `transform` maps the shape's coordinates to device pixels, as returned by
+/// [`current_transform`]. Odd widths are centered on pixel centers and even
+/// widths on pixel edges. The result is in the shape's own coordinates, ready
+/// to be stroked in place of the original.
+///
+/// Curve control points are snapped too, which moves curves by at most half
+/// a pixel. If `transform` isn't invertible, the shape is returned unsnapped.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Affine, Line, PathEl, Point};
+///
+/// let line = Line::new((10.3, 2.2), (10.3, 8.2));
+/// let snapped = piet::snap_path_to_pixels(line, Affine::default(), 1.0);
+/// match snapped.elements()[0] {
+///     PathEl::MoveTo(p) => assert_eq!(p, Point::new(10.5, 2.5)),
+///     _ => unreachable!(),
+/// }
+/// ```
+///
+/// [`current_transform`]: trait.RenderContext.html#tymethod.current_transform
+pub fn snap_path_to_pixels(
+    shape: impl Shape,
+    transform: Affine,
+    device_stroke_width: f64,
+) -> BezPath {
+    let path = shape.into_bez_path(1e-3);
+    if transform.determinant() == 0.0 {
+        return path;
+    }
+    let inverse = transform.inverse();
+    let offset = if device_stroke_width.round() as i64 % 2 == 1 {
+        0.5
+    } else {
+        0.0
+    };
+    let snap = |p: Point| {
+        let p = transform * p;
+        let snapped = Point::new(
+            (p.x - offset).round() + offset,
+            (p.y - offset).round() + offset,
+        );
+        inverse * snapped
+    };
+    let mut result = BezPath::new();
+    for el in path.elements() {
+        result.push(match *el {
+            PathEl::MoveTo(p) => PathEl::MoveTo(snap(p)),
+            PathEl::LineTo(p) => PathEl::LineTo(snap(p)),
+            PathEl::QuadTo(p1, p2) => PathEl::QuadTo(snap(p1), snap(p2)),
+            PathEl::CurveTo(p1, p2, p3) => PathEl::CurveTo(snap(p1), snap(p2), snap(p3)),
+            PathEl::ClosePath => PathEl::ClosePath,
+        });
+    }
+    result
+}
+
+/// Snap the edges of a rectangle to the device pixel grid.
+///
+/// `transform` maps the rectangle to device pixels, as returned by
+/// [`current_transform`]. Edges are only snapped when the transform keeps
+/// them axis-aligned, that is for scales and translations, possibly with
+/// quarter turns. Otherwise, or if snapping would leave the rectangle
+/// empty, it is returned unchanged. Each edge stays on the side it was on,
+/// so a rectangle with `x0 > x1` or `y0 > y1`, which mirrors an image drawn
+/// into it, stays mirrored.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Affine, Rect};
+///
+/// let rect = Rect::new(10.3, 4.6, 42.2, 20.5);
+/// let snapped = piet::snap_rect_to_pixels(rect, Affine::scale(2.0));
+/// let edges = (snapped.x0, snapped.y0, snapped.x1, snapped.y1);
+/// assert_eq!(edges, (10.5, 4.5, 42.0, 20.5));
+///
+/// // mirrored left to right
+/// let rect = Rect::new(42.2, 4.6, 10.3, 20.5);
+/// let snapped = piet::snap_rect_to_pixels(rect, Affine::scale(2.0));
+/// let edges = (snapped.x0, snapped.y0, snapped.x1, snapped.y1);
+/// assert_eq!(edges, (42.0, 4.5, 10.5, 20.5));
+/// ```
+///
+/// [`current_transform`]: trait.RenderContext.html#tymethod.current_transform
+pub fn snap_rect_to_pixels(rect: Rect, transform: Affine) -> Rect {
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    let axis_aligned = (b == 0.0 && c == 0.0) || (a == 0.0 && d == 0.0);
+    if !axis_aligned || transform.determinant() == 0.0 {
+        return rect;
+    }
+    let snap = |p: Point| {
+        let p = transform * p;
+        Point::new(p.x.round(), p.y.round())
+    };
+    let (p0, p1) = (snap(rect.origin()), snap(Point::new(rect.x1, rect.y1)));
+    if p0.x == p1.x || p0.y == p1.y {
+        return rect;
+    }
+    let inverse = transform.inverse();
+    // the corners map back to the ones they came from, keeping the edges'
+    // order
+    let (p0, p1) = (inverse * p0, inverse * p1);
+    Rect::new(p0.x, p0.y, p1.x, p1.y)
+}
+
+/// Where to draw an image given `rect`, as [`ImagePositioning`] describes,
+/// for backends.
+///
+/// `transform` maps the rectangle to device pixels, as returned by
+/// [`current_transform`].
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Affine, Rect};
+/// use piet::ImagePositioning;
+///
+/// let rect = Rect::new(0.4, 0.4, 10.4, 10.4);
+/// let exact = piet::position_image(rect, ImagePositioning::Exact, Affine::default());
+/// assert_eq!(exact.x0, 0.4);
+/// let snapped = piet::position_image(rect, ImagePositioning::PixelSnapped, Affine::default());
+/// assert_eq!(snapped.x0, 0.0);
+/// ```
+///
+/// [`ImagePositioning`]: enum.ImagePositioning.html
+/// [`current_transform`]: trait.RenderContext.html#tymethod.current_transform
+pub fn position_image(rect: Rect, positioning: ImagePositioning, transform: Affine) -> Rect {
+    match positioning {
+        ImagePositioning::PixelSnapped => snap_rect_to_pixels(rect, transform),
+        ImagePositioning::Exact => rect,
+    }
+}
+
+/// Check that a transform can be drawn under, for backends: one with a NaN
+/// or infinite coefficient is [`ErrorKind::InvalidInput`].
+///
+/// Backends check the transform [`transform`] would leave current, which
+/// can overflow even when the one passed in is finite.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::Affine;
+///
+/// assert!(piet::check_transform(Affine::scale(0.0)).is_ok());
+/// assert!(piet::check_transform(Affine::scale(f64::NAN)).is_err());
+/// let huge = Affine::scale(1e200);
+/// assert!(piet::check_transform(huge * huge).is_err());
+/// ```
+///
+/// [`ErrorKind::InvalidInput`]: enum.ErrorKind.html#variant.InvalidInput
+/// [`transform`]: trait.RenderContext.html#tymethod.transform
+pub fn check_transform(transform: Affine) -> Result<(), Error> {
+    if transform.as_coeffs().iter().all(|c| c.is_finite()) {
+        Ok(())
+    } else {
+        Err(new_error(ErrorKind::InvalidInput))
+    }
+}
+
+/// The tolerance contexts start with, in device pixels; see
+/// [`set_tolerance`].
+///
+/// [`set_tolerance`]: trait.RenderContext.html#tymethod.set_tolerance
+pub const DEFAULT_TOLERANCE: f64 = 1e-3;
+
+/// For backends: the tolerance in user space that keeps curves within
+/// `tolerance` device pixels under `transform`.
+///
+/// The tolerance shrinks by the largest scale of the transform, so curves
+/// stay as close in the direction they're stretched most. Under a
+/// degenerate transform, nothing is visible, and the tolerance is returned
+/// as it is.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::Affine;
+///
+/// let tolerance = piet::user_tolerance(0.1, Affine::new([4.0, 0.0, 0.0, 2.0, 0.0, 0.0]));
+/// assert_eq!(tolerance, 0.025);
+/// ```
+pub fn user_tolerance(tolerance: f64, transform: Affine) -> f64 {
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    // the largest singular value of the linear part
+    let sum = a * a + b * b + c * c + d * d;
+    let det = a * d - b * c;
+    let scale = ((sum + (sum * sum - 4.0 * det * det).max(0.0).sqrt()) / 2.0).sqrt();
+    if scale > 0.0 && scale.is_finite() {
+        tolerance / scale
+    } else {
+        tolerance
+    }
+}
+
+/// The smallest tolerance [`flattening_tolerance`] allows, as a fraction
+/// of the size of the shape.
+///
+/// [`flattening_tolerance`]: fn.flattening_tolerance.html
+const MIN_RELATIVE_TOLERANCE: f64 = 1e-9;
+
+/// For backends: `tolerance`, loosened for a shape so large that flattening
+/// it to `tolerance` would make an unbounded number of elements.
+///
+/// Circles, ellipses and rounded rectangles are made of more arcs the
+/// larger they are for the tolerance, so a circle with a radius of 1e300
+/// would take more memory than there is. The tolerance is kept to at least
+/// a billionth of the size of the shape, which is a few dozen arcs for a
+/// circle. A shape of infinite or NaN size, which can't be drawn, gets an
+/// infinite tolerance. Paths and rectangles aren't flattened, and keep
+/// `tolerance`.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Circle, Shape};
+///
+/// let huge = Circle::new((0.0, 0.0), 1e300);
+/// let tolerance = piet::flattening_tolerance(0.1, &huge);
+/// assert!(huge.to_bez_path(tolerance).count() < 100);
+/// let small = Circle::new((0.0, 0.0), 10.0);
+/// assert_eq!(piet::flattening_tolerance(0.1, &small), 0.1);
+/// let endless = Circle::new((0.0, f64::NAN), f64::INFINITY);
+/// assert_eq!(piet::flattening_tolerance(0.1, &endless), f64::INFINITY);
+/// ```
+pub fn flattening_tolerance(tolerance: f64, shape: &impl Shape) -> f64 {
+    if shape.as_path_slice().is_some() || shape.as_rect().is_some() {
+        return tolerance;
+    }
+    let size = shape.bounding_box().size();
+    if !(size.width.is_finite() && size.height.is_finite()) {
+        // nothing of the shape can be drawn, so it's made of as few
+        // elements as can be
+        return f64::INFINITY;
+    }
+    tolerance.max(size.width.max(size.height) * MIN_RELATIVE_TOLERANCE)
+}
+
+/// The elements of `shape`, for backends to stream into the platform's path
+/// without copying them.
+///
+/// Rectangles and path slices are iterated directly, without going through
+/// `to_bez_path`, whose iterator is boxed for a `&dyn DynShape`. Other
+/// shapes are flattened at `tolerance`, or coarser as
+/// [`flattening_tolerance`] allows.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{PathEl, Point, Rect};
+/// use piet::{shape_elements, DynShape};
+///
+/// let rect = Rect::new(0.0, 0.0, 4.0, 2.0);
+/// let shape: &dyn DynShape = &rect;
+/// let corners: Vec<Point> = shape_elements(&shape, 1e-3)
+///     .filter_map(|el| match el {
+///         PathEl::MoveTo(p) | PathEl::LineTo(p) => Some(p),
+///         _ => None,
+///     })
+///     .collect();
+/// assert_eq!(corners[2], Point::new(4.0, 2.0));
+/// assert_eq!(shape_elements(&shape, 1e-3).count(), 5);
+/// ```
+///
+/// [`flattening_tolerance`]: fn.flattening_tolerance.html
+pub fn shape_elements<S: Shape>(shape: &S, tolerance: f64) -> ShapeElements<'_, S::BezPathIter> {
+    let inner = if let Some(rect) = shape.as_rect() {
+        Elements::Rect(rect, 0)
+    } else if let Some(elements) = shape.as_path_slice() {
+        Elements::Slice(elements.iter())
+    } else {
+        let tolerance = flattening_tolerance(tolerance, shape);
+        Elements::Path(shape.to_bez_path(tolerance))
+    };
+    ShapeElements { inner }
+}
+
+/// The elements of a shape, from [`shape_elements`].
+///
+/// [`shape_elements`]: fn.shape_elements.html
+pub struct ShapeElements<'a, I> {
+    inner: Elements<'a, I>,
+}
+
+enum Elements<'a, I> {
+    /// A rectangle, with the index of the next of its five elements.
+    Rect(Rect, usize),
+    Slice(slice::Iter<'a, PathEl>),
+    Path(I),
+}
+
+impl<'a, I: Iterator<Item = PathEl>> Iterator for ShapeElements<'a, I> {
+    type Item = PathEl;
+
+    fn next(&mut self) -> Option<PathEl> {
+        match &mut self.inner {
+            Elements::Rect(rect, i) => {
+                let el = match *i {
+                    0 => PathEl::MoveTo(Point::new(rect.x0, rect.y0)),
+                    1 => PathEl::LineTo(Point::new(rect.x1, rect.y0)),
+                    2 => PathEl::LineTo(Point::new(rect.x1, rect.y1)),
+                    3 => PathEl::LineTo(Point::new(rect.x0, rect.y1)),
+                    4 => PathEl::ClosePath,
+                    _ => return None,
+                };
+                *i += 1;
+                Some(el)
+            }
+            Elements::Slice(elements) => elements.next().copied(),
+            Elements::Path(elements) => elements.next(),
+        }
+    }
+}
+
+/// A place on a path: the index of a segment, as counted by
+/// `BezPath::segments`, and the curve parameter along it, from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathPosition {
+    pub segment: usize,
+    pub t: f64,
+}
+
+/// The point of a path nearest to another point, from [`nearest_point`].
+///
+/// [`nearest_point`]: fn.nearest_point.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NearestPoint {
+    pub position: PathPosition,
+    pub point: Point,
+    /// The distance from the point searched from.
+    pub distance: f64,
+}
+
+/// A point where two paths cross, from [`path_intersections`].
+///
+/// [`path_intersections`]: fn.path_intersections.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathIntersection {
+    pub point: Point,
+    /// Where the point is on the first path.
+    pub a: PathPosition,
+    /// Where the point is on the second path.
+    pub b: PathPosition,
+}
+
+/// The point on the outline of `shape` nearest to `point`, for snapping to
+/// a path or picking the one under the pointer.
+///
+/// Shapes other than paths are converted to one with `accuracy` as the
+/// tolerance, which is also the accuracy of the search along curves.
+/// Returns `None` if the shape has no segments.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Circle, Point};
+///
+/// let circle = Circle::new((50.0, 50.0), 10.0);
+/// let nearest = piet::nearest_point(circle, Point::new(80.0, 50.0), 1e-3).unwrap();
+/// assert!((nearest.point - Point::new(60.0, 50.0)).hypot() < 1e-2);
+/// assert!((nearest.distance - 20.0).abs() < 1e-2);
+/// ```
+pub fn nearest_point(shape: impl Shape, point: Point, accuracy: f64) -> Option<NearestPoint> {
+    let path = shape.into_bez_path(accuracy);
+    let mut best: Option<(PathPosition, PathSeg, f64)> = None;
+    for (segment, seg) in path.segments().enumerate() {
+        let (t, distance2) = seg.nearest(point, accuracy);
+        if best.is_none_or(|(_, _, best)| distance2 < best) {
+            best = Some((PathPosition { segment, t }, seg, distance2));
+        }
+    }
+    best.map(|(position, seg, distance2)| NearestPoint {
+        position,
+        point: seg.eval(position.t),
+        distance: distance2.sqrt(),
+    })
+}
+
+/// The points where the outlines of two shapes cross, ordered along the
+/// first.
+///
+/// Points are found to within `accuracy`, which is also the tolerance for
+/// converting shapes other than paths. Crossings closer together than that
+/// are reported once, so a crossing at the joint of two segments isn't
+/// repeated. Where the outlines run along each other rather than cross,
+/// the points found are arbitrary.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Circle, Line, Point};
+///
+/// let circle = Circle::new((0.0, 0.0), 10.0);
+/// let line = Line::new((-20.0, 0.0), (20.0, 0.0));
+/// let crossings = piet::path_intersections(line, circle, 1e-6);
+/// assert_eq!(crossings.len(), 2);
+/// assert!((crossings[0].point - Point::new(-10.0, 0.0)).hypot() < 1e-3);
+/// assert!((crossings[0].a.t - 0.25).abs() < 1e-4);
+/// ```
+pub fn path_intersections(a: impl Shape, b: impl Shape, accuracy: f64) -> Vec<PathIntersection> {
+    let a = a.into_bez_path(accuracy);
+    let b = b.into_bez_path(accuracy);
+    let b_segs: Vec<(PathSeg, Rect)> = b.segments().map(|seg| (seg, hull_box(seg))).collect();
+    let mut result: Vec<PathIntersection> = Vec::new();
+    for (i, seg_a) in a.segments().enumerate() {
+        let box_a = hull_box(seg_a);
+        for (j, &(seg_b, box_b)) in b_segs.iter().enumerate() {
+            if !overlaps(box_a, box_b) {
+                continue;
+            }
+            for (t_a, t_b) in segment_intersections(seg_a, seg_b, accuracy) {
+                let point = seg_a.eval(t_a);
+                if result
+                    .iter()
+                    .any(|found| (found.point - point).hypot() <= accuracy)
+                {
+                    continue;
+                }
+                result.push(PathIntersection {
+                    point,
+                    a: PathPosition { segment: i, t: t_a },
+                    b: PathPosition { segment: j, t: t_b },
+                });
+            }
+        }
+    }
+    result.sort_by(|x, y| {
+        let key = |found: &PathIntersection| (found.a.segment, found.a.t);
+        key(x).partial_cmp(&key(y)).unwrap_or(Ordering::Equal)
+    });
+    result
+}
+
+/// The curve parameters where two segments cross, ordered along `a`.
+///
+/// This is what [`path_intersections`] finds for each pair of segments; see
+/// there for how `accuracy` is used.
+///
+/// [`path_intersections`]: fn.path_intersections.html
+pub fn segment_intersections(a: PathSeg, b: PathSeg, accuracy: f64) -> Vec<(f64, f64)> {
+    // no accuracy would subdivide to the depth limit everywhere
+    let accuracy = accuracy.abs().max(1e-12);
+    let mut found = Vec::new();
+    intersect_pieces((a, 0.0..1.0), (b, 0.0..1.0), accuracy, 0, &mut found);
+    found.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(Ordering::Equal));
+    let mut result: Vec<(f64, f64)> = Vec::new();
+    for (t_a, t_b) in found {
+        let point = a.eval(t_a);
+        if !result
+            .iter()
+            .any(|&(t, _)| (a.eval(t) - point).hypot() <= accuracy)
+        {
+            result.push((t_a, t_b));
+        }
+    }
+    result
+}
+
+/// The transforms that bend text along the outline of `shape`, for
+/// [`draw_glyph_run`], given the layout's [`cluster_metrics`].
+///
+/// Each cluster is moved to the point as far along the outline as its
+/// center is from the left of the layout, and turned to follow the
+/// outline there; later lines follow it at their distance below the first
+/// baseline. Clusters past either end of the outline carry on in a straight
+/// line. Shapes other than paths are converted to one with `accuracy` as
+/// the tolerance, which is also the accuracy of the lengths along curves.
+/// Returns no transforms if the shape has no segments, so the text is
+/// drawn in place.
+///
+/// This is what [`draw_text_on_path`] draws with.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Line, Point};
+/// use piet::ClusterMetric;
+///
+/// let clusters = [ClusterMetric { origin: Point::new(5.0, 0.0), advance: 4.0 }];
+/// let path = Line::new((0.0, 10.0), (0.0, 100.0));
+/// let transforms = piet::text_on_path_transforms(&clusters, path, 1e-3);
+/// // the center of the cluster lands 7.0 along the line
+/// let center = transforms[0] * Point::new(2.0, 0.0) + clusters[0].origin.to_vec2();
+/// assert!((center - Point::new(0.0, 17.0)).hypot() < 1e-6);
+/// ```
+///
+/// [`draw_glyph_run`]: trait.RenderContext.html#tymethod.draw_glyph_run
+/// [`cluster_metrics`]: trait.TextLayout.html#tymethod.cluster_metrics
+/// [`draw_text_on_path`]: trait.RenderContext.html#method.draw_text_on_path
+pub fn text_on_path_transforms(
+    clusters: &[ClusterMetric],
+    shape: impl Shape,
+    accuracy: f64,
+) -> Vec<Affine> {
+    let path = shape.into_bez_path(accuracy);
+    let mut start = 0.0;
+    let segs: Vec<(PathSeg, f64, f64)> = path
+        .segments()
+        .map(|seg| {
+            let len = seg.arclen(accuracy);
+            start += len;
+            (seg, start - len, len)
+        })
+        .collect();
+    let (first, last) = match (segs.first(), segs.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Vec::new(),
+    };
+    clusters
+        .iter()
+        .map(|cluster| {
+            let along = cluster.origin.x + cluster.advance / 2.0;
+            let (point, tangent) = if along < 0.0 {
+                let tangent = seg_tangent(first.0, 0.0);
+                (first.0.eval(0.0) + tangent * along, tangent)
+            } else if along >= last.1 + last.2 {
+                let tangent = seg_tangent(last.0, 1.0);
+                (
+                    last.0.eval(1.0) + tangent * (along - last.1 - last.2),
+                    tangent,
+                )
+            } else {
+                let i = segs.partition_point(|&(_, start, _)| start <= along) - 1;
+                let (seg, start, _) = segs[i];
+                let t = seg.inv_arclen(along - start, accuracy);
+                (seg.eval(t), seg_tangent(seg, t))
+            };
+            Affine::translate(point - cluster.origin)
+                * Affine::rotate(tangent.atan2())
+                * Affine::translate((-cluster.advance / 2.0, cluster.origin.y))
+        })
+        .collect()
+}
+
+/// The unit direction of `seg` at `t`, or along its chord where it has
+/// none there, as at a degenerate control point.
+fn seg_tangent(seg: PathSeg, t: f64) -> Vec2 {
+    let tangent = seg.to_cubic().deriv().eval(t).to_vec2();
+    let tangent = if tangent.hypot() > 1e-12 {
+        tangent
+    } else {
+        seg.eval(1.0) - seg.eval(0.0)
+    };
+    if tangent.hypot() > 1e-12 {
+        tangent / tangent.hypot()
+    } else {
+        Vec2::new(1.0, 0.0)
+    }
+}
+
+/// Subdivisions after which pieces count as flat, whatever their shape.
+const MAX_DEPTH: usize = 48;
+
+/// Find where two pieces of segments cross, each given with its parameter
+/// range on the whole segment, by halving them until they're flat enough
+/// to intersect as lines.
+fn intersect_pieces(
+    a: (PathSeg, Range<f64>),
+    b: (PathSeg, Range<f64>),
+    accuracy: f64,
+    depth: usize,
+    found: &mut Vec<(f64, f64)>,
+) {
+    let (box_a, box_b) = (hull_box(a.0), hull_box(b.0));
+    if !overlaps(box_a, box_b) {
+        return;
+    }
+    let (flat_a, flat_b) = (is_flat(a.0, accuracy), is_flat(b.0, accuracy));
+    if (flat_a && flat_b) || depth >= MAX_DEPTH {
+        let chord = |seg: PathSeg| Line::new(seg.start(), seg.end());
+        if let Some((t_a, t_b)) = line_intersection(chord(a.0), chord(b.0)) {
+            found.push((lerp(&a.1, t_a), lerp(&b.1, t_b)));
+        }
+        return;
+    }
+    let size = |rect: Rect| rect.width().max(rect.height());
+    let split_a = !flat_a && (flat_b || size(box_a) >= size(box_b));
+    let (whole, other) = if split_a { (a, b) } else { (b, a) };
+    let (seg, range) = whole;
+    let mid = lerp(&range, 0.5);
+    for (half, half_range) in [
+        (seg.subsegment(0.0..0.5), range.start..mid),
+        (seg.subsegment(0.5..1.0), mid..range.end),
+    ]
+    .iter()
+    .cloned()
+    {
+        if split_a {
+            intersect_pieces(
+                (half, half_range),
+                other.clone(),
+                accuracy,
+                depth + 1,
+                found,
+            );
+        } else {
+            intersect_pieces(
+                other.clone(),
+                (half, half_range),
+                accuracy,
+                depth + 1,
+                found,
+            );
+        }
+    }
+}
+
+/// The box around a segment's control points, which contains it.
+fn hull_box(seg: PathSeg) -> Rect {
+    match seg {
+        PathSeg::Line(line) => Rect::from_points(line.p0, line.p1),
+        PathSeg::Quad(quad) => Rect::from_points(quad.p0, quad.p2).union_pt(quad.p1),
+        PathSeg::Cubic(cubic) => Rect::from_points(cubic.p0, cubic.p3)
+            .union_pt(cubic.p1)
+            .union_pt(cubic.p2),
+    }
+}
+
+fn overlaps(a: Rect, b: Rect) -> bool {
+    a.x0 <= b.x1 && b.x0 <= a.x1 && a.y0 <= b.y1 && b.y0 <= a.y1
+}
+
+/// Whether a segment is within `accuracy` of its chord, with its control
+/// points between the chord's ends.
+fn is_flat(seg: PathSeg, accuracy: f64) -> bool {
+    let (p0, p1, controls) = match seg {
+        PathSeg::Line(_) => return true,
+        PathSeg::Quad(quad) => (quad.p0, quad.p2, [quad.p1, quad.p1]),
+        PathSeg::Cubic(cubic) => (cubic.p0, cubic.p3, [cubic.p1, cubic.p2]),
+    };
+    let chord = p1 - p0;
+    let len2 = chord.hypot2();
+    controls.iter().all(|&p| {
+        let v = p - p0;
+        if len2 == 0.0 {
+            return v.hypot() <= accuracy;
+        }
+        let along = v.dot(chord) / len2;
+        (0.0..=1.0).contains(&along) && v.cross(chord).abs() <= accuracy * len2.sqrt()
+    })
+}
+
+/// The parameters where two lines cross, if they do.
+fn line_intersection(a: Line, b: Line) -> Option<(f64, f64)> {
+    let r = a.p1 - a.p0;
+    let s = b.p1 - b.p0;
+    let denom = r.cross(s);
+    if denom == 0.0 {
+        return None;
+    }
+    let q = b.p0 - a.p0;
+    let t = q.cross(s) / denom;
+    let u = q.cross(r) / denom;
+    // let crossings at the very ends through, despite rounding
+    const SLACK: f64 = 1e-9;
+    let range = -SLACK..=1.0 + SLACK;
+    if range.contains(&t) && range.contains(&u) {
+        Some((t.clamp(0.0, 1.0), u.clamp(0.0, 1.0)))
+    } else {
+        None
+    }
+}
+
+fn lerp(range: &Range<f64>, t: f64) -> f64 {
+    range.start + (range.end - range.start) * t
+}
@@ -22,13 +22,19 @@
 //! [`RadialGradient`]: struct.RadialGradient.html
 //! [`FixedLinearGradient`]: struct.FixedLinearGradient.html
 //! [`FixedRadialGradient`]: struct.FixedRadialGradient.html
+//! Mesh gradients ([`FixedMeshGradient`]) are made of patches, each with a
+//! color at its four corners. They only come in a fixed flavor, and not every
+//! backend supports them; see [`Capabilities::mesh_gradients`].
+//!
 //! [unit square]: https://en.wikipedia.org/wiki/Unit_square
+//! [`FixedMeshGradient`]: struct.FixedMeshGradient.html
+//! [`Capabilities::mesh_gradients`]: struct.Capabilities.html#structfield.mesh_gradients
 
 use std::borrow::Cow;
 
 use kurbo::{Point, Rect, Size, Vec2};
 
-use crate::{IntoBrush, RenderContext};
+use crate::{new_error, Error, ErrorKind, ImageFormat, IntoBrush, RenderContext};
 
 use crate::Color;
 
@@ -49,6 +55,8 @@ pub struct FixedLinearGradient {
     ///
     /// There must be at least two for the gradient to be valid.
     pub stops: Vec<GradientStop>,
+    /// The color space the stops are interpolated in.
+    pub color_space: GradientColorSpace,
 }
 
 /// Specification of a radial gradient in image-space.
@@ -70,27 +78,129 @@ pub struct FixedRadialGradient {
     pub radius: f64,
     /// The stops (see similar field in [`LinearGradient`](struct.LinearGradient.html)).
     pub stops: Vec<GradientStop>,
+    /// The color space the stops are interpolated in.
+    pub color_space: GradientColorSpace,
+}
+
+/// The color space gradient stops are interpolated in.
+///
+/// Interpolation is always of premultiplied colors, so a stop fading to
+/// transparent doesn't darken along the way whatever color the transparent
+/// stop has.
+///
+/// Backends interpolate natively in sRGB, and not all of them premultiply;
+/// [`resolve_stops`] produces stops that render the same everywhere, and is
+/// what backends apply before handing stops to the platform.
+///
+/// [`resolve_stops`]: #method.resolve_stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GradientColorSpace {
+    /// Interpolate the sRGB components, as CSS and SVG do.
+    #[default]
+    Srgb,
+    /// Interpolate linear-light RGB components, which is physically
+    /// accurate and keeps blends between saturated colors from going dark
+    /// in the middle.
+    LinearRgb,
+}
+
+/// Specification of a mesh gradient in image-space.
+///
+/// The gradient is made of [`MeshPatch`]es, which are painted in order, so
+/// later patches cover earlier ones where they overlap. Points outside every
+/// patch are transparent. This is the model of PDF's Coons patch meshes and
+/// of SVG2 mesh gradients.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{Color, FixedMeshGradient, MeshPatch, RenderContext};
+/// use piet::kurbo::{Point, Rect};
+///
+/// # let mut render_ctx = piet::NullRenderContext::new();
+/// let patch = MeshPatch::new(
+///     [
+///         Point::new(0.0, 0.0),
+///         Point::new(100.0, 0.0),
+///         Point::new(100.0, 100.0),
+///         Point::new(0.0, 100.0),
+///     ],
+///     [Color::WHITE, Color::rgb8(0xff, 0, 0), Color::BLACK, Color::rgb8(0, 0, 0xff)],
+/// );
+/// let mesh = FixedMeshGradient { patches: vec![patch] };
+/// if render_ctx.capabilities().mesh_gradients {
+///     let brush = render_ctx.gradient(mesh).unwrap();
+///     render_ctx.fill(Rect::new(0.0, 0.0, 100.0, 100.0), &brush);
+/// }
+/// ```
+///
+/// [`MeshPatch`]: struct.MeshPatch.html
+#[derive(Debug, Clone)]
+pub struct FixedMeshGradient {
+    /// The patches, in painting order.
+    pub patches: Vec<MeshPatch>,
+}
+
+/// One patch of a [`FixedMeshGradient`].
+///
+/// A patch is bounded by four edges joining its four corners, and colors are
+/// interpolated bilinearly between the corners. The corners go around the
+/// patch, so for an upright rectangle they would be top-left, top-right,
+/// bottom-right and bottom-left.
+///
+/// [`FixedMeshGradient`]: struct.FixedMeshGradient.html
+#[derive(Debug, Clone)]
+pub struct MeshPatch {
+    /// The corners.
+    pub corners: [Point; 4],
+    /// The two control points of each cubic edge, where `edges[i]` runs from
+    /// `corners[i]` to the next corner; `None` for straight edges.
+    ///
+    /// Curved edges make this a Coons patch.
+    pub edges: Option<[(Point, Point); 4]>,
+    /// The color at each corner.
+    pub colors: [Color; 4],
 }
 
 /// Any fixed gradient.
 ///
 /// This is provided as a convenience, so that we can provide API that
-/// accept both [`FixedLinearGradient`] and [`FixedRadialGradient`].
-/// You should not construct this type dirctly; rather construct one of those
-/// types, both of which impl `Into<FixedGradient>`.
+/// accept [`FixedLinearGradient`], [`FixedRadialGradient`] and
+/// [`FixedMeshGradient`]. You should not construct this type dirctly; rather
+/// construct one of those types, all of which impl `Into<FixedGradient>`.
 ///
 /// [`FixedLinearGradient`]: struct.FixedLinearGradient.html
 /// [`FixedRadialGradient`]: struct.FixedRadialGradient.html
+/// [`FixedMeshGradient`]: struct.FixedMeshGradient.html
 #[derive(Debug, Clone)]
 pub enum FixedGradient {
     /// A linear gradient.
     Linear(FixedLinearGradient),
     /// A radial gradient.
     Radial(FixedRadialGradient),
+    /// A mesh gradient.
+    Mesh(FixedMeshGradient),
 }
 
 /// Specification of a gradient stop.
+///
+/// Stops can be made in `const` contexts, so the stops of a theme's
+/// gradients can live in a `static` and be passed to a gradient as they are:
+///
+/// ```
+/// use piet::{Color, GradientStop, LinearGradient, UnitPoint};
+///
+/// static SKY: [GradientStop; 3] = [
+///     GradientStop::new(0.0, Color::rgb8(0x1e, 0x3a, 0x8a)),
+///     GradientStop::new(0.6, Color::rgb8(0x60, 0xa5, 0xfa)),
+///     GradientStop::new(1.0, Color::WHITE),
+/// ];
+///
+/// let gradient = LinearGradient::new(UnitPoint::TOP, UnitPoint::BOTTOM, &SKY);
+/// ```
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GradientStop {
     /// The coordinate of the stop.
     pub pos: f32,
@@ -115,9 +225,10 @@ pub trait GradientStops {
 /// [`FixedLinearGradient`]: struct.FixedLinearGradient.html
 #[derive(Debug, Clone)]
 pub struct LinearGradient {
-    start: UnitPoint,
-    end: UnitPoint,
-    stops: Vec<GradientStop>,
+    pub(crate) start: UnitPoint,
+    pub(crate) end: UnitPoint,
+    pub(crate) stops: Vec<GradientStop>,
+    pub(crate) color_space: GradientColorSpace,
 }
 
 /// A description of a radial gradient in the unit rect, which can be resolved
@@ -149,15 +260,17 @@ pub struct LinearGradient {
 /// [`with_scale_mode`]: struct.RadialGradient.html#method.with_scale_mode
 #[derive(Debug, Clone)]
 pub struct RadialGradient {
-    center: UnitPoint,
-    origin: UnitPoint,
-    radius: f64,
-    stops: Vec<GradientStop>,
-    scale_mode: ScaleMode,
+    pub(crate) center: UnitPoint,
+    pub(crate) origin: UnitPoint,
+    pub(crate) radius: f64,
+    pub(crate) stops: Vec<GradientStop>,
+    pub(crate) scale_mode: ScaleMode,
+    pub(crate) color_space: GradientColorSpace,
 }
 
 /// Mappings from the unit square into a non-square rectangle.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ScaleMode {
     /// The unit 1.0 is mapped to the smaller of width & height, but the mapped
     /// item may not cover the entire rectangle.
@@ -170,8 +283,249 @@ pub enum ScaleMode {
 /// A representation of a point relative to a unit rectangle.
 #[derive(Debug, Clone, Copy)]
 pub struct UnitPoint {
-    u: f64,
-    v: f64,
+    pub(crate) u: f64,
+    pub(crate) v: f64,
+}
+
+impl GradientColorSpace {
+    /// The number of stops each segment is split into when the platform's
+    /// interpolation can't be used directly.
+    const SUBDIVISIONS: usize = 16;
+
+    /// Convert `stops` into stops that give this interpolation on a backend
+    /// that interpolates straight sRGB.
+    ///
+    /// Segments the platform would already get right are kept as they are;
+    /// others are approximated with extra stops. When every segment is
+    /// kept, `stops` is borrowed rather than copied.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::borrow::Cow;
+    /// use piet::{Color, GradientColorSpace, GradientStops};
+    ///
+    /// let stops = (Color::rgb8(0xff, 0, 0), Color::rgb8(0, 0, 0xff)).to_vec();
+    /// let srgb = GradientColorSpace::Srgb.resolve_stops(&stops);
+    /// assert!(matches!(srgb, Cow::Borrowed(_)));
+    /// assert_eq!(srgb.len(), 2);
+    ///
+    /// let linear = GradientColorSpace::LinearRgb.resolve_stops(&stops);
+    /// assert_eq!(linear.len(), 17);
+    /// // linear blending is brighter halfway
+    /// assert_eq!(linear[8].color.as_rgba_u32(), 0xbc00bcff);
+    /// ```
+    pub fn resolve_stops(self, stops: &[GradientStop]) -> Cow<'_, [GradientStop]> {
+        if !stops
+            .windows(2)
+            .any(|pair| self.subdivides(&pair[0], &pair[1]))
+        {
+            return Cow::Borrowed(stops);
+        }
+        Cow::Owned(self.resolved_stops(stops).collect())
+    }
+
+    /// The stops of [`resolve_stops`], one at a time, for a backend to
+    /// collect into a buffer it reuses.
+    ///
+    /// [`resolve_stops`]: #method.resolve_stops
+    pub fn resolved_stops(self, stops: &[GradientStop]) -> impl Iterator<Item = GradientStop> + '_ {
+        let segments = stops.windows(2).flat_map(move |pair| {
+            let (start, end) = (&pair[0], &pair[1]);
+            let between = if self.subdivides(start, end) {
+                1..Self::SUBDIVISIONS
+            } else {
+                0..0
+            };
+            let start_rgba = color_components(&start.color);
+            let end_rgba = color_components(&end.color);
+            let extra = between.map(move |i| {
+                let t = i as f64 / Self::SUBDIVISIONS as f64;
+                GradientStop {
+                    pos: start.pos + (end.pos - start.pos) * t as f32,
+                    color: self.interpolate(start_rgba, end_rgba, t),
+                }
+            });
+            extra.chain(std::iter::once(end.clone()))
+        });
+        stops.first().cloned().into_iter().chain(segments)
+    }
+
+    /// Whether the segment from `start` to `end` needs extra stops for the
+    /// platform to get it right.
+    fn subdivides(self, start: &GradientStop, end: &GradientStop) -> bool {
+        let start_rgba = color_components(&start.color);
+        let end_rgba = color_components(&end.color);
+        let exact = self == GradientColorSpace::Srgb && start_rgba[3] == end_rgba[3];
+        !exact && start.pos < end.pos && start_rgba != end_rgba
+    }
+
+    /// Interpolate premultiplied colors in this space, returning a straight
+    /// sRGB color.
+    fn interpolate(self, start: [f64; 4], end: [f64; 4], t: f64) -> Color {
+        let to_space = |c: f64| match self {
+            GradientColorSpace::Srgb => c,
+            GradientColorSpace::LinearRgb => srgb_to_linear(c),
+        };
+        let alpha = start[3] + (end[3] - start[3]) * t;
+        let mut rgb = [0.0; 3];
+        for (i, c) in rgb.iter_mut().enumerate() {
+            let premul = to_space(start[i]) * start[3] * (1.0 - t) + to_space(end[i]) * end[3] * t;
+            let straight = if alpha > 0.0 { premul / alpha } else { 0.0 };
+            *c = match self {
+                GradientColorSpace::Srgb => straight,
+                GradientColorSpace::LinearRgb => linear_to_srgb(straight),
+            };
+        }
+        Color::rgba(rgb[0], rgb[1], rgb[2], alpha)
+    }
+}
+
+/// Gradient stops that reproduce a row of pixels, such as a palette strip
+/// exported from a design tool.
+///
+/// The first pixel is at position 0.0 and the last at 1.0, with the rest
+/// evenly spaced between. Stops are placed greedily where the gradient
+/// strays furthest from the pixels, until no premultiplied component is off
+/// by more than `tolerance` (in `0.0..=1.0`, so `1.0 / 255.0` is one level
+/// of an 8-bit channel) or there are `max_stops` stops. Smooth strips come
+/// out with a handful of stops, and hard edges between colors are kept.
+///
+/// The error is measured against interpolation in
+/// [`GradientColorSpace::Srgb`], the default.
+///
+/// Returns an error if `pixels` is empty or not a whole number of pixels,
+/// or if `max_stops` is less than two.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::Rect;
+/// use piet::{ImageFormat, LinearGradient, RenderContext, UnitPoint};
+///
+/// // black to red to yellow, as 64 pixels
+/// let strip: Vec<u8> = (0..64u32)
+///     .flat_map(|i| {
+///         let (r, g) = if i < 32 { (i * 255 / 31, 0) } else { (255, (i - 32) * 255 / 31) };
+///         vec![r as u8, g as u8, 0]
+///     })
+///     .collect();
+///
+/// let stops = piet::gradient_stops_from_pixels(&strip, ImageFormat::Rgb, 16, 2.0 / 255.0).unwrap();
+/// assert_eq!(stops.len(), 4);
+///
+/// # let mut rc = piet::NullRenderContext::new();
+/// let heat = LinearGradient::new(UnitPoint::LEFT, UnitPoint::RIGHT, stops);
+/// rc.fill(Rect::new(0.0, 0.0, 200.0, 20.0), &heat);
+/// ```
+///
+/// [`GradientColorSpace::Srgb`]: enum.GradientColorSpace.html#variant.Srgb
+pub fn gradient_stops_from_pixels(
+    pixels: &[u8],
+    format: ImageFormat,
+    max_stops: usize,
+    tolerance: f64,
+) -> Result<Vec<GradientStop>, Error> {
+    if format == ImageFormat::_NonExhaustive {
+        return Err(new_error(ErrorKind::InvalidInput));
+    }
+    let bpp = format.bytes_per_pixel();
+    if pixels.is_empty() || !pixels.len().is_multiple_of(bpp) || max_stops < 2 {
+        return Err(new_error(ErrorKind::InvalidInput));
+    }
+    let colors: Vec<[f64; 4]> = pixels
+        .chunks_exact(bpp)
+        .map(|px| premul_components(px, format))
+        .collect();
+    let last = colors.len() - 1;
+    // the pixels kept as stops, in order
+    let mut kept = vec![0, last];
+    while kept.len() < max_stops {
+        let worst = kept
+            .windows(2)
+            .enumerate()
+            .filter_map(|(k, pair)| worst_pixel(&colors, pair[0], pair[1]).map(|w| (k, w)))
+            .max_by(|(_, a), (_, b)| a.1.total_cmp(&b.1));
+        match worst {
+            Some((k, (i, error))) if error > tolerance => kept.insert(k + 1, i),
+            _ => break,
+        }
+    }
+    let stops = kept.iter().enumerate().map(|(k, &i)| GradientStop {
+        // a single pixel is both ends
+        pos: if last == 0 {
+            k as f32
+        } else {
+            i as f32 / last as f32
+        },
+        color: straight_color(colors[i]),
+    });
+    Ok(stops.collect())
+}
+
+/// The pixel between `start` and `end` furthest from the straight line
+/// between them, with its largest component difference.
+fn worst_pixel(colors: &[[f64; 4]], start: usize, end: usize) -> Option<(usize, f64)> {
+    (start + 1..end)
+        .map(|i| {
+            let t = (i - start) as f64 / (end - start) as f64;
+            let error = (0..4)
+                .map(|c| (colors[start][c] * (1.0 - t) + colors[end][c] * t - colors[i][c]).abs())
+                .fold(0.0, f64::max);
+            (i, error)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// A pixel's premultiplied RGBA components, each in `0.0..=1.0`.
+fn premul_components(px: &[u8], format: ImageFormat) -> [f64; 4] {
+    let c = |i: usize| px[i] as f64 / 255.0;
+    match format {
+        ImageFormat::Rgb => [c(0), c(1), c(2), 1.0],
+        ImageFormat::RgbaSeparate => [c(0) * c(3), c(1) * c(3), c(2) * c(3), c(3)],
+        ImageFormat::RgbaPremul => [c(0), c(1), c(2), c(3)],
+        ImageFormat::BgraPremul => [c(2), c(1), c(0), c(3)],
+        ImageFormat::Grayscale => [c(0), c(0), c(0), 1.0],
+        _ => [0.0, 0.0, 0.0, c(0)],
+    }
+}
+
+fn straight_color(premul: [f64; 4]) -> Color {
+    let [r, g, b, a] = premul;
+    if a > 0.0 {
+        Color::rgba(r / a, g / a, b / a, a)
+    } else {
+        Color::rgba(0.0, 0.0, 0.0, 0.0)
+    }
+}
+
+/// A color's components, each in `0.0..=1.0`.
+fn color_components(color: &Color) -> [f64; 4] {
+    let rgba = color.as_rgba_u32();
+    let c = |shift: u32| ((rgba >> shift) & 0xff) as f64 / 255.0;
+    [c(24), c(16), c(8), c(0)]
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl GradientStop {
+    pub const fn new(pos: f32, color: Color) -> GradientStop {
+        GradientStop { pos, color }
+    }
 }
 
 impl GradientStops for Vec<GradientStop> {
@@ -186,6 +540,18 @@ impl<'a> GradientStops for &'a [GradientStop] {
     }
 }
 
+impl<const N: usize> GradientStops for [GradientStop; N] {
+    fn to_vec(self) -> Vec<GradientStop> {
+        self.into()
+    }
+}
+
+impl<const N: usize> GradientStops for &[GradientStop; N] {
+    fn to_vec(self) -> Vec<GradientStop> {
+        self.to_owned().into()
+    }
+}
+
 // Generate equally-spaced stops.
 impl<'a> GradientStops for &'a [Color] {
     fn to_vec(self) -> Vec<GradientStop> {
@@ -204,6 +570,18 @@ impl<'a> GradientStops for &'a [Color] {
     }
 }
 
+impl<const N: usize> GradientStops for [Color; N] {
+    fn to_vec(self) -> Vec<GradientStop> {
+        GradientStops::to_vec(&self[..])
+    }
+}
+
+impl<const N: usize> GradientStops for &[Color; N] {
+    fn to_vec(self) -> Vec<GradientStop> {
+        GradientStops::to_vec(&self[..])
+    }
+}
+
 impl<'a> GradientStops for (Color, Color) {
     fn to_vec(self) -> Vec<GradientStop> {
         let stops: &[Color] = &[self.0, self.1];
@@ -295,20 +673,31 @@ impl LinearGradient {
             start,
             end,
             stops: stops.to_vec(),
+            color_space: GradientColorSpace::default(),
         }
     }
 
+    /// A builder-style method for changing the [`GradientColorSpace`] the
+    /// stops are interpolated in.
+    ///
+    /// [`GradientColorSpace`]: enum.GradientColorSpace.html
+    pub fn with_color_space(mut self, color_space: GradientColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
     // maybe these should be public API? that was my original intention but I'm not
     // sure there's a clear use, so keeping them private for now.
     /// Generate a [`FixedLinearGradient`] by mapping points in the unit square
     /// onto points in `rect`.
     ///
     /// [`FixedLinearGradient`]: struct.FixedLinearGradient.html
-    fn resolve(&self, rect: Rect) -> FixedLinearGradient {
+    pub(crate) fn resolve(&self, rect: Rect) -> FixedLinearGradient {
         FixedLinearGradient {
             start: self.start.resolve(rect),
             end: self.end.resolve(rect),
             stops: self.stops.clone(),
+            color_space: self.color_space,
         }
     }
 }
@@ -330,6 +719,7 @@ impl RadialGradient {
             radius,
             stops: stops.to_vec(),
             scale_mode: ScaleMode::Fill,
+            color_space: GradientColorSpace::default(),
         }
     }
 
@@ -363,11 +753,20 @@ impl RadialGradient {
         self
     }
 
+    /// A builder-style method for changing the [`GradientColorSpace`] the
+    /// stops are interpolated in.
+    ///
+    /// [`GradientColorSpace`]: enum.GradientColorSpace.html
+    pub fn with_color_space(mut self, color_space: GradientColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
     /// Generate a [`FixedRadialGradient`] by mapping points in the unit square
     /// onto points in `rect`.
     ///
     /// [`FixedRadialGradient`]: struct.FixedRadialGradient.html
-    fn resolve(&self, rect: Rect) -> FixedRadialGradient {
+    pub(crate) fn resolve(&self, rect: Rect) -> FixedRadialGradient {
         let scale_len = match self.scale_mode {
             ScaleMode::Fill => rect.width().max(rect.height()),
             ScaleMode::Fit => rect.width().min(rect.height()),
@@ -383,10 +782,65 @@ impl RadialGradient {
             origin_offset,
             radius,
             stops: self.stops.clone(),
+            color_space: self.color_space,
         }
     }
 }
 
+impl FixedMeshGradient {
+    /// The solid color that stands in for the mesh on backends that can't
+    /// draw it, under [`FallbackPolicy::Degrade`]: the color at the middle
+    /// of the first patch, or transparent if there are no patches.
+    ///
+    /// [`FallbackPolicy::Degrade`]: enum.FallbackPolicy.html#variant.Degrade
+    pub fn fallback_color(&self) -> Color {
+        self.patches
+            .first()
+            .map(|patch| patch.color_at(0.5, 0.5))
+            .unwrap_or(Color::rgba8(0, 0, 0, 0))
+    }
+}
+
+impl MeshPatch {
+    /// Create a patch with straight edges.
+    pub fn new(corners: [Point; 4], colors: [Color; 4]) -> MeshPatch {
+        MeshPatch {
+            corners,
+            edges: None,
+            colors,
+        }
+    }
+
+    /// A builder-style method for curving the edges of the patch.
+    ///
+    /// See the [`edges`] field for the layout of the control points.
+    ///
+    /// [`edges`]: #structfield.edges
+    pub fn with_edges(mut self, edges: [(Point, Point); 4]) -> MeshPatch {
+        self.edges = Some(edges);
+        self
+    }
+
+    /// The color at parametric coordinates `(u, v)` of the patch, each in
+    /// `0.0..=1.0`, where `(0, 0)` is `corners[0]`, `(1, 0)` is `corners[1]`
+    /// and `(0, 1)` is `corners[3]`.
+    ///
+    /// Backends without native mesh support can use this to shade a
+    /// subdivision of the patch.
+    pub fn color_at(&self, u: f64, v: f64) -> Color {
+        let u = u.clamp(0.0, 1.0);
+        let v = v.clamp(0.0, 1.0);
+        let [c0, c1, c2, c3] = &self.colors;
+        let channel = |shift: u32| {
+            let get = |c: &Color| ((c.as_rgba_u32() >> shift) & 0xff) as f64;
+            let top = get(c0) * (1.0 - u) + get(c1) * u;
+            let bottom = get(c3) * (1.0 - u) + get(c2) * u;
+            (top * (1.0 - v) + bottom * v).round() as u8
+        };
+        Color::rgba8(channel(24), channel(16), channel(8), channel(0))
+    }
+}
+
 impl From<FixedLinearGradient> for FixedGradient {
     fn from(src: FixedLinearGradient) -> FixedGradient {
         FixedGradient::Linear(src)
@@ -399,6 +853,12 @@ impl From<FixedRadialGradient> for FixedGradient {
     }
 }
 
+impl From<FixedMeshGradient> for FixedGradient {
+    fn from(src: FixedMeshGradient) -> FixedGradient {
+        FixedGradient::Mesh(src)
+    }
+}
+
 impl<P: RenderContext> IntoBrush<P> for FixedGradient {
     fn make_brush<'a>(&'a self, piet: &mut P, _bbox: impl FnOnce() -> Rect) -> Cow<'a, P::Brush> {
         // Also, at some point we might want to be smarter about the extra clone here.
@@ -0,0 +1,119 @@
+//! Text on a fixed grid of cells, as in a terminal emulator.
+
+use std::ops::Range;
+
+use kurbo::{Point, Rect, Size};
+
+use crate::{Color, Error, Text, TextLayout, TextLayoutBuilder};
+
+/// One cell of a row drawn with [`draw_grid_row`].
+///
+/// [`draw_grid_row`]: trait.RenderContext.html#method.draw_grid_row
+#[derive(Debug, Clone)]
+pub struct GridCell {
+    /// The character shown in the cell; whitespace and control characters
+    /// draw only the background.
+    pub ch: char,
+    /// The color of the glyph.
+    pub fg: Color,
+    /// The color filling the cell, if any.
+    pub bg: Option<Color>,
+}
+
+/// The geometry of a grid of cells.
+#[derive(Debug, Clone, Copy)]
+pub struct GridMetrics {
+    /// The size of each cell.
+    pub cell_size: Size,
+    /// The distance from the top of a cell to the baseline of its glyph.
+    pub baseline: f64,
+}
+
+impl GridCell {
+    /// A cell with a glyph and no background.
+    pub fn new(ch: char, fg: Color) -> GridCell {
+        GridCell { ch, fg, bg: None }
+    }
+
+    /// A builder-style method for setting the background color.
+    pub fn with_bg(mut self, bg: Color) -> GridCell {
+        self.bg = Some(bg);
+        self
+    }
+}
+
+impl GridMetrics {
+    /// Create metrics for cells of `cell_size`, with the baseline `baseline`
+    /// below the top of each cell.
+    pub fn new(cell_size: Size, baseline: f64) -> GridMetrics {
+        GridMetrics {
+            cell_size,
+            baseline,
+        }
+    }
+
+    /// Measure the cells of a monospace `font`.
+    ///
+    /// A cell is as wide as the font's advance and as tall as its ascent
+    /// plus descent, with no extra line spacing.
+    pub fn measure<T: Text>(text: &mut T, font: &T::Font) -> Result<GridMetrics, Error> {
+        let layout = text.new_text_layout(font, "M").build()?;
+        let bounds = layout.range_bounds(0..1).unwrap_or(Rect::ZERO);
+        Ok(GridMetrics {
+            cell_size: Size::new(layout.width(), bounds.height()),
+            baseline: -bounds.y0,
+        })
+    }
+
+    /// The rectangle covering `cells` of a row whose top-left is at `origin`.
+    pub fn cells_rect(&self, origin: Point, cells: Range<usize>) -> Rect {
+        let width = self.cell_size.width;
+        Rect::new(
+            origin.x + cells.start as f64 * width,
+            origin.y,
+            origin.x + cells.end as f64 * width,
+            origin.y + self.cell_size.height,
+        )
+    }
+}
+
+/// Split a row into runs of cells sharing a background color, skipping
+/// cells without one.
+///
+/// Backends drawing grids themselves can use this to fill each run with a
+/// single rectangle.
+pub fn grid_background_runs(cells: &[GridCell]) -> Vec<(Range<usize>, Color)> {
+    color_runs(cells, |cell| cell.bg.as_ref())
+}
+
+/// Split a row into runs of cells sharing a foreground color, skipping
+/// whitespace and control characters, which have nothing to draw.
+pub fn grid_foreground_runs(cells: &[GridCell]) -> Vec<(Range<usize>, Color)> {
+    color_runs(cells, |cell| {
+        if cell.ch.is_whitespace() || cell.ch.is_control() {
+            None
+        } else {
+            Some(&cell.fg)
+        }
+    })
+}
+
+fn color_runs(
+    cells: &[GridCell],
+    color: impl Fn(&GridCell) -> Option<&Color>,
+) -> Vec<(Range<usize>, Color)> {
+    let mut runs: Vec<(Range<usize>, Color)> = Vec::new();
+    for (i, cell) in cells.iter().enumerate() {
+        let color = match color(cell) {
+            Some(color) => color,
+            None => continue,
+        };
+        match runs.last_mut() {
+            Some((range, last)) if range.end == i && last.as_rgba_u32() == color.as_rgba_u32() => {
+                range.end = i + 1
+            }
+            _ => runs.push((i..i + 1, color.clone())),
+        }
+    }
+    runs
+}
@@ -0,0 +1,182 @@
+//! Helpers for drawing images.
+
+use kurbo::{Insets, Rect, Size};
+
+use crate::{new_error, Error, ErrorKind, ImageFormat};
+
+/// An image made by a [`RenderContext`], for drawing with it.
+///
+/// # Examples
+///
+/// Drawing an image as large as fits in a box, keeping its aspect ratio:
+///
+/// ```
+/// use piet::kurbo::{Rect, Vec2};
+/// use piet::{Image, ImageFormat, InterpolationMode, RenderContext};
+///
+/// fn draw_fitted<R: RenderContext>(rc: &mut R, image: &R::Image, dst: Rect) {
+///     let size = image.size();
+///     let scale = (dst.width() / size.width).min(dst.height() / size.height);
+///     let half = Vec2::new(size.width, size.height) * (scale / 2.0);
+///     let fitted = Rect::from_points(dst.center() - half, dst.center() + half);
+///     rc.draw_image(image, fitted, InterpolationMode::Bilinear);
+/// }
+///
+/// # let mut rc = piet::NullRenderContext::new();
+/// let image = rc.make_image(4, 2, &[0; 32], ImageFormat::RgbaSeparate).unwrap();
+/// draw_fitted(&mut rc, &image, Rect::new(0.0, 0.0, 100.0, 100.0));
+/// ```
+///
+/// Checking an image's dimensions, whatever the backend:
+///
+/// ```
+/// use piet::{Image, RenderContext};
+///
+/// fn is_power_of_two<R: RenderContext>(image: &R::Image) -> bool {
+///     image.width().is_power_of_two() && image.height().is_power_of_two()
+/// }
+/// ```
+///
+/// [`RenderContext`]: trait.RenderContext.html
+pub trait Image {
+    /// The size of the image, in pixels.
+    fn size(&self) -> Size;
+
+    /// The width of the image, in pixels.
+    fn width(&self) -> usize {
+        self.size().width as usize
+    }
+
+    /// The height of the image, in pixels.
+    fn height(&self) -> usize {
+        self.size().height as usize
+    }
+
+    /// The format of the pixels the image was made from.
+    ///
+    /// Images the context rendered itself, with [`make_render_target`] or
+    /// [`capture_image_area`], report `RgbaPremul`.
+    ///
+    /// [`make_render_target`]: trait.RenderContext.html#tymethod.make_render_target
+    /// [`capture_image_area`]: trait.RenderContext.html#tymethod.capture_image_area
+    fn format(&self) -> ImageFormat;
+}
+
+/// Check that `buf` holds `width` by `height` pixels of `format`, as
+/// [`make_image`] expects, for backends to call before reading it.
+///
+/// A buffer of another length, or a size that overflows, is
+/// [`ErrorKind::InvalidInput`].
+///
+/// # Examples
+///
+/// ```
+/// use piet::ImageFormat;
+///
+/// assert!(piet::check_image_buffer(2, 2, &[0; 12], ImageFormat::Rgb).is_ok());
+/// assert!(piet::check_image_buffer(2, 2, &[0; 12], ImageFormat::RgbaPremul).is_err());
+/// assert!(piet::check_image_buffer(usize::MAX, 2, &[], ImageFormat::Alpha).is_err());
+/// ```
+///
+/// [`make_image`]: trait.RenderContext.html#tymethod.make_image
+/// [`ErrorKind::InvalidInput`]: enum.ErrorKind.html#variant.InvalidInput
+pub fn check_image_buffer(
+    width: usize,
+    height: usize,
+    buf: &[u8],
+    format: ImageFormat,
+) -> Result<(), Error> {
+    let bytes_per_pixel = match format {
+        ImageFormat::_NonExhaustive => return Err(new_error(ErrorKind::NotSupported)),
+        _ => format.bytes_per_pixel(),
+    };
+    let len = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(bytes_per_pixel));
+    if len != Some(buf.len()) {
+        return Err(new_error(ErrorKind::InvalidInput));
+    }
+    Ok(())
+}
+
+/// Split an image into the nine regions used for nine-slice drawing.
+///
+/// `insets` gives the width of each fixed border of the source image, in
+/// pixels, measured inward from its edges (`x0` is the left border, `y1` the
+/// bottom one); signs are ignored. The corners are drawn at their source size,
+/// the edges are stretched along one axis, and the center along both.
+///
+/// If `dst_rect` is too small to fit two opposite borders, both are scaled
+/// down proportionally so they meet in the middle.
+///
+/// Returns `(src_rect, dst_rect)` pairs suitable for
+/// [`draw_image_area`]; slices that would be empty are skipped.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Insets, Rect, Size};
+///
+/// let slices = piet::nine_slice(
+///     Size::new(30.0, 30.0),
+///     Insets::uniform(10.0),
+///     Rect::new(0.0, 0.0, 100.0, 50.0),
+/// );
+/// assert_eq!(slices.len(), 9);
+/// // corners keep their size, the center stretches
+/// assert_eq!(slices[0].1.width(), 10.0);
+/// assert_eq!(slices[4].1.width(), 80.0);
+/// ```
+///
+/// [`draw_image_area`]: trait.RenderContext.html#tymethod.draw_image_area
+pub fn nine_slice(image_size: Size, insets: Insets, dst_rect: Rect) -> Vec<(Rect, Rect)> {
+    let dst_rect = dst_rect.abs();
+    let (src_x, dst_x) = slice_axis(
+        image_size.width,
+        insets.x0.abs(),
+        insets.x1.abs(),
+        dst_rect.x0,
+        dst_rect.x1,
+    );
+    let (src_y, dst_y) = slice_axis(
+        image_size.height,
+        insets.y0.abs(),
+        insets.y1.abs(),
+        dst_rect.y0,
+        dst_rect.y1,
+    );
+
+    let mut slices = Vec::with_capacity(9);
+    for row in 0..3 {
+        for col in 0..3 {
+            let src = Rect::new(src_x[col], src_y[row], src_x[col + 1], src_y[row + 1]);
+            let dst = Rect::new(dst_x[col], dst_y[row], dst_x[col + 1], dst_y[row + 1]);
+            if src.area() > 0.0 && dst.area() > 0.0 {
+                slices.push((src, dst));
+            }
+        }
+    }
+    slices
+}
+
+/// Compute the slice boundaries along one axis, for source and destination.
+fn slice_axis(len: f64, start: f64, end: f64, dst0: f64, dst1: f64) -> ([f64; 4], [f64; 4]) {
+    // borders can't overlap in the source either
+    let src_scale = (len / (start + end)).min(1.0);
+    let (start, end) = if src_scale.is_finite() {
+        (start * src_scale, end * src_scale)
+    } else {
+        (0.0, 0.0)
+    };
+    let dst_len = dst1 - dst0;
+    let dst_scale = (dst_len / (start + end)).min(1.0);
+    let (dst_start, dst_end) = if dst_scale.is_finite() {
+        (start * dst_scale, end * dst_scale)
+    } else {
+        (0.0, 0.0)
+    };
+    (
+        [0.0, start, len - end, len],
+        [dst0, dst0 + dst_start, dst1 - dst_end, dst1],
+    )
+}
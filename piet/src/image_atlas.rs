@@ -0,0 +1,274 @@
+//! Many small images packed into one.
+
+use kurbo::{Point, Rect, Size};
+
+use crate::{
+    check_image_buffer, new_error, Error, ErrorKind, ImageFormat, InterpolationMode, RenderContext,
+};
+
+/// The key of an image in an [`ImageAtlas`], returned by
+/// [`ImageAtlasBuilder::add`].
+///
+/// [`ImageAtlas`]: struct.ImageAtlas.html
+/// [`ImageAtlasBuilder::add`]: struct.ImageAtlasBuilder.html#method.add
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AtlasId(usize);
+
+/// Collects small images, such as sprites or glyphs, to pack into one
+/// [`ImageAtlas`].
+///
+/// Each image is surrounded by a gutter of `padding` pixels, which repeat
+/// the pixels at its edge. Bilinear filtering samples a little way past the
+/// edges of the area it's asked to draw, and the gutter makes what it finds
+/// there the image's own edge rather than a neighbor's. The default of one
+/// pixel is enough for images drawn at their size or larger; images drawn
+/// smaller than that need a gutter about as wide as the factor they're
+/// shrunk by.
+///
+/// [`ImageAtlas`]: struct.ImageAtlas.html
+#[derive(Debug, Clone)]
+pub struct ImageAtlasBuilder {
+    format: ImageFormat,
+    padding: usize,
+    /// The images in order, each as its width, height and pixels.
+    images: Vec<(usize, usize, Vec<u8>)>,
+}
+
+/// Many small images packed into one backend image, drawn by area.
+///
+/// Backends set up an image for drawing each time a different one is drawn:
+/// a cairo pattern, a Direct2D bitmap brush. Drawing hundreds of sprites or
+/// glyphs from their own tiny images pays that each time, and drawing them
+/// from areas of one atlas image doesn't, especially with [`draw_many`].
+///
+/// Like other images, an atlas can be kept and drawn with any context of
+/// the backend that made it.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Point, Rect};
+/// use piet::{ImageAtlasBuilder, ImageFormat, InterpolationMode, NullRenderContext};
+///
+/// let mut rc = NullRenderContext::new();
+/// let mut builder = ImageAtlasBuilder::new(ImageFormat::RgbaSeparate);
+/// let coin = builder.add(8, 8, &[0xff; 8 * 8 * 4]).unwrap();
+/// let heart = builder.add(12, 10, &[0x80; 12 * 10 * 4]).unwrap();
+/// let atlas = builder.build(&mut rc).unwrap();
+///
+/// assert_eq!(atlas.rect(heart).size(), (12.0, 10.0).into());
+/// atlas.draw(&mut rc, coin, Rect::new(0.0, 0.0, 16.0, 16.0), InterpolationMode::Bilinear);
+/// atlas.draw_many(
+///     &mut rc,
+///     &[(coin, Point::new(20.0, 0.0)), (heart, Point::new(30.0, 0.0))],
+///     InterpolationMode::NearestNeighbor,
+/// );
+/// ```
+///
+/// [`draw_many`]: #method.draw_many
+pub struct ImageAtlas<I> {
+    image: I,
+    /// The area of each image, without its gutter, in pixels of `image`.
+    rects: Vec<Rect>,
+}
+
+impl ImageAtlasBuilder {
+    /// An empty builder for images of `format`, with a one pixel gutter.
+    pub fn new(format: ImageFormat) -> ImageAtlasBuilder {
+        ImageAtlasBuilder {
+            format,
+            padding: 1,
+            images: Vec::new(),
+        }
+    }
+
+    /// The builder, with a gutter of `padding` pixels around each image.
+    pub fn with_padding(mut self, padding: usize) -> ImageAtlasBuilder {
+        self.padding = padding;
+        self
+    }
+
+    /// Add a `width` by `height` image, with pixels laid out as for
+    /// [`make_image`] in the builder's format.
+    ///
+    /// Returns an error if `buf` has the wrong length for the image, or the
+    /// image is empty.
+    ///
+    /// [`make_image`]: trait.RenderContext.html#tymethod.make_image
+    pub fn add(&mut self, width: usize, height: usize, buf: &[u8]) -> Result<AtlasId, Error> {
+        check_image_buffer(width, height, buf, self.format)?;
+        if width == 0 || height == 0 {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        self.images.push((width, height, buf.to_vec()));
+        Ok(AtlasId(self.images.len() - 1))
+    }
+
+    /// The number of images added.
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    /// Whether no images have been added.
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Pack the images and make the atlas image with `rc`.
+    ///
+    /// Images are packed in rows, tallest first, into an image as wide as
+    /// the power of two that makes it roughly square.
+    ///
+    /// Returns an error if no images were added, or if making the image
+    /// fails.
+    pub fn build<R: RenderContext>(&self, rc: &mut R) -> Result<ImageAtlas<R::Image>, Error> {
+        if self.images.is_empty() {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        let (width, height, origins) = self.pack();
+        let bpp = self.format.bytes_per_pixel();
+        let mut pixels = vec![0; width * height * bpp];
+        let mut rects = Vec::with_capacity(self.images.len());
+        for ((w, h, buf), &(x, y)) in self.images.iter().zip(&origins) {
+            self.blit(&mut pixels, width, (x, y), (*w, *h), buf);
+            let (x0, y0) = ((x + self.padding) as f64, (y + self.padding) as f64);
+            rects.push(Rect::new(x0, y0, x0 + *w as f64, y0 + *h as f64));
+        }
+        let image = rc.make_image(width, height, &pixels, self.format)?;
+        Ok(ImageAtlas { image, rects })
+    }
+
+    /// The atlas size, and the top left corner of each image's gutter.
+    fn pack(&self) -> (usize, usize, Vec<(usize, usize)>) {
+        let sizes: Vec<_> = self
+            .images
+            .iter()
+            .map(|&(w, h, _)| (w + 2 * self.padding, h + 2 * self.padding))
+            .collect();
+        let widest = sizes.iter().map(|&(w, _)| w).max().unwrap_or(1);
+        let area: usize = sizes.iter().map(|&(w, h)| w * h).sum();
+        let side = (area as f64).sqrt().ceil() as usize;
+        let width = widest.max(side).next_power_of_two();
+
+        let mut order: Vec<usize> = (0..sizes.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(sizes[i].1));
+        let mut origins = vec![(0, 0); sizes.len()];
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+        for i in order {
+            let (w, h) = sizes[i];
+            if x + w > width {
+                x = 0;
+                y += row_height;
+                row_height = 0;
+            }
+            origins[i] = (x, y);
+            x += w;
+            row_height = row_height.max(h);
+        }
+        (width, y + row_height, origins)
+    }
+
+    /// Copy a `size` image into `pixels` with its gutter's top left corner
+    /// at `origin`, filling the gutter with the nearest edge pixels.
+    fn blit(
+        &self,
+        pixels: &mut [u8],
+        stride: usize,
+        origin: (usize, usize),
+        size: (usize, usize),
+        buf: &[u8],
+    ) {
+        let bpp = self.format.bytes_per_pixel();
+        let (w, h) = size;
+        let pad = self.padding;
+        for row in 0..h + 2 * pad {
+            let src_row = row.saturating_sub(pad).min(h - 1);
+            let src = &buf[src_row * w * bpp..(src_row + 1) * w * bpp];
+            let start = ((origin.1 + row) * stride + origin.0) * bpp;
+            let dst = &mut pixels[start..start + (w + 2 * pad) * bpp];
+            let (left, rest) = dst.split_at_mut(pad * bpp);
+            let (middle, right) = rest.split_at_mut(w * bpp);
+            middle.copy_from_slice(src);
+            for px in left.chunks_exact_mut(bpp) {
+                px.copy_from_slice(&src[..bpp]);
+            }
+            for px in right.chunks_exact_mut(bpp) {
+                px.copy_from_slice(&src[(w - 1) * bpp..]);
+            }
+        }
+    }
+}
+
+impl<I> ImageAtlas<I> {
+    /// The image everything is packed into.
+    pub fn image(&self) -> &I {
+        &self.image
+    }
+
+    /// The number of images in the atlas.
+    pub fn len(&self) -> usize {
+        self.rects.len()
+    }
+
+    /// Whether the atlas has no images, which is never the case for one
+    /// that was built.
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    /// The area of `id` in the atlas image, in pixels, without its gutter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is from a different atlas with more images.
+    pub fn rect(&self, id: AtlasId) -> Rect {
+        self.rects[id.0]
+    }
+
+    /// The size of `id`, in pixels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is from a different atlas with more images.
+    pub fn size(&self, id: AtlasId) -> Size {
+        self.rect(id).size()
+    }
+
+    /// Draw `id` into `dst_rect`. See [`draw_image`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is from a different atlas with more images.
+    ///
+    /// [`draw_image`]: trait.RenderContext.html#tymethod.draw_image
+    pub fn draw<R: RenderContext<Image = I>>(
+        &self,
+        rc: &mut R,
+        id: AtlasId,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        rc.draw_image_area(&self.image, self.rect(id), dst_rect, interp);
+    }
+
+    /// Draw many images at their own size, each `(id, pos)` pair with its
+    /// top left corner at `pos`. See [`draw_tiles`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if an id is from a different atlas with more images.
+    ///
+    /// [`draw_tiles`]: trait.RenderContext.html#tymethod.draw_tiles
+    pub fn draw_many<R: RenderContext<Image = I>>(
+        &self,
+        rc: &mut R,
+        images: &[(AtlasId, Point)],
+        interp: InterpolationMode,
+    ) {
+        let tiles: Vec<_> = images
+            .iter()
+            .map(|&(id, pos)| (self.rect(id), pos))
+            .collect();
+        rc.draw_tiles(&self.image, &tiles, interp);
+    }
+}
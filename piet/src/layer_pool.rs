@@ -0,0 +1,164 @@
+//! Offscreen surfaces kept for reuse by layers and effects.
+
+use crate::{MemoryCharge, MemoryCounter};
+
+/// A pool of offscreen surfaces, for backends to draw layers into without
+/// allocating a new surface each time.
+///
+/// Layers are drawn into a part of a surface at least as large as they
+/// need. Surfaces are made at the largest size asked for since the pool was
+/// last trimmed, so once a frame's biggest layer has been seen, the frames
+/// after it allocate nothing. Backends keep a pool around between frames and
+/// free it with [`trim_layer_pool`].
+///
+/// # Examples
+///
+/// ```
+/// use piet::LayerPool;
+///
+/// let mut made = Vec::new();
+/// let mut make = |width: usize, height: usize| -> Result<Vec<u8>, ()> {
+///     made.push((width, height));
+///     Ok(vec![0; width * height * 4])
+/// };
+///
+/// let mut pool = LayerPool::new();
+/// let big = pool.take(64, 32, &mut make).unwrap();
+/// pool.give_back(big);
+/// // a smaller layer reuses the surface
+/// let small = pool.take(16, 16, &mut make).unwrap();
+/// assert_eq!((small.width(), small.height()), (64, 32));
+/// pool.give_back(small);
+/// // a taller one replaces it, at the largest size seen so far
+/// let tall = pool.take(8, 48, &mut make).unwrap();
+/// assert_eq!((tall.width(), tall.height()), (64, 48));
+/// assert_eq!(made, vec![(64, 32), (64, 48)]);
+/// ```
+///
+/// [`trim_layer_pool`]: trait.RenderContext.html#tymethod.trim_layer_pool
+#[derive(Debug)]
+pub struct LayerPool<S> {
+    free: Vec<PooledSurface<S>>,
+    /// The largest size asked for since the last trim.
+    width: usize,
+    height: usize,
+    /// The counter surfaces are charged to, with their bytes per pixel.
+    memory: Option<(MemoryCounter, usize)>,
+}
+
+/// A surface taken from a [`LayerPool`], with its size in pixels.
+///
+/// [`LayerPool`]: struct.LayerPool.html
+#[derive(Debug)]
+pub struct PooledSurface<S> {
+    surface: S,
+    width: usize,
+    height: usize,
+    /// Held until the surface is dropped, by the pool or by whoever took it.
+    _memory: Option<MemoryCharge>,
+}
+
+impl<S> PooledSurface<S> {
+    pub fn surface(&self) -> &S {
+        &self.surface
+    }
+
+    pub fn surface_mut(&mut self) -> &mut S {
+        &mut self.surface
+    }
+
+    /// The width of the whole surface, which may be more than was asked for.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The height of the whole surface, which may be more than was asked
+    /// for.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+impl<S> LayerPool<S> {
+    /// An empty pool.
+    pub fn new() -> LayerPool<S> {
+        LayerPool {
+            free: Vec::new(),
+            width: 0,
+            height: 0,
+            memory: None,
+        }
+    }
+
+    /// An empty pool that charges the surfaces it makes to `counter`, at
+    /// `bytes_per_pixel`, until they're dropped.
+    pub fn with_memory(counter: &MemoryCounter, bytes_per_pixel: usize) -> LayerPool<S> {
+        LayerPool {
+            memory: Some((counter.clone(), bytes_per_pixel)),
+            ..LayerPool::new()
+        }
+    }
+
+    /// Take a surface at least `width` by `height` pixels.
+    ///
+    /// If no free surface is large enough, one of them is dropped and `make`
+    /// is called for a new one at the largest size asked for so far. The
+    /// surface's contents are whatever was last drawn into it.
+    pub fn take<E>(
+        &mut self,
+        width: usize,
+        height: usize,
+        make: impl FnOnce(usize, usize) -> Result<S, E>,
+    ) -> Result<PooledSurface<S>, E> {
+        self.width = self.width.max(width);
+        self.height = self.height.max(height);
+        let fits = self
+            .free
+            .iter()
+            .position(|pooled| pooled.width >= width && pooled.height >= height);
+        if let Some(i) = fits {
+            return Ok(self.free.swap_remove(i));
+        }
+        // too small for this layer, so too small for the high-water mark
+        self.free.pop();
+        let surface = make(self.width, self.height)?;
+        let memory = self.memory.as_ref().map(|(counter, bytes_per_pixel)| {
+            counter.charge(self.width * self.height * bytes_per_pixel)
+        });
+        Ok(PooledSurface {
+            surface,
+            width: self.width,
+            height: self.height,
+            _memory: memory,
+        })
+    }
+
+    /// Return a surface to the pool, for the next layer to use.
+    pub fn give_back(&mut self, pooled: PooledSurface<S>) {
+        self.free.push(pooled);
+    }
+
+    /// Drop the free surfaces, and forget the sizes asked for so far.
+    ///
+    /// Surfaces taken and not yet given back are kept when they are.
+    pub fn trim(&mut self) {
+        self.free.clear();
+        self.width = 0;
+        self.height = 0;
+    }
+
+    /// The number of free surfaces.
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.free.is_empty()
+    }
+}
+
+impl<S> Default for LayerPool<S> {
+    fn default() -> Self {
+        LayerPool::new()
+    }
+}
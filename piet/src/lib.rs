@@ -2,20 +2,71 @@
 
 pub use kurbo;
 
+mod bidi;
+mod blur;
+mod capabilities;
 mod color;
 mod conv;
+mod dyn_shape;
+mod editable_text;
+mod emoji;
 mod error;
+mod export;
+mod font_file;
+mod frame_arena;
+mod geometry;
 mod gradient;
+mod grid;
+mod image;
+mod image_atlas;
+mod layer_pool;
+mod memory;
+mod mipmap;
 mod null_renderer;
+mod owned;
+mod panic_free;
+mod profile;
+mod recording;
 mod render_context;
+mod replay;
+#[cfg(feature = "serde")]
+mod serialize;
+mod shape_group;
 mod shapes;
+mod stroke;
+mod styles;
 mod text;
 
+pub use crate::bidi::*;
+pub use crate::blur::*;
+pub use crate::capabilities::*;
 pub use crate::color::*;
 pub use crate::conv::*;
+pub use crate::dyn_shape::*;
+pub use crate::editable_text::*;
+pub use crate::emoji::*;
 pub use crate::error::*;
+pub use crate::font_file::*;
+pub use crate::frame_arena::*;
+pub use crate::geometry::*;
 pub use crate::gradient::*;
+pub use crate::grid::*;
+pub use crate::image::*;
+pub use crate::image_atlas::*;
+pub use crate::layer_pool::*;
+pub use crate::memory::*;
+pub use crate::mipmap::*;
 pub use crate::null_renderer::*;
+pub use crate::owned::*;
+pub use crate::panic_free::*;
+pub use crate::profile::*;
+pub use crate::recording::*;
 pub use crate::render_context::*;
+pub use crate::replay::*;
+#[cfg(feature = "serde")]
+pub use crate::serialize::*;
+pub use crate::shape_group::*;
 pub use crate::shapes::*;
+pub use crate::stroke::*;
+pub use crate::styles::*;
 pub use crate::text::*;
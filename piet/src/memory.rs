@@ -0,0 +1,148 @@
+//! Accounting for the memory backend resources hold.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The approximate memory held by a backend's resources, in bytes.
+///
+/// Sizes are estimated from what the backend allocated, such as the pixels of
+/// an image, so they leave out the driver's and the platform's own overhead.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{NullRenderContext, RenderContext};
+///
+/// let mut rc = NullRenderContext::new();
+/// // once a frame, or when the app goes idle
+/// if rc.memory_report().total() > 64 << 20 {
+///     rc.trim_caches();
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// Images made with [`make_image`] and [`capture_image_area`] that are
+    /// still alive.
+    ///
+    /// [`make_image`]: trait.RenderContext.html#tymethod.make_image
+    /// [`capture_image_area`]: trait.RenderContext.html#tymethod.capture_image_area
+    pub images: usize,
+    /// Brushes the backend keeps for reuse. The backends here make brushes
+    /// when they're asked for, so this is zero for them.
+    pub brushes: usize,
+    /// Glyph lookups and rasterized glyphs the backend keeps for reuse.
+    pub glyphs: usize,
+    /// Offscreen surfaces kept for layers and effects, in a [`LayerPool`].
+    ///
+    /// [`LayerPool`]: struct.LayerPool.html
+    pub layers: usize,
+}
+
+impl MemoryReport {
+    /// The memory held by all the resources.
+    pub fn total(&self) -> usize {
+        self.images + self.brushes + self.glyphs + self.layers
+    }
+}
+
+/// A running total of the bytes held by one kind of resource.
+///
+/// Clones share the total, so a backend can hand one to each resource it
+/// makes, and each resource [`charge`]s its size until it's dropped.
+///
+/// # Examples
+///
+/// ```
+/// use piet::MemoryCounter;
+///
+/// let images = MemoryCounter::new();
+/// let image = images.charge(64 * 64 * 4);
+/// let thumbnail = images.charge(16 * 16 * 4);
+/// assert_eq!(images.bytes(), 17408);
+/// drop(image);
+/// assert_eq!(images.bytes(), 1024);
+/// ```
+///
+/// [`charge`]: #method.charge
+#[derive(Debug, Clone, Default)]
+pub struct MemoryCounter(Arc<AtomicUsize>);
+
+/// Bytes counted by a [`MemoryCounter`], until this is dropped.
+///
+/// [`MemoryCounter`]: struct.MemoryCounter.html
+#[derive(Debug)]
+pub struct MemoryCharge {
+    counter: MemoryCounter,
+    bytes: usize,
+}
+
+/// A counter for each kind of resource in a [`MemoryReport`], for a device or
+/// a thread to share with everything made from it.
+///
+/// [`MemoryReport`]: struct.MemoryReport.html
+#[derive(Debug, Clone, Default)]
+pub struct MemoryCounters {
+    pub images: MemoryCounter,
+    pub brushes: MemoryCounter,
+    pub glyphs: MemoryCounter,
+    pub layers: MemoryCounter,
+}
+
+impl MemoryCounter {
+    /// A counter at zero.
+    pub fn new() -> MemoryCounter {
+        MemoryCounter::default()
+    }
+
+    /// The bytes charged and not yet dropped.
+    pub fn bytes(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Count `bytes` until the returned charge is dropped.
+    pub fn charge(&self, bytes: usize) -> MemoryCharge {
+        self.0.fetch_add(bytes, Ordering::Relaxed);
+        MemoryCharge {
+            counter: self.clone(),
+            bytes,
+        }
+    }
+}
+
+impl MemoryCharge {
+    /// The bytes counted by this charge.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Count `bytes` in place of what was counted, for a resource that grew
+    /// or shrank.
+    pub fn set(&mut self, bytes: usize) {
+        self.counter.0.fetch_add(bytes, Ordering::Relaxed);
+        self.counter.0.fetch_sub(self.bytes, Ordering::Relaxed);
+        self.bytes = bytes;
+    }
+}
+
+impl Drop for MemoryCharge {
+    fn drop(&mut self) {
+        self.counter.0.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+impl MemoryCounters {
+    /// Counters at zero.
+    pub fn new() -> MemoryCounters {
+        MemoryCounters::default()
+    }
+
+    /// The bytes charged to each counter.
+    pub fn report(&self) -> MemoryReport {
+        MemoryReport {
+            images: self.images.bytes(),
+            brushes: self.brushes.bytes(),
+            glyphs: self.glyphs.bytes(),
+            layers: self.layers.bytes(),
+        }
+    }
+}
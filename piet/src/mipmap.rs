@@ -0,0 +1,219 @@
+//! Images prefiltered at several resolutions.
+
+use kurbo::{Affine, Rect, Size};
+
+use crate::{new_error, Error, ErrorKind, Image, ImageFormat, InterpolationMode, RenderContext};
+
+/// An image stored at full resolution and at each halving of it, for
+/// drawing at many zoom levels.
+///
+/// Drawing a large image much smaller than its size samples only a few of
+/// its pixels, so fine detail shimmers as the view pans and zooms. A mipmap
+/// draws from the smallest level that still has at least one pixel per
+/// device pixel, which was averaged from all the pixels beneath it.
+///
+/// The levels are made once, when the mipmap is created, and take a third
+/// more memory than the image alone. Like other images, a mipmap can be
+/// kept and drawn with any context of the backend that made it.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Affine, Rect};
+/// use piet::{ImageFormat, InterpolationMode, MipmapImage, NullRenderContext, RenderContext};
+///
+/// let mut rc = NullRenderContext::new();
+/// let map = vec![0x80; 256 * 256 * 4];
+/// let mipmap = MipmapImage::new(&mut rc, 256, 256, &map, ImageFormat::RgbaSeparate).unwrap();
+/// assert_eq!(mipmap.level_count(), 9);
+///
+/// // zoomed out to a quarter, the third level has the right resolution
+/// let dst = Rect::new(0.0, 0.0, 256.0, 256.0);
+/// assert_eq!(mipmap.level_for(dst, Affine::scale(0.25)), 2);
+///
+/// rc.transform(Affine::scale(0.25));
+/// mipmap.draw(&mut rc, dst, InterpolationMode::Bilinear);
+/// ```
+pub struct MipmapImage<I> {
+    size: Size,
+    levels: Vec<I>,
+}
+
+impl<I: Image> MipmapImage<I> {
+    /// Make the levels of an image from a pixel buffer, as for
+    /// [`make_image`].
+    ///
+    /// Levels are averaged with premultiplied alpha, so images with separate
+    /// alpha are stored premultiplied from the second level down.
+    ///
+    /// Returns an error if `buf` has the wrong length for the image, or if
+    /// making one of the levels fails.
+    ///
+    /// [`make_image`]: trait.RenderContext.html#tymethod.make_image
+    pub fn new<R: RenderContext<Image = I>>(
+        rc: &mut R,
+        width: usize,
+        height: usize,
+        buf: &[u8],
+        format: ImageFormat,
+    ) -> Result<Self, Error> {
+        let bpp = format.bytes_per_pixel();
+        if width == 0 || height == 0 || buf.len() != width * height * bpp {
+            return Err(new_error(ErrorKind::InvalidInput));
+        }
+        let mut levels = vec![rc.make_image(width, height, buf, format)?];
+        let (mut pixels, format) = match format {
+            ImageFormat::RgbaSeparate => (premultiply(buf), ImageFormat::RgbaPremul),
+            _ => (buf.to_vec(), format),
+        };
+        let (mut w, mut h) = (width, height);
+        while w > 1 || h > 1 {
+            let (half_w, half_h) = (w.div_ceil(2), h.div_ceil(2));
+            pixels = downsample(&pixels, w, h, bpp);
+            levels.push(rc.make_image(half_w, half_h, &pixels, format)?);
+            w = half_w;
+            h = half_h;
+        }
+        Ok(MipmapImage {
+            size: Size::new(width as f64, height as f64),
+            levels,
+        })
+    }
+
+    /// The size of the full resolution image, in pixels.
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// The number of levels, including the full resolution one.
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Level `level`, 0 being full resolution and each one after it half
+    /// the size of the one before, rounded up.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `level` is out of bounds.
+    pub fn level(&self, level: usize) -> &I {
+        &self.levels[level]
+    }
+
+    /// The level to draw the whole image into `dst_rect` with, under
+    /// `transform`.
+    ///
+    /// This is the smallest level with at least one pixel per device pixel
+    /// along both axes.
+    pub fn level_for(&self, dst_rect: Rect, transform: Affine) -> usize {
+        self.level_for_area(self.size, dst_rect, transform)
+    }
+
+    /// Draw the whole image into `dst_rect`, from the level that fits the
+    /// current transform. See [`draw_image`].
+    ///
+    /// [`draw_image`]: trait.RenderContext.html#tymethod.draw_image
+    pub fn draw<R: RenderContext<Image = I>>(
+        &self,
+        rc: &mut R,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let dst_rect = dst_rect.into();
+        let level = self.level_for(dst_rect, rc.current_transform());
+        rc.draw_image(&self.levels[level], dst_rect, interp);
+    }
+
+    /// Draw the `src_rect` area of the image into `dst_rect`, from the level
+    /// that fits the current transform. See [`draw_image_area`].
+    ///
+    /// `src_rect` is in pixels of the full resolution image, whichever level
+    /// is drawn.
+    ///
+    /// [`draw_image_area`]: trait.RenderContext.html#tymethod.draw_image_area
+    pub fn draw_area<R: RenderContext<Image = I>>(
+        &self,
+        rc: &mut R,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let src_rect = src_rect.into();
+        let dst_rect = dst_rect.into();
+        let level = self.level_for_area(src_rect.size(), dst_rect, rc.current_transform());
+        // level sizes are rounded up, so scale each axis by its own ratio
+        let (w, h) = self.level_size(level);
+        let scale_x = w / self.size.width;
+        let scale_y = h / self.size.height;
+        let src_rect = Rect::new(
+            src_rect.x0 * scale_x,
+            src_rect.y0 * scale_y,
+            src_rect.x1 * scale_x,
+            src_rect.y1 * scale_y,
+        );
+        rc.draw_image_area(&self.levels[level], src_rect, dst_rect, interp);
+    }
+
+    /// The level for `src` image pixels drawn into `dst_rect`.
+    fn level_for_area(&self, src: Size, dst_rect: Rect, transform: Affine) -> usize {
+        // device pixels per user space unit along each axis of user space
+        let [a, b, c, d, _, _] = transform.as_coeffs();
+        let device_w = dst_rect.width().abs() * a.hypot(b);
+        let device_h = dst_rect.height().abs() * c.hypot(d);
+        // image pixels per device pixel, along the more minified axis
+        let footprint = (src.width.abs() / device_w).max(src.height.abs() / device_h);
+        if footprint.is_nan() || footprint <= 1.0 {
+            return 0;
+        }
+        let level = footprint.log2().floor();
+        if level.is_finite() {
+            (level as usize).min(self.levels.len() - 1)
+        } else {
+            self.levels.len() - 1
+        }
+    }
+
+    fn level_size(&self, level: usize) -> (f64, f64) {
+        let (mut w, mut h) = (self.size.width as usize, self.size.height as usize);
+        for _ in 0..level {
+            w = w.div_ceil(2);
+            h = h.div_ceil(2);
+        }
+        (w as f64, h as f64)
+    }
+}
+
+fn premultiply(buf: &[u8]) -> Vec<u8> {
+    let mut pixels = buf.to_vec();
+    for px in pixels.chunks_exact_mut(4) {
+        let a = px[3] as u32;
+        for c in &mut px[..3] {
+            *c = ((*c as u32 * a + 127) / 255) as u8;
+        }
+    }
+    pixels
+}
+
+/// Halve an image in each dimension, rounding up, by averaging 2 by 2
+/// blocks of pixels. Blocks past the right and bottom edges repeat the last
+/// column and row.
+fn downsample(pixels: &[u8], width: usize, height: usize, bpp: usize) -> Vec<u8> {
+    let (half_w, half_h) = (width.div_ceil(2), height.div_ceil(2));
+    let mut out = Vec::with_capacity(half_w * half_h * bpp);
+    for y in 0..half_h {
+        let rows = [2 * y, (2 * y + 1).min(height - 1)];
+        for x in 0..half_w {
+            let cols = [2 * x, (2 * x + 1).min(width - 1)];
+            for c in 0..bpp {
+                let mut sum = 2;
+                for &row in &rows {
+                    for &col in &cols {
+                        sum += pixels[(row * width + col) * bpp + c] as u32;
+                    }
+                }
+                out.push((sum / 4) as u8);
+            }
+        }
+    }
+    out
+}
@@ -1,12 +1,17 @@
 //! A render context that does nothing.
 
 use std::borrow::Cow;
+use std::ops::Range;
 
-use kurbo::{Affine, Point, Rect, Shape};
+use kurbo::{Affine, Insets, Point, Rect, Shape, Size};
 
 use crate::{
-    Color, Error, FixedGradient, Font, FontBuilder, HitTestPoint, HitTestTextPosition, ImageFormat,
-    InterpolationMode, IntoBrush, RenderContext, StrokeStyle, Text, TextLayout, TextLayoutBuilder,
+    new_error, Antialiasing, Capabilities, ClusterMetric, Color, DecorationMetrics, EmojiStrategy,
+    Error, ErrorKind, FallbackPolicy, FixedGradient, Font, FontBuilder, FontFileInfo, FontStretch,
+    FontStyle, FontSubstitutions, FontVariation, FontWeight, GlyphPositioning, HitTestPoint,
+    HitTestTextPosition, Image, ImageFormat, ImagePositioning, InterpolationMode, IntoBrush,
+    LineMetric, MemoryReport, RenderContext, StrokeStyle, Text, TextAlignment, TextAttribute,
+    TextLayout, TextLayoutBuilder, TextTruncation, WritingMode, DEFAULT_TOLERANCE,
 };
 
 /// A render context that doesn't render.
@@ -21,8 +26,11 @@ pub struct NullRenderContext(NullText);
 pub struct NullBrush;
 #[doc(hidden)]
 pub struct NullImage;
+#[doc(hidden)]
+pub struct NullPath;
 
 #[doc(hidden)]
+#[derive(Clone)]
 pub struct NullText;
 
 #[doc(hidden)]
@@ -45,6 +53,7 @@ impl NullRenderContext {
 impl RenderContext for NullRenderContext {
     type Brush = NullBrush;
     type Image = NullImage;
+    type Path = NullPath;
     type Text = NullText;
     type TextLayout = NullTextLayout;
 
@@ -52,6 +61,10 @@ impl RenderContext for NullRenderContext {
         Ok(())
     }
 
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
     fn solid_brush(&mut self, _color: Color) -> Self::Brush {
         NullBrush
     }
@@ -62,6 +75,8 @@ impl RenderContext for NullRenderContext {
 
     fn clear(&mut self, _color: Color) {}
 
+    fn clear_rect(&mut self, _rect: Rect, _color: Color) {}
+
     fn stroke(&mut self, _shape: impl Shape, _brush: &impl IntoBrush<Self>, _width: f64) {}
 
     fn stroke_styled(
@@ -79,8 +94,35 @@ impl RenderContext for NullRenderContext {
 
     fn clip(&mut self, _shape: impl Shape) {}
 
-    fn text(&mut self) -> &mut Self::Text {
-        &mut self.0
+    fn clip_text(&mut self, _layout: &Self::TextLayout, _pos: impl Into<Point>) {}
+
+    fn push_clip(&mut self, _shape: impl Shape) {}
+
+    fn pop_clip(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn reset_clip(&mut self) {}
+
+    fn make_path(&mut self, _shape: impl Shape) -> Result<Self::Path, Error> {
+        Ok(NullPath)
+    }
+
+    fn fill_path(&mut self, _path: &Self::Path, _brush: &impl IntoBrush<Self>) {}
+
+    fn stroke_path(&mut self, _path: &Self::Path, _brush: &impl IntoBrush<Self>, _width: f64) {}
+
+    fn stroke_path_styled(
+        &mut self,
+        _path: &Self::Path,
+        _brush: &impl IntoBrush<Self>,
+        _width: f64,
+        _style: &StrokeStyle,
+    ) {
+    }
+
+    fn text(&self) -> Self::Text {
+        self.0.clone()
     }
 
     fn draw_text(
@@ -91,6 +133,39 @@ impl RenderContext for NullRenderContext {
     ) {
     }
 
+    fn draw_glyph_run(
+        &mut self,
+        _layout: &Self::TextLayout,
+        _pos: impl Into<Point>,
+        _brush: &impl IntoBrush<Self>,
+        _transforms: &[Affine],
+    ) {
+    }
+
+    fn draw_glyphs(
+        &mut self,
+        _font: &NullFont,
+        _glyphs: &[(u16, Point)],
+        _brush: &impl IntoBrush<Self>,
+    ) {
+    }
+
+    fn set_glyph_positioning(&mut self, _positioning: GlyphPositioning) {}
+
+    fn set_image_positioning(&mut self, _positioning: ImagePositioning) {}
+
+    fn set_tolerance(&mut self, _tolerance: f64) {}
+
+    fn tolerance(&self) -> f64 {
+        DEFAULT_TOLERANCE
+    }
+
+    fn set_antialiasing(&mut self, _mode: Antialiasing) {}
+
+    fn set_pick_id(&mut self, _id: Option<u64>) {}
+
+    fn set_fallback_policy(&mut self, _policy: FallbackPolicy) {}
+
     fn save(&mut self) -> Result<(), Error> {
         Ok(())
     }
@@ -127,6 +202,74 @@ impl RenderContext for NullRenderContext {
     ) {
     }
 
+    fn draw_image_transformed(
+        &mut self,
+        _image: &Self::Image,
+        _transform: Affine,
+        _interp: InterpolationMode,
+    ) {
+    }
+
+    fn draw_tiles(
+        &mut self,
+        _atlas: &Self::Image,
+        _tiles: &[(Rect, Point)],
+        _interp: InterpolationMode,
+    ) {
+    }
+
+    fn draw_images(&mut self, _images: &[(&Self::Image, Rect, f64)], _interp: InterpolationMode) {}
+
+    fn make_render_target(&mut self, _width: usize, _height: usize) -> Result<Self::Image, Error> {
+        Ok(NullImage)
+    }
+
+    fn draw_to_target(
+        &mut self,
+        _target: &mut Self::Image,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        f(self)
+    }
+
+    fn capture_image_area(&mut self, _src_rect: impl Into<Rect>) -> Result<Self::Image, Error> {
+        Ok(NullImage)
+    }
+
+    fn with_blur(
+        &mut self,
+        _radius: f64,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        f(self)
+    }
+
+    fn trim_layer_pool(&mut self) {}
+
+    fn memory_report(&self) -> MemoryReport {
+        MemoryReport::default()
+    }
+
+    fn trim_caches(&mut self) {}
+
+    fn draw_image_nine(
+        &mut self,
+        _image: &Self::Image,
+        _insets: Insets,
+        _dst_rect: impl Into<Rect>,
+        _interp: InterpolationMode,
+    ) {
+    }
+
+    fn draw_image_clipped(
+        &mut self,
+        _image: &Self::Image,
+        _dst_rect: impl Into<Rect>,
+        _shape: impl Shape,
+        _interp: InterpolationMode,
+    ) {
+    }
+
     fn current_transform(&self) -> Affine {
         Affine::default()
     }
@@ -145,13 +288,60 @@ impl Text for NullText {
     fn new_text_layout(&mut self, _font: &Self::Font, _text: &str) -> Self::TextLayoutBuilder {
         NullTextLayoutBuilder
     }
+
+    fn set_font_substitutions(&mut self, _substitutions: FontSubstitutions) {}
+
+    fn set_emoji_strategy(&mut self, _strategy: EmojiStrategy) {}
+
+    fn load_font(&mut self, data: &[u8]) -> Result<String, Error> {
+        match FontFileInfo::read(data) {
+            Some(info) => Ok(info.family),
+            None => Err(new_error(ErrorKind::InvalidInput)),
+        }
+    }
+
+    fn set_system_fonts(&mut self, _enabled: bool) {}
 }
 
-impl Font for NullFont {}
+impl Image for NullImage {
+    fn size(&self) -> Size {
+        Size::ZERO
+    }
+
+    fn format(&self) -> ImageFormat {
+        ImageFormat::RgbaPremul
+    }
+}
+
+impl Font for NullFont {
+    fn decoration_metrics(&self) -> DecorationMetrics {
+        DecorationMetrics::default()
+    }
+}
 
 impl FontBuilder for NullFontBuilder {
     type Out = NullFont;
 
+    fn weight(self, _weight: FontWeight) -> Self {
+        self
+    }
+
+    fn style(self, _style: FontStyle) -> Self {
+        self
+    }
+
+    fn stretch(self, _stretch: FontStretch) -> Self {
+        self
+    }
+
+    fn fallback(self, _family: &str) -> Self {
+        self
+    }
+
+    fn variation(self, _variation: FontVariation) -> Self {
+        self
+    }
+
     fn build(self) -> Result<Self::Out, Error> {
         Ok(NullFont)
     }
@@ -160,6 +350,38 @@ impl FontBuilder for NullFontBuilder {
 impl TextLayoutBuilder for NullTextLayoutBuilder {
     type Out = NullTextLayout;
 
+    fn obscure(self, _mask: char) -> Self {
+        self
+    }
+
+    fn range_attribute(self, _range: Range<usize>, _attribute: TextAttribute) -> Self {
+        self
+    }
+
+    fn default_color(self, _color: Color) -> Self {
+        self
+    }
+
+    fn transform(self, _transform: Affine) -> Self {
+        self
+    }
+
+    fn max_width(self, _width: f64) -> Self {
+        self
+    }
+
+    fn alignment(self, _alignment: TextAlignment) -> Self {
+        self
+    }
+
+    fn max_lines(self, _lines: usize, _truncation: TextTruncation) -> Self {
+        self
+    }
+
+    fn writing_mode(self, _mode: WritingMode) -> Self {
+        self
+    }
+
     fn build(self) -> Result<Self::Out, Error> {
         Ok(NullTextLayout)
     }
@@ -177,6 +399,34 @@ impl TextLayout for NullTextLayout {
     fn hit_test_text_position(&self, _text_position: usize) -> Option<HitTestTextPosition> {
         None
     }
+
+    fn range_bounds(&self, _range: Range<usize>) -> Option<Rect> {
+        None
+    }
+
+    fn rects_for_range(&self, _range: Range<usize>) -> Vec<Rect> {
+        Vec::new()
+    }
+
+    fn caret_bounds(&self, _text_position: usize) -> Option<Rect> {
+        None
+    }
+
+    fn line_metrics(&self) -> Vec<LineMetric> {
+        Vec::new()
+    }
+
+    fn cluster_metrics(&self) -> Vec<ClusterMetric> {
+        Vec::new()
+    }
+
+    fn decoration_metrics(&self) -> DecorationMetrics {
+        DecorationMetrics::default()
+    }
+
+    fn truncated_at(&self) -> Option<usize> {
+        None
+    }
 }
 
 impl IntoBrush<NullRenderContext> for NullBrush {
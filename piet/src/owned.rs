@@ -0,0 +1,300 @@
+//! Descriptions of backend resources that can move between threads.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use kurbo::Size;
+
+use crate::{
+    check_image_buffer, Color, EmojiAtlas, EmojiStrategy, Error, FixedGradient, FontBuilder,
+    FontFileInfo, FontStyle, FontSubstitutions, FontVariation, FontWeight, Image,
+    ImageAtlasBuilder, ImageFormat, MemoryCounter, MemoryCounters, PaintBrush, RecordedImage,
+    RenderContext, StrokeStyle, Text, TextAlignment, TextAttribute, TextLayoutBuilder,
+    TextTruncation, WritingMode,
+};
+
+/// An image's pixels, which any thread can hold, to make into an image for a
+/// render context.
+///
+/// Whether a backend's images can be sent to other threads depends on the
+/// platform: Direct2D's can, as they come from a multithreaded factory, but
+/// cairo's and the web's are tied to the thread that made them. Code that
+/// decodes images on worker threads hands them over as `OwnedImage`s, and
+/// the drawing thread makes them with [`to_image`], once.
+///
+/// # Examples
+///
+/// ```
+/// use std::thread;
+///
+/// use piet::kurbo::Size;
+/// use piet::{Image, ImageFormat, NullRenderContext, OwnedImage};
+///
+/// let decoded = thread::spawn(|| {
+///     OwnedImage::new(2, 2, vec![0xff; 16], ImageFormat::RgbaSeparate).unwrap()
+/// })
+/// .join()
+/// .unwrap();
+///
+/// assert_eq!(decoded.size(), Size::new(2.0, 2.0));
+/// let mut rc = NullRenderContext::new();
+/// let image = decoded.to_image(&mut rc).unwrap();
+///
+/// // the buffer has to be the length the format asks for
+/// assert!(OwnedImage::new(2, 2, vec![0; 15], ImageFormat::RgbaSeparate).is_err());
+/// ```
+///
+/// [`to_image`]: #method.to_image
+#[derive(Debug, Clone)]
+pub struct OwnedImage {
+    width: usize,
+    height: usize,
+    format: ImageFormat,
+    pixels: Arc<[u8]>,
+}
+
+/// A text layout as it's asked for, which any thread can hold, to build
+/// with a render context's text factory.
+///
+/// Fonts and layouts are tied to the thread that made them on cairo and the
+/// web, and hold the factory's font state, such as the fonts it loaded. A
+/// layout described on one thread is built with [`build`] on the drawing
+/// thread.
+///
+/// # Examples
+///
+/// ```
+/// use std::thread;
+///
+/// use piet::{FontWeight, NullRenderContext, OwnedTextLayout, RenderContext, TextAttribute};
+///
+/// let label = thread::spawn(|| {
+///     OwnedTextLayout::new("sans-serif", 12.0, "Saved 3 files")
+///         .range_attribute(6..7, TextAttribute::Weight(FontWeight::BOLD))
+///         .max_width(200.0)
+/// })
+/// .join()
+/// .unwrap();
+///
+/// let mut rc = NullRenderContext::new();
+/// let layout = label.build(&mut rc.text()).unwrap();
+/// ```
+///
+/// [`build`]: #method.build
+#[derive(Debug, Clone)]
+pub struct OwnedTextLayout {
+    text: String,
+    family: String,
+    size: f64,
+    weight: FontWeight,
+    style: FontStyle,
+    variations: Vec<FontVariation>,
+    attributes: Vec<(Range<usize>, TextAttribute)>,
+    default_color: Option<Color>,
+    max_width: f64,
+    alignment: TextAlignment,
+    max_lines: Option<(usize, TextTruncation)>,
+    writing_mode: WritingMode,
+}
+
+impl OwnedImage {
+    /// Take `pixels` laid out as [`RenderContext::make_image`] takes them.
+    ///
+    /// Returns an [`ErrorKind::InvalidInput`] error if the buffer isn't the
+    /// length `width`, `height` and `format` call for.
+    ///
+    /// [`RenderContext::make_image`]: trait.RenderContext.html#tymethod.make_image
+    /// [`ErrorKind::InvalidInput`]: enum.ErrorKind.html#variant.InvalidInput
+    pub fn new(
+        width: usize,
+        height: usize,
+        pixels: impl Into<Arc<[u8]>>,
+        format: ImageFormat,
+    ) -> Result<OwnedImage, Error> {
+        let pixels = pixels.into();
+        check_image_buffer(width, height, &pixels, format)?;
+        Ok(OwnedImage {
+            width,
+            height,
+            format,
+            pixels,
+        })
+    }
+
+    /// The pixels, in the image's format.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Make the image for drawing with `rc`.
+    pub fn to_image<R: RenderContext>(&self, rc: &mut R) -> Result<R::Image, Error> {
+        rc.make_image(self.width, self.height, &self.pixels, self.format)
+    }
+}
+
+impl Image for OwnedImage {
+    fn size(&self) -> Size {
+        Size::new(self.width as f64, self.height as f64)
+    }
+
+    fn format(&self) -> ImageFormat {
+        self.format
+    }
+}
+
+impl From<RecordedImage> for OwnedImage {
+    fn from(image: RecordedImage) -> OwnedImage {
+        OwnedImage {
+            width: image.width,
+            height: image.height,
+            format: image.format,
+            pixels: image.pixels,
+        }
+    }
+}
+
+impl OwnedTextLayout {
+    /// A layout of `text` in the regular face of `family`, at `size`.
+    pub fn new(family: impl Into<String>, size: f64, text: impl Into<String>) -> Self {
+        OwnedTextLayout {
+            text: text.into(),
+            family: family.into(),
+            size,
+            weight: FontWeight::NORMAL,
+            style: FontStyle::Normal,
+            variations: Vec::new(),
+            attributes: Vec::new(),
+            default_color: None,
+            max_width: f64::INFINITY,
+            alignment: TextAlignment::Start,
+            max_lines: None,
+            writing_mode: WritingMode::Horizontal,
+        }
+    }
+
+    /// See [`FontBuilder::weight`].
+    ///
+    /// [`FontBuilder::weight`]: trait.FontBuilder.html#tymethod.weight
+    pub fn weight(mut self, weight: FontWeight) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// See [`FontBuilder::style`].
+    ///
+    /// [`FontBuilder::style`]: trait.FontBuilder.html#tymethod.style
+    pub fn style(mut self, style: FontStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// See [`FontBuilder::variation`].
+    ///
+    /// [`FontBuilder::variation`]: trait.FontBuilder.html#tymethod.variation
+    pub fn variation(mut self, variation: FontVariation) -> Self {
+        self.variations.push(variation);
+        self
+    }
+
+    /// See [`TextLayoutBuilder::range_attribute`].
+    ///
+    /// [`TextLayoutBuilder::range_attribute`]: trait.TextLayoutBuilder.html#tymethod.range_attribute
+    pub fn range_attribute(mut self, range: Range<usize>, attribute: TextAttribute) -> Self {
+        self.attributes.push((range, attribute));
+        self
+    }
+
+    /// See [`TextLayoutBuilder::default_color`].
+    ///
+    /// [`TextLayoutBuilder::default_color`]: trait.TextLayoutBuilder.html#tymethod.default_color
+    pub fn default_color(mut self, color: Color) -> Self {
+        self.default_color = Some(color);
+        self
+    }
+
+    /// See [`TextLayoutBuilder::max_width`].
+    ///
+    /// [`TextLayoutBuilder::max_width`]: trait.TextLayoutBuilder.html#tymethod.max_width
+    pub fn max_width(mut self, width: f64) -> Self {
+        self.max_width = width;
+        self
+    }
+
+    /// See [`TextLayoutBuilder::alignment`].
+    ///
+    /// [`TextLayoutBuilder::alignment`]: trait.TextLayoutBuilder.html#tymethod.alignment
+    pub fn alignment(mut self, alignment: TextAlignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// See [`TextLayoutBuilder::max_lines`].
+    ///
+    /// [`TextLayoutBuilder::max_lines`]: trait.TextLayoutBuilder.html#tymethod.max_lines
+    pub fn max_lines(mut self, lines: usize, truncation: TextTruncation) -> Self {
+        self.max_lines = Some((lines, truncation));
+        self
+    }
+
+    /// See [`TextLayoutBuilder::writing_mode`].
+    ///
+    /// [`TextLayoutBuilder::writing_mode`]: trait.TextLayoutBuilder.html#tymethod.writing_mode
+    pub fn writing_mode(mut self, mode: WritingMode) -> Self {
+        self.writing_mode = mode;
+        self
+    }
+
+    /// The text laid out.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Build the font and the layout with `factory`.
+    pub fn build<T: Text>(&self, factory: &mut T) -> Result<T::TextLayout, Error> {
+        let mut font = factory
+            .new_font_by_name(&self.family, self.size)
+            .weight(self.weight)
+            .style(self.style);
+        for variation in &self.variations {
+            font = font.variation(*variation);
+        }
+        let font = font.build()?;
+        let mut builder = factory
+            .new_text_layout(&font, &self.text)
+            .max_width(self.max_width)
+            .alignment(self.alignment)
+            .writing_mode(self.writing_mode);
+        if let Some((lines, truncation)) = self.max_lines {
+            builder = builder.max_lines(lines, truncation);
+        }
+        if let Some(color) = &self.default_color {
+            builder = builder.default_color(color.clone());
+        }
+        for (range, attribute) in &self.attributes {
+            builder = builder.range_attribute(range.clone(), attribute.clone());
+        }
+        builder.build()
+    }
+}
+
+// The backend-independent types are plain data, and can go anywhere. Keep
+// them that way.
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn send_sync<T: Send + Sync>() {}
+    send_sync::<Color>();
+    send_sync::<PaintBrush>();
+    send_sync::<FixedGradient>();
+    send_sync::<StrokeStyle>();
+    send_sync::<TextAttribute>();
+    send_sync::<FontSubstitutions>();
+    send_sync::<EmojiStrategy>();
+    send_sync::<EmojiAtlas>();
+    send_sync::<ImageAtlasBuilder>();
+    send_sync::<FontFileInfo>();
+    send_sync::<MemoryCounter>();
+    send_sync::<MemoryCounters>();
+    send_sync::<RecordedImage>();
+    send_sync::<OwnedImage>();
+    send_sync::<OwnedTextLayout>();
+}
@@ -0,0 +1,101 @@
+//! Reporting misuse as errors rather than panics.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{new_error, Error, ErrorKind};
+
+static PANIC_FREE: AtomicBool = AtomicBool::new(false);
+
+/// Turn panic-free mode on or off, for the whole process.
+///
+/// Malformed input, such as an image buffer of the wrong length or a
+/// rectangle with a NaN corner, gives an `Err` or draws nothing in any mode.
+/// Misuse of the API, such as finishing a render context with saves not
+/// restored, is an error too, but debug builds panic on it first, to catch
+/// the mistake where it happens. Panic-free mode skips those panics.
+///
+/// Hosts that draw content they don't trust, such as plugins or documents
+/// from the network, turn this on at startup, and can put [`catch_panic`]
+/// around drawing for bugs that would panic anyway.
+///
+/// The mode only skips piet's own debug checks of API misuse. Methods a
+/// backend hasn't implemented yet, such as piet-svg's text drawing, still
+/// panic with `unimplemented!()` whatever the mode; check the context's
+/// [`Capabilities`] before calling them.
+///
+/// # Examples
+///
+/// ```
+/// use piet::ErrorKind;
+///
+/// piet::set_panic_free(true);
+/// // what a backend's `finish` does with a save left open: an error, and
+/// // no panic even in debug builds
+/// let open_saves = 1;
+/// let result = piet::check_usage(open_saves == 0, ErrorKind::StackUnbalance, "save not restored");
+/// assert!(result.is_err());
+/// # piet::set_panic_free(false);
+/// ```
+///
+/// [`catch_panic`]: fn.catch_panic.html
+/// [`Capabilities`]: struct.Capabilities.html
+pub fn set_panic_free(panic_free: bool) {
+    PANIC_FREE.store(panic_free, Ordering::Relaxed);
+}
+
+/// Whether panic-free mode is on. See [`set_panic_free`].
+///
+/// [`set_panic_free`]: fn.set_panic_free.html
+pub fn is_panic_free() -> bool {
+    PANIC_FREE.load(Ordering::Relaxed)
+}
+
+/// Check a rule of the API that callers can break, for backends.
+///
+/// Returns an error of `kind` if `ok` is false, after panicking with
+/// `message` in debug builds unless in panic-free mode.
+pub fn check_usage(ok: bool, kind: ErrorKind, message: &str) -> Result<(), Error> {
+    if ok {
+        return Ok(());
+    }
+    if cfg!(debug_assertions) && !is_panic_free() {
+        panic!("{}", message);
+    }
+    Err(new_error(kind))
+}
+
+/// Run `f`, turning a panic in it into an error.
+///
+/// This is a backstop for hosts that can't let a bug take them down; the
+/// error is an [`ErrorKind::BackendError`] with the panic's message. The
+/// panic hook still runs, and whatever `f` was drawing into may be left half
+/// drawn, with saves open, so it's best dropped.
+///
+/// # Examples
+///
+/// ```
+/// use piet::Error;
+///
+/// let result = piet::catch_panic(|| -> Result<(), Error> {
+///     let pixels: Vec<u8> = Vec::new();
+///     let _ = pixels[3];
+///     Ok(())
+/// });
+/// assert!(result.is_err());
+/// ```
+///
+/// [`ErrorKind::BackendError`]: enum.ErrorKind.html#variant.BackendError
+pub fn catch_panic<T>(f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => match payload.downcast_ref::<String>() {
+                Some(message) => message.clone(),
+                None => "unknown cause".to_owned(),
+            },
+        };
+        let error: Box<dyn std::error::Error> = format!("panicked: {}", message).into();
+        Err(error.into())
+    })
+}
@@ -0,0 +1,245 @@
+//! Counting and timing draw calls.
+
+use std::time::Duration;
+
+#[cfg(feature = "profiling")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "profiling")]
+use std::time::Instant;
+
+/// A kind of call to a [`RenderContext`], as a [`DrawProfile`] groups them.
+///
+/// [`RenderContext`]: trait.RenderContext.html
+/// [`DrawProfile`]: struct.DrawProfile.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DrawCall {
+    /// Filling shapes, including clearing.
+    Fill,
+    /// Stroking shapes.
+    Stroke,
+    /// Drawing text and glyph runs.
+    Text,
+    /// Drawing images.
+    Image,
+    /// Saves, restores, transforms and clips.
+    State,
+}
+
+impl DrawCall {
+    /// Every kind of call, in the order of their declaration.
+    pub const ALL: [DrawCall; 5] = [
+        DrawCall::Fill,
+        DrawCall::Stroke,
+        DrawCall::Text,
+        DrawCall::Image,
+        DrawCall::State,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The number of calls of one kind, and the time spent in them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CallStats {
+    /// The number of calls.
+    pub count: u64,
+    /// The time spent in them, all together.
+    pub time: Duration,
+}
+
+/// The calls made to a render context so far, by kind, from
+/// [`draw_profile`].
+///
+/// Only the calls the application makes are counted: a call a backend
+/// makes while handling another, such as a fill for the background of some
+/// text, is part of the time of the outer one. The time is the time spent
+/// in the call, which for backends that queue drawing for a GPU is the time
+/// to queue it, not to draw it.
+///
+/// Calls are counted with the `profiling` feature of piet on; without it,
+/// profiles are always empty.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::Rect;
+/// use piet::{Color, DrawCall, NullRenderContext, RenderContext};
+///
+/// let mut rc = NullRenderContext::new();
+/// rc.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::BLACK);
+/// rc.finish().unwrap();
+///
+/// let profile = rc.draw_profile();
+/// if let Some(hottest) = profile.hottest() {
+///     println!("most time in {:?}: {:?}", hottest, profile.get(hottest).time);
+/// }
+/// assert!(profile.total().count <= 1);
+/// ```
+///
+/// [`draw_profile`]: trait.RenderContext.html#method.draw_profile
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrawProfile {
+    calls: [CallStats; 5],
+}
+
+impl DrawProfile {
+    /// The calls of kind `call`.
+    pub fn get(&self, call: DrawCall) -> CallStats {
+        self.calls[call.index()]
+    }
+
+    /// All the calls, together.
+    pub fn total(&self) -> CallStats {
+        self.calls
+            .iter()
+            .fold(CallStats::default(), |total, stats| CallStats {
+                count: total.count + stats.count,
+                time: total.time + stats.time,
+            })
+    }
+
+    /// The kind of call the most time was spent in, if any calls were
+    /// made.
+    pub fn hottest(&self) -> Option<DrawCall> {
+        DrawCall::ALL
+            .iter()
+            .copied()
+            .filter(|&call| self.get(call).count > 0)
+            .max_by_key(|&call| self.get(call).time)
+    }
+
+    /// Count a call of kind `call` that took `time`.
+    pub fn record(&mut self, call: DrawCall, time: Duration) {
+        let stats = &mut self.calls[call.index()];
+        stats.count += 1;
+        stats.time += time;
+    }
+}
+
+/// The running [`DrawProfile`] of a render context, for backends.
+///
+/// A backend keeps one, times each call with [`time`], and returns
+/// [`profile`] from [`draw_profile`]. Clones share the profile, for contexts
+/// that hand out others drawing to the same place. Without the `profiling`
+/// feature of piet this is empty, and timing a call does nothing.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{DrawCall, Profiler};
+///
+/// struct Backend {
+///     profiler: Profiler,
+/// }
+///
+/// impl Backend {
+///     fn fill(&mut self) {
+///         let _timer = self.profiler.time(DrawCall::Fill);
+///         // ...
+///     }
+/// }
+///
+/// let mut backend = Backend { profiler: Profiler::new() };
+/// backend.fill();
+/// let fills = backend.profiler.profile().get(DrawCall::Fill).count;
+/// assert_eq!(fills, if cfg!(feature = "profiling") { 1 } else { 0 });
+/// ```
+///
+/// [`DrawProfile`]: struct.DrawProfile.html
+/// [`time`]: #method.time
+/// [`profile`]: #method.profile
+/// [`draw_profile`]: trait.RenderContext.html#method.draw_profile
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    #[cfg(feature = "profiling")]
+    state: Arc<Mutex<ProfilerState>>,
+}
+
+#[cfg(feature = "profiling")]
+#[derive(Debug, Default)]
+struct ProfilerState {
+    profile: DrawProfile,
+    /// The number of calls being timed, one inside another.
+    depth: usize,
+}
+
+/// Times a call until it's dropped. See [`Profiler::time`].
+///
+/// [`Profiler::time`]: struct.Profiler.html#method.time
+#[must_use = "the call is timed until the timer is dropped"]
+#[derive(Debug)]
+pub struct CallTimer {
+    #[cfg(feature = "profiling")]
+    state: Arc<Mutex<ProfilerState>>,
+    #[cfg(feature = "profiling")]
+    call: DrawCall,
+    #[cfg(feature = "profiling")]
+    start: Instant,
+}
+
+impl Profiler {
+    /// A profiler with nothing counted.
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    /// Start timing a call of kind `call`, which is counted when the timer
+    /// is dropped, unless it's inside another call being timed.
+    #[inline]
+    pub fn time(&self, call: DrawCall) -> CallTimer {
+        #[cfg(feature = "profiling")]
+        {
+            lock(&self.state).depth += 1;
+            CallTimer {
+                state: self.state.clone(),
+                call,
+                start: Instant::now(),
+            }
+        }
+        #[cfg(not(feature = "profiling"))]
+        {
+            let _ = call;
+            CallTimer {}
+        }
+    }
+
+    /// The calls counted so far.
+    pub fn profile(&self) -> DrawProfile {
+        #[cfg(feature = "profiling")]
+        {
+            lock(&self.state).profile
+        }
+        #[cfg(not(feature = "profiling"))]
+        {
+            DrawProfile::default()
+        }
+    }
+
+    /// Forget the calls counted so far, to start a new frame's count.
+    pub fn reset(&self) {
+        #[cfg(feature = "profiling")]
+        {
+            lock(&self.state).profile = DrawProfile::default();
+        }
+    }
+}
+
+#[cfg(feature = "profiling")]
+impl Drop for CallTimer {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        let mut state = lock(&self.state);
+        state.depth -= 1;
+        if state.depth == 0 {
+            state.profile.record(self.call, elapsed);
+        }
+    }
+}
+
+/// Lock `state`, whether or not a panic poisoned it; it's only counts.
+#[cfg(feature = "profiling")]
+fn lock(state: &Mutex<ProfilerState>) -> std::sync::MutexGuard<'_, ProfilerState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,1516 @@
+//! A render context that records drawing commands into a display list.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use kurbo::{Affine, BezPath, Insets, Point, Rect, Shape, Size, Vec2};
+
+use crate::replay::paint_bounds;
+use crate::stroke::flatten;
+use crate::{
+    align_stroke, check_image_buffer, check_transform, flattening_tolerance, new_error, nine_slice,
+    stroke_contains, user_tolerance, Antialiasing, Capabilities, ClusterMetric, Color,
+    DecorationMetrics, EmojiStrategy, Error, ErrorKind, FallbackPolicy, FixedGradient, Font,
+    FontBuilder, FontStretch, FontStyle, FontSubstitutions, FontVariation, FontWarning, FontWeight,
+    GlyphPositioning, HitTestPoint, HitTestTextPosition, Image, ImageFormat, ImagePositioning,
+    InterpolationMode, IntoBrush, LineJoin, LineMetric, MemoryReport, PaintBrush, RenderContext,
+    StrokeStyle, Text, TextAlignment, TextAttribute, TextLayout, TextLayoutBuilder, TextTruncation,
+    WritingMode, DEFAULT_TOLERANCE,
+};
+
+/// How closely curves are followed when hit testing, in the units of the
+/// lasso.
+const LASSO_TOLERANCE: f64 = 0.1;
+
+/// The miter limit of strokes whose style doesn't set one, as the backends
+/// have it.
+const DEFAULT_MITER_LIMIT: f64 = 10.0;
+
+/// A render context that draws nothing, but records what it is asked to
+/// draw in a [`DisplayList`].
+///
+/// Text is laid out by the [`Text`] the context is made with, so that the
+/// recorded layouts can be measured, hit tested, and drawn later by a
+/// context of the same backend. Images keep a copy of their pixels.
+///
+/// Offscreen drawing with [`make_render_target`] and reading back with
+/// [`capture_image_area`] aren't supported, since nothing is rendered.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::Rect;
+/// use piet::{Color, DrawCommand, RecordingContext, RenderContext};
+/// # use piet::NullText;
+///
+/// let mut rc = RecordingContext::new(NullText);
+/// rc.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::WHITE);
+/// rc.finish().unwrap();
+///
+/// let list = rc.into_display_list();
+/// assert!(matches!(list.commands()[0], DrawCommand::Fill { .. }));
+/// ```
+///
+/// [`DisplayList`]: struct.DisplayList.html
+/// [`Text`]: trait.Text.html
+/// [`make_render_target`]: trait.RenderContext.html#tymethod.make_render_target
+/// [`capture_image_area`]: trait.RenderContext.html#tymethod.capture_image_area
+pub struct RecordingContext<T: Text> {
+    text: RecordingText<T>,
+    list: DisplayList<T::TextLayout>,
+    state: GraphicsState,
+    /// Shapes are kept as paths; this is the tolerance of converting curved
+    /// shapes like circles, in device pixels.
+    tolerance: f64,
+    err: Result<(), Error>,
+}
+
+/// The drawing commands recorded by a [`RecordingContext`], in order.
+///
+/// [`RecordingContext`]: struct.RecordingContext.html
+pub struct DisplayList<L> {
+    pub(crate) commands: Vec<DrawCommand<L>>,
+}
+
+/// One call recorded by a [`RecordingContext`].
+///
+/// Calls that [`RenderContext`] implements in terms of others, such as
+/// [`stroke_hairline`] and [`draw_grid_row`], are recorded as those others.
+/// So are some image calls: [`draw_image_transformed`] and
+/// [`draw_image_clipped`] become an `Image` between a `Save` and a
+/// `Restore`, and [`draw_image_nine`], [`draw_tiles`] and [`draw_images`]
+/// an `Image` for each piece.
+///
+/// Shapes are in the user space of the transform at the time, which the
+/// `Save`, `Restore` and `Transform` commands before them set.
+///
+/// [`RecordingContext`]: struct.RecordingContext.html
+/// [`RenderContext`]: trait.RenderContext.html
+/// [`stroke_hairline`]: trait.RenderContext.html#method.stroke_hairline
+/// [`draw_grid_row`]: trait.RenderContext.html#method.draw_grid_row
+/// [`draw_image_transformed`]: trait.RenderContext.html#tymethod.draw_image_transformed
+/// [`draw_image_clipped`]: trait.RenderContext.html#tymethod.draw_image_clipped
+/// [`draw_image_nine`]: trait.RenderContext.html#tymethod.draw_image_nine
+/// [`draw_tiles`]: trait.RenderContext.html#tymethod.draw_tiles
+/// [`draw_images`]: trait.RenderContext.html#tymethod.draw_images
+pub enum DrawCommand<L> {
+    Clear(Color),
+    ClearRect(Rect, Color),
+    Fill {
+        shape: BezPath,
+        brush: PaintBrush,
+        even_odd: bool,
+    },
+    /// A stroke, with `style` if it was drawn with [`stroke_styled`].
+    ///
+    /// [`stroke_styled`]: trait.RenderContext.html#tymethod.stroke_styled
+    Stroke {
+        shape: BezPath,
+        brush: PaintBrush,
+        width: f64,
+        style: Option<StrokeStyle>,
+    },
+    Clip(BezPath),
+    ClipText {
+        layout: RecordedTextLayout<L>,
+        pos: Point,
+    },
+    PushClip(BezPath),
+    PopClip,
+    ResetClip,
+    /// Text, with the transform of each glyph if it was drawn with
+    /// [`draw_glyph_run`].
+    ///
+    /// [`draw_glyph_run`]: trait.RenderContext.html#tymethod.draw_glyph_run
+    Text {
+        layout: RecordedTextLayout<L>,
+        pos: Point,
+        brush: PaintBrush,
+        glyph_transforms: Option<Vec<Affine>>,
+    },
+    GlyphPositioning(GlyphPositioning),
+    ImagePositioning(ImagePositioning),
+    Antialiasing(Antialiasing),
+    PickId(Option<u64>),
+    Save,
+    Restore,
+    Transform(Affine),
+    /// The `src_rect` area of an image drawn into `dst_rect`, with `alpha`
+    /// from 0.0 to 1.0.
+    Image {
+        image: RecordedImage,
+        src_rect: Rect,
+        dst_rect: Rect,
+        interp: InterpolationMode,
+        alpha: f64,
+    },
+    /// The start of the commands drawn by [`with_blur`], blurred together
+    /// until the matching `EndBlur`.
+    ///
+    /// [`with_blur`]: trait.RenderContext.html#tymethod.with_blur
+    BeginBlur(f64),
+    EndBlur,
+}
+
+/// The text factory of a [`RecordingContext`], which lays text out with
+/// another one.
+///
+/// [`RecordingContext`]: struct.RecordingContext.html
+#[derive(Clone)]
+pub struct RecordingText<T>(pub(crate) T);
+
+/// A font builder for a [`RecordingContext`].
+///
+/// [`RecordingContext`]: struct.RecordingContext.html
+pub struct RecordingFontBuilder<B> {
+    builder: B,
+    face: RecordedFace,
+}
+
+/// A font made for a [`RecordingContext`], with the family, size and style
+/// it was asked for.
+///
+/// [`RecordingContext`]: struct.RecordingContext.html
+pub struct RecordedFont<F> {
+    font: F,
+    face: RecordedFace,
+}
+
+/// What a font was asked for.
+#[derive(Clone)]
+pub(crate) struct RecordedFace {
+    pub(crate) family: String,
+    pub(crate) size: f64,
+    pub(crate) weight: FontWeight,
+    pub(crate) style: FontStyle,
+    pub(crate) stretch: FontStretch,
+    pub(crate) fallbacks: Vec<String>,
+    pub(crate) variations: Vec<FontVariation>,
+}
+
+/// A text layout builder for a [`RecordingContext`].
+///
+/// [`RecordingContext`]: struct.RecordingContext.html
+pub struct RecordingTextLayoutBuilder<B> {
+    builder: B,
+    source: LayoutSource,
+}
+
+/// A text layout recorded in a [`DisplayList`], shared with the commands
+/// that draw it.
+///
+/// Besides the backend's layout, it keeps the text and font it was made
+/// from, for exporting the display list.
+///
+/// [`DisplayList`]: struct.DisplayList.html
+pub struct RecordedTextLayout<L> {
+    layout: Arc<L>,
+    pub(crate) source: Arc<LayoutSource>,
+}
+
+/// What a recorded layout was made from.
+pub(crate) struct LayoutSource {
+    pub(crate) text: String,
+    pub(crate) face: RecordedFace,
+    pub(crate) mask: Option<char>,
+    /// The calls made to the layout's builder, in order, to serialize.
+    #[cfg(feature = "serde")]
+    pub(crate) options: Vec<LayoutOption>,
+}
+
+/// A call made to a text layout builder, kept to make the layout again.
+#[cfg(feature = "serde")]
+#[derive(Clone)]
+pub(crate) enum LayoutOption {
+    Attribute(Range<usize>, TextAttribute),
+    DefaultColor(Color),
+    Transform(Affine),
+    MaxWidth(f64),
+    Alignment(TextAlignment),
+    MaxLines(usize, TextTruncation),
+    WritingMode(WritingMode),
+}
+
+/// An image made by a [`RecordingContext`], with a copy of its pixels.
+///
+/// [`RecordingContext`]: struct.RecordingContext.html
+#[derive(Clone, Debug)]
+pub struct RecordedImage {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) format: ImageFormat,
+    pub(crate) pixels: Arc<[u8]>,
+}
+
+/// The transform and clips at a point in a display list, and those saved
+/// by each save, pushed clip and blur before it, tracked the way backends
+/// keep them.
+#[derive(Default)]
+pub(crate) struct GraphicsState {
+    pub(crate) transform: Affine,
+    /// Each clip with the transform it was made under.
+    pub(crate) clips: Vec<(Affine, BezPath)>,
+    saved: Vec<Saved>,
+}
+
+struct Saved {
+    kind: SaveKind,
+    transform: Affine,
+    /// The number of clips at the time.
+    clips: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum SaveKind {
+    Save,
+    Clip,
+    Blur,
+}
+
+impl GraphicsState {
+    /// Follow a command, or return an error if it doesn't match the saves
+    /// before it.
+    pub(crate) fn update<L>(&mut self, command: &DrawCommand<L>) -> Result<(), Error> {
+        match command {
+            DrawCommand::Save => self.save(SaveKind::Save),
+            DrawCommand::BeginBlur(_) => self.save(SaveKind::Blur),
+            DrawCommand::PushClip(shape) => {
+                self.save(SaveKind::Clip);
+                self.clips.push((self.transform, shape.clone()));
+            }
+            DrawCommand::Clip(shape) => self.clips.push((self.transform, shape.clone())),
+            DrawCommand::Transform(transform) => self.transform *= *transform,
+            DrawCommand::PopClip => match self.saved.last() {
+                Some(saved) if saved.kind == SaveKind::Clip => self.pop_clip(),
+                _ => return Err(new_error(ErrorKind::StackUnbalance)),
+            },
+            DrawCommand::ResetClip => {
+                // clips from before the last save stay
+                self.pop_clips();
+                let clips = self.saved.last().map(|saved| saved.clips).unwrap_or(0);
+                self.clips.truncate(clips);
+            }
+            DrawCommand::Restore => {
+                // clips pushed since the save go with it
+                self.pop_clips();
+                match self.saved.last() {
+                    Some(saved) if saved.kind == SaveKind::Save => {
+                        self.restore_to(self.saved.len() - 1)
+                    }
+                    _ => return Err(new_error(ErrorKind::StackUnbalance)),
+                }
+            }
+            DrawCommand::EndBlur => {
+                // anything left from inside the blur ends with it
+                let blur = self
+                    .saved
+                    .iter()
+                    .rposition(|saved| saved.kind == SaveKind::Blur)
+                    .ok_or_else(|| new_error(ErrorKind::StackUnbalance))?;
+                self.restore_to(blur);
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// What the last save that's still open was made by.
+    pub(crate) fn innermost(&self) -> Option<SaveKind> {
+        self.saved.last().map(|saved| saved.kind)
+    }
+
+    fn save(&mut self, kind: SaveKind) {
+        self.saved.push(Saved {
+            kind,
+            transform: self.transform,
+            clips: self.clips.len(),
+        });
+    }
+
+    /// Go back to the state saved at `depth`, and drop it and those after.
+    fn restore_to(&mut self, depth: usize) {
+        let saved = &self.saved[depth];
+        self.transform = saved.transform;
+        self.clips.truncate(saved.clips);
+        self.saved.truncate(depth);
+    }
+
+    /// Drop the last pushed clip, which is on top of the saves. Popping a
+    /// clip leaves the transform as it is.
+    fn pop_clip(&mut self) {
+        let transform = self.transform;
+        self.restore_to(self.saved.len() - 1);
+        self.transform = transform;
+    }
+
+    fn pop_clips(&mut self) {
+        while let Some(SaveKind::Clip) = self.saved.last().map(|saved| saved.kind) {
+            self.pop_clip();
+        }
+    }
+
+    /// `rect`, before any transform, cut down to the boxes of the clips.
+    fn clip_bounds(&self, rect: Rect) -> Rect {
+        self.clips.iter().fold(rect, |rect, (transform, shape)| {
+            rect.intersect(transform.transform_rect_bbox(shape.bounding_box()))
+        })
+    }
+
+    /// Whether `point`, before any transform, is inside all the clips.
+    fn clips_contain(&self, point: Point) -> bool {
+        self.clips.iter().all(|(transform, shape)| {
+            transform.determinant() != 0.0 && shape.winding(transform.inverse() * point) != 0
+        })
+    }
+}
+
+impl<T: Text + Clone> RecordingContext<T> {
+    /// A context that lays out text with `text`, with nothing recorded yet.
+    pub fn new(text: T) -> RecordingContext<T> {
+        RecordingContext {
+            text: RecordingText(text),
+            list: DisplayList {
+                commands: Vec::new(),
+            },
+            state: GraphicsState::default(),
+            tolerance: DEFAULT_TOLERANCE,
+            err: Ok(()),
+        }
+    }
+
+    /// The commands recorded so far.
+    pub fn display_list(&self) -> &DisplayList<T::TextLayout> {
+        &self.list
+    }
+
+    /// Stop recording, and return the commands recorded.
+    pub fn into_display_list(self) -> DisplayList<T::TextLayout> {
+        self.list
+    }
+
+    fn record(&mut self, command: DrawCommand<T::TextLayout>) -> Result<(), Error> {
+        self.state.update(&command)?;
+        self.list.commands.push(command);
+        Ok(())
+    }
+
+    /// `shape` as a path, in the current user space.
+    fn path(&self, shape: impl Shape) -> BezPath {
+        let tolerance = user_tolerance(self.tolerance, self.state.transform);
+        let tolerance = flattening_tolerance(tolerance, &shape);
+        shape.into_bez_path(tolerance)
+    }
+
+    /// Record a command that can't be out of balance.
+    fn push(&mut self, command: DrawCommand<T::TextLayout>) {
+        let _ = self.record(command);
+    }
+
+    fn paint(&mut self, brush: &impl IntoBrush<Self>, bbox: Rect) -> PaintBrush {
+        brush.make_brush(self, || bbox).into_owned()
+    }
+
+    fn fill_with(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, even_odd: bool) {
+        let shape = self.path(shape);
+        let brush = self.paint(brush, shape.bounding_box());
+        self.push(DrawCommand::Fill {
+            shape,
+            brush,
+            even_odd,
+        });
+    }
+
+    fn stroke_with(
+        &mut self,
+        shape: impl Shape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: Option<&StrokeStyle>,
+    ) {
+        let shape = self.path(shape);
+        let brush = self.paint(brush, shape.bounding_box());
+        self.push(DrawCommand::Stroke {
+            shape,
+            brush,
+            width,
+            style: style.cloned(),
+        });
+    }
+
+    fn text_with(
+        &mut self,
+        layout: &RecordedTextLayout<T::TextLayout>,
+        pos: Point,
+        brush: &impl IntoBrush<Self>,
+        glyph_transforms: Option<Vec<Affine>>,
+    ) {
+        let bbox = text_bounds(layout.layout(), pos).unwrap_or(Rect::ZERO);
+        let brush = self.paint(brush, bbox);
+        self.push(DrawCommand::Text {
+            layout: layout.clone(),
+            pos,
+            brush,
+            glyph_transforms,
+        });
+    }
+
+    fn image_interp(
+        &mut self,
+        image: &RecordedImage,
+        src_rect: Rect,
+        dst_rect: Rect,
+        alpha: f64,
+        interp: InterpolationMode,
+    ) {
+        self.push(DrawCommand::Image {
+            image: image.clone(),
+            src_rect,
+            dst_rect,
+            interp,
+            alpha,
+        });
+    }
+}
+
+impl<T: Text + Clone> RenderContext for RecordingContext<T> {
+    type Brush = PaintBrush;
+    type Image = RecordedImage;
+    type Path = BezPath;
+    type Text = RecordingText<T>;
+    type TextLayout = RecordedTextLayout<T::TextLayout>;
+
+    fn status(&mut self) -> Result<(), Error> {
+        std::mem::replace(&mut self.err, Ok(()))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            text: true,
+            images: true,
+            image_formats: &[
+                ImageFormat::Rgb,
+                ImageFormat::RgbaSeparate,
+                ImageFormat::RgbaPremul,
+                ImageFormat::BgraPremul,
+                ImageFormat::Grayscale,
+                ImageFormat::Alpha,
+            ],
+            nearest_neighbor_interpolation: true,
+            linear_gradients: true,
+            radial_gradients: true,
+            mesh_gradients: true,
+            dashes: true,
+            antialiasing: true,
+            picking: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn solid_brush(&mut self, color: Color) -> Self::Brush {
+        PaintBrush::Color(color)
+    }
+
+    fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Error> {
+        Ok(PaintBrush::Fixed(gradient.into()))
+    }
+
+    fn clear(&mut self, color: Color) {
+        self.push(DrawCommand::Clear(color));
+    }
+
+    fn clear_rect(&mut self, rect: Rect, color: Color) {
+        self.push(DrawCommand::ClearRect(rect, color));
+    }
+
+    fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
+        self.stroke_with(shape, brush, width, None);
+    }
+
+    fn stroke_styled(
+        &mut self,
+        shape: impl Shape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        if let Some((path, style)) = align_stroke(&shape, width, style) {
+            return self.stroke_styled(path, brush, width, &style);
+        }
+        self.stroke_with(shape, brush, width, Some(style));
+    }
+
+    fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        self.fill_with(shape, brush, false);
+    }
+
+    fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        self.fill_with(shape, brush, true);
+    }
+
+    fn clip(&mut self, shape: impl Shape) {
+        let shape = self.path(shape);
+        self.push(DrawCommand::Clip(shape));
+    }
+
+    fn clip_text(&mut self, layout: &Self::TextLayout, pos: impl Into<Point>) {
+        self.push(DrawCommand::ClipText {
+            layout: layout.clone(),
+            pos: pos.into(),
+        });
+    }
+
+    fn push_clip(&mut self, shape: impl Shape) {
+        let shape = self.path(shape);
+        self.push(DrawCommand::PushClip(shape));
+    }
+
+    fn pop_clip(&mut self) -> Result<(), Error> {
+        self.record(DrawCommand::PopClip)
+    }
+
+    fn reset_clip(&mut self) {
+        self.push(DrawCommand::ResetClip);
+    }
+
+    fn make_path(&mut self, shape: impl Shape) -> Result<Self::Path, Error> {
+        Ok(self.path(shape))
+    }
+
+    fn fill_path(&mut self, path: &Self::Path, brush: &impl IntoBrush<Self>) {
+        self.fill_with(path.clone(), brush, false);
+    }
+
+    fn stroke_path(&mut self, path: &Self::Path, brush: &impl IntoBrush<Self>, width: f64) {
+        self.stroke_with(path.clone(), brush, width, None);
+    }
+
+    fn stroke_path_styled(
+        &mut self,
+        path: &Self::Path,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        self.stroke_with(path.clone(), brush, width, Some(style));
+    }
+
+    fn text(&self) -> Self::Text {
+        self.text.clone()
+    }
+
+    fn draw_text(
+        &mut self,
+        layout: &Self::TextLayout,
+        pos: impl Into<Point>,
+        brush: &impl IntoBrush<Self>,
+    ) {
+        self.text_with(layout, pos.into(), brush, None);
+    }
+
+    fn draw_glyph_run(
+        &mut self,
+        layout: &Self::TextLayout,
+        pos: impl Into<Point>,
+        brush: &impl IntoBrush<Self>,
+        transforms: &[Affine],
+    ) {
+        self.text_with(layout, pos.into(), brush, Some(transforms.to_vec()));
+    }
+
+    fn draw_glyphs(
+        &mut self,
+        _font: &RecordedFont<T::Font>,
+        _glyphs: &[(u16, Point)],
+        _brush: &impl IntoBrush<Self>,
+    ) {
+        // a recorded font can't be sure of its face, so the ids wouldn't
+        // replay as the same glyphs
+    }
+
+    fn set_glyph_positioning(&mut self, positioning: GlyphPositioning) {
+        self.push(DrawCommand::GlyphPositioning(positioning));
+    }
+
+    fn set_image_positioning(&mut self, positioning: ImagePositioning) {
+        self.push(DrawCommand::ImagePositioning(positioning));
+    }
+
+    fn set_tolerance(&mut self, tolerance: f64) {
+        if tolerance > 0.0 && tolerance.is_finite() {
+            self.tolerance = tolerance;
+        }
+    }
+
+    fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    fn set_antialiasing(&mut self, mode: Antialiasing) {
+        self.push(DrawCommand::Antialiasing(mode));
+    }
+
+    fn set_pick_id(&mut self, id: Option<u64>) {
+        self.push(DrawCommand::PickId(id));
+    }
+
+    // everything is recorded, and what's missing is up to the context the
+    // list is replayed into
+    fn set_fallback_policy(&mut self, _policy: FallbackPolicy) {}
+
+    fn save(&mut self) -> Result<(), Error> {
+        self.record(DrawCommand::Save)
+    }
+
+    fn restore(&mut self) -> Result<(), Error> {
+        self.record(DrawCommand::Restore)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn transform(&mut self, transform: Affine) {
+        // not recorded, so replaying the list doesn't poison the target
+        match check_transform(self.state.transform * transform) {
+            Ok(()) => self.push(DrawCommand::Transform(transform)),
+            Err(e) => self.err = Err(e),
+        }
+    }
+
+    fn make_image(
+        &mut self,
+        width: usize,
+        height: usize,
+        buf: &[u8],
+        format: ImageFormat,
+    ) -> Result<Self::Image, Error> {
+        check_image_buffer(width, height, buf, format)?;
+        Ok(RecordedImage {
+            width,
+            height,
+            format,
+            pixels: buf.into(),
+        })
+    }
+
+    fn draw_image(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let src_rect = image.size().to_rect();
+        self.image_interp(image, src_rect, dst_rect.into(), 1.0, interp);
+    }
+
+    fn draw_image_area(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        self.image_interp(image, src_rect.into(), dst_rect.into(), 1.0, interp);
+    }
+
+    fn draw_image_transformed(
+        &mut self,
+        image: &Self::Image,
+        transform: Affine,
+        interp: InterpolationMode,
+    ) {
+        let rect = image.size().to_rect();
+        self.push(DrawCommand::Save);
+        self.push(DrawCommand::Transform(transform));
+        self.image_interp(image, rect, rect, 1.0, interp);
+        self.push(DrawCommand::Restore);
+    }
+
+    fn draw_tiles(
+        &mut self,
+        atlas: &Self::Image,
+        tiles: &[(Rect, Point)],
+        interp: InterpolationMode,
+    ) {
+        for &(cell, pos) in tiles {
+            let dst_rect = Rect::from_origin_size(pos, cell.size());
+            self.image_interp(atlas, cell, dst_rect, 1.0, interp);
+        }
+    }
+
+    fn draw_images(&mut self, images: &[(&Self::Image, Rect, f64)], interp: InterpolationMode) {
+        for &(image, dst_rect, alpha) in images {
+            self.image_interp(image, image.size().to_rect(), dst_rect, alpha, interp);
+        }
+    }
+
+    fn make_render_target(&mut self, _width: usize, _height: usize) -> Result<Self::Image, Error> {
+        Err(new_error(ErrorKind::NotSupported))
+    }
+
+    fn draw_to_target(
+        &mut self,
+        _target: &mut Self::Image,
+        _f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        Err(new_error(ErrorKind::NotSupported))
+    }
+
+    fn capture_image_area(&mut self, _src_rect: impl Into<Rect>) -> Result<Self::Image, Error> {
+        Err(new_error(ErrorKind::NotSupported))
+    }
+
+    fn with_blur(
+        &mut self,
+        radius: f64,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.push(DrawCommand::BeginBlur(radius));
+        let result = f(self);
+        self.push(DrawCommand::EndBlur);
+        result
+    }
+
+    fn trim_layer_pool(&mut self) {}
+
+    fn memory_report(&self) -> MemoryReport {
+        MemoryReport::default()
+    }
+
+    fn trim_caches(&mut self) {}
+
+    fn draw_image_nine(
+        &mut self,
+        image: &Self::Image,
+        insets: Insets,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        for (src_rect, dst_rect) in nine_slice(image.size(), insets, dst_rect.into()) {
+            self.image_interp(image, src_rect, dst_rect, 1.0, interp);
+        }
+    }
+
+    fn draw_image_clipped(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        shape: impl Shape,
+        interp: InterpolationMode,
+    ) {
+        self.push(DrawCommand::Save);
+        self.clip(shape);
+        self.draw_image(image, dst_rect, interp);
+        self.push(DrawCommand::Restore);
+    }
+
+    fn current_transform(&self) -> Affine {
+        self.state.transform
+    }
+}
+
+impl<L> DisplayList<L> {
+    pub fn commands(&self) -> &[DrawCommand<L>] {
+        &self.commands
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+impl<L: TextLayout> DisplayList<L> {
+    /// The indices of the commands that paint inside `lasso`, for selecting
+    /// objects with a lasso or a selection rectangle.
+    ///
+    /// The lasso is closed, and counts as the area it winds around; it's in
+    /// the coordinates the list was recorded in, before any of its
+    /// transforms. A command is hit if what it paints touches that area
+    /// anywhere: fills by their fill rule, strokes as everything within half
+    /// their width of the path, text by the box around its layout, images
+    /// by their destination rectangle, and clears by their rectangle, or
+    /// everywhere. Caps, joins and dashes aren't taken into account, and
+    /// neither are clips, so commands also count where they're clipped away.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::{Affine, Circle, Rect};
+    /// use piet::{Color, RecordingContext, RenderContext};
+    /// # use piet::NullText;
+    ///
+    /// let mut rc = RecordingContext::new(NullText);
+    /// rc.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::WHITE);
+    /// rc.transform(Affine::translate((100.0, 0.0)));
+    /// rc.stroke(Circle::new((0.0, 0.0), 20.0), &Color::WHITE, 2.0);
+    /// let list = rc.into_display_list();
+    ///
+    /// // the square, and the ring of the circle's stroke
+    /// assert_eq!(list.hit_test_lasso(Rect::new(5.0, 5.0, 90.0, 6.0)), [0, 2]);
+    /// // inside the circle, clear of its stroke
+    /// assert!(list.hit_test_lasso(Rect::new(95.0, -5.0, 105.0, 5.0)).is_empty());
+    /// ```
+    pub fn hit_test_lasso(&self, lasso: impl Shape) -> Vec<usize> {
+        let lasso = lasso.into_bez_path(LASSO_TOLERANCE);
+        let mut state = GraphicsState::default();
+        let mut hits = Vec::new();
+        for (i, command) in self.commands.iter().enumerate() {
+            let _ = state.update(command);
+            let coverage = match coverage(command) {
+                Some(coverage) => coverage,
+                None => continue,
+            };
+            let scale = state.transform.determinant().abs().sqrt();
+            if scale == 0.0 || !scale.is_finite() {
+                // nothing is drawn
+                continue;
+            }
+            let tolerance = LASSO_TOLERANCE / scale;
+            let lasso = flatten(state.transform.inverse() * lasso.clone(), tolerance);
+            if coverage.touches(&lasso, tolerance) {
+                hits.push(i);
+            }
+        }
+        hits
+    }
+
+    /// The pick ids of the commands that paint at `point`, topmost first,
+    /// each once. See [`set_pick_id`].
+    ///
+    /// The point is in the coordinates the list was recorded in, before any
+    /// of its transforms. Fills follow their fill rule, and strokes their
+    /// width, style and dashes, so points in a shape's holes or between
+    /// dashes don't pick it. Text is picked by the box around each of its
+    /// lines, and images by their destination rectangle. Clips made with
+    /// shapes are taken into account; clips to text aren't.
+    ///
+    /// [`set_pick_id`]: trait.RenderContext.html#tymethod.set_pick_id
+    pub fn pick(&self, point: Point) -> Vec<u64> {
+        let mut state = GraphicsState::default();
+        let mut id = None;
+        let mut picked = Vec::new();
+        for command in &self.commands {
+            let _ = state.update(command);
+            if let DrawCommand::PickId(new_id) = command {
+                id = *new_id;
+            }
+            let id = match id {
+                Some(id) => id,
+                None => continue,
+            };
+            let transform = state.transform;
+            if transform.determinant() == 0.0 || !state.clips_contain(point) {
+                continue;
+            }
+            if paints_at(command, transform.inverse() * point) {
+                // a later command is on top of the earlier ones
+                picked.retain(|&picked| picked != id);
+                picked.push(id);
+            }
+        }
+        picked.reverse();
+        picked
+    }
+
+    /// The box around everything the list paints, in the coordinates it was
+    /// recorded in, or `None` if it paints nothing.
+    ///
+    /// Each command's box is cut down to the boxes of the clips around it,
+    /// and what's blurred grows by three times the blur's radius. Strokes
+    /// take in their caps and joins, at the longest miter allowed, which
+    /// is 10 times half the width when their style doesn't say. Clears of
+    /// everything are everywhere, and their box is infinite.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::{Affine, Rect};
+    /// use piet::{Color, RecordingContext, RenderContext};
+    /// # use piet::NullText;
+    ///
+    /// let mut rc = RecordingContext::new(NullText);
+    /// rc.push_clip(Rect::new(0.0, 0.0, 15.0, 15.0));
+    /// rc.transform(Affine::translate((10.0, 0.0)));
+    /// rc.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::WHITE);
+    /// let list = rc.into_display_list();
+    ///
+    /// let bounds = list.paint_bounds().unwrap();
+    /// assert_eq!((bounds.x0, bounds.y0, bounds.x1, bounds.y1), (10.0, 0.0, 15.0, 10.0));
+    /// ```
+    pub fn paint_bounds(&self) -> Option<Rect> {
+        let mut state = GraphicsState::default();
+        let mut bounds = None;
+        // for each blur that's open, the bounds outside it and how far it
+        // spreads what's inside
+        let mut blurs = Vec::new();
+        for command in &self.commands {
+            match command {
+                DrawCommand::BeginBlur(radius) => {
+                    let scale = state.transform.determinant().abs().sqrt();
+                    blurs.push((bounds.take(), 3.0 * radius.abs() * scale));
+                }
+                DrawCommand::EndBlur => {
+                    if let Some((outside, spread)) = blurs.pop() {
+                        let _ = state.update(command);
+                        let blurred =
+                            bounds.map(|b: Rect| state.clip_bounds(b.inflate(spread, spread)));
+                        bounds = union(outside, blurred);
+                        continue;
+                    }
+                }
+                _ => (),
+            }
+            let _ = state.update(command);
+            let painted = match command {
+                DrawCommand::Clear(_) => Some(Rect::new(
+                    f64::NEG_INFINITY,
+                    f64::NEG_INFINITY,
+                    f64::INFINITY,
+                    f64::INFINITY,
+                )),
+                DrawCommand::Stroke {
+                    shape,
+                    width,
+                    style,
+                    ..
+                } => {
+                    let reach = stroke_reach(*width, style.as_ref());
+                    let user = shape.bounding_box().inflate(reach, reach);
+                    Some(state.clip_bounds(state.transform.transform_rect_bbox(user)))
+                }
+                _ => paint_bounds(command)
+                    .map(|user| state.clip_bounds(state.transform.transform_rect_bbox(user))),
+            };
+            bounds = union(bounds, painted);
+        }
+        // blurs left open are still blurred
+        while let Some((outside, spread)) = blurs.pop() {
+            bounds = union(outside, bounds.map(|b| b.inflate(spread, spread)));
+        }
+        bounds
+    }
+}
+
+fn union(a: Option<Rect>, b: Option<Rect>) -> Option<Rect> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.union(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// How far a stroke reaches from its path, with its caps and joins.
+fn stroke_reach(width: f64, style: Option<&StrokeStyle>) -> f64 {
+    let half = width.abs() / 2.0;
+    let join = style.and_then(|style| style.line_join);
+    let miter = match join {
+        Some(LineJoin::Miter) | None => style
+            .and_then(|style| style.miter_limit)
+            .unwrap_or(DEFAULT_MITER_LIMIT),
+        Some(LineJoin::Round) | Some(LineJoin::Bevel) => 1.0,
+    };
+    // square caps reach out to their corners
+    half * miter.max(std::f64::consts::SQRT_2)
+}
+
+/// What a command paints, in its user space.
+enum Coverage {
+    Everything,
+    Fill(BezPath, bool),
+    Stroke(BezPath, f64),
+}
+
+fn coverage<L: TextLayout>(command: &DrawCommand<L>) -> Option<Coverage> {
+    let coverage = match command {
+        DrawCommand::Clear(_) => Coverage::Everything,
+        DrawCommand::ClearRect(rect, _) => Coverage::Fill(rect.into_bez_path(0.0), false),
+        DrawCommand::Fill {
+            shape, even_odd, ..
+        } => Coverage::Fill(shape.clone(), *even_odd),
+        DrawCommand::Stroke { shape, width, .. } => {
+            Coverage::Stroke(shape.clone(), width.abs() / 2.0)
+        }
+        DrawCommand::Text { layout, pos, .. } => Coverage::Fill(
+            text_bounds(layout.layout(), *pos)?.into_bez_path(0.0),
+            false,
+        ),
+        DrawCommand::Image { dst_rect, .. } => Coverage::Fill(dst_rect.into_bez_path(0.0), false),
+        _ => return None,
+    };
+    Some(coverage)
+}
+
+impl Coverage {
+    /// Whether this touches the area inside `lasso`, flattened to
+    /// `tolerance`.
+    fn touches(&self, lasso: &[(Vec<Point>, bool)], tolerance: f64) -> bool {
+        if lasso.is_empty() {
+            return false;
+        }
+        let (shape, closed, reach) = match self {
+            Coverage::Everything => return true,
+            Coverage::Fill(shape, even_odd) => {
+                let shape = flatten(shape.elements(), tolerance);
+                let inside = |p: Point| {
+                    let winding = winding(&shape, p);
+                    if *even_odd {
+                        winding % 2 != 0
+                    } else {
+                        winding != 0
+                    }
+                };
+                if lasso.iter().any(|(points, _)| inside(points[0])) {
+                    return true;
+                }
+                (shape, true, 0.0)
+            }
+            Coverage::Stroke(shape, half) => (flatten(shape.elements(), tolerance), false, *half),
+        };
+        if shape
+            .iter()
+            .any(|(points, _)| winding(lasso, points[0]) != 0)
+        {
+            return true;
+        }
+        let near = edges(&shape, closed).any(|(a0, a1)| {
+            edges(lasso, true).any(|(b0, b1)| segment_distance(a0, a1, b0, b1) <= reach)
+        });
+        near
+    }
+}
+
+/// Whether a command paints at `point`, in its user space.
+fn paints_at<L: TextLayout>(command: &DrawCommand<L>, point: Point) -> bool {
+    match command {
+        DrawCommand::Clear(_) => true,
+        DrawCommand::ClearRect(rect, _) => rect.abs().contains(point),
+        DrawCommand::Fill {
+            shape, even_odd, ..
+        } => {
+            let winding = shape.winding(point);
+            if *even_odd {
+                winding % 2 != 0
+            } else {
+                winding != 0
+            }
+        }
+        DrawCommand::Stroke {
+            shape,
+            width,
+            style,
+            ..
+        } => match style {
+            Some(style) => stroke_contains(shape.elements(), *width, style, point),
+            None => stroke_contains(shape.elements(), *width, &StrokeStyle::new(), point),
+        },
+        DrawCommand::Text { layout, pos, .. } => layout
+            .layout()
+            .rects_for_range(0..usize::MAX)
+            .iter()
+            .any(|rect| rect.contains(point - pos.to_vec2())),
+        DrawCommand::Image { dst_rect, .. } => dst_rect.abs().contains(point),
+        _ => false,
+    }
+}
+
+pub(crate) fn text_bounds<L: TextLayout>(layout: &L, pos: Point) -> Option<Rect> {
+    // ranges past the end are clamped to the text
+    layout
+        .range_bounds(0..usize::MAX)
+        .map(|bounds| bounds + pos.to_vec2())
+}
+
+/// The edges of flattened subpaths, each closed if `close` is set.
+fn edges(
+    subpaths: &[(Vec<Point>, bool)],
+    close: bool,
+) -> impl Iterator<Item = (Point, Point)> + '_ {
+    subpaths.iter().flat_map(move |(points, _)| {
+        let closing = if close {
+            Some((points[points.len() - 1], points[0]))
+        } else {
+            None
+        };
+        points.windows(2).map(|w| (w[0], w[1])).chain(closing)
+    })
+}
+
+/// The winding number of closed subpaths around `p`.
+fn winding(subpaths: &[(Vec<Point>, bool)], p: Point) -> i32 {
+    edges(subpaths, true)
+        .map(|(a, b)| {
+            if a.y <= p.y && b.y > p.y && (b - a).cross(p - a) > 0.0 {
+                1
+            } else if b.y <= p.y && a.y > p.y && (b - a).cross(p - a) < 0.0 {
+                -1
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+fn segment_distance(a0: Point, a1: Point, b0: Point, b1: Point) -> f64 {
+    let (da, db) = (a1 - a0, b1 - b0);
+    let side = |d: Vec2, o: Point, p: Point| d.cross(p - o);
+    let crosses = side(da, a0, b0) * side(da, a0, b1) <= 0.0
+        && side(db, b0, a0) * side(db, b0, a1) <= 0.0
+        && da.cross(db) != 0.0;
+    if crosses {
+        return 0.0;
+    }
+    point_distance(a0, b0, b1)
+        .min(point_distance(a1, b0, b1))
+        .min(point_distance(b0, a0, a1))
+        .min(point_distance(b1, a0, a1))
+}
+
+/// The distance from `p` to the segment from `a` to `b`.
+fn point_distance(p: Point, a: Point, b: Point) -> f64 {
+    let d = b - a;
+    let len2 = d.hypot2();
+    let t = if len2 > 0.0 {
+        ((p - a).dot(d) / len2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (p - (a + d * t)).hypot()
+}
+
+impl<T: Text> Text for RecordingText<T> {
+    type Font = RecordedFont<T::Font>;
+    type FontBuilder = RecordingFontBuilder<T::FontBuilder>;
+    type TextLayout = RecordedTextLayout<T::TextLayout>;
+    type TextLayoutBuilder = RecordingTextLayoutBuilder<T::TextLayoutBuilder>;
+
+    fn new_font_by_name(&mut self, name: &str, size: f64) -> Self::FontBuilder {
+        RecordingFontBuilder {
+            builder: self.0.new_font_by_name(name, size),
+            face: RecordedFace {
+                family: name.to_owned(),
+                size,
+                weight: FontWeight::default(),
+                style: FontStyle::default(),
+                stretch: FontStretch::default(),
+                fallbacks: Vec::new(),
+                variations: Vec::new(),
+            },
+        }
+    }
+
+    fn new_text_layout(&mut self, font: &Self::Font, text: &str) -> Self::TextLayoutBuilder {
+        RecordingTextLayoutBuilder {
+            builder: self.0.new_text_layout(&font.font, text),
+            source: LayoutSource {
+                text: text.to_owned(),
+                face: font.face.clone(),
+                mask: None,
+                #[cfg(feature = "serde")]
+                options: Vec::new(),
+            },
+        }
+    }
+
+    fn set_font_substitutions(&mut self, substitutions: FontSubstitutions) {
+        self.0.set_font_substitutions(substitutions);
+    }
+
+    fn set_emoji_strategy(&mut self, strategy: EmojiStrategy) {
+        self.0.set_emoji_strategy(strategy);
+    }
+
+    fn load_font(&mut self, data: &[u8]) -> Result<String, Error> {
+        self.0.load_font(data)
+    }
+
+    fn set_system_fonts(&mut self, enabled: bool) {
+        self.0.set_system_fonts(enabled);
+    }
+
+    fn prewarm(&mut self, font: &Self::Font, text: &str) {
+        self.0.prewarm(&font.font, text);
+    }
+}
+
+impl<B: FontBuilder> FontBuilder for RecordingFontBuilder<B> {
+    type Out = RecordedFont<B::Out>;
+
+    fn weight(mut self, weight: FontWeight) -> Self {
+        self.builder = self.builder.weight(weight);
+        self.face.weight = weight;
+        self
+    }
+
+    fn style(mut self, style: FontStyle) -> Self {
+        self.builder = self.builder.style(style);
+        self.face.style = style;
+        self
+    }
+
+    fn stretch(mut self, stretch: FontStretch) -> Self {
+        self.builder = self.builder.stretch(stretch);
+        self.face.stretch = stretch;
+        self
+    }
+
+    fn fallback(mut self, family: &str) -> Self {
+        self.builder = self.builder.fallback(family);
+        self.face.fallbacks.push(family.to_owned());
+        self
+    }
+
+    fn variation(mut self, variation: FontVariation) -> Self {
+        self.builder = self.builder.variation(variation);
+        let variations = &mut self.face.variations;
+        variations.retain(|v| v.tag() != variation.tag());
+        variations.push(variation);
+        self
+    }
+
+    fn build(self) -> Result<Self::Out, Error> {
+        Ok(RecordedFont {
+            font: self.builder.build()?,
+            face: self.face,
+        })
+    }
+}
+
+impl<F> RecordedFont<F> {
+    /// The font made by the backend's text factory.
+    pub fn font(&self) -> &F {
+        &self.font
+    }
+
+    /// The family name the font was asked for by.
+    pub fn family(&self) -> &str {
+        &self.face.family
+    }
+
+    pub fn size(&self) -> f64 {
+        self.face.size
+    }
+
+    pub fn weight(&self) -> FontWeight {
+        self.face.weight
+    }
+
+    pub fn style(&self) -> FontStyle {
+        self.face.style
+    }
+
+    pub fn stretch(&self) -> FontStretch {
+        self.face.stretch
+    }
+
+    /// The fallback families the font was given, in order.
+    pub fn fallbacks(&self) -> &[String] {
+        &self.face.fallbacks
+    }
+
+    /// The variable font axes the font was given, one for each axis.
+    pub fn variations(&self) -> &[FontVariation] {
+        &self.face.variations
+    }
+}
+
+impl<F: Font> Font for RecordedFont<F> {
+    fn warnings(&self) -> &[FontWarning] {
+        self.font.warnings()
+    }
+
+    fn decoration_metrics(&self) -> DecorationMetrics {
+        self.font.decoration_metrics()
+    }
+}
+
+impl<B: TextLayoutBuilder> TextLayoutBuilder for RecordingTextLayoutBuilder<B> {
+    type Out = RecordedTextLayout<B::Out>;
+
+    fn obscure(mut self, mask: char) -> Self {
+        self.builder = self.builder.obscure(mask);
+        self.source.mask = Some(mask);
+        self
+    }
+
+    fn range_attribute(mut self, range: Range<usize>, attribute: TextAttribute) -> Self {
+        #[cfg(feature = "serde")]
+        self.source
+            .options
+            .push(LayoutOption::Attribute(range.clone(), attribute.clone()));
+        self.builder = self.builder.range_attribute(range, attribute);
+        self
+    }
+
+    fn default_color(mut self, color: Color) -> Self {
+        #[cfg(feature = "serde")]
+        self.source
+            .options
+            .push(LayoutOption::DefaultColor(color.clone()));
+        self.builder = self.builder.default_color(color);
+        self
+    }
+
+    fn transform(mut self, transform: Affine) -> Self {
+        self.builder = self.builder.transform(transform);
+        #[cfg(feature = "serde")]
+        self.source.options.push(LayoutOption::Transform(transform));
+        self
+    }
+
+    fn max_width(mut self, width: f64) -> Self {
+        self.builder = self.builder.max_width(width);
+        #[cfg(feature = "serde")]
+        self.source.options.push(LayoutOption::MaxWidth(width));
+        self
+    }
+
+    fn alignment(mut self, alignment: TextAlignment) -> Self {
+        self.builder = self.builder.alignment(alignment);
+        #[cfg(feature = "serde")]
+        self.source.options.push(LayoutOption::Alignment(alignment));
+        self
+    }
+
+    fn max_lines(mut self, lines: usize, truncation: TextTruncation) -> Self {
+        self.builder = self.builder.max_lines(lines, truncation);
+        #[cfg(feature = "serde")]
+        self.source
+            .options
+            .push(LayoutOption::MaxLines(lines, truncation));
+        self
+    }
+
+    fn writing_mode(mut self, mode: WritingMode) -> Self {
+        self.builder = self.builder.writing_mode(mode);
+        #[cfg(feature = "serde")]
+        self.source.options.push(LayoutOption::WritingMode(mode));
+        self
+    }
+
+    fn build(self) -> Result<Self::Out, Error> {
+        Ok(RecordedTextLayout {
+            layout: Arc::new(self.builder.build()?),
+            source: Arc::new(self.source),
+        })
+    }
+}
+
+impl<L> RecordedTextLayout<L> {
+    /// The layout made by the backend's text factory.
+    pub fn layout(&self) -> &L {
+        &self.layout
+    }
+
+    /// The text the layout was made from, before any masking.
+    pub fn text(&self) -> &str {
+        &self.source.text
+    }
+
+    /// The family name of the layout's font, as it was asked for.
+    pub fn font_family(&self) -> &str {
+        &self.source.face.family
+    }
+
+    pub fn font_size(&self) -> f64 {
+        self.source.face.size
+    }
+
+    pub fn font_weight(&self) -> FontWeight {
+        self.source.face.weight
+    }
+
+    pub fn font_style(&self) -> FontStyle {
+        self.source.face.style
+    }
+
+    pub fn font_stretch(&self) -> FontStretch {
+        self.source.face.stretch
+    }
+
+    /// The fallback families of the layout's font, in order.
+    pub fn font_fallbacks(&self) -> &[String] {
+        &self.source.face.fallbacks
+    }
+
+    /// The variable font axes of the layout's font, one for each axis.
+    pub fn font_variations(&self) -> &[FontVariation] {
+        &self.source.face.variations
+    }
+
+    /// The character the text is displayed as, if it's obscured.
+    pub fn mask(&self) -> Option<char> {
+        self.source.mask
+    }
+}
+
+impl<L> Clone for RecordedTextLayout<L> {
+    fn clone(&self) -> Self {
+        RecordedTextLayout {
+            layout: self.layout.clone(),
+            source: self.source.clone(),
+        }
+    }
+}
+
+impl<L: TextLayout> TextLayout for RecordedTextLayout<L> {
+    fn width(&self) -> f64 {
+        self.layout.width()
+    }
+
+    fn hit_test_point(&self, point: Point) -> HitTestPoint {
+        self.layout.hit_test_point(point)
+    }
+
+    fn hit_test_text_position(&self, text_position: usize) -> Option<HitTestTextPosition> {
+        self.layout.hit_test_text_position(text_position)
+    }
+
+    fn range_bounds(&self, range: Range<usize>) -> Option<Rect> {
+        self.layout.range_bounds(range)
+    }
+
+    fn rects_for_range(&self, range: Range<usize>) -> Vec<Rect> {
+        self.layout.rects_for_range(range)
+    }
+
+    fn caret_bounds(&self, text_position: usize) -> Option<Rect> {
+        self.layout.caret_bounds(text_position)
+    }
+
+    fn line_metrics(&self) -> Vec<LineMetric> {
+        self.layout.line_metrics()
+    }
+
+    fn cluster_metrics(&self) -> Vec<ClusterMetric> {
+        self.layout.cluster_metrics()
+    }
+
+    fn decoration_metrics(&self) -> DecorationMetrics {
+        self.layout.decoration_metrics()
+    }
+
+    fn truncated_at(&self) -> Option<usize> {
+        self.layout.truncated_at()
+    }
+}
+
+impl RecordedImage {
+    /// The pixels the image was made from, in its format.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+}
+
+impl Image for RecordedImage {
+    fn size(&self) -> Size {
+        Size::new(self.width as f64, self.height as f64)
+    }
+
+    fn format(&self) -> ImageFormat {
+        self.format
+    }
+}
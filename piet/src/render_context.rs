@@ -1,16 +1,21 @@
 //! The main render context trait.
 
 use std::borrow::Cow;
+use std::ops::{Deref, DerefMut};
 
-use kurbo::{Affine, Point, Rect, Shape};
+use kurbo::{Affine, BezPath, Insets, Line, Point, Rect, Shape};
 
+use crate::{grid_background_runs, grid_foreground_runs};
 use crate::{
-    Color, Error, FixedGradient, FixedLinearGradient, FixedRadialGradient, LinearGradient,
-    RadialGradient, StrokeStyle, Text, TextLayout,
+    snap_path_to_pixels, text_on_path_transforms, user_tolerance, Capabilities, Color, DrawProfile,
+    Error, FallbackPolicy, FixedGradient, FixedLinearGradient, FixedMeshGradient,
+    FixedRadialGradient, GlyphPositioning, GridCell, GridMetrics, Image, LinearGradient,
+    MemoryReport, RadialGradient, StrokeStyle, Text, TextLayout, TextLayoutBuilder,
 };
 
 /// A requested interpolation mode for drawing images.
 #[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InterpolationMode {
     /// Don't interpolate, use nearest neighbor.
     NearestNeighbor,
@@ -18,8 +23,52 @@ pub enum InterpolationMode {
     Bilinear,
 }
 
+/// How the destination rectangles of images are placed relative to the
+/// device pixel grid.
+///
+/// Set with [`RenderContext::set_image_positioning`]. The default is
+/// [`Exact`](#variant.Exact).
+///
+/// [`RenderContext::set_image_positioning`]: trait.RenderContext.html#tymethod.set_image_positioning
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImagePositioning {
+    /// Draw images at exactly the requested rectangle, including fractional
+    /// pixel edges.
+    #[default]
+    Exact,
+    /// Round the edges of the rectangle to whole device pixels when the
+    /// transform is axis-aligned, as [`snap_rect_to_pixels`] does.
+    ///
+    /// Rectangles computed by layout code often land on half pixels, which
+    /// blurs an image drawn at its natural size across its neighbors.
+    ///
+    /// [`snap_rect_to_pixels`]: fn.snap_rect_to_pixels.html
+    PixelSnapped,
+}
+
+/// How the edges of shapes and text are antialiased.
+///
+/// Backends that don't support a mode use the closest one they have.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Antialiasing {
+    /// Whatever the backend or platform considers appropriate.
+    #[default]
+    Default,
+    /// No antialiasing: pixels are either covered or not. Useful for pixel
+    /// art and when speed matters more than smooth edges.
+    None,
+    /// Antialias using shades of the drawing color.
+    Grayscale,
+    /// Antialias using the individual color subpixels of an LCD. Only text
+    /// rendering distinguishes this from `Grayscale`.
+    Subpixel,
+}
+
 /// The pixel format for bitmap images.
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ImageFormat {
     /// 3 bytes per pixel, in RGB order.
     Rgb,
@@ -27,6 +76,17 @@ pub enum ImageFormat {
     RgbaSeparate,
     /// 4 bytes per pixel, in RGBA order, with premultiplied alpha.
     RgbaPremul,
+    /// 4 bytes per pixel, in BGRA order, with premultiplied alpha.
+    ///
+    /// This is the native layout of Cairo and Direct2D surfaces on
+    /// little-endian machines, so those backends can copy it without
+    /// converting each pixel.
+    BgraPremul,
+    /// 1 byte per pixel, a gray level. The image is opaque.
+    Grayscale,
+    /// 1 byte per pixel, opacity only, as in a mask or glyph atlas. The image
+    /// draws as black with this opacity.
+    Alpha,
     /// More formats may be added later.
     #[doc(hidden)]
     _NonExhaustive,
@@ -36,7 +96,8 @@ impl ImageFormat {
     pub fn bytes_per_pixel(self) -> usize {
         match self {
             ImageFormat::Rgb => 3,
-            ImageFormat::RgbaPremul | ImageFormat::RgbaSeparate => 4,
+            ImageFormat::RgbaPremul | ImageFormat::RgbaSeparate | ImageFormat::BgraPremul => 4,
+            ImageFormat::Grayscale | ImageFormat::Alpha => 1,
             _ => panic!(),
         }
     }
@@ -63,11 +124,19 @@ where
     type Brush: Clone;
 
     /// An associated factory for creating text layouts and related resources.
-    type Text: Text<TextLayout = Self::TextLayout>;
+    ///
+    /// It's a handle: clones share the fonts loaded and the settings made
+    /// with any of them, and with the context.
+    type Text: Text<TextLayout = Self::TextLayout> + Clone;
     type TextLayout: TextLayout;
 
     /// The associated type of an image.
-    type Image;
+    type Image: Image;
+
+    /// A shape converted for the backend, made with [`make_path`].
+    ///
+    /// [`make_path`]: #tymethod.make_path
+    type Path;
 
     /// Report an internal error.
     ///
@@ -76,6 +145,9 @@ where
     /// any such error that has been detected.
     fn status(&mut self) -> Result<(), Error>;
 
+    /// Report which optional features this backend supports.
+    fn capabilities(&self) -> Capabilities;
+
     /// Create a new brush resource.
     ///
     /// TODO: figure out how to document lifetime and rebuilding requirements. Should
@@ -86,13 +158,30 @@ where
     fn solid_brush(&mut self, color: Color) -> Self::Brush;
 
     /// Create a new gradient brush.
+    ///
+    /// Backends without mesh gradients (see [`Capabilities`]) make a solid
+    /// brush for them, or return [`ErrorKind::NotSupported`], as set by the
+    /// [`FallbackPolicy`].
+    ///
+    /// [`Capabilities`]: struct.Capabilities.html
+    /// [`ErrorKind::NotSupported`]: enum.ErrorKind.html#variant.NotSupported
+    /// [`FallbackPolicy`]: enum.FallbackPolicy.html
     fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Error>;
 
     /// Clear the canvas with the given color.
     ///
-    /// Note: only opaque colors are meaningful.
+    /// Every pixel is replaced, alpha included, so a transparent color
+    /// erases the canvas. The current transform doesn't apply.
     fn clear(&mut self, color: Color);
 
+    /// Replace the pixels in `rect` with `color`, alpha included.
+    ///
+    /// Unlike a fill, nothing underneath shows through a translucent color,
+    /// which makes this suitable for resetting a dirty region of a surface
+    /// that persists between frames. The rectangle is in user space; under
+    /// a rotation, backends may clear its bounding box instead.
+    fn clear_rect(&mut self, rect: Rect, color: Color);
+
     /// Stroke a shape.
     fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64);
 
@@ -105,24 +194,346 @@ where
         style: &StrokeStyle,
     );
 
+    /// Stroke a shape with a hairline: a crisp line exactly one device pixel
+    /// wide, whatever the current transform.
+    ///
+    /// The shape is snapped to pixel centers with [`snap_path_to_pixels`], so
+    /// thin borders don't blur across two pixels at fractional coordinates.
+    /// Gradient brushes are resolved in device space. Nothing is drawn if the
+    /// transform is degenerate.
+    ///
+    /// [`snap_path_to_pixels`]: fn.snap_path_to_pixels.html
+    fn stroke_hairline(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let transform = self.current_transform();
+        if transform.determinant() == 0.0 {
+            return;
+        }
+        let tolerance = user_tolerance(self.tolerance(), transform);
+        let device_path = snap_path_to_pixels(
+            transform * shape.into_bez_path(tolerance),
+            Affine::default(),
+            1.0,
+        );
+        let _ = self.with_save(|rc| {
+            rc.transform(transform.inverse());
+            rc.stroke(device_path, brush, 1.0);
+            Ok(())
+        });
+    }
+
     /// Fill a shape, using non-zero fill rule.
     fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>);
 
     /// Fill a shape, using even-odd fill rule
     fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>);
 
+    /// Fill many rectangles with one brush, as for the bars of a chart.
+    ///
+    /// This is one fill of all the rectangles together, which backends draw
+    /// as one path or with a call that takes rectangles in bulk, rather than
+    /// a call for each. It looks the same as filling them one by one, except
+    /// that where they overlap a translucent brush is applied once, not once
+    /// for each, and gradients span the box around all of them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::Rect;
+    /// use piet::{Color, RenderContext};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let values = [3.0, 7.0, 4.0, 9.0];
+    /// let bars: Vec<_> = values
+    ///     .iter()
+    ///     .enumerate()
+    ///     .map(|(i, v)| Rect::new(i as f64 * 12.0, 100.0 - v * 10.0, i as f64 * 12.0 + 10.0, 100.0))
+    ///     .collect();
+    /// rc.fill_rects(&bars, &Color::rgb8(0x1f, 0x77, 0xb4));
+    /// ```
+    fn fill_rects(&mut self, rects: &[Rect], brush: &impl IntoBrush<Self>) {
+        if rects.is_empty() {
+            return;
+        }
+        let mut path = BezPath::new();
+        for rect in rects {
+            // the same way round, so overlaps don't cancel out
+            for el in rect.abs().to_bez_path(0.0) {
+                path.push(el);
+            }
+        }
+        self.fill(path, brush);
+    }
+
+    /// Stroke many lines with one brush and width, as for the ticks of an
+    /// axis or the edges of a graph.
+    ///
+    /// Like [`fill_rects`], this is one stroke of all the lines together,
+    /// so where they cross a translucent brush is applied once.
+    ///
+    /// [`fill_rects`]: #method.fill_rects
+    fn draw_lines(&mut self, lines: &[Line], brush: &impl IntoBrush<Self>, width: f64) {
+        if lines.is_empty() {
+            return;
+        }
+        let mut path = BezPath::new();
+        for line in lines {
+            path.move_to(line.p0);
+            path.line_to(line.p1);
+        }
+        self.stroke(path, brush, width);
+    }
+
+    /// Fill a copy of `marker` centered on each of `points`, as for the
+    /// markers of a scatter plot.
+    ///
+    /// `marker` is drawn around the origin, and moved to each point. Like
+    /// [`fill_rects`], this is one fill of all the copies together.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::{Circle, Point};
+    /// use piet::{Color, RenderContext};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let data: Vec<_> = (0..1000)
+    ///     .map(|i| Point::new(i as f64 * 0.5, 50.0 + (i as f64 * 0.1).sin() * 40.0))
+    ///     .collect();
+    /// rc.draw_markers(Circle::new(Point::ORIGIN, 2.0), &data, &Color::BLACK);
+    /// ```
+    ///
+    /// [`fill_rects`]: #method.fill_rects
+    fn draw_markers(&mut self, marker: impl Shape, points: &[Point], brush: &impl IntoBrush<Self>) {
+        if points.is_empty() {
+            return;
+        }
+        let tolerance = user_tolerance(self.tolerance(), self.current_transform());
+        let marker = marker.into_bez_path(tolerance);
+        let marker = marker.elements();
+        let mut elements = Vec::with_capacity(marker.len() * points.len());
+        for &point in points {
+            let offset = Affine::translate(point.to_vec2());
+            elements.extend(marker.iter().map(|&el| offset * el));
+        }
+        self.fill(BezPath::from_vec(elements), brush);
+    }
+
     /// Clip to a shape.
     ///
     /// All subsequent drawing operations up to the next [`restore`](#method.restore)
     /// are clipped by the shape.
+    ///
+    /// The clip is antialiased: pixels the shape's edge passes through keep a
+    /// share of what's drawn in them matching how much the shape covers, so
+    /// rounded corners look the same on every backend. The exception is a
+    /// clip set while [`Antialiasing::None`] is in effect, which backends
+    /// may apply without antialiasing.
+    ///
+    /// Backends whose native clips are aliased draw the clipped content on a
+    /// copy of the render target, and blend it back through the shape when
+    /// the clip ends, so each clip costs a copy of the target there.
+    ///
+    /// [`Antialiasing::None`]: enum.Antialiasing.html#variant.None
     fn clip(&mut self, shape: impl Shape);
 
-    fn text(&mut self) -> &mut Self::Text;
+    /// Clip to the glyphs of a text layout.
+    ///
+    /// This works like [`clip`] with the outlines of the text as the shape,
+    /// so that what's drawn next, such as an image or a gradient spanning
+    /// several glyphs, shows only through the letters. `pos` is the start of
+    /// the baseline, as for [`draw_text`], and the clip lasts until the next
+    /// [`restore`]. Backgrounds and colors of the layout play no part.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::Rect;
+    /// use piet::{Color, FontBuilder, LinearGradient, RenderContext, Text, TextLayoutBuilder};
+    /// use piet::UnitPoint;
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let font = rc.text().new_font_by_name("sans-serif", 48.0).build().unwrap();
+    /// let layout = rc.text().new_text_layout(&font, "Sunset").build().unwrap();
+    /// let sunset = LinearGradient::new(
+    ///     UnitPoint::TOP,
+    ///     UnitPoint::BOTTOM,
+    ///     (Color::rgb8(0xff, 0xc0, 0x40), Color::rgb8(0x80, 0x20, 0x60)),
+    /// );
+    /// rc.with_save(|rc| {
+    ///     rc.clip_text(&layout, (10.0, 60.0));
+    ///     rc.fill(Rect::new(10.0, 10.0, 300.0, 70.0), &sunset);
+    ///     Ok(())
+    /// })
+    /// .unwrap();
+    /// ```
+    ///
+    /// [`clip`]: #tymethod.clip
+    /// [`draw_text`]: #tymethod.draw_text
+    /// [`restore`]: #tymethod.restore
+    fn clip_text(&mut self, layout: &Self::TextLayout, pos: impl Into<Point>);
+
+    /// Clip to a shape, until the matching [`pop_clip`].
+    ///
+    /// This is [`clip`] with a stack of its own, for nested clips that come
+    /// off one at a time. Popping leaves the rest of the context state
+    /// alone, so a transform applied after the push is still in effect
+    /// after the pop.
+    ///
+    /// Pushed clips nest within saves: [`restore`] pops the clips pushed
+    /// since its [`save`], and a clip can't be popped past a save.
+    ///
+    /// # Examples
+    ///
+    /// Clipping each level of a widget tree to its bounds:
+    ///
+    /// ```
+    /// use piet::kurbo::{Affine, Rect};
+    /// use piet::{Color, RenderContext};
+    ///
+    /// struct Widget {
+    ///     bounds: Rect,
+    ///     children: Vec<Widget>,
+    /// }
+    ///
+    /// fn paint(rc: &mut impl RenderContext, widget: &Widget) {
+    ///     rc.push_clip(widget.bounds);
+    ///     rc.fill(widget.bounds, &Color::grey(0.9));
+    ///     // children are positioned within their parent
+    ///     rc.transform(Affine::translate(widget.bounds.origin().to_vec2()));
+    ///     for child in &widget.children {
+    ///         paint(rc, child);
+    ///     }
+    ///     rc.transform(Affine::translate(-widget.bounds.origin().to_vec2()));
+    ///     rc.pop_clip().unwrap();
+    /// }
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let leaf = Widget { bounds: Rect::new(5.0, 5.0, 50.0, 20.0), children: vec![] };
+    /// let root = Widget { bounds: Rect::new(0.0, 0.0, 40.0, 40.0), children: vec![leaf] };
+    /// paint(&mut rc, &root);
+    /// ```
+    ///
+    /// [`clip`]: #tymethod.clip
+    /// [`pop_clip`]: #tymethod.pop_clip
+    /// [`restore`]: #tymethod.restore
+    /// [`save`]: #tymethod.save
+    fn push_clip(&mut self, shape: impl Shape);
+
+    /// Remove the clip set by the last [`push_clip`], along with any
+    /// [`clip`] or [`clip_text`] since.
+    ///
+    /// Returns an error if no clip was pushed since the last
+    /// [`save`](#tymethod.save).
+    ///
+    /// [`clip`]: #tymethod.clip
+    /// [`clip_text`]: #tymethod.clip_text
+    /// [`push_clip`]: #tymethod.push_clip
+    fn pop_clip(&mut self) -> Result<(), Error>;
+
+    /// Remove every clip set since the last [`save`], whether pushed or
+    /// not, leaving the transform as it is.
+    ///
+    /// Clips set before the save still apply, and those removed don't come
+    /// back on [`restore`]. With no save in effect, drawing is unclipped.
+    ///
+    /// [`restore`]: #tymethod.restore
+    /// [`save`]: #tymethod.save
+    fn reset_clip(&mut self);
+
+    /// Convert a shape once, for filling or stroking it many times.
+    ///
+    /// Drawing a shape converts it to the backend's own path representation
+    /// each time. For scenes that redraw the same complex shapes every frame,
+    /// making a path once and drawing it with [`fill_path`] and
+    /// [`stroke_path`] skips that work.
+    ///
+    /// Paths are in user space: they're drawn with the transform in effect
+    /// when drawing, not when they were made.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::{Affine, BezPath};
+    /// use piet::{Color, RenderContext};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let mut star = BezPath::new();
+    /// star.move_to((50.0, 0.0));
+    /// for i in 1..5 {
+    ///     let angle = i as f64 * 4.0 * std::f64::consts::PI / 5.0;
+    ///     star.line_to((50.0 + 50.0 * angle.sin(), 50.0 - 50.0 * angle.cos()));
+    /// }
+    /// star.close_path();
+    ///
+    /// let star = rc.make_path(star).unwrap();
+    /// for i in 0..10 {
+    ///     rc.with_save(|rc| {
+    ///         rc.transform(Affine::translate((i as f64 * 100.0, 0.0)));
+    ///         rc.fill_path(&star, &Color::WHITE);
+    ///         rc.stroke_path(&star, &Color::BLACK, 2.0);
+    ///         Ok(())
+    ///     })
+    ///     .unwrap();
+    /// }
+    /// ```
+    ///
+    /// [`fill_path`]: #tymethod.fill_path
+    /// [`stroke_path`]: #tymethod.stroke_path
+    fn make_path(&mut self, shape: impl Shape) -> Result<Self::Path, Error>;
+
+    /// Fill a path made with [`make_path`], using non-zero fill rule.
+    ///
+    /// [`make_path`]: #tymethod.make_path
+    fn fill_path(&mut self, path: &Self::Path, brush: &impl IntoBrush<Self>);
+
+    /// Stroke a path made with [`make_path`].
+    ///
+    /// [`make_path`]: #tymethod.make_path
+    fn stroke_path(&mut self, path: &Self::Path, brush: &impl IntoBrush<Self>, width: f64);
+
+    /// Stroke a path made with [`make_path`], with a style.
+    ///
+    /// [`make_path`]: #tymethod.make_path
+    fn stroke_path_styled(
+        &mut self,
+        path: &Self::Path,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    );
+
+    /// A handle to the context's text factory.
+    ///
+    /// The handle doesn't borrow the context, so layouts can be built in
+    /// the middle of drawing, and a handle can be kept for as long as the
+    /// context is used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::Point;
+    /// use piet::{Color, FontBuilder, RenderContext, Text, TextLayoutBuilder};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let mut text = rc.text();
+    /// let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+    /// let brush = rc.solid_brush(Color::BLACK);
+    /// for label in &["one", "two"] {
+    ///     let layout = text.new_text_layout(&font, label).build().unwrap();
+    ///     rc.draw_text(&layout, Point::ORIGIN, &brush);
+    /// }
+    /// ```
+    fn text(&self) -> Self::Text;
 
     /// Draw a text layout.
     ///
     /// The `pos` parameter specifies the baseline of the left starting place of
     /// the text. Note: this is true even if the text is right-to-left.
+    ///
+    /// `brush` paints the text the layout doesn't give a color; see
+    /// [`TextLayoutBuilder::default_color`].
+    ///
+    /// [`TextLayoutBuilder::default_color`]: trait.TextLayoutBuilder.html#tymethod.default_color
     fn draw_text(
         &mut self,
         layout: &Self::TextLayout,
@@ -130,6 +541,274 @@ where
         brush: &impl IntoBrush<Self>,
     );
 
+    /// Draw a text layout with a transform applied to each grapheme cluster,
+    /// for animating text.
+    ///
+    /// `transforms[i]` applies to the `i`th grapheme cluster of the displayed
+    /// text, in a space whose origin is the cluster's pen position on the
+    /// baseline; clusters past the end of `transforms` are drawn in place.
+    /// The glyphs of a cluster move together, so combining marks stay on
+    /// their base.
+    ///
+    /// The layout is not shaped again, so this is cheap to call every frame.
+    /// Background attributes are drawn untransformed.
+    ///
+    /// # Examples
+    ///
+    /// A wave:
+    ///
+    /// ```
+    /// use piet::kurbo::Affine;
+    /// use piet::{Color, FontBuilder, RenderContext, Text, TextLayoutBuilder};
+    ///
+    /// # let mut render_ctx = piet::NullRenderContext::new();
+    /// # let time = 0.0_f64;
+    /// let font = render_ctx.text().new_font_by_name("sans-serif", 24.0).build().unwrap();
+    /// let layout = render_ctx.text().new_text_layout(&font, "wavy").build().unwrap();
+    /// let transforms: Vec<_> = (0..4)
+    ///     .map(|i| Affine::translate((0.0, 4.0 * (time + i as f64).sin())))
+    ///     .collect();
+    /// render_ctx.draw_glyph_run(&layout, (10.0, 40.0), &Color::BLACK, &transforms);
+    /// ```
+    fn draw_glyph_run(
+        &mut self,
+        layout: &Self::TextLayout,
+        pos: impl Into<Point>,
+        brush: &impl IntoBrush<Self>,
+        transforms: &[Affine],
+    );
+
+    /// Draw a text layout bent along the outline of `shape`, for labels on
+    /// map roads or around badges.
+    ///
+    /// The first baseline follows the outline from its start, and each
+    /// grapheme cluster is turned to the outline's direction at its center;
+    /// see [`text_on_path_transforms`] for the details. Align the layout to
+    /// move the text along the outline.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::BezPath;
+    /// use piet::{Color, FontBuilder, RenderContext, Text, TextLayoutBuilder};
+    ///
+    /// # let mut render_ctx = piet::NullRenderContext::new();
+    /// let font = render_ctx.text().new_font_by_name("sans-serif", 14.0).build().unwrap();
+    /// let layout = render_ctx.text().new_text_layout(&font, "Main Street").build().unwrap();
+    /// let mut road = BezPath::new();
+    /// road.move_to((20.0, 100.0));
+    /// road.curve_to((60.0, 20.0), (140.0, 20.0), (180.0, 100.0));
+    /// render_ctx.draw_text_on_path(&layout, &road, &Color::BLACK);
+    /// ```
+    ///
+    /// [`text_on_path_transforms`]: fn.text_on_path_transforms.html
+    fn draw_text_on_path(
+        &mut self,
+        layout: &Self::TextLayout,
+        shape: impl Shape,
+        brush: &impl IntoBrush<Self>,
+    ) {
+        let tolerance = user_tolerance(self.tolerance(), self.current_transform());
+        let transforms = text_on_path_transforms(&layout.cluster_metrics(), shape, tolerance);
+        self.draw_glyph_run(layout, Point::ORIGIN, brush, &transforms);
+    }
+
+    /// Draw glyphs that were shaped and positioned by the caller, such as
+    /// with harfbuzz or swash.
+    ///
+    /// Each glyph is a glyph id in `font`'s face, and the origin of the glyph
+    /// on the baseline, in user space. The glyphs are drawn exactly where
+    /// they're placed; [`set_glyph_positioning`] doesn't apply to them, and
+    /// ids the face doesn't have draw its missing glyph.
+    ///
+    /// Only backends that report [`Capabilities::glyph_drawing`] can look
+    /// glyphs up by id; the rest draw nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::Point;
+    /// use piet::{Color, FontBuilder, RenderContext, Text};
+    ///
+    /// # let mut render_ctx = piet::NullRenderContext::new();
+    /// let font = render_ctx.text().new_font_by_name("sans-serif", 16.0).build().unwrap();
+    /// // the output of a shaper: ids, and advances of 9 pixels
+    /// let glyphs: Vec<_> = [43, 72, 79]
+    ///     .iter()
+    ///     .enumerate()
+    ///     .map(|(i, &id)| (id, Point::new(10.0 + 9.0 * i as f64, 30.0)))
+    ///     .collect();
+    /// if render_ctx.capabilities().glyph_drawing {
+    ///     render_ctx.draw_glyphs(&font, &glyphs, &Color::BLACK);
+    /// }
+    /// ```
+    ///
+    /// [`set_glyph_positioning`]: #tymethod.set_glyph_positioning
+    /// [`Capabilities::glyph_drawing`]: struct.Capabilities.html#structfield.glyph_drawing
+    fn draw_glyphs(
+        &mut self,
+        font: &<Self::Text as Text>::Font,
+        glyphs: &[(u16, Point)],
+        brush: &impl IntoBrush<Self>,
+    );
+
+    /// Draw a row of a character grid, such as a terminal screen.
+    ///
+    /// `origin` is the top-left corner of the first cell. Cell backgrounds
+    /// are painted first, then the glyphs, each starting on its cell. The
+    /// font should be monospace, and wide characters aren't given two cells.
+    ///
+    /// Compared to laying out and drawing each cell, this batches runs of
+    /// cells that share a color, and backends may cache glyph lookups.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::{Color, FontBuilder, GridCell, GridMetrics, RenderContext, Text};
+    ///
+    /// # let mut render_ctx = piet::NullRenderContext::new();
+    /// let font = render_ctx.text().new_font_by_name("monospace", 12.0).build().unwrap();
+    /// let metrics = GridMetrics::measure(&mut render_ctx.text(), &font).unwrap();
+    /// let row: Vec<_> = "$ ls"
+    ///     .chars()
+    ///     .map(|ch| GridCell::new(ch, Color::WHITE).with_bg(Color::BLACK))
+    ///     .collect();
+    /// render_ctx.draw_grid_row(&font, &metrics, (0.0, 0.0), &row);
+    /// ```
+    fn draw_grid_row(
+        &mut self,
+        font: &<Self::Text as Text>::Font,
+        metrics: &GridMetrics,
+        origin: impl Into<Point>,
+        cells: &[GridCell],
+    ) {
+        let origin = origin.into();
+        for (range, color) in grid_background_runs(cells) {
+            let brush = self.solid_brush(color);
+            self.fill(metrics.cells_rect(origin, range), &brush);
+        }
+        for (range, color) in grid_foreground_runs(cells) {
+            let run: String = cells[range.clone()].iter().map(|cell| cell.ch).collect();
+            let layout = match self.text().new_text_layout(font, &run).build() {
+                Ok(layout) => layout,
+                Err(_) => continue,
+            };
+            let x = metrics.cells_rect(origin, range).x0;
+            let brush = self.solid_brush(color);
+            self.draw_text(&layout, (x, origin.y + metrics.baseline), &brush);
+        }
+    }
+
+    /// Set how text origins are aligned to the device pixel grid.
+    ///
+    /// This affects all subsequent [`draw_text`](#tymethod.draw_text) calls
+    /// and is not part of the state saved by [`save`](#method.save). See
+    /// [`GlyphPositioning`] for the available modes.
+    ///
+    /// [`GlyphPositioning`]: enum.GlyphPositioning.html
+    fn set_glyph_positioning(&mut self, positioning: GlyphPositioning);
+
+    /// Set how the destination rectangles of images are aligned to the
+    /// device pixel grid.
+    ///
+    /// This affects all subsequent image drawing and is not part of the
+    /// state saved by [`save`](#method.save). See [`ImagePositioning`] for
+    /// the available modes.
+    ///
+    /// [`ImagePositioning`]: enum.ImagePositioning.html
+    fn set_image_positioning(&mut self, positioning: ImagePositioning);
+
+    /// Set how far, in device pixels, curves may move when shapes are
+    /// converted to paths, as when circles and rounded corners become
+    /// Bézier curves.
+    ///
+    /// The default is [`DEFAULT_TOLERANCE`]. A looser tolerance makes huge
+    /// paths cheaper to build. Being in device pixels, the tolerance is
+    /// tighter in user space under a scale, so curves exported at a high
+    /// DPI stay smooth. Tolerances that aren't positive and finite are
+    /// ignored.
+    ///
+    /// This affects shapes drawn, clipped to or made into paths afterwards,
+    /// and is not part of the state saved by [`save`](#method.save). Paths
+    /// made with [`make_path`] keep the tolerance and transform they were
+    /// made with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::{Affine, Circle};
+    /// use piet::{Color, RenderContext};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// // a rough preview of a huge plot
+    /// rc.set_tolerance(0.25);
+    /// rc.fill(Circle::new((50.0, 50.0), 40.0), &Color::BLACK);
+    /// rc.set_tolerance(piet::DEFAULT_TOLERANCE);
+    /// ```
+    ///
+    /// [`DEFAULT_TOLERANCE`]: constant.DEFAULT_TOLERANCE.html
+    /// [`make_path`]: #tymethod.make_path
+    fn set_tolerance(&mut self, tolerance: f64);
+
+    /// The tolerance set with [`set_tolerance`], in device pixels.
+    ///
+    /// [`set_tolerance`]: #tymethod.set_tolerance
+    fn tolerance(&self) -> f64;
+
+    /// Set how subsequent drawing is antialiased.
+    ///
+    /// Like [`set_glyph_positioning`](#tymethod.set_glyph_positioning), this is
+    /// not part of the state saved by [`save`](#method.save). The canvas API
+    /// has no control over antialiasing, so the web backend ignores this.
+    fn set_antialiasing(&mut self, mode: Antialiasing);
+
+    /// Tag subsequent fills, strokes, text and images with `id`, or with no
+    /// id for `None`, so that picking can tell which of them are under a
+    /// point.
+    ///
+    /// Picking needs a context that keeps what it drew: the display list of
+    /// a [`RecordingContext`] answers with [`DisplayList::pick`]. Contexts
+    /// that only render ignore the id, and report so with
+    /// [`Capabilities::picking`]. Like
+    /// [`set_glyph_positioning`](#tymethod.set_glyph_positioning), this is
+    /// not part of the state saved by [`save`](#method.save).
+    ///
+    /// ```
+    /// use piet::kurbo::{Circle, Point, Rect};
+    /// use piet::{Color, RecordingContext, RenderContext};
+    /// # use piet::NullText;
+    ///
+    /// let mut rc = RecordingContext::new(NullText);
+    /// rc.set_pick_id(Some(1));
+    /// rc.fill(Rect::new(0.0, 0.0, 100.0, 100.0), &Color::WHITE);
+    /// rc.set_pick_id(Some(2));
+    /// rc.fill(Circle::new((50.0, 50.0), 10.0), &Color::BLACK);
+    /// rc.set_pick_id(None);
+    /// rc.stroke(Rect::new(0.0, 0.0, 100.0, 100.0), &Color::BLACK, 4.0);
+    ///
+    /// let list = rc.display_list();
+    /// assert_eq!(list.pick(Point::new(50.0, 50.0)), [2, 1]);
+    /// assert_eq!(list.pick(Point::new(90.0, 90.0)), [1]);
+    /// // only the stroke, which has no id
+    /// assert!(list.pick(Point::new(101.0, 50.0)).is_empty());
+    /// ```
+    ///
+    /// [`RecordingContext`]: struct.RecordingContext.html
+    /// [`DisplayList::pick`]: struct.DisplayList.html#method.pick
+    /// [`Capabilities::picking`]: struct.Capabilities.html#structfield.picking
+    fn set_pick_id(&mut self, id: Option<u64>);
+
+    /// Choose whether features the backend doesn't have are approximated or
+    /// fail; see [`FallbackPolicy`] for which features this covers. The
+    /// default is [`FallbackPolicy::Degrade`].
+    ///
+    /// Like [`set_glyph_positioning`](#tymethod.set_glyph_positioning), this
+    /// is not part of the state saved by [`save`](#method.save).
+    ///
+    /// [`FallbackPolicy`]: enum.FallbackPolicy.html
+    /// [`FallbackPolicy::Degrade`]: enum.FallbackPolicy.html#variant.Degrade
+    fn set_fallback_policy(&mut self, policy: FallbackPolicy);
+
     /// Save the context state.
     ///
     /// Pushes the current context state onto a stack, to be popped by
@@ -158,20 +837,142 @@ where
         f(self).and(self.restore())
     }
 
+    /// Do graphics operations with `transform` applied, then restore the
+    /// context state.
+    ///
+    /// Equivalent to [`with_save`](#method.with_save) with `transform`
+    /// applied first, so clips set by `f` end with it too.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::{Affine, Circle, Rect};
+    /// use piet::{Color, RenderContext};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let colors = [Color::rgb8(0xff, 0, 0), Color::rgb8(0, 0xff, 0), Color::rgb8(0, 0, 0xff)];
+    /// for (i, color) in colors.iter().enumerate() {
+    ///     let offset = Affine::translate((i as f64 * 30.0, 0.0));
+    ///     rc.with_transform(offset, |rc| {
+    ///         rc.with_clip(Rect::new(0.0, 0.0, 20.0, 10.0), |rc| {
+    ///             rc.fill(Circle::new((10.0, 10.0), 10.0), color);
+    ///             Ok(())
+    ///         })
+    ///     })
+    ///     .unwrap();
+    /// }
+    /// ```
+    fn with_transform(
+        &mut self,
+        transform: Affine,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.with_save(|rc| {
+            rc.transform(transform);
+            f(rc)
+        })
+    }
+
+    /// Do graphics operations clipped to `shape`.
+    ///
+    /// The clip is pushed with [`push_clip`] and popped after `f`, so other
+    /// state `f` changes, such as the transform, stays changed.
+    ///
+    /// [`push_clip`]: #tymethod.push_clip
+    fn with_clip(
+        &mut self,
+        shape: impl Shape,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.push_clip(shape);
+        // Always try to pop the clip, even if `f` errored.
+        f(self).and(self.pop_clip())
+    }
+
+    /// Save the context state, returning a guard that restores it when
+    /// dropped.
+    ///
+    /// The guard derefs to the context, so it can be drawn with directly,
+    /// and the state is restored however the scope is left, including by an
+    /// early return with `?`. Errors from restoring on drop are ignored;
+    /// [`SaveGuard::restore`] reports them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::{Affine, Rect};
+    /// use piet::{Color, Error, RenderContext};
+    ///
+    /// fn draw_badge(rc: &mut impl RenderContext, selected: bool) -> Result<(), Error> {
+    ///     let mut rc = rc.save_guard();
+    ///     rc.transform(Affine::translate((10.0, 10.0)));
+    ///     rc.clip(Rect::new(0.0, 0.0, 16.0, 16.0));
+    ///     if !selected {
+    ///         // restored here too
+    ///         return Ok(());
+    ///     }
+    ///     rc.fill(Rect::new(0.0, 0.0, 16.0, 16.0), &Color::WHITE);
+    ///     Ok(())
+    /// }
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// draw_badge(&mut rc, true).unwrap();
+    /// ```
+    fn save_guard(&mut self) -> SaveGuard<'_, Self> {
+        let saved = self.save().is_ok();
+        SaveGuard { rc: self, saved }
+    }
+
     /// Finish any pending operations.
     ///
     /// This will generally be called by a shell after all user drawing
     /// operations but before presenting. Not all back-ends will handle this
     /// the same way.
+    ///
+    /// Every [`save`](#method.save) should have been restored by now.
+    /// Backends that track saves return [`ErrorKind::StackUnbalance`] if
+    /// one wasn't, after panicking in debug builds unless in
+    /// [panic-free mode](fn.set_panic_free.html).
+    ///
+    /// [`ErrorKind::StackUnbalance`]: enum.ErrorKind.html#variant.StackUnbalance
     fn finish(&mut self) -> Result<(), Error>;
 
     /// Apply a transform.
     ///
     /// Apply an affine transformation. The transformation remains in effect
     /// until a [`restore`](#method.restore) operation.
+    ///
+    /// A transform that would leave a NaN or infinite coefficient in the
+    /// current one is ignored, and [`status`] returns
+    /// [`ErrorKind::InvalidInput`], so one bad scale doesn't blank every
+    /// frame after it. Cairo can't draw under a transform it can't invert,
+    /// so it ignores those that collapse the plane to a line too.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::Affine;
+    /// use piet::{NullText, RecordingContext, RenderContext};
+    ///
+    /// let mut rc = RecordingContext::new(NullText);
+    /// rc.transform(Affine::scale(std::f64::NAN));
+    /// assert!(rc.status().is_err());
+    /// assert_eq!(rc.current_transform().as_coeffs(), [1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+    /// ```
+    ///
+    /// [`status`]: #tymethod.status
+    /// [`ErrorKind::InvalidInput`]: enum.ErrorKind.html#variant.InvalidInput
     fn transform(&mut self, transform: Affine);
 
     /// Create a new image from a pixel buffer.
+    ///
+    /// `buf` holds the rows of pixels in order, with no padding, so it's
+    /// `width * height * format.bytes_per_pixel()` bytes long. A buffer of
+    /// another length is [`ErrorKind::InvalidInput`]; see
+    /// [`check_image_buffer`].
+    ///
+    /// [`ErrorKind::InvalidInput`]: enum.ErrorKind.html#variant.InvalidInput
+    /// [`check_image_buffer`]: fn.check_image_buffer.html
     fn make_image(
         &mut self,
         width: usize,
@@ -203,10 +1004,404 @@ where
         interp: InterpolationMode,
     );
 
+    /// Draw an image placed by an arbitrary transform, for rotated and skewed
+    /// sprites.
+    ///
+    /// `transform` maps the image's pixel space, from `(0, 0)` to its size,
+    /// to user space: the pixel at `(x, y)` is drawn at `transform * (x, y)`.
+    /// Drawing into a rectangle with [`draw_image`] is the special case of a
+    /// translation and a scale. The image positioning mode isn't applied,
+    /// since a rotated image can't line up with device pixels.
+    ///
+    /// # Examples
+    ///
+    /// Spinning a sprite around its center:
+    ///
+    /// ```
+    /// use piet::kurbo::{Affine, Point};
+    /// use piet::{Image, ImageFormat, InterpolationMode, RenderContext};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let sprite = rc.make_image(16, 16, &[0xff; 1024], ImageFormat::RgbaSeparate).unwrap();
+    /// let center = Point::new(100.0, 100.0);
+    /// let half = sprite.size().to_vec2() / 2.0;
+    /// let angle = 0.3;
+    /// let placement =
+    ///     Affine::translate(center.to_vec2()) * Affine::rotate(angle) * Affine::translate(-half);
+    /// rc.draw_image_transformed(&sprite, placement, InterpolationMode::Bilinear);
+    /// ```
+    ///
+    /// [`draw_image`]: #tymethod.draw_image
+    fn draw_image_transformed(
+        &mut self,
+        image: &Self::Image,
+        transform: Affine,
+        interp: InterpolationMode,
+    );
+
+    /// Draw many cells of a tile atlas, for tile maps and sprite sheets.
+    ///
+    /// Each of `tiles` is a `(src_cell, dst_pos)` pair: the `src_cell` area
+    /// of `atlas`, in pixels, is drawn at its own size with its top left
+    /// corner at `dst_pos`; scale the whole map with [`transform`]. This is
+    /// like calling [`draw_image_area`] for every tile, but backends set up
+    /// the atlas for drawing once, which matters for maps of thousands of
+    /// tiles.
+    ///
+    /// With `InterpolationMode::Bilinear`, some backends blend in pixels
+    /// from just outside a cell at its edges, so leave a border around the
+    /// cells of an atlas that's drawn scaled or at fractional positions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::{Point, Rect};
+    /// use piet::{ImageFormat, InterpolationMode, RenderContext};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// // grass and water, side by side in a 32 by 16 atlas
+    /// let atlas = rc.make_image(32, 16, &[0xff; 2048], ImageFormat::RgbaSeparate).unwrap();
+    /// let cells = [Rect::new(0.0, 0.0, 16.0, 16.0), Rect::new(16.0, 0.0, 32.0, 16.0)];
+    /// let map = [[0, 0, 1], [0, 1, 1]];
+    ///
+    /// let mut tiles = Vec::new();
+    /// for (row, line) in map.iter().enumerate() {
+    ///     for (col, &cell) in line.iter().enumerate() {
+    ///         let pos = Point::new(col as f64 * 16.0, row as f64 * 16.0);
+    ///         tiles.push((cells[cell], pos));
+    ///     }
+    /// }
+    /// rc.draw_tiles(&atlas, &tiles, InterpolationMode::NearestNeighbor);
+    /// ```
+    ///
+    /// [`transform`]: #tymethod.transform
+    /// [`draw_image_area`]: #tymethod.draw_image_area
+    fn draw_tiles(
+        &mut self,
+        atlas: &Self::Image,
+        tiles: &[(Rect, Point)],
+        interp: InterpolationMode,
+    );
+
+    /// Draw many images, each into its own rectangle with its own opacity.
+    ///
+    /// `images` holds `(image, dst_rect, alpha)` triples, drawn in order as
+    /// if by [`draw_image`], with `alpha` from 0.0 (invisible) to 1.0
+    /// (opaque). Drawing them in one call lets backends set up for image
+    /// drawing once rather than per image, which adds up in image-heavy
+    /// scenes like photo grids and map tiles.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::Rect;
+    /// use piet::{ImageFormat, InterpolationMode, RenderContext};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let tile = rc.make_image(2, 2, &[0x80; 16], ImageFormat::RgbaSeparate).unwrap();
+    /// let grid: Vec<_> = (0..16)
+    ///     .map(|i| {
+    ///         let (x, y) = ((i % 4) as f64 * 64.0, (i / 4) as f64 * 64.0);
+    ///         let fade = if i % 2 == 0 { 1.0 } else { 0.5 };
+    ///         (&tile, Rect::new(x, y, x + 64.0, y + 64.0), fade)
+    ///     })
+    ///     .collect();
+    /// rc.draw_images(&grid, InterpolationMode::Bilinear);
+    /// ```
+    ///
+    /// [`draw_image`]: #tymethod.draw_image
+    fn draw_images(&mut self, images: &[(&Self::Image, Rect, f64)], interp: InterpolationMode);
+
+    /// Make a new offscreen image to draw into, for render-to-texture
+    /// caching.
+    ///
+    /// The image is `width` by `height` pixels and starts out transparent.
+    /// Draw into it with [`draw_to_target`] as often as needed, and draw it
+    /// like any other image in between; it keeps what was drawn into it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::{Line, Rect};
+    /// use piet::{Color, InterpolationMode, NullRenderContext, RenderContext};
+    ///
+    /// let mut rc = NullRenderContext::new();
+    /// let mut cached = rc.make_render_target(64, 64).unwrap();
+    /// rc.draw_to_target(&mut cached, |rc| {
+    ///     rc.fill(Rect::new(0.0, 0.0, 64.0, 64.0), &Color::WHITE);
+    ///     Ok(())
+    /// })
+    /// .unwrap();
+    /// rc.draw_image(&cached, Rect::new(0.0, 0.0, 32.0, 32.0), InterpolationMode::Bilinear);
+    ///
+    /// // later, add to what's cached
+    /// rc.draw_to_target(&mut cached, |rc| {
+    ///     rc.stroke(Line::new((0.0, 0.0), (64.0, 64.0)), &Color::BLACK, 1.0);
+    ///     Ok(())
+    /// })
+    /// .unwrap();
+    /// rc.draw_image(&cached, Rect::new(0.0, 0.0, 32.0, 32.0), InterpolationMode::Bilinear);
+    /// ```
+    ///
+    /// [`draw_to_target`]: #tymethod.draw_to_target
+    fn make_render_target(&mut self, width: usize, height: usize) -> Result<Self::Image, Error>;
+
+    /// Draw into `target`, an image made with [`make_render_target`].
+    ///
+    /// This calls `f` with this context redirected to draw into `target`,
+    /// over what it already holds, starting with an identity transform and
+    /// no clip. Once `f` returns, drawing goes back to the original target
+    /// with the state it had before.
+    ///
+    /// Save and restore calls within `f` should balance; any state left
+    /// saved is discarded. Images that weren't made with
+    /// [`make_render_target`] may not be drawable into, and are refused
+    /// with [`ErrorKind::InvalidInput`] where they aren't.
+    ///
+    /// [`make_render_target`]: #tymethod.make_render_target
+    /// [`ErrorKind::InvalidInput`]: enum.ErrorKind.html#variant.InvalidInput
+    fn draw_to_target(
+        &mut self,
+        target: &mut Self::Image,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error>;
+
+    /// Read back what has been drawn so far as a new image.
+    ///
+    /// `src_rect` is in the current user space. The image holds the device
+    /// pixels of its bounding box in device space, rounded out to whole
+    /// pixels, one image pixel to each device pixel; areas outside the
+    /// render target come back transparent.
+    ///
+    /// Drawing the image back at `src_rect` only reproduces the original
+    /// content when the current transform is a scale and translation that
+    /// puts `src_rect` on whole device pixels. Otherwise the image covers
+    /// more than `src_rect`: under a rotation it is the box around the
+    /// rotated rect, and a rect on fractional pixels is rounded out, so the
+    /// content comes back shrunk to fit. To put it back in place, draw it
+    /// with an identity transform at
+    /// `current_transform().transform_rect_bbox(src_rect).expand()`.
+    ///
+    /// Returns an error if the rect covers no pixels.
+    fn capture_image_area(&mut self, src_rect: impl Into<Rect>) -> Result<Self::Image, Error>;
+
+    /// Draw what `f` draws, blurred, as for soft shadows and frosted glass.
+    ///
+    /// `f` draws into a transparent layer covering the current clip, with
+    /// the current transform. The layer is then blurred by a Gaussian whose
+    /// standard deviation is `radius` in user space, and composited onto the
+    /// target with the current clip. Under a transform that scales unevenly,
+    /// the blur uses the geometric mean of the two scales.
+    ///
+    /// Save and restore calls within `f` should balance; any state left
+    /// saved is discarded. A context that can't make the layer draws `f`
+    /// unblurred, or returns [`ErrorKind::NotSupported`] without calling it,
+    /// as set by the [`FallbackPolicy`].
+    ///
+    /// # Examples
+    ///
+    /// A frosted glass panel, blurring what was drawn behind it:
+    ///
+    /// ```
+    /// use piet::kurbo::{Rect, RoundedRect};
+    /// use piet::{Color, InterpolationMode, RenderContext};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let panel = Rect::new(20.0, 20.0, 180.0, 120.0);
+    /// let backdrop = rc.capture_image_area(panel).unwrap();
+    /// rc.with_save(|rc| {
+    ///     rc.clip(RoundedRect::from_rect(panel, 8.0));
+    ///     rc.with_blur(6.0, |rc| {
+    ///         rc.draw_image(&backdrop, panel, InterpolationMode::Bilinear);
+    ///         Ok(())
+    ///     })?;
+    ///     rc.fill(panel, &Color::rgba8(0xff, 0xff, 0xff, 0x40));
+    ///     Ok(())
+    /// })
+    /// .unwrap();
+    /// ```
+    ///
+    /// [`ErrorKind::NotSupported`]: enum.ErrorKind.html#variant.NotSupported
+    /// [`FallbackPolicy`]: enum.FallbackPolicy.html
+    fn with_blur(
+        &mut self,
+        radius: f64,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error>;
+
+    /// Free the offscreen surfaces kept for reuse by layers and effects.
+    ///
+    /// Backends draw [`with_blur`] layers, and clips on backends that need
+    /// a copy of the target, into offscreen surfaces that they keep for the
+    /// next frame rather than allocating each time. The surfaces are as
+    /// large as the largest layer drawn since the last trim, so call this
+    /// after drawing something unusually large, or when the app goes idle.
+    ///
+    /// [`with_blur`]: #tymethod.with_blur
+    fn trim_layer_pool(&mut self);
+
+    /// The approximate memory held by the backend's resources: the images
+    /// made with this context that are still alive, and what the backend
+    /// keeps for reuse.
+    ///
+    /// Backends share their resources with every context made from the same
+    /// device, or on the same thread where they have no device, so the report
+    /// covers all of them. See [`MemoryReport`].
+    ///
+    /// [`MemoryReport`]: struct.MemoryReport.html
+    fn memory_report(&self) -> MemoryReport;
+
+    /// Free everything the backend keeps for reuse: the layer pool, as with
+    /// [`trim_layer_pool`], and any glyph or brush caches.
+    ///
+    /// Caches fill up again as they're used, so a long-running app can call
+    /// this when [`memory_report`] grows past what it wants to spend.
+    ///
+    /// [`trim_layer_pool`]: #tymethod.trim_layer_pool
+    /// [`memory_report`]: #tymethod.memory_report
+    fn trim_caches(&mut self);
+
+    /// The number of times this context has allocated to flatten a shape.
+    ///
+    /// Backends that collect shapes into a [`FrameArena`] reuse its buffers
+    /// from one shape to the next, so this stops growing once the longest
+    /// path has been drawn; a count that keeps climbing from frame to frame
+    /// means something is allocating per shape. Backends that stream shapes
+    /// into the platform's path return zero.
+    ///
+    /// [`FrameArena`]: struct.FrameArena.html
+    fn path_allocations(&self) -> usize {
+        0
+    }
+
+    /// The calls made to this context so far, by kind, with the time spent
+    /// in them, to find what a frame spends its time drawing.
+    ///
+    /// Backends count calls when the `profiling` feature of piet is on, and
+    /// keep counting after [`finish`]; contexts that don't count them return
+    /// an empty profile. See [`DrawProfile`].
+    ///
+    /// [`finish`]: #tymethod.finish
+    /// [`DrawProfile`]: struct.DrawProfile.html
+    fn draw_profile(&self) -> DrawProfile {
+        DrawProfile::default()
+    }
+
+    /// Forget the calls counted so far, for [`draw_profile`] to count the
+    /// next frame's on their own.
+    ///
+    /// [`draw_profile`]: #method.draw_profile
+    fn reset_draw_profile(&mut self) {}
+
+    /// Fill a shape, blurred by a Gaussian with standard deviation `radius`
+    /// in user space.
+    ///
+    /// This is [`fill`] inside [`with_blur`]; errors setting up the blur are
+    /// ignored, and leave the shape undrawn.
+    ///
+    /// [`fill`]: #tymethod.fill
+    /// [`with_blur`]: #tymethod.with_blur
+    fn fill_blurred(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, radius: f64) {
+        let _ = self.with_blur(radius, |rc| {
+            rc.fill(shape, brush);
+            Ok(())
+        });
+    }
+
+    /// Draw an image as a nine-slice, for scalable frames and buttons.
+    ///
+    /// The `insets` give the width of the image's fixed borders, in image
+    /// pixels. The corners are drawn unscaled, the edges are stretched along
+    /// their length, and the center fills the rest of `dst_rect`. See
+    /// [`nine_slice`] for how the regions are computed.
+    ///
+    /// [`nine_slice`]: fn.nine_slice.html
+    fn draw_image_nine(
+        &mut self,
+        image: &Self::Image,
+        insets: Insets,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    );
+
+    /// Draw an image into `dst_rect`, showing only the parts inside `shape`.
+    ///
+    /// This looks the same as a [`clip`] to `shape` followed by
+    /// [`draw_image`], but doesn't need the copy of the render target that a
+    /// clip costs on some backends, so it's the cheaper way to draw round
+    /// avatars and thumbnails with rounded corners. Parts of `shape` outside
+    /// `dst_rect` are left untouched.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::{Circle, Rect};
+    /// use piet::{ImageFormat, InterpolationMode, RenderContext};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let avatar = rc.make_image(2, 2, &[0x80; 16], ImageFormat::RgbaSeparate).unwrap();
+    /// let dst = Rect::new(10.0, 10.0, 42.0, 42.0);
+    /// let circle = Circle::new(dst.center(), 16.0);
+    /// rc.draw_image_clipped(&avatar, dst, circle, InterpolationMode::Bilinear);
+    /// ```
+    ///
+    /// [`clip`]: #tymethod.clip
+    /// [`draw_image`]: #tymethod.draw_image
+    fn draw_image_clipped(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        shape: impl Shape,
+        interp: InterpolationMode,
+    );
+
     /// Returns the transformations currently applied to the context.
     fn current_transform(&self) -> Affine;
 }
 
+/// Restores the state of a context when dropped. See
+/// [`RenderContext::save_guard`].
+///
+/// [`RenderContext::save_guard`]: trait.RenderContext.html#method.save_guard
+pub struct SaveGuard<'a, R: RenderContext + ?Sized> {
+    rc: &'a mut R,
+    /// Whether the save succeeded, so there's something to restore.
+    saved: bool,
+}
+
+impl<R: RenderContext + ?Sized> SaveGuard<'_, R> {
+    /// Restore the state now, reporting any error.
+    ///
+    /// This is an associated function, as `guard.restore()` calls the
+    /// context's own `restore`.
+    pub fn restore(mut guard: Self) -> Result<(), Error> {
+        guard.saved = false;
+        guard.rc.restore()
+    }
+}
+
+impl<R: RenderContext + ?Sized> Deref for SaveGuard<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.rc
+    }
+}
+
+impl<R: RenderContext + ?Sized> DerefMut for SaveGuard<'_, R> {
+    fn deref_mut(&mut self) -> &mut R {
+        self.rc
+    }
+}
+
+impl<R: RenderContext + ?Sized> Drop for SaveGuard<'_, R> {
+    fn drop(&mut self) {
+        if self.saved {
+            let _ = self.rc.restore();
+        }
+    }
+}
+
 /// A trait for various types that can be used as brushes. These include
 /// backend-independent types such `Color` and `LinearGradient`, as well
 /// as the types used to represent these on a specific backend.
@@ -308,3 +1503,9 @@ impl From<FixedRadialGradient> for PaintBrush {
         PaintBrush::Fixed(src.into())
     }
 }
+
+impl From<FixedMeshGradient> for PaintBrush {
+    fn from(src: FixedMeshGradient) -> PaintBrush {
+        PaintBrush::Fixed(src.into())
+    }
+}
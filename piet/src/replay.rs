@@ -0,0 +1,312 @@
+//! Drawing display lists again, in whole or in part.
+
+use std::collections::hash_map::{Entry, HashMap};
+
+use kurbo::{BezPath, Rect, Shape};
+
+use crate::recording::{text_bounds, GraphicsState, SaveKind};
+use crate::{Color, DisplayList, DrawCommand, Error, Image, RenderContext, TextLayout};
+
+/// How much of a [`DisplayList`] to replay, and what to outline over it.
+///
+/// Replaying the first few commands of a scene, then one more each time,
+/// finds the command that draws something wrong; outlining the box around
+/// what each command paints shows where they all went.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::Rect;
+/// use piet::{Color, NullRenderContext, RecordingContext, RenderContext, ReplayOptions};
+/// # use piet::NullText;
+///
+/// let mut rc = RecordingContext::new(NullText);
+/// rc.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::WHITE);
+/// rc.fill(Rect::new(20.0, 0.0, 30.0, 10.0), &Color::BLACK);
+/// let list = rc.into_display_list();
+///
+/// // step through the list, outlining the command each step adds
+/// for step in 1..=list.len() {
+///     let options = ReplayOptions::new()
+///         .steps(step)
+///         .highlight(Color::rgb8(0xff, 0, 0));
+///     let mut rc = NullRenderContext::new();
+///     list.replay_with(&mut rc, &options).unwrap();
+/// }
+/// ```
+///
+/// [`DisplayList`]: struct.DisplayList.html
+#[derive(Clone, Debug)]
+pub struct ReplayOptions {
+    steps: usize,
+    bounds: Option<Color>,
+    highlight: Option<Color>,
+}
+
+impl ReplayOptions {
+    /// Replay every command, with nothing outlined.
+    pub fn new() -> ReplayOptions {
+        ReplayOptions {
+            steps: usize::MAX,
+            bounds: None,
+            highlight: None,
+        }
+    }
+
+    /// Replay only the first `steps` commands.
+    pub fn steps(mut self, steps: usize) -> Self {
+        self.steps = steps;
+        self
+    }
+
+    /// Outline the box around what each command replayed paints, with a
+    /// hairline of `color`. Clears of everything aren't outlined.
+    pub fn bounds(mut self, color: Color) -> Self {
+        self.bounds = Some(color);
+        self
+    }
+
+    /// Outline the last of those boxes with a hairline of `color`, over the
+    /// others, whether they're outlined or not.
+    pub fn highlight(mut self, color: Color) -> Self {
+        self.highlight = Some(color);
+        self
+    }
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        ReplayOptions::new()
+    }
+}
+
+impl<L: TextLayout> DisplayList<L> {
+    /// Draw the list with `rc`.
+    ///
+    /// The recorded text layouts are drawn as they are, so `rc` has to be
+    /// of the backend that laid them out; images are made again with `rc`,
+    /// once each. The list starts from the transform and clip `rc` has, and
+    /// is drawn between a save and a restore, closing any saves and clips it
+    /// leaves open, so its own don't outlast it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::Rect;
+    /// use piet::{Color, NullRenderContext, RecordingContext, RenderContext};
+    /// # use piet::NullText;
+    ///
+    /// let mut rc = RecordingContext::new(NullText);
+    /// rc.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::WHITE);
+    /// let list = rc.into_display_list();
+    ///
+    /// let mut rc = NullRenderContext::new();
+    /// list.replay(&mut rc).unwrap();
+    /// ```
+    pub fn replay<R>(&self, rc: &mut R) -> Result<(), Error>
+    where
+        R: RenderContext<TextLayout = L>,
+    {
+        self.replay_with(rc, &ReplayOptions::new())
+    }
+
+    /// Draw the list with `rc`, stopping and outlining commands as
+    /// `options` says. See [`replay`].
+    ///
+    /// When the last step is inside a blur, the commands in the blur so far
+    /// are blurred. The outlines are drawn after the list, with the
+    /// transform and clip `rc` had before it.
+    ///
+    /// [`replay`]: #method.replay
+    pub fn replay_with<R>(&self, rc: &mut R, options: &ReplayOptions) -> Result<(), Error>
+    where
+        R: RenderContext<TextLayout = L>,
+    {
+        let end = options.steps.min(self.len());
+        let mut replayer = Replayer {
+            commands: &self.commands()[..end],
+            next: 0,
+            state: GraphicsState::default(),
+            images: HashMap::new(),
+            outlines: Vec::new(),
+        };
+        rc.save()?;
+        replayer.run(rc)?;
+        rc.restore()?;
+
+        let mut outlines = replayer.outlines;
+        let last = match options.highlight {
+            Some(_) => outlines.pop(),
+            None => None,
+        };
+        if let Some(color) = &options.bounds {
+            for outline in &outlines {
+                rc.stroke_hairline(outline, color);
+            }
+        }
+        if let (Some(color), Some(last)) = (&options.highlight, last) {
+            rc.stroke_hairline(last, color);
+        }
+        Ok(())
+    }
+}
+
+/// The state of a replay.
+struct Replayer<'a, L, I> {
+    commands: &'a [DrawCommand<L>],
+    next: usize,
+    state: GraphicsState,
+    /// The images made so far, by the address of their pixels.
+    images: HashMap<*const u8, I>,
+    /// The box around each command that paints, in the coordinates the list
+    /// was recorded in.
+    outlines: Vec<BezPath>,
+}
+
+impl<'a, L: TextLayout, I> Replayer<'a, L, I> {
+    /// Draw commands up to the end of the list or of the blur they're in,
+    /// and close the saves and clips left open.
+    fn run<R>(&mut self, rc: &mut R) -> Result<(), Error>
+    where
+        R: RenderContext<TextLayout = L, Image = I>,
+    {
+        let commands = self.commands;
+        while let Some(command) = commands.get(self.next) {
+            self.next += 1;
+            if let DrawCommand::EndBlur = command {
+                break;
+            }
+            self.state.update(command)?;
+            match command {
+                DrawCommand::BeginBlur(radius) => rc.with_blur(*radius, |rc| self.run(rc))?,
+                _ => self.draw(rc, command)?,
+            }
+        }
+        self.unwind(rc)?;
+        // the state doesn't have a blur to end at the top level
+        let _ = self.state.update(&DrawCommand::<L>::EndBlur);
+        Ok(())
+    }
+
+    /// Close saves and pushed clips, as far as the innermost blur.
+    fn unwind<R>(&mut self, rc: &mut R) -> Result<(), Error>
+    where
+        R: RenderContext<TextLayout = L, Image = I>,
+    {
+        loop {
+            let command: DrawCommand<L> = match self.state.innermost() {
+                Some(SaveKind::Save) => {
+                    rc.restore()?;
+                    DrawCommand::Restore
+                }
+                Some(SaveKind::Clip) => {
+                    rc.pop_clip()?;
+                    DrawCommand::PopClip
+                }
+                _ => return Ok(()),
+            };
+            self.state.update(&command)?;
+        }
+    }
+
+    fn draw<R>(&mut self, rc: &mut R, command: &DrawCommand<L>) -> Result<(), Error>
+    where
+        R: RenderContext<TextLayout = L, Image = I>,
+    {
+        match command {
+            DrawCommand::Clear(color) => rc.clear(color.clone()),
+            DrawCommand::ClearRect(rect, color) => rc.clear_rect(*rect, color.clone()),
+            DrawCommand::Fill {
+                shape,
+                brush,
+                even_odd: false,
+            } => rc.fill(shape, brush),
+            DrawCommand::Fill {
+                shape,
+                brush,
+                even_odd: true,
+            } => rc.fill_even_odd(shape, brush),
+            DrawCommand::Stroke {
+                shape,
+                brush,
+                width,
+                style: None,
+            } => rc.stroke(shape, brush, *width),
+            DrawCommand::Stroke {
+                shape,
+                brush,
+                width,
+                style: Some(style),
+            } => rc.stroke_styled(shape, brush, *width, style),
+            DrawCommand::Clip(shape) => rc.clip(shape),
+            DrawCommand::ClipText { layout, pos } => rc.clip_text(layout.layout(), *pos),
+            DrawCommand::PushClip(shape) => rc.push_clip(shape),
+            DrawCommand::PopClip => rc.pop_clip()?,
+            DrawCommand::ResetClip => rc.reset_clip(),
+            DrawCommand::Text {
+                layout,
+                pos,
+                brush,
+                glyph_transforms: None,
+            } => rc.draw_text(layout.layout(), *pos, brush),
+            DrawCommand::Text {
+                layout,
+                pos,
+                brush,
+                glyph_transforms: Some(transforms),
+            } => rc.draw_glyph_run(layout.layout(), *pos, brush, transforms),
+            DrawCommand::GlyphPositioning(positioning) => rc.set_glyph_positioning(*positioning),
+            DrawCommand::ImagePositioning(positioning) => rc.set_image_positioning(*positioning),
+            DrawCommand::Antialiasing(mode) => rc.set_antialiasing(*mode),
+            DrawCommand::PickId(id) => rc.set_pick_id(*id),
+            DrawCommand::Save => rc.save()?,
+            DrawCommand::Restore => rc.restore()?,
+            DrawCommand::Transform(transform) => rc.transform(*transform),
+            DrawCommand::Image {
+                image,
+                src_rect,
+                dst_rect,
+                interp,
+                alpha,
+            } => {
+                let made = match self.images.entry(image.pixels().as_ptr()) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(rc.make_image(
+                        image.width(),
+                        image.height(),
+                        image.pixels(),
+                        image.format(),
+                    )?),
+                };
+                if *alpha == 1.0 {
+                    rc.draw_image_area(made, *src_rect, *dst_rect, *interp);
+                } else {
+                    // only whole images are recorded with an alpha
+                    rc.draw_images(&[(made, *dst_rect, *alpha)], *interp);
+                }
+            }
+            DrawCommand::BeginBlur(_) | DrawCommand::EndBlur => (),
+        }
+        if let Some(bounds) = paint_bounds(command) {
+            self.outlines
+                .push(self.state.transform * bounds.into_bez_path(0.0));
+        }
+        Ok(())
+    }
+}
+
+/// The box around what a command paints, in its user space, or `None` if
+/// it paints nothing, or everything.
+pub(crate) fn paint_bounds<L: TextLayout>(command: &DrawCommand<L>) -> Option<Rect> {
+    match command {
+        DrawCommand::ClearRect(rect, _) => Some(rect.abs()),
+        DrawCommand::Fill { shape, .. } => Some(shape.bounding_box()),
+        DrawCommand::Stroke { shape, width, .. } => {
+            let half = width.abs() / 2.0;
+            Some(shape.bounding_box().inflate(half, half))
+        }
+        DrawCommand::Text { layout, pos, .. } => text_bounds(layout.layout(), *pos),
+        DrawCommand::Image { dst_rect, .. } => Some(dst_rect.abs()),
+        _ => None,
+    }
+}
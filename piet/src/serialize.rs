@@ -0,0 +1,767 @@
+//! Writing display lists out with serde, and reading them back.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use kurbo::{Affine, BezPath, Point, Rect, Vec2};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::export::base64;
+use crate::recording::{LayoutOption, RecordingText};
+use crate::{
+    check_image_buffer, new_error, Antialiasing, Color, DisplayList, DrawCommand, Error, ErrorKind,
+    FixedGradient, FixedLinearGradient, FixedMeshGradient, FixedRadialGradient, FontBuilder,
+    FontFeature, FontStretch, FontStyle, FontVariation, FontWeight, GlyphPositioning,
+    GradientColorSpace, GradientStop, ImageFormat, ImagePositioning, InterpolationMode,
+    LinearGradient, MeshPatch, PaintBrush, RadialGradient, RecordedImage, RecordedTextLayout,
+    ScaleMode, StrokeStyle, Text, TextAlignment, TextAttribute, TextLayoutBuilder, TextTruncation,
+    UnitPoint, WritingMode,
+};
+
+/// A [`DisplayList`] in a form serde can write out and read back, to draw
+/// it again somewhere else: with another backend, on another machine, or
+/// from a bug report.
+///
+/// Shapes are kept as SVG path data, colors as `"#rrggbbaa"` strings, and
+/// images as base64 pixels, once each however often they're drawn. Text is
+/// kept as what it was laid out from: the text, the font it was asked for,
+/// and the calls made to its layout builder. It's laid out again when the
+/// list is read back, so it comes out the same where the same fonts are
+/// installed.
+///
+/// A `DisplayList` serializes as one of these, so either can be written.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::Rect;
+/// use piet::{
+///     Color, NullRenderContext, NullText, RecordingContext, RenderContext,
+///     SerializedDisplayList,
+/// };
+///
+/// let mut rc = RecordingContext::new(NullText);
+/// rc.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::rgb8(0xff, 0, 0));
+/// let json = serde_json::to_string(rc.display_list()).unwrap();
+/// assert!(json.contains(r##""color":"#ff0000ff""##));
+///
+/// let list: SerializedDisplayList = serde_json::from_str(&json).unwrap();
+/// let list = list.to_display_list(NullText).unwrap();
+/// list.replay(&mut NullRenderContext::new()).unwrap();
+/// ```
+///
+/// [`DisplayList`]: struct.DisplayList.html
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SerializedDisplayList {
+    /// The images drawn, which `Image` commands refer to by index.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<ImageData>,
+    commands: Vec<Command>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct ImageData {
+    width: usize,
+    height: usize,
+    format: ImageFormat,
+    /// The pixels in `format`, in base64.
+    pixels: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Command {
+    Clear(Color),
+    ClearRect {
+        rect: [f64; 4],
+        color: Color,
+    },
+    Fill {
+        path: String,
+        brush: Brush,
+        #[serde(default)]
+        even_odd: bool,
+    },
+    Stroke {
+        path: String,
+        brush: Brush,
+        width: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        style: Option<StrokeStyle>,
+    },
+    Clip(String),
+    ClipText {
+        layout: Layout,
+        pos: [f64; 2],
+    },
+    PushClip(String),
+    PopClip,
+    ResetClip,
+    Text {
+        layout: Layout,
+        pos: [f64; 2],
+        brush: Brush,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        glyph_transforms: Option<Vec<[f64; 6]>>,
+    },
+    GlyphPositioning(GlyphPositioning),
+    ImagePositioning(ImagePositioning),
+    Antialiasing(Antialiasing),
+    PickId(Option<u64>),
+    Save,
+    Restore,
+    Transform([f64; 6]),
+    Image {
+        image: usize,
+        src_rect: [f64; 4],
+        dst_rect: [f64; 4],
+        interp: InterpolationMode,
+        #[serde(default = "opaque")]
+        alpha: f64,
+    },
+    BeginBlur(f64),
+    EndBlur,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Brush {
+    Color(Color),
+    /// A [`LinearGradient`], in the unit square of what it fills.
+    ///
+    /// [`LinearGradient`]: struct.LinearGradient.html
+    Linear {
+        start: [f64; 2],
+        end: [f64; 2],
+        stops: Vec<GradientStop>,
+        #[serde(default)]
+        color_space: GradientColorSpace,
+    },
+    /// A [`RadialGradient`], in the unit square of what it fills.
+    ///
+    /// [`RadialGradient`]: struct.RadialGradient.html
+    Radial {
+        center: [f64; 2],
+        origin: [f64; 2],
+        radius: f64,
+        stops: Vec<GradientStop>,
+        scale_mode: ScaleMode,
+        #[serde(default)]
+        color_space: GradientColorSpace,
+    },
+    FixedLinear {
+        start: [f64; 2],
+        end: [f64; 2],
+        stops: Vec<GradientStop>,
+        #[serde(default)]
+        color_space: GradientColorSpace,
+    },
+    FixedRadial {
+        center: [f64; 2],
+        origin_offset: [f64; 2],
+        radius: f64,
+        stops: Vec<GradientStop>,
+        #[serde(default)]
+        color_space: GradientColorSpace,
+    },
+    Mesh(Vec<Patch>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Patch {
+    corners: [[f64; 2]; 4],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    edges: Option<[[[f64; 2]; 2]; 4]>,
+    colors: [Color; 4],
+}
+
+/// What a text layout was made from.
+#[derive(Clone, Serialize, Deserialize)]
+struct Layout {
+    text: String,
+    font: Face,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mask: Option<char>,
+    /// The calls made to the layout's builder, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    options: Vec<Call>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Face {
+    family: String,
+    size: f64,
+    #[serde(default = "normal_weight")]
+    weight: u16,
+    #[serde(default)]
+    style: FontStyle,
+    #[serde(default)]
+    stretch: FontStretch,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fallbacks: Vec<String>,
+    /// Each axis, by its tag.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    variations: Vec<(String, f64)>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Call {
+    Attribute {
+        range: Range<usize>,
+        attribute: Attribute,
+    },
+    DefaultColor(Color),
+    Transform([f64; 6]),
+    MaxWidth(f64),
+    Alignment(TextAlignment),
+    MaxLines(usize, TextTruncation),
+    WritingMode(WritingMode),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Attribute {
+    Background(Color),
+    Foreground(Color),
+    Size(f64),
+    Weight(u16),
+    Italic(bool),
+    Underline(bool),
+    Strikethrough(bool),
+    LetterSpacing(f64),
+    WordSpacing(f64),
+    /// A feature by its tag, with its value.
+    FontFeature(String, u32),
+}
+
+fn opaque() -> f64 {
+    1.0
+}
+
+fn normal_weight() -> u16 {
+    FontWeight::NORMAL.to_raw()
+}
+
+impl<L> Serialize for DisplayList<L> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedDisplayList::from(self).serialize(serializer)
+    }
+}
+
+impl<'a, L> From<&'a DisplayList<L>> for SerializedDisplayList {
+    fn from(list: &'a DisplayList<L>) -> SerializedDisplayList {
+        let mut images = Vec::new();
+        // each image's index, by the address of its pixels
+        let mut indices = HashMap::new();
+        let commands = list
+            .commands()
+            .iter()
+            .map(|command| match command {
+                DrawCommand::Clear(color) => Command::Clear(color.clone()),
+                DrawCommand::ClearRect(rect, color) => Command::ClearRect {
+                    rect: from_rect(*rect),
+                    color: color.clone(),
+                },
+                DrawCommand::Fill {
+                    shape,
+                    brush,
+                    even_odd,
+                } => Command::Fill {
+                    path: shape.to_svg(),
+                    brush: Brush::from(brush),
+                    even_odd: *even_odd,
+                },
+                DrawCommand::Stroke {
+                    shape,
+                    brush,
+                    width,
+                    style,
+                } => Command::Stroke {
+                    path: shape.to_svg(),
+                    brush: Brush::from(brush),
+                    width: *width,
+                    style: style.clone(),
+                },
+                DrawCommand::Clip(shape) => Command::Clip(shape.to_svg()),
+                DrawCommand::ClipText { layout, pos } => Command::ClipText {
+                    layout: Layout::from(layout),
+                    pos: from_point(*pos),
+                },
+                DrawCommand::PushClip(shape) => Command::PushClip(shape.to_svg()),
+                DrawCommand::PopClip => Command::PopClip,
+                DrawCommand::ResetClip => Command::ResetClip,
+                DrawCommand::Text {
+                    layout,
+                    pos,
+                    brush,
+                    glyph_transforms,
+                } => Command::Text {
+                    layout: Layout::from(layout),
+                    pos: from_point(*pos),
+                    brush: Brush::from(brush),
+                    glyph_transforms: glyph_transforms.as_ref().map(|transforms| {
+                        transforms.iter().copied().map(Affine::as_coeffs).collect()
+                    }),
+                },
+                DrawCommand::GlyphPositioning(positioning) => {
+                    Command::GlyphPositioning(*positioning)
+                }
+                DrawCommand::ImagePositioning(positioning) => {
+                    Command::ImagePositioning(*positioning)
+                }
+                DrawCommand::Antialiasing(mode) => Command::Antialiasing(*mode),
+                DrawCommand::PickId(id) => Command::PickId(*id),
+                DrawCommand::Save => Command::Save,
+                DrawCommand::Restore => Command::Restore,
+                DrawCommand::Transform(transform) => Command::Transform(transform.as_coeffs()),
+                DrawCommand::Image {
+                    image,
+                    src_rect,
+                    dst_rect,
+                    interp,
+                    alpha,
+                } => Command::Image {
+                    image: *indices.entry(image.pixels.as_ptr()).or_insert_with(|| {
+                        images.push(ImageData {
+                            width: image.width,
+                            height: image.height,
+                            format: image.format,
+                            pixels: base64(&image.pixels),
+                        });
+                        images.len() - 1
+                    }),
+                    src_rect: from_rect(*src_rect),
+                    dst_rect: from_rect(*dst_rect),
+                    interp: *interp,
+                    alpha: *alpha,
+                },
+                DrawCommand::BeginBlur(radius) => Command::BeginBlur(*radius),
+                DrawCommand::EndBlur => Command::EndBlur,
+            })
+            .collect();
+        SerializedDisplayList { images, commands }
+    }
+}
+
+impl SerializedDisplayList {
+    /// The display list, with its text laid out again by `text`.
+    ///
+    /// The list can then be replayed by a context of `text`'s backend.
+    /// Returns an error of [`ErrorKind::InvalidInput`] for path data,
+    /// images or font tags that aren't valid, and any error from laying the
+    /// text out.
+    ///
+    /// [`ErrorKind::InvalidInput`]: enum.ErrorKind.html#variant.InvalidInput
+    pub fn to_display_list<T: Text>(&self, text: T) -> Result<DisplayList<T::TextLayout>, Error> {
+        let images = self
+            .images
+            .iter()
+            .map(ImageData::to_image)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut text = RecordingText(text);
+        let mut commands = Vec::with_capacity(self.commands.len());
+        for command in &self.commands {
+            commands.push(match command {
+                Command::Clear(color) => DrawCommand::Clear(color.clone()),
+                Command::ClearRect { rect, color } => {
+                    DrawCommand::ClearRect(to_rect(*rect), color.clone())
+                }
+                Command::Fill {
+                    path,
+                    brush,
+                    even_odd,
+                } => DrawCommand::Fill {
+                    shape: to_path(path)?,
+                    brush: brush.to_brush()?,
+                    even_odd: *even_odd,
+                },
+                Command::Stroke {
+                    path,
+                    brush,
+                    width,
+                    style,
+                } => DrawCommand::Stroke {
+                    shape: to_path(path)?,
+                    brush: brush.to_brush()?,
+                    width: *width,
+                    style: style.clone(),
+                },
+                Command::Clip(path) => DrawCommand::Clip(to_path(path)?),
+                Command::ClipText { layout, pos } => DrawCommand::ClipText {
+                    layout: layout.to_layout(&mut text)?,
+                    pos: to_point(*pos),
+                },
+                Command::PushClip(path) => DrawCommand::PushClip(to_path(path)?),
+                Command::PopClip => DrawCommand::PopClip,
+                Command::ResetClip => DrawCommand::ResetClip,
+                Command::Text {
+                    layout,
+                    pos,
+                    brush,
+                    glyph_transforms,
+                } => DrawCommand::Text {
+                    layout: layout.to_layout(&mut text)?,
+                    pos: to_point(*pos),
+                    brush: brush.to_brush()?,
+                    glyph_transforms: glyph_transforms
+                        .as_ref()
+                        .map(|transforms| transforms.iter().copied().map(Affine::new).collect()),
+                },
+                Command::GlyphPositioning(positioning) => {
+                    DrawCommand::GlyphPositioning(*positioning)
+                }
+                Command::ImagePositioning(positioning) => {
+                    DrawCommand::ImagePositioning(*positioning)
+                }
+                Command::Antialiasing(mode) => DrawCommand::Antialiasing(*mode),
+                Command::PickId(id) => DrawCommand::PickId(*id),
+                Command::Save => DrawCommand::Save,
+                Command::Restore => DrawCommand::Restore,
+                Command::Transform(coeffs) => DrawCommand::Transform(Affine::new(*coeffs)),
+                Command::Image {
+                    image,
+                    src_rect,
+                    dst_rect,
+                    interp,
+                    alpha,
+                } => DrawCommand::Image {
+                    image: images
+                        .get(*image)
+                        .cloned()
+                        .ok_or_else(|| new_error(ErrorKind::InvalidInput))?,
+                    src_rect: to_rect(*src_rect),
+                    dst_rect: to_rect(*dst_rect),
+                    interp: *interp,
+                    alpha: *alpha,
+                },
+                Command::BeginBlur(radius) => DrawCommand::BeginBlur(*radius),
+                Command::EndBlur => DrawCommand::EndBlur,
+            });
+        }
+        Ok(DisplayList { commands })
+    }
+}
+
+impl ImageData {
+    fn to_image(&self) -> Result<RecordedImage, Error> {
+        let pixels = from_base64(&self.pixels).ok_or_else(|| new_error(ErrorKind::InvalidInput))?;
+        check_image_buffer(self.width, self.height, &pixels, self.format)?;
+        Ok(RecordedImage {
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            pixels: pixels.into(),
+        })
+    }
+}
+
+impl<'a> From<&'a PaintBrush> for Brush {
+    fn from(brush: &'a PaintBrush) -> Brush {
+        match brush {
+            PaintBrush::Color(color) => Brush::Color(color.clone()),
+            PaintBrush::Linear(gradient) => Brush::Linear {
+                start: from_unit(gradient.start),
+                end: from_unit(gradient.end),
+                stops: gradient.stops.clone(),
+                color_space: gradient.color_space,
+            },
+            PaintBrush::Radial(gradient) => Brush::Radial {
+                center: from_unit(gradient.center),
+                origin: from_unit(gradient.origin),
+                radius: gradient.radius,
+                stops: gradient.stops.clone(),
+                scale_mode: gradient.scale_mode.clone(),
+                color_space: gradient.color_space,
+            },
+            PaintBrush::Fixed(FixedGradient::Linear(gradient)) => Brush::FixedLinear {
+                start: from_point(gradient.start),
+                end: from_point(gradient.end),
+                stops: gradient.stops.clone(),
+                color_space: gradient.color_space,
+            },
+            PaintBrush::Fixed(FixedGradient::Radial(gradient)) => Brush::FixedRadial {
+                center: from_point(gradient.center),
+                origin_offset: [gradient.origin_offset.x, gradient.origin_offset.y],
+                radius: gradient.radius,
+                stops: gradient.stops.clone(),
+                color_space: gradient.color_space,
+            },
+            PaintBrush::Fixed(FixedGradient::Mesh(mesh)) => Brush::Mesh(
+                mesh.patches
+                    .iter()
+                    .map(|patch| Patch {
+                        corners: patch.corners.map(from_point),
+                        edges: patch
+                            .edges
+                            .map(|edges| edges.map(|(a, b)| [from_point(a), from_point(b)])),
+                        colors: patch.colors.clone(),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Brush {
+    fn to_brush(&self) -> Result<PaintBrush, Error> {
+        Ok(match self {
+            Brush::Color(color) => PaintBrush::Color(color.clone()),
+            Brush::Linear {
+                start,
+                end,
+                stops,
+                color_space,
+            } => PaintBrush::Linear(
+                LinearGradient::new(to_unit(*start), to_unit(*end), stops.clone())
+                    .with_color_space(*color_space),
+            ),
+            Brush::Radial {
+                center,
+                origin,
+                radius,
+                stops,
+                scale_mode,
+                color_space,
+            } => PaintBrush::Radial(
+                RadialGradient::new(*radius, stops.clone())
+                    .with_center(to_unit(*center))
+                    .with_origin(to_unit(*origin))
+                    .with_scale_mode(scale_mode.clone())
+                    .with_color_space(*color_space),
+            ),
+            Brush::FixedLinear {
+                start,
+                end,
+                stops,
+                color_space,
+            } => PaintBrush::Fixed(FixedGradient::Linear(FixedLinearGradient {
+                start: to_point(*start),
+                end: to_point(*end),
+                stops: stops.clone(),
+                color_space: *color_space,
+            })),
+            Brush::FixedRadial {
+                center,
+                origin_offset,
+                radius,
+                stops,
+                color_space,
+            } => PaintBrush::Fixed(FixedGradient::Radial(FixedRadialGradient {
+                center: to_point(*center),
+                origin_offset: Vec2::new(origin_offset[0], origin_offset[1]),
+                radius: *radius,
+                stops: stops.clone(),
+                color_space: *color_space,
+            })),
+            Brush::Mesh(patches) => PaintBrush::Fixed(FixedGradient::Mesh(FixedMeshGradient {
+                patches: patches
+                    .iter()
+                    .map(|patch| MeshPatch {
+                        corners: patch.corners.map(to_point),
+                        edges: patch
+                            .edges
+                            .map(|edges| edges.map(|[a, b]| (to_point(a), to_point(b)))),
+                        colors: patch.colors.clone(),
+                    })
+                    .collect(),
+            })),
+        })
+    }
+}
+
+impl<'a, L> From<&'a RecordedTextLayout<L>> for Layout {
+    fn from(layout: &'a RecordedTextLayout<L>) -> Layout {
+        let source = &layout.source;
+        let face = &source.face;
+        Layout {
+            text: source.text.clone(),
+            font: Face {
+                family: face.family.clone(),
+                size: face.size,
+                weight: face.weight.to_raw(),
+                style: face.style,
+                stretch: face.stretch,
+                fallbacks: face.fallbacks.clone(),
+                variations: face
+                    .variations
+                    .iter()
+                    .map(|v| (from_tag(v.tag()), v.value()))
+                    .collect(),
+            },
+            mask: source.mask,
+            options: source
+                .options
+                .iter()
+                .filter_map(Call::from_option)
+                .collect(),
+        }
+    }
+}
+
+impl Layout {
+    /// Lay the text out again with `text`.
+    fn to_layout<T: Text>(
+        &self,
+        text: &mut RecordingText<T>,
+    ) -> Result<RecordedTextLayout<T::TextLayout>, Error> {
+        let face = &self.font;
+        let mut font = text
+            .new_font_by_name(&face.family, face.size)
+            .weight(FontWeight::new(face.weight))
+            .style(face.style)
+            .stretch(face.stretch);
+        for family in &face.fallbacks {
+            font = font.fallback(family);
+        }
+        for (tag, value) in &face.variations {
+            font = font.variation(FontVariation::new(to_tag(tag)?, *value));
+        }
+        let font = font.build()?;
+
+        let mut builder = text.new_text_layout(&font, &self.text);
+        if let Some(mask) = self.mask {
+            builder = builder.obscure(mask);
+        }
+        for call in &self.options {
+            builder = match call {
+                Call::Attribute { range, attribute } => {
+                    builder.range_attribute(range.clone(), attribute.to_attribute()?)
+                }
+                Call::DefaultColor(color) => builder.default_color(color.clone()),
+                Call::Transform(coeffs) => builder.transform(Affine::new(*coeffs)),
+                Call::MaxWidth(width) => builder.max_width(*width),
+                Call::Alignment(alignment) => builder.alignment(*alignment),
+                Call::MaxLines(lines, truncation) => builder.max_lines(*lines, *truncation),
+                Call::WritingMode(mode) => builder.writing_mode(*mode),
+            };
+        }
+        builder.build()
+    }
+}
+
+impl Call {
+    fn from_option(option: &LayoutOption) -> Option<Call> {
+        Some(match option {
+            LayoutOption::Attribute(range, attribute) => Call::Attribute {
+                range: range.clone(),
+                attribute: Attribute::from_attribute(attribute)?,
+            },
+            LayoutOption::DefaultColor(color) => Call::DefaultColor(color.clone()),
+            LayoutOption::Transform(transform) => Call::Transform(transform.as_coeffs()),
+            LayoutOption::MaxWidth(width) => Call::MaxWidth(*width),
+            LayoutOption::Alignment(alignment) => Call::Alignment(*alignment),
+            LayoutOption::MaxLines(lines, truncation) => Call::MaxLines(*lines, *truncation),
+            LayoutOption::WritingMode(mode) => Call::WritingMode(*mode),
+        })
+    }
+}
+
+impl Attribute {
+    fn from_attribute(attribute: &TextAttribute) -> Option<Attribute> {
+        Some(match attribute {
+            TextAttribute::Background(color) => Attribute::Background(color.clone()),
+            TextAttribute::Foreground(color) => Attribute::Foreground(color.clone()),
+            TextAttribute::Size(size) => Attribute::Size(*size),
+            TextAttribute::Weight(weight) => Attribute::Weight(weight.to_raw()),
+            TextAttribute::Italic(italic) => Attribute::Italic(*italic),
+            TextAttribute::Underline(underline) => Attribute::Underline(*underline),
+            TextAttribute::Strikethrough(strikethrough) => Attribute::Strikethrough(*strikethrough),
+            TextAttribute::LetterSpacing(spacing) => Attribute::LetterSpacing(*spacing),
+            TextAttribute::WordSpacing(spacing) => Attribute::WordSpacing(*spacing),
+            TextAttribute::FontFeature(feature) => {
+                Attribute::FontFeature(from_tag(feature.tag()), feature.value())
+            }
+            TextAttribute::_NonExhaustive => return None,
+        })
+    }
+
+    fn to_attribute(&self) -> Result<TextAttribute, Error> {
+        Ok(match self {
+            Attribute::Background(color) => TextAttribute::Background(color.clone()),
+            Attribute::Foreground(color) => TextAttribute::Foreground(color.clone()),
+            Attribute::Size(size) => TextAttribute::Size(*size),
+            Attribute::Weight(weight) => TextAttribute::Weight(FontWeight::new(*weight)),
+            Attribute::Italic(italic) => TextAttribute::Italic(*italic),
+            Attribute::Underline(underline) => TextAttribute::Underline(*underline),
+            Attribute::Strikethrough(strikethrough) => TextAttribute::Strikethrough(*strikethrough),
+            Attribute::LetterSpacing(spacing) => TextAttribute::LetterSpacing(*spacing),
+            Attribute::WordSpacing(spacing) => TextAttribute::WordSpacing(*spacing),
+            Attribute::FontFeature(tag, value) => {
+                TextAttribute::FontFeature(FontFeature::new(to_tag(tag)?, *value))
+            }
+        })
+    }
+}
+
+fn from_point(point: Point) -> [f64; 2] {
+    [point.x, point.y]
+}
+
+fn to_point([x, y]: [f64; 2]) -> Point {
+    Point::new(x, y)
+}
+
+fn from_rect(rect: Rect) -> [f64; 4] {
+    [rect.x0, rect.y0, rect.x1, rect.y1]
+}
+
+fn to_rect([x0, y0, x1, y1]: [f64; 4]) -> Rect {
+    Rect::new(x0, y0, x1, y1)
+}
+
+fn from_unit(point: UnitPoint) -> [f64; 2] {
+    [point.u, point.v]
+}
+
+fn to_unit([u, v]: [f64; 2]) -> UnitPoint {
+    UnitPoint::new(u, v)
+}
+
+fn to_path(data: &str) -> Result<BezPath, Error> {
+    BezPath::from_svg(data).map_err(|_| new_error(ErrorKind::InvalidInput))
+}
+
+fn from_tag(tag: [u8; 4]) -> String {
+    String::from_utf8_lossy(&tag).into_owned()
+}
+
+fn to_tag(tag: &str) -> Result<[u8; 4], Error> {
+    match *tag.as_bytes() {
+        [a, b, c, d] => Ok([a, b, c, d]),
+        _ => Err(new_error(ErrorKind::InvalidInput)),
+    }
+}
+
+/// The bytes of `encoded`, or `None` if it isn't base64.
+fn from_base64(encoded: &str) -> Option<Vec<u8>> {
+    fn digit(c: u8) -> Option<u32> {
+        Some(match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        } as u32)
+    }
+    let encoded = encoded.as_bytes();
+    if encoded.len() & 3 != 0 {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(encoded.len() / 4 * 3);
+    for (i, chunk) in encoded.chunks(4).enumerate() {
+        let last = i + 1 == encoded.len() / 4;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0;
+        for &c in &chunk[..4 - padding] {
+            n = n << 6 | digit(c)?;
+        }
+        n <<= 6 * padding;
+        bytes.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(bytes)
+}
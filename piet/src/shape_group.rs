@@ -0,0 +1,166 @@
+//! Compound paths built from several shapes.
+
+use kurbo::{BezPath, PathEl, Point, Rect, Shape};
+
+use crate::DynShape;
+
+/// The tolerance for the group's own measurements of itself.
+const TOLERANCE: f64 = 1e-3;
+
+/// Several shapes treated as one compound path, such as a ring made of two
+/// circles, or a badge with a hole punched through it.
+///
+/// Each subpath of a shape added with [`with_shape`] is turned the same
+/// way, and each of a hole added with [`with_hole`] the other way, so a
+/// hole cuts through a shape under it when filled with [`fill`], whichever
+/// way round the shapes were drawn. Where shapes overlap, [`fill`] fills
+/// them together and a hole cuts through only one of them;
+/// [`fill_even_odd`] leaves out any place covered an even number of times
+/// instead.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{Circle, Point, Rect, Shape};
+/// use piet::{Color, RenderContext, ShapeGroup};
+///
+/// # let mut rc = piet::NullRenderContext::new();
+/// let badge = ShapeGroup::new()
+///     .with_shape(Rect::new(0.0, 0.0, 40.0, 40.0))
+///     .with_hole(Circle::new((20.0, 20.0), 10.0));
+/// assert_eq!(badge.winding(Point::new(20.0, 20.0)), 0);
+/// assert_ne!(badge.winding(Point::new(5.0, 5.0)), 0);
+/// rc.fill(&badge, &Color::BLACK);
+/// ```
+///
+/// [`with_shape`]: #method.with_shape
+/// [`with_hole`]: #method.with_hole
+/// [`fill`]: trait.RenderContext.html#tymethod.fill
+/// [`fill_even_odd`]: trait.RenderContext.html#tymethod.fill_even_odd
+#[derive(Default)]
+pub struct ShapeGroup {
+    /// The shapes in order, each with whether it's a hole.
+    shapes: Vec<(Box<dyn DynShape>, bool)>,
+}
+
+impl ShapeGroup {
+    /// An empty group, which draws nothing.
+    pub fn new() -> ShapeGroup {
+        ShapeGroup::default()
+    }
+
+    /// The group, with `shape` filled.
+    pub fn with_shape(mut self, shape: impl Shape + 'static) -> ShapeGroup {
+        self.shapes.push((Box::new(shape), false));
+        self
+    }
+
+    /// The group, with `shape` cut out of the shapes under it.
+    pub fn with_hole(mut self, shape: impl Shape + 'static) -> ShapeGroup {
+        self.shapes.push((Box::new(shape), true));
+        self
+    }
+
+    /// Whether the group has no shapes.
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    fn path(&self) -> BezPath {
+        BezPath::from_vec(self.to_bez_path(TOLERANCE).collect())
+    }
+}
+
+impl Shape for ShapeGroup {
+    type BezPathIter = std::vec::IntoIter<PathEl>;
+
+    fn to_bez_path(&self, tolerance: f64) -> Self::BezPathIter {
+        let mut elements = Vec::new();
+        for (shape, hole) in &self.shapes {
+            let shape: &dyn DynShape = shape.as_ref();
+            let mut subpath = Vec::new();
+            for el in shape.to_bez_path(tolerance) {
+                if let PathEl::MoveTo(_) = el {
+                    push_turned(&mut elements, &subpath, *hole);
+                    subpath.clear();
+                }
+                subpath.push(el);
+            }
+            push_turned(&mut elements, &subpath, *hole);
+        }
+        elements.into_iter()
+    }
+
+    fn area(&self) -> f64 {
+        self.path().area()
+    }
+
+    fn perimeter(&self, accuracy: f64) -> f64 {
+        self.shapes
+            .iter()
+            .map(|(shape, _)| shape.dyn_perimeter(accuracy))
+            .sum()
+    }
+
+    fn winding(&self, pt: Point) -> i32 {
+        self.path().winding(pt)
+    }
+
+    /// The bounds of the shapes that aren't holes.
+    fn bounding_box(&self) -> Rect {
+        self.shapes
+            .iter()
+            .filter(|(_, hole)| !hole)
+            .map(|(shape, _)| shape.dyn_bounding_box())
+            .fold(None, |acc: Option<Rect>, rect| {
+                Some(acc.map(|acc| acc.union(rect)).unwrap_or(rect))
+            })
+            .unwrap_or(Rect::ZERO)
+    }
+}
+
+/// Append `subpath`, reversed if it doesn't go the way a shape, or a hole,
+/// should: positive area for shapes, negative for holes.
+fn push_turned(elements: &mut Vec<PathEl>, subpath: &[PathEl], hole: bool) {
+    let area = BezPath::from_vec(subpath.to_vec()).area();
+    if area != 0.0 && (area < 0.0) != hole {
+        elements.extend(reverse_subpath(subpath));
+    } else {
+        elements.extend_from_slice(subpath);
+    }
+}
+
+/// The same outline as `subpath`, drawn from its end to its start.
+fn reverse_subpath(subpath: &[PathEl]) -> Vec<PathEl> {
+    let start = match subpath.first() {
+        Some(PathEl::MoveTo(p)) => *p,
+        _ => return subpath.to_vec(),
+    };
+    let closed = matches!(subpath.last(), Some(PathEl::ClosePath));
+    // each segment, with the point it starts from
+    let mut segments = Vec::new();
+    let mut last = start;
+    for &el in &subpath[1..] {
+        let end = match el {
+            PathEl::LineTo(p) | PathEl::QuadTo(_, p) | PathEl::CurveTo(_, _, p) => p,
+            PathEl::MoveTo(_) | PathEl::ClosePath => continue,
+        };
+        segments.push((last, el));
+        last = end;
+    }
+    if closed && last != start {
+        // the implied closing line is drawn first on the way back
+        segments.push((last, PathEl::LineTo(start)));
+        last = start;
+    }
+    let mut reversed = vec![PathEl::MoveTo(last)];
+    reversed.extend(segments.iter().rev().map(|&(from, el)| match el {
+        PathEl::QuadTo(p1, _) => PathEl::QuadTo(p1, from),
+        PathEl::CurveTo(p1, p2, _) => PathEl::CurveTo(p2, p1, from),
+        _ => PathEl::LineTo(from),
+    }));
+    if closed {
+        reversed.push(PathEl::ClosePath);
+    }
+    reversed
+}
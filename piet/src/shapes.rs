@@ -1,13 +1,27 @@
 //! Options for drawing paths.
 
+use std::borrow::Cow;
+
 /// Options for drawing stroked lines.
 /// Most of these are self explanatory, but some aren't.
 ///
-/// `dash` has two parts. The Vec<f64> pattern array and an offset. The array
-/// represents alternating lengths to be drawn and undrawn repeatedly. The offset
-/// specifes how far into the pattern it should start. On platforms that do not
-/// support an odd number of lengths in the array, the implementation may
-/// concatenate two copies of the array to reach an even count.
+/// `dash` has two parts. The pattern array and an offset. The array
+/// represents alternating lengths to be drawn and undrawn repeatedly, starting
+/// with a drawn one. An array with an odd number of lengths is read twice
+/// over, so the second time through the drawn and undrawn lengths swap. The
+/// offset specifies how far into the pattern the path starts, wrapping
+/// around, so it can be negative.
+///
+/// The lengths and the offset are in the same units as the stroke width, and
+/// don't scale with it: a `[4.0, 4.0]` pattern has dashes 4 units long at any
+/// width. Each dash ends in the line cap, so a dash of length zero is drawn
+/// as a dot with round or square caps, and as nothing with butt caps. A
+/// pattern with a negative length, or with no length at all, draws a solid
+/// line. Backends draw the pattern returned by
+/// [`dash_pattern`](#method.dash_pattern), which has these rules applied.
+///
+/// `alignment` places the stroke on, inside or outside the outline of a
+/// closed shape; see [`StrokeAlignment`](enum.StrokeAlignment.html).
 ///
 /// `miter_limit` controls how corners are drawn when `line_join` is set to
 /// Miter. Will draw corners as `Bevel` instead of `Miter` if the limit is
@@ -16,16 +30,32 @@
 /// See
 /// https://www.adobe.com/content/dam/acom/en/devnet/actionscript/articles/psrefman.pdf
 /// for more information and examples
+///
+/// The constructors and `with_` methods are `const`, and a pattern can
+/// borrow a static array, so styles used over and over can be `static`s
+/// that never allocate:
+///
+/// ```
+/// use piet::{LineCap, StrokeStyle};
+///
+/// static DOTTED: StrokeStyle = StrokeStyle::dashed(&[0.0, 4.0], 0.0).with_line_cap(LineCap::Round);
+///
+/// assert_eq!(DOTTED.dash.as_ref().unwrap().0[1], 4.0);
+/// ```
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StrokeStyle {
     pub line_join: Option<LineJoin>,
     pub line_cap: Option<LineCap>,
-    pub dash: Option<(Vec<f64>, f64)>,
+    pub dash: Option<(Cow<'static, [f64]>, f64)>,
     pub miter_limit: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub alignment: StrokeAlignment,
 }
 
 /// Options for angled joins in strokes.
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LineJoin {
     Miter,
     Round,
@@ -34,20 +64,58 @@ pub enum LineJoin {
 
 /// Options for the cap of stroked lines.
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LineCap {
     Butt,
     Round,
     Square,
 }
 
+/// Where a stroke lies relative to the outline of a closed shape.
+///
+/// A border specified as "2px inside" is a `2.0` wide stroke aligned
+/// `Inside`, and stays inside at rounded corners. Backends have no such
+/// option, so the outline is offset by half the width with
+/// [`align_stroke`] and stroked centered. Rectangles, rounded rectangles
+/// and circles are offset exactly; other closed shapes are flattened to
+/// polygons first. Open subpaths are always stroked centered, as are paths
+/// made with [`make_path`].
+///
+/// [`align_stroke`]: fn.align_stroke.html
+/// [`make_path`]: trait.RenderContext.html#tymethod.make_path
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StrokeAlignment {
+    /// Centered on the outline.
+    #[default]
+    Center,
+    /// Within the outline, so the stroke doesn't grow the shape.
+    Inside,
+    /// Around the outline, so the stroke doesn't cover the fill.
+    Outside,
+}
+
 impl StrokeStyle {
     #[allow(clippy::new_without_default)]
-    pub fn new() -> StrokeStyle {
+    pub const fn new() -> StrokeStyle {
         StrokeStyle {
             line_join: None,
             line_cap: None,
             dash: None,
             miter_limit: None,
+            alignment: StrokeAlignment::Center,
+        }
+    }
+
+    /// A style dashed with a pattern borrowed from a `static`; see
+    /// [`set_dash`](#method.set_dash) for other patterns.
+    pub const fn dashed(dashes: &'static [f64], offset: f64) -> StrokeStyle {
+        StrokeStyle {
+            line_join: None,
+            line_cap: None,
+            dash: Some((Cow::Borrowed(dashes), offset)),
+            miter_limit: None,
+            alignment: StrokeAlignment::Center,
         }
     }
 
@@ -59,11 +127,100 @@ impl StrokeStyle {
         self.line_cap = Some(line_cap);
     }
 
-    pub fn set_dash(&mut self, dashes: Vec<f64>, offset: f64) {
-        self.dash = Some((dashes, offset));
+    /// Set the dash pattern, either owned or borrowed from a `static`.
+    pub fn set_dash(&mut self, dashes: impl Into<Cow<'static, [f64]>>, offset: f64) {
+        self.dash = Some((dashes.into(), offset));
+    }
+
+    /// The dash pattern as it's drawn: an even number of lengths, and an
+    /// offset within one repetition of them.
+    ///
+    /// Returns `None` when the stroke is solid, including when the pattern
+    /// is one that draws a solid line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::StrokeStyle;
+    ///
+    /// let style = StrokeStyle::dashed(&[1.0, 2.0, 3.0], -1.0);
+    /// let (dashes, offset) = style.dash_pattern().unwrap();
+    /// assert_eq!(&dashes[..], &[1.0, 2.0, 3.0, 1.0, 2.0, 3.0]);
+    /// assert_eq!(offset, 11.0);
+    ///
+    /// assert!(StrokeStyle::dashed(&[2.0, -1.0], 0.0).dash_pattern().is_none());
+    /// ```
+    pub fn dash_pattern(&self) -> Option<(Cow<'_, [f64]>, f64)> {
+        let (dashes, offset) = self.dash_lengths()?;
+        if dashes.len() % 2 == 1 {
+            Some((Cow::Owned([dashes, dashes].concat()), offset))
+        } else {
+            Some((Cow::Borrowed(dashes), offset))
+        }
+    }
+
+    /// The dash pattern as it's drawn, without copying it: the lengths as
+    /// they were given, and an offset within one repetition of the pattern
+    /// that [`dash_pattern`] returns.
+    ///
+    /// An odd number of lengths is repeated twice, as cairo and canvas do
+    /// natively, so backends built on those can hand the lengths over
+    /// without the allocation `dash_pattern` makes for them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::StrokeStyle;
+    ///
+    /// let style = StrokeStyle::dashed(&[1.0, 2.0, 3.0], -1.0);
+    /// let (dashes, offset) = style.dash_lengths().unwrap();
+    /// assert_eq!(dashes, &[1.0, 2.0, 3.0]);
+    /// assert_eq!(offset, 11.0);
+    /// ```
+    ///
+    /// [`dash_pattern`]: #method.dash_pattern
+    pub fn dash_lengths(&self) -> Option<(&[f64], f64)> {
+        let (dashes, offset) = self.dash.as_ref()?;
+        let mut period: f64 = dashes.iter().sum();
+        if dashes.iter().any(|&d| d < 0.0) || !period.is_finite() || period <= 0.0 {
+            return None;
+        }
+        if dashes.len() % 2 == 1 {
+            period *= 2.0;
+        }
+        let offset = if offset.is_finite() {
+            offset.rem_euclid(period)
+        } else {
+            0.0
+        };
+        Some((&dashes[..], offset))
     }
 
     pub fn set_miter_limit(&mut self, miter_limit: f64) {
         self.miter_limit = Some(miter_limit);
     }
+
+    pub fn set_alignment(&mut self, alignment: StrokeAlignment) {
+        self.alignment = alignment;
+    }
+
+    pub const fn with_line_join(mut self, line_join: LineJoin) -> StrokeStyle {
+        self.line_join = Some(line_join);
+        self
+    }
+
+    pub const fn with_line_cap(mut self, line_cap: LineCap) -> StrokeStyle {
+        self.line_cap = Some(line_cap);
+        self
+    }
+
+    pub const fn with_miter_limit(mut self, miter_limit: f64) -> StrokeStyle {
+        self.miter_limit = Some(miter_limit);
+        self
+    }
+
+    pub const fn with_alignment(mut self, alignment: StrokeAlignment) -> StrokeStyle {
+        self.alignment = alignment;
+        self
+    }
 }
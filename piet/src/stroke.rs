@@ -0,0 +1,466 @@
+//! Hit testing and aligning strokes.
+
+use kurbo::{
+    BezPath, Circle, CubicBez, ParamCurve, PathEl, Point, QuadBez, Rect, RoundedRect, Shape, Vec2,
+};
+
+use crate::{flattening_tolerance, LineCap, LineJoin, StrokeAlignment, StrokeStyle};
+
+/// The longest a corner of an offset polygon is moved, in offsets; sharper
+/// corners are cut short rather than drawn as spikes.
+const OFFSET_MITER_LIMIT: f64 = 4.0;
+
+/// Whether `point` is inside the stroke of `shape`, drawn `width` wide with
+/// `style`.
+///
+/// This follows what backends draw: caps, joins, miter limit and dashes
+/// included, with the same defaults as [`stroke_styled`] for options that
+/// aren't set, and the outline offset for the style's [`StrokeAlignment`].
+/// Curves are flattened to a precision relative to `width`, so points within
+/// a small fraction of the width of an edge may go either way.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::Line;
+/// use piet::{LineCap, StrokeStyle};
+///
+/// let line = Line::new((0.0, 0.0), (100.0, 0.0));
+/// let mut style = StrokeStyle::new();
+/// style.set_dash(vec![10.0, 10.0], 0.0);
+///
+/// assert!(piet::stroke_contains(line, 4.0, &style, (5.0, 1.0).into()));
+/// // a gap in the dashes
+/// assert!(!piet::stroke_contains(line, 4.0, &style, (15.0, 1.0).into()));
+/// // past the end, but within a round cap
+/// style.set_line_cap(LineCap::Round);
+/// assert!(piet::stroke_contains(line, 4.0, &style, (11.5, 0.0).into()));
+/// ```
+///
+/// [`stroke_styled`]: trait.RenderContext.html#tymethod.stroke_styled
+/// [`StrokeAlignment`]: enum.StrokeAlignment.html
+pub fn stroke_contains(shape: impl Shape, width: f64, style: &StrokeStyle, point: Point) -> bool {
+    let half = width.abs() / 2.0;
+    if half == 0.0 || !half.is_finite() {
+        return false;
+    }
+    if let Some((path, style)) = align_stroke(&shape, width, style) {
+        return stroke_contains(path, width, &style, point);
+    }
+    let hit = StrokeHit {
+        point,
+        half,
+        cap: style.line_cap.unwrap_or(LineCap::Butt),
+        join: style.line_join.unwrap_or(LineJoin::Miter),
+        miter_limit: style.miter_limit.unwrap_or(10.0),
+    };
+    let tolerance = (half / 50.0).max(1e-4);
+    for (points, closed) in flatten(shape, tolerance) {
+        let pieces = match style.dash_pattern() {
+            Some((dashes, offset)) => dash(&points, closed, &dashes, offset),
+            None => vec![Piece::solid(points, closed)],
+        };
+        if pieces.iter().any(|piece| hit.piece(piece)) {
+            return true;
+        }
+    }
+    false
+}
+
+/// The outline to stroke centered in place of `shape`, so the stroke lies
+/// where `style`'s [`StrokeAlignment`] puts it, with the style to stroke it
+/// with; `None` if the style is already centered.
+///
+/// Backends call this from [`stroke_styled`], and there's no need to call
+/// it otherwise. Inside strokes shrink at most to the middle of the shape.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::{RoundedRect, Shape};
+/// use piet::{StrokeAlignment, StrokeStyle};
+///
+/// let button = RoundedRect::new(0.0, 0.0, 100.0, 40.0, 8.0);
+/// let border = StrokeStyle::new().with_alignment(StrokeAlignment::Inside);
+/// let (outline, style) = piet::align_stroke(button, 2.0, &border).unwrap();
+/// let bounds = outline.bounding_box();
+/// assert_eq!((bounds.x0, bounds.y0, bounds.x1, bounds.y1), (1.0, 1.0, 99.0, 39.0));
+/// assert_eq!(style.alignment, StrokeAlignment::Center);
+/// ```
+///
+/// [`stroke_styled`]: trait.RenderContext.html#tymethod.stroke_styled
+/// [`StrokeAlignment`]: enum.StrokeAlignment.html
+pub fn align_stroke(
+    shape: impl Shape,
+    width: f64,
+    style: &StrokeStyle,
+) -> Option<(BezPath, StrokeStyle)> {
+    let offset = match style.alignment {
+        StrokeAlignment::Center => return None,
+        StrokeAlignment::Inside => -width.abs() / 2.0,
+        StrokeAlignment::Outside => width.abs() / 2.0,
+    };
+    let centered = style.clone().with_alignment(StrokeAlignment::Center);
+    let tolerance = flattening_tolerance((offset.abs() / 50.0).max(1e-4), &shape);
+    if offset == 0.0 || !offset.is_finite() {
+        return Some((shape.into_bez_path(tolerance), centered));
+    }
+    let path = if let Some(rect) = shape.as_rect() {
+        grow_rect(rect, offset).into_bez_path(tolerance)
+    } else if let Some(rect) = shape.as_rounded_rect() {
+        let radius = (rect.radius() + offset).max(0.0);
+        RoundedRect::from_rect(grow_rect(rect.rect(), offset), radius).into_bez_path(tolerance)
+    } else if let Some(circle) = shape.as_circle() {
+        Circle::new(circle.center, (circle.radius + offset).max(0.0)).into_bez_path(tolerance)
+    } else {
+        let mut path = BezPath::new();
+        for (points, closed) in flatten(shape, tolerance) {
+            let points = if closed {
+                offset_polygon(&points, offset)
+            } else {
+                points
+            };
+            for (i, &p) in points.iter().enumerate() {
+                if i == 0 {
+                    path.move_to(p);
+                } else {
+                    path.line_to(p);
+                }
+            }
+            if closed {
+                path.close_path();
+            }
+        }
+        path
+    };
+    Some((path, centered))
+}
+
+/// `rect` grown by `by` on each side, or shrunk to no smaller than its
+/// center.
+fn grow_rect(rect: Rect, by: f64) -> Rect {
+    let rect = rect.abs();
+    let center = rect.center();
+    Rect::new(
+        (rect.x0 - by).min(center.x),
+        (rect.y0 - by).min(center.y),
+        (rect.x1 + by).max(center.x),
+        (rect.y1 + by).max(center.y),
+    )
+}
+
+/// Move the edges of a closed polygon outward by `by`, or inward when it's
+/// negative, whichever way round the polygon goes.
+fn offset_polygon(points: &[Point], by: f64) -> Vec<Point> {
+    let mut points = points.to_vec();
+    points.dedup();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    let n = points.len();
+    if n < 3 {
+        return points;
+    }
+    let area: f64 = (0..n)
+        .map(|i| points[i].to_vec2().cross(points[(i + 1) % n].to_vec2()))
+        .sum();
+    if area == 0.0 {
+        return points;
+    }
+    let outward = |a: Point, b: Point| {
+        let e = direction(a, b).unwrap_or_default();
+        Vec2::new(e.y, -e.x) * area.signum()
+    };
+    let mut result: Vec<Point> = (0..n)
+        .map(|i| {
+            let p = points[i];
+            let n1 = outward(points[(i + n - 1) % n], p);
+            let n2 = outward(p, points[(i + 1) % n]);
+            let denom = 1.0 + n1.dot(n2);
+            let miter = if denom > 1e-9 {
+                (n1 + n2) / denom
+            } else {
+                // the outline doubles back on itself
+                n1
+            };
+            let len = miter.hypot();
+            let miter = if len > OFFSET_MITER_LIMIT {
+                miter * (OFFSET_MITER_LIMIT / len)
+            } else {
+                miter
+            };
+            p + miter * by
+        })
+        .collect();
+    result.push(result[0]);
+    result
+}
+
+/// A stretch of stroke drawn without interruption.
+struct Piece {
+    points: Vec<Point>,
+    closed: bool,
+    /// The unit directions at the ends, for caps; known even when the piece
+    /// has no length.
+    start_dir: Vec2,
+    end_dir: Vec2,
+}
+
+impl Piece {
+    fn solid(points: Vec<Point>, closed: bool) -> Piece {
+        let dir = |w: &[Point]| direction(w[0], w[1]);
+        let start_dir = points
+            .windows(2)
+            .find_map(dir)
+            .unwrap_or(Vec2::new(1.0, 0.0));
+        let end_dir = points.windows(2).rev().find_map(dir).unwrap_or(start_dir);
+        Piece {
+            points,
+            closed,
+            start_dir,
+            end_dir,
+        }
+    }
+}
+
+struct StrokeHit {
+    point: Point,
+    half: f64,
+    cap: LineCap,
+    join: LineJoin,
+    miter_limit: f64,
+}
+
+impl StrokeHit {
+    fn piece(&self, piece: &Piece) -> bool {
+        let points = &piece.points;
+        if points.windows(2).any(|w| self.segment(w[0], w[1])) {
+            return true;
+        }
+        let n = points.len();
+        for i in 1..n.saturating_sub(1) {
+            if self.join(points[i - 1], points[i], points[i + 1]) {
+                return true;
+            }
+        }
+        if piece.closed {
+            // the last point repeats the first
+            return n >= 3 && self.join(points[n - 2], points[0], points[1]);
+        }
+        self.cap(points[0], -piece.start_dir) || self.cap(points[n - 1], piece.end_dir)
+    }
+
+    fn segment(&self, a: Point, b: Point) -> bool {
+        let d = b - a;
+        let len2 = d.hypot2();
+        if len2 == 0.0 {
+            return false;
+        }
+        let v = self.point - a;
+        let t = v.dot(d) / len2;
+        (0.0..=1.0).contains(&t) && v.cross(d).abs() <= self.half * len2.sqrt()
+    }
+
+    /// A cap at `p`, extending in the direction `dir`.
+    fn cap(&self, p: Point, dir: Vec2) -> bool {
+        let v = self.point - p;
+        match self.cap {
+            LineCap::Butt => false,
+            LineCap::Round => v.hypot() <= self.half,
+            LineCap::Square => {
+                let along = v.dot(dir);
+                (0.0..=self.half).contains(&along) && v.cross(dir).abs() <= self.half
+            }
+        }
+    }
+
+    /// The join at `v` between the segments from `prev` and to `next`.
+    fn join(&self, prev: Point, v: Point, next: Point) -> bool {
+        let (d_in, d_out) = match (direction(prev, v), direction(v, next)) {
+            (Some(d_in), Some(d_out)) => (d_in, d_out),
+            _ => return false,
+        };
+        let turn = d_in.cross(d_out);
+        let dot = d_in.dot(d_out);
+        if turn == 0.0 && dot > 0.0 {
+            // straight on, the segments meet flush
+            return false;
+        }
+        if self.join == LineJoin::Round {
+            return (self.point - v).hypot() <= self.half;
+        }
+        // the corners on the outside of the turn
+        let side = if turn > 0.0 { -self.half } else { self.half };
+        let a = v + Vec2::new(-d_in.y, d_in.x) * side;
+        let b = v + Vec2::new(-d_out.y, d_out.x) * side;
+        if in_triangle(self.point, v, a, b) {
+            return true;
+        }
+        // the ratio of miter length to stroke width
+        let ratio = 1.0 / ((1.0 + dot) / 2.0).sqrt();
+        if self.join == LineJoin::Bevel || ratio > self.miter_limit {
+            return false;
+        }
+        let tip = v + ((a - v) + (b - v)).normalize() * (self.half * ratio);
+        in_triangle(self.point, a, tip, b)
+    }
+}
+
+fn direction(a: Point, b: Point) -> Option<Vec2> {
+    let d = b - a;
+    let len = d.hypot();
+    if len > 0.0 {
+        Some(d / len)
+    } else {
+        None
+    }
+}
+
+fn in_triangle(p: Point, a: Point, b: Point, c: Point) -> bool {
+    let d1 = (b - a).cross(p - a);
+    let d2 = (c - b).cross(p - b);
+    let d3 = (a - c).cross(p - c);
+    let negative = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let positive = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    !(negative && positive)
+}
+
+/// Flatten a shape to polylines, one per subpath, each with whether it's
+/// closed. Closed polylines end with their first point.
+pub(crate) fn flatten(shape: impl Shape, tolerance: f64) -> Vec<(Vec<Point>, bool)> {
+    let mut subpaths = Vec::new();
+    let mut points: Vec<Point> = Vec::new();
+    for el in shape.to_bez_path(tolerance) {
+        let last = points.last().copied().unwrap_or_default();
+        match el {
+            PathEl::MoveTo(p) => {
+                if points.len() > 1 {
+                    subpaths.push((std::mem::take(&mut points), false));
+                }
+                points = vec![p];
+            }
+            PathEl::LineTo(p) => points.push(p),
+            PathEl::QuadTo(p1, p2) => {
+                let dd = (last.to_vec2() - 2.0 * p1.to_vec2() + p2.to_vec2()).hypot();
+                let quad = QuadBez::new(last, p1, p2);
+                let n = subdivisions(dd / 4.0, tolerance);
+                points.extend((1..=n).map(|i| quad.eval(i as f64 / n as f64)));
+            }
+            PathEl::CurveTo(p1, p2, p3) => {
+                let dd1 = (last.to_vec2() - 2.0 * p1.to_vec2() + p2.to_vec2()).hypot();
+                let dd2 = (p1.to_vec2() - 2.0 * p2.to_vec2() + p3.to_vec2()).hypot();
+                let cubic = CubicBez::new(last, p1, p2, p3);
+                let n = subdivisions(0.75 * dd1.max(dd2), tolerance);
+                points.extend((1..=n).map(|i| cubic.eval(i as f64 / n as f64)));
+            }
+            PathEl::ClosePath => {
+                if let Some(&first) = points.first() {
+                    if last != first {
+                        points.push(first);
+                    }
+                    let start = vec![first];
+                    if points.len() > 1 {
+                        subpaths.push((std::mem::replace(&mut points, start), true));
+                    }
+                }
+            }
+        }
+    }
+    if points.len() > 1 {
+        subpaths.push((points, false));
+    }
+    subpaths
+}
+
+/// The number of chords needed for a curve whose deviation from one chord
+/// is about `deviation`.
+fn subdivisions(deviation: f64, tolerance: f64) -> usize {
+    let n = (deviation / tolerance).sqrt().ceil();
+    if n.is_finite() {
+        (n as usize).clamp(1, 1000)
+    } else {
+        1
+    }
+}
+
+/// Split a polyline into the pieces left by a dash pattern, as returned by
+/// `StrokeStyle::dash_pattern`.
+fn dash(points: &[Point], closed: bool, pattern: &[f64], offset: f64) -> Vec<Piece> {
+    let mut i = 0;
+    let mut phase = offset;
+    while phase >= pattern[i] {
+        phase -= pattern[i];
+        i = (i + 1) % pattern.len();
+    }
+    let mut remaining = pattern[i] - phase;
+    let mut on = i % 2 == 0;
+    let started_on = on;
+    let mut toggled = false;
+
+    let mut pieces = Vec::new();
+    let mut current = vec![points[0]];
+    let mut start_dir = Vec2::new(1.0, 0.0);
+    for w in points.windows(2) {
+        let (a, b) = (w[0], w[1]);
+        let dir = match direction(a, b) {
+            Some(dir) => dir,
+            None => continue,
+        };
+        if current.len() == 1 && pieces.is_empty() && !toggled {
+            start_dir = dir;
+        }
+        let len = (b - a).hypot();
+        let mut pos = 0.0;
+        loop {
+            if remaining > len - pos {
+                remaining -= len - pos;
+                if on {
+                    current.push(b);
+                }
+                break;
+            }
+            pos += remaining;
+            let p = a + dir * pos;
+            if on {
+                current.push(p);
+                pieces.push(Piece {
+                    points: std::mem::take(&mut current),
+                    closed: false,
+                    start_dir,
+                    end_dir: dir,
+                });
+            } else {
+                current = vec![p];
+                start_dir = dir;
+            }
+            on = !on;
+            toggled = true;
+            i = (i + 1) % pattern.len();
+            remaining = pattern[i];
+        }
+    }
+    let end_dir = Piece::solid(points.to_vec(), false).end_dir;
+    if on && current.len() > 1 {
+        pieces.push(Piece {
+            points: current,
+            closed: false,
+            start_dir,
+            end_dir,
+        });
+    }
+
+    if closed && on && started_on {
+        if !toggled {
+            // the dash covers the whole outline
+            return vec![Piece::solid(points.to_vec(), true)];
+        }
+        // the first and last dashes meet at the start, with a join
+        if pieces.len() > 1 {
+            let first = pieces.remove(0);
+            let last = pieces.last_mut().unwrap();
+            last.points.extend_from_slice(&first.points[1..]);
+            last.end_dir = first.end_dir;
+        }
+    }
+    pieces
+}
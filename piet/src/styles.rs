@@ -0,0 +1,185 @@
+//! Line style presets for charts.
+
+use kurbo::Shape;
+
+use crate::{Color, IntoBrush, LineCap, LineJoin, RenderContext, StrokeStyle};
+
+static GRID_DASHES: [f64; 2] = [2.0, 3.0];
+static ANNOTATION_DASHES: [f64; 2] = [4.0, 2.0];
+
+/// The parts of a chart that [`ChartStyles`] has a preset for.
+///
+/// [`ChartStyles`]: struct.ChartStyles.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ChartElement {
+    /// The lines across the plot at each tick.
+    Grid,
+    /// The axes, and their ticks.
+    Axis,
+    /// The lines of a series of data.
+    DataLine,
+    /// Markers and callouts drawn over the data.
+    Annotation,
+}
+
+/// How to stroke a line, independent of any context.
+///
+/// [`resolve`] makes the brush for a context, which can be kept for as long
+/// as the context is.
+///
+/// [`resolve`]: #method.resolve
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineStyle {
+    /// The color of the line.
+    pub color: Color,
+    /// The width of the line.
+    pub width: f64,
+    /// The joins, caps and dashes of the line; when missing from a file, the
+    /// line is solid, with the context's default joins and caps.
+    #[cfg_attr(feature = "serde", serde(default = "StrokeStyle::new"))]
+    pub stroke: StrokeStyle,
+}
+
+/// A [`LineStyle`] with its brush made for a context.
+///
+/// [`LineStyle`]: struct.LineStyle.html
+#[derive(Debug, Clone)]
+pub struct ResolvedLineStyle<B> {
+    /// The brush of the line's color.
+    pub brush: B,
+    /// The width of the line.
+    pub width: f64,
+    /// The joins, caps and dashes of the line.
+    pub stroke: StrokeStyle,
+}
+
+/// A set of line styles for the parts of a chart, to give the charts of an
+/// application, or of several libraries, the same look.
+///
+/// The [`Default`] presets are light, finely dashed grid lines, solid dark axes,
+/// data lines with round joins, and dashed annotations. They can be changed
+/// field by field, or with the `serde` feature, read from a file, where any
+/// preset that's missing keeps its default:
+///
+/// ```
+/// use piet::{ChartElement, ChartStyles, Color, RenderContext};
+/// use piet::kurbo::Line;
+///
+/// # let mut ctx = piet::NullRenderContext::new();
+/// let mut styles = ChartStyles::default();
+/// styles.data_line.color = Color::rgb8(0xd0, 0x40, 0x20);
+///
+/// let data = styles.resolve(&mut ctx, ChartElement::DataLine);
+/// data.stroke(&mut ctx, Line::new((0.0, 40.0), (100.0, 10.0)));
+///
+/// # #[cfg(feature = "serde")]
+/// # {
+/// let file = r##"{ "axis": { "color": "#202020", "width": 2.0 } }"##;
+/// let styles: ChartStyles = serde_json::from_str(file).unwrap();
+/// assert_eq!(styles.axis.width, 2.0);
+/// assert_eq!(styles.grid.stroke, ChartStyles::default().grid.stroke);
+/// # }
+/// ```
+///
+/// [`Default`]: #impl-Default
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ChartStyles {
+    /// The style of [`ChartElement::Grid`](enum.ChartElement.html#variant.Grid).
+    pub grid: LineStyle,
+    /// The style of [`ChartElement::Axis`](enum.ChartElement.html#variant.Axis).
+    pub axis: LineStyle,
+    /// The style of [`ChartElement::DataLine`](enum.ChartElement.html#variant.DataLine).
+    pub data_line: LineStyle,
+    /// The style of [`ChartElement::Annotation`](enum.ChartElement.html#variant.Annotation).
+    pub annotation: LineStyle,
+}
+
+impl LineStyle {
+    /// A solid line.
+    pub const fn new(color: Color, width: f64) -> LineStyle {
+        LineStyle {
+            color,
+            width,
+            stroke: StrokeStyle::new(),
+        }
+    }
+
+    /// The line, with its joins, caps and dashes set by `stroke`.
+    pub fn with_stroke(mut self, stroke: StrokeStyle) -> LineStyle {
+        self.stroke = stroke;
+        self
+    }
+
+    /// Make the brush of the line for `ctx`.
+    pub fn resolve<R: RenderContext>(&self, ctx: &mut R) -> ResolvedLineStyle<R::Brush> {
+        ResolvedLineStyle {
+            brush: ctx.solid_brush(self.color.clone()),
+            width: self.width,
+            stroke: self.stroke.clone(),
+        }
+    }
+}
+
+impl<B> ResolvedLineStyle<B> {
+    /// Stroke `shape` with the line style.
+    pub fn stroke<R: RenderContext>(&self, ctx: &mut R, shape: impl Shape)
+    where
+        B: IntoBrush<R>,
+    {
+        ctx.stroke_styled(shape, &self.brush, self.width, &self.stroke);
+    }
+}
+
+impl ChartStyles {
+    /// The style of `element`.
+    pub fn get(&self, element: ChartElement) -> &LineStyle {
+        match element {
+            ChartElement::Grid => &self.grid,
+            ChartElement::Axis => &self.axis,
+            ChartElement::DataLine => &self.data_line,
+            ChartElement::Annotation => &self.annotation,
+        }
+    }
+
+    /// The style of `element`, to change it.
+    pub fn get_mut(&mut self, element: ChartElement) -> &mut LineStyle {
+        match element {
+            ChartElement::Grid => &mut self.grid,
+            ChartElement::Axis => &mut self.axis,
+            ChartElement::DataLine => &mut self.data_line,
+            ChartElement::Annotation => &mut self.annotation,
+        }
+    }
+
+    /// Make the brush of `element`'s style for `ctx`.
+    pub fn resolve<R: RenderContext>(
+        &self,
+        ctx: &mut R,
+        element: ChartElement,
+    ) -> ResolvedLineStyle<R::Brush> {
+        self.get(element).resolve(ctx)
+    }
+}
+
+impl Default for ChartStyles {
+    fn default() -> ChartStyles {
+        ChartStyles {
+            grid: LineStyle::new(Color::grey8(0xd0), 0.5)
+                .with_stroke(StrokeStyle::dashed(&GRID_DASHES, 0.0).with_line_cap(LineCap::Butt)),
+            axis: LineStyle::new(Color::grey8(0x40), 1.0)
+                .with_stroke(StrokeStyle::new().with_line_cap(LineCap::Square)),
+            data_line: LineStyle::new(Color::rgb8(0x1f, 0x77, 0xb4), 1.5).with_stroke(
+                StrokeStyle::new()
+                    .with_line_join(LineJoin::Round)
+                    .with_line_cap(LineCap::Round),
+            ),
+            annotation: LineStyle::new(Color::grey8(0x60), 1.0)
+                .with_stroke(StrokeStyle::dashed(&ANNOTATION_DASHES, 0.0)),
+        }
+    }
+}
@@ -1,7 +1,12 @@
 //! Traits for fonts and text handling.
 
-use crate::kurbo::Point;
-use crate::Error;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::kurbo::{Affine, Point, Rect, Vec2};
+use crate::{BidiLevels, Color, EmojiStrategy, Error};
 
 pub trait Text {
     type FontBuilder: FontBuilder<Out = Self::Font>;
@@ -10,22 +15,926 @@ pub trait Text {
     type TextLayoutBuilder: TextLayoutBuilder<Out = Self::TextLayout>;
     type TextLayout: TextLayout;
 
+    /// Create a font builder for the named family.
+    ///
+    /// Generic family names, as recognized by [`GenericFamily::from_name`],
+    /// are resolved to a suitable face on each platform.
+    ///
+    /// [`GenericFamily::from_name`]: enum.GenericFamily.html#method.from_name
     fn new_font_by_name(&mut self, name: &str, size: f64) -> Self::FontBuilder;
 
     fn new_text_layout(&mut self, font: &Self::Font, text: &str) -> Self::TextLayoutBuilder;
+
+    /// Set the table consulted when a requested family isn't installed.
+    ///
+    /// This only affects fonts built afterwards. See [`FontSubstitutions`].
+    ///
+    /// [`FontSubstitutions`]: struct.FontSubstitutions.html
+    fn set_font_substitutions(&mut self, substitutions: FontSubstitutions);
+
+    /// Choose how emoji are drawn in text layouts built afterwards.
+    ///
+    /// See [`EmojiStrategy`].
+    ///
+    /// [`EmojiStrategy`]: enum.EmojiStrategy.html
+    fn set_emoji_strategy(&mut self, strategy: EmojiStrategy);
+
+    /// Load the TrueType or OpenType font in `data`, or the first font of a
+    /// collection, for fonts built afterwards, returning its family name.
+    ///
+    /// Faces loaded with the same family are one family, picked between by
+    /// weight and style like the installed ones. Nothing is read from the
+    /// filesystem, so this works in sandboxes without access to the system's
+    /// fonts.
+    ///
+    /// Returns [`ErrorKind::InvalidInput`] if `data` isn't a font, and
+    /// [`ErrorKind::NotSupported`] if the backend can't load fonts from
+    /// memory; see [`Capabilities::font_loading`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use piet::{FontBuilder, RenderContext, Text};
+    ///
+    /// # let mut render_ctx = piet::NullRenderContext::new();
+    /// let data = std::fs::read("assets/Inter-Regular.otf").unwrap();
+    /// let mut text = render_ctx.text();
+    /// text.set_system_fonts(false);
+    /// let family = text.load_font(&data).unwrap();
+    /// let font = text.new_font_by_name(&family, 12.0).build().unwrap();
+    /// ```
+    ///
+    /// [`ErrorKind::InvalidInput`]: enum.ErrorKind.html#variant.InvalidInput
+    /// [`ErrorKind::NotSupported`]: enum.ErrorKind.html#variant.NotSupported
+    /// [`Capabilities::font_loading`]: struct.Capabilities.html#structfield.font_loading
+    fn load_font(&mut self, data: &[u8]) -> Result<String, Error>;
+
+    /// Whether fonts built afterwards may use the fonts installed on the
+    /// system, which they do by default.
+    ///
+    /// With them off, only families from [`load_font`] are used: any other
+    /// family, generic ones included, falls back to the first family loaded,
+    /// with a [`FontWarning::MissingFamily`]. Building a font fails with
+    /// [`ErrorKind::NotSupported`] if none has been loaded, or if the backend
+    /// can't draw text without the system's fonts.
+    ///
+    /// [`load_font`]: #tymethod.load_font
+    /// [`FontWarning::MissingFamily`]: enum.FontWarning.html#variant.MissingFamily
+    /// [`ErrorKind::NotSupported`]: enum.ErrorKind.html#variant.NotSupported
+    fn set_system_fonts(&mut self, enabled: bool);
+
+    /// Prepare the glyphs of `text` in `font` ahead of time.
+    ///
+    /// Backends load, shape and rasterize glyphs the first time they're
+    /// drawn, which can cause a visible hitch when a large font size or a
+    /// script with many glyphs (such as CJK) first appears. Calling this
+    /// while loading moves that work out of the first frame. Pass the text
+    /// that's about to be shown, or every character of a range you expect.
+    ///
+    /// Fonts are tied to the thread that made them, so this does its work on
+    /// the calling thread. It is only a hint; the default does nothing.
+    fn prewarm(&mut self, _font: &Self::Font, _text: &str) {}
 }
 
+/// A font being made by [`new_font_by_name`], at the normal weight, style
+/// and width until they're changed, and with no fallback families.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{FontBuilder, FontStretch, FontStyle, FontWeight, RenderContext, Text};
+///
+/// # let mut render_ctx = piet::NullRenderContext::new();
+/// let font = render_ctx
+///     .text()
+///     .new_font_by_name("sans-serif", 14.0)
+///     .weight(FontWeight::SEMI_BOLD)
+///     .style(FontStyle::Italic)
+///     .stretch(FontStretch::Condensed)
+///     .build()
+///     .unwrap();
+/// ```
+///
+/// [`new_font_by_name`]: trait.Text.html#tymethod.new_font_by_name
 pub trait FontBuilder {
     type Out: Font;
 
+    /// Use the face of the family closest to `weight`.
+    fn weight(self, weight: FontWeight) -> Self;
+
+    /// Use an italic or oblique face of the family.
+    ///
+    /// Where the family has no face in that style, backends slant the normal
+    /// one.
+    fn style(self, style: FontStyle) -> Self;
+
+    /// Use the face of the family closest to `stretch`, for narrower or wider
+    /// glyphs.
+    ///
+    /// Families with a single width ignore this, and so does cairo, whose toy
+    /// text API can't select faces by width.
+    fn stretch(self, stretch: FontStretch) -> Self;
+
+    /// Draw the characters the font has no glyph for with `family`, rather
+    /// than as boxes.
+    ///
+    /// Fallback families are tried in the order they're added, for each
+    /// grapheme cluster that the font and the families before lack; generic
+    /// names like `"monospace"` work too. Clusters none of them have go on
+    /// to the platform's own fallbacks, where it has them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::{FontBuilder, RenderContext, Text};
+    ///
+    /// # let mut render_ctx = piet::NullRenderContext::new();
+    /// let font = render_ctx
+    ///     .text()
+    ///     .new_font_by_name("Inter", 14.0)
+    ///     .fallback("Noto Sans CJK JP")
+    ///     .fallback("Noto Sans Symbols")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    fn fallback(self, family: &str) -> Self;
+
+    /// Set an axis of a variable font, such as its weight or width, to any
+    /// value the font allows, rather than picking the nearest face.
+    ///
+    /// A later setting of the same axis replaces an earlier one, and axes the
+    /// font doesn't have are ignored. Backends that can't vary fonts, as
+    /// [`Capabilities::variable_fonts`] reports, ignore every axis, so set
+    /// the nearest [`weight`] and [`stretch`] too.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::{FontBuilder, FontVariation, FontWeight, RenderContext, Text};
+    ///
+    /// # let mut render_ctx = piet::NullRenderContext::new();
+    /// let font = render_ctx
+    ///     .text()
+    ///     .new_font_by_name("Inter", 14.0)
+    ///     .weight(FontWeight::SEMI_BOLD)
+    ///     .variation(FontVariation::weight(650.0))
+    ///     .variation(FontVariation::new(*b"opsz", 14.0))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`Capabilities::variable_fonts`]: struct.Capabilities.html#structfield.variable_fonts
+    /// [`weight`]: #tymethod.weight
+    /// [`stretch`]: #tymethod.stretch
+    fn variation(self, variation: FontVariation) -> Self;
+
     fn build(self) -> Result<Self::Out, Error>;
 }
 
-pub trait Font {}
+pub trait Font {
+    /// Problems encountered while resolving this font.
+    ///
+    /// Building a font doesn't fail when the requested family isn't
+    /// available; the backend falls back to a default family instead. This
+    /// reports when that happened, so applications can tell the user, or
+    /// bundle the font.
+    fn warnings(&self) -> &[FontWarning] {
+        &[]
+    }
+
+    /// Where the font recommends drawing underlines and strikethroughs.
+    fn decoration_metrics(&self) -> DecorationMetrics;
+}
+
+/// The placement of underlines and strikethroughs recommended by a font.
+///
+/// Offsets are to the top edge of each line, from the baseline, positive
+/// downward like the rest of piet's coordinates; an underline has a positive
+/// offset and a strikethrough a negative one. All values are in the same
+/// units as the font size.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::Vec2;
+/// use piet::{Color, FontBuilder, RenderContext, Text, TextLayout, TextLayoutBuilder};
+///
+/// # let mut render_ctx = piet::NullRenderContext::new();
+/// let mut text = render_ctx.text();
+/// let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+/// let layout = text.new_text_layout(&font, "underlined").build().unwrap();
+/// let metrics = layout.decoration_metrics();
+/// let (x, y) = (10.0, 20.0);
+/// render_ctx.draw_text(&layout, (x, y), &Color::BLACK);
+/// let underline = metrics.underline_rect(x, x + layout.width());
+/// render_ctx.fill(underline + Vec2::new(0.0, y), &Color::BLACK);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DecorationMetrics {
+    pub underline_offset: f64,
+    pub underline_thickness: f64,
+    pub strikethrough_offset: f64,
+    pub strikethrough_thickness: f64,
+}
+
+impl DecorationMetrics {
+    /// Typical metrics for a font of `size`, for backends that can't read
+    /// them from the font.
+    ///
+    /// The proportions are close to those of common sans-serif faces.
+    pub fn estimate(size: f64) -> DecorationMetrics {
+        DecorationMetrics {
+            underline_offset: size * 0.1,
+            underline_thickness: size * 0.07,
+            strikethrough_offset: size * -0.3,
+            strikethrough_thickness: size * 0.05,
+        }
+    }
+
+    /// The rectangle covered by an underline from `x0` to `x1`, relative to
+    /// the baseline.
+    pub fn underline_rect(&self, x0: f64, x1: f64) -> Rect {
+        let y0 = self.underline_offset;
+        Rect::new(x0, y0, x1, y0 + self.underline_thickness)
+    }
+
+    /// The rectangle covered by a strikethrough from `x0` to `x1`, relative
+    /// to the baseline.
+    pub fn strikethrough_rect(&self, x0: f64, x1: f64) -> Rect {
+        let y0 = self.strikethrough_offset;
+        Rect::new(x0, y0, x1, y0 + self.strikethrough_thickness)
+    }
+}
+
+/// A non-fatal problem encountered while building a [`Font`].
+///
+/// [`Font`]: trait.Font.html
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontWarning {
+    /// The requested family was not found, and `fallback` was used instead.
+    MissingFamily {
+        /// The family name passed to [`new_font_by_name`].
+        ///
+        /// [`new_font_by_name`]: trait.Text.html#tymethod.new_font_by_name
+        requested: String,
+        /// The family used in its place.
+        fallback: String,
+    },
+    /// The requested family was not found, and `substitute` from the
+    /// [`FontSubstitutions`] table was used instead.
+    ///
+    /// [`FontSubstitutions`]: struct.FontSubstitutions.html
+    Substituted {
+        /// The family name passed to [`new_font_by_name`].
+        ///
+        /// [`new_font_by_name`]: trait.Text.html#tymethod.new_font_by_name
+        requested: String,
+        /// The family used in its place.
+        substitute: String,
+    },
+    /// More warnings may be added later.
+    #[doc(hidden)]
+    _NonExhaustive,
+}
+
+impl std::fmt::Display for FontWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FontWarning::MissingFamily {
+                requested,
+                fallback,
+            } => write!(
+                f,
+                "font family \"{}\" not found, using \"{}\"",
+                requested, fallback
+            ),
+            FontWarning::Substituted {
+                requested,
+                substitute,
+            } => write!(
+                f,
+                "font family \"{}\" not found, substituted \"{}\"",
+                requested, substitute
+            ),
+            _ => write!(f, "unknown font warning"),
+        }
+    }
+}
+
+/// A style applied to a range of a text layout, with
+/// [`TextLayoutBuilder::range_attribute`].
+///
+/// # Examples
+///
+/// Highlighting a search match:
+///
+/// ```
+/// use piet::{Color, FontBuilder, RenderContext, Text, TextAttribute, TextLayoutBuilder};
+///
+/// # let mut render_ctx = piet::NullRenderContext::new();
+/// let mut text = render_ctx.text();
+/// let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+/// let layout = text
+///     .new_text_layout(&font, "needle in a haystack")
+///     .range_attribute(0..6, TextAttribute::Background(Color::rgb8(0xff, 0xff, 0)))
+///     .build()
+///     .unwrap();
+/// render_ctx.draw_text(&layout, (10.0, 20.0), &Color::BLACK);
+/// ```
+///
+/// Mixing styles in one label:
+///
+/// ```
+/// use piet::{FontBuilder, FontWeight, RenderContext, Text, TextAttribute, TextLayoutBuilder};
+///
+/// # let mut render_ctx = piet::NullRenderContext::new();
+/// let mut text = render_ctx.text();
+/// let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+/// let layout = text
+///     .new_text_layout(&font, "Warning: disk almost full")
+///     .range_attribute(0..8, TextAttribute::Weight(FontWeight::BOLD))
+///     .range_attribute(9..13, TextAttribute::Italic(true))
+///     .range_attribute(14..25, TextAttribute::Size(16.0))
+///     .range_attribute(14..25, TextAttribute::Underline(true))
+///     .build()
+///     .unwrap();
+/// ```
+///
+/// [`TextLayoutBuilder::range_attribute`]: trait.TextLayoutBuilder.html#tymethod.range_attribute
+#[derive(Debug, Clone)]
+pub enum TextAttribute {
+    /// A color painted behind the glyphs.
+    ///
+    /// It fills the rectangles [`range_bounds`] would report for the range,
+    /// one per visual run when bidirectional text splits it, so it covers the
+    /// full line height and adjacent ranges meet without gaps.
+    ///
+    /// [`range_bounds`]: trait.TextLayout.html#tymethod.range_bounds
+    Background(Color),
+    /// The color of the glyphs, in place of the layout's default color.
+    ///
+    /// Emoji drawn from bitmaps keep their own colors.
+    Foreground(Color),
+    /// The font size, in place of the layout font's.
+    ///
+    /// A line is as tall as the largest text on it, so lines with larger
+    /// text push the baselines around them apart. Sizes that aren't positive
+    /// and finite are ignored.
+    Size(f64),
+    /// The font weight, in place of the layout font's.
+    Weight(FontWeight),
+    /// Whether the glyphs are italic, in place of the layout font's style.
+    Italic(bool),
+    /// Whether to draw a line under the glyphs, in their color.
+    ///
+    /// It's placed by the [`DecorationMetrics`] of the font at the text's
+    /// size.
+    ///
+    /// [`DecorationMetrics`]: struct.DecorationMetrics.html
+    Underline(bool),
+    /// Whether to draw a line through the glyphs, in their color, placed
+    /// like an underline.
+    Strikethrough(bool),
+    /// Space added after each grapheme cluster, in the units of the font
+    /// size; negative spacing tightens the text.
+    ///
+    /// Glyphs are still shaped together, so kerning and ligatures are kept
+    /// inside each cluster. Spacing that isn't finite is ignored.
+    LetterSpacing(f64),
+    /// Space added after each space between words, on top of any letter
+    /// spacing. See [`is_word_separator`].
+    ///
+    /// [`is_word_separator`]: fn.is_word_separator.html
+    WordSpacing(f64),
+    /// An OpenType feature to turn on or off, such as tabular numbers or
+    /// small capitals. Features for the same tag in overlapping ranges
+    /// replace each other, as other attributes do.
+    ///
+    /// DirectWrite applies any feature the font has. Cairo's toy text API
+    /// can't apply features, so cairo lays [`TABULAR_NUMBERS`] out itself, by
+    /// centering each digit in the advance of the widest one, and ignores
+    /// the rest; canvas ignores them all.
+    ///
+    /// [`TABULAR_NUMBERS`]: struct.FontFeature.html#associatedconstant.TABULAR_NUMBERS
+    FontFeature(FontFeature),
+    /// More attributes may be added later.
+    #[doc(hidden)]
+    _NonExhaustive,
+}
+
+/// An OpenType feature setting: a four-letter tag, and a value that is 1 to
+/// turn the feature on, 0 to turn it off, or for features with alternates,
+/// the alternate to use.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{FontBuilder, FontFeature, RenderContext, Text, TextAttribute, TextLayoutBuilder};
+///
+/// # let mut rc = piet::NullRenderContext::new();
+/// let mut text = rc.text();
+/// let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+/// // numbers that line up down a column
+/// let price = "1,111.10";
+/// let cell = text
+///     .new_text_layout(&font, price)
+///     .range_attribute(0..price.len(), TextAttribute::FontFeature(FontFeature::TABULAR_NUMBERS))
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(FontFeature::new(*b"ss01", 1).to_string(), "\"ss01\" 1");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FontFeature {
+    tag: [u8; 4],
+    value: u32,
+}
+
+impl FontFeature {
+    /// Standard ligatures, such as "fi"; most fonts turn these on by default.
+    pub const LIGATURES: FontFeature = FontFeature::new(*b"liga", 1);
+    pub const NO_LIGATURES: FontFeature = FontFeature::new(*b"liga", 0);
+    /// Lowercase letters drawn as small capitals.
+    pub const SMALL_CAPS: FontFeature = FontFeature::new(*b"smcp", 1);
+    /// Digits of equal width, for numbers that line up in columns.
+    pub const TABULAR_NUMBERS: FontFeature = FontFeature::new(*b"tnum", 1);
+    /// Digits as wide as their shapes, for numbers in running text.
+    pub const PROPORTIONAL_NUMBERS: FontFeature = FontFeature::new(*b"pnum", 1);
+    /// Digits that rise and fall like lowercase letters.
+    pub const OLDSTYLE_NUMBERS: FontFeature = FontFeature::new(*b"onum", 1);
+    /// Digits the height of capitals.
+    pub const LINING_NUMBERS: FontFeature = FontFeature::new(*b"lnum", 1);
+    /// A zero with a slash, to tell it apart from the letter O.
+    pub const SLASHED_ZERO: FontFeature = FontFeature::new(*b"zero", 1);
+
+    /// A feature with an OpenType tag, such as `*b"ss01"`.
+    pub const fn new(tag: [u8; 4], value: u32) -> FontFeature {
+        FontFeature { tag, value }
+    }
+
+    pub fn tag(&self) -> [u8; 4] {
+        self.tag
+    }
+
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+}
+
+/// The feature as CSS `font-feature-settings` writes it.
+impl std::fmt::Display for FontFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write_setting(f, self.tag, self.value)
+    }
+}
+
+/// Write a tag and its value as CSS writes font settings: `"tag" value`.
+fn write_setting(
+    f: &mut std::fmt::Formatter,
+    tag: [u8; 4],
+    value: impl std::fmt::Display,
+) -> std::fmt::Result {
+    write!(f, "\"{}\" {}", String::from_utf8_lossy(&tag), value)
+}
+
+/// The setting of an axis of a variable font: a four-letter tag, and a
+/// value in the axis's units.
+///
+/// The registered axes have constructors; others, including a font's own
+/// axes, whose tags are in capitals, are made with [`new`].
+///
+/// ```
+/// use piet::FontVariation;
+///
+/// assert_eq!(FontVariation::weight(650.0).to_string(), "\"wght\" 650");
+/// assert_eq!(FontVariation::new(*b"GRAD", -25.0).to_string(), "\"GRAD\" -25");
+/// ```
+///
+/// [`new`]: #method.new
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontVariation {
+    tag: [u8; 4],
+    value: f64,
+}
+
+impl FontVariation {
+    /// A setting of the axis with an OpenType tag, such as `*b"opsz"`.
+    pub const fn new(tag: [u8; 4], value: f64) -> FontVariation {
+        FontVariation { tag, value }
+    }
+
+    /// The weight, from 1 to 1000 like [`FontWeight`].
+    ///
+    /// [`FontWeight`]: struct.FontWeight.html
+    pub const fn weight(weight: f64) -> FontVariation {
+        FontVariation::new(*b"wght", weight)
+    }
+
+    /// The width, as a percentage of the normal width.
+    pub const fn width(percent: f64) -> FontVariation {
+        FontVariation::new(*b"wdth", percent)
+    }
+
+    /// The slant, in degrees counterclockwise from upright, so text that
+    /// leans right has a negative slant.
+    pub const fn slant(degrees: f64) -> FontVariation {
+        FontVariation::new(*b"slnt", degrees)
+    }
+
+    pub fn tag(&self) -> [u8; 4] {
+        self.tag
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+/// The setting as CSS `font-variation-settings` writes it.
+impl std::fmt::Display for FontVariation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write_setting(f, self.tag, self.value)
+    }
+}
+
+/// The weight of a font, from 1 to 1000 as in CSS, with 400 the normal
+/// weight and 700 bold.
+///
+/// Backends use the closest weight the family has; cairo's toy text API only
+/// knows normal and bold, and picks bold from 600 up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FontWeight(u16);
+
+impl FontWeight {
+    pub const THIN: FontWeight = FontWeight(100);
+    pub const EXTRA_LIGHT: FontWeight = FontWeight(200);
+    pub const LIGHT: FontWeight = FontWeight(300);
+    pub const NORMAL: FontWeight = FontWeight(400);
+    pub const MEDIUM: FontWeight = FontWeight(500);
+    pub const SEMI_BOLD: FontWeight = FontWeight(600);
+    pub const BOLD: FontWeight = FontWeight(700);
+    pub const EXTRA_BOLD: FontWeight = FontWeight(800);
+    pub const BLACK: FontWeight = FontWeight(900);
+
+    /// A weight from its number, clamped to 1 to 1000.
+    pub fn new(weight: u16) -> FontWeight {
+        FontWeight(weight.clamp(1, 1000))
+    }
+
+    pub fn to_raw(self) -> u16 {
+        self.0
+    }
+}
+
+impl Default for FontWeight {
+    fn default() -> Self {
+        FontWeight::NORMAL
+    }
+}
+
+/// The slant of a font, as in CSS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FontStyle {
+    #[default]
+    Normal,
+    /// A face designed to be slanted, often with different letterforms.
+    Italic,
+    /// The normal letterforms, slanted.
+    Oblique,
+}
+
+impl FontStyle {
+    /// The CSS name of this style.
+    pub fn name(self) -> &'static str {
+        match self {
+            FontStyle::Normal => "normal",
+            FontStyle::Italic => "italic",
+            FontStyle::Oblique => "oblique",
+        }
+    }
+}
+
+/// How narrow or wide the glyphs of a font are, from the nine widths of
+/// CSS and OpenType.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FontStretch {
+    UltraCondensed,
+    ExtraCondensed,
+    Condensed,
+    SemiCondensed,
+    #[default]
+    Normal,
+    SemiExpanded,
+    Expanded,
+    ExtraExpanded,
+    UltraExpanded,
+}
+
+impl FontStretch {
+    /// The CSS name of this width.
+    pub fn name(self) -> &'static str {
+        match self {
+            FontStretch::UltraCondensed => "ultra-condensed",
+            FontStretch::ExtraCondensed => "extra-condensed",
+            FontStretch::Condensed => "condensed",
+            FontStretch::SemiCondensed => "semi-condensed",
+            FontStretch::Normal => "normal",
+            FontStretch::SemiExpanded => "semi-expanded",
+            FontStretch::Expanded => "expanded",
+            FontStretch::ExtraExpanded => "extra-expanded",
+            FontStretch::UltraExpanded => "ultra-expanded",
+        }
+    }
+
+    /// The width as a percentage of the normal one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::FontStretch;
+    ///
+    /// assert_eq!(FontStretch::Normal.percentage(), 100.0);
+    /// assert_eq!(FontStretch::Condensed.percentage(), 75.0);
+    /// ```
+    pub fn percentage(self) -> f64 {
+        match self {
+            FontStretch::UltraCondensed => 50.0,
+            FontStretch::ExtraCondensed => 62.5,
+            FontStretch::Condensed => 75.0,
+            FontStretch::SemiCondensed => 87.5,
+            FontStretch::Normal => 100.0,
+            FontStretch::SemiExpanded => 112.5,
+            FontStretch::Expanded => 125.0,
+            FontStretch::ExtraExpanded => 150.0,
+            FontStretch::UltraExpanded => 200.0,
+        }
+    }
+}
+
+/// How the lines of a layout are placed across its width.
+///
+/// The width is the layout's [`max_width`], or without one, that of its
+/// widest line.
+///
+/// [`max_width`]: trait.TextLayoutBuilder.html#tymethod.max_width
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TextAlignment {
+    /// Lines start at the left edge.
+    #[default]
+    Start,
+    /// Lines end at the right edge.
+    End,
+    /// Lines are centered.
+    Center,
+    /// Lines that wrap are widened to both edges, at their spaces. The last
+    /// line of each paragraph, and lines with no spaces, stay at the start.
+    Justified,
+}
+
+/// What a layout shows of text that runs past its [`max_lines`].
+///
+/// [`max_lines`]: trait.TextLayoutBuilder.html#tymethod.max_lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TextTruncation {
+    /// The lines past the limit aren't shown.
+    #[default]
+    Clip,
+    /// The last line shown ends in "…", after as many characters of the
+    /// rest of its paragraph as fit.
+    CharacterEllipsis,
+    /// The last line shown ends in "…", after as many whole words of the
+    /// rest of its paragraph as fit. A word too long to fit on its own is
+    /// cut between characters.
+    WordEllipsis,
+}
+
+/// The direction the lines of a layout run in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WritingMode {
+    /// Lines run across, and stack downward.
+    #[default]
+    Horizontal,
+    /// Lines run down as columns, and stack leftward, as East Asian text is
+    /// set vertically. Han, kana and hangul stand upright; Latin and other
+    /// horizontal scripts are turned a quarter turn clockwise, reading down
+    /// the column.
+    VerticalRightToLeft,
+}
 
 pub trait TextLayoutBuilder {
     type Out: TextLayout;
 
+    /// Display every character as `mask`, as in a password field.
+    ///
+    /// Only the displayed glyphs change: text positions passed to and
+    /// returned from the layout's hit testing still refer to the original
+    /// string. See [`ObscuredText`] for the mapping.
+    ///
+    /// [`ObscuredText`]: struct.ObscuredText.html
+    fn obscure(self, mask: char) -> Self;
+
+    /// Apply `attribute` to the text in `range`.
+    ///
+    /// Attributes added later take precedence where ranges overlap. Ranges
+    /// past the end of the text are clamped, and empty ranges are ignored.
+    fn range_attribute(self, range: Range<usize>, attribute: TextAttribute) -> Self;
+
+    /// Draw the text in `color`, except where a [`TextAttribute::Foreground`]
+    /// range overrides it.
+    ///
+    /// Text with no color from the layout is drawn with the brush passed to
+    /// [`draw_text`], so a layout with a default color ignores that brush.
+    /// Together with foreground ranges, this draws syntax-highlighted code
+    /// as a single layout:
+    ///
+    /// ```
+    /// use piet::{Color, FontBuilder, RenderContext, Text, TextAttribute, TextLayoutBuilder};
+    ///
+    /// # let mut render_ctx = piet::NullRenderContext::new();
+    /// let mut text = render_ctx.text();
+    /// let font = text.new_font_by_name("monospace", 12.0).build().unwrap();
+    /// let layout = text
+    ///     .new_text_layout(&font, "let x = 42;")
+    ///     .default_color(Color::grey(0.9))
+    ///     .range_attribute(0..3, TextAttribute::Foreground(Color::rgb8(0xc6, 0x78, 0xdd)))
+    ///     .range_attribute(8..10, TextAttribute::Foreground(Color::rgb8(0xd1, 0x9a, 0x66)))
+    ///     .build()
+    ///     .unwrap();
+    /// render_ctx.draw_text(&layout, (10.0, 20.0), &Color::BLACK);
+    /// ```
+    ///
+    /// [`TextAttribute::Foreground`]: enum.TextAttribute.html#variant.Foreground
+    /// [`draw_text`]: trait.RenderContext.html#tymethod.draw_text
+    fn default_color(self, color: Color) -> Self;
+
+    /// Lay the text out in a local coordinate frame, for drawing under
+    /// `transform`.
+    ///
+    /// The layout's measurements stay in the frame's own units; the
+    /// transform only says which device pixels the text will land on.
+    /// Backends that round glyph advances to whole pixels round them in
+    /// device space instead, so scaled text keeps its spacing, and don't
+    /// round them at all under rotation or skew, where the text doesn't
+    /// follow the pixel grid. Without this, text is laid out for the
+    /// identity transform.
+    ///
+    /// Pass the transform that will be current when the layout is drawn.
+    /// Its translation is ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::kurbo::Affine;
+    /// use piet::{Color, FontBuilder, RenderContext, Text, TextLayout, TextLayoutBuilder};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// // an axis label, running up the side of a chart
+    /// rc.transform(Affine::translate((20.0, 200.0)) * Affine::rotate(-std::f64::consts::FRAC_PI_2));
+    /// let transform = rc.current_transform();
+    /// let mut text = rc.text();
+    /// let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+    /// let layout = text
+    ///     .new_text_layout(&font, "Throughput (MB/s)")
+    ///     .transform(transform)
+    ///     .build()
+    ///     .unwrap();
+    /// // centered along the axis, in the rotated frame
+    /// rc.draw_text(&layout, (100.0 - layout.width() / 2.0, 0.0), &Color::BLACK);
+    /// ```
+    fn transform(self, transform: Affine) -> Self;
+
+    /// Wrap the text into lines no wider than `width`.
+    ///
+    /// Lines break at spaces, keeping words whole where they fit; a word
+    /// too wide for a line of its own is broken between grapheme clusters.
+    /// The spaces at a wrap hang past the end of the line, and don't count
+    /// toward its width. Each line's baseline is one line height below the
+    /// one before, and [`width`] is the width of the widest line. See
+    /// [`wrap_lines`] for the exact rules.
+    ///
+    /// `'\n'` starts a new line whether or not the text wraps. `width` is
+    /// in the layout's own units, whatever its [`transform`]. Without this,
+    /// or with a `width` that is infinite or NaN, lines are as long as the
+    /// text between line breaks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::{Color, FontBuilder, RenderContext, Text, TextLayout, TextLayoutBuilder};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let mut text = rc.text();
+    /// let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+    /// let layout = text
+    ///     .new_text_layout(&font, "A paragraph long enough to need more than one line.")
+    ///     .max_width(120.0)
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(layout.width() <= 120.0);
+    /// // the first baseline is at the position drawn to
+    /// rc.draw_text(&layout, (10.0, 20.0), &Color::BLACK);
+    /// ```
+    ///
+    /// [`width`]: trait.TextLayout.html#tymethod.width
+    /// [`wrap_lines`]: fn.wrap_lines.html
+    /// [`transform`]: #tymethod.transform
+    fn max_width(self, width: f64) -> Self;
+
+    /// Place the lines across the layout's width, as [`TextAlignment`]
+    /// describes. The default is [`TextAlignment::Start`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::{FontBuilder, RenderContext, Text, TextAlignment, TextLayoutBuilder};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let mut text = rc.text();
+    /// let font = text.new_font_by_name("serif", 12.0).build().unwrap();
+    /// let column = text
+    ///     .new_text_layout(&font, "Justified lines meet both edges of the column.")
+    ///     .max_width(120.0)
+    ///     .alignment(TextAlignment::Justified)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`TextAlignment`]: enum.TextAlignment.html
+    /// [`TextAlignment::Start`]: enum.TextAlignment.html#variant.Start
+    fn alignment(self, alignment: TextAlignment) -> Self;
+
+    /// Show at most `lines` lines, cutting the text short as `truncation`
+    /// describes if it runs past them. Zero is taken to be one.
+    ///
+    /// The last line shown is as wide as [`max_width`] allows, ellipsis
+    /// included. Hit testing maps the ellipsis to the text it stands for,
+    /// and positions in that text to the ellipsis.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::{FontBuilder, RenderContext, Text, TextLayout, TextLayoutBuilder, TextTruncation};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let mut text = rc.text();
+    /// let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+    /// let label = text
+    ///     .new_text_layout(&font, "A file name far too long for its column.txt")
+    ///     .max_width(80.0)
+    ///     .max_lines(1, TextTruncation::CharacterEllipsis)
+    ///     .build()
+    ///     .unwrap();
+    /// if label.is_truncated() {
+    ///     // show the full name in a tooltip
+    /// }
+    /// ```
+    ///
+    /// [`max_width`]: #tymethod.max_width
+    fn max_lines(self, lines: usize, truncation: TextTruncation) -> Self;
+
+    /// Lay the lines out in the direction [`WritingMode`] describes. The
+    /// default is [`WritingMode::Horizontal`].
+    ///
+    /// In vertical text, [`max_width`] limits the length of each column,
+    /// which runs down the layout, and [`width`] is the width of all the
+    /// columns side by side. The position a vertical layout is drawn at is
+    /// its top-left corner, rather than a baseline.
+    ///
+    /// Not every backend can set text vertically; those that can't lay it
+    /// out horizontally instead, as if this wasn't called. Check
+    /// [`Capabilities::vertical_text`] to choose another layout for them,
+    /// such as one character per line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::{FontBuilder, RenderContext, Text, TextLayoutBuilder, WritingMode};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let vertical = rc.capabilities().vertical_text;
+    /// let mut text = rc.text();
+    /// let font = text.new_font_by_name("serif", 16.0).build().unwrap();
+    /// let builder = text.new_text_layout(&font, "縦書きの文章");
+    /// let column = if vertical {
+    ///     builder.writing_mode(WritingMode::VerticalRightToLeft).max_width(200.0)
+    /// } else {
+    ///     builder.max_width(16.0)
+    /// };
+    /// let layout = column.build().unwrap();
+    /// ```
+    ///
+    /// [`WritingMode`]: enum.WritingMode.html
+    /// [`WritingMode::Horizontal`]: enum.WritingMode.html#variant.Horizontal
+    /// [`max_width`]: #tymethod.max_width
+    /// [`width`]: trait.TextLayout.html#tymethod.width
+    /// [`Capabilities::vertical_text`]: struct.Capabilities.html#structfield.vertical_text
+    fn writing_mode(self, mode: WritingMode) -> Self;
+
     fn build(self) -> Result<Self::Out, Error>;
 }
 
@@ -42,7 +951,8 @@ pub trait TextLayoutBuilder {
 /// occur.
 ///
 pub trait TextLayout {
-    /// Measure the advance width of the text.
+    /// Measure the advance width of the text, or of its widest line when it
+    /// has more than one.
     fn width(&self) -> f64;
 
     /// Given a `Point`, determine the corresponding text position.
@@ -58,7 +968,8 @@ pub trait TextLayout {
     /// ## Notes:
     ///
     /// Some text position will always be returned; if the tested point is inside, it returns the appropriate text
-    /// position; if it's outside, it will return the nearest text position (either `0` or `text.len()`).
+    /// position; if it's outside, it will return the nearest text position (the start or end of the nearest
+    /// line).
     ///
     /// For more on text positions, see docs for the [`TextLayout`](../piet/trait.TextLayout.html)
     /// trait.
@@ -68,7 +979,6 @@ pub trait TextLayout {
     fn hit_test_point(&self, point: Point) -> HitTestPoint;
 
     /// Given a text position, determine the corresponding pixel location.
-    /// (currently consider the text layout just one line)
     ///
     /// ## Return value:
     /// Returns a [`HitTestTextPosition`][] describing the results of the test.
@@ -79,12 +989,8 @@ pub trait TextLayout {
     /// [`HitTestTextPosition`][] field `metrics` is a [`HitTestMetrics`][] struct. [`HitTestMetrics`][] field `text_position` is the original text position (unless out of bounds).
     ///
     /// ## Notes:
-    /// In directwrite, if a text position is not at code point boundary, this method will panic.
-    /// Cairo and web are more lenient and may not panic.
-    ///
-    /// For text position that is greater than `text.len()`, web/cairo will return the
-    /// [`HitTestTextPosition`][] as if `text_position == text.len()`. In directwrite, the method will
-    /// panic, as the text position is out of bounds.
+    /// A text position inside a grapheme cluster gives the point of the start of the cluster. A text
+    /// position greater than `text.len()` gives the [`HitTestTextPosition`][] for `text.len()`.
     ///
     /// For more on text positions, see docs for the [`TextLayout`](../piet/trait.TextLayout.html)
     /// trait.
@@ -92,6 +998,134 @@ pub trait TextLayout {
     /// [`HitTestTextPosition`]: struct.HitTestTextPosition.html
     /// [`HitTestMetrics`]: struct.HitTestMetrics.html
     fn hit_test_text_position(&self, text_position: usize) -> Option<HitTestTextPosition>;
+
+    /// Return the bounding box of the text in the given range.
+    ///
+    /// The rectangle is in the same coordinate space as the `pos` passed to
+    /// [`draw_text`], that is, relative to the left end of the first
+    /// baseline. A range over several lines gets the box around all of
+    /// them; see [`rects_for_range`] for a box per line. It spans the full
+    /// ascent and descent of the font, so the rectangles for adjacent ranges
+    /// line up, which makes this suitable for hover highlights.
+    ///
+    /// Returns `None` if the range is empty. A range that extends past the
+    /// end of the text is clamped to the text length.
+    ///
+    /// [`draw_text`]: trait.RenderContext.html#tymethod.draw_text
+    /// [`rects_for_range`]: #tymethod.rects_for_range
+    fn range_bounds(&self, range: Range<usize>) -> Option<Rect>;
+
+    /// The rectangles covering the text in the given range, for drawing a
+    /// selection highlight.
+    ///
+    /// There is at least one rectangle for each line the range is on, and
+    /// more where a line is split into runs drawn apart from each other.
+    /// Each has the height of [`range_bounds`], and their union is the
+    /// rectangle it returns.
+    ///
+    /// ```
+    /// use piet::kurbo::Point;
+    /// use piet::{Color, FontBuilder, RenderContext, Text, TextLayout, TextLayoutBuilder};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let mut text = rc.text();
+    /// let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+    /// let layout = text.new_text_layout(&font, "two\nlines").build().unwrap();
+    /// let origin = Point::new(10.0, 20.0);
+    /// let highlight = Color::rgba8(0x33, 0x66, 0xff, 0x66);
+    /// for rect in layout.rects_for_range(1..6) {
+    ///     rc.fill(rect + origin.to_vec2(), &highlight);
+    /// }
+    /// rc.draw_text(&layout, origin, &Color::BLACK);
+    /// ```
+    ///
+    /// Returns no rectangles if the range is empty. A range that extends
+    /// past the end of the text is clamped to the text length.
+    ///
+    /// [`range_bounds`]: #tymethod.range_bounds
+    fn rects_for_range(&self, range: Range<usize>) -> Vec<Rect>;
+
+    /// The caret at a text position, as a rectangle of no width from the
+    /// top to the bottom of its line.
+    ///
+    /// The rectangle's `x` is that of the point from
+    /// [`hit_test_text_position`], and it spans the same height as
+    /// [`range_bounds`]. Inflate it for a caret with some width:
+    ///
+    /// ```
+    /// use piet::kurbo::Point;
+    /// use piet::{Color, FontBuilder, RenderContext, Text, TextLayout, TextLayoutBuilder};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let mut text = rc.text();
+    /// let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+    /// let layout = text.new_text_layout(&font, "caret").build().unwrap();
+    /// let origin = Point::new(10.0, 20.0);
+    /// rc.draw_text(&layout, origin, &Color::BLACK);
+    /// if let Some(caret) = layout.caret_bounds(2) {
+    ///     rc.fill(caret.inflate(0.5, 0.0) + origin.to_vec2(), &Color::BLACK);
+    /// }
+    /// ```
+    ///
+    /// Returns `None` where [`hit_test_text_position`] does.
+    ///
+    /// [`hit_test_text_position`]: #tymethod.hit_test_text_position
+    /// [`range_bounds`]: #tymethod.range_bounds
+    fn caret_bounds(&self, text_position: usize) -> Option<Rect>;
+
+    /// The metrics of each line of the layout, from top to bottom.
+    ///
+    /// Every layout has at least one line, even with no text. Centering the
+    /// text in a box, or lining up its baseline with other text, works the
+    /// same whatever the font and backend:
+    ///
+    /// ```
+    /// use piet::kurbo::Rect;
+    /// use piet::{Color, FontBuilder, RenderContext, Text, TextLayout, TextLayoutBuilder};
+    ///
+    /// # let mut rc = piet::NullRenderContext::new();
+    /// let mut text = rc.text();
+    /// let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+    /// let layout = text.new_text_layout(&font, "OK").build().unwrap();
+    /// let button = Rect::new(0.0, 0.0, 80.0, 24.0);
+    /// let lines = layout.line_metrics();
+    /// if let (Some(first), Some(last)) = (lines.first(), lines.last()) {
+    ///     // from the top of the first line to the bottom of the last
+    ///     let height = first.ascent + last.baseline + last.descent;
+    ///     let x = button.center().x - layout.width() / 2.0;
+    ///     let y = button.center().y - height / 2.0 + first.ascent;
+    ///     rc.draw_text(&layout, (x, y), &Color::BLACK);
+    /// }
+    /// ```
+    fn line_metrics(&self) -> Vec<LineMetric>;
+
+    /// The position and advance of each grapheme cluster of the displayed
+    /// text, in order.
+    ///
+    /// There's one for each cluster that [`draw_glyph_run`] takes a
+    /// transform for, so the two can be zipped to move glyphs by hand;
+    /// [`draw_text_on_path`] does this to bend text along a curve.
+    ///
+    /// [`draw_glyph_run`]: trait.RenderContext.html#tymethod.draw_glyph_run
+    /// [`draw_text_on_path`]: trait.RenderContext.html#method.draw_text_on_path
+    fn cluster_metrics(&self) -> Vec<ClusterMetric>;
+
+    /// Where the layout's font recommends drawing underlines and
+    /// strikethroughs.
+    fn decoration_metrics(&self) -> DecorationMetrics;
+
+    /// Where the text was cut short to fit [`max_lines`], or `None` if it
+    /// fits. The text from there on isn't shown, or is shown as an ellipsis.
+    ///
+    /// [`max_lines`]: trait.TextLayoutBuilder.html#tymethod.max_lines
+    fn truncated_at(&self) -> Option<usize>;
+
+    /// Whether the text was cut short to fit [`max_lines`].
+    ///
+    /// [`max_lines`]: trait.TextLayoutBuilder.html#tymethod.max_lines
+    fn is_truncated(&self) -> bool {
+        self.truncated_at().is_some()
+    }
 }
 
 /// return values for [`hit_test_point`](../piet/trait.TextLayout.html#tymethod.hit_test_point).
@@ -108,7 +1142,8 @@ pub struct HitTestPoint {
 /// return values for [`hit_test_text_position`](../piet/trait.TextLayout.html#tymethod.hit_test_text_position).
 #[derive(Debug, Default)]
 pub struct HitTestTextPosition {
-    /// the `point`'s `x` value is the position of the leading edge of the grapheme cluster containing the text position.
+    /// the `point`'s `x` value is the position of the leading edge of the grapheme cluster containing the text position,
+    /// and its `y` value is the baseline of the line it's on.
     pub point: Point,
     /// `metrics.text_position` will give you the text position.
     pub metrics: HitTestMetrics,
@@ -123,3 +1158,1786 @@ pub struct HitTestMetrics {
     // consider adding other metrics as needed, such as those provided in
     // [DWRITE_HIT_TEST_METRICS](https://docs.microsoft.com/en-us/windows/win32/api/dwrite/ns-dwrite-dwrite_hit_test_metrics).
 }
+
+/// The metrics of a line of text, returned by
+/// [`line_metrics`](../piet/trait.TextLayout.html#tymethod.line_metrics).
+///
+/// Like other positions in a layout, `baseline` is relative to the first
+/// baseline, so it's 0.0 for the first line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LineMetric {
+    /// The text on the line, without the line break or the spaces it
+    /// wrapped at.
+    pub range: Range<usize>,
+    /// The y of the line's baseline.
+    pub baseline: f64,
+    /// How far the line reaches above its baseline.
+    pub ascent: f64,
+    /// How far the line reaches below its baseline.
+    pub descent: f64,
+    /// The distance from this line's baseline to the next one's.
+    pub height: f64,
+}
+
+/// The position of a grapheme cluster in a layout, returned by
+/// [`cluster_metrics`](../piet/trait.TextLayout.html#tymethod.cluster_metrics).
+///
+/// Like other positions in a layout, `origin` is relative to the left end of
+/// the first baseline.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClusterMetric {
+    /// The left end of the cluster, on its line's baseline.
+    pub origin: Point,
+    /// The width of the cluster; 0.0 for line breaks, and the spaces
+    /// hanging at wraps.
+    pub advance: f64,
+}
+
+/// How the origin of drawn text is placed relative to the device pixel grid.
+///
+/// Set with [`RenderContext::set_glyph_positioning`]. The default is
+/// [`PixelSnapped`](#variant.PixelSnapped).
+///
+/// [`RenderContext::set_glyph_positioning`]: trait.RenderContext.html#tymethod.set_glyph_positioning
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GlyphPositioning {
+    /// Round the text origin to a whole device pixel, taking the current
+    /// transform into account.
+    ///
+    /// This gives the crispest result for static UI text, but text that is
+    /// animated will move in one-pixel steps.
+    #[default]
+    PixelSnapped,
+    /// Draw text at exactly the requested position, including fractional
+    /// pixel offsets.
+    ///
+    /// Use this for text that moves smoothly, such as during scrolling or
+    /// zoom animations.
+    Subpixel,
+}
+
+/// A generic font family, as in CSS.
+///
+/// Requesting a generic family gives a sensible face for that style on every
+/// platform: fontconfig aliases with cairo, the usual GDI families with
+/// DirectWrite, and the CSS generics on the web.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GenericFamily {
+    /// Fonts with serifs, such as Times New Roman.
+    Serif,
+    /// Fonts without serifs, such as Arial or DejaVu Sans.
+    SansSerif,
+    /// Fonts where every glyph has the same advance, such as Consolas.
+    Monospace,
+    /// Handwriting-like fonts.
+    Cursive,
+    /// Decorative fonts.
+    Fantasy,
+    /// The platform's user interface font.
+    SystemUi,
+}
+
+impl GenericFamily {
+    /// Recognize a generic family name, ignoring case.
+    ///
+    /// Besides the CSS names, the fontconfig shorthands `sans` and `mono`
+    /// are accepted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use piet::GenericFamily;
+    ///
+    /// assert_eq!(GenericFamily::from_name("Monospace"), Some(GenericFamily::Monospace));
+    /// assert_eq!(GenericFamily::from_name("sans"), Some(GenericFamily::SansSerif));
+    /// assert_eq!(GenericFamily::from_name("Helvetica"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<GenericFamily> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "serif" => Some(GenericFamily::Serif),
+            "sans-serif" | "sans" => Some(GenericFamily::SansSerif),
+            "monospace" | "mono" => Some(GenericFamily::Monospace),
+            "cursive" => Some(GenericFamily::Cursive),
+            "fantasy" => Some(GenericFamily::Fantasy),
+            "system-ui" => Some(GenericFamily::SystemUi),
+            _ => None,
+        }
+    }
+
+    /// The CSS name of this family, which fontconfig also understands.
+    pub fn name(self) -> &'static str {
+        match self {
+            GenericFamily::Serif => "serif",
+            GenericFamily::SansSerif => "sans-serif",
+            GenericFamily::Monospace => "monospace",
+            GenericFamily::Cursive => "cursive",
+            GenericFamily::Fantasy => "fantasy",
+            GenericFamily::SystemUi => "system-ui",
+        }
+    }
+}
+
+/// A table of replacement families for fonts that aren't installed.
+///
+/// When a requested family is missing, backends look it up here before
+/// falling back to their default. This lets documents that name common
+/// Windows or macOS fonts lay out with similar metrics elsewhere. Names are
+/// matched ignoring case.
+///
+/// # Examples
+///
+/// ```
+/// use piet::FontSubstitutions;
+///
+/// let mut subs = FontSubstitutions::metric_compatible();
+/// subs.insert("Segoe UI", "Noto Sans");
+/// assert_eq!(subs.get("arial"), Some("Liberation Sans"));
+/// assert_eq!(subs.get("Segoe UI"), Some("Noto Sans"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FontSubstitutions {
+    map: HashMap<String, String>,
+}
+
+impl FontSubstitutions {
+    /// An empty table.
+    pub fn new() -> FontSubstitutions {
+        FontSubstitutions::default()
+    }
+
+    /// A table of freely available fonts designed to be metric-compatible
+    /// with common proprietary ones, such as Liberation Sans for Arial.
+    pub fn metric_compatible() -> FontSubstitutions {
+        let mut subs = FontSubstitutions::new();
+        for &(family, substitute) in &[
+            ("Arial", "Liberation Sans"),
+            ("Helvetica", "Liberation Sans"),
+            ("Times New Roman", "Liberation Serif"),
+            ("Times", "Liberation Serif"),
+            ("Courier New", "Liberation Mono"),
+            ("Courier", "Liberation Mono"),
+            ("Arial Narrow", "Liberation Sans Narrow"),
+            ("Calibri", "Carlito"),
+            ("Cambria", "Caladea"),
+            ("Georgia", "Gelasio"),
+        ] {
+            subs.insert(family, substitute);
+        }
+        subs
+    }
+
+    /// Use `substitute` when `family` is missing, replacing any previous
+    /// entry for `family`.
+    pub fn insert(&mut self, family: &str, substitute: &str) {
+        self.map
+            .insert(family.to_lowercase(), substitute.to_owned());
+    }
+
+    /// The substitute for `family`, if there is one.
+    pub fn get(&self, family: &str) -> Option<&str> {
+        self.map.get(&family.to_lowercase()).map(String::as_str)
+    }
+}
+
+/// The displayed form of obscured text, with the mapping back to the
+/// original.
+///
+/// Each character of the original is shown as one `mask` character. This is
+/// what backends use to implement [`TextLayoutBuilder::obscure`]; it's public
+/// for custom layouts that need the same behavior.
+///
+/// # Examples
+///
+/// ```
+/// use piet::ObscuredText;
+///
+/// let obscured = ObscuredText::new("pa\u{df}word", '\u{2022}');
+/// assert_eq!(obscured.display(), "\u{2022}".repeat(7));
+/// // 'ß' is two bytes in the original, '•' three in the display
+/// assert_eq!(obscured.to_display(4), 9);
+/// assert_eq!(obscured.to_original(9), 4);
+/// ```
+///
+/// [`TextLayoutBuilder::obscure`]: trait.TextLayoutBuilder.html#tymethod.obscure
+#[derive(Debug, Clone)]
+pub struct ObscuredText {
+    display: String,
+    mask_len: usize,
+    /// Byte offset of each character in the original, plus its length.
+    original_boundaries: Vec<usize>,
+}
+
+impl ObscuredText {
+    /// Obscure `text`, showing each character as `mask`.
+    pub fn new(text: &str, mask: char) -> ObscuredText {
+        let mut original_boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+        original_boundaries.push(text.len());
+        let count = original_boundaries.len() - 1;
+        ObscuredText {
+            display: std::iter::repeat_n(mask, count).collect(),
+            mask_len: mask.len_utf8(),
+            original_boundaries,
+        }
+    }
+
+    /// The text to lay out and draw.
+    pub fn display(&self) -> &str {
+        &self.display
+    }
+
+    /// Convert a text position in the original to one in the display text.
+    ///
+    /// Positions inside a character round down to its start; positions past
+    /// the end are clamped.
+    pub fn to_display(&self, original: usize) -> usize {
+        let index = match self.original_boundaries.binary_search(&original) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        };
+        index * self.mask_len
+    }
+
+    /// The length of the original text.
+    pub fn original_len(&self) -> usize {
+        *self.original_boundaries.last().unwrap()
+    }
+
+    /// Convert a text position in the display text to one in the original.
+    ///
+    /// Positions past the end are clamped.
+    pub fn to_original(&self, display: usize) -> usize {
+        let index = (display / self.mask_len).min(self.original_boundaries.len() - 1);
+        self.original_boundaries[index]
+    }
+}
+
+/// The displayed form of text cut short to a number of lines, with the
+/// mapping back to the whole text.
+///
+/// This is what backends use to implement [`TextLayoutBuilder::max_lines`];
+/// it's public for custom layouts that need the same behavior. The ellipsis
+/// stands for the text cut off: positions in that text map to the end of the
+/// ellipsis, and the ellipsis maps to where it starts or the end of the text.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{TextTruncation, TruncatedText};
+///
+/// // every character is 1 wide
+/// let measure = |range: std::ops::Range<usize>| range.len() as f64;
+/// let text = "one two three\nfour";
+/// let lines = piet::wrap_lines(text, 8.0, |s| s.len() as f64);
+/// assert_eq!(lines, [0..7, 8..13, 14..18]);
+///
+/// let truncated =
+///     TruncatedText::new(text, &lines, 1, TextTruncation::WordEllipsis, 8.0, 1.0, measure)
+///         .unwrap();
+/// assert_eq!(truncated.display(), "one two\u{2026}");
+/// assert_eq!(truncated.lines(), [0..10]);
+/// assert_eq!(truncated.to_display(9), 10);
+/// assert_eq!(truncated.to_original(10), text.len());
+///
+/// let clipped = TruncatedText::new(text, &lines, 2, TextTruncation::Clip, 8.0, 1.0, measure);
+/// assert_eq!(clipped.unwrap().display(), "one two three");
+/// // text that fits isn't truncated
+/// assert!(TruncatedText::new(text, &lines, 3, TextTruncation::Clip, 8.0, 1.0, measure).is_none());
+/// ```
+///
+/// [`TextLayoutBuilder::max_lines`]: trait.TextLayoutBuilder.html#tymethod.max_lines
+#[derive(Debug, Clone)]
+pub struct TruncatedText {
+    display: String,
+    lines: Vec<Range<usize>>,
+    /// Where the text is cut, in both it and the display.
+    cut: usize,
+    original_len: usize,
+}
+
+impl TruncatedText {
+    /// The ellipsis shown at the end of truncated text.
+    pub const ELLIPSIS: &'static str = "\u{2026}";
+
+    /// Cut `text`, laid out in `lines` as [`wrap_lines`] returns them, to
+    /// `max_lines`, or return `None` if it fits.
+    ///
+    /// `measure` gives the width of a range of `text` as it's laid out, and
+    /// `ellipsis_width` that of [`ELLIPSIS`]. The last line shown is at most
+    /// `max_width` wide with the ellipsis, if that's finite.
+    ///
+    /// [`wrap_lines`]: fn.wrap_lines.html
+    /// [`ELLIPSIS`]: #associatedconstant.ELLIPSIS
+    pub fn new(
+        text: &str,
+        lines: &[Range<usize>],
+        max_lines: usize,
+        truncation: TextTruncation,
+        max_width: f64,
+        ellipsis_width: f64,
+        mut measure: impl FnMut(Range<usize>) -> f64,
+    ) -> Option<TruncatedText> {
+        let max_lines = max_lines.max(1);
+        if lines.len() <= max_lines {
+            return None;
+        }
+        let mut kept = lines[..max_lines].to_vec();
+        let last = kept.last_mut().unwrap();
+        if truncation == TextTruncation::Clip {
+            return Some(TruncatedText {
+                display: text[..last.end].to_owned(),
+                cut: last.end,
+                original_len: text.len(),
+                lines: kept,
+            });
+        }
+        // the rest of the last line's paragraph is what could be shown
+        let newline = text[last.start..]
+            .find('\n')
+            .map_or(text.len(), |i| last.start + i);
+        let paragraph = text[last.start..newline].trim_end_matches('\r');
+        let available = if max_width.is_nan() {
+            f64::INFINITY
+        } else {
+            max_width - ellipsis_width
+        };
+        let paragraph_end = last.start + paragraph.len();
+        let at_word_end =
+            |end: usize| end == paragraph_end || text[end..].starts_with(is_break_space);
+        let mut cut = last.start;
+        let mut word_cut = last.start;
+        for (i, grapheme) in paragraph.grapheme_indices(true) {
+            let end = last.start + i + grapheme.len();
+            if measure(last.start..end) > available {
+                break;
+            }
+            cut = end;
+            if at_word_end(end) {
+                word_cut = end;
+            }
+        }
+        if truncation == TextTruncation::WordEllipsis && word_cut > last.start {
+            cut = word_cut;
+        }
+        cut = last.start + text[last.start..cut].trim_end().len();
+        let mut display = text[..cut].to_owned();
+        display.push_str(Self::ELLIPSIS);
+        last.end = display.len();
+        Some(TruncatedText {
+            display,
+            cut,
+            original_len: text.len(),
+            lines: kept,
+        })
+    }
+
+    /// The text to lay out and draw.
+    pub fn display(&self) -> &str {
+        &self.display
+    }
+
+    /// The range of each line of the display text.
+    pub fn lines(&self) -> &[Range<usize>] {
+        &self.lines
+    }
+
+    /// Where the text is cut, in both it and the display text.
+    pub fn cut(&self) -> usize {
+        self.cut
+    }
+
+    /// The length of the whole text.
+    pub fn original_len(&self) -> usize {
+        self.original_len
+    }
+
+    /// Convert a text position in the whole text to one in the display text.
+    pub fn to_display(&self, original: usize) -> usize {
+        if original <= self.cut {
+            original
+        } else {
+            self.display.len()
+        }
+    }
+
+    /// Convert a text position in the display text to one in the whole text.
+    pub fn to_original(&self, display: usize) -> usize {
+        if display <= self.cut {
+            display
+        } else {
+            self.original_len
+        }
+    }
+}
+
+/// Split `text` into lines no wider than `max_width`, as measured by
+/// `measure`, returning the range of each line's text.
+///
+/// This is the line breaking behind [`TextLayoutBuilder::max_width`], for
+/// backends and custom layouts that measure text themselves:
+///
+/// - `'\n'` (or `"\r\n"`) always ends a line, and isn't part of it. Text
+///   ending with one has an empty last line.
+/// - Otherwise lines break before a word that follows spaces, if the line
+///   would be too wide with it. The spaces are left between the lines'
+///   ranges, so they don't count toward either width. No-break spaces don't
+///   allow a break.
+/// - A word too wide for a line of its own is broken between grapheme
+///   clusters, with at least one cluster on each line.
+///
+/// `measure` is given each word and run of spaces, and the grapheme
+/// clusters of words it breaks, and a line is as wide as the sum of its
+/// pieces. It isn't called at all when `max_width` is infinite or NaN.
+/// Every position in the text is either in a line's range, or between the
+/// end of one line and the start of the next.
+///
+/// # Examples
+///
+/// ```
+/// // every character is 1 wide
+/// let measure = |s: &str| s.chars().count() as f64;
+/// let text = "the quick brown fox\njumps";
+/// let lines: Vec<&str> = piet::wrap_lines(text, 10.0, measure)
+///     .into_iter()
+///     .map(|range| &text[range])
+///     .collect();
+/// assert_eq!(lines, ["the quick", "brown fox", "jumps"]);
+///
+/// let lines = piet::wrap_lines("abcdefgh", 3.0, measure);
+/// assert_eq!(lines, [0..3, 3..6, 6..8]);
+///
+/// // each word and space is measured once, however long the text
+/// let mut calls = 0;
+/// piet::wrap_lines("a b c d", 3.0, |s| {
+///     calls += 1;
+///     measure(s)
+/// });
+/// assert_eq!(calls, 7);
+/// ```
+///
+/// [`TextLayoutBuilder::max_width`]: trait.TextLayoutBuilder.html#tymethod.max_width
+pub fn wrap_lines(
+    text: &str,
+    max_width: f64,
+    mut measure: impl FnMut(&str) -> f64,
+) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    loop {
+        match text[start..].find('\n') {
+            Some(i) => {
+                let end = start + i;
+                let content_end = if text[..end].ends_with('\r') {
+                    end - 1
+                } else {
+                    end
+                };
+                wrap_paragraph(
+                    text,
+                    start..content_end,
+                    max_width,
+                    &mut measure,
+                    &mut lines,
+                );
+                start = end + 1;
+            }
+            None => {
+                wrap_paragraph(text, start..text.len(), max_width, &mut measure, &mut lines);
+                return lines;
+            }
+        }
+    }
+}
+
+/// Wrap the text of `range`, which has no line breaks, pushing its lines.
+fn wrap_paragraph(
+    text: &str,
+    range: Range<usize>,
+    max_width: f64,
+    measure: &mut impl FnMut(&str) -> f64,
+    lines: &mut Vec<Range<usize>>,
+) {
+    if max_width.is_nan() || max_width == f64::INFINITY {
+        lines.push(range);
+        return;
+    }
+    let mut line_start = range.start;
+    // the width of the line's text so far
+    let mut width = 0.0;
+    // the end of the line and the start of the next, if it breaks at spaces,
+    // and the width of the line before the next starts
+    let mut last_break = None;
+    let mut spaces_start = None;
+    for (i, segment) in text[range.clone()].split_word_bound_indices() {
+        let i = range.start + i;
+        let segment_width = measure(segment);
+        if segment.chars().all(is_break_space) {
+            spaces_start.get_or_insert(i);
+            width += segment_width;
+            continue;
+        }
+        if let Some(spaces) = spaces_start.take() {
+            if spaces > line_start {
+                last_break = Some((spaces, i, width));
+            }
+        }
+        width += segment_width;
+        if width <= max_width {
+            continue;
+        }
+        if let Some((line_end, next, before)) = last_break.take() {
+            lines.push(line_start..line_end);
+            line_start = next;
+            width -= before;
+        }
+        if width <= max_width {
+            continue;
+        }
+        // break between clusters, with at least one on each line
+        let end = i + segment.len();
+        let word_start = line_start;
+        width = 0.0;
+        for (j, cluster) in text[word_start..end].grapheme_indices(true) {
+            let j = word_start + j;
+            let cluster_width = measure(cluster);
+            if j > line_start && width + cluster_width > max_width {
+                lines.push(line_start..j);
+                line_start = j;
+                width = 0.0;
+            }
+            width += cluster_width;
+        }
+    }
+    lines.push(line_start..range.end);
+}
+
+/// Where [`align_lines`] places a line.
+///
+/// [`align_lines`]: fn.align_lines.html
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinePlacement {
+    /// How far the line's start is from the layout's.
+    pub x: f64,
+    /// The space added after each of the line's spaces, to justify it.
+    pub space: f64,
+}
+
+impl LinePlacement {
+    /// How far a position on the line moves, given the line's text before
+    /// it.
+    pub fn offset(&self, before: &str) -> f64 {
+        if self.space == 0.0 {
+            return self.x;
+        }
+        self.x + self.space * before.chars().filter(|&c| is_justifiable(c)).count() as f64
+    }
+}
+
+/// Place the `lines` of `text` across `width`, as `alignment` describes,
+/// for backends that lay out lines themselves.
+///
+/// `lines` are as [`wrap_lines`] returns them, and `measure` is given the
+/// range of each to measure. A `width` that's infinite or NaN is taken to be
+/// that of the widest line. Lines wider than `width` start at the start.
+///
+/// # Examples
+///
+/// ```
+/// use piet::{LinePlacement, TextAlignment};
+///
+/// // every character is 1 wide
+/// let text = "a bc de\nfg";
+/// let lines = piet::wrap_lines(text, 5.0, |s| s.len() as f64);
+/// assert_eq!(lines, [0..4, 5..7, 8..10]);
+///
+/// let measure = |range: std::ops::Range<usize>| range.len() as f64;
+/// let centered = piet::align_lines(text, &lines, TextAlignment::Center, 5.0, measure);
+/// assert_eq!(centered[1], LinePlacement { x: 1.5, space: 0.0 });
+///
+/// let justified = piet::align_lines(text, &lines, TextAlignment::Justified, 5.0, measure);
+/// // "a bc" widens to 5 at its space, so "bc" moves along by 1
+/// assert_eq!(justified[0], LinePlacement { x: 0.0, space: 1.0 });
+/// assert_eq!(justified[0].offset("a "), 1.0);
+/// // each paragraph ends at the start
+/// assert_eq!(justified[1], LinePlacement::default());
+/// assert_eq!(justified[2], LinePlacement::default());
+/// ```
+///
+/// [`wrap_lines`]: fn.wrap_lines.html
+pub fn align_lines(
+    text: &str,
+    lines: &[Range<usize>],
+    alignment: TextAlignment,
+    width: f64,
+    mut measure: impl FnMut(Range<usize>) -> f64,
+) -> Vec<LinePlacement> {
+    if alignment == TextAlignment::Start {
+        return vec![LinePlacement::default(); lines.len()];
+    }
+    let widths: Vec<f64> = lines.iter().map(|line| measure(line.clone())).collect();
+    let width = if width.is_finite() {
+        width
+    } else {
+        widths.iter().copied().fold(0.0, f64::max)
+    };
+    lines
+        .iter()
+        .zip(widths)
+        .map(|(line, line_width)| {
+            let extra = (width - line_width).max(0.0);
+            match alignment {
+                TextAlignment::Start => LinePlacement::default(),
+                TextAlignment::End => LinePlacement {
+                    x: extra,
+                    space: 0.0,
+                },
+                TextAlignment::Center => LinePlacement {
+                    x: extra / 2.0,
+                    space: 0.0,
+                },
+                TextAlignment::Justified => {
+                    let rest = &text[line.end..];
+                    let last =
+                        rest.is_empty() || rest.starts_with('\n') || rest.starts_with("\r\n");
+                    let spaces = text[line.clone()]
+                        .chars()
+                        .filter(|&c| is_justifiable(c))
+                        .count();
+                    if last || spaces == 0 {
+                        LinePlacement::default()
+                    } else {
+                        LinePlacement {
+                            x: 0.0,
+                            space: extra / spaces as f64,
+                        }
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
+/// Whether justifying a line widens `c`.
+fn is_justifiable(c: char) -> bool {
+    matches!(c, ' ' | '\u{a0}' | '\u{3000}')
+}
+
+/// Whether a line can break after `c`.
+fn is_break_space(c: char) -> bool {
+    c.is_whitespace() && !matches!(c, '\u{a0}' | '\u{2007}' | '\u{202f}')
+}
+
+/// Whether `grapheme` separates words, so it's widened by
+/// [`TextAttribute::WordSpacing`].
+///
+/// These are the spaces CSS word spacing applies to: the space, the no-break
+/// space, and a few word dividers of other scripts.
+///
+/// # Examples
+///
+/// ```
+/// assert!(piet::is_word_separator(" "));
+/// assert!(!piet::is_word_separator("\t"));
+/// assert!(!piet::is_word_separator("a"));
+/// ```
+///
+/// [`TextAttribute::WordSpacing`]: enum.TextAttribute.html#variant.WordSpacing
+pub fn is_word_separator(grapheme: &str) -> bool {
+    let mut chars = grapheme.chars();
+    let first = chars.next();
+    // a combining mark makes a different cluster
+    chars.next().is_none()
+        && matches!(
+            first,
+            Some(
+                ' ' | '\u{a0}' | '\u{1361}' | '\u{10100}' | '\u{10101}' | '\u{1039f}' | '\u{1091f}'
+            )
+        )
+}
+
+/// `range` of `text` widened to whole grapheme clusters, for layouts to
+/// measure ranges callers pass in.
+///
+/// The range is clamped to the text first, and if it's empty or reversed,
+/// it's the empty range at the start of the cluster its start is in.
+///
+/// # Examples
+///
+/// ```
+/// let text = "e\u{301}t\u{e9}";
+/// // the combining accent is one cluster with the 'e' before it
+/// assert_eq!(piet::grapheme_range(text, 1..4), 0..4);
+/// // 'é' is two bytes
+/// assert_eq!(piet::grapheme_range(text, 5..6), 4..6);
+/// assert_eq!(piet::grapheme_range(text, 5..2), 4..4);
+/// assert_eq!(piet::grapheme_range(text, 4..100), 4..6);
+/// ```
+pub fn grapheme_range(text: &str, range: Range<usize>) -> Range<usize> {
+    let boundaries = || {
+        let starts = text.grapheme_indices(true).map(|(i, _)| i);
+        starts.chain(std::iter::once(text.len()))
+    };
+    let start = boundaries()
+        .take_while(|&i| i <= range.start)
+        .last()
+        .unwrap_or(0);
+    if range.end <= range.start {
+        return start..start;
+    }
+    let end = boundaries().find(|&i| i >= range.end).unwrap_or(text.len());
+    start..end
+}
+
+/// The part of a text layout that doesn't depend on the backend: the text
+/// shown for the original, its lines and where they're placed, and where
+/// each grapheme cluster is, for drawing and hit testing.
+///
+/// Backends that lay out lines themselves build one of these in steps,
+/// measuring the text with their own fonts, and answer [`TextLayout`]'s
+/// queries from it:
+///
+/// 1. [`new`] obscures the text if asked, and [`style_ranges`] and
+///    [`attributes_at`] tell the backend which fonts to measure it in.
+/// 2. [`wrap`] breaks it into lines, and [`truncate`] cuts it short, after
+///    which the backend picks its fonts again.
+/// 3. [`place`] places the lines and measures each grapheme cluster, given
+///    the runs of the text in one font.
+///
+/// `measure` gives the advance of a range of the displayed text, which is
+/// passed to it, with any letter or word spacing. Positions are in the
+/// displayed text, except in the methods named after [`TextLayout`]'s,
+/// which take and return positions in the original.
+///
+/// # Examples
+///
+/// ```
+/// use piet::kurbo::Point;
+/// use piet::{LayoutModel, LayoutRun, TextAlignment};
+///
+/// // every byte is 1 wide
+/// let measure = |_: &str, range: std::ops::Range<usize>| range.len() as f64;
+/// let mut model = LayoutModel::new("one two three".to_string(), None, Vec::new(), None);
+/// model.wrap(8.0, measure);
+/// assert_eq!(model.lines(), [0..7, 8..13]);
+///
+/// let run = LayoutRun {
+///     range: 0..13,
+///     ascent: 8.0,
+///     descent: 2.0,
+///     height: 12.0,
+///     ..Default::default()
+/// };
+/// model.place(TextAlignment::Start, 8.0, vec![run], measure);
+/// assert_eq!(model.width(), 7.0);
+/// let hit = model.hit_test_text_position(9).unwrap();
+/// assert_eq!(hit.point, Point::new(1.0, 12.0));
+/// assert_eq!(model.hit_test_point(Point::new(1.2, 12.0)).metrics.text_position, 9);
+/// ```
+///
+/// [`TextLayout`]: trait.TextLayout.html
+/// [`new`]: #method.new
+/// [`style_ranges`]: #method.style_ranges
+/// [`attributes_at`]: #method.attributes_at
+/// [`wrap`]: #method.wrap
+/// [`truncate`]: #method.truncate
+/// [`place`]: #method.place
+#[derive(Debug, Clone)]
+pub struct LayoutModel {
+    /// The displayed text.
+    text: String,
+    obscured: Option<ObscuredText>,
+    /// Set when the text is cut short at a line limit; the cut is in the
+    /// obscured text, when it's obscured.
+    truncated: Option<TruncatedText>,
+    /// Ranges are into the original text.
+    attributes: Vec<(Range<usize>, TextAttribute)>,
+    default_color: Option<Color>,
+    runs: Vec<LayoutRun>,
+    /// The range of each line in the displayed text, from `wrap_lines`.
+    lines: Vec<Range<usize>>,
+    /// The vertical metrics of each line, from the largest font on it.
+    line_extents: Vec<LineExtents>,
+    /// Where each line is placed across the layout's width.
+    placements: Vec<LinePlacement>,
+    /// The bidi levels of the displayed text, when any of it is
+    /// right-to-left and its lines are reordered.
+    bidi: Option<BidiLevels>,
+    /// The start of each grapheme cluster of the displayed text.
+    clusters: Vec<usize>,
+    /// How far the end of each cluster is from the start of its line,
+    /// before the line is aligned.
+    cluster_ends: Vec<f64>,
+}
+
+/// A run of a [`LayoutModel`]'s text in one font, with the metrics the
+/// backend read from the font.
+///
+/// [`LayoutModel`]: struct.LayoutModel.html
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayoutRun {
+    /// The run's range of the displayed text.
+    pub range: Range<usize>,
+    /// How far the font reaches above the baseline.
+    pub ascent: f64,
+    /// How far the font reaches below the baseline.
+    pub descent: f64,
+    /// The distance between baselines the font asks for.
+    pub height: f64,
+    /// Where the font's underline and strikethrough are drawn.
+    pub decorations: DecorationMetrics,
+    pub underline: bool,
+    pub strikethrough: bool,
+}
+
+/// A grapheme cluster of a line of a [`LayoutModel`] that's reordered for
+/// right-to-left text, with where it's drawn.
+///
+/// [`LayoutModel`]: struct.LayoutModel.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisualCluster {
+    /// The cluster's range of the displayed text.
+    pub range: Range<usize>,
+    /// How far its left edge is from the start of the layout.
+    pub x0: f64,
+    /// How far its right edge is from the start of the layout.
+    pub x1: f64,
+    /// Whether it reads right to left.
+    pub rtl: bool,
+}
+
+impl VisualCluster {
+    /// The position at the cluster's left edge.
+    fn left(&self) -> usize {
+        if self.rtl {
+            self.range.end
+        } else {
+            self.range.start
+        }
+    }
+
+    /// The position at the cluster's right edge.
+    fn right(&self) -> usize {
+        if self.rtl {
+            self.range.start
+        } else {
+            self.range.end
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct LineExtents {
+    baseline: f64,
+    ascent: f64,
+    descent: f64,
+    /// The distance to the next baseline, or the font's line height on the
+    /// last line.
+    height: f64,
+}
+
+/// The edges of a grapheme cluster on its line, for hit testing.
+struct ClusterBounds {
+    start: usize,
+    end: usize,
+    leading: f64,
+    // not technically trailing; it's the leading edge of the next cluster
+    trailing: f64,
+}
+
+impl ClusterBounds {
+    /// The edge of the cluster nearer to `x`, if it's within the cluster.
+    fn hit_test(&self, x: f64) -> Option<HitTestPoint> {
+        if x < self.leading || x > self.trailing {
+            return None;
+        }
+        let mut hit = HitTestPoint::default();
+        let midpoint = self.leading + (self.trailing - self.leading) / 2.0;
+        hit.metrics.text_position = if x >= midpoint { self.end } else { self.start };
+        hit.is_inside = true;
+        Some(hit)
+    }
+}
+
+/// How many grapheme clusters of a word [`LayoutModel::place`] measures
+/// together, so that kerning and joining count, before it starts again.
+///
+/// [`LayoutModel::place`]: struct.LayoutModel.html#method.place
+const MEASURED_TOGETHER: usize = 32;
+
+impl LayoutModel {
+    /// A model of `text`, obscured with `mask` if it's given, broken into
+    /// lines only at its line breaks.
+    ///
+    /// `attributes` are as given to the layout builder, with ranges into
+    /// `text`.
+    pub fn new(
+        text: String,
+        mask: Option<char>,
+        attributes: Vec<(Range<usize>, TextAttribute)>,
+        default_color: Option<Color>,
+    ) -> LayoutModel {
+        let obscured = mask.map(|mask| ObscuredText::new(&text, mask));
+        let text = match &obscured {
+            Some(obscured) => obscured.display().to_owned(),
+            None => text,
+        };
+        LayoutModel {
+            lines: wrap_lines(&text, f64::INFINITY, |_| 0.0),
+            text,
+            obscured,
+            truncated: None,
+            attributes,
+            default_color,
+            runs: Vec::new(),
+            line_extents: Vec::new(),
+            placements: Vec::new(),
+            bidi: None,
+            clusters: Vec::new(),
+            cluster_ends: Vec::new(),
+        }
+    }
+
+    /// The text to lay out and draw.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The range of each line of the displayed text.
+    pub fn lines(&self) -> &[Range<usize>] {
+        &self.lines
+    }
+
+    /// The displayed text split where the attributes that change its font,
+    /// its spacing or its decorations start and end, moved back to the
+    /// start of the grapheme cluster they're in, in order.
+    ///
+    /// Empty text has no ranges.
+    pub fn style_ranges(&self) -> Vec<Range<usize>> {
+        let starts: Vec<usize> = self.text.grapheme_indices(true).map(|(i, _)| i).collect();
+        let snap = |i: usize| {
+            if i >= self.text.len() {
+                self.text.len()
+            } else {
+                starts[starts.partition_point(|&start| start <= i) - 1]
+            }
+        };
+        let mut bounds = vec![0, self.text.len()];
+        for (range, attribute) in &self.attributes {
+            if changes_style(attribute) {
+                let range = self.to_display_range(range.clone());
+                bounds.extend_from_slice(&[snap(range.start), snap(range.end)]);
+            }
+        }
+        bounds.sort_unstable();
+        bounds.dedup();
+        bounds.windows(2).map(|pair| pair[0]..pair[1]).collect()
+    }
+
+    /// The attributes that apply at `position` of the displayed text, in
+    /// the order they were given; the ellipsis of truncated text takes
+    /// those of the text before it.
+    pub fn attributes_at(&self, position: usize) -> impl Iterator<Item = &TextAttribute> + '_ {
+        let original = self.to_original(self.styled_position(position));
+        self.attributes
+            .iter()
+            .filter(move |(range, _)| range.contains(&original))
+            .map(|(_, attribute)| attribute)
+    }
+
+    /// Break the text into lines no wider than `max_width`, as
+    /// [`wrap_lines`] does.
+    ///
+    /// [`wrap_lines`]: fn.wrap_lines.html
+    pub fn wrap(&mut self, max_width: f64, mut measure: impl FnMut(&str, Range<usize>) -> f64) {
+        let text = &self.text;
+        self.lines = wrap_lines(text, max_width, |piece| {
+            // the pieces are slices of the text
+            let start = piece.as_ptr() as usize - text.as_ptr() as usize;
+            measure(text, start..start + piece.len())
+        });
+    }
+
+    /// Cut the text short at `max_lines`, as [`TruncatedText`] does, and
+    /// return whether it was, in which case the backend's runs need making
+    /// again.
+    ///
+    /// [`TruncatedText`]: struct.TruncatedText.html
+    pub fn truncate(
+        &mut self,
+        max_lines: usize,
+        truncation: TextTruncation,
+        max_width: f64,
+        ellipsis_width: f64,
+        mut measure: impl FnMut(&str, Range<usize>) -> f64,
+    ) -> bool {
+        let text = &self.text;
+        let truncated = TruncatedText::new(
+            text,
+            &self.lines,
+            max_lines,
+            truncation,
+            max_width,
+            ellipsis_width,
+            |range| measure(text, range),
+        );
+        match truncated {
+            Some(truncated) => {
+                self.text = truncated.display().to_owned();
+                self.lines = truncated.lines().to_vec();
+                self.truncated = Some(truncated);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Place the lines across `width` as `alignment` describes, as
+    /// [`align_lines`] does, and measure each grapheme cluster.
+    ///
+    /// `runs` cover the displayed text in order. Each cluster is measured
+    /// with the rest of its word before it, so kerning and joining between
+    /// letters count.
+    ///
+    /// [`align_lines`]: fn.align_lines.html
+    pub fn place(
+        &mut self,
+        alignment: TextAlignment,
+        width: f64,
+        runs: Vec<LayoutRun>,
+        mut measure: impl FnMut(&str, Range<usize>) -> f64,
+    ) {
+        let bidi = BidiLevels::new(&self.text);
+        self.bidi = if bidi.has_rtl() { Some(bidi) } else { None };
+        self.runs = runs;
+        self.line_extents = self.line_extents();
+        let text = &self.text;
+        self.placements = align_lines(text, &self.lines, alignment, width, |range| {
+            measure(text, range)
+        });
+
+        self.clusters = text.grapheme_indices(true).map(|(i, _)| i).collect();
+        self.cluster_ends = Vec::with_capacity(self.clusters.len());
+        // the start of the text measured together, and how far it is from
+        // the start of its line
+        let (mut segment, mut segment_x) = (0, 0.0);
+        let (mut measured, mut after_separator) = (0, false);
+        for (i, grapheme) in text.grapheme_indices(true) {
+            let separator = is_word_separator(grapheme);
+            let line_start = self.starts_line(i);
+            if line_start || separator || after_separator || measured == MEASURED_TOGETHER {
+                segment_x = match self.cluster_ends.last() {
+                    Some(&end) if !line_start => end,
+                    _ => 0.0,
+                };
+                segment = i;
+                measured = 0;
+            }
+            self.cluster_ends
+                .push(segment_x + measure(text, segment..i + grapheme.len()));
+            measured += 1;
+            after_separator = separator;
+        }
+    }
+
+    /// Whether a line starts at `position`, which is the start of a cluster.
+    fn starts_line(&self, position: usize) -> bool {
+        self.lines
+            .binary_search_by_key(&position, |line| line.start)
+            .is_ok()
+    }
+
+    /// The extents of each line, with the baselines spaced so each line's
+    /// top is below the gap its font leaves under the line before.
+    fn line_extents(&self) -> Vec<LineExtents> {
+        let mut lines: Vec<LineExtents> = self
+            .lines
+            .iter()
+            .map(|range| {
+                let on_line = self
+                    .runs
+                    .iter()
+                    .filter(|run| run.range.start < range.end && range.start < run.range.end);
+                let mut runs: Vec<&LayoutRun> = on_line.collect();
+                if runs.is_empty() {
+                    // an empty line is as tall as the text it's in
+                    runs.extend(self.run_of(range.start));
+                }
+                runs.into_iter()
+                    .fold(LineExtents::default(), |acc, run| LineExtents {
+                        baseline: 0.0,
+                        ascent: acc.ascent.max(run.ascent),
+                        descent: acc.descent.max(run.descent),
+                        height: acc.height.max(run.height),
+                    })
+            })
+            .collect();
+        for line in 1..lines.len() {
+            let above = lines[line - 1];
+            lines[line].baseline =
+                above.baseline + above.height - above.ascent + lines[line].ascent;
+            lines[line - 1].height = lines[line].baseline - above.baseline;
+        }
+        lines
+    }
+
+    /// The run `position` in the displayed text is in.
+    fn run_of(&self, position: usize) -> Option<&LayoutRun> {
+        let run = self
+            .runs
+            .partition_point(|run| run.range.start <= position)
+            .saturating_sub(1);
+        self.runs.get(run)
+    }
+
+    /// The line a position in the displayed text is on; positions between
+    /// lines are at the end of the one before.
+    pub fn line_of(&self, position: usize) -> usize {
+        self.lines
+            .partition_point(|line| line.start <= position)
+            .saturating_sub(1)
+    }
+
+    /// The line nearest to `y`, relative to the first baseline.
+    fn line_at(&self, y: f64) -> usize {
+        self.line_extents
+            .partition_point(|line| line.baseline - line.ascent <= y)
+            .clamp(1, self.lines.len())
+            - 1
+    }
+
+    /// The y of `line`'s baseline, relative to the first.
+    pub fn baseline(&self, line: usize) -> f64 {
+        self.line_extents[line].baseline
+    }
+
+    /// The offset of `position` from the start of the layout, on `line`.
+    pub fn line_x(&self, line: usize, position: usize) -> f64 {
+        if let Some(clusters) = self.visual_clusters(line) {
+            let range = &self.lines[line];
+            if position == range.end {
+                // the end of the line is where its paragraph reads to
+                let level = self
+                    .bidi
+                    .as_ref()
+                    .map_or(0, |b| b.paragraph_level(range.start));
+                let rtl = level % 2 == 1;
+                return match (rtl, clusters.first(), clusters.last()) {
+                    (true, Some(first), _) => first.x0,
+                    (false, _, Some(last)) => last.x1,
+                    _ => self.placements[line].x,
+                };
+            }
+            // the edge the position's cluster starts at, or the one before
+            // ends at
+            let starting = clusters.iter().find(|c| c.range.start == position);
+            let ending = clusters.iter().find(|c| c.range.end == position);
+            return match (starting, ending) {
+                (Some(c), _) if c.rtl => c.x1,
+                (Some(c), _) => c.x0,
+                (None, Some(c)) if c.rtl => c.x0,
+                (None, Some(c)) => c.x1,
+                (None, None) => self.placements[line].x,
+            };
+        }
+        let start = self.lines[line].start;
+        // the end of the cluster before `position`, or the one it's in
+        let advance = match self.clusters.partition_point(|&c| c < position) {
+            0 => 0.0,
+            _ if position <= start => 0.0,
+            after => self.cluster_ends[after - 1],
+        };
+        self.line_offset(line, position) + advance
+    }
+
+    /// How far alignment moves `position` on `line`.
+    pub fn line_offset(&self, line: usize, position: usize) -> f64 {
+        let before = self
+            .text
+            .get(self.lines[line].start..position)
+            .unwrap_or("");
+        self.placements[line].offset(before)
+    }
+
+    /// The range of the `cluster`th grapheme cluster.
+    fn cluster_range(&self, cluster: usize) -> Range<usize> {
+        let end = self
+            .clusters
+            .get(cluster + 1)
+            .copied()
+            .unwrap_or(self.text.len());
+        self.clusters[cluster]..end
+    }
+
+    /// The advance of the `cluster`th grapheme cluster.
+    fn cluster_advance(&self, cluster: usize) -> f64 {
+        let start = self.clusters[cluster];
+        let before = if cluster == 0 || self.starts_line(start) {
+            0.0
+        } else {
+            self.cluster_ends[cluster - 1]
+        };
+        self.cluster_ends[cluster] - before
+    }
+
+    /// The grapheme clusters of `line` from left to right, with their edges,
+    /// or `None` if the line has no right-to-left text and isn't reordered.
+    pub fn visual_clusters(&self, line: usize) -> Option<Vec<VisualCluster>> {
+        let runs = self.bidi.as_ref()?.visual_runs(self.lines[line].clone());
+        if !runs.iter().any(|run| run.is_rtl()) {
+            return None;
+        }
+        let placement = &self.placements[line];
+        let mut x = placement.x;
+        let mut clusters = Vec::new();
+        for run in runs {
+            let first = self.clusters.partition_point(|&c| c < run.range.start);
+            let end = self.clusters.partition_point(|&c| c < run.range.end);
+            let mut indices: Vec<usize> = (first..end).collect();
+            if run.is_rtl() {
+                indices.reverse();
+            }
+            for cluster in indices {
+                let range = self.cluster_range(cluster);
+                let range = range.start..range.end.min(run.range.end);
+                // the space justification adds after the cluster
+                let space = placement.offset(&self.text[range.clone()]) - placement.x;
+                let x1 = x + self.cluster_advance(cluster) + space;
+                clusters.push(VisualCluster {
+                    range,
+                    x0: x,
+                    x1,
+                    rtl: run.is_rtl(),
+                });
+                x = x1;
+            }
+        }
+        Some(clusters)
+    }
+
+    /// The runs of `line` at one direction, from left to right, each with
+    /// whether it reads right to left.
+    pub fn visual_runs(&self, line: usize) -> Vec<(Range<usize>, bool)> {
+        let range = self.lines[line].clone();
+        match &self.bidi {
+            Some(bidi) => bidi
+                .visual_runs(range)
+                .into_iter()
+                .map(|run| {
+                    let rtl = run.is_rtl();
+                    (run.range, rtl)
+                })
+                .collect(),
+            None => vec![(range, false)],
+        }
+    }
+
+    /// The horizontal extents of `range` on `line`, one for each visually
+    /// contiguous piece of it.
+    fn line_spans(&self, line: usize, range: Range<usize>) -> Vec<(f64, f64)> {
+        match self.visual_clusters(line) {
+            Some(clusters) if range.start < range.end => {
+                let mut spans: Vec<(f64, f64)> = Vec::new();
+                let inside =
+                    |c: &&VisualCluster| range.start <= c.range.start && c.range.end <= range.end;
+                for cluster in clusters.iter().filter(inside) {
+                    match spans.last_mut() {
+                        Some(span) if span.1 == cluster.x0 => span.1 = cluster.x1,
+                        _ => spans.push((cluster.x0, cluster.x1)),
+                    }
+                }
+                spans
+            }
+            _ => vec![(self.line_x(line, range.start), self.line_x(line, range.end))],
+        }
+    }
+
+    /// Whether any of the text reads right to left, so lines may be
+    /// reordered.
+    pub fn has_rtl(&self) -> bool {
+        self.bidi.is_some()
+    }
+
+    /// Whether the character at `position` of the displayed text reads right
+    /// to left.
+    pub fn is_rtl_at(&self, position: usize) -> bool {
+        self.bidi
+            .as_ref()
+            .is_some_and(|bidi| bidi.is_rtl_at(position))
+    }
+
+    /// Whether any line is moved from the start by alignment.
+    pub fn is_aligned(&self) -> bool {
+        self.placements
+            .iter()
+            .any(|placement| *placement != LinePlacement::default())
+    }
+
+    /// Whether any line is justified.
+    pub fn is_justified(&self) -> bool {
+        self.placements
+            .iter()
+            .any(|placement| placement.space != 0.0)
+    }
+
+    /// The color of each grapheme cluster of the displayed text, or `None`
+    /// where it's drawn with the brush.
+    pub fn cluster_colors(&self) -> Vec<Option<&Color>> {
+        self.text
+            .grapheme_indices(true)
+            .map(|(i, _)| {
+                let original = self.to_original(self.styled_position(i));
+                self.attributes
+                    .iter()
+                    .rev()
+                    .find_map(|(range, attribute)| match attribute {
+                        TextAttribute::Foreground(color) if range.contains(&original) => {
+                            Some(color)
+                        }
+                        _ => None,
+                    })
+                    .or(self.default_color.as_ref())
+            })
+            .collect()
+    }
+
+    /// Whether any of the text has a color of its own.
+    pub fn has_colors(&self) -> bool {
+        self.default_color.is_some()
+            || self
+                .attributes
+                .iter()
+                .any(|(_, attribute)| matches!(attribute, TextAttribute::Foreground(_)))
+    }
+
+    /// The background rectangles to paint before the text, relative to the
+    /// baseline origin, in painting order, one per line a range is on.
+    pub fn backgrounds(&self) -> impl Iterator<Item = (Rect, &Color)> + '_ {
+        self.attributes
+            .iter()
+            .flat_map(move |(range, attribute)| match attribute {
+                TextAttribute::Background(color) => self
+                    .display_line_rects(self.to_display_range(range.clone()))
+                    .into_iter()
+                    .map(|rect| (rect, color))
+                    .collect(),
+                _ => Vec::new(),
+            })
+    }
+
+    /// The underlines and strikethroughs to paint over the text, relative
+    /// to the baseline origin, each with its color or `None` for the brush.
+    pub fn decorations(&self) -> Vec<(Rect, Option<&Color>)> {
+        let decorated = |run: &&LayoutRun| run.underline || run.strikethrough;
+        if !self.runs.iter().any(|run| decorated(&run)) {
+            return Vec::new();
+        }
+        let colors = self.cluster_colors();
+        let mut decorations = Vec::new();
+        for run in self.runs.iter().filter(decorated) {
+            // split the run where the color changes
+            let mut pieces: Vec<(usize, Option<&Color>)> = Vec::new();
+            for (&start, &color) in self.clusters.iter().zip(&colors) {
+                let same = pieces
+                    .last()
+                    .is_some_and(|&(_, last)| same_color(last, color));
+                if run.range.contains(&start) && !same {
+                    pieces.push((start, color));
+                }
+            }
+            let metrics = &run.decorations;
+            for (i, &(piece_start, color)) in pieces.iter().enumerate() {
+                let piece_end = pieces.get(i + 1).map_or(run.range.end, |&(start, _)| start);
+                for line in self.line_of(piece_start)..=self.line_of(piece_end) {
+                    let start = piece_start.max(self.lines[line].start);
+                    let end = piece_end.min(self.lines[line].end);
+                    if start >= end {
+                        continue;
+                    }
+                    let baseline = Vec2::new(0.0, self.baseline(line));
+                    for (x0, x1) in self.line_spans(line, start..end) {
+                        if run.underline {
+                            decorations.push((metrics.underline_rect(x0, x1) + baseline, color));
+                        }
+                        if run.strikethrough {
+                            let rect = metrics.strikethrough_rect(x0, x1) + baseline;
+                            decorations.push((rect, color));
+                        }
+                    }
+                }
+            }
+        }
+        decorations
+    }
+
+    /// Convert a position in the original text to one in the displayed text.
+    pub fn to_display(&self, position: usize) -> usize {
+        let position = match &self.obscured {
+            Some(obscured) => obscured.to_display(position),
+            None => position,
+        };
+        match &self.truncated {
+            Some(truncated) => truncated.to_display(position),
+            None => position,
+        }
+    }
+
+    /// Convert a position in the displayed text to one in the original text.
+    pub fn to_original(&self, position: usize) -> usize {
+        let position = match &self.truncated {
+            Some(truncated) => truncated.to_original(position),
+            None => position,
+        };
+        match &self.obscured {
+            Some(obscured) => obscured.to_original(position),
+            None => position,
+        }
+    }
+
+    /// The position in the displayed text whose style the text at
+    /// `position` is drawn in; the ellipsis takes that of the text before it.
+    fn styled_position(&self, position: usize) -> usize {
+        match &self.truncated {
+            Some(truncated) if position >= truncated.cut() => truncated.cut().saturating_sub(1),
+            _ => position,
+        }
+    }
+
+    fn original_len(&self) -> usize {
+        match (&self.obscured, &self.truncated) {
+            (Some(obscured), _) => obscured.original_len(),
+            (None, Some(truncated)) => truncated.original_len(),
+            (None, None) => self.text.len(),
+        }
+    }
+
+    fn to_display_range(&self, range: Range<usize>) -> Range<usize> {
+        self.to_display(range.start)..self.to_display(range.end)
+    }
+
+    fn to_original_range(&self, range: Range<usize>) -> Range<usize> {
+        self.to_original(range.start)..self.to_original(range.end)
+    }
+
+    /// The bounds of `range` of the displayed text.
+    pub fn display_range_bounds(&self, range: Range<usize>) -> Option<Rect> {
+        self.display_line_rects(range)
+            .into_iter()
+            .fold(None, |acc: Option<Rect>, rect| {
+                Some(acc.map(|acc| acc.union(rect)).unwrap_or(rect))
+            })
+    }
+
+    /// The rectangles covering `range` of the displayed text, one for each
+    /// line it's on, or more where reordering splits it.
+    fn display_line_rects(&self, range: Range<usize>) -> Vec<Rect> {
+        // ranges from callers may split a character
+        let range = grapheme_range(&self.text, range);
+        let end = range.end;
+        if range.start >= end {
+            return Vec::new();
+        }
+        let (first, last) = (self.line_of(range.start), self.line_of(end));
+        (first..=last)
+            .flat_map(|line| {
+                let line_range = &self.lines[line];
+                let start = range.start.max(line_range.start);
+                let end = end.min(line_range.end).max(start);
+                if start == end && first != last {
+                    // only the break before or after the line is in the range
+                    return Vec::new();
+                }
+                let extents = &self.line_extents[line];
+                self.line_spans(line, start..end)
+                    .into_iter()
+                    .map(|(x0, x1)| {
+                        Rect::new(
+                            x0,
+                            extents.baseline - extents.ascent,
+                            x1,
+                            extents.baseline + extents.descent,
+                        )
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn hit_test_display_point(&self, point: Point) -> HitTestPoint {
+        // internal logic is using grapheme clusters, but return the text position associated
+        // with the border of the grapheme cluster.
+
+        // null case
+        if self.text.is_empty() {
+            return HitTestPoint::default();
+        }
+
+        let line = self.line_at(point.y);
+        let mut hit = match self.visual_clusters(line) {
+            Some(clusters) => hit_test_visual(&clusters, point.x),
+            None => self.hit_test_line_point(line, point.x),
+        };
+        let (first, last) = (
+            &self.line_extents[0],
+            &self.line_extents[self.lines.len() - 1],
+        );
+        hit.is_inside &= point.y >= -first.ascent && point.y <= last.baseline + last.descent;
+        hit
+    }
+
+    /// The edges of the `cluster`th grapheme cluster, on the line it's on.
+    fn cluster_bounds(&self, cluster: usize) -> ClusterBounds {
+        let range = self.cluster_range(cluster);
+        // both edges are measured on the line the cluster is on, even when
+        // the next one starts a new line
+        let line = self.line_of(range.start);
+        ClusterBounds {
+            start: range.start,
+            end: range.end,
+            leading: self.line_x(line, range.start),
+            trailing: self.line_x(line, range.end),
+        }
+    }
+
+    /// Hit test `x` against the grapheme clusters of `line`.
+    fn hit_test_line_point(&self, line: usize, x: f64) -> HitTestPoint {
+        let range = &self.lines[line];
+        let mut beyond = HitTestPoint::default();
+        let start = self.clusters.partition_point(|&c| c < range.start);
+        let count = self.clusters.partition_point(|&c| c < range.end) - start;
+        if count == 0 {
+            beyond.metrics.text_position = range.start;
+            return beyond;
+        }
+
+        // get bounds
+        let end = start + count - 1;
+        let end_bounds = self.cluster_bounds(end);
+        let start_bounds = self.cluster_bounds(start);
+
+        // first test beyond ends
+        if x > end_bounds.trailing {
+            beyond.metrics.text_position = range.end;
+            return beyond;
+        }
+        // NaN is neither beyond nor inside any grapheme
+        if x.is_nan() || x <= start_bounds.leading {
+            beyond.metrics.text_position = range.start;
+            return beyond;
+        }
+
+        // then test the beginning and end (common cases)
+        if let Some(hit) = start_bounds.hit_test(x) {
+            return hit;
+        }
+        if let Some(hit) = end_bounds.hit_test(x) {
+            return hit;
+        }
+
+        // Now that we know it's not beginning or end, begin binary search.
+        // Iterative style
+        let mut left = start;
+        let mut right = end;
+        loop {
+            // pick halfway point
+            let middle = left + ((right - left) / 2);
+            let bounds = self.cluster_bounds(middle);
+            if let Some(hit) = bounds.hit_test(x) {
+                return hit;
+            }
+
+            // since it's not a hit, check if closer to start or finish
+            // and move the appropriate search boundary
+            if x < bounds.leading {
+                right = middle;
+            } else if x > bounds.trailing {
+                left = middle + 1;
+            } else {
+                unreachable!("hit_test_point conditional is exhaustive");
+            }
+        }
+    }
+
+    fn hit_test_display_position(&self, text_position: usize) -> Option<HitTestTextPosition> {
+        let text_len = self.text.len();
+
+        // If text position is not at a grapheme boundary, use the text position of current
+        // grapheme cluster. But return the original text position
+        let (byte_idx, text_position) = if text_position >= text_len {
+            (text_len, text_len)
+        } else {
+            let cluster = self.clusters.partition_point(|&c| c <= text_position);
+            let byte_idx = self.clusters[cluster.saturating_sub(1)];
+            (byte_idx, text_position)
+        };
+
+        let line = self.line_of(byte_idx);
+        Some(HitTestTextPosition {
+            point: Point {
+                x: self.line_x(line, byte_idx),
+                y: self.baseline(line),
+            },
+            metrics: HitTestMetrics { text_position },
+        })
+    }
+
+    /// The width of the widest line, as [`TextLayout::width`].
+    ///
+    /// [`TextLayout::width`]: trait.TextLayout.html#tymethod.width
+    pub fn width(&self) -> f64 {
+        (0..self.lines.len())
+            .map(|line| match self.visual_clusters(line) {
+                Some(clusters) => clusters.last().map_or(0.0, |c| c.x1),
+                None => self.line_x(line, self.lines[line].end),
+            })
+            .fold(0.0, f64::max)
+    }
+
+    /// As [`TextLayout::hit_test_point`].
+    ///
+    /// [`TextLayout::hit_test_point`]: trait.TextLayout.html#tymethod.hit_test_point
+    pub fn hit_test_point(&self, point: Point) -> HitTestPoint {
+        let mut hit = self.hit_test_display_point(point);
+        hit.metrics.text_position = self.to_original(hit.metrics.text_position);
+        hit
+    }
+
+    /// As [`TextLayout::hit_test_text_position`].
+    ///
+    /// [`TextLayout::hit_test_text_position`]: trait.TextLayout.html#tymethod.hit_test_text_position
+    pub fn hit_test_text_position(&self, text_position: usize) -> Option<HitTestTextPosition> {
+        if self.obscured.is_none() && self.truncated.is_none() {
+            return self.hit_test_display_position(text_position);
+        }
+        let mut hit = self.hit_test_display_position(self.to_display(text_position))?;
+        hit.metrics.text_position = text_position.min(self.original_len());
+        Some(hit)
+    }
+
+    /// As [`TextLayout::range_bounds`].
+    ///
+    /// [`TextLayout::range_bounds`]: trait.TextLayout.html#method.range_bounds
+    pub fn range_bounds(&self, range: Range<usize>) -> Option<Rect> {
+        self.display_range_bounds(self.to_display_range(range))
+    }
+
+    /// As [`TextLayout::rects_for_range`].
+    ///
+    /// [`TextLayout::rects_for_range`]: trait.TextLayout.html#method.rects_for_range
+    pub fn rects_for_range(&self, range: Range<usize>) -> Vec<Rect> {
+        self.display_line_rects(self.to_display_range(range))
+    }
+
+    /// As [`TextLayout::caret_bounds`].
+    ///
+    /// [`TextLayout::caret_bounds`]: trait.TextLayout.html#method.caret_bounds
+    pub fn caret_bounds(&self, text_position: usize) -> Option<Rect> {
+        let point = self.hit_test_text_position(text_position)?.point;
+        let extents = &self.line_extents[self.line_at(point.y)];
+        Some(Rect::new(
+            point.x,
+            point.y - extents.ascent,
+            point.x,
+            point.y + extents.descent,
+        ))
+    }
+
+    /// As [`TextLayout::truncated_at`].
+    ///
+    /// [`TextLayout::truncated_at`]: trait.TextLayout.html#method.truncated_at
+    pub fn truncated_at(&self) -> Option<usize> {
+        let cut = self.truncated.as_ref()?.cut();
+        Some(match &self.obscured {
+            Some(obscured) => obscured.to_original(cut),
+            None => cut,
+        })
+    }
+
+    /// As [`TextLayout::line_metrics`].
+    ///
+    /// [`TextLayout::line_metrics`]: trait.TextLayout.html#method.line_metrics
+    pub fn line_metrics(&self) -> Vec<LineMetric> {
+        self.lines
+            .iter()
+            .zip(&self.line_extents)
+            .map(|(range, extents)| LineMetric {
+                range: self.to_original_range(range.clone()),
+                baseline: extents.baseline,
+                ascent: extents.ascent,
+                descent: extents.descent,
+                height: extents.height,
+            })
+            .collect()
+    }
+
+    /// As [`TextLayout::cluster_metrics`].
+    ///
+    /// [`TextLayout::cluster_metrics`]: trait.TextLayout.html#method.cluster_metrics
+    pub fn cluster_metrics(&self) -> Vec<ClusterMetric> {
+        (0..self.clusters.len())
+            .map(|cluster| {
+                let range = self.cluster_range(cluster);
+                let line = self.line_of(range.start);
+                let end = self.lines[line].end;
+                let baseline = self.baseline(line);
+                match self.display_range_bounds(range.clone()) {
+                    Some(rect) if range.start < end => ClusterMetric {
+                        origin: Point::new(rect.x0, baseline),
+                        advance: rect.width(),
+                    },
+                    // the line break, or a space hanging at a wrap
+                    _ => ClusterMetric {
+                        origin: Point::new(self.line_x(line, range.start.min(end)), baseline),
+                        advance: 0.0,
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+/// Hit test `x` against the clusters of a reordered line, which reads
+/// towards the side of each cluster `x` is nearer.
+fn hit_test_visual(clusters: &[VisualCluster], x: f64) -> HitTestPoint {
+    let mut hit = HitTestPoint::default();
+    let (first, last) = match (clusters.first(), clusters.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return hit,
+    };
+    // NaN is neither beyond nor inside any cluster
+    if x.is_nan() || x <= first.x0 {
+        hit.metrics.text_position = first.left();
+        return hit;
+    }
+    if x > last.x1 {
+        hit.metrics.text_position = last.right();
+        return hit;
+    }
+    let cluster = clusters.iter().find(|c| x <= c.x1).unwrap_or(last);
+    hit.metrics.text_position = if x < (cluster.x0 + cluster.x1) / 2.0 {
+        cluster.left()
+    } else {
+        cluster.right()
+    };
+    hit.is_inside = true;
+    hit
+}
+
+/// Whether `attribute` changes the font of the text, its spacing, or its
+/// decorations.
+fn changes_style(attribute: &TextAttribute) -> bool {
+    matches!(
+        attribute,
+        TextAttribute::Size(_)
+            | TextAttribute::Weight(_)
+            | TextAttribute::Italic(_)
+            | TextAttribute::Underline(_)
+            | TextAttribute::Strikethrough(_)
+            | TextAttribute::LetterSpacing(_)
+            | TextAttribute::WordSpacing(_)
+            | TextAttribute::FontFeature(_)
+    )
+}
+
+fn same_color(a: Option<&Color>, b: Option<&Color>) -> bool {
+    a.map(Color::as_rgba_u32) == b.map(Color::as_rgba_u32)
+}
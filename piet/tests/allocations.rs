@@ -0,0 +1,136 @@
+//! Check that the steps from a shape to backend commands that every backend
+//! shares don't allocate for simple rectangles.
+//!
+//! The allocator counts for the whole process, so these checks are kept in
+//! a file of their own. Each test counts only its own thread's allocations,
+//! so they can run side by side.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::hint::black_box;
+
+use piet::kurbo::{Affine, Rect};
+use piet::{
+    align_stroke, shape_elements, user_tolerance, Color, DynShape, FrameArena, GradientColorSpace,
+    GradientStops, StrokeStyle,
+};
+
+/// The system allocator, counting each allocation made on each thread.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count() {
+    // the count may already be gone while the thread exits
+    let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const ITERATIONS: u32 = 1000;
+
+/// Run `f` once to warm up, then again, and return the number of
+/// allocations it made after warming up.
+fn allocations(mut f: impl FnMut(u32)) -> usize {
+    f(0);
+    let before = ALLOCATIONS.with(Cell::get);
+    for i in 0..ITERATIONS {
+        f(i);
+    }
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+fn rect(i: u32) -> Rect {
+    let x = (i % 100) as f64;
+    Rect::new(x, 0.0, x + 10.0, 10.0)
+}
+
+#[test]
+fn collect_rect() {
+    let mut paths = FrameArena::new();
+    let tolerance = user_tolerance(1e-3, Affine::scale(2.0));
+    let n = allocations(|i| {
+        let path = paths.collect(shape_elements(&rect(i), tolerance));
+        paths.give_back(black_box(path));
+    });
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn collect_dyn_rect() {
+    let mut paths = FrameArena::new();
+    let n = allocations(|i| {
+        let shape = rect(i);
+        let shape: &dyn DynShape = &shape;
+        let path = paths.collect(shape_elements(&shape, 1e-3));
+        paths.give_back(black_box(path));
+    });
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn stream_dyn_rect() {
+    let n = allocations(|i| {
+        let shape = rect(i);
+        let shape: &dyn DynShape = &shape;
+        black_box(shape_elements(&shape, 1e-3).count());
+    });
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn centered_stroke() {
+    let solid = StrokeStyle::new();
+    let n = allocations(|i| {
+        black_box(align_stroke(rect(i), 1.0, &solid));
+    });
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn odd_dash_lengths() {
+    let dashed = StrokeStyle::dashed(&[4.0, 2.0, 1.0], 0.0);
+    let n = allocations(|_| {
+        black_box(dashed.dash_lengths());
+    });
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn srgb_gradient_stops() {
+    let stops = (Color::rgb8(0xff, 0, 0), Color::rgb8(0, 0, 0xff)).to_vec();
+    let n = allocations(|_| {
+        black_box(GradientColorSpace::Srgb.resolve_stops(&stops));
+    });
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn collect_linear_stops() {
+    let stops = (Color::rgb8(0xff, 0, 0), Color::rgb8(0, 0, 0xff)).to_vec();
+    let mut arena = FrameArena::new();
+    let n = allocations(|_| {
+        let resolved = arena.collect(GradientColorSpace::LinearRgb.resolved_stops(&stops));
+        arena.give_back(black_box(resolved));
+    });
+    assert_eq!(n, 0);
+}